@@ -0,0 +1,166 @@
+//! Letting the host provide inputs and receive outputs via function pointers,
+//! rather than filling in tensors before each call to
+//! `rune_runtime_predict()`.
+
+use std::os::raw::{c_int, c_void};
+
+use hotg_rune_runtime::{
+    sink::{Delivery, RetryPolicy},
+    ElementType, OutputTensor, Tensor,
+};
+
+use crate::{runtime::Destructor, Error, OutputTensors, Runtime};
+
+/// A function called at the start of every prediction to fill in a
+/// capability's input.
+///
+/// The `tensor` is zeroed and has the element type and dimensions passed to
+/// `rune_runtime_set_input_callback()`, so the callback only needs to write
+/// to its buffer (see `rune_tensor_buffer()`).
+///
+/// Return `null` on success. Any other `Error` (e.g. one created with
+/// `rune_error_new()`) stops the prediction, and the runtime takes ownership
+/// of it.
+pub type InputCallback = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    node_id: u32,
+    tensor: *mut Tensor,
+) -> *mut Error;
+
+/// A function called after every prediction with the tensors written to an
+/// output.
+///
+/// The `tensors` are only valid until the callback returns and must not be
+/// freed.
+///
+/// Return `null` on success. Any other `Error` is owned by the runtime, and
+/// doesn't stop other outputs from being delivered.
+pub type OutputCallback = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    node_id: u32,
+    tensors: *mut OutputTensors<'_>,
+) -> *mut Error;
+
+/// Ask a callback for a capability's input every time the Rune is run
+/// instead of reading it from `rune_runtime_input_tensors()`, which lets
+/// hosts pull from sources like live sensors.
+///
+/// The `destructor` (if provided) will be called with `user_data` when the
+/// callback is replaced or the runtime is freed.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_set_input_callback(
+    runtime: *mut Runtime,
+    node_id: u32,
+    element_type: ElementType,
+    dimensions: *const usize,
+    rank: c_int,
+    callback: InputCallback,
+    user_data: *mut c_void,
+    destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> *mut Error {
+    // Note: Create this first so the destructor is called if we bail early
+    let user_data = UserData {
+        ptr: user_data,
+        destructor,
+    };
+
+    expect!(!runtime.is_null());
+    let runtime = &mut *runtime;
+
+    let dimensions = match crate::dimensions_from_raw(dimensions, rank) {
+        Some(d) => d,
+        None => {
+            return Error::boxed(anyhow::anyhow!(
+                "A tensor's dimensions must all be greater than zero"
+            ))
+        },
+    };
+
+    let capability = move || {
+        let mut tensor = Tensor::zeroed(element_type, dimensions.clone());
+
+        // Safety: Ensured by the caller.
+        let error = unsafe { callback(user_data.ptr(), node_id, &mut tensor) };
+
+        if error.is_null() {
+            Ok(tensor)
+        } else {
+            Err(unsafe { Error::from_raw(error) })
+        }
+    };
+
+    match runtime.inner.set_capability(node_id, capability) {
+        Ok(_) => std::ptr::null_mut(),
+        Err(e) => Error::boxed(e),
+    }
+}
+
+/// Pass everything written to an output to a callback after each
+/// prediction.
+///
+/// The `destructor` (if provided) will be called with `user_data` when the
+/// runtime is freed.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_set_output_callback(
+    runtime: *mut Runtime,
+    node_id: u32,
+    callback: OutputCallback,
+    user_data: *mut c_void,
+    destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> *mut Error {
+    let user_data = UserData {
+        ptr: user_data,
+        destructor,
+    };
+
+    expect!(!runtime.is_null());
+    let runtime = &mut *runtime;
+
+    let sink = move |outputs: &[OutputTensor]| {
+        let mut tensors = OutputTensors::for_output(node_id, outputs);
+
+        // Safety: Ensured by the caller.
+        let error = unsafe { callback(user_data.ptr(), node_id, &mut tensors) };
+
+        if error.is_null() {
+            Delivery::Delivered
+        } else {
+            Delivery::Fatal(unsafe { Error::from_raw(error) })
+        }
+    };
+
+    match runtime.inner.add_sink(node_id, sink, RetryPolicy::none()) {
+        Ok(_) => std::ptr::null_mut(),
+        Err(e) => Error::boxed(e),
+    }
+}
+
+/// The `user_data` passed to a callback, which is destroyed along with the
+/// callback.
+struct UserData {
+    ptr: *mut c_void,
+    destructor: Option<Destructor>,
+}
+
+impl UserData {
+    // Note: Closures capture individual fields in Rust 2021, so accessing the
+    // pointer through a method makes sure they take ownership of the whole
+    // UserData.
+    fn ptr(&self) -> *mut c_void { self.ptr }
+}
+
+impl Drop for UserData {
+    fn drop(&mut self) {
+        if let Some(destructor) = self.destructor {
+            unsafe {
+                destructor(self.ptr);
+            }
+        }
+    }
+}
+
+// Safety: Ensured by the caller.
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
@@ -3,6 +3,8 @@ use std::{
     os::raw::{c_char, c_int},
 };
 
+use hotg_rune_runtime::ErrorCode;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(u32)]
 #[non_exhaustive]
@@ -21,6 +23,8 @@ pub enum ErrorKind {
     BadImports = 2,
     /// The call into WebAssembly raised an error.
     CallFailed = 3,
+    /// The Rune couldn't be read from a file or an Android asset.
+    ReadFailed = 4,
 }
 
 /// An error that may be returned by the Rune native library.
@@ -62,6 +66,12 @@ impl Error {
         let boxed = Box::new(Error(error.into()));
         Box::into_raw(boxed)
     }
+
+    /// Take ownership of an `Error` returned by the caller (e.g. from a
+    /// callback).
+    pub(crate) unsafe fn from_raw(error: *mut Error) -> anyhow::Error {
+        Box::from_raw(error).0
+    }
 }
 
 impl From<anyhow::Error> for Error {
@@ -139,6 +149,18 @@ pub unsafe extern "C" fn rune_error_kind(e: *const Error) -> ErrorKind {
     error_kind_for_anyhow(&*e)
 }
 
+/// Get the runtime's [`ErrorCode`] for this error, which is more precise than
+/// `rune_error_kind()` (e.g. it can tell a missing input apart from a Rune
+/// that panicked).
+#[no_mangle]
+pub unsafe extern "C" fn rune_error_code(e: *const Error) -> ErrorCode {
+    if e.is_null() {
+        return ErrorCode::Other;
+    }
+
+    ErrorCode::of(&*e)
+}
+
 fn error_kind_for_anyhow(error: &anyhow::Error) -> ErrorKind {
     for error in error.chain() {
         if let Some(error_kind) = specific_error_kind(error) {
@@ -152,6 +174,10 @@ fn error_kind_for_anyhow(error: &anyhow::Error) -> ErrorKind {
 fn specific_error_kind(
     e: &(dyn std::error::Error + 'static),
 ) -> Option<ErrorKind> {
+    if e.is::<crate::loader::ReadFailed>() {
+        return Some(ErrorKind::ReadFailed);
+    }
+
     if let Some(load_error) = e.downcast_ref::<hotg_rune_runtime::LoadError>() {
         if let Some(kind) = rune_load_error(load_error) {
             return Some(kind);
@@ -235,4 +261,18 @@ mod tests {
             std::mem::size_of::<*mut usize>()
         );
     }
+
+    #[test]
+    fn error_codes_look_through_context() {
+        let error = Error::from(
+            anyhow::Error::from(
+                hotg_rune_runtime::RuntimeError::UnknownOutput { id: 3 },
+            )
+            .context("Unable to register the callback"),
+        );
+
+        let code = unsafe { rune_error_code(&error) };
+
+        assert_eq!(code, ErrorCode::UnknownOutput);
+    }
 }
@@ -1,9 +1,7 @@
 use std::{
     collections::HashMap,
-    num::NonZeroUsize,
     os::raw::c_int,
     ptr::{self, NonNull},
-    slice,
 };
 
 use hotg_rune_runtime::{ElementType, Tensor};
@@ -91,20 +89,10 @@ pub unsafe extern "C" fn rune_input_tensors_insert(
 
     let tensors = &mut *tensors;
 
-    if rank <= 0 {
-        return ptr::null_mut();
-    }
-
-    let dimensions = slice::from_raw_parts(dimensions, rank as usize);
-
-    let mut dims = Vec::new();
-
-    for &dim in dimensions {
-        match usize::try_from(dim).ok().and_then(|d| NonZeroUsize::new(d)) {
-            Some(dim) => dims.push(dim),
-            None => return ptr::null_mut(),
-        }
-    }
+    let dims = match crate::dimensions_from_raw(dimensions, rank) {
+        Some(d) => d,
+        None => return ptr::null_mut(),
+    };
 
     tensors.insert(node_id, Tensor::zeroed(element_type, dims));
     tensors
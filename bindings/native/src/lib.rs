@@ -1,14 +1,18 @@
 #[macro_use]
 mod macros;
 
+mod callbacks;
 mod error;
 mod input_tensors;
+mod loader;
 mod metadata;
 mod output_tensors;
+mod profiling;
 mod runtime;
 mod utils;
 
 pub(crate) use crate::utils::*;
 pub use crate::{
-    error::*, input_tensors::*, metadata::*, output_tensors::*, runtime::*,
+    callbacks::*, error::*, input_tensors::*, loader::*, metadata::*,
+    output_tensors::*, profiling::*, runtime::*,
 };
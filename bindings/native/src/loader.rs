@@ -0,0 +1,205 @@
+//! Platform-specific ways of getting a Rune into memory.
+
+use std::{
+    ffi::CStr,
+    fmt::{self, Display, Formatter},
+    io,
+    os::raw::c_char,
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, Runtime};
+
+/// Load a Rune from a file on disk.
+///
+/// The `path` must be a null-terminated UTF-8 string on every platform. On
+/// Windows, `rune_runtime_load_from_file_w()` can be used when the path is
+/// already a wide string.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_load_from_file(
+    path: *const c_char,
+    runtime_out: *mut *mut Runtime,
+) -> *mut Error {
+    expect!(!path.is_null());
+    expect!(!runtime_out.is_null());
+
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(p) => PathBuf::from(p),
+        Err(e) => {
+            return Error::boxed(
+                anyhow::Error::new(e).context("The path isn't valid UTF-8"),
+            )
+        },
+    };
+
+    load_from_path(&path, runtime_out)
+}
+
+/// Load a Rune from a file on disk, where `path` is a null-terminated UTF-16
+/// string (i.e. a `const wchar_t *`).
+#[cfg(windows)]
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_load_from_file_w(
+    path: *const u16,
+    runtime_out: *mut *mut Runtime,
+) -> *mut Error {
+    use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+
+    expect!(!path.is_null());
+    expect!(!runtime_out.is_null());
+
+    let mut len = 0;
+    while *path.add(len) != 0 {
+        len += 1;
+    }
+    let wide = std::slice::from_raw_parts(path, len);
+    let path = PathBuf::from(OsString::from_wide(wide));
+
+    load_from_path(&path, runtime_out)
+}
+
+/// Load a Rune that was packaged inside an Android app's `assets/` folder.
+///
+/// The `env` and `asset_manager` are the `JNIEnv *` and
+/// `android.content.res.AssetManager` object passed to a JNI function, and
+/// `name` is the asset's null-terminated path relative to `assets/`.
+#[cfg(target_os = "android")]
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_load_from_asset(
+    env: *mut std::os::raw::c_void,
+    asset_manager: *mut std::os::raw::c_void,
+    name: *const c_char,
+    runtime_out: *mut *mut Runtime,
+) -> *mut Error {
+    expect!(!env.is_null());
+    expect!(!asset_manager.is_null());
+    expect!(!name.is_null());
+    expect!(!runtime_out.is_null());
+
+    let display_name = CStr::from_ptr(name).to_string_lossy().into_owned();
+
+    match android::read_asset(env.cast(), asset_manager.cast(), name) {
+        Ok(rune) => crate::runtime::load_into(&rune, None, runtime_out),
+        Err(e) => Error::boxed(ReadFailed::new(display_name, e)),
+    }
+}
+
+unsafe fn load_from_path(
+    path: &Path,
+    runtime_out: *mut *mut Runtime,
+) -> *mut Error {
+    match std::fs::read(path) {
+        Ok(rune) => crate::runtime::load_into(&rune, None, runtime_out),
+        Err(e) => Error::boxed(ReadFailed::new(path.display(), e)),
+    }
+}
+
+/// The error returned when a Rune couldn't be read.
+///
+/// The message depends only on the [`io::ErrorKind`] rather than the
+/// operating system's own description, so applications see the same text on
+/// every platform.
+#[derive(Debug)]
+pub(crate) struct ReadFailed {
+    name: String,
+    error: io::Error,
+}
+
+impl ReadFailed {
+    fn new(name: impl Display, error: io::Error) -> Self {
+        ReadFailed {
+            name: name.to_string(),
+            error,
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.error.kind() {
+            io::ErrorKind::NotFound => "it doesn't exist",
+            io::ErrorKind::PermissionDenied => "permission denied",
+            io::ErrorKind::InvalidInput => "the name is invalid",
+            io::ErrorKind::UnexpectedEof => "it was truncated",
+            _ => "an I/O error occurred",
+        }
+    }
+}
+
+impl Display for ReadFailed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unable to read \"{}\" because {}",
+            self.name,
+            self.reason()
+        )
+    }
+}
+
+impl std::error::Error for ReadFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(target_os = "android")]
+mod android {
+    use std::{io, os::raw::c_char};
+
+    use ndk_sys::{
+        AAssetManager_fromJava, AAssetManager_open, AAsset_close,
+        AAsset_getBuffer, AAsset_getLength64, JNIEnv, AASSET_MODE_BUFFER,
+    };
+
+    pub(crate) unsafe fn read_asset(
+        env: *mut JNIEnv,
+        asset_manager: ndk_sys::jobject,
+        name: *const c_char,
+    ) -> Result<Vec<u8>, io::Error> {
+        let manager = AAssetManager_fromJava(env, asset_manager);
+        if manager.is_null() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+
+        let asset = AAssetManager_open(manager, name, AASSET_MODE_BUFFER as _);
+        if asset.is_null() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+
+        let buffer = AAsset_getBuffer(asset);
+        let len = AAsset_getLength64(asset);
+
+        let result = if buffer.is_null() || len < 0 {
+            Err(io::ErrorKind::UnexpectedEof.into())
+        } else {
+            Ok(
+                std::slice::from_raw_parts(buffer.cast::<u8>(), len as usize)
+                    .to_vec(),
+            )
+        };
+
+        AAsset_close(asset);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_errors_are_the_same_on_every_platform() {
+        let missing = std::env::temp_dir().join("does-not-exist.rune");
+        let error = std::fs::read(&missing).unwrap_err();
+
+        let got = ReadFailed::new("does-not-exist.rune", error);
+
+        assert_eq!(
+            got.to_string(),
+            "Unable to read \"does-not-exist.rune\" because it doesn't exist"
+        );
+    }
+}
@@ -23,6 +23,13 @@ impl<'rt> From<&'rt HashMap<u32, Vec<OutputTensor>>> for OutputTensors<'rt> {
     }
 }
 
+impl<'rt> OutputTensors<'rt> {
+    /// Iterate over the tensors written to a single output.
+    pub(crate) fn for_output(id: u32, tensors: &'rt [OutputTensor]) -> Self {
+        OutputTensors(Box::new(tensors.iter().map(move |t| (id, t))))
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn rune_output_tensors_free(tensors: *mut OutputTensors) {
     if tensors.is_null() {
@@ -97,6 +104,7 @@ pub unsafe extern "C" fn rune_output_tensor_as_string_tensor<'tensor>(
         } => Box::into_raw(Box::new(StringTensor {
             dimensions,
             strings,
+            packed: Tensor::from_strings(strings, dimensions),
         })),
     }
 }
@@ -116,6 +124,8 @@ pub unsafe extern "C" fn rune_output_tensor_as_string_tensor<'tensor>(
 pub struct StringTensor<'tensor> {
     dimensions: &'tensor [usize],
     strings: &'tensor [String],
+    /// The strings encoded as a `utf8` tensor.
+    packed: Tensor,
 }
 
 /// Get the number of dimensions in this `StringTensor`.
@@ -142,6 +152,38 @@ pub unsafe extern "C" fn rune_string_tensor_dimensions(
     (&*tensor).dimensions.as_ptr()
 }
 
+/// Get the number of strings in this `StringTensor`.
+#[no_mangle]
+pub unsafe extern "C" fn rune_string_tensor_len(
+    tensor: *const StringTensor<'_>,
+) -> usize {
+    if tensor.is_null() {
+        return 0;
+    }
+
+    (&*tensor).strings.len()
+}
+
+/// Get a pointer to all of this `StringTensor`'s strings, packed into a single
+/// buffer and setting `len_out` to the buffer's length in bytes.
+///
+/// Each string is stored as a little-endian `uint32_t` length followed by
+/// that many bytes of UTF-8 text, with no null terminator.
+#[no_mangle]
+pub unsafe extern "C" fn rune_string_tensor_buffer(
+    tensor: *const StringTensor<'_>,
+    len_out: *mut usize,
+) -> *const u8 {
+    if tensor.is_null() {
+        len_out.write(0);
+        return ptr::null();
+    }
+
+    let buffer = (&*tensor).packed.buffer();
+    len_out.write(buffer.len());
+    buffer.as_ptr()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn rune_string_tensor_free(
     tensor: *const StringTensor<'_>,
@@ -0,0 +1,203 @@
+use std::{
+    os::raw::c_int,
+    ptr,
+    sync::{Arc, Mutex},
+};
+
+use hotg_rune_runtime::profiling::{NodeKind, NodeTiming, Profile};
+
+use crate::Runtime;
+
+/// A snapshot of how long each node in the Rune has taken so far.
+pub struct Profiling {
+    predictions: u64,
+    total_us: u64,
+    rune_us: u64,
+    nodes: Vec<ProfiledNode>,
+}
+
+impl From<&'_ Profile> for Profiling {
+    fn from(profile: &'_ Profile) -> Self {
+        Profiling {
+            predictions: profile.predictions,
+            total_us: profile.total.as_micros() as u64,
+            rune_us: profile.rune_time().as_micros() as u64,
+            nodes: profile.nodes().map(ProfiledNode::from).collect(),
+        }
+    }
+}
+
+/// The kind of node a `ProfiledNode` refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ProfiledNodeKind {
+    Capability = 0,
+    Model = 1,
+    Output = 2,
+}
+
+/// Execution timings for a single capability, model, or output.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct ProfiledNode {
+    pub kind: ProfiledNodeKind,
+    /// The node's ID.
+    pub id: u32,
+    /// How many times the node was executed.
+    pub invocations: u64,
+    /// The total time spent in this node, in microseconds.
+    pub total_us: u64,
+    /// The longest a single execution took, in microseconds.
+    pub max_us: u64,
+}
+
+impl From<&'_ NodeTiming> for ProfiledNode {
+    fn from(timing: &'_ NodeTiming) -> Self {
+        let kind = match timing.kind {
+            NodeKind::Capability => ProfiledNodeKind::Capability,
+            NodeKind::Model => ProfiledNodeKind::Model,
+            NodeKind::Output => ProfiledNodeKind::Output,
+        };
+
+        ProfiledNode {
+            kind,
+            id: timing.id,
+            invocations: timing.invocations,
+            total_us: timing.total.as_micros() as u64,
+            max_us: timing.max.as_micros() as u64,
+        }
+    }
+}
+
+/// Start recording how long each node takes to execute, discarding any
+/// timings which have already been recorded.
+#[no_mangle]
+pub unsafe extern "C" fn rune_runtime_enable_profiling(runtime: *mut Runtime) {
+    if runtime.is_null() {
+        return;
+    }
+    let runtime = &mut *runtime;
+
+    let profile = Arc::new(Mutex::new(Profile::default()));
+    runtime.inner.set_profiler(Arc::clone(&profile));
+    runtime.profile = Some(profile);
+}
+
+/// Take a snapshot of the timings recorded since profiling was enabled.
+///
+/// This will return `null` if `runtime` is `null` or profiling hasn't been
+/// enabled with `rune_runtime_enable_profiling()`.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_profiling(
+    runtime: *const Runtime,
+) -> *mut Profiling {
+    if runtime.is_null() {
+        return ptr::null_mut();
+    }
+
+    match &(&*runtime).profile {
+        Some(profile) => {
+            let profile = profile.lock().expect("Lock was poisoned");
+            Box::into_raw(Box::new(Profiling::from(&*profile)))
+        },
+        None => ptr::null_mut(),
+    }
+}
+
+/// Get the timings for just the most recent call to `rune_runtime_predict()`.
+///
+/// These are always collected, so profiling doesn't need to be enabled first.
+/// This will return `null` if `runtime` is `null`.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_last_run_profiling(
+    runtime: *const Runtime,
+) -> *mut Profiling {
+    if runtime.is_null() {
+        return ptr::null_mut();
+    }
+
+    let profile = (&*runtime).inner.last_run_profile();
+    Box::into_raw(Box::new(Profiling::from(&profile)))
+}
+
+/// Free a `Profiling` snapshot when you are done with it.
+#[no_mangle]
+pub unsafe extern "C" fn rune_profiling_free(profiling: *mut Profiling) {
+    if profiling.is_null() {
+        return;
+    }
+
+    let _ = Box::from_raw(profiling);
+}
+
+/// How many predictions have been run?
+#[no_mangle]
+pub unsafe extern "C" fn rune_profiling_predictions(
+    profiling: *const Profiling,
+) -> u64 {
+    if profiling.is_null() {
+        return 0;
+    }
+
+    (&*profiling).predictions
+}
+
+/// The total time spent running predictions, in microseconds.
+#[no_mangle]
+pub unsafe extern "C" fn rune_profiling_total_us(
+    profiling: *const Profiling,
+) -> u64 {
+    if profiling.is_null() {
+        return 0;
+    }
+
+    (&*profiling).total_us
+}
+
+/// The time spent inside the Rune itself (e.g. running proc-blocks) rather
+/// than in a capability, model, or output, in microseconds.
+#[no_mangle]
+pub unsafe extern "C" fn rune_profiling_rune_us(
+    profiling: *const Profiling,
+) -> u64 {
+    if profiling.is_null() {
+        return 0;
+    }
+
+    (&*profiling).rune_us
+}
+
+/// How many nodes have timings?
+#[no_mangle]
+pub unsafe extern "C" fn rune_profiling_node_count(
+    profiling: *const Profiling,
+) -> c_int {
+    if profiling.is_null() {
+        return 0;
+    }
+
+    (&*profiling).nodes.len() as c_int
+}
+
+/// Get the timings for a node, returning `null` if the index is out of
+/// bounds.
+///
+/// # Safety
+///
+/// The returned pointer can't outlive the `Profiling` it came from.
+#[no_mangle]
+pub unsafe extern "C" fn rune_profiling_get_node(
+    profiling: *const Profiling,
+    index: c_int,
+) -> *const ProfiledNode {
+    if profiling.is_null() {
+        return ptr::null();
+    }
+
+    match (&*profiling).nodes.get(index as usize) {
+        Some(node) => node,
+        None => ptr::null(),
+    }
+}
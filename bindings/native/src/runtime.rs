@@ -1,18 +1,26 @@
 use std::{
+    collections::HashMap,
+    ffi::CStr,
     ops::{Deref, DerefMut},
     os::raw::{c_char, c_int, c_void},
+    path::{Path, PathBuf},
     ptr, slice,
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
 };
 
-use hotg_rune_core::SerializableRecord;
-use hotg_rune_runtime::{LoadError, Runtime as RustRuntime};
-use log::Record;
+use hotg_rune_runtime::{
+    profiling::Profile, Engine as RustEngine, LoadError, LogRecord,
+    NodeMetadata, OutputTensor, Runtime as RustRuntime, Tensor,
+};
 
 use crate::{Error, InputTensors, Metadata, OutputTensors};
 
 /// A loaded Rune.
 pub struct Runtime {
-    inner: RustRuntime,
+    pub(crate) inner: RustRuntime,
+    /// Set by `rune_runtime_enable_profiling()`.
+    pub(crate) profile: Option<Arc<Mutex<Profile>>>,
 }
 
 impl Deref for Runtime {
@@ -30,6 +38,12 @@ impl DerefMut for Runtime {
 pub struct Config {
     pub rune: *const u8,
     pub rune_len: c_int,
+    /// An optional null-terminated UTF-8 path to a directory where compiled
+    /// artifacts can be cached, so loading the same Rune after a restart is
+    /// faster.
+    ///
+    /// Leave this as `null` to disable caching.
+    pub cache_dir: *const c_char,
 }
 
 #[no_mangle]
@@ -129,6 +143,184 @@ pub unsafe extern "C" fn rune_runtime_output_tensors<'rt>(
     Box::into_raw(Box::new(output_tensors))
 }
 
+/// Make the runtime read a capability's input directly from a buffer owned
+/// by the caller, instead of copying it into an input tensor first.
+///
+/// The buffer is read every time `rune_runtime_predict()` is called, so you
+/// can write the next frame into it between predictions. Its length must
+/// match the size of the capability's tensor.
+///
+/// # Safety
+///
+/// `buffer` must stay valid until it is replaced, removed with
+/// `rune_runtime_clear_input_buffer()`, or the runtime is freed. It must not
+/// be modified while `rune_runtime_predict()` is running.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_set_input_buffer(
+    runtime: *mut Runtime,
+    node_id: u32,
+    buffer: *const u8,
+    len: c_int,
+) -> *mut Error {
+    expect!(!runtime.is_null());
+    expect!(!buffer.is_null());
+    expect!(len >= 0);
+    let runtime = &mut *runtime;
+
+    let buffer = CallerBuffer {
+        ptr: buffer,
+        len: len as usize,
+    };
+
+    match runtime.inner.set_input_buffer(node_id, buffer) {
+        Ok(_) => ptr::null_mut(),
+        Err(e) => Error::boxed(e),
+    }
+}
+
+/// Go back to reading a capability's input from its input tensor, returning
+/// `true` if a buffer had been set with `rune_runtime_set_input_buffer()`.
+#[no_mangle]
+pub unsafe extern "C" fn rune_runtime_clear_input_buffer(
+    runtime: *mut Runtime,
+    node_id: u32,
+) -> bool {
+    if runtime.is_null() {
+        return false;
+    }
+
+    (&mut *runtime).inner.clear_input_buffer(node_id)
+}
+
+/// Memory passed to `rune_runtime_set_input_buffer()`.
+struct CallerBuffer {
+    ptr: *const u8,
+    len: usize,
+}
+
+impl AsRef<[u8]> for CallerBuffer {
+    fn as_ref(&self) -> &[u8] {
+        // Safety: the caller promised the buffer stays valid while it is
+        // attached to the runtime.
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+// Safety: Ensured by the caller.
+unsafe impl Send for CallerBuffer {}
+unsafe impl Sync for CallerBuffer {}
+
+/// Look up the input tensor for a capability by name (e.g. `"SOUND"`),
+/// saving you from searching through `rune_runtime_inputs()` yourself.
+///
+/// Runes don't keep the names from their Runefile, so `name` is matched
+/// case-insensitively against each capability's kind, and it is an error if
+/// zero or several capabilities have that kind.
+///
+/// If the capability exists but no tensor has been provided yet,
+/// `tensor_out` is set to `null` and the tensor can be created with
+/// `rune_input_tensors_insert()` using the ID from `id_out`.
+///
+/// # Safety
+///
+/// The tensor points directly into the runtime's internals, so any use of
+/// the runtime while this reference is alive may invalidate it.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_input_tensor_by_name(
+    runtime: *mut Runtime,
+    name: *const c_char,
+    id_out: *mut u32,
+    tensor_out: *mut *mut Tensor,
+) -> *mut Error {
+    expect!(!runtime.is_null());
+    expect!(!name.is_null());
+    expect!(!id_out.is_null());
+    expect!(!tensor_out.is_null());
+    let runtime = &mut *runtime;
+
+    let id = match node_by_name(runtime.capabilities(), name) {
+        Ok(id) => id,
+        Err(e) => return Error::boxed(e),
+    };
+
+    id_out.write(id);
+    tensor_out.write(match runtime.input_tensors().get_mut(&id) {
+        Some(tensor) => tensor,
+        None => ptr::null_mut(),
+    });
+
+    ptr::null_mut()
+}
+
+/// Look up the most recent tensor written to an output by name (e.g.
+/// `"SERIAL"`), using the same rules as `rune_runtime_input_tensor_by_name()`.
+///
+/// `tensor_out` is set to `null` if nothing was written to the output during
+/// the last call to `rune_runtime_predict()`.
+///
+/// # Safety
+///
+/// The tensor points directly into the runtime's internals, so any use of
+/// the runtime while this reference is alive may invalidate it.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn rune_runtime_output_tensor_by_name(
+    runtime: *const Runtime,
+    name: *const c_char,
+    id_out: *mut u32,
+    tensor_out: *mut *const OutputTensor,
+) -> *mut Error {
+    expect!(!runtime.is_null());
+    expect!(!name.is_null());
+    expect!(!id_out.is_null());
+    expect!(!tensor_out.is_null());
+    let runtime = &*runtime;
+
+    let id = match node_by_name(runtime.outputs(), name) {
+        Ok(id) => id,
+        Err(e) => return Error::boxed(e),
+    };
+
+    id_out.write(id);
+    tensor_out.write(
+        match runtime.output_tensors().get(&id).and_then(|t| t.last()) {
+            Some(tensor) => tensor,
+            None => ptr::null(),
+        },
+    );
+
+    ptr::null_mut()
+}
+
+/// Find the ID of the only node whose kind matches `name`.
+unsafe fn node_by_name(
+    nodes: &HashMap<u32, NodeMetadata>,
+    name: *const c_char,
+) -> Result<u32, anyhow::Error> {
+    let name = CStr::from_ptr(name)
+        .to_str()
+        .map_err(|e| anyhow::Error::new(e).context("Invalid name"))?;
+
+    let mut matches: Vec<u32> = nodes
+        .iter()
+        .filter(|(_, meta)| meta.kind.eq_ignore_ascii_case(name))
+        .map(|(id, _)| *id)
+        .collect();
+    matches.sort_unstable();
+
+    match matches.as_slice() {
+        [id] => Ok(*id),
+        [] => Err(anyhow::anyhow!("There is no \"{}\" node", name)),
+        ids => Err(anyhow::anyhow!(
+            "The name \"{}\" is ambiguous because it could refer to nodes {:?}",
+            name,
+            ids
+        )),
+    }
+}
+
 #[no_mangle]
 #[must_use]
 pub unsafe extern "C" fn rune_runtime_load(
@@ -141,31 +333,92 @@ pub unsafe extern "C" fn rune_runtime_load(
 
     let wasm = slice::from_raw_parts(cfg.rune, cfg.rune_len as usize);
 
-    match load(wasm) {
+    let cache_dir = if cfg.cache_dir.is_null() {
+        None
+    } else {
+        match CStr::from_ptr(cfg.cache_dir).to_str() {
+            Ok(dir) => Some(PathBuf::from(dir)),
+            Err(e) => {
+                return Error::boxed(
+                    anyhow::Error::new(e)
+                        .context("The cache directory isn't valid UTF-8"),
+                )
+            },
+        }
+    };
+
+    load_into(wasm, cache_dir.as_deref(), runtime_out)
+}
+
+/// Load the Rune and write it to `runtime_out`, used by all the
+/// `rune_runtime_load_*()` functions.
+pub(crate) unsafe fn load_into(
+    wasm: &[u8],
+    cache_dir: Option<&Path>,
+    runtime_out: *mut *mut Runtime,
+) -> *mut Error {
+    match load(wasm, cache_dir) {
         Ok(inner) => {
-            runtime_out.write(Box::into_raw(Box::new(Runtime { inner })));
+            runtime_out.write(Box::into_raw(Box::new(Runtime {
+                inner,
+                profile: None,
+            })));
             std::ptr::null_mut()
         },
         Err(e) => Error::boxed(e),
     }
 }
 
-fn load(wasm: &[u8]) -> Result<RustRuntime, LoadError> {
+fn load(
+    wasm: &[u8],
+    cache_dir: Option<&Path>,
+) -> Result<RustRuntime, LoadError> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(feature = "wasmer", feature = "wasm3"))] {
+            let mut builder = RustRuntime::builder().engine(engine()).rune(wasm);
+            if let Some(dir) = cache_dir {
+                builder = builder.cache_dir(dir);
+            }
+
+            builder.build()
+        } else {
+            let _ = (wasm, cache_dir);
+            Err(LoadError::Other(anyhow::Error::msg("")))
+        }
+    }
+}
+
+/// The WebAssembly engine used by the `rune_runtime_load_*()` functions,
+/// preferring Wasmer when it is enabled.
+#[cfg(any(feature = "wasmer", feature = "wasm3"))]
+fn engine() -> RustEngine {
     cfg_if::cfg_if! {
         if #[cfg(feature = "wasmer")] {
-            return RustRuntime::wasmer(wasm);
-        } else if #[cfg(feature = "wasm3")] {
-            return RustRuntime::wasm3(wasm);
+            RustEngine::Wasmer
         } else {
-            let _ = wasm;
-            return Err(LoadError::Other(anyhow::Error::msg("")));
+            RustEngine::Wasm3
         }
     }
 }
 
 pub type Logger = unsafe extern "C" fn(*mut c_void, *const c_char, c_int);
-type Destructor = unsafe extern "C" fn(*mut c_void);
+pub(crate) type Destructor = unsafe extern "C" fn(*mut c_void);
 
+/// Register a callback which will be given each message the Rune logs.
+///
+/// Messages are passed to the `logger` as a JSON object (not null-terminated)
+/// with the following fields:
+///
+/// - `level` - one of `"ERROR"`, `"WARN"`, `"INFO"`, `"DEBUG"`, or `"TRACE"`
+/// - `target` - the module the message came from
+/// - `message` - the message itself
+/// - `node` - the name of the pipeline stage that was running, or `null`
+/// - `timestamp_ms` - when the message was received, in milliseconds since the
+///   Unix epoch
+/// - `module_path`, `file`, and `line` - where the message was logged, if known
+///
+/// The `destructor` (if provided) will be called with `user_data` when the
+/// logger is replaced or the runtime is freed.
 #[no_mangle]
 pub unsafe extern "C" fn rune_runtime_set_logger(
     runtime: *mut Runtime,
@@ -180,8 +433,22 @@ pub unsafe extern "C" fn rune_runtime_set_logger(
     }
 
     impl LogThunk {
-        fn log(&self, record: &Record<'_>) {
-            let record = SerializableRecord::from(record);
+        fn log(&self, record: &LogRecord<'_>) {
+            let timestamp_ms = record
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default();
+            let record = serde_json::json!({
+                "level": record.level.to_string(),
+                "target": record.target,
+                "message": record.message,
+                "node": record.node,
+                "timestamp_ms": timestamp_ms,
+                "module_path": record.module_path,
+                "file": record.file,
+                "line": record.line,
+            });
 
             if let Ok(serialized) = serde_json::to_string(&record) {
                 unsafe {
@@ -1,6 +1,8 @@
 use std::{
     alloc::{GlobalAlloc, Layout},
-    os::raw::c_char,
+    num::NonZeroUsize,
+    os::raw::{c_char, c_int},
+    slice,
 };
 
 pub(crate) fn c_str(rust_str: &str) -> *mut c_char {
@@ -20,3 +22,19 @@ pub(crate) fn c_str(rust_str: &str) -> *mut c_char {
         buffer.cast()
     }
 }
+
+/// Read a tensor's dimensions from an array of `rank` elements, returning
+/// `None` if the array is empty or contains a zero.
+pub(crate) unsafe fn dimensions_from_raw(
+    dimensions: *const usize,
+    rank: c_int,
+) -> Option<Vec<NonZeroUsize>> {
+    if dimensions.is_null() || rank <= 0 {
+        return None;
+    }
+
+    slice::from_raw_parts(dimensions, rank as usize)
+        .iter()
+        .map(|&d| NonZeroUsize::new(d))
+        .collect()
+}
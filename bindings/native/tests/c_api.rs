@@ -1,8 +1,12 @@
 use std::{
-    ffi::CStr, os::raw::c_int, path::Path, process::Command, ptr, slice,
+    ffi::CStr,
+    os::raw::{c_int, c_void},
+    path::Path,
+    process::Command,
+    ptr, slice,
 };
 
-use hotg_rune_runtime::ElementType;
+use hotg_rune_runtime::{ElementType, OutputTensor, Tensor};
 use once_cell::sync::Lazy;
 use rune_native::*;
 
@@ -29,6 +33,7 @@ fn load_the_sine_rune() {
         let cfg = Config {
             rune: SINE_RUNE.as_ptr(),
             rune_len: SINE_RUNE.len() as c_int,
+            cache_dir: ptr::null(),
         };
 
         let error = rune_runtime_load(&cfg, &mut runtime);
@@ -46,6 +51,7 @@ fn run_prediction_with_missing_input() {
         let cfg = Config {
             rune: SINE_RUNE.as_ptr(),
             rune_len: SINE_RUNE.len() as c_int,
+            cache_dir: ptr::null(),
         };
 
         let error = rune_runtime_load(&cfg, &mut runtime);
@@ -74,6 +80,7 @@ fn inspect_input_metadata() {
         let cfg = Config {
             rune: SINE_RUNE.as_ptr(),
             rune_len: SINE_RUNE.len() as c_int,
+            cache_dir: ptr::null(),
         };
 
         let error = rune_runtime_load(&cfg, &mut runtime);
@@ -112,6 +119,7 @@ fn set_inputs() {
         let cfg = Config {
             rune: SINE_RUNE.as_ptr(),
             rune_len: SINE_RUNE.len() as c_int,
+            cache_dir: ptr::null(),
         };
 
         let error = rune_runtime_load(&cfg, &mut runtime);
@@ -148,6 +156,55 @@ fn set_inputs() {
     }
 }
 
+#[test]
+fn look_up_tensors_by_name() {
+    unsafe {
+        let mut runtime: *mut Runtime = ptr::null_mut();
+        let cfg = Config {
+            rune: SINE_RUNE.as_ptr(),
+            rune_len: SINE_RUNE.len() as c_int,
+            cache_dir: ptr::null(),
+        };
+
+        let error = rune_runtime_load(&cfg, &mut runtime);
+        assert!(error.is_null());
+
+        let mut id = 0;
+        let mut tensor: *mut Tensor = ptr::null_mut();
+        let error = rune_runtime_input_tensor_by_name(
+            runtime,
+            b"raw\0".as_ptr().cast(),
+            &mut id,
+            &mut tensor,
+        );
+        assert!(error.is_null());
+        assert_eq!(id, 1);
+        assert!(tensor.is_null());
+
+        let mut output: *const OutputTensor = ptr::null();
+        let error = rune_runtime_output_tensor_by_name(
+            runtime,
+            b"SERIAL\0".as_ptr().cast(),
+            &mut id,
+            &mut output,
+        );
+        assert!(error.is_null());
+        assert_eq!(id, 3);
+        assert!(output.is_null());
+
+        let error = rune_runtime_input_tensor_by_name(
+            runtime,
+            b"IMAGE\0".as_ptr().cast(),
+            &mut id,
+            &mut tensor,
+        );
+        assert!(!error.is_null());
+
+        rune_error_free(error);
+        rune_runtime_free(runtime);
+    }
+}
+
 #[test]
 fn inspect_output_metadata() {
     unsafe {
@@ -155,6 +212,7 @@ fn inspect_output_metadata() {
         let cfg = Config {
             rune: SINE_RUNE.as_ptr(),
             rune_len: SINE_RUNE.len() as c_int,
+            cache_dir: ptr::null(),
         };
 
         let error = rune_runtime_load(&cfg, &mut runtime);
@@ -189,6 +247,7 @@ fn run_the_sine_rune() {
         let cfg = Config {
             rune: SINE_RUNE.as_ptr(),
             rune_len: SINE_RUNE.len() as c_int,
+            cache_dir: ptr::null(),
         };
 
         let error = rune_runtime_load(&cfg, &mut runtime);
@@ -220,3 +279,118 @@ fn run_the_sine_rune() {
         rune_runtime_free(runtime);
     }
 }
+
+#[test]
+fn provide_inputs_and_receive_outputs_with_callbacks() {
+    #[derive(Default)]
+    struct Calls {
+        inputs: usize,
+        outputs: usize,
+    }
+
+    unsafe extern "C" fn fill_input(
+        user_data: *mut c_void,
+        node_id: u32,
+        tensor: *mut Tensor,
+    ) -> *mut Error {
+        let calls = &mut *user_data.cast::<Calls>();
+        calls.inputs += 1;
+
+        assert_eq!(node_id, 1);
+        assert_eq!(rune_tensor_buffer_len(tensor), 4);
+        let data = [1_u8, 2, 3, 4];
+        ptr::copy_nonoverlapping(
+            data.as_ptr(),
+            rune_tensor_buffer(tensor),
+            data.len(),
+        );
+
+        ptr::null_mut()
+    }
+
+    unsafe extern "C" fn count_outputs(
+        user_data: *mut c_void,
+        node_id: u32,
+        tensors: *mut OutputTensors<'_>,
+    ) -> *mut Error {
+        let calls = &mut *user_data.cast::<Calls>();
+
+        let mut id = 0;
+        let mut tensor: *const OutputTensor = ptr::null();
+        while rune_output_tensors_next(tensors, &mut id, &mut tensor) {
+            assert_eq!(id, node_id);
+            calls.outputs += 1;
+        }
+
+        ptr::null_mut()
+    }
+
+    unsafe {
+        let mut runtime: *mut Runtime = ptr::null_mut();
+        let cfg = Config {
+            rune: SINE_RUNE.as_ptr(),
+            rune_len: SINE_RUNE.len() as c_int,
+            cache_dir: ptr::null(),
+        };
+        let error = rune_runtime_load(&cfg, &mut runtime);
+        assert!(error.is_null());
+
+        let mut calls = Calls::default();
+        let user_data: *mut c_void = (&mut calls as *mut Calls).cast();
+        let dims = [1, 4];
+
+        let error = rune_runtime_set_input_callback(
+            runtime,
+            1,
+            ElementType::U8,
+            dims.as_ptr(),
+            2,
+            fill_input,
+            user_data,
+            None,
+        );
+        assert!(error.is_null());
+        let error = rune_runtime_set_output_callback(
+            runtime,
+            3,
+            count_outputs,
+            user_data,
+            None,
+        );
+        assert!(error.is_null());
+
+        for _ in 0..2 {
+            let error = rune_runtime_predict(runtime);
+            assert!(error.is_null());
+        }
+
+        assert_eq!(calls.inputs, 2);
+        assert_eq!(calls.outputs, 2);
+
+        rune_runtime_free(runtime);
+    }
+}
+
+#[test]
+fn load_a_missing_file() {
+    unsafe {
+        let mut runtime: *mut Runtime = ptr::null_mut();
+
+        let error = rune_runtime_load_from_file(
+            b"does-not-exist.rune\0".as_ptr().cast(),
+            &mut runtime,
+        );
+        assert!(!error.is_null());
+
+        assert_eq!(rune_error_kind(error), ErrorKind::ReadFailed);
+        let msg = rune_error_to_string(error);
+        assert_eq!(
+            CStr::from_ptr(msg).to_str(),
+            Ok("Unable to read \"does-not-exist.rune\" because it doesn't \
+                exist")
+        );
+        libc::free(msg.cast());
+
+        rune_error_free(error);
+    }
+}
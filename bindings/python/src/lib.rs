@@ -0,0 +1,38 @@
+//! Python bindings to the Rune runtime.
+//!
+//! ```python
+//! import numpy as np
+//! from hotg_rune import Runtime
+//!
+//! runtime = Runtime.from_file("sine.rune")
+//! runtime.set_input("RAW", np.array([[0.5]], dtype=np.float32))
+//! runtime.predict()
+//!
+//! for output_id, tensors in runtime.output_tensors().items():
+//!     print(output_id, tensors)
+//! ```
+//!
+//! Numeric tensors are passed around as numpy arrays, while string tensors
+//! are a flat list of `str`s.
+
+mod metadata;
+mod runtime;
+mod tensor;
+
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+
+pub use crate::{metadata::Node, runtime::Runtime};
+
+#[pymodule]
+fn hotg_rune(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Runtime>()?;
+    m.add_class::<Node>()?;
+
+    Ok(())
+}
+
+/// Turn an error from the runtime into a Python `RuntimeError`, keeping the
+/// full chain of causes in its message.
+pub(crate) fn to_py_err(error: impl Into<anyhow::Error>) -> PyErr {
+    PyRuntimeError::new_err(format!("{:?}", error.into()))
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use hotg_rune_runtime::NodeMetadata;
+use pyo3::prelude::*;
+
+/// Metadata for an input or output node in the ML pipeline.
+#[pyclass]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    /// The ID used when setting inputs and reading outputs.
+    #[pyo3(get)]
+    pub id: u32,
+    /// The kind of node (e.g. `"RAW"` or `"SERIAL"`).
+    #[pyo3(get)]
+    pub kind: String,
+    #[pyo3(get)]
+    pub arguments: HashMap<String, String>,
+}
+
+#[pymethods]
+impl Node {
+    fn __repr__(&self) -> String {
+        format!(
+            "Node(id={}, kind={:?}, arguments={:?})",
+            self.id, self.kind, self.arguments
+        )
+    }
+}
+
+/// Get the [`Node`]s for a set of nodes, sorted by ID.
+pub(crate) fn nodes(metadata: &HashMap<u32, NodeMetadata>) -> Vec<Node> {
+    let mut nodes: Vec<Node> = metadata
+        .iter()
+        .map(|(&id, meta)| Node {
+            id,
+            kind: meta.kind.clone(),
+            arguments: meta.arguments.clone(),
+        })
+        .collect();
+    nodes.sort_by_key(|n| n.id);
+
+    nodes
+}
+
+/// A node's ID, or its kind (e.g. `"SOUND"`).
+#[derive(Debug, Clone, PartialEq, FromPyObject)]
+pub(crate) enum NodeRef {
+    Id(u32),
+    Name(String),
+}
+
+impl NodeRef {
+    /// Find the ID this refers to.
+    ///
+    /// Runes don't keep the names from their Runefile, so a name is matched
+    /// case-insensitively against each node's kind and it is an error if
+    /// zero or several nodes have that kind.
+    pub(crate) fn resolve<'a>(
+        &self,
+        nodes: impl IntoIterator<Item = (u32, &'a str)>,
+    ) -> Result<u32, anyhow::Error> {
+        let mut matches: Vec<u32> = match self {
+            NodeRef::Id(id) => nodes
+                .into_iter()
+                .filter(|(candidate, _)| candidate == id)
+                .map(|(id, _)| id)
+                .collect(),
+            NodeRef::Name(name) => nodes
+                .into_iter()
+                .filter(|(_, kind)| kind.eq_ignore_ascii_case(name))
+                .map(|(id, _)| id)
+                .collect(),
+        };
+        matches.sort_unstable();
+
+        match (self, matches.as_slice()) {
+            (_, [id]) => Ok(*id),
+            (NodeRef::Id(id), _) => {
+                Err(anyhow::anyhow!("There is no node with ID {}", id))
+            },
+            (NodeRef::Name(name), []) => {
+                Err(anyhow::anyhow!("There is no \"{}\" node", name))
+            },
+            (NodeRef::Name(name), ids) => Err(anyhow::anyhow!(
+                "The name \"{}\" is ambiguous because it could refer to nodes \
+                 {:?}",
+                name,
+                ids
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn look_up_nodes_by_id_or_kind() {
+        let nodes = vec![(1, "RAW"), (2, "SOUND"), (3, "SOUND")];
+
+        assert_eq!(NodeRef::Id(2).resolve(nodes.clone()).unwrap(), 2);
+        assert_eq!(
+            NodeRef::Name("raw".to_string())
+                .resolve(nodes.clone())
+                .unwrap(),
+            1
+        );
+        assert!(NodeRef::Id(42).resolve(nodes.clone()).is_err());
+
+        let err = NodeRef::Name("SOUND".to_string())
+            .resolve(nodes)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The name \"SOUND\" is ambiguous because it could refer to nodes \
+             [2, 3]"
+        );
+    }
+}
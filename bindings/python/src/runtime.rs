@@ -0,0 +1,113 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use hotg_rune_runtime::{LoadError, Runtime as RustRuntime};
+use pyo3::{exceptions::PyIOError, prelude::*};
+
+use crate::{
+    metadata::{self, Node, NodeRef},
+    tensor, to_py_err,
+};
+
+/// A loaded Rune.
+#[pyclass(unsendable)]
+pub struct Runtime {
+    inner: RustRuntime,
+}
+
+#[pymethods]
+impl Runtime {
+    /// Load a Rune from the bytes of its WebAssembly module.
+    #[new]
+    fn new(wasm: &[u8]) -> PyResult<Self> {
+        let inner = load(wasm).map_err(to_py_err)?;
+        Ok(Runtime { inner })
+    }
+
+    /// Load a Rune from a file on disk.
+    #[staticmethod]
+    fn from_file(path: PathBuf) -> PyResult<Self> {
+        let wasm = std::fs::read(&path).map_err(|e| {
+            PyIOError::new_err(format!(
+                "Unable to read \"{}\": {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        Runtime::new(&wasm)
+    }
+
+    /// The Rune's inputs (capabilities), sorted by ID.
+    #[getter]
+    fn inputs(&self) -> Vec<Node> { metadata::nodes(self.inner.capabilities()) }
+
+    /// The Rune's outputs, sorted by ID.
+    #[getter]
+    fn outputs(&self) -> Vec<Node> { metadata::nodes(self.inner.outputs()) }
+
+    /// Set the tensor passed to a capability, where `node` is either the
+    /// capability's ID or its kind (e.g. `"SOUND"`).
+    ///
+    /// The `tensor` can be a numpy array or a list of strings.
+    fn set_input(&mut self, node: NodeRef, tensor: &PyAny) -> PyResult<()> {
+        let capabilities = self.inner.capabilities();
+        let id = node
+            .resolve(capabilities.iter().map(|(&id, m)| (id, m.kind.as_str())))
+            .map_err(to_py_err)?;
+        let tensor = tensor::from_python(tensor)?;
+
+        self.inner.input_tensors().insert(id, tensor);
+
+        Ok(())
+    }
+
+    /// Run the Rune.
+    fn predict(&mut self) -> PyResult<()> {
+        self.inner.predict().map_err(to_py_err)
+    }
+
+    /// Run the Rune once for each item in a batch, where every input has an
+    /// extra leading dimension of `batch_size`.
+    fn predict_batch(&mut self, batch_size: usize) -> PyResult<()> {
+        self.inner.predict_batch(batch_size).map_err(to_py_err)
+    }
+
+    /// Get the tensors written to each output, keyed by output ID.
+    fn output_tensors(
+        &self,
+        py: Python<'_>,
+    ) -> PyResult<HashMap<u32, Vec<PyObject>>> {
+        self.inner
+            .output_tensors()
+            .iter()
+            .map(|(&id, tensors)| {
+                let tensors = tensors
+                    .iter()
+                    .map(|t| tensor::to_python(py, t))
+                    .collect::<PyResult<Vec<_>>>()?;
+                Ok((id, tensors))
+            })
+            .collect()
+    }
+
+    /// The IDs of the outputs which were written to during the last run.
+    #[getter]
+    fn updated_outputs(&self) -> Vec<u32> {
+        self.inner.updated_outputs().to_vec()
+    }
+}
+
+fn load(wasm: &[u8]) -> Result<RustRuntime, LoadError> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "wasmer")] {
+            RustRuntime::wasmer(wasm)
+        } else if #[cfg(feature = "wasm3")] {
+            RustRuntime::wasm3(wasm)
+        } else {
+            let _ = wasm;
+            Err(LoadError::Other(anyhow::Error::msg(
+                "The Python bindings were compiled without a WebAssembly engine",
+            )))
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Converting between [`Tensor`]s and Python objects.
+
+use hotg_rune_runtime::{ElementType, OutputTensor, Tensor};
+use numpy::{IntoPyArray, PyReadonlyArrayDyn};
+use pyo3::{
+    exceptions::{PyTypeError, PyValueError},
+    prelude::*,
+    types::PyList,
+};
+
+/// Convert a numpy array (or a list of strings) into a [`Tensor`].
+pub(crate) fn from_python(obj: &PyAny) -> PyResult<Tensor> {
+    macro_rules! try_array {
+        ($($ty:ty),* $(,)?) => {
+            $(
+                if let Ok(array) = obj.extract::<PyReadonlyArrayDyn<'_, $ty>>() {
+                    let array = array.as_array();
+                    check_dimensions(array.shape())?;
+                    return Ok(Tensor::from_ndarray(&array));
+                }
+            )*
+        };
+    }
+
+    try_array!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+    if let Ok(list) = obj.downcast::<PyList>() {
+        let strings: Vec<String> = list.extract()?;
+        check_dimensions(&[strings.len()])?;
+        return Ok(Tensor::from_strings(&strings, &[strings.len()]));
+    }
+
+    Err(PyTypeError::new_err(format!(
+        "Expected a numpy array or a list of strings, but found a {}",
+        obj.get_type().name()?
+    )))
+}
+
+/// Convert an output tensor into a numpy array, or a flat list for string
+/// tensors.
+pub(crate) fn to_python(
+    py: Python<'_>,
+    tensor: &OutputTensor,
+) -> PyResult<PyObject> {
+    let tensor = match tensor {
+        OutputTensor::Tensor(t) => t,
+        OutputTensor::StringTensor { strings, .. } => {
+            return Ok(strings.to_object(py))
+        },
+    };
+
+    macro_rules! to_array {
+        ($ty:ty) => {
+            tensor
+                .to_ndarray::<$ty>()
+                .map(|array| array.into_pyarray(py).to_object(py))
+        };
+    }
+
+    let array = match tensor.element_type() {
+        ElementType::U8 => to_array!(u8),
+        ElementType::I8 => to_array!(i8),
+        ElementType::U16 => to_array!(u16),
+        ElementType::I16 => to_array!(i16),
+        ElementType::U32 => to_array!(u32),
+        ElementType::I32 => to_array!(i32),
+        ElementType::F32 => to_array!(f32),
+        ElementType::U64 => to_array!(u64),
+        ElementType::I64 => to_array!(i64),
+        ElementType::F64 => to_array!(f64),
+        ElementType::Utf8 => {
+            return Ok(tensor.strings().unwrap_or_default().to_object(py))
+        },
+        other => {
+            return Err(PyTypeError::new_err(format!(
+                "{} tensors can't be converted to numpy arrays",
+                other
+            )))
+        },
+    };
+
+    array.ok_or_else(|| PyValueError::new_err("The tensor was misaligned"))
+}
+
+fn check_dimensions(dimensions: &[usize]) -> PyResult<()> {
+    if dimensions.is_empty() || dimensions.contains(&0) {
+        return Err(PyValueError::new_err(format!(
+            "Tensors can't be empty, but the dimensions were {:?}",
+            dimensions
+        )));
+    }
+
+    Ok(())
+}
@@ -5,6 +5,11 @@ import Shape from "./Shape";
  */
 export interface Output {
     consume(data: Uint8Array): void;
+    /**
+     * Called when the Rune configures the output (e.g. to tell the host which
+     * "format" a SERIAL output's messages are encoded with).
+     */
+    setParameter?(name: string, value: string): void;
 }
 
 /**
@@ -126,11 +131,15 @@ function importsToHostFunctions(
     const ids = counter();
     const outputs: Dict<number, Output> = {};
     const capabilities: Dict<number, Capability> = {};
+    // Tensors being copied across the boundary a chunk at a time
+    const pendingInputs: Dict<number, Uint8Array> = {};
+    const pendingOutputs: Dict<number, Uint8Array> = {};
     const pendingModels: Promise<[number, Model]>[] = [];
     const models: Record<number, Model> = {};
     const modelsDescription: Record<number, ModelInfo> = {};
     const utf8 = new TextDecoder();
     const decoder = new TextDecoder("utf8");
+    let pendingAssertion: string | undefined;
 
     // Annoyingly, this needs to be an object literal instead of a class.
     const env = {
@@ -160,6 +169,41 @@ function importsToHostFunctions(
             }
         },
 
+        rune_panic(msg: number, msgLen: number, file: number, fileLen: number, line: number, column: number, stage: number, stageLen: number) {
+            const message = utf8.decode(memory().subarray(msg, msg + msgLen));
+            const location = utf8.decode(memory().subarray(file, file + fileLen));
+            const stageName = utf8.decode(memory().subarray(stage, stage + stageLen));
+            const inStage = stageName ? ` in ${stageName} block` : "";
+            const assertion = pendingAssertion;
+            pendingAssertion = undefined;
+
+            if (assertion) {
+                throw new Error(`${assertion}${inStage} at ${location}:${line}:${column}`);
+            }
+
+            throw new Error(`${message}${inStage} at ${location}:${line}:${column}`);
+        },
+
+        rune_assertion_failed(expr: number, exprLen: number, msg: number, msgLen: number, names: number, values: number, count: number) {
+            const readString = (ptr: number, len: number) => utf8.decode(memory().subarray(ptr, ptr + len));
+            const view = new DataView(memory().buffer);
+            const expression = readString(expr, exprLen);
+            const message = readString(msg, msgLen);
+
+            let description = `Assertion failed: ${expression}`;
+            if (message) {
+                description += ` (${message})`;
+            }
+
+            for (let i = 0; i < count; i++) {
+                const name = readString(view.getUint32(names + 8 * i, true), view.getUint32(names + 8 * i + 4, true));
+                const value = readString(view.getUint32(values + 8 * i, true), view.getUint32(values + 8 * i + 4, true));
+                description += `, ${name}: ${value}`;
+            }
+
+            pendingAssertion = description;
+        },
+
         request_output(type: number) {
             const output = imports.createOutput(type);
             const id = ids();
@@ -168,6 +212,23 @@ function importsToHostFunctions(
             return id;
         },
 
+        request_output_set_param(id: number,
+            keyPtr: number,
+            keyLength: number,
+            valuePtr: number,
+            valueLength: number) {
+            const key = decoder.decode(memory().subarray(keyPtr, keyPtr + keyLength));
+            const value = decoder.decode(memory().subarray(valuePtr, valuePtr + valueLength));
+
+            const output = outputs[id];
+
+            if (!output) {
+                throw new Error(`Tried to set "${key}" to "${value}" but output ${id} doesn't exist`);
+            }
+
+            output.setParameter?.(key, value);
+        },
+
         consume_output(id: number, buffer: number, len: number) {
             const output = outputs[id];
             if (output) {
@@ -179,6 +240,32 @@ function importsToHostFunctions(
             }
         },
 
+        consume_output_chunk(id: number, offset: bigint, buffer: number, len: number, totalLen: bigint) {
+            const output = outputs[id];
+            if (!output) {
+                throw new Error("Invalid output");
+            }
+
+            const start = Number(offset);
+            const total = Number(totalLen);
+            if (start == 0) {
+                pendingOutputs[id] = new Uint8Array(total);
+            }
+
+            const pending = pendingOutputs[id];
+            if (!pending || start + len > pending.length) {
+                throw new Error(`Invalid chunk at offset ${start} for output ${id}`);
+            }
+            pending.set(memory().subarray(buffer, buffer + len), start);
+
+            if (start + len == total) {
+                delete pendingOutputs[id];
+                output.consume(pending);
+            }
+
+            return len;
+        },
+
         request_capability(type: number) {
             const capability = imports.createCapability(type);
             const id = ids();
@@ -217,6 +304,56 @@ function importsToHostFunctions(
             cap.generate(dest);
         },
 
+        request_provider_response_chunk(id: number, offset: bigint, buffer: number, len: number, totalLen: bigint) {
+            const cap = capabilities[id];
+            if (!cap) {
+                throw new Error("Invalid capability");
+            }
+
+            const start = Number(offset);
+            if (start == 0) {
+                const data = new Uint8Array(Number(totalLen));
+                cap.generate(data);
+                pendingInputs[id] = data;
+            }
+
+            const pending = pendingInputs[id];
+            if (!pending) {
+                throw new Error(`Invalid chunk at offset ${start} for capability ${id}`);
+            }
+            const chunk = pending.subarray(start, start + len);
+            memory().set(chunk, buffer);
+
+            if (start + chunk.length >= pending.length) {
+                delete pendingInputs[id];
+            }
+
+            return chunk.length;
+        },
+
+        rune_model_select_variant(names: number, sizes: number, count: number) {
+            // The browser doesn't tell us how much memory is available, so
+            // always use the model's default variant.
+            return 0;
+        },
+
+        rune_model_name(name: number, name_len: number) {
+            // Models are only referred to by ID in the browser
+        },
+
+        rune_node_name(id: number, name: number, name_len: number): number {
+            // Capabilities and outputs are only referred to by ID in the browser
+            return 0;
+        },
+
+        rune_proc_block_parameter(node: number, node_len: number, type_name: number, type_name_len: number, name: number, name_len: number, value: number, value_len: number) {
+            // Proc-block parameters can't be changed from the browser yet
+        },
+
+        rune_proc_block_parameter_updates(buffer: number, buffer_len: number): number {
+            return 0;
+        },
+
         rune_model_load(mimetype: number, mimetype_len: number, model: number, model_len: number, input_descriptors: number, input_len: number, output_descriptors: number, output_len: number) {
             const mime = decoder.decode(memory().subarray(mimetype, mimetype + mimetype_len));
             const model_data = memory().subarray(model, model + model_len);
@@ -365,6 +502,9 @@ function decodeValue(valueType: number, raw: Uint8Array): number {
         case 7:
             const i8s = new Int8Array(bytes);
             return i8s[0];
+        case 8:
+            const f64s = new Float64Array(bytes);
+            return f64s[0];
 
         default:
             throw new Error(`Unknown value type, ${valueType}, with binary representation, ${raw}`);
@@ -14,6 +14,7 @@ export const Capabilities = {
     "image": 4,
     "raw": 5,
     "float-image": 6,
+    "stream": 7,
 } as const;
 
 /**
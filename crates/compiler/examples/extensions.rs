@@ -8,7 +8,8 @@
 //!    `foo`) have the corresponding environment variable set (e.g. `$FOO`). If
 //!    so, the resource's [`ResourceData`] component is overridden with its
 //!    value.
-//! 3. Print out some any diagnostics at the end so we can see the effects from
+//! 3. Add a `NOTICE` file to the generated Rust project
+//! 4. Print out some any diagnostics at the end so we can see the effects from
 //!    step 1.
 
 use std::{fmt::Write as _, path::Path};
@@ -16,6 +17,7 @@ use std::{fmt::Write as _, path::Path};
 use codespan_reporting::diagnostic::{Diagnostic, Severity};
 use env_logger::Env;
 use hotg_rune_compiler::{
+    codegen::File,
     hooks::{
         AfterCodegenContext, AfterLoweringContext, AfterTypeCheckingContext,
         Continuation, Hooks,
@@ -82,9 +84,7 @@ impl Hooks for CustomHooks {
         &mut self,
         ctx: &mut dyn AfterCodegenContext,
     ) -> Continuation {
-        for file in
-            <&hotg_rune_compiler::codegen::File>::query().iter(ctx.world())
-        {
+        for file in <&File>::query().iter(ctx.world()) {
             let mut msg = String::new();
 
             match core::str::from_utf8(&file.data) {
@@ -98,6 +98,11 @@ impl Hooks for CustomHooks {
             log::info!("Reading: {}\n{}", file.path.display(), msg);
         }
 
+        ctx.add_file(File::new(
+            "NOTICE",
+            b"This Rune was built with the extensions example".to_vec(),
+        ));
+
         Continuation::Continue
     }
 }
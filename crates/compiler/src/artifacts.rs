@@ -0,0 +1,528 @@
+//! A content-addressed store for compiled Runes.
+//!
+//! Every successful build is saved under an [`ArtifactKey`] derived from the
+//! [`BuildContext`], the generated project and the sources of any local
+//! (`path = "..."`) dependencies, so rebuilding a Rune that
+//! hasn't changed (e.g. when [`crate::watch`] sees a file being reverted) can
+//! skip `cargo build` entirely.
+//!
+//! The store lives in `$build_cache_dir/artifacts/` (see
+//! [`FeatureFlags::set_build_cache_dir()`]) and is kept in check with a
+//! [`PruningPolicy`] after each build.
+//!
+//! [`FeatureFlags::set_build_cache_dir()`]: crate::FeatureFlags::set_build_cache_dir
+
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use cargo_toml::Manifest;
+use sha2::{Digest, Sha256};
+
+use crate::{codegen::File, compile::CompiledBinary, BuildContext};
+
+const RUNE_FILE: &str = "rune.wasm";
+const METADATA_FILE: &str = "metadata.json";
+
+/// The hash used to identify a build.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct ArtifactKey(pub String);
+
+impl ArtifactKey {
+    /// Calculate the key for building a project containing `files` with a
+    /// particular [`BuildContext`].
+    ///
+    /// The order of `files` doesn't matter. Any `path` dependencies (or
+    /// patches) in the generated `Cargo.toml` are part of the key too, so
+    /// editing a local proc-block invalidates the previous build.
+    pub fn new<'a>(
+        ctx: &BuildContext,
+        files: impl IntoIterator<Item = &'a File>,
+    ) -> Self {
+        let mut files: Vec<&File> = files.into_iter().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        // Where the project gets emitted to doesn't change what we compile
+        let ctx = &BuildContext {
+            emit_project: None,
+            ..ctx.clone()
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(
+            serde_json::to_vec(ctx)
+                .expect("The build context is always serializable"),
+        );
+
+        for File { path, data } in &files {
+            hash_file(&mut hasher, path, data);
+        }
+
+        let manifest = files.iter().find(|f| f.path == Path::new("Cargo.toml"));
+
+        if let Some(File { data, .. }) = manifest {
+            for dir in path_dependencies(data, &ctx.working_directory) {
+                hash_directory(&mut hasher, &dir, &dir);
+            }
+        }
+
+        ArtifactKey(format!("{:x}", hasher.finalize()))
+    }
+}
+
+fn hash_file(hasher: &mut Sha256, path: &Path, data: &[u8]) {
+    let path = path.to_string_lossy();
+    hasher.update((path.len() as u64).to_le_bytes());
+    hasher.update(path.as_bytes());
+    hasher.update((data.len() as u64).to_le_bytes());
+    hasher.update(data);
+}
+
+/// Every local directory the generated `Cargo.toml` depends on.
+fn path_dependencies(
+    cargo_toml: &[u8],
+    working_dir: &Path,
+) -> BTreeSet<PathBuf> {
+    let manifest: Manifest = match toml::from_slice(cargo_toml) {
+        Ok(m) => m,
+        // We generated the file, so this should never happen. If it does,
+        // the build will fail anyway.
+        Err(_) => return BTreeSet::new(),
+    };
+
+    let patches = manifest.patch.values().flat_map(|deps| deps.values());
+
+    manifest
+        .dependencies
+        .values()
+        .chain(patches)
+        .filter_map(|dep| dep.detail()?.path.as_deref())
+        .map(|path| working_dir.join(path))
+        .collect()
+}
+
+/// Hash the contents of a crate's source tree, skipping build outputs and
+/// hidden files (e.g. `.git/`).
+fn hash_directory(hasher: &mut Sha256, root: &Path, dir: &Path) {
+    let mut entries: Vec<PathBuf> = match dir.read_dir() {
+        Ok(entries) => {
+            entries.filter_map(|e| e.ok()).map(|e| e.path()).collect()
+        },
+        Err(e) => {
+            log::debug!("Unable to read \"{}\": {}", dir.display(), e);
+            return;
+        },
+    };
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+
+        if path.is_dir() {
+            hash_directory(hasher, root, &path);
+        } else {
+            match std::fs::read(&path) {
+                Ok(data) => hash_file(hasher, relative, &data),
+                Err(e) => {
+                    log::debug!("Unable to read \"{}\": {}", path.display(), e)
+                },
+            }
+        }
+    }
+}
+
+impl Display for ArtifactKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+/// Information about a previous build.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ArtifactInfo {
+    pub key: ArtifactKey,
+    /// The name of the Rune that was compiled.
+    pub name: String,
+    pub optimized: bool,
+    /// The size of the compiled Rune, in bytes.
+    pub size: u64,
+    /// When the artifact was added to the store.
+    pub created: SystemTime,
+    /// The last time the artifact was retrieved (or created).
+    pub last_used: SystemTime,
+}
+
+/// Limits used when deciding which artifacts to delete.
+///
+/// The least recently used artifacts are removed first.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PruningPolicy {
+    /// The maximum number of artifacts to keep.
+    pub max_entries: Option<usize>,
+    /// Remove artifacts which haven't been used for this long.
+    pub max_age: Option<Duration>,
+    /// The maximum number of bytes all artifacts may take up.
+    pub max_total_size: Option<u64>,
+}
+
+impl PruningPolicy {
+    /// The policy used by default, which keeps the store to a reasonable
+    /// size without throwing away recent builds.
+    pub const fn bounded() -> Self {
+        PruningPolicy {
+            max_entries: Some(64),
+            max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+            max_total_size: Some(512 * 1024 * 1024),
+        }
+    }
+
+    /// A policy that never removes anything.
+    pub const fn keep_everything() -> Self {
+        PruningPolicy {
+            max_entries: None,
+            max_age: None,
+            max_total_size: None,
+        }
+    }
+}
+
+impl Default for PruningPolicy {
+    fn default() -> Self { PruningPolicy::bounded() }
+}
+
+/// A directory containing compiled Runes, indexed by [`ArtifactKey`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactStore {
+    root: PathBuf,
+}
+
+impl ArtifactStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        ArtifactStore { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path { &self.root }
+
+    /// Save a compiled Rune, replacing any previous artifact with the same
+    /// key.
+    pub fn insert(
+        &self,
+        key: &ArtifactKey,
+        ctx: &BuildContext,
+        binary: &CompiledBinary,
+    ) -> Result<ArtifactInfo, Error> {
+        let dir = self.entry(key);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(RUNE_FILE), binary.as_ref())?;
+
+        let now = SystemTime::now();
+        let info = ArtifactInfo {
+            key: key.clone(),
+            name: ctx.name.clone(),
+            optimized: ctx.optimized,
+            size: binary.len() as u64,
+            created: now,
+            last_used: now,
+        };
+        write_info(&dir, &info)?;
+
+        Ok(info)
+    }
+
+    /// Look up a previous build, marking it as recently used.
+    pub fn get(
+        &self,
+        key: &ArtifactKey,
+    ) -> Result<Option<CompiledBinary>, Error> {
+        let dir = self.entry(key);
+        let mut info = match read_info(&dir) {
+            Ok(info) => info,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let binary = match std::fs::read(dir.join(RUNE_FILE)) {
+            Ok(bytes) => CompiledBinary::from(bytes),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        info.last_used = SystemTime::now();
+        write_info(&dir, &info)?;
+
+        Ok(Some(binary))
+    }
+
+    /// Every artifact in the store, most recently used first.
+    ///
+    /// Entries which can't be read (e.g. because a build was interrupted
+    /// while saving) are skipped.
+    pub fn list(&self) -> Result<Vec<ArtifactInfo>, Error> {
+        let entries = match self.root.read_dir() {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut artifacts = Vec::new();
+
+        for entry in entries {
+            let path = entry?.path();
+
+            match read_info(&path) {
+                Ok(info) => artifacts.push(info),
+                Err(e) => log::debug!(
+                    "Skipping \"{}\" in the artifact store: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        artifacts.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+
+        Ok(artifacts)
+    }
+
+    pub fn remove(&self, key: &ArtifactKey) -> Result<(), Error> {
+        match std::fs::remove_dir_all(self.entry(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Delete artifacts until the store satisfies the [`PruningPolicy`],
+    /// returning the keys that were removed.
+    pub fn prune(
+        &self,
+        policy: &PruningPolicy,
+    ) -> Result<Vec<ArtifactKey>, Error> {
+        let now = SystemTime::now();
+        let mut removed = Vec::new();
+        let mut kept = 0;
+        let mut total_size = 0;
+
+        for info in self.list()? {
+            let age = now.duration_since(info.last_used).unwrap_or_default();
+
+            let keep = policy.max_entries.map_or(true, |max| kept < max)
+                && policy.max_age.map_or(true, |max| age <= max)
+                && policy
+                    .max_total_size
+                    .map_or(true, |max| total_size + info.size <= max);
+
+            if keep {
+                kept += 1;
+                total_size += info.size;
+            } else {
+                log::debug!("Removing \"{}\" ({})", info.name, info.key);
+                self.remove(&info.key)?;
+                removed.push(info.key);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    fn entry(&self, key: &ArtifactKey) -> PathBuf { self.root.join(&key.0) }
+}
+
+fn read_info(dir: &Path) -> Result<ArtifactInfo, Error> {
+    let json = std::fs::read(dir.join(METADATA_FILE))?;
+    let Metadata {
+        key,
+        name,
+        optimized,
+        size,
+        created_ms,
+        last_used_ms,
+    } = serde_json::from_slice(&json)?;
+
+    Ok(ArtifactInfo {
+        key,
+        name,
+        optimized,
+        size,
+        created: UNIX_EPOCH + Duration::from_millis(created_ms),
+        last_used: UNIX_EPOCH + Duration::from_millis(last_used_ms),
+    })
+}
+
+fn write_info(dir: &Path, info: &ArtifactInfo) -> Result<(), Error> {
+    let millis = |t: SystemTime| {
+        t.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+    };
+
+    let metadata = Metadata {
+        key: info.key.clone(),
+        name: info.name.clone(),
+        optimized: info.optimized,
+        size: info.size,
+        created_ms: millis(info.created),
+        last_used_ms: millis(info.last_used),
+    };
+    let json = serde_json::to_vec_pretty(&metadata)?;
+
+    std::fs::write(dir.join(METADATA_FILE), json)
+}
+
+/// The on-disk version of [`ArtifactInfo`], using timestamps that are
+/// portable between platforms.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Metadata {
+    key: ArtifactKey,
+    name: String,
+    optimized: bool,
+    size: u64,
+    created_ms: u64,
+    last_used_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> BuildContext {
+        BuildContext::from_doc(
+            crate::parse::Document::parse(
+                "version: 1\nimage: runicos/base\npipeline: {}",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn keys_depend_on_the_generated_files() {
+        let ctx = ctx();
+        let lib_rs = File::new("lib.rs", b"fn main() {}".to_vec());
+        let cargo_toml = File::new("Cargo.toml", b"[package]".to_vec());
+        let modified = File::new("lib.rs", b"fn main() { }".to_vec());
+
+        let original = ArtifactKey::new(&ctx, vec![&lib_rs, &cargo_toml]);
+
+        assert_eq!(
+            original,
+            ArtifactKey::new(&ctx, vec![&cargo_toml, &lib_rs])
+        );
+        assert_ne!(
+            original,
+            ArtifactKey::new(&ctx, vec![&modified, &cargo_toml])
+        );
+        let optimized = BuildContext {
+            optimized: true,
+            ..ctx.clone()
+        };
+        assert_ne!(
+            original,
+            ArtifactKey::new(&optimized, vec![&lib_rs, &cargo_toml])
+        );
+    }
+
+    #[test]
+    fn keys_ignore_where_the_project_is_emitted() {
+        let ctx = ctx();
+        let lib_rs = File::new("lib.rs", b"fn main() {}".to_vec());
+        let emitted = BuildContext {
+            emit_project: Some(PathBuf::from("project")),
+            ..ctx.clone()
+        };
+
+        assert_eq!(
+            ArtifactKey::new(&ctx, vec![&lib_rs]),
+            ArtifactKey::new(&emitted, vec![&lib_rs])
+        );
+    }
+
+    #[test]
+    fn keys_depend_on_the_source_of_path_dependencies() {
+        let proc_block = tempfile::tempdir().unwrap();
+        let lib_rs = proc_block.path().join("src").join("lib.rs");
+        std::fs::create_dir_all(lib_rs.parent().unwrap()).unwrap();
+        std::fs::write(&lib_rs, "pub struct Modulo;").unwrap();
+        let ctx = ctx();
+        let cargo_toml = File::new(
+            "Cargo.toml",
+            format!(
+                "[package]\nname = \"rune\"\nversion = \
+                 \"0.0.0\"\n\n[dependencies]\nmodulo = {{ path = {:?} }}\n",
+                proc_block.path().display().to_string(),
+            )
+            .into_bytes(),
+        );
+
+        let original = ArtifactKey::new(&ctx, vec![&cargo_toml]);
+        // Build outputs shouldn't invalidate the key
+        std::fs::create_dir_all(proc_block.path().join("target")).unwrap();
+        std::fs::write(proc_block.path().join("target").join("x"), "").unwrap();
+        assert_eq!(original, ArtifactKey::new(&ctx, vec![&cargo_toml]));
+
+        std::fs::write(&lib_rs, "pub struct Modulo(u32);").unwrap();
+
+        assert_ne!(original, ArtifactKey::new(&ctx, vec![&cargo_toml]));
+    }
+
+    #[test]
+    fn store_and_retrieve_builds() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = ArtifactStore::new(temp.path());
+        let ctx = ctx();
+        let key = ArtifactKey("abcd".to_string());
+        let binary = CompiledBinary::from(vec![0, b'a', b's', b'm']);
+
+        assert_eq!(store.get(&key).unwrap(), None);
+        store.insert(&key, &ctx, &binary).unwrap();
+
+        assert_eq!(store.get(&key).unwrap(), Some(binary));
+        let listed = store.list().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "rune");
+        assert_eq!(listed[0].size, 4);
+    }
+
+    #[test]
+    fn prune_the_least_recently_used_artifacts() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = ArtifactStore::new(temp.path());
+        let ctx = ctx();
+        let binary = CompiledBinary::from(vec![0; 10]);
+        let keys: Vec<_> =
+            (0..3).map(|i| ArtifactKey(format!("key-{}", i))).collect();
+        for (i, key) in keys.iter().enumerate() {
+            let mut info = store.insert(key, &ctx, &binary).unwrap();
+            // Backdate each artifact so they have distinct timestamps
+            info.last_used = SystemTime::now() - Duration::from_secs(60)
+                + Duration::from_secs(i as u64);
+            write_info(&store.entry(key), &info).unwrap();
+        }
+        let policy = PruningPolicy {
+            max_total_size: Some(25),
+            ..PruningPolicy::keep_everything()
+        };
+
+        let removed = store.prune(&policy).unwrap();
+
+        assert_eq!(removed, vec![keys[0].clone()]);
+        let remaining: Vec<_> =
+            store.list().unwrap().into_iter().map(|a| a.key).collect();
+        assert_eq!(remaining, vec![keys[2].clone(), keys[1].clone()]);
+    }
+}
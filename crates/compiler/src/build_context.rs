@@ -3,7 +3,13 @@ use std::{
     process::Command,
 };
 
-use crate::codegen::RuneVersion;
+use crate::{
+    artifacts::{ArtifactStore, PruningPolicy},
+    codegen::RuneVersion,
+    config::Config,
+    parse::ByteSize,
+    prebuilt::PrebuiltProcBlocks,
+};
 
 /// Inputs used during the compilation process.
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -18,15 +24,61 @@ pub struct BuildContext {
     pub current_directory: PathBuf,
     /// Generate an optimized build.
     pub optimized: bool,
+    /// Keep the DWARF and `name` sections in the compiled Rune and generate
+    /// [`crate::compile::DebugSymbols`].
+    pub debug_symbols: bool,
+    /// Let optimized builds use the WebAssembly SIMD128 extension.
+    ///
+    /// Not every runtime supports SIMD, so you may also want a scalar build
+    /// to fall back to.
+    #[serde(default)]
+    pub simd: bool,
+    /// Compile for the `wasm64-unknown-unknown` target, giving the Rune a
+    /// 64-bit address space (the WebAssembly memory64 proposal) so its
+    /// models and working buffers can exceed 4 GB.
+    ///
+    /// Only runtimes using Wasmtime can load these Runes, and the standard
+    /// library needs to be compiled from source, which requires the
+    /// `rust-src` component.
+    #[serde(default)]
+    pub memory64: bool,
+    /// Require every proc-block to match what is pinned in the
+    /// `Runefile.lock`, failing the build instead of re-resolving anything
+    /// that has drifted (see [`crate::lockfile`]).
+    #[serde(default)]
+    pub locked: bool,
+    /// How much memory the device the Rune will run on has, used to warn
+    /// when the Rune's models and `memory` section won't fit.
+    #[serde(default)]
+    pub device_memory: Option<ByteSize>,
+    /// Write the generated Rust project to this directory so it can be
+    /// vendored, inspected, or built with a custom toolchain.
+    ///
+    /// Whether the project is compiled afterwards is up to
+    /// [`crate::hooks::Hooks::after_emit_project()`].
+    #[serde(default)]
+    pub emit_project: Option<PathBuf>,
     pub verbosity: Verbosity,
     /// The version of Rune being used.
     pub rune_version: Option<RuneVersion>,
 }
 
 impl BuildContext {
+    /// The target triple the Rune will be compiled for.
+    pub fn target_triple(&self) -> &'static str {
+        if self.memory64 {
+            "wasm64-unknown-unknown"
+        } else {
+            "wasm32-unknown-unknown"
+        }
+    }
+
     /// Create a new [`BuildContext`] using the convention that the
     /// [`BuildContext.name`] is named after the
     /// [`BuildContext.current_directory`].
+    ///
+    /// If the directory contains a [`Config`] (`rune.toml` or
+    /// `[package.metadata.rune]`), its default profile will be applied.
     pub fn for_directory(
         directory: impl Into<PathBuf>,
     ) -> Result<BuildContext, std::io::Error> {
@@ -46,17 +98,31 @@ impl BuildContext {
         let runefile = current_directory.join("Runefile.yml");
         let runefile = std::fs::read_to_string(runefile)?;
 
-        Ok(BuildContext {
+        let config = Config::load(&current_directory)?;
+
+        let mut ctx = BuildContext {
             name,
             runefile,
             working_directory,
             current_directory,
             optimized: true,
+            debug_symbols: false,
+            simd: false,
+            memory64: false,
+            locked: false,
+            device_memory: None,
+            emit_project: None,
             verbosity: Verbosity::Normal,
             rune_version: Some(RuneVersion {
                 version: env!("CARGO_PKG_VERSION").to_string(),
             }),
-        })
+        };
+
+        if let Some(config) = config {
+            config.apply(&mut ctx, None)?;
+        }
+
+        Ok(ctx)
     }
 
     #[cfg(test)]
@@ -67,6 +133,12 @@ impl BuildContext {
             working_directory: PathBuf::from("."),
             current_directory: PathBuf::from("."),
             optimized: false,
+            debug_symbols: false,
+            simd: false,
+            memory64: false,
+            locked: false,
+            device_memory: None,
+            emit_project: None,
             verbosity: Verbosity::Normal,
             rune_version: Some(RuneVersion {
                 version: env!("CARGO_PKG_VERSION").to_string(),
@@ -112,6 +184,11 @@ impl Verbosity {
 #[derive(Debug, Clone, PartialEq)]
 pub struct FeatureFlags {
     pub(crate) rune_repo_dir: Option<PathBuf>,
+    pub(crate) build_cache_dir: Option<PathBuf>,
+    pub(crate) artifact_pruning: PruningPolicy,
+    pub(crate) optimizations: bool,
+    pub(crate) load_proc_block_descriptors: bool,
+    pub(crate) prebuilt_proc_blocks: Option<PrebuiltProcBlocks>,
 }
 
 impl FeatureFlags {
@@ -124,12 +201,22 @@ impl FeatureFlags {
 
         FeatureFlags {
             rune_repo_dir: hotg_repo_dir,
+            build_cache_dir: None,
+            artifact_pruning: PruningPolicy::bounded(),
+            optimizations: false,
+            load_proc_block_descriptors: true,
+            prebuilt_proc_blocks: None,
         }
     }
 
     pub const fn production() -> Self {
         FeatureFlags {
             rune_repo_dir: None,
+            build_cache_dir: None,
+            artifact_pruning: PruningPolicy::bounded(),
+            optimizations: false,
+            load_proc_block_descriptors: true,
+            prebuilt_proc_blocks: None,
         }
     }
 
@@ -142,6 +229,78 @@ impl FeatureFlags {
         self.rune_repo_dir = hotg_repo_dir.into();
         self
     }
+
+    /// If specified, compiled dependencies (e.g. builtin proc-blocks) will be
+    /// shared between all Runes built with the same version of Rune, instead
+    /// of being recompiled for every Rune.
+    ///
+    /// Artifacts are stored in a `$build_cache_dir/$version/$target/`
+    /// directory, and compiled Runes are saved to the [`ArtifactStore`] in
+    /// `$build_cache_dir/artifacts/` so identical builds can be reused.
+    pub fn set_build_cache_dir(
+        &mut self,
+        build_cache_dir: impl Into<Option<PathBuf>>,
+    ) -> &mut Self {
+        self.build_cache_dir = build_cache_dir.into();
+        self
+    }
+
+    /// Limit how many compiled Runes are kept in the [`ArtifactStore`].
+    pub fn set_artifact_pruning(&mut self, policy: PruningPolicy) -> &mut Self {
+        self.artifact_pruning = policy;
+        self
+    }
+
+    /// Run optimization passes (e.g. removing proc-blocks whose outputs are
+    /// never used) over the pipeline after it has been lowered.
+    ///
+    /// See [`crate::lowering::rewrite`] for more.
+    pub fn set_optimizations(&mut self, enabled: bool) -> &mut Self {
+        self.optimizations = enabled;
+        self
+    }
+
+    /// Compile each proc-block on its own while lowering so the descriptor it
+    /// embeds can be used to infer output shapes and check its arguments and
+    /// transforms.
+    ///
+    /// This is enabled by default, but means every proc-block needs to be
+    /// fetched and compiled before the Runefile can be type checked.
+    pub fn set_load_proc_block_descriptors(
+        &mut self,
+        enabled: bool,
+    ) -> &mut Self {
+        self.load_proc_block_descriptors = enabled;
+        self
+    }
+
+    /// Download these proc-blocks instead of compiling them from source when
+    /// loading their descriptors.
+    ///
+    /// See [`crate::prebuilt`] for more.
+    pub fn set_prebuilt_proc_blocks(
+        &mut self,
+        prebuilt: impl Into<Option<PrebuiltProcBlocks>>,
+    ) -> &mut Self {
+        self.prebuilt_proc_blocks = prebuilt.into();
+        self
+    }
+
+    /// The store previous builds are saved to, if the build cache is
+    /// enabled.
+    pub fn artifact_store(&self) -> Option<ArtifactStore> {
+        self.build_cache_dir
+            .as_ref()
+            .map(|dir| ArtifactStore::new(dir.join("artifacts")))
+    }
+
+    /// The directory cargo should put artifacts compiled for `target` in, if
+    /// they are being cached.
+    pub(crate) fn target_dir(&self, target: &str) -> Option<PathBuf> {
+        self.build_cache_dir
+            .as_ref()
+            .map(|dir| dir.join(hotg_rune_core::VERSION).join(target))
+    }
 }
 
 impl Default for FeatureFlags {
@@ -7,16 +7,18 @@ use std::{
 };
 
 use hotg_rune_core::Shape;
+pub use hotg_rune_core::{GRAPH_CUSTOM_SECTION, VERSION_CUSTOM_SECTION};
+use indexmap::IndexMap;
 use serde::Serialize;
+use wasmparser::{BinaryReaderError, Parser, Payload};
 
 use crate::{
     lowering::{Name, Resource, SinkKind, SourceKind},
     parse::{Path, ResourceOrString},
 };
-
-pub const GRAPH_CUSTOM_SECTION: &str = ".rune_graph";
-pub const VERSION_CUSTOM_SECTION: &str = ".rune_version";
 pub const RESOURCE_CUSTOM_SECTION: &str = ".rune_resource";
+pub const TESTS_CUSTOM_SECTION: &str = ".rune_tests";
+pub const TEST_FIXTURE_CUSTOM_SECTION: &str = ".rune_test_fixture";
 
 /// A file that will be written to the Rune's build directory.
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -102,18 +104,21 @@ impl Display for RuneVersion {
 }
 
 /// A summary of the Rune pipeline that will be embedded in the Rune.
+///
+/// Capabilities, models, proc-blocks, and outputs are kept in the order the
+/// Rune initializes them, which is also the order the runtime gives them IDs.
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct RuneGraph {
     pub rune: RuneSummary,
-    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
-    pub capabilities: HashMap<Name, CapabilitySummary>,
-    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
-    pub models: HashMap<Name, ModelSummary>,
-    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
-    pub proc_blocks: HashMap<Name, ProcBlockSummary>,
-    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
-    pub outputs: HashMap<Name, OutputSummary>,
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    pub capabilities: IndexMap<Name, CapabilitySummary>,
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    pub models: IndexMap<Name, ModelSummary>,
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    pub proc_blocks: IndexMap<Name, ProcBlockSummary>,
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    pub outputs: IndexMap<Name, OutputSummary>,
     #[serde(skip_serializing_if = "HashMap::is_empty", default)]
     pub resources: HashMap<Name, Resource>,
     #[serde(skip_serializing_if = "HashMap::is_empty", default)]
@@ -135,9 +140,18 @@ pub struct CapabilitySummary {
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct ModelSummary {
     pub file: ResourceOrString,
+    /// The model's format (e.g. [`hotg_rune_core::TFLITE_MIMETYPE`]).
+    ///
+    /// This is empty for Runes compiled before it was recorded.
+    #[serde(default)]
+    pub mimetype: String,
     pub args: HashMap<String, ResourceOrString>,
     pub inputs: Vec<TensorId>,
     pub outputs: Vec<TensorId>,
+    /// Only run this node when the tensor has a non-zero element (the
+    /// `enabled-when` field in the Runefile).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled_when: Option<TensorId>,
 }
 
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -146,6 +160,10 @@ pub struct ProcBlockSummary {
     pub args: HashMap<String, ResourceOrString>,
     pub inputs: Vec<TensorId>,
     pub outputs: Vec<TensorId>,
+    /// Only run this node when the tensor has a non-zero element (the
+    /// `enabled-when` field in the Runefile).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled_when: Option<TensorId>,
 }
 
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -153,6 +171,10 @@ pub struct OutputSummary {
     pub kind: SinkKind,
     pub args: HashMap<String, ResourceOrString>,
     pub inputs: Vec<TensorId>,
+    /// Only run this node when the tensor has a non-zero element (the
+    /// `enabled-when` field in the Runefile).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled_when: Option<TensorId>,
 }
 
 #[derive(
@@ -179,9 +201,143 @@ impl Deref for TensorId {
 }
 
 impl RuneGraph {
+    /// Read the [`RuneGraph`] embedded in a compiled Rune.
+    ///
+    /// This returns `None` if the Rune doesn't have a
+    /// [`GRAPH_CUSTOM_SECTION`].
+    pub fn from_wasm(wasm: &[u8]) -> Result<Option<RuneGraph>, ReadGraphError> {
+        for payload in Parser::default().parse_all(wasm) {
+            if let Payload::CustomSection { name, data, .. } = payload? {
+                if name == GRAPH_CUSTOM_SECTION {
+                    return serde_json::from_slice(data)
+                        .map(Some)
+                        .map_err(ReadGraphError::InvalidGraph);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
     pub(crate) fn as_custom_section(
         &self,
     ) -> Result<CustomSection, serde_json::Error> {
         CustomSection::from_json(GRAPH_CUSTOM_SECTION, self)
     }
 }
+
+/// The error returned by [`RuneGraph::from_wasm()`].
+#[derive(Debug)]
+pub enum ReadGraphError {
+    InvalidWebAssembly(BinaryReaderError),
+    InvalidGraph(serde_json::Error),
+}
+
+impl From<BinaryReaderError> for ReadGraphError {
+    fn from(e: BinaryReaderError) -> Self {
+        ReadGraphError::InvalidWebAssembly(e)
+    }
+}
+
+impl Display for ReadGraphError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadGraphError::InvalidWebAssembly(_) => {
+                write!(f, "Unable to parse the WebAssembly module")
+            },
+            ReadGraphError::InvalidGraph(_) => {
+                write!(f, "Unable to deserialize the Rune graph")
+            },
+        }
+    }
+}
+
+impl std::error::Error for ReadGraphError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadGraphError::InvalidWebAssembly(e) => Some(e),
+            ReadGraphError::InvalidGraph(e) => Some(e),
+        }
+    }
+}
+
+/// The test cases from the Runefile's `tests:` section, embedded in the Rune
+/// so the runtime can execute them.
+///
+/// Capabilities and outputs are looked up by their name, which the Rune
+/// reports to the runtime when it is loaded. Their `index` (the order they are
+/// created in) is only used by runtimes that don't know the names. The input
+/// data itself lives in a separate [`TEST_FIXTURE_CUSTOM_SECTION`], keyed by
+/// [`EmbeddedTestInput::fixture`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EmbeddedTests {
+    pub cases: Vec<EmbeddedTestCase>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EmbeddedTestCase {
+    pub name: String,
+    pub inputs: Vec<EmbeddedTestInput>,
+    pub outputs: Vec<EmbeddedTestOutput>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EmbeddedTestInput {
+    pub capability: Name,
+    pub index: usize,
+    pub fixture: String,
+    pub element_type: String,
+    pub dimensions: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EmbeddedTestOutput {
+    pub output: Name,
+    pub index: usize,
+    pub values: Vec<f64>,
+    pub tolerance: f64,
+}
+
+impl EmbeddedTests {
+    pub(crate) fn as_custom_section(
+        &self,
+    ) -> Result<CustomSection, serde_json::Error> {
+        CustomSection::from_json(TESTS_CUSTOM_SECTION, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WebAssembly module containing nothing but one custom section.
+    fn module_with_custom_section(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut payload = vec![name.len() as u8];
+        payload.extend(name.as_bytes());
+        payload.extend(data);
+
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.push(0);
+        wasm.push(payload.len() as u8);
+        wasm.extend(payload);
+        wasm
+    }
+
+    #[test]
+    fn read_the_graph_back_out_of_a_rune() {
+        let wasm = module_with_custom_section(
+            GRAPH_CUSTOM_SECTION,
+            br#"{"rune":{"name":"sine"}}"#,
+        );
+
+        let graph = RuneGraph::from_wasm(&wasm).unwrap().unwrap();
+
+        assert_eq!(graph.rune.name, "sine");
+        assert!(graph.capabilities.is_empty());
+        assert!(RuneGraph::from_wasm(&module_with_custom_section(
+            ".other", b""
+        ))
+        .unwrap()
+        .is_none());
+    }
+}
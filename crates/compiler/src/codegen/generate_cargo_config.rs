@@ -1,33 +1,146 @@
-use legion::systems::CommandBuffer;
+use std::collections::BTreeMap;
 
-use crate::{codegen::File, BuildContext};
+use legion::{systems::CommandBuffer, world::SubWorld, Query};
+
+use crate::{
+    codegen::File,
+    lowering::{embedded_data_size, ModelData, ModelVariantData, ResourceData},
+    parse::{DocumentV1, MemoryConfig},
+    BuildContext,
+};
+
+/// The size of a WebAssembly page.
+const PAGE_SIZE: u64 = 64 * 1024;
+/// Extra memory for the allocator's own bookkeeping and any other static
+/// data (string tables, the pipeline's closures, etc.).
+const MEMORY_HEADROOM: u64 = 1024 * 1024;
+/// The most memory a `wasm32` module can address.
+const MAX_WASM32_MEMORY: u64 = 1 << 32;
 
 /// Generate a `.cargo/config.toml` file.
 #[legion::system]
-pub(crate) fn run(cmd: &mut CommandBuffer, #[resource] ctx: &BuildContext) {
-    let config = generate_config(ctx.optimized);
+pub(crate) fn run(
+    cmd: &mut CommandBuffer,
+    world: &SubWorld,
+    #[resource] ctx: &BuildContext,
+    #[resource] doc: &DocumentV1,
+    models: &mut Query<&ModelData>,
+    variants: &mut Query<&ModelVariantData>,
+    resources: &mut Query<&ResourceData>,
+) {
+    let limits = doc.memory.as_ref().map(|memory| {
+        let embedded = embedded_data_size(
+            models.iter(world),
+            variants.iter(world),
+            resources.iter(world),
+        );
+        MemoryLimits::new(memory, embedded, ctx.memory64)
+    });
+
+    let config = generate_config(
+        ctx.target_triple(),
+        ctx.optimized,
+        ctx.debug_symbols,
+        ctx.simd,
+        limits.as_ref(),
+    );
     cmd.push((config,));
 }
 
-fn generate_config(optimized: bool) -> File {
-    let target = if optimized {
-        Some(Targets {
-            wasm32_unknown_unknown: Target {
-                rustflags: &["-C", "link-arg=-s"],
-            },
-        })
+/// Linker settings derived from the Runefile's `memory` section.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct MemoryLimits {
+    stack_size: Option<u64>,
+    max_memory: Option<u64>,
+}
+
+impl MemoryLimits {
+    fn new(memory: &MemoryConfig, embedded: u64, memory64: bool) -> Self {
+        // We can only put an upper bound on linear memory when we know how
+        // big the heap will get.
+        let max_memory = memory.heap.map(|_| {
+            let bytes =
+                memory.peak_usage(embedded).saturating_add(MEMORY_HEADROOM);
+            let pages = (bytes + PAGE_SIZE - 1) / PAGE_SIZE;
+            let max_memory = pages.saturating_mul(PAGE_SIZE);
+
+            if memory64 {
+                max_memory
+            } else {
+                max_memory.min(MAX_WASM32_MEMORY)
+            }
+        });
+
+        MemoryLimits {
+            stack_size: memory.stack.map(|s| s.0),
+            max_memory,
+        }
+    }
+}
+
+fn generate_config(
+    target: &'static str,
+    optimized: bool,
+    debug_symbols: bool,
+    simd: bool,
+    memory: Option<&MemoryLimits>,
+) -> File {
+    let mut rustflags: Vec<String> = Vec::new();
+    let mut flag = |name: &str, value: String| {
+        rustflags.push(name.to_string());
+        rustflags.push(value);
+    };
+
+    if debug_symbols {
+        // Release builds don't emit debug info by default, and we need to
+        // make sure the linker doesn't strip it.
+        flag("-C", "debuginfo=2".to_string());
+    } else if optimized {
+        flag("-C", "link-arg=-s".to_string());
+    }
+
+    if optimized && simd {
+        flag("-C", "target-feature=+simd128".to_string());
+    }
+
+    if let Some(memory) = memory {
+        if let Some(stack_size) = memory.stack_size {
+            flag("-C", format!("link-arg=-zstack-size={}", stack_size));
+        }
+        if let Some(max_memory) = memory.max_memory {
+            flag("-C", format!("link-arg=--max-memory={}", max_memory));
+        }
+    }
+
+    let rustflags = if rustflags.is_empty() {
+        None
     } else {
+        Some(rustflags)
+    };
+
+    let targets = rustflags.map(|rustflags| {
+        let mut targets = BTreeMap::new();
+        targets.insert(target, Target { rustflags });
+        targets
+    });
+
+    // There are no pre-compiled standard libraries for wasm64, so cargo
+    // will need to build them from source.
+    let unstable = if target == "wasm32-unknown-unknown" {
         None
+    } else {
+        Some(Unstable {
+            build_std: vec!["std", "panic_abort"],
+        })
     };
 
     let config = Config {
-        target,
+        target: targets,
         net: Net {
             git_fetch_with_cli: true,
         },
-        build: Build {
-            target: "wasm32-unknown-unknown",
-        },
+        build: Build { target },
+        unstable,
     };
 
     let config = toml::to_vec(&config)
@@ -38,9 +151,11 @@ fn generate_config(optimized: bool) -> File {
 
 #[derive(Debug, serde::Serialize)]
 struct Config {
-    target: Option<Targets>,
+    target: Option<BTreeMap<&'static str, Target>>,
     net: Net,
     build: Build,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unstable: Option<Unstable>,
 }
 
 /// The [`[build]`](https://doc.rust-lang.org/cargo/reference/config.html#build)
@@ -51,16 +166,9 @@ struct Build {
     target: &'static str,
 }
 
-/// The `[target]` table.
-#[derive(Debug, serde::Serialize)]
-#[serde(rename_all = "kebab-case")]
-struct Targets {
-    wasm32_unknown_unknown: Target,
-}
-
 #[derive(Debug, serde::Serialize)]
 struct Target {
-    rustflags: &'static [&'static str],
+    rustflags: Vec<String>,
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -69,11 +177,22 @@ struct Net {
     git_fetch_with_cli: bool,
 }
 
+/// The [`[unstable]`](https://doc.rust-lang.org/cargo/reference/unstable.html)
+/// table.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Unstable {
+    build_std: Vec<&'static str>,
+}
+
 #[cfg(test)]
 mod tests {
     use toml::Value;
 
     use super::*;
+    use crate::parse::ByteSize;
+
+    const WASM32: &str = "wasm32-unknown-unknown";
 
     #[test]
     fn request_small_binaries_when_optimised() {
@@ -88,7 +207,7 @@ mod tests {
             target = "wasm32-unknown-unknown"
         };
 
-        let got = generate_config(true);
+        let got = generate_config(WASM32, true, false, false, None);
 
         assert_eq!(toml::from_slice::<Value>(&got.data).unwrap(), should_be);
     }
@@ -103,7 +222,102 @@ mod tests {
             target = "wasm32-unknown-unknown"
         };
 
-        let got = generate_config(false);
+        let got = generate_config(WASM32, false, false, false, None);
+
+        assert_eq!(toml::from_slice::<Value>(&got.data).unwrap(), should_be);
+    }
+
+    #[test]
+    fn keep_debug_info_when_requested() {
+        let should_be = toml::toml! {
+            [target.wasm32-unknown-unknown]
+            rustflags = ["-C", "debuginfo=2"]
+
+            [net]
+            git-fetch-with-cli = true
+
+            [build]
+            target = "wasm32-unknown-unknown"
+        };
+
+        let got = generate_config(WASM32, true, true, false, None);
+
+        assert_eq!(toml::from_slice::<Value>(&got.data).unwrap(), should_be);
+    }
+
+    #[test]
+    fn enable_simd128_for_optimised_builds() {
+        let should_be = toml::toml! {
+            [target.wasm32-unknown-unknown]
+            rustflags = ["-C", "link-arg=-s", "-C", "target-feature=+simd128"]
+
+            [net]
+            git-fetch-with-cli = true
+
+            [build]
+            target = "wasm32-unknown-unknown"
+        };
+
+        let got = generate_config(WASM32, true, false, true, None);
+
+        assert_eq!(toml::from_slice::<Value>(&got.data).unwrap(), should_be);
+    }
+
+    #[test]
+    fn size_the_stack_and_linear_memory() {
+        let memory = MemoryConfig {
+            heap: Some(ByteSize(4 * 1024 * 1024)),
+            stack: Some(ByteSize(64 * 1024)),
+        };
+        let limits = MemoryLimits::new(&memory, 1000, false);
+        let should_be = toml::toml! {
+            [target.wasm32-unknown-unknown]
+            rustflags = [
+                "-C", "link-arg=-s",
+                "-C", "link-arg=-zstack-size=65536",
+                "-C", "link-arg=--max-memory=5373952",
+            ]
+
+            [net]
+            git-fetch-with-cli = true
+
+            [build]
+            target = "wasm32-unknown-unknown"
+        };
+
+        let got = generate_config(WASM32, true, false, false, Some(&limits));
+
+        assert_eq!(toml::from_slice::<Value>(&got.data).unwrap(), should_be);
+    }
+
+    #[test]
+    fn build_the_standard_library_for_memory64() {
+        let memory = MemoryConfig {
+            heap: Some(ByteSize(6 * 1024 * 1024 * 1024)),
+            stack: None,
+        };
+        let limits = MemoryLimits::new(&memory, 0, true);
+        let should_be = toml::toml! {
+            [target.wasm64-unknown-unknown]
+            rustflags = ["-C", "link-arg=--max-memory=6444548096"]
+
+            [net]
+            git-fetch-with-cli = true
+
+            [build]
+            target = "wasm64-unknown-unknown"
+
+            [unstable]
+            build-std = ["std", "panic_abort"]
+        };
+
+        let got = generate_config(
+            "wasm64-unknown-unknown",
+            false,
+            false,
+            false,
+            Some(&limits),
+        );
 
         assert_eq!(toml::from_slice::<Value>(&got.data).unwrap(), should_be);
     }
@@ -5,10 +5,12 @@ use cargo_toml::{
     Manifest, Package, PatchSet, Product, Profiles, Publish, Resolver,
     TargetDepsSet, Workspace,
 };
+use codespan_reporting::diagnostic::Diagnostic;
 use legion::{systems::CommandBuffer, world::SubWorld, Query};
 
 use crate::{
-    codegen::File, lowering::ProcBlock, parse, BuildContext, FeatureFlags,
+    codegen::File, download, lockfile::Lockfile, lowering::ProcBlock, parse,
+    BuildContext, Diagnostics, FeatureFlags,
 };
 
 /// Generate a `Cargo.toml` file which includes all the relevant dependencies
@@ -19,6 +21,7 @@ pub(crate) fn run(
     cmd: &mut CommandBuffer,
     #[resource] ctx: &BuildContext,
     #[resource] features: &FeatureFlags,
+    #[resource] diags: &mut Diagnostics,
     query: &mut Query<&ProcBlock>,
 ) {
     let core_version = hotg_rune_core::VERSION;
@@ -43,13 +46,9 @@ pub(crate) fn run(
         );
     }
 
-    let proc_blocks = query.iter(world);
-    let mut manifest =
-        generate_manifest(proc_blocks, &ctx.name, &ctx.current_directory);
-
-    if let Some(hotg_repo_dir) = features.rune_repo_dir.as_deref() {
-        patch_hotg_dependencies(hotg_repo_dir, &mut manifest);
-    }
+    let proc_blocks: Vec<&ProcBlock> = query.iter(world).collect();
+    let manifest =
+        cargo_manifest(&ctx.name, &proc_blocks, ctx, features, diags);
 
     let manifest = toml::to_string_pretty(&manifest)
         .expect("Serializing to a string should never fail");
@@ -57,11 +56,126 @@ pub(crate) fn run(
     cmd.push((file,));
 }
 
+const HINT_GENERATE_LOCKFILE: &str =
+    "hint: build once without locking to generate it";
+
+/// Generate the manifest for a crate which depends on `proc_blocks`, pinning
+/// them to the [`Lockfile`] and patching the HOTG crates if necessary.
+pub(crate) fn cargo_manifest(
+    name: &str,
+    proc_blocks: &[&ProcBlock],
+    ctx: &BuildContext,
+    features: &FeatureFlags,
+    diags: &mut Diagnostics,
+) -> Manifest {
+    let mut manifest = generate_manifest(
+        proc_blocks.iter().copied(),
+        name,
+        &ctx.current_directory,
+        &download::cache_dir(ctx),
+    );
+
+    match Lockfile::load(&ctx.current_directory) {
+        Ok(Some(lockfile)) => {
+            apply_lockfile(&lockfile, proc_blocks, &mut manifest, ctx, diags)
+        },
+        Ok(None) if ctx.locked => diags.push(
+            Diagnostic::error()
+                .with_message(format!(
+                    "The build is locked, but there is no {} in \"{}\"",
+                    Lockfile::FILE_NAME,
+                    ctx.current_directory.display()
+                ))
+                .with_notes(vec![HINT_GENERATE_LOCKFILE.to_string()]),
+        ),
+        Ok(None) => {},
+        Err(e) => {
+            let cause = std::error::Error::source(&e)
+                .map(|cause| cause.to_string())
+                .unwrap_or_default();
+            diags.push(
+                Diagnostic::error()
+                    .with_message(e.to_string())
+                    .with_notes(vec![cause]),
+            );
+        },
+    }
+
+    if let Some(hotg_repo_dir) = features.rune_repo_dir.as_deref() {
+        patch_hotg_dependencies(hotg_repo_dir, &mut manifest);
+    }
+
+    manifest
+}
+
+/// Pin each proc-block to the version recorded in the [`Lockfile`].
+///
+/// Proc-blocks which have changed (or aren't in the lockfile yet) are left
+/// alone so they can be re-resolved, unless the build is
+/// [`BuildContext::locked`].
+fn apply_lockfile(
+    lockfile: &Lockfile,
+    proc_blocks: &[&ProcBlock],
+    manifest: &mut Manifest,
+    ctx: &BuildContext,
+    diags: &mut Diagnostics,
+) {
+    for proc_block in proc_blocks {
+        let path = &proc_block.path;
+
+        let drift = match lockfile.get(path) {
+            Some(locked) => match locked.check(path, &ctx.current_directory) {
+                Ok(()) => {
+                    if let Some(Dependency::Detailed(dep)) =
+                        manifest.dependencies.get_mut(proc_block.name())
+                    {
+                        locked.pin(dep);
+                    }
+                    continue;
+                },
+                Err(msg) => msg,
+            },
+            None => {
+                format!("\"{}\" isn't in {}", path, Lockfile::FILE_NAME)
+            },
+        };
+
+        if ctx.locked {
+            diags.push(
+                Diagnostic::error().with_message(format!(
+                    "The build is locked, but {}",
+                    drift
+                )),
+            );
+        } else {
+            log::debug!("Re-resolving \"{}\" because {}", path, drift);
+        }
+    }
+}
+
+/// Add a dependency to a `Cargo.toml` generated by [`run()`], replacing any
+/// existing dependency with the same name.
+pub(crate) fn add_dependency(
+    cargo_toml: &[u8],
+    name: &str,
+    dependency: Dependency,
+) -> Vec<u8> {
+    let mut manifest: Manifest = toml::from_slice(cargo_toml)
+        .expect("We should always be able to parse our own Cargo.toml");
+
+    manifest.dependencies.insert(name.to_string(), dependency);
+
+    toml::to_string_pretty(&manifest)
+        .expect("Serializing to a string should never fail")
+        .into_bytes()
+}
+
 // Generate the `Cargo.toml` manifest.
 fn generate_manifest<'rune, I>(
     proc_blocks: I,
     name: &str,
     current_dir: &Path,
+    downloads: &Path,
 ) -> Manifest
 where
     I: IntoIterator<Item = &'rune ProcBlock> + 'rune,
@@ -76,7 +190,7 @@ where
     Manifest {
         package: Some(package(name)),
         lib: Some(product),
-        dependencies: dependencies(proc_blocks, current_dir),
+        dependencies: dependencies(proc_blocks, current_dir, downloads),
         workspace: Some(Workspace {
             members: vec![String::from(".")],
             default_members: vec![String::from(".")],
@@ -98,7 +212,11 @@ fn package(name: &str) -> Package {
     }
 }
 
-fn dependencies<'rune, I>(proc_blocks: I, current_dir: &Path) -> DepsSet
+fn dependencies<'rune, I>(
+    proc_blocks: I,
+    current_dir: &Path,
+    downloads: &Path,
+) -> DepsSet
 where
     I: IntoIterator<Item = &'rune ProcBlock> + 'rune,
 {
@@ -140,7 +258,8 @@ where
     );
 
     for proc_block in proc_blocks {
-        let dep = proc_block_dependency(&proc_block.path, current_dir);
+        let dep =
+            proc_block_dependency(&proc_block.path, current_dir, downloads);
         let name = proc_block.name();
         deps.insert(name.to_string(), Dependency::Detailed(dep));
     }
@@ -151,11 +270,20 @@ where
 fn proc_block_dependency(
     path: &parse::Path,
     current_dir: &Path,
+    downloads: &Path,
 ) -> DependencyDetail {
     if path.base.starts_with('.') {
         return local_proc_block(path, current_dir);
     }
 
+    if let Some(archive) = path.remote_archive() {
+        // The archive was already extracted while lowering
+        return DependencyDetail {
+            path: Some(archive.archive_dir(downloads).display().to_string()),
+            ..empty_dependency_detail()
+        };
+    }
+
     if path.sub_path.is_none() && !path.base.contains('/') {
         if let Some(version) = &path.version {
             // it's from crates.io
@@ -308,7 +436,8 @@ mod tests {
 
     #[test]
     fn base_dependencies() {
-        let got = dependencies(Vec::new(), Path::new("."));
+        let got =
+            dependencies(Vec::new(), Path::new("."), Path::new("downloads"));
 
         assert_eq!(got.len(), 5);
         assert!(got.contains_key("log"));
@@ -339,7 +468,11 @@ mod tests {
             ..empty_dependency_detail()
         };
 
-        let got = proc_block_dependency(&path, Path::new("."));
+        let got = proc_block_dependency(
+            &path,
+            Path::new("."),
+            Path::new("downloads"),
+        );
 
         assert_eq!(got, should_be);
     }
@@ -355,14 +488,50 @@ mod tests {
             ..empty_dependency_detail()
         };
 
-        let got = proc_block_dependency(&path, Path::new("."));
+        let got = proc_block_dependency(
+            &path,
+            Path::new("."),
+            Path::new("downloads"),
+        );
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn downloaded_proc_block() {
+        let hash =
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let path =
+            format!("https://example.com/my_proc_block.zip#sha256={}", hash)
+                .parse()
+                .unwrap();
+        let should_be = DependencyDetail {
+            path: Some(
+                Path::new("downloads")
+                    .join(format!("{}.d", hash))
+                    .display()
+                    .to_string(),
+            ),
+            ..empty_dependency_detail()
+        };
+
+        let got = proc_block_dependency(
+            &path,
+            Path::new("."),
+            Path::new("downloads"),
+        );
 
         assert_eq!(got, should_be);
     }
 
     #[test]
     fn manifest_generates_cdylib() {
-        let got = generate_manifest(Vec::new(), "foo", Path::new("."));
+        let got = generate_manifest(
+            Vec::new(),
+            "foo",
+            Path::new("."),
+            Path::new("downloads"),
+        );
 
         let crate_type = got.lib.unwrap().crate_type.unwrap();
         assert!(crate_type.contains(&String::from("cdylib")));
@@ -370,8 +539,38 @@ mod tests {
 
     #[test]
     fn manifest_is_in_its_own_workspace() {
-        let got = generate_manifest(Vec::new(), "foo", Path::new("."));
+        let got = generate_manifest(
+            Vec::new(),
+            "foo",
+            Path::new("."),
+            Path::new("downloads"),
+        );
 
         assert!(got.workspace.is_some());
     }
+
+    #[test]
+    fn add_extra_dependencies() {
+        let manifest = generate_manifest(
+            Vec::new(),
+            "foo",
+            Path::new("."),
+            Path::new("downloads"),
+        );
+        let cargo_toml = toml::to_string_pretty(&manifest).unwrap();
+
+        let updated = add_dependency(
+            cargo_toml.as_bytes(),
+            "glue",
+            Dependency::Simple("1.0".to_string()),
+        );
+
+        let got: Manifest = toml::from_slice(&updated).unwrap();
+        assert_eq!(
+            got.dependencies["glue"],
+            Dependency::Simple("1.0".to_string())
+        );
+        assert_eq!(got.dependencies.len(), manifest.dependencies.len() + 1);
+        assert_eq!(got.package, manifest.package);
+    }
 }
@@ -1,19 +1,24 @@
 use std::collections::{HashMap, HashSet};
 
 use heck::{ToSnakeCase, ToUpperCamelCase};
-use hotg_rune_core::{ElementType, Shape};
+use hotg_rune_core::{
+    framing::Framing, serial_format::SerialFormat, ElementType, Shape,
+};
 use legion::{systems::CommandBuffer, world::SubWorld, Entity, Query};
 use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::{quote, ToTokens};
 
 use crate::{
-    codegen::{CustomSection, File},
+    codegen::{
+        generate_model_variant_files::variant_file_name,
+        string_table::StringTable, CustomSection, File,
+    },
     lowering::{
-        Inputs, Mimetype, Model, ModelFile, Name, Outputs, PipelineNode,
-        ProcBlock, Resource, ResourceData, ResourceOrString, Sink, SinkKind,
-        Source, Tensor,
+        Condition, Inputs, Mimetype, Model, ModelFile, Name, Outputs,
+        PipelineNode, ProcBlock, Resource, ResourceData, ResourceOrString,
+        Sink, SinkKind, Source, SourceKind, Tensor,
     },
-    parse::ResourceType,
+    parse::{DocumentV1, ResourceType},
 };
 
 /// Generate the entire `lib.rs` file.
@@ -26,6 +31,7 @@ use crate::{
 pub(crate) fn run(
     cmd: &mut CommandBuffer,
     world: &SubWorld,
+    #[resource] doc: &DocumentV1,
     sections: &mut Query<&CustomSection>,
     models: &mut Query<(&Name, &Model, &Mimetype, &Inputs, &Outputs)>,
     names: &mut Query<&Name>,
@@ -42,6 +48,7 @@ pub(crate) fn run(
         Option<&Outputs>,
         &PipelineNode,
     )>,
+    conditions: &mut Query<(Entity, &Condition)>,
 ) {
     let models: Vec<_> = models.iter(world).collect();
     let sections: Vec<_> = sections.iter(world).collect();
@@ -51,8 +58,17 @@ pub(crate) fn run(
     let outputs: Vec<_> = outputs.iter(world).collect();
     let pipeline_nodes: Vec<_> = pipeline_nodes.iter(world).collect();
     let tensors: Vec<_> = tensors.iter(world).collect();
+    let conditions: HashMap<Entity, Entity> = conditions
+        .iter(world)
+        .map(|(&node, condition)| (node, condition.tensor))
+        .collect();
 
+    let heap_limit = doc.memory.as_ref().and_then(|m| m.heap).map(|h| h.0);
+
+    let mut strings = StringTable::default();
     let lib_rs = generate_lib_rs(
+        &mut strings,
+        heap_limit,
         &sections,
         &models,
         &resources,
@@ -61,15 +77,20 @@ pub(crate) fn run(
         &outputs,
         &pipeline_nodes,
         &tensors,
+        &conditions,
         |ent| names.get(world, ent).ok(),
         |ent| tensor_by_ent.get(world, ent).ok(),
     );
     let file = File::new("lib.rs", lib_rs.to_string().into_bytes());
+    let report = strings.report();
 
     cmd.push((file,));
+    cmd.exec_mut(move |_, res| res.insert(report));
 }
 
 fn generate_lib_rs<'world>(
+    strings: &mut StringTable,
+    heap_limit: Option<u64>,
     sections: &[&CustomSection],
     models: &'world [(
         &'world Name,
@@ -84,31 +105,38 @@ fn generate_lib_rs<'world>(
     outputs: &[(&Name, &Sink)],
     pipeline_nodes: &[Node<'_>],
     tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+    conditions: &HashMap<Entity, Entity>,
     mut get_name: impl FnMut(Entity) -> Option<&'world Name>,
     mut get_tensor: impl FnMut(Entity) -> Option<&'world Tensor>,
 ) -> TokenStream {
     let prelude = generate_prelude();
     let custom_sections = generate_custom_sections(sections);
-    let resources_module = generate_resources_module(resources);
+    let resources_module = generate_resources_module(resources, strings);
     let models_module = generate_models_module(
         models.iter().map(|(n, m, ..)| (*n, *m)),
         &mut get_name,
     );
     let manifest = generate_manifest_function(
+        heap_limit,
         models,
         capabilities,
         proc_blocks,
         outputs,
         pipeline_nodes,
         tensors,
+        conditions,
         &mut get_name,
         &mut get_tensor,
+        strings,
     );
     let call = generate_call_function();
+    // Note: this needs to come last so it contains every string
+    let strings_module = strings.to_module();
 
     quote! {
         #prelude
         #custom_sections
+        #strings_module
         #resources_module
         #models_module
         #manifest
@@ -120,34 +148,46 @@ fn generate_lib_rs<'world>(
 /// our pipeline then turns it into a closure that gets stored in the
 /// `PIPELINE` static variable.
 fn generate_manifest_function<'world, F, T>(
+    heap_limit: Option<u64>,
     models: &[(&Name, &Model, &Mimetype, &Inputs, &Outputs)],
     capabilities: &[(&Name, &Source, &Outputs)],
     proc_blocks: &[(&Name, &ProcBlock)],
     outputs: &[(&Name, &Sink)],
     pipeline_nodes: &[Node<'_>],
     tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+    conditions: &HashMap<Entity, Entity>,
     get_name: &mut F,
     get_tensor: &mut T,
+    strings: &mut StringTable,
 ) -> TokenStream
 where
     F: FnMut(Entity) -> Option<&'world Name>,
     T: FnMut(Entity) -> Option<&'world Tensor>,
 {
     let capabilities =
-        initialize_capabilities(capabilities, get_tensor, get_name);
-    let proc_blocks = initialize_proc_blocks(proc_blocks, get_name);
+        initialize_capabilities(capabilities, get_tensor, get_name, strings);
+    let parameter_updates = apply_parameter_updates(proc_blocks);
+    let proc_blocks = initialize_proc_blocks(proc_blocks, get_name, strings);
     let models: TokenStream = models
         .iter()
         .map(|(n, m, mt, i, o)| {
             initialize_model(n, m, mt, i, o, get_name, get_tensor)
         })
         .collect();
-    let outputs = initialize_outputs(outputs);
-    let pipeline = execute_pipeline(pipeline_nodes, tensors);
+    let outputs = initialize_outputs(outputs, pipeline_nodes);
+    let pipeline = execute_pipeline(pipeline_nodes, tensors, conditions);
+    let heap_limit = heap_limit.map(|bytes| {
+        // Note: usize is 32 bits inside the Rune
+        let bytes = Literal::u64_unsuffixed(bytes.min(u64::from(u32::MAX)));
+        quote! {
+            hotg_runicos_base_wasm::ALLOCATOR.set_heap_limit(#bytes);
+        }
+    });
 
     quote! {
         #[no_mangle]
         pub extern "C" fn _manifest() -> i32 {
+            #heap_limit
             let _setup = hotg_runicos_base_wasm::SetupGuard::default();
             #capabilities
             #proc_blocks
@@ -156,6 +196,7 @@ where
 
             let pipeline = move || {
                 let _guard = hotg_runicos_base_wasm::PipelineGuard::default();
+                #parameter_updates
                 #pipeline
             };
 
@@ -177,27 +218,117 @@ fn execute_pipeline(
         &PipelineNode,
     )],
     tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+    conditions: &HashMap<Entity, Entity>,
 ) -> TokenStream {
     let ExecutionOrder {
         order,
         tensor_names,
         pipeline_nodes,
+        conditional_nodes,
+        optional_tensors,
         ..
-    } = ExecutionOrder::calculate(pipeline_nodes, tensors);
+    } = ExecutionOrder::calculate(pipeline_nodes, tensors, conditions);
 
     order
         .iter()
         .map(|entity| {
-            execute_pipeline_node(
+            let execute = execute_pipeline_node(
                 entity,
                 &pipeline_nodes,
                 &tensor_names,
                 tensors,
-            )
+            );
+
+            if conditional_nodes.contains(entity) {
+                execute_conditionally(
+                    execute,
+                    pipeline_nodes[entity],
+                    conditions.get(entity).copied(),
+                    &optional_tensors,
+                    &tensor_names,
+                )
+            } else {
+                execute
+            }
         })
         .collect()
 }
 
+/// Wrap a node so it only executes when its `enabled-when` tensor is set and
+/// all of its inputs were produced. Each of the node's outputs becomes an
+/// `Option` which is `None` when the node was skipped.
+fn execute_conditionally(
+    execute: TokenStream,
+    (_, inputs, outputs): (&Name, Option<&Inputs>, Option<&Outputs>),
+    condition: Option<Entity>,
+    optional_tensors: &HashSet<Entity>,
+    tensor_names: &HashMap<Entity, Ident>,
+) -> TokenStream {
+    let mut optional_inputs: Vec<&Ident> = Vec::new();
+    for tensor in inputs.map(|i| i.tensors.as_slice()).unwrap_or_default() {
+        let name = &tensor_names[tensor];
+        if optional_tensors.contains(tensor) && !optional_inputs.contains(&name)
+        {
+            optional_inputs.push(name);
+        }
+    }
+
+    let mut checks: Vec<TokenStream> = optional_inputs
+        .iter()
+        .map(|name| quote!(#name.is_some()))
+        .collect();
+
+    if let Some(condition) = condition {
+        let name = &tensor_names[&condition];
+        checks.push(if optional_tensors.contains(&condition) {
+            quote!(#name.as_ref().map_or(false, is_enabled))
+        } else {
+            quote!(is_enabled(&#name))
+        });
+    }
+
+    // Note: inside the branch we know every optional input is present, so
+    // shadow them with the unwrapped tensor
+    let body = quote! {
+        #( let #optional_inputs = #optional_inputs.as_ref().unwrap(); )*
+        #execute
+    };
+
+    let outputs: Vec<&Ident> = outputs
+        .map(|o| o.tensors.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|t| &tensor_names[t])
+        .collect();
+
+    match outputs.as_slice() {
+        [] => quote! {
+            if #(#checks)&&* {
+                #body
+            }
+        },
+        [output] => quote! {
+            let #output = if #(#checks)&&* {
+                #body
+                Some(#output)
+            } else {
+                None
+            };
+        },
+        outputs => {
+            let nones = outputs.iter().map(|_| quote!(None));
+            quote! {
+                let (#(#outputs),*) = if #(#checks)&&* {
+                    #body
+                    (#(Some(#outputs)),*)
+                } else {
+                    (#(#nones),*)
+                };
+            }
+        },
+    }
+}
+
 fn execute_pipeline_node(
     node: &Entity,
     pipeline_nodes: &HashMap<
@@ -211,8 +342,9 @@ fn execute_pipeline_node(
         .get(node)
         .copied()
         .expect("This pipeline node always be present");
+    let stage = name.as_str();
 
-    match (inputs, outputs) {
+    let execute = match (inputs, outputs) {
         (Some(inputs), Some(outputs)) => execute_model_or_proc_block(
             name,
             inputs,
@@ -230,6 +362,12 @@ fn execute_pipeline_node(
                 name
             )
         },
+    };
+
+    // Note: the stage name is reported to the runtime if this node panics
+    quote! {
+        hotg_runicos_base_wasm::set_current_stage(#stage);
+        #execute
     }
 }
 
@@ -316,6 +454,8 @@ fn shape_to_tensor_type(shape: &Shape) -> TokenStream {
         ElementType::U64 => quote!(u64),
         ElementType::I64 => quote!(i64),
         ElementType::F64 => quote!(f64),
+        ElementType::F16 => quote!(hotg_rune_core::f16),
+        ElementType::BF16 => quote!(hotg_rune_core::bf16),
         ElementType::String => quote!(alloc::borrow::Cow<'static, str>),
     };
     quote!(Tensor<#element_type>)
@@ -352,7 +492,7 @@ fn execute_capability(
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct ExecutionOrder<'world> {
     order: Vec<Entity>,
     tensor_names: HashMap<Entity, Ident>,
@@ -367,6 +507,13 @@ struct ExecutionOrder<'world> {
         ),
     >,
     tensor_inputs: HashMap<Entity, &'world [Entity]>,
+    /// The `enabled-when` tensor for each pipeline node that has one.
+    conditions: &'world HashMap<Entity, Entity>,
+    /// Nodes which may be skipped, either because they have a condition or
+    /// one of their inputs may not be produced.
+    conditional_nodes: HashSet<Entity>,
+    /// Tensors produced by a conditional node.
+    optional_tensors: HashSet<Entity>,
 }
 
 type Node<'world> = (
@@ -398,6 +545,7 @@ impl<'world> ExecutionOrder<'world> {
             Option<&'world Inputs>,
             Option<&'world Outputs>,
         )],
+        conditions: &'world HashMap<Entity, Entity>,
     ) -> Self {
         let mut order = ExecutionOrder {
             order: Vec::new(),
@@ -422,6 +570,9 @@ impl<'world> ExecutionOrder<'world> {
                     )
                 })
                 .collect(),
+            conditions,
+            conditional_nodes: HashSet::new(),
+            optional_tensors: HashSet::new(),
         };
 
         for (entity, ..) in pipeline_nodes.iter().copied() {
@@ -439,23 +590,42 @@ impl<'world> ExecutionOrder<'world> {
         self.visited_nodes.insert(entity);
 
         let (name, inputs, outputs) = self.pipeline_nodes[&entity];
+        let condition = self.conditions.get(&entity).copied();
+        let dependencies: Vec<Entity> = inputs
+            .map(|i| i.tensors.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .copied()
+            .chain(condition)
+            .collect();
 
         // We need to make sure all the inputs have been initialized first
-        if let Some(inputs) = inputs {
-            for input in &inputs.tensors {
-                let previous_nodes =
-                    self.tensor_inputs.get(input).copied().expect(
-                        "All tensors must have a node that created them",
-                    );
-                for &previous_node in previous_nodes {
-                    self.visit(previous_node);
-                }
+        for input in &dependencies {
+            let previous_nodes = self
+                .tensor_inputs
+                .get(input)
+                .copied()
+                .expect("All tensors must have a node that created them");
+            for &previous_node in previous_nodes {
+                self.visit(previous_node);
             }
         }
 
         // the pipeline node is executed
         self.order.push(entity);
 
+        let is_conditional = condition.is_some()
+            || dependencies
+                .iter()
+                .any(|t| self.optional_tensors.contains(t));
+        if is_conditional {
+            self.conditional_nodes.insert(entity);
+            if let Some(outputs) = outputs {
+                self.optional_tensors
+                    .extend(outputs.tensors.iter().copied());
+            }
+        }
+
         // and now it's been executed, we can mark each of its outputs as
         // available.
         if let Some(outputs) = outputs {
@@ -470,20 +640,125 @@ impl<'world> ExecutionOrder<'world> {
     }
 }
 
-fn initialize_outputs(outputs: &[(&Name, &Sink)]) -> TokenStream {
+fn initialize_outputs(
+    outputs: &[(&Name, &Sink)],
+    pipeline_nodes: &[Node<'_>],
+) -> TokenStream {
     outputs
         .iter()
-        .map(|(name, sink)| initialize_output(name, sink))
+        .map(|(name, sink)| {
+            let sources = sink_sources(name, pipeline_nodes);
+            initialize_output(name, sink, &sources)
+        })
         .collect()
 }
 
-fn initialize_output(name: &Name, sink: &Sink) -> TokenStream {
+fn initialize_output(
+    name: &Name,
+    sink: &Sink,
+    sources: &[String],
+) -> TokenStream {
+    let node_name = name.as_str();
     let name = Ident::new(name, Span::call_site());
     let type_name: TokenStream = sink_type_name(&sink.kind);
 
-    quote! {
-        let mut #name = #type_name::default();
+    let constructor = match serial_framing(sink) {
+        Some(framing) => quote!(#type_name::with_framing(#framing)),
+        None => quote!(#type_name::default()),
+    };
+    let constructor = quote!(#constructor.with_name(#node_name));
+    let constructor = match serial_format(sink) {
+        Some(format) => quote!(#constructor.with_format(#format)),
+        None => constructor,
+    };
+
+    // Tag each tensor with where it came from so the host can tell the
+    // inputs to a multi-input SERIAL output apart.
+    if sink.kind == SinkKind::Serial && sources.len() > 1 {
+        quote! {
+            let mut #name = #constructor.with_sources(&[#(#sources),*]);
+        }
+    } else {
+        quote! {
+            let mut #name = #constructor;
+        }
+    }
+}
+
+/// Find the stage output that each of a sink's inputs comes from, using the
+/// same `stage` or `stage.1` syntax as the Runefile.
+fn sink_sources(name: &Name, pipeline_nodes: &[Node<'_>]) -> Vec<String> {
+    let inputs = pipeline_nodes
+        .iter()
+        .find(|(_, n, ..)| *n == name)
+        .and_then(|(_, _, inputs, ..)| *inputs);
+
+    let inputs = match inputs {
+        Some(inputs) => inputs,
+        None => return Vec::new(),
+    };
+
+    inputs
+        .tensors
+        .iter()
+        .filter_map(|tensor| {
+            pipeline_nodes
+                .iter()
+                .find_map(|(_, producer, _, outputs, _)| {
+                    let index =
+                        (*outputs)?.tensors.iter().position(|t| t == tensor)?;
+
+                    if index == 0 {
+                        Some(producer.to_string())
+                    } else {
+                        Some(format!("{}.{}", producer, index))
+                    }
+                })
+        })
+        .collect()
+}
+
+fn serial_framing(sink: &Sink) -> Option<TokenStream> {
+    if sink.kind != SinkKind::Serial {
+        return None;
+    }
+
+    let framing = match sink.args.get("framing")? {
+        ResourceOrString::String(s) => Framing::from_name(s)
+            .expect("The framing should have been validated when lowering"),
+        ResourceOrString::Resource(_) => {
+            unreachable!("The framing can't come from a resource")
+        },
+    };
+
+    let variant = match framing {
+        Framing::None => quote!(None),
+        Framing::Cobs => quote!(Cobs),
+    };
+
+    Some(quote!(hotg_rune_core::framing::Framing::#variant))
+}
+
+fn serial_format(sink: &Sink) -> Option<TokenStream> {
+    if sink.kind != SinkKind::Serial {
+        return None;
     }
+
+    let format = match sink.args.get("format")? {
+        ResourceOrString::String(s) => SerialFormat::from_name(s)
+            .expect("The format should have been validated when lowering"),
+        ResourceOrString::Resource(_) => {
+            unreachable!("The format can't come from a resource")
+        },
+    };
+
+    let variant = match format {
+        SerialFormat::Json => quote!(Json),
+        SerialFormat::Cbor => quote!(Cbor),
+        SerialFormat::Protobuf => quote!(Protobuf),
+    };
+
+    Some(quote!(hotg_rune_core::serial_format::SerialFormat::#variant))
 }
 
 fn sink_type_name(kind: &SinkKind) -> TokenStream {
@@ -509,6 +784,10 @@ where
     N: FnMut(Entity) -> Option<&'world Name>,
     T: FnMut(Entity) -> Option<&'world Tensor>,
 {
+    let variant_idents: Vec<_> = (0..model.variants.len())
+        .map(|i| Ident::new(&variant_file_name(name, i), Span::call_site()))
+        .collect();
+    let node_name = name.as_str();
     let name = Ident::new(name, Span::call_site());
 
     let path_to_model_bytes = match &model.model_file {
@@ -528,10 +807,28 @@ where
 
     let mimetype = mimetype.as_ref();
 
+    if variant_idents.is_empty() {
+        return quote! {
+            let mut #name = hotg_runicos_base_wasm::Model::load(
+                #node_name,
+                #mimetype,
+                &#path_to_model_bytes,
+                #input_descriptors,
+                #output_descriptors,
+            );
+        };
+    }
+
+    let variant_names = model.variants.keys();
+
     quote! {
-        let mut #name = hotg_runicos_base_wasm::Model::load(
+        let mut #name = hotg_runicos_base_wasm::Model::load_variant(
+            #node_name,
             #mimetype,
-            &#path_to_model_bytes,
+            &[
+                ("default", &#path_to_model_bytes[..]),
+                #( (#variant_names, &crate::models::#variant_idents[..]), )*
+            ],
             #input_descriptors,
             #output_descriptors,
         );
@@ -566,6 +863,8 @@ fn element_type_to_tokens(element_type: ElementType) -> TokenStream {
         ElementType::U64 => "U64",
         ElementType::F64 => "F64",
         ElementType::I64 => "I64",
+        ElementType::F16 => "F16",
+        ElementType::BF16 => "BF16",
         ElementType::String => "String",
     };
     let ident = Ident::new(name, Span::call_site());
@@ -587,6 +886,7 @@ fn shape_to_tokens(shape: &Shape<'_>) -> TokenStream {
 fn initialize_proc_blocks<'world, N>(
     proc_blocks: &[(&Name, &ProcBlock)],
     get_name: &mut N,
+    strings: &mut StringTable,
 ) -> TokenStream
 where
     N: FnMut(Entity) -> Option<&'world Name>,
@@ -595,7 +895,7 @@ where
         .iter()
         .copied()
         .map(|(name, proc_block)| {
-            initialize_proc_block(name, proc_block, get_name)
+            initialize_proc_block(name, proc_block, get_name, strings)
         })
         .collect()
 }
@@ -604,27 +904,87 @@ fn initialize_proc_block<'world, N>(
     name: &Name,
     proc_block: &ProcBlock,
     get_name: &mut N,
+    strings: &mut StringTable,
 ) -> TokenStream
 where
     N: FnMut(Entity) -> Option<&'world Name>,
 {
     let ty = proc_block_type(proc_block);
 
+    let node = name.as_str();
     let name = Ident::new(name, Span::call_site());
-    let setters = proc_block.parameters.iter().map(|(key, value)| {
-        let value = proc_block_argument_to_tokens(value, get_name);
-        let setter = format!("set_{}", key).replace("-", "_");
-        let setter = Ident::new(&setter, Span::call_site());
-        let error_message =
-            format!("Unable to set {}'s \"{}\" to {}", name, key, value);
+    let setters: Vec<_> = proc_block
+        .parameters
+        .iter()
+        .map(|(key, value)| {
+            let description = match value {
+                ResourceOrString::String(s) => format!("{:?}", s),
+                ResourceOrString::Resource(r) => {
+                    format!("the \"{}\" resource", get_name(*r).unwrap())
+                },
+            };
+            // Note: The message is interned first so the value can reuse it
+            let error_message = strings.intern(&format!(
+                "Unable to set {}'s \"{}\" to {}",
+                name, key, description
+            ));
+            let value = proc_block_argument_to_tokens(value, get_name, strings);
+            let setter = format!("set_{}", key).replace("-", "_");
+            let setter = Ident::new(&setter, Span::call_site());
+            quote! {
+                #name.#setter(#value).expect(#error_message);
+            }
+        })
+        .collect();
+    // The first report comes straight from the Default impl, so the runtime
+    // can use it as the parameters' defaults
+    let report_arguments = if setters.is_empty() {
+        None
+    } else {
+        Some(quote!(report_parameters(#node, &#name);))
+    };
+
+    quote! {
+        let mut #name = #ty::default();
+        report_parameters(#node, &#name);
+        #( #setters )*
+        #report_arguments
+    }
+}
+
+/// Apply any parameter changes the runtime has queued up (see
+/// `Runtime::set_proc_block_parameter()`) before the pipeline runs.
+fn apply_parameter_updates(proc_blocks: &[(&Name, &ProcBlock)]) -> TokenStream {
+    if proc_blocks.is_empty() {
+        return TokenStream::new();
+    }
+
+    let arms = proc_blocks.iter().map(|(name, _)| {
+        let node = name.as_str();
+        let name = Ident::new(name, Span::call_site());
         quote! {
-            #name.#setter(#value).expect(#error_message);
+            #node => {
+                if let Err(e) = #name.set_parameter(&update.name, &update.value) {
+                    panic!(
+                        "Unable to set {}'s \"{}\" to {:?}: {}",
+                        update.node,
+                        update.name,
+                        update.value,
+                        e,
+                    );
+                }
+                report_parameters(#node, &#name);
+            },
         }
     });
 
     quote! {
-        let mut #name = #ty::default();
-        #( #setters )*
+        for update in hotg_runicos_base_wasm::parameter_updates() {
+            match update.node.as_str() {
+                #( #arms )*
+                _ => {},
+            }
+        }
     }
 }
 
@@ -642,6 +1002,7 @@ fn initialize_capabilities<'world, T, N>(
     capabilities: &[(&Name, &Source, &Outputs)],
     get_tensor: &mut T,
     get_name: &mut N,
+    strings: &mut StringTable,
 ) -> TokenStream
 where
     T: FnMut(Entity) -> Option<&'world Tensor>,
@@ -651,7 +1012,9 @@ where
         .iter()
         .copied()
         .map(|(name, source, outputs)| {
-            initialize_capability(name, source, outputs, get_tensor, get_name)
+            initialize_capability(
+                name, source, outputs, get_tensor, get_name, strings,
+            )
         })
         .collect()
 }
@@ -662,6 +1025,7 @@ fn initialize_capability<'world, T, N>(
     outputs: &Outputs,
     get_tensor: &mut T,
     get_name: &mut N,
+    strings: &mut StringTable,
 ) -> TokenStream
 where
     T: FnMut(Entity) -> Option<&'world Tensor>,
@@ -684,30 +1048,49 @@ where
     };
     let shape = shape_to_tokens(&output_tensor.0);
 
+    let node_name = name.as_str();
     let name = Ident::new(name, Span::call_site());
     let setters = source.parameters.iter().map(|(key, value)| {
         let key = key.replace("-", "_");
-        let value = capability_argument_to_tokens(value, get_name);
+        let value = if RUNTIME_ARGUMENTS.contains(&key.as_str()) {
+            runtime_argument_to_tokens(&key, value)
+        } else {
+            capability_argument_to_tokens(value, get_name, strings)
+        };
         quote! {
             #name.set_parameter(#key, #value);
         }
     });
 
+    // The host reads a STREAM capability in chunks that fill its output
+    let length = match (&source.kind, output_tensor.0.size()) {
+        (SourceKind::Stream, Some(size))
+            if !source.parameters.contains_key("length") =>
+        {
+            let size = Literal::i32_suffixed(size as i32);
+            quote!(#name.set_parameter("length", #size);)
+        },
+        _ => TokenStream::new(),
+    };
+
     quote! {
         let mut #name = hotg_runicos_base_wasm::Capability::new(#capability_type, #shape);
+        #name.set_name(#node_name);
         #( #setters )*
+        #length
     }
 }
 
 fn proc_block_argument_to_tokens<'world, F>(
     value: &ResourceOrString,
     get_name: &mut F,
+    strings: &mut StringTable,
 ) -> TokenStream
 where
     F: FnMut(Entity) -> Option<&'world Name>,
 {
     match value {
-        ResourceOrString::String(s) => quote!(#s),
+        ResourceOrString::String(s) => strings.intern(s),
         ResourceOrString::Resource(r) => {
             let name = get_name(*r).unwrap();
             let resource_name = Ident::new(&name, Span::call_site());
@@ -716,6 +1099,49 @@ where
     }
 }
 
+/// Capability arguments which configure the runtime rather than the capability
+/// itself (e.g. `stream: true` on a `SOUND` capability).
+///
+/// These are stripped out before [`capability_argument_to_tokens`] so a
+/// boolean doesn't get parsed as a [`hotg_rune_core::Value`] at runtime, and
+/// are passed to the runtime as plain integers instead.
+const RUNTIME_ARGUMENTS: &[&str] = &["stream", "hop_length_ms"];
+
+/// Turn one of the [`RUNTIME_ARGUMENTS`] into an `i32` literal, where `true`
+/// and `false` become `1` and `0`.
+fn runtime_argument_to_tokens(
+    key: &str,
+    value: &ResourceOrString,
+) -> TokenStream {
+    let s = match value {
+        ResourceOrString::String(s) => s.trim(),
+        ResourceOrString::Resource(_) => {
+            let msg = format!("The \"{}\" argument can't be a resource", key);
+            return quote!(compile_error!(#msg));
+        },
+    };
+
+    let number = if s.eq_ignore_ascii_case("true") {
+        Some(1)
+    } else if s.eq_ignore_ascii_case("false") {
+        Some(0)
+    } else {
+        s.parse::<i32>().ok()
+    };
+
+    match number {
+        Some(number) => Literal::i32_suffixed(number).into_token_stream(),
+        None => {
+            let msg = format!(
+                "The \"{}\" argument should be a boolean or an integer, found \
+                 \"{}\"",
+                key, s
+            );
+            quote!(compile_error!(#msg))
+        },
+    }
+}
+
 /// Take a [`ResourceOrString`] and turn it into an `impl Into<Value>`
 /// expression so it can be passed to a capability.
 ///
@@ -726,6 +1152,7 @@ where
 fn capability_argument_to_tokens<'world, F>(
     value: &ResourceOrString,
     get_name: &mut F,
+    strings: &mut StringTable,
 ) -> TokenStream
 where
     F: FnMut(Entity) -> Option<&'world Name>,
@@ -741,10 +1168,13 @@ where
                     quote!(compile_error!(#msg))
                 })
             } else {
+                let s = strings.intern(s);
                 quote! {
-                    #s
-                        .parse::<hotg_rune_core::Value>()
-                        .unwrap_or_else(|_| { panic!( "Unable to parse \"{}\" as a number", #s); })
+                    {
+                        let s = #s;
+                        s.parse::<hotg_rune_core::Value>()
+                            .unwrap_or_else(|_| { panic!( "Unable to parse \"{}\" as a number", s); })
+                    }
                 }
             }
         },
@@ -771,15 +1201,39 @@ fn generate_prelude() -> TokenStream {
         extern crate lazy_static;
 
         use alloc::boxed::Box;
-        use hotg_rune_core::PixelFormat;
+        use hotg_rune_core::{PixelFormat, ResizeFilter};
         use hotg_rune_proc_blocks::*;
 
         static mut PIPELINE: Option<Box<dyn FnMut()>> = None;
+
+        /// Should a stage guarded by this `enabled-when` tensor run?
+        fn is_enabled<T: Default + PartialEq>(tensor: &Tensor<T>) -> bool {
+            let zero = T::default();
+            tensor.elements().iter().any(|element| *element != zero)
+        }
+
+        /// Tell the runtime the current value of each of a proc-block's
+        /// parameters.
+        fn report_parameters<P: ProcBlock>(node: &str, proc_block: &P) {
+            let descriptor = P::DESCRIPTOR;
+
+            for parameter in descriptor.parameters.iter() {
+                if let Some(value) = proc_block.get_parameter(&parameter.name) {
+                    hotg_runicos_base_wasm::report_parameter(
+                        node,
+                        &descriptor.type_name,
+                        &parameter.name,
+                        &value,
+                    );
+                }
+            }
+        }
     }
 }
 
 /// The `call()` function - a simple function which invokes the `PIPELINE`
-/// constructed by [`generate_manifest_function()`].
+/// constructed by [`generate_manifest_function()`] - and the
+/// `_memory_stats()` function the runtime uses to monitor the allocator.
 fn generate_call_function() -> TokenStream {
     quote! {
         #[no_mangle]
@@ -796,6 +1250,14 @@ fn generate_call_function() -> TokenStream {
                 0
             }
         }
+
+        /// Let the runtime see how much memory the Rune is using.
+        ///
+        /// This returns a pointer, so it is an `i64` for wasm64 Runes.
+        #[no_mangle]
+        pub extern "C" fn _memory_stats() -> isize {
+            hotg_runicos_base_wasm::memory_stats() as isize
+        }
     }
 }
 
@@ -843,11 +1305,11 @@ fn generate_custom_section(
 
 fn generate_resources_module(
     resources: &[(&Name, &Resource, Option<&ResourceData>)],
+    strings: &mut StringTable,
 ) -> TokenStream {
-    let initializers = resources
-        .iter()
-        .copied()
-        .map(|(name, res, data)| resource_initializer(name, res, data));
+    let initializers = resources.iter().copied().map(|(name, res, data)| {
+        resource_initializer(name, res, data, strings)
+    });
 
     quote! {
         /// Lazily loaded accessors for all resources used by this Rune.
@@ -863,25 +1325,38 @@ fn resource_initializer(
     name: &Name,
     res: &Resource,
     data: Option<&ResourceData>,
+    strings: &mut StringTable,
 ) -> TokenStream {
     let name = name.as_str();
 
+    let interned_name = strings.intern(name);
+
     // First we try to read the resource using the runtime, returning a
     // Result<Vec<u8>, _>
     let maybe_bytes = quote! {
-        hotg_runicos_base_wasm::Resource::read_to_end(#name)
+        hotg_runicos_base_wasm::Resource::read_to_end(#interned_name)
     };
 
     // We then take the Result and unwrap it, either falling back to a default
     // value (provided in the Runefile) or blowing up
     let bytes = match data {
         Some(default_value) => {
-            let default_value = Literal::byte_string(default_value);
-            quote!(#maybe_bytes.unwrap_or_else(|_| #default_value.as_ref().into()))
+            let default_value =
+                match (res.ty, std::str::from_utf8(default_value)) {
+                    (ResourceType::String, Ok(text)) => {
+                        let text = strings.intern(text);
+                        quote!(#text.as_bytes())
+                    },
+                    _ => {
+                        let bytes = Literal::byte_string(default_value);
+                        quote!(#bytes.as_ref())
+                    },
+                };
+            quote!(#maybe_bytes.unwrap_or_else(|_| #default_value.into()))
         },
         None => {
-            let error_message =
-                format!("Unable to read the \"{}\" resource", name);
+            let error_message = strings
+                .intern(&format!("Unable to read the \"{}\" resource", name));
             quote!(#maybe_bytes.expect(#error_message))
         },
     };
@@ -891,8 +1366,10 @@ fn resource_initializer(
     // And now we can initialize our "static ref"
     match res.ty {
         ResourceType::String => {
-            let error_message =
-                format!("The \"{}\" resource isn't valid UTF-8", name);
+            let error_message = strings.intern(&format!(
+                "The \"{}\" resource isn't valid UTF-8",
+                name
+            ));
 
             quote! {
                 pub(crate) static ref #ident: alloc::string::String = {
@@ -938,9 +1415,19 @@ fn model_initializer<'world, N>(
 where
     N: FnMut(Entity) -> Option<&'world Name>,
 {
+    let variants = (0..model.variants.len()).map(|i| {
+        let variant = variant_file_name(name, i);
+        let path = format!("models/{}", variant);
+        let ident = Ident::new(&variant, Span::call_site());
+
+        quote! {
+            pub(crate) static ref #ident: &'static [u8] = include_bytes!(#path);
+        }
+    });
+
     let name = Ident::new(name, Span::call_site());
 
-    match &model.model_file {
+    let model = match &model.model_file {
         ModelFile::FromDisk(_) => {
             let path = format!("models/{}", name);
 
@@ -956,6 +1443,11 @@ where
                 pub(crate) static ref #name: &'static [u8] = crate::resources::#resource_name.as_ref();
             }
         },
+    };
+
+    quote! {
+        #model
+        #(#variants)*
     }
 }
 
@@ -966,6 +1458,7 @@ mod tests {
         process::{Command, Stdio},
     };
 
+    use indexmap::IndexMap;
     use legion::{IntoQuery, Resources, World};
 
     use super::*;
@@ -1076,7 +1569,11 @@ mod tests {
             order,
             tensor_names,
             ..
-        } = ExecutionOrder::calculate(&pipeline_nodes, &tensors);
+        } = ExecutionOrder::calculate(
+            &pipeline_nodes,
+            &tensors,
+            &HashMap::new(),
+        );
 
         let order_should_be = vec![first, second, third];
         assert_eq!(order, order_should_be);
@@ -1089,6 +1586,242 @@ mod tests {
         assert_eq!(tensor_names, tensor_names_should_be);
     }
 
+    #[test]
+    fn stages_after_a_condition_are_conditional() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut cmd = CommandBuffer::new(&world);
+        let audio = cmd.push((Tensor("i16[16000]".parse().unwrap()),));
+        cmd.push((
+            Name::from("mic"),
+            Outputs {
+                tensors: vec![audio],
+            },
+            PipelineNode,
+        ));
+        let flag = cmd.push((Tensor("u8[1]".parse().unwrap()),));
+        cmd.push((
+            Name::from("wake_word"),
+            Inputs {
+                tensors: vec![audio],
+            },
+            Outputs {
+                tensors: vec![flag],
+            },
+            PipelineNode,
+        ));
+        let label = cmd.push((Tensor("f32[6]".parse().unwrap()),));
+        let classifier = cmd.push((
+            Name::from("classifier"),
+            Inputs {
+                tensors: vec![audio],
+            },
+            Outputs {
+                tensors: vec![label],
+            },
+            PipelineNode,
+        ));
+        let serial = cmd.push((
+            Name::from("serial"),
+            Inputs {
+                tensors: vec![label],
+            },
+            PipelineNode,
+        ));
+        cmd.flush(&mut world, &mut resources);
+        let pipeline_nodes: Vec<_> = <(
+            Entity,
+            &Name,
+            Option<&Inputs>,
+            Option<&Outputs>,
+            &PipelineNode,
+        )>::query()
+        .iter(&world)
+        .collect();
+        let tensors: Vec<_> =
+            <(Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)>::query()
+                .iter(&world)
+                .collect();
+        let conditions = vec![(classifier, flag)].into_iter().collect();
+
+        let order =
+            ExecutionOrder::calculate(&pipeline_nodes, &tensors, &conditions);
+
+        let conditional_should_be: HashSet<_> =
+            vec![classifier, serial].into_iter().collect();
+        assert_eq!(order.conditional_nodes, conditional_should_be);
+        assert_eq!(
+            order.optional_tensors,
+            vec![label].into_iter().collect::<HashSet<_>>()
+        );
+
+        let got = execute_conditionally(
+            quote!(serial.consume(classifier_0.clone());),
+            order.pipeline_nodes[&serial],
+            None,
+            &order.optional_tensors,
+            &order.tensor_names,
+        );
+
+        let should_be = quote! {
+            if classifier_0.is_some() {
+                let classifier_0 = classifier_0.as_ref().unwrap();
+                serial.consume(classifier_0.clone());
+            }
+        };
+        assert_quote_eq!(got, should_be);
+    }
+
+    #[test]
+    fn stream_capabilities_read_a_whole_tensor_at_a_time() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut cmd = CommandBuffer::new(&world);
+        let tensor = Tensor("f32[1, 4]".parse().unwrap());
+        let output = cmd.push((tensor.clone(),));
+        cmd.flush(&mut world, &mut resources);
+        let source = Source {
+            kind: SourceKind::Stream,
+            parameters: IndexMap::new(),
+        };
+        let outputs = Outputs {
+            tensors: vec![output],
+        };
+
+        let got = initialize_capability(
+            &Name::from("dump"),
+            &source,
+            &outputs,
+            &mut |_| Some(&tensor),
+            &mut |_| None,
+            &mut StringTable::default(),
+        );
+
+        let should_be = quote! {
+            let mut dump = hotg_runicos_base_wasm::Capability::new(
+                hotg_rune_core::capabilities::STREAM,
+                hotg_rune_core::Shape::new(
+                    hotg_rune_core::ElementType::F32,
+                    [1usize, 4usize].as_ref(),
+                )
+            );
+            dump.set_name("dump");
+            dump.set_parameter("length", 16i32);
+        };
+        assert_quote_eq!(got, should_be);
+    }
+
+    #[test]
+    fn runtime_arguments_are_passed_as_integers() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut cmd = CommandBuffer::new(&world);
+        let tensor = Tensor("i16[16000]".parse().unwrap());
+        let output = cmd.push((tensor.clone(),));
+        cmd.flush(&mut world, &mut resources);
+        let source = Source {
+            kind: SourceKind::Sound,
+            parameters: [
+                ("stream", "true"),
+                ("hop_length_ms", "250"),
+                ("hz", "16000"),
+            ]
+            .iter()
+            .map(|(k, v)| {
+                (k.to_string(), ResourceOrString::String(v.to_string()))
+            })
+            .collect(),
+        };
+        let outputs = Outputs {
+            tensors: vec![output],
+        };
+        let mut strings = StringTable::default();
+
+        let got = initialize_capability(
+            &Name::from("audio"),
+            &source,
+            &outputs,
+            &mut |_| Some(&tensor),
+            &mut |_| None,
+            &mut strings,
+        );
+
+        let hz = strings.intern("16000");
+        let should_be = quote! {
+            let mut audio = hotg_runicos_base_wasm::Capability::new(
+                hotg_rune_core::capabilities::SOUND,
+                hotg_rune_core::Shape::new(
+                    hotg_rune_core::ElementType::I16,
+                    [16000usize].as_ref(),
+                )
+            );
+            audio.set_name("audio");
+            audio.set_parameter("stream", 1i32);
+            audio.set_parameter("hop_length_ms", 250i32);
+            audio.set_parameter("hz", {
+                let s = #hz;
+                s.parse::<hotg_rune_core::Value>()
+                    .unwrap_or_else(|_| { panic!( "Unable to parse \"{}\" as a number", s); })
+            });
+        };
+        assert_quote_eq!(got, should_be);
+    }
+
+    #[test]
+    fn proc_blocks_report_their_parameters() {
+        let proc_block = ProcBlock {
+            path: "hotg-ai/rune#proc_blocks/fft".parse().unwrap(),
+            parameters: [(
+                "bins".to_string(),
+                ResourceOrString::String("64".to_string()),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        };
+        let mut strings = StringTable::default();
+
+        let got = initialize_proc_block(
+            &Name::from("fft"),
+            &proc_block,
+            &mut |_| None,
+            &mut strings,
+        );
+        let updates =
+            apply_parameter_updates(&[(&Name::from("fft"), &proc_block)]);
+
+        let message = strings.intern("Unable to set fft's \"bins\" to \"64\"");
+        let value = strings.intern("64");
+        let should_be = quote! {
+            let mut fft = fft::Fft::default();
+            report_parameters("fft", &fft);
+            fft.set_bins(#value).expect(#message);
+            report_parameters("fft", &fft);
+        };
+        assert_quote_eq!(got, should_be);
+        let should_be = quote! {
+            for update in hotg_runicos_base_wasm::parameter_updates() {
+                match update.node.as_str() {
+                    "fft" => {
+                        if let Err(e) = fft.set_parameter(&update.name, &update.value) {
+                            panic!(
+                                "Unable to set {}'s \"{}\" to {:?}: {}",
+                                update.node,
+                                update.name,
+                                update.value,
+                                e,
+                            );
+                        }
+                        report_parameters("fft", &fft);
+                    },
+                    _ => {},
+                }
+            }
+        };
+        assert_quote_eq!(updates, should_be);
+        assert!(apply_parameter_updates(&[]).is_empty());
+    }
+
     #[test]
     fn execute_a_capability() {
         let mut world = World::default();
@@ -1195,6 +1928,93 @@ mod tests {
         assert_quote_eq!(got, should_be);
     }
 
+    #[test]
+    fn sources_for_a_multi_input_sink() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut cmd = CommandBuffer::new(&world);
+        let first_0 = cmd.push((Tensor("f32[1]".parse().unwrap()),));
+        let first_1 = cmd.push((Tensor("i16[2]".parse().unwrap()),));
+        let second_0 = cmd.push((Tensor("u8[3]".parse().unwrap()),));
+        cmd.push((
+            Name::from("first"),
+            Outputs {
+                tensors: vec![first_0, first_1],
+            },
+            PipelineNode,
+        ));
+        cmd.push((
+            Name::from("second"),
+            Outputs {
+                tensors: vec![second_0],
+            },
+            PipelineNode,
+        ));
+        cmd.push((
+            Name::from("serial"),
+            Inputs {
+                tensors: vec![first_1, second_0, first_0],
+            },
+            PipelineNode,
+        ));
+        cmd.flush(&mut world, &mut resources);
+        let pipeline_nodes: Vec<_> = <(
+            Entity,
+            &Name,
+            Option<&Inputs>,
+            Option<&Outputs>,
+            &PipelineNode,
+        )>::query()
+        .iter(&world)
+        .collect();
+
+        let got = sink_sources(&Name::from("serial"), &pipeline_nodes);
+
+        assert_eq!(got, vec!["first.1", "second", "first"]);
+    }
+
+    #[test]
+    fn tag_multi_input_serial_outputs() {
+        let name = Name::from("serial");
+        let sink = Sink {
+            kind: SinkKind::Serial,
+            args: IndexMap::new(),
+        };
+        let sources = vec!["fft".to_string(), "noise.1".to_string()];
+
+        let got = initialize_output(&name, &sink, &sources);
+
+        let should_be = quote! {
+            let mut serial = hotg_runicos_base_wasm::Serial::default()
+                .with_name("serial")
+                .with_sources(&["fft", "noise.1"]);
+        };
+        assert_quote_eq!(got, should_be);
+    }
+
+    #[test]
+    fn choose_the_serial_format() {
+        let name = Name::from("serial");
+        let mut args = IndexMap::new();
+        args.insert("framing".to_string(), "cobs".into());
+        args.insert("format".to_string(), "protobuf".into());
+        let sink = Sink {
+            kind: SinkKind::Serial,
+            args,
+        };
+
+        let got = initialize_output(&name, &sink, &[]);
+
+        let should_be = quote! {
+            let mut serial = hotg_runicos_base_wasm::Serial::with_framing(
+                hotg_rune_core::framing::Framing::Cobs
+            )
+            .with_name("serial")
+            .with_format(hotg_rune_core::serial_format::SerialFormat::Protobuf);
+        };
+        assert_quote_eq!(got, should_be);
+    }
+
     #[test]
     fn tensor_shapes_as_rust_types() {
         let inputs = vec![
@@ -0,0 +1,29 @@
+use std::{path::Path, sync::Arc};
+
+use legion::systems::CommandBuffer;
+
+use crate::{
+    codegen::File,
+    lowering::{ModelVariantData, Name},
+};
+
+/// Create a [`File`] for each of a model's variants and put it in the
+/// `models/` directory alongside the main model.
+#[legion::system(for_each)]
+pub(crate) fn run(
+    cmd: &mut CommandBuffer,
+    name: &Name,
+    variants: &ModelVariantData,
+) {
+    for (i, data) in variants.values().enumerate() {
+        let path = Path::new("models").join(variant_file_name(name, i));
+        let file = File::new(path, Arc::clone(&data.0));
+        cmd.push((file,));
+    }
+}
+
+/// The name used for the `i`'th variant of a model, both as a file name and
+/// as an identifier in the generated code.
+pub(crate) fn variant_file_name(name: &Name, i: usize) -> String {
+    format!("{}_variant_{}", name.as_str(), i)
+}
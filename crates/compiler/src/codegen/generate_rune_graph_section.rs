@@ -14,8 +14,8 @@ use crate::{
         ModelSummary, OutputSummary, ProcBlockSummary, RuneGraph, TensorId,
     },
     lowering::{
-        self, Inputs, Model, ModelFile, Name, Outputs, ProcBlock, Resource,
-        Sink, Source, Tensor,
+        self, Condition, Inputs, Mimetype, Model, ModelFile, Name, Outputs,
+        ProcBlock, Resource, Sink, Source, Tensor,
     },
     parse::{ResourceName, ResourceOrString},
     BuildContext,
@@ -29,9 +29,22 @@ pub(crate) fn run(
     #[resource] ctx: &BuildContext,
     capabilities: &mut Query<(&Name, &Source, &Outputs)>,
     tensors: &mut Query<(Entity, &Tensor)>,
-    models: &mut Query<(&Name, &Model, &Inputs, &Outputs)>,
-    proc_blocks: &mut Query<(&Name, &ProcBlock, &Inputs, &Outputs)>,
-    outputs: &mut Query<(&Name, &Sink, &Inputs)>,
+    models: &mut Query<(
+        &Name,
+        &Model,
+        &Mimetype,
+        &Inputs,
+        &Outputs,
+        Option<&Condition>,
+    )>,
+    proc_blocks: &mut Query<(
+        &Name,
+        &ProcBlock,
+        &Inputs,
+        &Outputs,
+        Option<&Condition>,
+    )>,
+    outputs: &mut Query<(&Name, &Sink, &Inputs, Option<&Condition>)>,
     resources: &mut Query<(&Name, &Resource)>,
 ) {
     let canon = Canon::default();
@@ -52,20 +65,20 @@ pub(crate) fn run(
             .collect(),
         models: models
             .iter(world)
-            .map(|(n, m, i, o)| {
-                model_summary(n, m, i, o, &mut resource_name, &canon)
+            .map(|(n, m, t, i, o, c)| {
+                model_summary(n, m, t, i, o, c, &mut resource_name, &canon)
             })
             .collect(),
         proc_blocks: proc_blocks
             .iter(world)
-            .map(|(n, p, i, o)| {
-                proc_block_summary(n, p, i, o, &canon, &mut resource_name)
+            .map(|(n, p, i, o, c)| {
+                proc_block_summary(n, p, i, o, c, &canon, &mut resource_name)
             })
             .collect(),
         outputs: outputs
             .iter(world)
-            .map(|(n, s, i)| {
-                output_summary(n, s, i, &canon, &mut resource_name)
+            .map(|(n, s, i, c)| {
+                output_summary(n, s, i, c, &canon, &mut resource_name)
             })
             .collect(),
         resources: resources
@@ -100,6 +113,13 @@ fn tensor_shapes(tensors: &[Entity], get_tensor: &Canon) -> Vec<TensorId> {
         .collect()
 }
 
+fn condition_tensor(
+    condition: Option<&Condition>,
+    get_tensor: &Canon,
+) -> Option<TensorId> {
+    condition.map(|c| TensorId(get_tensor.to_serialized(c.tensor).to_string()))
+}
+
 fn capability_summary(
     name: &Name,
     source: &Source,
@@ -119,8 +139,10 @@ fn capability_summary(
 fn model_summary(
     name: &Name,
     model: &Model,
+    mimetype: &Mimetype,
     inputs: &Inputs,
     outputs: &Outputs,
+    condition: Option<&Condition>,
     mut resources: impl FnMut(Entity) -> ResourceName,
     get_tensor: &Canon,
 ) -> (Name, ModelSummary) {
@@ -135,9 +157,11 @@ fn model_summary(
 
     let summary = ModelSummary {
         file,
+        mimetype: mimetype.to_string(),
         args: convert_args(&model.args, resources),
         inputs: tensor_shapes(&inputs.tensors, get_tensor),
         outputs: tensor_shapes(&outputs.tensors, get_tensor),
+        enabled_when: condition_tensor(condition, get_tensor),
     };
 
     (name.clone(), summary)
@@ -148,6 +172,7 @@ fn proc_block_summary(
     proc_block: &ProcBlock,
     inputs: &Inputs,
     outputs: &Outputs,
+    condition: Option<&Condition>,
     get_tensor: &Canon,
     mut resource_name: impl FnMut(Entity) -> ResourceName,
 ) -> (Name, ProcBlockSummary) {
@@ -170,6 +195,7 @@ fn proc_block_summary(
             .collect(),
         inputs: tensor_shapes(&inputs.tensors, get_tensor),
         outputs: tensor_shapes(&outputs.tensors, get_tensor),
+        enabled_when: condition_tensor(condition, get_tensor),
     };
 
     (name.clone(), summary)
@@ -179,6 +205,7 @@ fn output_summary(
     name: &Name,
     sink: &Sink,
     inputs: &Inputs,
+    condition: Option<&Condition>,
     get_tensor: &Canon,
     mut get_resources: impl FnMut(Entity) -> ResourceName,
 ) -> (Name, OutputSummary) {
@@ -186,6 +213,7 @@ fn output_summary(
         kind: sink.kind.clone(),
         args: convert_args(&sink.args, &mut get_resources),
         inputs: tensor_shapes(&inputs.tensors, get_tensor),
+        enabled_when: condition_tensor(condition, get_tensor),
     };
 
     (name.clone(), summary)
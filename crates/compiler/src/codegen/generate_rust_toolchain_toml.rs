@@ -1,10 +1,11 @@
 use legion::systems::CommandBuffer;
 
-use crate::codegen::File;
+use crate::{codegen::File, BuildContext};
 
 #[legion::system]
-pub(crate) fn run(cmd: &mut CommandBuffer) {
-    let rust_toolchain = crate::rust_toolchain();
+pub(crate) fn run(cmd: &mut CommandBuffer, #[resource] ctx: &BuildContext) {
+    let rust_toolchain = crate::rust_toolchain_for_target(ctx.target_triple());
+
     let contents = toml::to_vec(&rust_toolchain)
         .expect("We can always serialize a hard-coded TOML object");
     let file = File::new("rust-toolchain.toml", contents);
@@ -0,0 +1,274 @@
+use std::{convert::TryFrom, path::Path};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use legion::{systems::CommandBuffer, world::SubWorld, Query};
+
+use crate::{
+    codegen::{
+        CustomSection, EmbeddedTestCase, EmbeddedTestInput, EmbeddedTestOutput,
+        EmbeddedTests, TEST_FIXTURE_CUSTOM_SECTION,
+    },
+    lowering::{Name, Outputs, Sink, Source, Tensor},
+    parse::{DocumentV1, TestCase},
+    BuildContext, Diagnostics,
+};
+
+/// Embed the Runefile's `tests:` section in the Rune, along with the input
+/// fixtures each test case uses.
+#[legion::system]
+pub(crate) fn run(
+    cmd: &mut CommandBuffer,
+    world: &SubWorld,
+    #[resource] doc: &DocumentV1,
+    #[resource] ctx: &BuildContext,
+    #[resource] diags: &mut Diagnostics,
+    capabilities: &mut Query<(&Name, &Source, &Outputs)>,
+    outputs: &mut Query<(&Name, &Sink)>,
+    tensors: &mut Query<&Tensor>,
+) {
+    if doc.tests.is_empty() {
+        return;
+    }
+
+    // Note: these need to be in the same order as generate_lib_rs so the
+    // indices line up with the order nodes are created at runtime.
+    let capabilities: Vec<(usize, &Name, &Tensor)> = capabilities
+        .iter(world)
+        .enumerate()
+        .filter_map(|(index, (name, _, outputs))| {
+            let tensor = outputs.tensors.first()?;
+            tensors.get(world, *tensor).ok().map(|t| (index, name, t))
+        })
+        .collect();
+    let outputs: Vec<&Name> = outputs.iter(world).map(|(n, _)| n).collect();
+
+    let mut cases = Vec::new();
+
+    for (case_name, case) in &doc.tests {
+        match embed_test_case(
+            &ctx.current_directory,
+            case_name,
+            case,
+            &capabilities,
+            &outputs,
+        ) {
+            Ok((embedded, fixtures)) => {
+                cases.push(embedded);
+                for section in fixtures {
+                    cmd.push((section,));
+                }
+            },
+            Err(diagnostics) => {
+                for diag in diagnostics {
+                    diags.push(diag);
+                }
+            },
+        }
+    }
+
+    let section = EmbeddedTests { cases }
+        .as_custom_section()
+        .expect("We should always be able to serialize to JSON");
+    cmd.push((section,));
+}
+
+fn embed_test_case(
+    current_dir: &Path,
+    case_name: &str,
+    case: &TestCase,
+    capabilities: &[(usize, &Name, &Tensor)],
+    outputs: &[&Name],
+) -> Result<(EmbeddedTestCase, Vec<CustomSection>), Vec<Diagnostic<()>>> {
+    let span = case.span();
+    let mut diags = Vec::new();
+    let mut inputs = Vec::new();
+    let mut fixtures = Vec::new();
+
+    for (capability, path) in &case.inputs {
+        let (index, tensor) = match capabilities
+            .iter()
+            .find(|(_, name, _)| name.as_str() == capability.as_str())
+        {
+            Some((index, _, tensor)) => (*index, *tensor),
+            None => {
+                diags.push(unknown_node(
+                    case_name,
+                    "capability",
+                    capability,
+                    span,
+                ));
+                continue;
+            },
+        };
+
+        let full_path = current_dir.join(path);
+        let data = match std::fs::read(&full_path) {
+            Ok(data) => data,
+            Err(e) => {
+                diags.push(read_failed(case_name, &full_path, e, span));
+                continue;
+            },
+        };
+
+        let fixture = format!("{}/{}", case_name, capability);
+        fixtures.push(inline_fixture(&fixture, &data));
+
+        inputs.push(EmbeddedTestInput {
+            capability: Name::from(capability),
+            index,
+            fixture,
+            element_type: tensor.0.element_type().rune_name().to_string(),
+            dimensions: tensor.0.dimensions().to_vec(),
+        });
+    }
+
+    let mut embedded_outputs = Vec::new();
+
+    for (output, expected) in &case.outputs {
+        match outputs
+            .iter()
+            .position(|name| name.as_str() == output.as_str())
+        {
+            Some(index) => embedded_outputs.push(EmbeddedTestOutput {
+                output: Name::from(output),
+                index,
+                values: expected.values.clone(),
+                tolerance: expected.tolerance,
+            }),
+            None => diags.push(unknown_node(case_name, "output", output, span)),
+        }
+    }
+
+    if !diags.is_empty() {
+        return Err(diags);
+    }
+
+    let embedded = EmbeddedTestCase {
+        name: case_name.to_string(),
+        inputs,
+        outputs: embedded_outputs,
+    };
+
+    Ok((embedded, fixtures))
+}
+
+/// Encode a fixture using the same framing as inline resources, so it can be
+/// read back with [`hotg_rune_core::decode_inline_resource()`].
+fn inline_fixture(name: &str, data: &[u8]) -> CustomSection {
+    let name_len = u32::try_from(name.len()).unwrap();
+    let data_len = u32::try_from(data.len()).unwrap();
+    let mut buffer = Vec::with_capacity(8 + name.len() + data.len());
+
+    buffer.extend(name_len.to_be_bytes());
+    buffer.extend_from_slice(name.as_bytes());
+    buffer.extend(data_len.to_be_bytes());
+    buffer.extend_from_slice(data);
+
+    CustomSection::new(TEST_FIXTURE_CUSTOM_SECTION, buffer)
+}
+
+fn unknown_node(
+    case_name: &str,
+    kind: &str,
+    name: &str,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" test refers to \"{}\", but there is no {} with that \
+             name",
+            case_name, name, kind
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn read_failed(
+    case_name: &str,
+    full_path: &Path,
+    e: std::io::Error,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "Unable to read \"{}\" for the \"{}\" test: {}",
+            full_path.display(),
+            case_name,
+            e
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+#[cfg(test)]
+mod tests {
+    use hotg_rune_core::{ElementType, Shape};
+
+    use super::*;
+    use crate::parse::ExpectedOutput;
+
+    #[test]
+    fn unknown_names_are_reported() {
+        let case = TestCase {
+            inputs: map! { audio: "audio.bin".to_string() },
+            outputs: map! {
+                serial: ExpectedOutput {
+                    values: vec![1.0],
+                    tolerance: 0.0,
+                },
+            },
+        };
+
+        let diags = embed_test_case(Path::new("."), "silence", &case, &[], &[])
+            .unwrap_err();
+
+        assert_eq!(diags.len(), 2);
+        assert_eq!(
+            diags[0].message,
+            "The \"silence\" test refers to \"audio\", but there is no \
+             capability with that name"
+        );
+    }
+
+    #[test]
+    fn embed_fixtures_and_expected_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("audio.bin"), [0_u8; 4]).unwrap();
+        let audio = Name::from("audio");
+        let tensor = Tensor(Shape::new(ElementType::I16, vec![2]));
+        let serial = Name::from("serial");
+        let case = TestCase {
+            inputs: map! { audio: "audio.bin".to_string() },
+            outputs: map! {
+                serial: ExpectedOutput {
+                    values: vec![0.5, 0.25],
+                    tolerance: 0.01,
+                },
+            },
+        };
+
+        let (embedded, fixtures) = embed_test_case(
+            dir.path(),
+            "silence",
+            &case,
+            &[(0, &audio, &tensor)],
+            &[&serial],
+        )
+        .unwrap();
+
+        assert_eq!(
+            embedded.inputs,
+            vec![EmbeddedTestInput {
+                capability: audio,
+                index: 0,
+                fixture: "silence/audio".to_string(),
+                element_type: "i16".to_string(),
+                dimensions: vec![2],
+            }]
+        );
+        assert_eq!(embedded.outputs[0].values, vec![0.5, 0.25]);
+        let (name, data, _) =
+            hotg_rune_core::decode_inline_resource(&fixtures[0].value).unwrap();
+        assert_eq!(name, "silence/audio");
+        assert_eq!(data, [0_u8; 4]);
+    }
+}
@@ -9,12 +9,16 @@ mod generate_cargo_config;
 mod generate_cargo_toml;
 mod generate_lib_rs;
 mod generate_model_files;
+mod generate_model_variant_files;
 mod generate_resource_section;
 mod generate_rune_graph_section;
 mod generate_rust_toolchain_toml;
+mod generate_test_section;
 mod generate_version_section;
+mod string_table;
 
 pub use components::*;
+pub(crate) use generate_cargo_toml::{add_dependency, cargo_manifest};
 use legion::Registry;
 
 use crate::{phases::Phase, serialize::RegistryExt};
@@ -25,9 +29,11 @@ pub fn phase() -> Phase {
         .and_then(generate_cargo_config::run_system)
         .and_then(generate_cargo_toml::run_system)
         .and_then(generate_model_files::run_system)
+        .and_then(generate_model_variant_files::run_system)
         .and_then(generate_resource_section::run_system)
         .and_then(generate_version_section::run_system)
         .and_then(generate_rune_graph_section::run_system)
+        .and_then(generate_test_section::run_system)
         .and_then(generate_lib_rs::run_system)
         .and_then(compile_generated_project::run_system)
 }
@@ -0,0 +1,97 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::compile::StringTableReport;
+
+/// Collects every string constant used by the generated `lib.rs` into a single
+/// table so each unique string (or substring) only ends up in the Rune's data
+/// segment once.
+///
+/// Strings are accessed from the Rune using `crate::strings::get()`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct StringTable {
+    table: String,
+    references: usize,
+    referenced_bytes: usize,
+}
+
+impl StringTable {
+    /// Add a string to the table, returning an expression which evaluates to
+    /// a `&'static str` with the same contents.
+    pub(crate) fn intern(&mut self, s: &str) -> TokenStream {
+        self.references += 1;
+        self.referenced_bytes += s.len();
+
+        // Note: UTF-8 is self-synchronizing so a match will always start and
+        // end on a char boundary.
+        let offset = match self.table.find(s) {
+            Some(offset) => offset,
+            None => {
+                let offset = self.table.len();
+                self.table.push_str(s);
+                offset
+            },
+        };
+        let len = s.len();
+
+        quote!(crate::strings::get(#offset, #len))
+    }
+
+    /// Generate the `strings` module containing the table and its accessor.
+    pub(crate) fn to_module(&self) -> TokenStream {
+        let table = &self.table;
+
+        quote! {
+            /// Every string constant used by this Rune, deduplicated.
+            mod strings {
+                static TABLE: &str = #table;
+
+                pub(crate) fn get(offset: usize, len: usize) -> &'static str {
+                    &TABLE[offset..offset + len]
+                }
+            }
+        }
+    }
+
+    pub(crate) fn report(&self) -> StringTableReport {
+        StringTableReport {
+            references: self.references,
+            referenced_bytes: self.referenced_bytes,
+            table_bytes: self.table.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_and_substrings_are_only_stored_once() {
+        let mut strings = StringTable::default();
+
+        let first = strings.intern("Unable to set labels to \"up,down\"");
+        let duplicate = strings.intern("Unable to set labels to \"up,down\"");
+        let substring = strings.intern("up,down");
+        let new = strings.intern("left");
+
+        assert_eq!(first.to_string(), duplicate.to_string());
+        assert_eq!(
+            substring.to_string(),
+            quote!(crate::strings::get(25usize, 7usize)).to_string()
+        );
+        assert_eq!(
+            new.to_string(),
+            quote!(crate::strings::get(33usize, 4usize)).to_string()
+        );
+        assert_eq!(
+            strings.report(),
+            StringTableReport {
+                references: 4,
+                referenced_bytes: 33 + 33 + 7 + 4,
+                table_bytes: 37,
+            }
+        );
+        assert_eq!(strings.report().saved(), 40);
+    }
+}
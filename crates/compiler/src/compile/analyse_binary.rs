@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use legion::{systems::CommandBuffer, world::SubWorld, Query};
+use wasmparser::{BinaryReaderError, Name, NameSectionReader, Parser, Payload};
+
+use crate::{
+    compile::{CompilationResult, DebugSymbols, SizeReport, StringTableReport},
+    lowering::{self, ProcBlock},
+    BuildContext,
+};
+
+/// Work out where the bytes in the compiled Rune went and, if requested, which
+/// functions belong to each stage.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] ctx: &BuildContext,
+    #[resource] result: &CompilationResult,
+    #[resource] strings: &StringTableReport,
+    query: &mut Query<(&lowering::Name, &ProcBlock)>,
+) {
+    let wasm = match &result.0 {
+        Ok(wasm) => wasm,
+        Err(_) => return,
+    };
+
+    let (mut size_report, functions) = match analyse(wasm) {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            log::warn!("Unable to analyse the compiled Rune: {}", e);
+            return;
+        },
+    };
+    size_report.strings = *strings;
+
+    let debug_symbols = if ctx.debug_symbols {
+        let stages = query
+            .iter(world)
+            .map(|(name, proc_block)| {
+                (name.to_string(), stage_functions(proc_block, &functions))
+            })
+            .collect();
+
+        Some(DebugSymbols { functions, stages })
+    } else {
+        None
+    };
+
+    cmd.exec_mut(move |_, res| {
+        res.insert(size_report.clone());
+
+        if let Some(debug_symbols) = &debug_symbols {
+            res.insert(debug_symbols.clone());
+        }
+    });
+}
+
+fn analyse(
+    wasm: &[u8],
+) -> Result<(SizeReport, BTreeMap<u32, String>), BinaryReaderError> {
+    let mut report = SizeReport {
+        total: wasm.len(),
+        ..Default::default()
+    };
+    let mut functions = BTreeMap::new();
+
+    for payload in Parser::default().parse_all(wasm) {
+        match payload? {
+            Payload::CodeSectionStart { size, .. } => {
+                report.code += size as usize;
+            },
+            Payload::CustomSection {
+                name,
+                data,
+                data_offset,
+                ..
+            } => {
+                if name.starts_with(".debug_") {
+                    report.debug_info += data.len();
+                } else if name == "name" {
+                    report.names += data.len();
+                    read_function_names(data, data_offset, &mut functions)?;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Ok((report, functions))
+}
+
+fn read_function_names(
+    data: &[u8],
+    offset: usize,
+    functions: &mut BTreeMap<u32, String>,
+) -> Result<(), BinaryReaderError> {
+    let mut reader = NameSectionReader::new(data, offset)?;
+
+    while !reader.eof() {
+        if let Name::Function(names) = reader.read()? {
+            let mut map = names.get_map()?;
+
+            for _ in 0..map.get_count() {
+                let naming = map.read()?;
+                let demangled = rustc_demangle::demangle(naming.name);
+                functions.insert(naming.index, format!("{:#}", demangled));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Find all the functions that were defined by a proc-block's crate.
+fn stage_functions(
+    proc_block: &ProcBlock,
+    functions: &BTreeMap<u32, String>,
+) -> Vec<u32> {
+    let crate_name = proc_block.name().replace('-', "_");
+    // Note: trait methods are demangled as "<my_crate::Type as Trait>::method"
+    let free_function = format!("{}::", crate_name);
+    let trait_impl = format!("<{}::", crate_name);
+
+    functions
+        .iter()
+        .filter(|(_, name)| {
+            name.starts_with(&free_function) || name.starts_with(&trait_impl)
+        })
+        .map(|(&index, _)| index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_functions_belonging_to_a_proc_block() {
+        let proc_block = ProcBlock {
+            path: "hotg-ai/proc-blocks#audio_float_conversion"
+                .parse()
+                .unwrap(),
+            parameters: Default::default(),
+        };
+        let functions: BTreeMap<u32, String> = vec![
+            (0, "audio_float_conversion::helper"),
+            (
+                1,
+                "<audio_float_conversion::AudioFloatConversion as \
+                 hotg_rune_proc_blocks::Transform<Tensor<i16>>>::transform",
+            ),
+            (2, "hotg_runicos_base_wasm::Model::infer"),
+            (3, "audio_float_conversion_v2::helper"),
+            (4, "core::ptr::drop_in_place<audio_float_conversion::Thing>"),
+        ]
+        .into_iter()
+        .map(|(ix, name)| (ix, name.to_string()))
+        .collect();
+
+        let got = stage_functions(&proc_block, &functions);
+
+        assert_eq!(got, vec![0, 1]);
+    }
+}
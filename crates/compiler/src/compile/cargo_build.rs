@@ -4,15 +4,23 @@ use std::{
     sync::Mutex,
 };
 
-use legion::systems::CommandBuffer;
+use legion::{systems::CommandBuffer, world::SubWorld, Query};
 
 use crate::{
+    artifacts::{ArtifactKey, ArtifactStore, PruningPolicy},
+    codegen::File,
     compile::{CompilationResult, CompileError, CompiledBinary},
-    BuildContext, Verbosity,
+    BuildContext, FeatureFlags, Verbosity,
 };
 
 #[legion::system]
-pub(crate) fn run(cmd: &mut CommandBuffer, #[resource] ctx: &BuildContext) {
+pub(crate) fn run(
+    cmd: &mut CommandBuffer,
+    world: &SubWorld,
+    files: &mut Query<&File>,
+    #[resource] ctx: &BuildContext,
+    #[resource] features: &FeatureFlags,
+) {
     let BuildContext {
         working_directory,
         optimized,
@@ -21,9 +29,34 @@ pub(crate) fn run(cmd: &mut CommandBuffer, #[resource] ctx: &BuildContext) {
         ..
     } = ctx;
 
-    rustfmt(working_directory);
+    let key = ArtifactKey::new(ctx, files.iter(world));
+    let store = features.artifact_store();
+
+    let result = match store.as_ref().and_then(|s| cached(s, &key)) {
+        Some(binary) => Ok(binary),
+        None => {
+            rustfmt(working_directory);
+
+            let target_dir = features
+                .target_dir(ctx.target_triple())
+                .unwrap_or_else(|| working_directory.join("target"));
+
+            let result = build(
+                name,
+                working_directory,
+                &target_dir,
+                ctx.target_triple(),
+                *optimized,
+                *verbosity,
+            );
+
+            if let (Some(store), Ok(binary)) = (&store, &result) {
+                save(store, &key, ctx, binary, &features.artifact_pruning);
+            }
 
-    let result = build(name, working_directory, *optimized, *verbosity);
+            result
+        },
+    };
 
     // Note: the exec_mut() method takes a Fn() closure and not a FnOnce(), so
     // we need to use a Mutex<Option<_>> to move the result.
@@ -31,12 +64,53 @@ pub(crate) fn run(cmd: &mut CommandBuffer, #[resource] ctx: &BuildContext) {
     cmd.exec_mut(move |_, res| {
         let result = result.lock().unwrap().take().unwrap();
         res.insert(CompilationResult(result));
+        res.insert(key.clone());
     })
 }
 
+/// Check whether an identical project has been compiled before.
+fn cached(store: &ArtifactStore, key: &ArtifactKey) -> Option<CompiledBinary> {
+    match store.get(key) {
+        Ok(Some(binary)) => {
+            log::debug!("Reusing the previous build, {}", key);
+            Some(binary)
+        },
+        Ok(None) => None,
+        Err(e) => {
+            log::warn!("Unable to read {} from the artifact store: {}", key, e);
+            None
+        },
+    }
+}
+
+/// Save the compiled Rune for next time, clearing out old builds. Failing to
+/// update the cache shouldn't fail the build.
+fn save(
+    store: &ArtifactStore,
+    key: &ArtifactKey,
+    ctx: &BuildContext,
+    binary: &CompiledBinary,
+    policy: &PruningPolicy,
+) {
+    if let Err(e) = store.insert(key, ctx, binary) {
+        log::warn!("Unable to save {} to the artifact store: {}", key, e);
+        return;
+    }
+
+    match store.prune(policy) {
+        Ok(removed) if !removed.is_empty() => {
+            log::debug!("Pruned {} old builds", removed.len());
+        },
+        Ok(_) => {},
+        Err(e) => log::warn!("Unable to prune the artifact store: {}", e),
+    }
+}
+
 fn build(
     name: &str,
     working_directory: &Path,
+    target_dir: &Path,
+    target: &str,
     optimized: bool,
     verbosity: Verbosity,
 ) -> Result<CompiledBinary, CompileError> {
@@ -44,7 +118,9 @@ fn build(
     cmd.arg("build")
         .arg("--manifest-path")
         .arg(working_directory.join("Cargo.toml"))
-        .arg("--target=wasm32-unknown-unknown");
+        .arg(format!("--target={}", target))
+        .arg("--target-dir")
+        .arg(target_dir);
 
     if optimized {
         cmd.arg("--release");
@@ -66,9 +142,8 @@ fn build(
 
     let config = if optimized { "release" } else { "debug" };
 
-    let wasm = working_directory
-        .join("target")
-        .join("wasm32-unknown-unknown")
+    let wasm = target_dir
+        .join(target)
         .join(config)
         .join(name.replace("-", "_"))
         .with_extension("wasm");
@@ -78,7 +153,7 @@ fn build(
         .map_err(|error| CompileError::UnableToReadBinary { path: wasm, error })
 }
 
-fn rustfmt(working_directory: &Path) {
+pub(super) fn rustfmt(working_directory: &Path) {
     let mut cmd = Command::new("cargo");
     cmd.arg("fmt")
         .arg("--manifest-path")
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     error::Error,
     fmt::{self, Display, Formatter},
     ops::Deref,
@@ -24,6 +25,65 @@ impl Deref for CompiledBinary {
     fn deref(&self) -> &Self::Target { &self.0 }
 }
 
+/// A breakdown of how much space each part of the compiled Rune takes up, in
+/// bytes.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct SizeReport {
+    pub total: usize,
+    /// The executable code.
+    pub code: usize,
+    /// DWARF debug info (the `.debug_*` custom sections).
+    pub debug_info: usize,
+    /// The `name` custom section.
+    pub names: usize,
+    /// How much space was saved by deduplicating string constants.
+    #[serde(default)]
+    pub strings: StringTableReport,
+}
+
+/// Statistics about the table that all of a Rune's string constants (labels,
+/// default resource values, error messages, etc.) are stored in.
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct StringTableReport {
+    /// The number of times the generated code refers to a string.
+    pub references: usize,
+    /// How many bytes the strings would take up if each reference had its
+    /// own copy.
+    pub referenced_bytes: usize,
+    /// The size of the deduplicated string table.
+    pub table_bytes: usize,
+}
+
+impl StringTableReport {
+    /// The number of bytes saved by deduplicating strings.
+    pub fn saved(&self) -> usize {
+        self.referenced_bytes.saturating_sub(self.table_bytes)
+    }
+}
+
+/// The functions making up a compiled Rune, generated when
+/// [`crate::BuildContext::debug_symbols`] is set.
+///
+/// This lets tools show `fft` instead of `func[137]` in backtraces.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DebugSymbols {
+    /// The demangled name for each function, keyed by its index.
+    pub functions: BTreeMap<u32, String>,
+    /// The indices of the functions defined by each stage's proc-block.
+    pub stages: BTreeMap<String, Vec<u32>>,
+}
+
 /// The result from compiling... Essentially a newtype'd `Result`.
 #[derive(Debug)]
 pub struct CompilationResult(pub Result<CompiledBinary, CompileError>);
@@ -1,5 +1,7 @@
+mod analyse_binary;
 mod cargo_build;
 mod components;
+mod update_lockfile;
 mod write_project_to_disk;
 
 pub use self::components::*;
@@ -9,4 +11,12 @@ pub fn phase() -> Phase {
     Phase::new()
         .and_then(write_project_to_disk::run_system)
         .and_then(cargo_build::run_system)
+        .and_then(update_lockfile::run_system)
+        .and_then(analyse_binary::run_system)
+}
+
+/// Write the generated project to [`crate::BuildContext::emit_project`]
+/// instead of compiling it.
+pub fn emit_project_phase() -> Phase {
+    Phase::new().and_then(write_project_to_disk::emit_system)
 }
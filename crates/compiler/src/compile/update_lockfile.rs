@@ -0,0 +1,63 @@
+use legion::{world::SubWorld, Query};
+
+use crate::{
+    compile::CompilationResult, lockfile::Lockfile, lowering::ProcBlock,
+    BuildContext,
+};
+
+/// Record what each proc-block was resolved to in the `Runefile.lock` after a
+/// successful build.
+///
+/// Locked builds never touch the `Runefile.lock` because any drift would
+/// have already been reported during codegen.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] ctx: &BuildContext,
+    #[resource] result: &CompilationResult,
+    query: &mut Query<&ProcBlock>,
+) {
+    if ctx.locked || result.0.is_err() {
+        return;
+    }
+
+    let cargo_lock = ctx.working_directory.join("Cargo.lock");
+    let cargo_lock = match std::fs::read_to_string(&cargo_lock) {
+        Ok(src) => src,
+        Err(e) => {
+            // This is normal when cargo didn't need to run because we
+            // reused a cached build.
+            log::debug!(
+                "Unable to read \"{}\", so {} won't be updated: {}",
+                cargo_lock.display(),
+                Lockfile::FILE_NAME,
+                e
+            );
+            return;
+        },
+    };
+
+    let proc_blocks = query.iter(world).map(|p| (&p.path, p.name()));
+    let lockfile = match Lockfile::resolve(
+        &cargo_lock,
+        proc_blocks,
+        &ctx.current_directory,
+    ) {
+        Ok(lockfile) => lockfile,
+        Err(e) => {
+            log::warn!("Unable to work out which proc-blocks were used: {}", e);
+            return;
+        },
+    };
+
+    match Lockfile::load(&ctx.current_directory) {
+        Ok(Some(existing)) if existing == lockfile => return,
+        Ok(Some(_)) | Ok(None) => {},
+        Err(e) => log::warn!("{}", e),
+    }
+
+    match lockfile.save(&ctx.current_directory) {
+        Ok(()) => log::debug!("Updated {}", Lockfile::FILE_NAME),
+        Err(e) => log::warn!("{}", e),
+    }
+}
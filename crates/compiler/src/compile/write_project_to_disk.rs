@@ -1,27 +1,116 @@
-use crate::{codegen::File, BuildContext};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use codespan_reporting::diagnostic::Diagnostic;
+use legion::{world::SubWorld, Query};
+
+use crate::{codegen::File, compile::cargo_build, BuildContext, Diagnostics};
 
 #[legion::system(for_each)]
 pub(crate) fn run(File { path, data }: &File, #[resource] ctx: &BuildContext) {
     let full_path = ctx.working_directory.join(path);
 
-    if let Some(parent) = full_path.parent() {
-        if let Err(e) = std::fs::create_dir_all(parent) {
-            log::error!(
-                "Unable to create the \"{}\" directory: {}",
-                parent.display(),
-                e
-            );
-            return;
+    match write_if_changed(&full_path, data) {
+        Ok(true) => log::debug!(
+            "Wrote {} bytes to \"{}\"",
+            data.len(),
+            full_path.display()
+        ),
+        Ok(false) => {
+            log::debug!("\"{}\" is unchanged", full_path.display())
+        },
+        Err(e) => {
+            log::error!("Unable to write to \"{}\": {}", full_path.display(), e)
+        },
+    }
+}
+
+/// Write the generated project to [`BuildContext::emit_project`] so it can be
+/// inspected, modified, or compiled by hand.
+#[legion::system]
+pub(crate) fn emit(
+    world: &SubWorld,
+    files: &mut Query<&File>,
+    #[resource] ctx: &BuildContext,
+    #[resource] diags: &mut Diagnostics,
+) {
+    let directory = match &ctx.emit_project {
+        Some(d) => d,
+        None => return,
+    };
+
+    let mut ok = true;
+
+    for File { path, data } in files.iter(world) {
+        let full_path = directory.join(path);
+
+        if let Err(e) = write_if_changed(&full_path, data) {
+            diags.push(unable_to_emit_diagnostic(full_path, e));
+            ok = false;
         }
     }
 
-    log::debug!(
-        "Writing {} bytes to \"{}\"",
-        data.len(),
-        full_path.display()
-    );
+    if ok {
+        cargo_build::rustfmt(directory);
+    }
+}
+
+fn unable_to_emit_diagnostic(
+    path: PathBuf,
+    e: std::io::Error,
+) -> Diagnostic<()> {
+    Diagnostic::error().with_message(format!(
+        "Unable to write the generated project to \"{}\": {}",
+        path.display(),
+        e
+    ))
+}
+
+/// Write to a file (creating its parent directories), leaving it alone if it
+/// already has the same contents.
+///
+/// Cargo decides what to rebuild based on modification times, so rewriting
+/// identical files would turn a no-op rebuild into a full one.
+fn write_if_changed(path: &Path, data: &[u8]) -> std::io::Result<bool> {
+    match std::fs::read(path) {
+        Ok(existing) if existing == data => return Ok(false),
+        Ok(_) => {},
+        Err(e) if e.kind() == ErrorKind::NotFound => {},
+        Err(e) => return Err(e),
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::write(path, data)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_files_are_not_rewritten() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("lib.rs");
+
+        assert!(write_if_changed(&path, b"fn main() {}").unwrap());
+        assert!(!write_if_changed(&path, b"fn main() {}").unwrap());
+        assert!(write_if_changed(&path, b"fn main() { todo!() }").unwrap());
+        assert_eq!(std::fs::read(&path).unwrap(), b"fn main() { todo!() }");
+    }
+
+    #[test]
+    fn parent_directories_are_created() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(".cargo").join("config.toml");
+
+        assert!(write_if_changed(&path, b"[build]").unwrap());
 
-    if let Err(e) = std::fs::write(&full_path, data) {
-        log::error!("Unable to write to \"{}\": {}", full_path.display(), e);
+        assert_eq!(std::fs::read(&path).unwrap(), b"[build]");
     }
 }
@@ -0,0 +1,283 @@
+//! Build settings which can be checked into a project's repository.
+//!
+//! Instead of passing the same flags to every build, a project can put a
+//! `rune.toml` next to its `Runefile.yml` (or add a `[package.metadata.rune]`
+//! table to its `Cargo.toml`).
+//!
+//! ```toml
+//! # The profile used when none is requested explicitly
+//! default-profile = "release"
+//!
+//! [profile.release]
+//! simd = true
+//!
+//! [profile.dev]
+//! optimized = false
+//! debug-symbols = true
+//!
+//! # Warn when the Rune won't fit on the target device
+//! [profile.esp32]
+//! device-memory = "4MB"
+//!
+//! # Development knobs, see FeatureFlags
+//! [features]
+//! rune-repo-dir = "../rune"
+//! build-cache-dir = "target/rune"
+//! ```
+//!
+//! Relative paths are resolved relative to the directory containing the
+//! config file.
+
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use crate::{parse::ByteSize, BuildContext, FeatureFlags};
+
+/// The name of the standalone config file.
+pub const CONFIG_FILE: &str = "rune.toml";
+
+/// Build settings loaded from a `rune.toml` or `Cargo.toml`.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// The profile to use when none is requested explicitly.
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    /// Named sets of build options (e.g. `release` or `dev`).
+    #[serde(default, rename = "profile")]
+    pub profiles: BTreeMap<String, Profile>,
+    #[serde(default)]
+    pub features: Features,
+}
+
+/// A named set of build options. Options that aren't set are left unchanged.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    /// See [`BuildContext::optimized`].
+    #[serde(default)]
+    pub optimized: Option<bool>,
+    /// See [`BuildContext::debug_symbols`].
+    #[serde(default)]
+    pub debug_symbols: Option<bool>,
+    /// See [`BuildContext::simd`].
+    #[serde(default)]
+    pub simd: Option<bool>,
+    /// See [`BuildContext::memory64`].
+    #[serde(default)]
+    pub memory64: Option<bool>,
+    /// See [`BuildContext::device_memory`].
+    #[serde(default)]
+    pub device_memory: Option<ByteSize>,
+}
+
+/// Overrides for the [`FeatureFlags`] used while building.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Features {
+    /// See [`FeatureFlags::set_rune_repo_dir()`].
+    #[serde(default)]
+    pub rune_repo_dir: Option<PathBuf>,
+    /// See [`FeatureFlags::set_build_cache_dir()`].
+    #[serde(default)]
+    pub build_cache_dir: Option<PathBuf>,
+}
+
+impl Config {
+    /// Look for a `rune.toml` in a directory, falling back to the
+    /// `[package.metadata.rune]` table in its `Cargo.toml`.
+    ///
+    /// Returns `None` if neither exist.
+    pub fn load(directory: &Path) -> Result<Option<Config>, Error> {
+        let rune_toml = directory.join(CONFIG_FILE);
+        if rune_toml.exists() {
+            let src = std::fs::read_to_string(&rune_toml)?;
+            let config = Config::parse(&src)
+                .map_err(|e| invalid(&rune_toml, e))?
+                .resolve_paths(directory);
+            return Ok(Some(config));
+        }
+
+        let cargo_toml = directory.join("Cargo.toml");
+        if cargo_toml.exists() {
+            let src = std::fs::read_to_string(&cargo_toml)?;
+            let config = Config::from_cargo_toml(&src)
+                .map_err(|e| invalid(&cargo_toml, e))?
+                .map(|c| c.resolve_paths(directory));
+            return Ok(config);
+        }
+
+        Ok(None)
+    }
+
+    /// Parse the contents of a `rune.toml` file.
+    pub fn parse(src: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(src)
+    }
+
+    /// Read the `[package.metadata.rune]` table from a `Cargo.toml` file, if
+    /// there is one.
+    pub fn from_cargo_toml(
+        src: &str,
+    ) -> Result<Option<Config>, toml::de::Error> {
+        let manifest: toml::Value = toml::from_str(src)?;
+
+        manifest
+            .get("package")
+            .and_then(|p| p.get("metadata"))
+            .and_then(|m| m.get("rune"))
+            .cloned()
+            .map(|table| table.try_into())
+            .transpose()
+    }
+
+    /// Look up a profile by name, or the [`Config::default_profile`] if no
+    /// name is provided.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile, Error> {
+        match name.or_else(|| self.default_profile.as_deref()) {
+            Some(name) => self.profiles.get(name).cloned().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("There is no \"{}\" profile", name),
+                )
+            }),
+            None => Ok(Profile::default()),
+        }
+    }
+
+    /// Update a [`BuildContext`] using the build options from a profile.
+    pub fn apply(
+        &self,
+        ctx: &mut BuildContext,
+        profile: Option<&str>,
+    ) -> Result<(), Error> {
+        let Profile {
+            optimized,
+            debug_symbols,
+            simd,
+            memory64,
+            device_memory,
+        } = self.profile(profile)?;
+
+        if let Some(optimized) = optimized {
+            ctx.optimized = optimized;
+        }
+        if let Some(debug_symbols) = debug_symbols {
+            ctx.debug_symbols = debug_symbols;
+        }
+        if let Some(simd) = simd {
+            ctx.simd = simd;
+        }
+        if let Some(memory64) = memory64 {
+            ctx.memory64 = memory64;
+        }
+        if device_memory.is_some() {
+            ctx.device_memory = device_memory;
+        }
+
+        Ok(())
+    }
+
+    /// Update the [`FeatureFlags`] with any overrides from the config.
+    pub fn apply_features(&self, features: &mut FeatureFlags) {
+        let Features {
+            rune_repo_dir,
+            build_cache_dir,
+        } = &self.features;
+
+        if let Some(dir) = rune_repo_dir {
+            features.set_rune_repo_dir(dir.clone());
+        }
+        if let Some(dir) = build_cache_dir {
+            features.set_build_cache_dir(dir.clone());
+        }
+    }
+
+    fn resolve_paths(mut self, directory: &Path) -> Self {
+        let resolve = |path: &mut Option<PathBuf>| {
+            if let Some(path) = path {
+                *path = directory.join(&*path);
+            }
+        };
+
+        resolve(&mut self.features.rune_repo_dir);
+        resolve(&mut self.features.build_cache_dir);
+
+        self
+    }
+}
+
+fn invalid(path: &Path, error: toml::de::Error) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Unable to parse \"{}\": {}", path.display(), error),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_override_the_build_context() {
+        let config = Config::parse(
+            r#"
+            default-profile = "release"
+
+            [profile.release]
+            simd = true
+
+            [profile.dev]
+            optimized = false
+            debug-symbols = true
+            "#,
+        )
+        .unwrap();
+        let doc = crate::parse::Document::parse(
+            "version: 1\nimage: runicos/base\npipeline: {}",
+        )
+        .unwrap();
+        let mut ctx = BuildContext::from_doc(doc);
+        ctx.optimized = true;
+
+        config.apply(&mut ctx, None).unwrap();
+        assert!(ctx.optimized && ctx.simd && !ctx.debug_symbols);
+
+        config.apply(&mut ctx, Some("dev")).unwrap();
+        assert!(!ctx.optimized && ctx.simd && ctx.debug_symbols);
+
+        assert!(config.apply(&mut ctx, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn read_the_config_from_cargo_metadata() {
+        let src = r#"
+            [package]
+            name = "my-rune"
+            version = "0.1.0"
+
+            [package.metadata.rune.features]
+            build-cache-dir = "target/rune"
+        "#;
+
+        let got = Config::from_cargo_toml(src)
+            .unwrap()
+            .unwrap()
+            .resolve_paths(Path::new("/project"));
+
+        assert_eq!(
+            got.features.build_cache_dir,
+            Some(PathBuf::from("/project/target/rune"))
+        );
+        assert_eq!(Config::from_cargo_toml("[package]").unwrap(), None);
+    }
+}
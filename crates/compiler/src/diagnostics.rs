@@ -1,4 +1,5 @@
-use codespan_reporting::diagnostic::{Diagnostic, Severity};
+use codespan_reporting::diagnostic::{Diagnostic, LabelStyle, Severity};
+use serde_json::{json, Value};
 
 type FileId = ();
 
@@ -48,6 +49,62 @@ impl Diagnostics {
     pub fn drain(&mut self) -> impl Iterator<Item = Diagnostic<()>> + '_ {
         self.0.drain(..)
     }
+
+    /// Convert the diagnostics to JSON so they can be displayed by something
+    /// other than a terminal (e.g. inline errors in an editor).
+    ///
+    /// Each diagnostic has a `severity`, `message`, optional `code`, the byte
+    /// ranges it refers to in the Runefile (`labels`), and any `notes`. Notes
+    /// starting with `"hint: "` are reported as `suggestions` instead.
+    pub fn to_json(&self) -> Value {
+        Value::Array(self.iter().map(diagnostic_to_json).collect())
+    }
+}
+
+fn diagnostic_to_json(diag: &Diagnostic<FileId>) -> Value {
+    let severity = match diag.severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    };
+
+    let labels: Vec<Value> = diag
+        .labels
+        .iter()
+        .map(|label| {
+            let style = match label.style {
+                LabelStyle::Primary => "primary",
+                LabelStyle::Secondary => "secondary",
+            };
+            json!({
+                "style": style,
+                "start": label.range.start,
+                "end": label.range.end,
+                "message": label.message,
+            })
+        })
+        .collect();
+
+    let (suggestions, notes): (Vec<&str>, Vec<&str>) = diag
+        .notes
+        .iter()
+        .map(|note| note.as_str())
+        .partition(|note| note.starts_with("hint: "));
+    let suggestions: Vec<&str> = suggestions
+        .into_iter()
+        .map(|s| s.trim_start_matches("hint: "))
+        .collect();
+
+    json!({
+        "severity": severity,
+        "code": diag.code,
+        "message": diag.message,
+        "labels": labels,
+        "notes": notes,
+        "suggestions": suggestions,
+    })
 }
 
 impl<'a> IntoIterator for &'a Diagnostics {
@@ -69,3 +126,41 @@ impl Extend<Diagnostic<FileId>> for Diagnostics {
         self.0.extend(iter);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use codespan_reporting::diagnostic::Label;
+
+    use super::*;
+
+    #[test]
+    fn diagnostics_as_json() {
+        let mut diags = Diagnostics::new();
+        diags.push(
+            Diagnostic::warning()
+                .with_code("W042")
+                .with_message("Unversioned proc-block")
+                .with_labels(vec![Label::primary((), 10..20)])
+                .with_notes(vec![
+                    "It may change unexpectedly".to_string(),
+                    "hint: add \"@v0.11\"".to_string(),
+                ]),
+        );
+
+        let got = diags.to_json();
+
+        assert_eq!(
+            got,
+            json!([{
+                "severity": "warning",
+                "code": "W042",
+                "message": "Unversioned proc-block",
+                "labels": [
+                    { "style": "primary", "start": 10, "end": 20, "message": "" },
+                ],
+                "notes": ["It may change unexpectedly"],
+                "suggestions": ["add \"@v0.11\""],
+            }])
+        );
+    }
+}
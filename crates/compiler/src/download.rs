@@ -0,0 +1,309 @@
+//! Fetching models, resources, and proc-blocks which a Runefile refers to by
+//! URL.
+//!
+//! Anything downloaded needs to say what it expects to get by adding the
+//! SHA-256 hash of its contents as a URL fragment (e.g.
+//! `https://example.com/model.tflite#sha256=e3b0c442...`). Downloads are
+//! stored in a content-addressed cache inside the build's
+//! [working directory][crate::BuildContext::working_directory], so
+//! subsequent builds don't need to touch the network.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::BuildContext;
+
+const SHA256_FRAGMENT: &str = "#sha256=";
+
+/// A file referenced by its URL and the SHA-256 hash of its contents.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct RemoteFile<'a> {
+    pub url: &'a str,
+    pub sha256: &'a str,
+}
+
+impl<'a> RemoteFile<'a> {
+    /// Parse a `https://...#sha256=...` URL, returning `None` if `s` isn't a
+    /// URL at all.
+    pub(crate) fn parse(s: &'a str) -> Option<Result<Self, DownloadError>> {
+        if !is_url(s) {
+            return None;
+        }
+
+        Some(RemoteFile::parse_url(s))
+    }
+
+    fn parse_url(s: &'a str) -> Result<Self, DownloadError> {
+        if !s.starts_with("https://") {
+            return Err(DownloadError::Insecure { url: s.to_string() });
+        }
+
+        let (url, sha256) = match s.find(SHA256_FRAGMENT) {
+            Some(ix) => (&s[..ix], &s[ix + SHA256_FRAGMENT.len()..]),
+            None => {
+                return Err(DownloadError::MissingHash { url: s.to_string() })
+            },
+        };
+
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(DownloadError::InvalidHash {
+                url: url.to_string(),
+                sha256: sha256.to_string(),
+            });
+        }
+
+        Ok(RemoteFile { url, sha256 })
+    }
+
+    /// Get the file's contents, downloading it into the `cache_dir` if it
+    /// hasn't been seen before.
+    pub(crate) fn fetch(
+        &self,
+        cache_dir: &Path,
+    ) -> Result<Vec<u8>, DownloadError> {
+        let cached = cache_dir.join(self.sha256.to_ascii_lowercase());
+
+        if let Ok(data) = std::fs::read(&cached) {
+            // Don't trust the cache blindly, it may have been corrupted
+            if sha256(&data) == self.sha256.to_ascii_lowercase() {
+                log::debug!("Using the cached copy of \"{}\"", self.url);
+                return Ok(data);
+            }
+        }
+
+        log::info!("Downloading \"{}\"", self.url);
+        let data = self.download()?;
+
+        std::fs::create_dir_all(cache_dir)
+            .and_then(|_| std::fs::write(&cached, &data))
+            .map_err(|error| DownloadError::Cache {
+                path: cached,
+                error,
+            })?;
+
+        Ok(data)
+    }
+
+    /// Fetch a zip archive and extract it, returning the directory its
+    /// contents were extracted to.
+    pub(crate) fn fetch_archive(
+        &self,
+        cache_dir: &Path,
+    ) -> Result<PathBuf, DownloadError> {
+        let dir = self.archive_dir(cache_dir);
+
+        if dir.exists() {
+            return Ok(dir);
+        }
+
+        let data = self.fetch(cache_dir)?;
+        let mut archive =
+            zip::ZipArchive::new(Cursor::new(data)).map_err(|error| {
+                DownloadError::Archive {
+                    url: self.url.to_string(),
+                    error,
+                }
+            })?;
+
+        // Extract to a temporary directory first so an interrupted build
+        // doesn't leave a half-extracted proc-block lying around.
+        let temp = dir.with_extension("partial");
+        let _ = std::fs::remove_dir_all(&temp);
+        archive
+            .extract(&temp)
+            .map_err(|error| DownloadError::Archive {
+                url: self.url.to_string(),
+                error,
+            })?;
+        std::fs::rename(&temp, &dir).map_err(|error| DownloadError::Cache {
+            path: dir.clone(),
+            error,
+        })?;
+
+        Ok(dir)
+    }
+
+    /// Where [`RemoteFile::fetch_archive()`] will extract the archive to.
+    pub(crate) fn archive_dir(&self, cache_dir: &Path) -> PathBuf {
+        cache_dir.join(format!("{}.d", self.sha256.to_ascii_lowercase()))
+    }
+
+    fn download(&self) -> Result<Vec<u8>, DownloadError> {
+        let request_failed = |error| DownloadError::Request {
+            url: self.url.to_string(),
+            error: Box::new(error),
+        };
+
+        let response = ureq::get(self.url).call().map_err(request_failed)?;
+
+        let mut data = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut data)
+            .map_err(|e| request_failed(e.into()))?;
+
+        let actual = sha256(&data);
+        if actual != self.sha256.to_ascii_lowercase() {
+            return Err(DownloadError::HashMismatch {
+                url: self.url.to_string(),
+                expected: self.sha256.to_string(),
+                actual,
+            });
+        }
+
+        Ok(data)
+    }
+}
+
+/// Where downloads for this build are cached.
+pub(crate) fn cache_dir(ctx: &BuildContext) -> PathBuf {
+    ctx.working_directory.join("downloads")
+}
+
+pub(crate) fn is_url(s: &str) -> bool {
+    s.starts_with("https://") || s.starts_with("http://")
+}
+
+/// Strip the `#sha256=...` fragment from a URL (e.g. so its file extension
+/// can be checked).
+pub(crate) fn without_hash(s: &str) -> &str {
+    match s.find(SHA256_FRAGMENT) {
+        Some(ix) if is_url(s) => &s[..ix],
+        _ => s,
+    }
+}
+
+fn sha256(data: &[u8]) -> String { format!("{:x}", Sha256::digest(data)) }
+
+#[derive(Debug)]
+pub enum DownloadError {
+    Insecure {
+        url: String,
+    },
+    MissingHash {
+        url: String,
+    },
+    InvalidHash {
+        url: String,
+        sha256: String,
+    },
+    Request {
+        url: String,
+        error: Box<ureq::Error>,
+    },
+    HashMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    Archive {
+        url: String,
+        error: zip::result::ZipError,
+    },
+    Cache {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Insecure { url } => {
+                write!(f, "\"{}\" must be downloaded over HTTPS", url)
+            },
+            DownloadError::MissingHash { url } => write!(
+                f,
+                "\"{}\" needs a \"{}<hash>\" so its contents can be checked",
+                url, SHA256_FRAGMENT
+            ),
+            DownloadError::InvalidHash { url, sha256 } => write!(
+                f,
+                "\"{}\" isn't a valid SHA-256 hash for \"{}\"",
+                sha256, url
+            ),
+            DownloadError::Request { url, .. } => {
+                write!(f, "Unable to download \"{}\"", url)
+            },
+            DownloadError::HashMismatch {
+                url,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Expected \"{}\" to have a SHA-256 hash of {}, but it was {}",
+                url, expected, actual
+            ),
+            DownloadError::Archive { url, .. } => {
+                write!(f, "Unable to extract the archive from \"{}\"", url)
+            },
+            DownloadError::Cache { path, .. } => {
+                write!(f, "Unable to save \"{}\"", path.display())
+            },
+        }
+    }
+}
+
+impl Error for DownloadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DownloadError::Request { error, .. } => Some(&**error),
+            DownloadError::Archive { error, .. } => Some(error),
+            DownloadError::Cache { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str =
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn parse_urls() {
+        let url = format!("https://example.com/model.tflite#sha256={}", HASH);
+
+        assert_eq!(
+            RemoteFile::parse(&url).unwrap().unwrap(),
+            RemoteFile {
+                url: "https://example.com/model.tflite",
+                sha256: HASH,
+            }
+        );
+        assert!(RemoteFile::parse("./model.tflite").is_none());
+        assert!(matches!(
+            RemoteFile::parse("https://example.com/model.tflite"),
+            Some(Err(DownloadError::MissingHash { .. }))
+        ));
+        assert!(matches!(
+            RemoteFile::parse(&url.replace("https", "http")),
+            Some(Err(DownloadError::Insecure { .. }))
+        ));
+        assert_eq!(without_hash(&url), "https://example.com/model.tflite");
+    }
+
+    #[test]
+    fn use_the_cached_copy_when_the_hash_matches() {
+        let cache = tempfile::tempdir().unwrap();
+        // The hash of an empty file
+        std::fs::write(cache.path().join(HASH), b"").unwrap();
+        let file = RemoteFile {
+            url: "https://example.invalid/empty.txt",
+            sha256: HASH,
+        };
+
+        let got = file.fetch(cache.path()).unwrap();
+
+        assert!(got.is_empty());
+    }
+}
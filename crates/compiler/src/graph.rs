@@ -0,0 +1,346 @@
+//! Render a Rune's pipeline as a graph, for documentation or for visually
+//! checking that tensor shapes line up.
+//!
+//! A [`PipelineGraph`] is generated from the [`RuneGraph`] and can be
+//! serialized as JSON or converted to [Graphviz][dot] using
+//! [`PipelineGraph::to_dot()`].
+//!
+//! [dot]: https://graphviz.org/doc/info/lang.html
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter, Write},
+};
+
+use crate::codegen::{RuneGraph, TensorId};
+
+/// Every stage in a Rune and the tensors passed between them.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PipelineGraph {
+    pub name: String,
+    /// The pipeline's stages, sorted by name.
+    pub nodes: Vec<Node>,
+    /// Connections between stages, sorted by tensor.
+    pub edges: Vec<Edge>,
+}
+
+/// A single stage in the pipeline.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Node {
+    pub name: String,
+    pub kind: NodeKind,
+    /// What the stage does (e.g. the capability type, model format, or
+    /// proc-block path).
+    pub detail: String,
+    pub inputs: Vec<Tensor>,
+    pub outputs: Vec<Tensor>,
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodeKind {
+    Capability,
+    Model,
+    ProcBlock,
+    Output,
+}
+
+impl Display for NodeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeKind::Capability => write!(f, "capability"),
+            NodeKind::Model => write!(f, "model"),
+            NodeKind::ProcBlock => write!(f, "proc-block"),
+            NodeKind::Output => write!(f, "out"),
+        }
+    }
+}
+
+/// A tensor flowing from one stage to another.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub tensor: Tensor,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Tensor {
+    pub id: String,
+    /// The element type, or `None` if the tensor's shape isn't known.
+    pub element_type: Option<String>,
+    pub dimensions: Vec<usize>,
+}
+
+impl Tensor {
+    fn new(id: &TensorId, graph: &RuneGraph) -> Self {
+        let shape = graph.tensors.get(id);
+
+        Tensor {
+            id: id.0.clone(),
+            element_type: shape
+                .map(|s| s.element_type().rune_name().to_string()),
+            dimensions: shape
+                .map(|s| s.dimensions().to_vec())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Display for Tensor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.element_type {
+            Some(element_type) => {
+                write!(f, "{}{:?}", element_type, self.dimensions)
+            },
+            None => write!(f, "?"),
+        }
+    }
+}
+
+/// Generate a [`PipelineGraph`] from the [`RuneGraph`].
+pub fn generate(graph: &RuneGraph) -> PipelineGraph {
+    let mut nodes = BTreeMap::new();
+    let tensors = |ids: &[TensorId]| -> Vec<Tensor> {
+        ids.iter().map(|id| Tensor::new(id, graph)).collect()
+    };
+
+    for (name, cap) in &graph.capabilities {
+        nodes.insert(
+            name.to_string(),
+            Node {
+                name: name.to_string(),
+                kind: NodeKind::Capability,
+                detail: cap.kind.to_string(),
+                inputs: Vec::new(),
+                outputs: tensors(&cap.outputs),
+            },
+        );
+    }
+    for (name, model) in &graph.models {
+        nodes.insert(
+            name.to_string(),
+            Node {
+                name: name.to_string(),
+                kind: NodeKind::Model,
+                detail: model.mimetype.clone(),
+                inputs: tensors(&model.inputs),
+                outputs: tensors(&model.outputs),
+            },
+        );
+    }
+    for (name, proc_block) in &graph.proc_blocks {
+        nodes.insert(
+            name.to_string(),
+            Node {
+                name: name.to_string(),
+                kind: NodeKind::ProcBlock,
+                detail: proc_block.path.to_string(),
+                inputs: tensors(&proc_block.inputs),
+                outputs: tensors(&proc_block.outputs),
+            },
+        );
+    }
+    for (name, out) in &graph.outputs {
+        nodes.insert(
+            name.to_string(),
+            Node {
+                name: name.to_string(),
+                kind: NodeKind::Output,
+                detail: out.kind.to_string(),
+                inputs: tensors(&out.inputs),
+                outputs: Vec::new(),
+            },
+        );
+    }
+
+    let mut edges = Vec::new();
+
+    for producer in nodes.values() {
+        for tensor in &producer.outputs {
+            for consumer in nodes.values() {
+                if consumer.inputs.iter().any(|t| t.id == tensor.id) {
+                    edges.push(Edge {
+                        from: producer.name.clone(),
+                        to: consumer.name.clone(),
+                        tensor: tensor.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    edges.sort_by(|a, b| (&a.tensor.id, &a.to).cmp(&(&b.tensor.id, &b.to)));
+
+    PipelineGraph {
+        name: graph.rune.name.clone(),
+        nodes: nodes.into_iter().map(|(_, node)| node).collect(),
+        edges,
+    }
+}
+
+impl PipelineGraph {
+    /// Render the graph using the Graphviz `dot` language.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+
+        writeln!(dot, "digraph {} {{", quoted(&self.name)).unwrap();
+        dot.push_str("    rankdir=LR;\n");
+
+        for node in &self.nodes {
+            writeln!(
+                dot,
+                "    {} [shape={}, label={}];",
+                quoted(&node.name),
+                dot_shape(node.kind),
+                quoted(&format!(
+                    "{}\n{}: {}",
+                    node.name, node.kind, node.detail
+                ))
+            )
+            .unwrap();
+        }
+
+        for edge in &self.edges {
+            writeln!(
+                dot,
+                "    {} -> {} [label={}];",
+                quoted(&edge.from),
+                quoted(&edge.to),
+                quoted(&edge.tensor.to_string())
+            )
+            .unwrap();
+        }
+
+        dot.push_str("}\n");
+
+        dot
+    }
+}
+
+fn dot_shape(kind: NodeKind) -> &'static str {
+    match kind {
+        NodeKind::Capability => "invhouse",
+        NodeKind::Model => "box3d",
+        NodeKind::ProcBlock => "box",
+        NodeKind::Output => "house",
+    }
+}
+
+/// JSON strings are valid Graphviz quoted strings, and `\n` is also how
+/// Graphviz spells a line break in a label.
+fn quoted(s: &str) -> String {
+    serde_json::to_string(s).expect("Strings are always serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use hotg_rune_core::{ElementType, Shape};
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::{
+        codegen::{
+            CapabilitySummary, ModelSummary, OutputSummary, RuneSummary,
+        },
+        lowering::{SinkKind, SourceKind},
+        parse::ResourceOrString,
+    };
+
+    fn graph() -> RuneGraph {
+        let mut capabilities = IndexMap::new();
+        capabilities.insert(
+            "audio".into(),
+            CapabilitySummary {
+                kind: SourceKind::Sound,
+                args: HashMap::new(),
+                outputs: vec![TensorId("audio".into())],
+            },
+        );
+
+        let mut models = IndexMap::new();
+        models.insert(
+            "model".into(),
+            ModelSummary {
+                file: ResourceOrString::String("model.tflite".into()),
+                mimetype: hotg_rune_core::TFLITE_MIMETYPE.into(),
+                args: HashMap::new(),
+                inputs: vec![TensorId("audio".into())],
+                outputs: vec![TensorId("model".into())],
+                enabled_when: None,
+            },
+        );
+
+        let mut outputs = IndexMap::new();
+        outputs.insert(
+            "serial".into(),
+            OutputSummary {
+                kind: SinkKind::Serial,
+                args: HashMap::new(),
+                inputs: vec![TensorId("model".into())],
+                enabled_when: None,
+            },
+        );
+
+        let mut tensors = HashMap::new();
+        tensors.insert(
+            TensorId("audio".into()),
+            Shape::new(ElementType::I16, vec![1, 16000]),
+        );
+        tensors.insert(
+            TensorId("model".into()),
+            Shape::new(ElementType::F32, vec![1, 4]),
+        );
+
+        RuneGraph {
+            rune: RuneSummary {
+                name: "microspeech".into(),
+            },
+            capabilities,
+            models,
+            proc_blocks: IndexMap::new(),
+            outputs,
+            resources: HashMap::new(),
+            tensors,
+        }
+    }
+
+    #[test]
+    fn connect_each_producer_to_its_consumers() {
+        let got = generate(&graph());
+
+        let names: Vec<_> = got.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, &["audio", "model", "serial"]);
+        let edges: Vec<_> = got
+            .edges
+            .iter()
+            .map(|e| (e.from.as_str(), e.to.as_str(), e.tensor.to_string()))
+            .collect();
+        assert_eq!(
+            edges,
+            &[
+                ("audio", "model", "i16[1, 16000]".to_string()),
+                ("model", "serial", "f32[1, 4]".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_as_dot() {
+        let dot = generate(&graph()).to_dot();
+
+        assert!(dot.starts_with("digraph \"microspeech\" {\n"));
+        assert!(dot.contains(
+            "    \"audio\" [shape=invhouse, label=\"audio\\ncapability: \
+             Sound\"];\n"
+        ));
+        assert!(dot.contains(
+            "    \"audio\" -> \"model\" [label=\"i16[1, 16000]\"];\n"
+        ));
+        assert!(dot.ends_with("}\n"));
+    }
+}
@@ -1,10 +1,19 @@
 //! Callbacks that allow users to hook into the build process.
 
+use std::path::Path;
+
 use atomic_refcell::{AtomicRef, AtomicRefMut};
-use legion::{Resources, World};
+use cargo_toml::Dependency;
+use legion::{Entity, IntoQuery, Resources, World};
 
 use crate::{
-    compile::CompilationResult, lowering::NameTable, parse::DocumentV1,
+    codegen::{File, RuneGraph},
+    compile::{CompilationResult, DebugSymbols, SizeReport},
+    graph::PipelineGraph,
+    lowering::{NameTable, ProcBlockDescriptors},
+    parse::DocumentV1,
+    sbom::BillOfMaterials,
+    typescript::TypeScriptBindings,
     BuildContext, Diagnostics, FeatureFlags,
 };
 
@@ -68,6 +77,19 @@ pub trait Hooks {
         }
     }
 
+    /// Callback fired after the generated project has been written to
+    /// [`BuildContext::emit_project`].
+    ///
+    /// Unlike the other hooks, this defaults to [`Continuation::Halt`] so the
+    /// project isn't compiled. Return [`Continuation::Continue`] if you want
+    /// a Rune as well.
+    fn after_emit_project(
+        &mut self,
+        _ctx: &mut dyn AfterCodegenContext,
+    ) -> Continuation {
+        Continuation::Halt
+    }
+
     fn after_compile(
         &mut self,
         ctx: &mut dyn AfterCompileContext,
@@ -104,6 +126,13 @@ pub trait Context {
     fn feature_flags(&self) -> AtomicRef<'_, FeatureFlags> {
         self.resources().get().unwrap()
     }
+
+    /// Metadata for proc-blocks, used to infer the shapes of their outputs.
+    fn proc_block_descriptors_mut(
+        &self,
+    ) -> AtomicRefMut<'_, ProcBlockDescriptors> {
+        self.resources().get_mut().unwrap()
+    }
 }
 
 /// Context passed to the [`Hooks::after_parse()`] method.
@@ -123,6 +152,12 @@ pub trait AfterParseContext: Context {
     fn diagnostics_mut(&self) -> AtomicRefMut<'_, Diagnostics> {
         self.resources().get_mut().unwrap()
     }
+
+    /// The diagnostics emitted so far, in the format used by
+    /// [`Diagnostics::to_json()`].
+    fn diagnostics_json(&self) -> serde_json::Value {
+        self.diagnostics().to_json()
+    }
 }
 
 /// Context passed to the [`Hooks::after_lowering()`] method.
@@ -130,19 +165,107 @@ pub trait AfterLoweringContext: AfterParseContext {
     fn names(&self) -> AtomicRef<'_, NameTable> {
         self.resources().get().unwrap()
     }
+
+    /// List the proc-blocks, models, and resources this Rune depends on.
+    fn bill_of_materials(&self) -> BillOfMaterials {
+        crate::sbom::bill_of_materials(self.world(), &self.build_context().name)
+    }
 }
 
 /// Context passed to the [`Hooks::after_type_checking()`] method.
 pub trait AfterTypeCheckingContext: AfterLoweringContext {}
 
 /// Context passed to the [`Hooks::after_codegen()`] method.
-pub trait AfterCodegenContext: AfterTypeCheckingContext {}
+pub trait AfterCodegenContext: AfterTypeCheckingContext {
+    /// Generate TypeScript declarations and a JavaScript wrapper for using
+    /// this Rune from the web bindings.
+    fn typescript_bindings(&self) -> Option<TypeScriptBindings> {
+        <&RuneGraph>::query()
+            .iter(self.world())
+            .next()
+            .map(crate::typescript::generate)
+    }
+
+    /// The stages in this Rune and the tensors passed between them, for
+    /// rendering with Graphviz or saving as JSON.
+    fn pipeline_graph(&self) -> Option<PipelineGraph> {
+        <&RuneGraph>::query()
+            .iter(self.world())
+            .next()
+            .map(crate::graph::generate)
+    }
+
+    /// The pipeline graph embedded in the Rune's `.rune_graph` section.
+    ///
+    /// This is enough for a host to run the pipeline natively instead of
+    /// compiling it to WebAssembly.
+    fn rune_graph(&self) -> Option<RuneGraph> {
+        <&RuneGraph>::query().iter(self.world()).next().cloned()
+    }
+
+    /// Every file in the generated project (`lib.rs`, `Cargo.toml`, etc.),
+    /// sorted by path.
+    fn generated_files(&self) -> Vec<File> {
+        let mut files: Vec<File> =
+            <&File>::query().iter(self.world()).cloned().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files
+    }
+
+    /// Add a file to the generated project, replacing any file which already
+    /// has the same path.
+    ///
+    /// Rust code won't be compiled unless `lib.rs` refers to it, so you may
+    /// need to replace `lib.rs` as well (e.g. to add a `mod` declaration).
+    fn add_file(&mut self, file: File) {
+        let world = self.world_mut();
+
+        let existing: Vec<Entity> = <(Entity, &File)>::query()
+            .iter(world)
+            .filter(|(_, f)| f.path == file.path)
+            .map(|(&entity, _)| entity)
+            .collect();
+
+        for entity in existing {
+            if let Some(mut entry) = world.entry(entity) {
+                entry.remove_component::<File>();
+            }
+        }
+
+        world.push((file,));
+    }
+
+    /// Add a dependency to the generated `Cargo.toml`, replacing any
+    /// dependency with the same name.
+    fn add_dependency(&mut self, name: &str, dependency: Dependency) {
+        let cargo_toml = self
+            .generated_files()
+            .into_iter()
+            .find(|f| f.path == Path::new("Cargo.toml"))
+            .expect("The Cargo.toml is always generated during codegen");
+
+        let updated =
+            crate::codegen::add_dependency(&cargo_toml.data, name, dependency);
+
+        self.add_file(File::new("Cargo.toml", updated));
+    }
+}
 
 /// Context passed to the [`Hooks::after_compile()`] method.
 pub trait AfterCompileContext: AfterCodegenContext {
     fn take_compilation_result(&mut self) -> CompilationResult {
         self.resources_mut().remove().unwrap()
     }
+
+    /// How large the compiled Rune is, if compilation succeeded.
+    fn size_report(&self) -> Option<AtomicRef<'_, SizeReport>> {
+        self.resources().get()
+    }
+
+    /// Take the [`DebugSymbols`], if they were requested.
+    fn take_debug_symbols(&mut self) -> Option<DebugSymbols> {
+        self.resources_mut().remove()
+    }
 }
 
 pub(crate) struct Ctx<'world, 'res> {
@@ -29,21 +29,30 @@ extern crate pretty_assertions;
 #[macro_use]
 mod macros;
 
+pub mod artifacts;
 mod build_context;
 pub mod codegen;
 pub mod compile;
+pub mod config;
 mod diagnostics;
+mod download;
+pub mod graph;
 pub mod hooks;
+pub mod lockfile;
 pub mod lowering;
 pub mod parse;
 mod phases;
+pub mod prebuilt;
+pub mod sbom;
 pub mod serialize;
 mod toolchain;
 pub mod type_check;
+pub mod typescript;
+pub mod watch;
 
 pub use crate::{
     build_context::{BuildContext, FeatureFlags, Verbosity},
     diagnostics::Diagnostics,
     phases::{build, build_with_hooks, Phase},
-    toolchain::rust_toolchain,
+    toolchain::{rust_toolchain, rust_toolchain_for_target},
 };
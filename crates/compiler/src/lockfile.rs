@@ -0,0 +1,407 @@
+//! Pinning proc-blocks to exactly what a previous build used, so Rune builds
+//! are reproducible.
+//!
+//! The first time a Rune is compiled, each proc-block's [`Path`] is resolved
+//! to something which can't change underneath us (a git commit, an exact
+//! crates.io version, or a hash of a local directory's contents) and written
+//! to a `Runefile.lock` next to the Runefile. Later builds reuse those pins
+//! instead of whatever the tag or version requirement currently points to.
+//!
+//! When [`crate::BuildContext::locked`] is set, a missing `Runefile.lock` or
+//! a proc-block which doesn't match its pin is an error instead of being
+//! silently re-resolved.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    path::{Path as FsPath, PathBuf},
+};
+
+use cargo_toml::DependencyDetail;
+use sha2::{Digest, Sha256};
+
+use crate::parse::Path;
+
+/// The contents of a `Runefile.lock`.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct Lockfile {
+    /// Each proc-block the Rune uses, keyed by the path from its Runefile.
+    #[serde(default, rename = "proc-blocks")]
+    pub proc_blocks: BTreeMap<String, LockedProcBlock>,
+}
+
+/// What a proc-block's [`Path`] was resolved to.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "source", rename_all = "kebab-case")]
+pub enum LockedProcBlock {
+    /// A specific commit from a git repository.
+    Git { repository: String, commit: String },
+    /// An exact version from crates.io.
+    CratesIo { version: String },
+    /// A directory on disk, identified by the SHA-256 hash of its contents.
+    Local { sha256: String },
+    /// A zip archive downloaded from a URL which already includes its
+    /// SHA-256 hash.
+    Archive { sha256: String },
+}
+
+impl Lockfile {
+    pub const FILE_NAME: &'static str = "Runefile.lock";
+
+    /// Load the `Runefile.lock` from a directory, if there is one.
+    pub fn load(dir: &FsPath) -> Result<Option<Lockfile>, LockfileError> {
+        let path = dir.join(Lockfile::FILE_NAME);
+
+        let src = match std::fs::read_to_string(&path) {
+            Ok(src) => src,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(None)
+            },
+            Err(error) => return Err(LockfileError::Read { path, error }),
+        };
+
+        toml::from_str(&src)
+            .map(Some)
+            .map_err(|error| LockfileError::Parse { path, error })
+    }
+
+    /// Write this lockfile to the `Runefile.lock` in a directory.
+    pub fn save(&self, dir: &FsPath) -> Result<(), LockfileError> {
+        let path = dir.join(Lockfile::FILE_NAME);
+        let mut contents = String::from(
+            "# This file is generated by the Rune compiler. Do not edit it.\n",
+        );
+        contents.push_str(
+            &toml::to_string_pretty(self)
+                .expect("Serializing to a string should never fail"),
+        );
+
+        std::fs::write(&path, contents)
+            .map_err(|error| LockfileError::Write { path, error })
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&LockedProcBlock> {
+        self.proc_blocks.get(&path.to_string())
+    }
+
+    /// Work out what each proc-block was resolved to, using the `Cargo.lock`
+    /// from a successful build of the generated project.
+    ///
+    /// The `proc_blocks` are each proc-block's [`Path`] and crate name.
+    pub fn resolve<'a>(
+        cargo_lock: &str,
+        proc_blocks: impl IntoIterator<Item = (&'a Path, &'a str)>,
+        current_dir: &FsPath,
+    ) -> Result<Lockfile, Box<dyn Error + Send + Sync>> {
+        let cargo_lock: CargoLock = toml::from_str(cargo_lock)?;
+        let mut pins = BTreeMap::new();
+
+        for (path, crate_name) in proc_blocks {
+            let locked = if let Some(archive) = path.remote_archive() {
+                LockedProcBlock::Archive {
+                    sha256: archive.sha256.to_ascii_lowercase(),
+                }
+            } else if is_local(path) {
+                let sha256 = hash_directory(&current_dir.join(&path.base))?;
+                LockedProcBlock::Local { sha256 }
+            } else {
+                let package = cargo_lock
+                    .package
+                    .iter()
+                    .find(|p| p.name == crate_name && p.source.is_some())
+                    .ok_or_else(|| {
+                        format!("\"{}\" isn't in the Cargo.lock", crate_name)
+                    })?;
+                package.locked()?
+            };
+
+            pins.insert(path.to_string(), locked);
+        }
+
+        Ok(Lockfile { proc_blocks: pins })
+    }
+}
+
+impl LockedProcBlock {
+    /// Pin a dependency to this exact proc-block.
+    ///
+    /// Local and downloaded proc-blocks can't be pinned. Use
+    /// [`LockedProcBlock::check()`] to make sure they haven't changed
+    /// instead.
+    pub(crate) fn pin(&self, dep: &mut DependencyDetail) {
+        match self {
+            LockedProcBlock::Git { repository, commit } => {
+                dep.git = Some(repository.clone());
+                dep.rev = Some(commit.clone());
+                dep.tag = None;
+                dep.branch = None;
+            },
+            LockedProcBlock::CratesIo { version } => {
+                dep.version = Some(format!("={}", version));
+            },
+            LockedProcBlock::Local { .. } => {},
+            LockedProcBlock::Archive { .. } => {},
+        }
+    }
+
+    /// Check whether the proc-block at `path` still matches this pin.
+    pub(crate) fn check(
+        &self,
+        path: &Path,
+        current_dir: &FsPath,
+    ) -> Result<(), String> {
+        match self {
+            LockedProcBlock::Local { sha256 } => {
+                let dir = current_dir.join(&path.base);
+                let actual = hash_directory(&dir).map_err(|e| {
+                    format!("Unable to hash \"{}\": {}", dir.display(), e)
+                })?;
+
+                if &actual == sha256 {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "\"{}\" has changed since {} was written",
+                        dir.display(),
+                        Lockfile::FILE_NAME
+                    ))
+                }
+            },
+            // Git commits, exact versions, and hashed downloads can't change
+            LockedProcBlock::Git { .. }
+            | LockedProcBlock::CratesIo { .. }
+            | LockedProcBlock::Archive { .. } => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LockfileError {
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    Write {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+}
+
+impl Display for LockfileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LockfileError::Read { path, .. } => {
+                write!(f, "Unable to read \"{}\"", path.display())
+            },
+            LockfileError::Parse { path, .. } => {
+                write!(f, "Unable to parse \"{}\"", path.display())
+            },
+            LockfileError::Write { path, .. } => {
+                write!(f, "Unable to write to \"{}\"", path.display())
+            },
+        }
+    }
+}
+
+impl Error for LockfileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LockfileError::Read { error, .. }
+            | LockfileError::Write { error, .. } => Some(error),
+            LockfileError::Parse { error, .. } => Some(error),
+        }
+    }
+}
+
+fn is_local(path: &Path) -> bool { path.base.starts_with('.') }
+
+/// The parts of a `Cargo.lock` we care about.
+#[derive(Debug, serde::Deserialize)]
+struct CargoLock {
+    #[serde(default)]
+    package: Vec<CargoLockPackage>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CargoLockPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+}
+
+impl CargoLockPackage {
+    fn locked(&self) -> Result<LockedProcBlock, String> {
+        let source = self.source.as_deref().unwrap_or_default();
+
+        if source.starts_with("registry+") {
+            return Ok(LockedProcBlock::CratesIo {
+                version: self.version.clone(),
+            });
+        }
+
+        // Git sources look like "git+https://github.com/x/y.git?rev=v1#<sha>"
+        if let Some(url) = source.strip_prefix("git+") {
+            if let Some((url, commit)) = url.rsplit_once('#') {
+                let repository = url.split('?').next().unwrap_or(url);
+                return Ok(LockedProcBlock::Git {
+                    repository: repository.to_string(),
+                    commit: commit.to_string(),
+                });
+            }
+        }
+
+        Err(format!(
+            "Unable to determine where \"{}\" came from (source: \"{}\")",
+            self.name, source
+        ))
+    }
+}
+
+/// Hash every file in a directory, ignoring build artifacts and hidden files
+/// (e.g. `.git/`).
+fn hash_directory(dir: &FsPath) -> Result<String, std::io::Error> {
+    let mut files = Vec::new();
+    collect_files(dir, dir, &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+
+    for relative_path in files {
+        hasher.update(relative_path.as_bytes());
+        hasher.update([0]);
+        hasher.update(std::fs::read(dir.join(&relative_path))?);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn collect_files(
+    root: &FsPath,
+    dir: &FsPath,
+    files: &mut Vec<String>,
+) -> Result<(), std::io::Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+
+        let path = entry.path();
+
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            // Always use "/" so the hash is the same on every platform
+            let components: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            files.push(components.join("/"));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARGO_LOCK: &str = r#"
+        [[package]]
+        name = "fft"
+        version = "0.11.3"
+        source = "git+https://github.com/hotg-ai/rune.git?rev=v0.11.3#0123456789abcdef"
+
+        [[package]]
+        name = "modulo"
+        version = "0.1.2"
+        source = "registry+https://github.com/rust-lang/crates.io-index"
+
+        [[package]]
+        name = "rune"
+        version = "0.0.0"
+    "#;
+
+    #[test]
+    fn resolve_proc_blocks_from_the_cargo_lock() {
+        let fft: Path = "hotg-ai/rune@v0.11.3#proc_blocks/fft".parse().unwrap();
+        let modulo: Path = "modulo@0.1".parse().unwrap();
+
+        let got = Lockfile::resolve(
+            CARGO_LOCK,
+            vec![(&fft, "fft"), (&modulo, "modulo")],
+            FsPath::new("."),
+        )
+        .unwrap();
+
+        assert_eq!(
+            got.get(&fft).unwrap(),
+            &LockedProcBlock::Git {
+                repository: "https://github.com/hotg-ai/rune.git".to_string(),
+                commit: "0123456789abcdef".to_string(),
+            }
+        );
+        assert_eq!(
+            got.get(&modulo).unwrap(),
+            &LockedProcBlock::CratesIo {
+                version: "0.1.2".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn pin_dependencies() {
+        let mut dep: DependencyDetail = toml::from_str(
+            r#"
+            git = "https://github.com/hotg-ai/rune.git"
+            tag = "v0.11.3"
+            "#,
+        )
+        .unwrap();
+
+        LockedProcBlock::Git {
+            repository: "https://github.com/hotg-ai/rune.git".to_string(),
+            commit: "0123456789abcdef".to_string(),
+        }
+        .pin(&mut dep);
+
+        assert_eq!(dep.rev.as_deref(), Some("0123456789abcdef"));
+        assert_eq!(dep.tag, None);
+    }
+
+    #[test]
+    fn local_proc_blocks_are_locked_by_their_contents() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("my_proc_block");
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::write(dir.join("src").join("lib.rs"), "// v1").unwrap();
+        std::fs::write(dir.join("target").join("junk"), "...").unwrap();
+        let path: Path = "./my_proc_block".parse().unwrap();
+
+        let lockfile =
+            Lockfile::resolve("", vec![(&path, "my_proc_block")], temp.path())
+                .unwrap();
+        let locked = lockfile.get(&path).unwrap();
+        assert!(locked.check(&path, temp.path()).is_ok());
+
+        // build artifacts are ignored
+        std::fs::write(dir.join("target").join("junk"), "changed").unwrap();
+        assert!(locked.check(&path, temp.path()).is_ok());
+
+        std::fs::write(dir.join("src").join("lib.rs"), "// v2").unwrap();
+        assert!(locked.check(&path, temp.path()).is_err());
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::{
     borrow::{Borrow, Cow},
+    collections::HashMap,
     fmt::{self, Display, Formatter},
     hash::Hash,
     ops::Deref,
@@ -10,6 +11,7 @@ use std::{
 };
 
 use hotg_rune_core::Shape;
+use hotg_rune_proc_blocks::{ProcBlockDescriptor, ShapeTransfer};
 use indexmap::IndexMap;
 use legion::Entity;
 
@@ -57,6 +59,10 @@ impl<'a> From<&'a str> for SinkKind {
 pub struct Model {
     pub model_file: ModelFile,
     pub args: IndexMap<String, ResourceOrString>,
+    /// Alternative versions of the model which the runtime may pick between,
+    /// keyed by name.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub variants: IndexMap<String, PathBuf>,
 }
 
 /// Where to load a model from.
@@ -86,6 +92,7 @@ pub enum SourceKind {
     Image,
     Raw,
     FloatImage,
+    Stream,
     Other(String),
 }
 
@@ -102,6 +109,7 @@ impl SourceKind {
             SourceKind::FloatImage => {
                 Some(hotg_rune_core::capabilities::FLOAT_IMAGE)
             },
+            SourceKind::Stream => Some(hotg_rune_core::capabilities::STREAM),
             _ => None,
         }
     }
@@ -121,6 +129,7 @@ impl<'a> From<&'a str> for SourceKind {
             "image" | "IMAGE" => SourceKind::Image,
             "raw" | "RAW" => SourceKind::Raw,
             "float-image" | "FLOAT_IMAGE" => SourceKind::FloatImage,
+            "stream" | "STREAM" => SourceKind::Stream,
             _ => SourceKind::Other(s.to_string()),
         }
     }
@@ -144,11 +153,45 @@ pub struct ProcBlock {
 
 impl ProcBlock {
     /// The name of the Rust crate that implements this [`ProcBlock`].
-    pub(crate) fn name(&self) -> &str {
-        let full_name = self.path.sub_path.as_ref().unwrap_or(&self.path.base);
-        let start_of_name = full_name.rfind('/').map(|ix| ix + 1).unwrap_or(0);
+    pub(crate) fn name(&self) -> &str { crate_name(&self.path) }
+}
+
+fn crate_name(path: &Path) -> &str {
+    if let Some(archive) = path.remote_archive() {
+        // "https://example.com/my_proc_block.zip" contains "my_proc_block"
+        let file_name = archive.url.rsplit('/').next().unwrap_or(archive.url);
+        return file_name.strip_suffix(".zip").unwrap_or(file_name);
+    }
 
-        &full_name[start_of_name..]
+    let full_name = path.sub_path.as_ref().unwrap_or(&path.base);
+    let start_of_name = full_name.rfind('/').map(|ix| ix + 1).unwrap_or(0);
+
+    &full_name[start_of_name..]
+}
+
+/// The [`ProcBlockDescriptor`]s for any proc-blocks whose metadata is known
+/// ahead of time, indexed by crate name.
+///
+/// Descriptors are read from each proc-block while lowering (see
+/// [`crate::FeatureFlags::set_load_proc_block_descriptors()`]) so the shapes
+/// of a proc-block's outputs can be inferred instead of being written out in
+/// the Runefile. Hooks can also register a descriptor before the lowering
+/// phase, which takes precedence.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProcBlockDescriptors(HashMap<String, ProcBlockDescriptor<'static>>);
+
+impl ProcBlockDescriptors {
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        descriptor: ProcBlockDescriptor<'static>,
+    ) {
+        self.0.insert(name.into(), descriptor);
+    }
+
+    /// Look up the descriptor for the proc-block at a particular [`Path`].
+    pub fn get(&self, path: &Path) -> Option<&ProcBlockDescriptor<'static>> {
+        self.0.get(crate_name(path))
     }
 }
 
@@ -221,6 +264,10 @@ impl NameTable {
     pub(crate) fn insert(&mut self, name: Name, ent: Entity) {
         self.0.insert(name, ent);
     }
+
+    pub(crate) fn remove(&mut self, name: &str) -> Option<Entity> {
+        self.0.shift_remove(name)
+    }
 }
 
 impl Deref for NameTable {
@@ -255,6 +302,11 @@ impl From<Shape<'static>> for Tensor {
     fn from(s: Shape<'static>) -> Self { Tensor(s) }
 }
 
+/// A tensor whose [`Shape`] will be calculated during type checking instead
+/// of being declared in the Runefile.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InferredShape(pub ShapeTransfer<'static>);
+
 /// The list of [`Tensor`]s that may be the output from a [`PipelineNode`].
 #[derive(
     Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
@@ -271,6 +323,13 @@ pub struct Inputs {
     pub tensors: Vec<Entity>,
 }
 
+/// A [`PipelineNode`] which should only run when this [`Tensor`] has a
+/// non-zero element.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Condition {
+    pub tensor: Entity,
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct ResourceData(pub Arc<[u8]>);
 
@@ -301,6 +360,37 @@ impl Deref for ModelData {
     fn deref(&self) -> &Self::Target { &self.0 }
 }
 
+/// The [`ModelData`] for each of a [`Model`]'s variants, in the order they
+/// were declared.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct ModelVariantData(pub IndexMap<String, ModelData>);
+
+impl Deref for ModelVariantData {
+    type Target = IndexMap<String, ModelData>;
+
+    fn deref(&self) -> &Self::Target { &self.0 }
+}
+
+/// The total number of bytes of model and resource data which will be
+/// embedded in the Rune's linear memory.
+pub(crate) fn embedded_data_size<'a>(
+    models: impl Iterator<Item = &'a ModelData>,
+    variants: impl Iterator<Item = &'a ModelVariantData>,
+    resources: impl Iterator<Item = &'a ResourceData>,
+) -> u64 {
+    let models = models.map(|m| m.len());
+    let variants = variants.flat_map(|v| v.values()).map(|m| m.len());
+    let resources = resources.map(|r| r.len());
+
+    models
+        .chain(variants)
+        .chain(resources)
+        .map(|len| len as u64)
+        .sum()
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Mimetype(Cow<'static, str>);
 
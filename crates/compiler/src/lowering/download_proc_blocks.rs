@@ -0,0 +1,43 @@
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use crate::{
+    download::{self, DownloadError},
+    lowering::{Name, ProcBlock},
+    BuildContext, Diagnostics,
+};
+
+/// Download and extract any proc-blocks referenced by a
+/// `https://...#sha256=...` URL so they can be used as local dependencies.
+#[legion::system(for_each)]
+pub(crate) fn run(
+    #[resource] diags: &mut Diagnostics,
+    #[resource] build_ctx: &BuildContext,
+    name: &Name,
+    proc_block: &ProcBlock,
+    &span: &Span,
+) {
+    if let Some(archive) = proc_block.path.remote_archive() {
+        if let Err(e) = archive.fetch_archive(&download::cache_dir(build_ctx)) {
+            diags.push(download_failed_diagnostic(name, &e, span));
+        }
+    }
+}
+
+pub(crate) fn download_failed_diagnostic(
+    name: &Name,
+    e: &DownloadError,
+    span: Span,
+) -> Diagnostic<()> {
+    let mut notes = Vec::new();
+    let mut cause = std::error::Error::source(e);
+    while let Some(c) = cause {
+        notes.push(format!("caused by: {}", c));
+        cause = c.source();
+    }
+
+    Diagnostic::error()
+        .with_message(format!("{} (needed by \"{}\")", e, name))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(notes)
+}
@@ -0,0 +1,404 @@
+//! Evaluating simple arithmetic expressions in a stage's `args`.
+//!
+//! An argument like `frame_size: $audio.hz / 100` refers to the `hz` argument
+//! on the `audio` stage. Expressions may use numbers, `+`, `-`, `*`, `/`, and
+//! parentheses, and are only recognised if they contain at least one
+//! `$stage.arg` reference so normal strings are left alone.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::parse::{DocumentV1, ResourceOrString};
+
+/// Does this argument contain a `$stage.arg` reference which needs to be
+/// evaluated?
+pub(crate) fn is_expression(value: &str) -> bool {
+    value.match_indices('$').any(|(i, _)| {
+        let mut parser = Parser::new(&value[i + 1..]);
+        parser.identifier().is_some()
+            && parser.bump() == Some('.')
+            && parser.identifier().is_some()
+    })
+}
+
+/// Evaluate an expression, looking up any references in the `doc`.
+pub(crate) fn evaluate(
+    expression: &str,
+    doc: &DocumentV1,
+) -> Result<String, EvaluationError> {
+    let value = evaluate_recursive(expression, doc, &mut Vec::new())?;
+    Ok(format_number(value))
+}
+
+fn evaluate_recursive(
+    expression: &str,
+    doc: &DocumentV1,
+    stack: &mut Vec<(String, String)>,
+) -> Result<f64, EvaluationError> {
+    let mut parser = Parser::new(expression);
+    let expr = parser.expression()?;
+    parser.skip_whitespace();
+
+    if let Some(c) = parser.peek() {
+        return Err(EvaluationError::Unexpected(c));
+    }
+
+    expr.evaluate(&mut |stage, arg| lookup(stage, arg, doc, stack))
+}
+
+fn lookup(
+    stage: &str,
+    arg: &str,
+    doc: &DocumentV1,
+    stack: &mut Vec<(String, String)>,
+) -> Result<f64, EvaluationError> {
+    let key = (stage.to_string(), arg.to_string());
+    if stack.contains(&key) {
+        return Err(EvaluationError::Cycle {
+            stage: key.0,
+            arg: key.1,
+        });
+    }
+
+    let value = doc
+        .pipeline
+        .get(stage)
+        .ok_or_else(|| EvaluationError::UnknownStage(stage.to_string()))?
+        .args()
+        .get(arg)
+        .ok_or_else(|| EvaluationError::UnknownArgument {
+            stage: stage.to_string(),
+            arg: arg.to_string(),
+        })?;
+
+    let value = match &value.0 {
+        ResourceOrString::String(s) => s,
+        ResourceOrString::Resource(_) => {
+            return Err(EvaluationError::NotANumber {
+                stage: key.0,
+                arg: key.1,
+            })
+        },
+    };
+
+    if let Ok(number) = value.parse() {
+        return Ok(number);
+    }
+
+    if !is_expression(value) {
+        return Err(EvaluationError::NotANumber {
+            stage: key.0,
+            arg: key.1,
+        });
+    }
+
+    stack.push(key);
+    let result = evaluate_recursive(value, doc, stack);
+    stack.pop();
+
+    result
+}
+
+/// Print a number the way somebody would have written it in a Runefile.
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum EvaluationError {
+    Unexpected(char),
+    UnexpectedEnd,
+    UnknownStage(String),
+    UnknownArgument { stage: String, arg: String },
+    NotANumber { stage: String, arg: String },
+    Cycle { stage: String, arg: String },
+    DivideByZero,
+}
+
+impl Display for EvaluationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EvaluationError::Unexpected(c) => {
+                write!(f, "Unexpected \"{}\"", c)
+            },
+            EvaluationError::UnexpectedEnd => {
+                write!(f, "The expression ended unexpectedly")
+            },
+            EvaluationError::UnknownStage(stage) => {
+                write!(f, "There is no \"{}\" stage", stage)
+            },
+            EvaluationError::UnknownArgument { stage, arg } => {
+                write!(f, "The \"{}\" stage has no \"{}\" argument", stage, arg)
+            },
+            EvaluationError::NotANumber { stage, arg } => {
+                write!(f, "\"${}.{}\" isn't a number", stage, arg)
+            },
+            EvaluationError::Cycle { stage, arg } => {
+                write!(f, "\"${}.{}\" refers to itself", stage, arg)
+            },
+            EvaluationError::DivideByZero => write!(f, "Division by zero"),
+        }
+    }
+}
+
+impl std::error::Error for EvaluationError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Reference { stage: String, arg: String },
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+impl Expr {
+    fn evaluate(
+        &self,
+        lookup: &mut dyn FnMut(&str, &str) -> Result<f64, EvaluationError>,
+    ) -> Result<f64, EvaluationError> {
+        match self {
+            Expr::Number(n) => Ok(*n),
+            Expr::Reference { stage, arg } => lookup(stage, arg),
+            Expr::Negate(inner) => Ok(-inner.evaluate(lookup)?),
+            Expr::Binary(left, op, right) => {
+                let left = left.evaluate(lookup)?;
+                let right = right.evaluate(lookup)?;
+
+                match op {
+                    '+' => Ok(left + right),
+                    '-' => Ok(left - right),
+                    '*' => Ok(left * right),
+                    '/' if right == 0.0 => Err(EvaluationError::DivideByZero),
+                    '/' => Ok(left / right),
+                    _ => unreachable!("Unknown operator, {}", op),
+                }
+            },
+        }
+    }
+}
+
+/// A recursive descent parser for
+///
+/// ```text
+/// expression := term (("+" | "-") term)*
+/// term       := factor (("*" | "/") factor)*
+/// factor     := "-" factor | "(" expression ")" | number | reference
+/// reference  := "$" identifier "." identifier
+/// ```
+struct Parser<'a> {
+    src: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Self { Parser { src } }
+
+    fn peek(&self) -> Option<char> { self.src.chars().next() }
+
+    fn skip_whitespace(&mut self) { self.src = self.src.trim_start(); }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.src = &self.src[c.len_utf8()..];
+        Some(c)
+    }
+
+    fn expression(&mut self) -> Result<Expr, EvaluationError> {
+        let mut expr = self.term()?;
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(op @ ('+' | '-')) => {
+                    self.bump();
+                    let right = self.term()?;
+                    expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+                },
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, EvaluationError> {
+        let mut expr = self.factor()?;
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(op @ ('*' | '/')) => {
+                    self.bump();
+                    let right = self.factor()?;
+                    expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+                },
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<Expr, EvaluationError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('-') => {
+                self.bump();
+                Ok(Expr::Negate(Box::new(self.factor()?)))
+            },
+            Some('(') => {
+                self.bump();
+                let expr = self.expression()?;
+                self.skip_whitespace();
+                match self.bump() {
+                    Some(')') => Ok(expr),
+                    Some(c) => Err(EvaluationError::Unexpected(c)),
+                    None => Err(EvaluationError::UnexpectedEnd),
+                }
+            },
+            Some('$') => {
+                self.bump();
+                let stage = self.expect_identifier()?;
+                match self.bump() {
+                    Some('.') => {},
+                    Some(c) => return Err(EvaluationError::Unexpected(c)),
+                    None => return Err(EvaluationError::UnexpectedEnd),
+                }
+                let arg = self.expect_identifier()?;
+                Ok(Expr::Reference {
+                    stage: stage.to_string(),
+                    arg: arg.to_string(),
+                })
+            },
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) => Err(EvaluationError::Unexpected(c)),
+            None => Err(EvaluationError::UnexpectedEnd),
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr, EvaluationError> {
+        let end = self
+            .src
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(self.src.len());
+        let (digits, rest) = self.src.split_at(end);
+
+        match digits.parse() {
+            Ok(n) => {
+                self.src = rest;
+                Ok(Expr::Number(n))
+            },
+            Err(_) => Err(EvaluationError::Unexpected('.')),
+        }
+    }
+
+    /// Identifiers may contain a `-` (e.g. `some-stage`), but only when it
+    /// is followed by a letter so `$a.b-1` is still a subtraction.
+    fn identifier(&mut self) -> Option<&'a str> {
+        let mut chars = self.src.char_indices().peekable();
+        match chars.next() {
+            Some((_, c)) if is_ident_start(c) => {},
+            _ => return None,
+        }
+
+        let mut end = self.src.len();
+        while let Some((i, c)) = chars.next() {
+            let continues = match c {
+                '-' => chars.peek().map_or(false, |&(_, c)| is_ident_start(c)),
+                c => is_ident_char(c),
+            };
+            if !continues {
+                end = i;
+                break;
+            }
+        }
+
+        let (ident, rest) = self.src.split_at(end);
+        self.src = rest;
+        Some(ident)
+    }
+
+    fn expect_identifier(&mut self) -> Result<&'a str, EvaluationError> {
+        match self.peek() {
+            Some(c) if is_ident_start(c) => {
+                Ok(self.identifier().expect("Already checked"))
+            },
+            Some(c) => Err(EvaluationError::Unexpected(c)),
+            None => Err(EvaluationError::UnexpectedEnd),
+        }
+    }
+}
+
+fn is_ident_start(c: char) -> bool { c.is_ascii_alphabetic() || c == '_' }
+
+fn is_ident_char(c: char) -> bool { c.is_ascii_alphanumeric() || c == '_' }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{CapabilityStage, Stage};
+
+    fn doc() -> DocumentV1 {
+        DocumentV1 {
+            version: 1,
+            image: "image".parse().unwrap(),
+            pipeline: map! {
+                audio: Stage::Capability(CapabilityStage {
+                    capability: "SOUND".to_string(),
+                    outputs: vec![],
+                    args: map! {
+                        hz: "16000".into(),
+                        frame: "$audio.hz / 100".into(),
+                        again: "$audio.again + 1".into(),
+                    },
+                }),
+            },
+            resources: map! {},
+            tests: map! {},
+            memory: None,
+        }
+    }
+
+    #[test]
+    fn evaluate_arithmetic_with_references() {
+        let doc = doc();
+        let inputs = vec![
+            ("$audio.hz / 100", "160"),
+            ("($audio.hz - 4000) * -2", "-24000"),
+            ("$audio.frame / 3", "53.333333333333336"),
+            ("2 * $audio.frame", "320"),
+        ];
+
+        for (src, should_be) in inputs {
+            assert!(is_expression(src), "{}", src);
+            assert_eq!(evaluate(src, &doc).unwrap(), should_be, "{}", src);
+        }
+    }
+
+    #[test]
+    fn normal_strings_are_not_expressions() {
+        for src in ["./model.tflite", "$WORD_LIST", "silence\nup-down", "1/2"] {
+            assert!(!is_expression(src), "{}", src);
+        }
+    }
+
+    #[test]
+    fn detect_invalid_references() {
+        let doc = doc();
+
+        assert_eq!(
+            evaluate("$audio.again", &doc),
+            Err(EvaluationError::Cycle {
+                stage: "audio".to_string(),
+                arg: "again".to_string()
+            })
+        );
+        assert_eq!(
+            evaluate("$mic.hz", &doc),
+            Err(EvaluationError::UnknownStage("mic".to_string()))
+        );
+        assert_eq!(
+            evaluate("$audio.hz / 0", &doc),
+            Err(EvaluationError::DivideByZero)
+        );
+    }
+}
@@ -2,7 +2,7 @@ use codespan::Span;
 use legion::{systems::CommandBuffer, Entity};
 
 use crate::{
-    lowering::{Model, ModelData, ModelFile, Name},
+    lowering::{Model, ModelData, ModelFile, ModelVariantData, Name},
     BuildContext, Diagnostics,
 };
 
@@ -18,16 +18,28 @@ pub(crate) fn run(
 ) {
     match &model.model_file {
         ModelFile::FromDisk(path) => {
-            match super::load_resource_data::load(
-                &build_ctx.current_directory,
-                path,
-                name,
-                span,
-            ) {
+            match super::load_resource_data::load(build_ctx, path, name, span) {
                 Ok(data) => cmd.add_component(entity, ModelData::from(data)),
                 Err(diag) => diags.push(diag),
             }
         },
         ModelFile::Resource(_) => {},
     }
+
+    if model.variants.is_empty() {
+        return;
+    }
+
+    let mut variants = ModelVariantData::default();
+
+    for (variant, path) in &model.variants {
+        match super::load_resource_data::load(build_ctx, path, name, span) {
+            Ok(data) => {
+                variants.0.insert(variant.clone(), ModelData::from(data));
+            },
+            Err(diag) => diags.push(diag),
+        }
+    }
+
+    cmd.add_component(entity, variants);
 }
@@ -0,0 +1,331 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use hotg_rune_proc_blocks::ProcBlockDescriptor;
+use wasmparser::{BinaryReaderError, Parser, Payload};
+
+use crate::{
+    codegen, download,
+    lowering::{Name, ProcBlock, ProcBlockDescriptors},
+    BuildContext, Diagnostics, FeatureFlags, Verbosity,
+};
+
+/// The descriptors are the same for every target, so we always probe using
+/// `wasm32` to avoid needing to build the standard library from source.
+const PROBE_TARGET: &str = "wasm32-unknown-unknown";
+
+/// Compile each proc-block on its own and read the [`ProcBlockDescriptor`]
+/// embedded in its `.rune_proc_block` custom section.
+///
+/// Descriptors registered by [`crate::hooks::Hooks`] take precedence. A
+/// proc-block that can't be probed is skipped, because we'll get a proper
+/// compile error when the Rune itself is built.
+#[legion::system(for_each)]
+pub(crate) fn run(
+    #[resource] ctx: &BuildContext,
+    #[resource] features: &FeatureFlags,
+    #[resource] descriptors: &mut ProcBlockDescriptors,
+    name: &Name,
+    proc_block: &ProcBlock,
+) {
+    if !features.load_proc_block_descriptors
+        || descriptors.get(&proc_block.path).is_some()
+    {
+        return;
+    }
+
+    match load_descriptor(ctx, features, proc_block) {
+        Ok(descriptor) => {
+            log::debug!(
+                "Loaded the \"{}\" descriptor for \"{}\"",
+                descriptor.type_name,
+                name
+            );
+            descriptors.insert(proc_block.name(), descriptor);
+        },
+        Err(e) => log::warn!(
+            "Unable to load the descriptor for \"{}\" ({}): {}",
+            name,
+            proc_block.path,
+            e
+        ),
+    }
+}
+
+fn load_descriptor(
+    ctx: &BuildContext,
+    features: &FeatureFlags,
+    proc_block: &ProcBlock,
+) -> Result<ProcBlockDescriptor<'static>, ProbeError> {
+    if let Some(descriptor) = prebuilt_descriptor(ctx, features, proc_block) {
+        return Ok(descriptor);
+    }
+
+    let target_dir = features
+        .target_dir(PROBE_TARGET)
+        .unwrap_or_else(|| ctx.working_directory.join("target"));
+    let probe_name = format!("{}-probe", proc_block.name());
+    let probe_dir = target_dir.join("probes").join(&probe_name);
+
+    generate_probe(&probe_dir, &probe_name, proc_block, ctx, features)?;
+
+    let mut cmd = Command::new("cargo");
+    cmd.arg("build")
+        .arg("--manifest-path")
+        .arg(probe_dir.join("Cargo.toml"))
+        .arg(format!("--target={}", PROBE_TARGET))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .current_dir(&probe_dir);
+
+    match ctx.verbosity {
+        Verbosity::Verbose => Verbosity::Verbose.add_flags(&mut cmd),
+        _ => Verbosity::Quiet.add_flags(&mut cmd),
+    }
+
+    log::debug!("Executing {:?}", cmd);
+
+    let status = cmd.status().map_err(ProbeError::DidntStart)?;
+    if !status.success() {
+        return Err(ProbeError::BuildFailed(status));
+    }
+
+    let wasm = target_dir
+        .join(PROBE_TARGET)
+        .join("debug")
+        .join(probe_name.replace("-", "_"))
+        .with_extension("wasm");
+    let wasm = std::fs::read(&wasm).map_err(|error| ProbeError::Io {
+        path: wasm.clone(),
+        error,
+    })?;
+
+    descriptor_from_wasm(&wasm)
+}
+
+/// Try to read the descriptor from a prebuilt copy of the proc-block, falling
+/// back to compiling it from source if that doesn't work.
+fn prebuilt_descriptor(
+    ctx: &BuildContext,
+    features: &FeatureFlags,
+    proc_block: &ProcBlock,
+) -> Option<ProcBlockDescriptor<'static>> {
+    let file = features
+        .prebuilt_proc_blocks
+        .as_ref()?
+        .get(&proc_block.path)?;
+
+    let result = file
+        .fetch(&download::cache_dir(ctx))
+        .map_err(|e| e.to_string())
+        .and_then(|wasm| {
+            descriptor_from_wasm(&wasm).map_err(|e| e.to_string())
+        });
+
+    match result {
+        Ok(descriptor) => Some(descriptor),
+        Err(e) => {
+            log::warn!(
+                "Unable to use the prebuilt \"{}\", compiling it from source \
+                 instead: {}",
+                proc_block.path,
+                e
+            );
+            None
+        },
+    }
+}
+
+/// Write a `cdylib` crate which just re-exports the proc-block, using the same
+/// dependency (and `Runefile.lock` pin) the Rune will be compiled with.
+fn generate_probe(
+    probe_dir: &Path,
+    probe_name: &str,
+    proc_block: &ProcBlock,
+    ctx: &BuildContext,
+    features: &FeatureFlags,
+) -> Result<(), ProbeError> {
+    // Problems with the lockfile are reported when generating the Rune's
+    // Cargo.toml
+    let mut diags = Diagnostics::new();
+    let mut manifest = codegen::cargo_manifest(
+        probe_name,
+        &[proc_block],
+        ctx,
+        features,
+        &mut diags,
+    );
+    // The probe doesn't need anything the Rune's lib.rs would use
+    for name in ["log", "lazy_static", "hotg-runicos-base-wasm"] {
+        manifest.dependencies.remove(name);
+    }
+    let manifest = toml::to_string_pretty(&manifest)
+        .expect("Serializing to a string should never fail");
+
+    let lib_rs =
+        format!("pub use {}::*;\n", proc_block.name().replace("-", "_"));
+    let rust_toolchain = toml::to_string(&crate::rust_toolchain())
+        .expect("We can always serialize a hard-coded TOML object");
+
+    write(probe_dir.join("Cargo.toml"), manifest)?;
+    write(probe_dir.join("lib.rs"), lib_rs)?;
+    write(probe_dir.join("rust-toolchain.toml"), rust_toolchain)?;
+
+    Ok(())
+}
+
+fn write(path: PathBuf, contents: String) -> Result<(), ProbeError> {
+    let dir = path.parent().expect("Always inside the probe directory");
+
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&path, contents))
+        .map_err(|error| ProbeError::Io { path, error })
+}
+
+fn descriptor_from_wasm(
+    wasm: &[u8],
+) -> Result<ProcBlockDescriptor<'static>, ProbeError> {
+    for payload in Parser::default().parse_all(wasm) {
+        if let Payload::CustomSection { name, data, .. } =
+            payload.map_err(ProbeError::InvalidWasm)?
+        {
+            if name == ProcBlockDescriptor::CUSTOM_SECTION_NAME {
+                // The linker concatenates sections with the same name, so
+                // only read the first descriptor
+                let mut descriptors =
+                    serde_json::Deserializer::from_slice(data).into_iter();
+
+                return match descriptors.next() {
+                    Some(result) => {
+                        result.map_err(ProbeError::InvalidDescriptor)
+                    },
+                    None => Err(ProbeError::MissingDescriptor),
+                };
+            }
+        }
+    }
+
+    Err(ProbeError::MissingDescriptor)
+}
+
+#[derive(Debug)]
+enum ProbeError {
+    DidntStart(std::io::Error),
+    BuildFailed(ExitStatus),
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    InvalidWasm(BinaryReaderError),
+    MissingDescriptor,
+    InvalidDescriptor(serde_json::Error),
+}
+
+impl Display for ProbeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::DidntStart(e) => {
+                write!(f, "Unable to start cargo: {}", e)
+            },
+            ProbeError::BuildFailed(status) => {
+                write!(f, "Compilation failed ({})", status)
+            },
+            ProbeError::Io { path, error } => {
+                write!(f, "Unable to access \"{}\": {}", path.display(), error)
+            },
+            ProbeError::InvalidWasm(e) => {
+                write!(f, "Unable to parse the WebAssembly module: {}", e)
+            },
+            ProbeError::MissingDescriptor => {
+                write!(f, "The proc-block doesn't have a descriptor")
+            },
+            ProbeError::InvalidDescriptor(e) => {
+                write!(f, "Unable to parse the descriptor: {}", e)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Digest;
+
+    use super::*;
+    use crate::prebuilt::PrebuiltProcBlocks;
+
+    /// A WebAssembly module containing nothing but one custom section.
+    fn module_with_custom_section(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut payload = vec![name.len() as u8];
+        payload.extend(name.as_bytes());
+        payload.extend(data);
+
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.push(0);
+        // The section length is a LEB128-encoded integer
+        let mut len = payload.len();
+        while len >= 0x80 {
+            wasm.push((len & 0x7f) as u8 | 0x80);
+            len >>= 7;
+        }
+        wasm.push(len as u8);
+        wasm.extend(payload);
+        wasm
+    }
+
+    #[test]
+    fn read_the_first_descriptor_in_a_concatenated_section() {
+        let wasm = module_with_custom_section(
+            ProcBlockDescriptor::CUSTOM_SECTION_NAME,
+            br#"{"type_name":"First","description":"","available_transforms":[]}{"type_name":"Second","description":"","available_transforms":[]}"#,
+        );
+
+        let descriptor = descriptor_from_wasm(&wasm).unwrap();
+
+        assert_eq!(descriptor.type_name, "First");
+        assert!(matches!(
+            descriptor_from_wasm(&module_with_custom_section(".other", b"")),
+            Err(ProbeError::MissingDescriptor)
+        ));
+    }
+
+    #[test]
+    fn read_the_descriptor_from_a_prebuilt_proc_block() {
+        let wasm = module_with_custom_section(
+            ProcBlockDescriptor::CUSTOM_SECTION_NAME,
+            br#"{"type_name":"Fft","description":"","available_transforms":[]}"#,
+        );
+        let sha256 = format!("{:x}", sha2::Sha256::digest(&wasm));
+        let working_directory = tempfile::tempdir().unwrap();
+        let doc = "version: 1\nimage: runicos/base\npipeline: {}";
+        let ctx = BuildContext::from_doc(serde_yaml::from_str(doc).unwrap());
+        let ctx = BuildContext {
+            working_directory: working_directory.path().to_path_buf(),
+            ..ctx
+        };
+        // Pretend it was downloaded earlier
+        let cache_dir = download::cache_dir(&ctx);
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        std::fs::write(cache_dir.join(&sha256), &wasm).unwrap();
+        let proc_block = ProcBlock {
+            path: "hotg-ai/proc-blocks@v0.11.3#fft".parse().unwrap(),
+            parameters: Default::default(),
+        };
+        let mut prebuilt = PrebuiltProcBlocks::new();
+        prebuilt
+            .insert(
+                &proc_block.path,
+                format!("https://example.invalid/fft.wasm#sha256={}", sha256),
+            )
+            .unwrap();
+        let mut features = FeatureFlags::production();
+        features.set_prebuilt_proc_blocks(prebuilt);
+
+        let descriptor =
+            prebuilt_descriptor(&ctx, &features, &proc_block).unwrap();
+
+        assert_eq!(descriptor.type_name, "Fft");
+    }
+}
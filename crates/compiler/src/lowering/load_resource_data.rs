@@ -10,7 +10,11 @@ use legion::{systems::CommandBuffer, Entity};
 use zip::{write::FileOptions, ZipWriter};
 
 use crate::{
-    lowering::{Name, Resource, ResourceData, ResourceSource},
+    download::{self, RemoteFile},
+    lowering::{
+        download_proc_blocks::download_failed_diagnostic, Name, Resource,
+        ResourceData, ResourceSource,
+    },
     BuildContext, Diagnostics,
 };
 
@@ -24,11 +28,9 @@ pub(crate) fn run(
     resource: &Resource,
     &span: &Span,
 ) {
-    let current_dir = &build_ctx.current_directory;
-
     match &resource.default_value {
         Some(ResourceSource::FromDisk(path)) => {
-            match load(current_dir, path, name, span) {
+            match load(build_ctx, path, name, span) {
                 Ok(data) => cmd.add_component(entity, ResourceData::from(data)),
                 Err(diag) => diags.push(diag),
             }
@@ -41,12 +43,18 @@ pub(crate) fn run(
 }
 
 pub(crate) fn load(
-    current_dir: &Path,
+    build_ctx: &BuildContext,
     filename: &Path,
     name: &Name,
     span: Span,
 ) -> Result<Vec<u8>, Diagnostic<()>> {
-    let full_path = current_dir.join(filename);
+    if let Some(remote) = filename.to_str().and_then(RemoteFile::parse) {
+        return remote
+            .and_then(|remote| remote.fetch(&download::cache_dir(build_ctx)))
+            .map_err(|e| download_failed_diagnostic(name, &e, span));
+    }
+
+    let full_path = build_ctx.current_directory.join(filename);
 
     let loaded = if full_path.is_dir() {
         load_directory(&full_path)
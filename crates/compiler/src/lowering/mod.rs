@@ -1,12 +1,16 @@
 //! The lowering phase.
 
 mod components;
+mod download_proc_blocks;
+mod expressions;
 mod load_model_data;
+mod load_proc_block_descriptors;
 mod load_resource_data;
 mod register_names;
 mod register_resources;
 mod register_stages;
 mod register_tensors;
+pub mod rewrite;
 mod update_nametable;
 
 pub use components::*;
@@ -17,11 +21,17 @@ use crate::{phases::Phase, serialize::RegistryExt};
 pub fn phase() -> Phase {
     Phase::with_setup(|res| {
         res.insert(NameTable::default());
+
+        if !res.contains::<ProcBlockDescriptors>() {
+            res.insert(ProcBlockDescriptors::default());
+        }
     })
     .and_then(register_names::run_system)
     .and_then(update_nametable::run_system)
     .and_then(register_resources::run_system)
     .and_then(register_stages::run_system)
+    .and_then(download_proc_blocks::run_system)
+    .and_then(load_proc_block_descriptors::run_system)
     .and_then(register_tensors::run_system)
     .and_then(load_resource_data::run_system)
     .and_then(load_model_data::run_system)
@@ -29,6 +39,8 @@ pub fn phase() -> Phase {
 
 pub(crate) fn register_components(registry: &mut Registry<String>) {
     registry
+        .register_with_type_name::<Condition>()
+        .register_with_type_name::<InferredShape>()
         .register_with_type_name::<Inputs>()
         .register_with_type_name::<Model>()
         .register_with_type_name::<ModelFile>()
@@ -46,5 +58,6 @@ pub(crate) fn register_components(registry: &mut Registry<String>) {
         .register_with_type_name::<Tensor>()
         .register_with_type_name::<ResourceData>()
         .register_with_type_name::<Mimetype>()
-        .register_with_type_name::<ModelData>();
+        .register_with_type_name::<ModelData>()
+        .register_with_type_name::<ModelVariantData>();
 }
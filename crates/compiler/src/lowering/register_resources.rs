@@ -105,6 +105,8 @@ mod tests {
                     ..Default::default()
                 }
             },
+            tests: map! {},
+            memory: None,
         }
     }
 
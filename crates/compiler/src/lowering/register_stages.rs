@@ -1,11 +1,16 @@
+use std::path::{Path, PathBuf};
+
 use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::{framing::Framing, serial_format::SerialFormat};
 use indexmap::IndexMap;
 use legion::{systems::CommandBuffer, world::SubWorld, Entity, Query};
 
 use crate::{
     lowering::{
-        self, Mimetype, Model, ModelFile, NameTable, ProcBlock, Resource,
-        ResourceData, Sink, Source,
+        self,
+        expressions::{self, is_expression, EvaluationError},
+        Mimetype, Model, ModelFile, NameTable, ProcBlock, Resource,
+        ResourceData, Sink, SinkKind, Source,
     },
     parse::{
         self, CapabilityStage, DocumentV1, ModelStage, OutStage,
@@ -32,7 +37,7 @@ pub(crate) fn run(
             None => continue,
         };
 
-        let args = match translate_args(stage.args(), names) {
+        let args = match translate_args(name, stage.args(), doc, names) {
             Ok(a) => a,
             Err(diag) => {
                 diags.push(diag);
@@ -41,10 +46,17 @@ pub(crate) fn run(
         };
 
         match stage {
-            parse::Stage::Model(ModelStage { model, .. }) => {
-                match register_model(names, name, model, &args, |e: Entity| {
-                    resources.get(world, e).ok()
-                }) {
+            parse::Stage::Model(ModelStage {
+                model, variants, ..
+            }) => {
+                match register_model(
+                    names,
+                    name,
+                    model,
+                    variants,
+                    &args,
+                    |e: Entity| resources.get(world, e).ok(),
+                ) {
                     Ok((model, mimetype)) => {
                         cmd.add_component(ent, model);
                         cmd.add_component(ent, mimetype);
@@ -53,7 +65,10 @@ pub(crate) fn run(
                 }
             },
             parse::Stage::ProcBlock(ProcBlockStage { proc_block, .. }) => {
-                if proc_block.version.is_none() {
+                // Downloaded proc-blocks are already pinned by their hash
+                if proc_block.version.is_none()
+                    && proc_block.remote_archive().is_none()
+                {
                     let diag = warn_on_unversioned_proc_block_diagnostic(
                         name, proc_block,
                     );
@@ -77,13 +92,17 @@ pub(crate) fn run(
                     parameters: args,
                 },
             ),
-            parse::Stage::Out(OutStage { out, .. }) => cmd.add_component(
-                ent,
-                Sink {
+            parse::Stage::Out(OutStage { out, .. }) => {
+                let sink = Sink {
                     kind: out.as_str().into(),
                     args,
-                },
-            ),
+                };
+
+                match check_sink_args(name, &sink) {
+                    Ok(_) => cmd.add_component(ent, sink),
+                    Err(diag) => diags.push(diag),
+                }
+            },
         }
     }
 }
@@ -109,8 +128,76 @@ fn warn_on_unversioned_proc_block_diagnostic(
         )])
 }
 
+fn check_sink_args(node_name: &str, sink: &Sink) -> Result<(), Diagnostic<()>> {
+    if sink.kind != SinkKind::Serial {
+        return Ok(());
+    }
+
+    match sink.args.get("framing") {
+        Some(lowering::ResourceOrString::String(framing))
+            if Framing::from_name(framing).is_none() =>
+        {
+            return Err(unknown_framing_diagnostic(node_name, framing));
+        },
+        Some(lowering::ResourceOrString::Resource(_)) => {
+            return Err(Diagnostic::error().with_message(format!(
+                "The framing for \"{}\" can't be read from a resource",
+                node_name
+            )));
+        },
+        _ => {},
+    }
+
+    match sink.args.get("format") {
+        Some(lowering::ResourceOrString::String(format))
+            if SerialFormat::from_name(format).is_none() =>
+        {
+            Err(unknown_serial_format_diagnostic(node_name, format))
+        },
+        Some(lowering::ResourceOrString::Resource(_)) => {
+            Err(Diagnostic::error().with_message(format!(
+                "The format for \"{}\" can't be read from a resource",
+                node_name
+            )))
+        },
+        _ => Ok(()),
+    }
+}
+
+fn unknown_serial_format_diagnostic(
+    node_name: &str,
+    format: &str,
+) -> Diagnostic<()> {
+    let expected = SerialFormat::ALL.map(SerialFormat::name);
+    let msg = format!(
+        "Expected the format for \"{}\" to be one of {}, but found {:?}",
+        node_name,
+        join(expected.iter().copied(), ", "),
+        format
+    );
+
+    Diagnostic::error().with_message(msg)
+}
+
+fn unknown_framing_diagnostic(
+    node_name: &str,
+    framing: &str,
+) -> Diagnostic<()> {
+    let expected = [Framing::None, Framing::Cobs].map(Framing::name);
+    let msg = format!(
+        "Expected the framing for \"{}\" to be one of {}, but found {:?}",
+        node_name,
+        join(expected.iter().copied(), ", "),
+        framing
+    );
+
+    Diagnostic::error().with_message(msg)
+}
+
 fn translate_args(
+    node_name: &str,
     args: &IndexMap<String, parse::Argument>,
+    doc: &DocumentV1,
     names: &NameTable,
 ) -> Result<IndexMap<String, lowering::ResourceOrString>, Diagnostic<()>> {
     let mut translated = IndexMap::new();
@@ -124,6 +211,16 @@ fn translate_args(
                 Some(entity) => lowering::ResourceOrString::Resource(entity),
                 None => return Err(not_a_resource_diagnostic(r)),
             },
+            parse::ResourceOrString::String(s) if is_expression(s) => {
+                match expressions::evaluate(s, doc) {
+                    Ok(value) => lowering::ResourceOrString::String(value),
+                    Err(e) => {
+                        return Err(invalid_expression_diagnostic(
+                            node_name, name, s, &e,
+                        ))
+                    },
+                }
+            },
             parse::ResourceOrString::String(s) => {
                 lowering::ResourceOrString::String(s.clone())
             },
@@ -135,17 +232,37 @@ fn translate_args(
     Ok(translated)
 }
 
+fn invalid_expression_diagnostic(
+    node_name: &str,
+    arg: &str,
+    expression: &str,
+    error: &EvaluationError,
+) -> Diagnostic<()> {
+    Diagnostic::error().with_message(format!(
+        "Unable to evaluate \"{}\" for the \"{}\" argument on \"{}\": {}",
+        expression, arg, node_name, error
+    ))
+}
+
 fn register_model<'a>(
     names: &NameTable,
     node_name: &str,
     model: &parse::ResourceOrString,
+    variants: &IndexMap<String, String>,
     args: &IndexMap<String, lowering::ResourceOrString>,
     mut get_resource: impl FnMut(Entity) -> Option<(&'a Resource, Option<&'a ResourceData>)>
         + 'a,
 ) -> Result<(Model, Mimetype), Diagnostic<()>> {
-    let (mimetype, args) = model_format_and_args(node_name, args, |e| {
-        get_resource(e).and_then(|r| r.1).cloned()
-    })?;
+    let model_path = match model {
+        parse::ResourceOrString::String(s) => {
+            Some(Path::new(crate::download::without_hash(s)))
+        },
+        parse::ResourceOrString::Resource(_) => None,
+    };
+    let (mimetype, args) =
+        model_format_and_args(node_name, args, model_path, |e| {
+            get_resource(e).and_then(|r| r.1).cloned()
+        })?;
 
     let model_file = match model {
         parse::ResourceOrString::Resource(resource_name) => {
@@ -156,12 +273,25 @@ fn register_model<'a>(
         parse::ResourceOrString::String(s) => ModelFile::FromDisk(s.into()),
     };
 
-    Ok((Model { model_file, args }, mimetype))
+    let variants = variants
+        .iter()
+        .map(|(name, path)| (name.clone(), PathBuf::from(path)))
+        .collect();
+
+    Ok((
+        Model {
+            model_file,
+            args,
+            variants,
+        },
+        mimetype,
+    ))
 }
 
 fn model_format_and_args(
     node_name: &str,
     args: &IndexMap<String, lowering::ResourceOrString>,
+    model_path: Option<&Path>,
     get_resource_data: impl FnOnce(Entity) -> Option<ResourceData>,
 ) -> Result<
     (Mimetype, IndexMap<String, lowering::ResourceOrString>),
@@ -186,12 +316,23 @@ fn model_format_and_args(
                 },
             }
         },
-        None => Mimetype::default(),
+        None => model_path
+            .and_then(mimetype_for_extension)
+            .unwrap_or_default(),
     };
 
     Ok((mimetype, args))
 }
 
+/// Guess the model's format from its file extension when the Runefile doesn't
+/// specify a `format` argument.
+fn mimetype_for_extension(path: &Path) -> Option<Mimetype> {
+    match path.extension()?.to_str()? {
+        "onnx" => Some(Mimetype::ONNX),
+        _ => None,
+    }
+}
+
 fn invalid_mimetype_diagnostic(
     node_name: &str,
     e: std::str::Utf8Error,
@@ -326,41 +467,53 @@ mod tests {
                     },
                     inputs: Vec::new(),
                     outputs: Vec::new(),
+                    enabled_when: None,
                 }),
                 model_from_disk: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::String("model.tflite".into()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    variants: IndexMap::new(),
+                    enabled_when: None,
                 }),
                 model_from_resource: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::Resource("$MODEL_FILE".parse().unwrap()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    variants: IndexMap::new(),
+                    enabled_when: None,
                 }),
                 model_with_not_a_resource: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::Resource("$cap".parse().unwrap()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    variants: IndexMap::new(),
+                    enabled_when: None,
                 }),
                 model_with_missing_resource: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::Resource("$NON_EXISTENT".parse().unwrap()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    variants: IndexMap::new(),
+                    enabled_when: None,
                 }),
                 model_with_string_resource: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::Resource("$STRING_RESOURCE".parse().unwrap()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    variants: IndexMap::new(),
+                    enabled_when: None,
                 }),
                 serial: Stage::Out(OutStage {
                     out: "SERIAL".to_string(),
                     args: Default::default(),
                     inputs: Vec::new(),
+                    enabled_when: None,
                 }),
             },
             resources: map! {
@@ -375,9 +528,66 @@ mod tests {
                     ty: ResourceType::String,
                 },
             },
+            tests: map! {},
+            memory: None,
         }
     }
 
+    #[test]
+    fn serial_framing_must_be_known() {
+        let mut args = IndexMap::new();
+        args.insert("framing".to_string(), "cobs".into());
+        let mut sink = Sink {
+            kind: SinkKind::Serial,
+            args,
+        };
+
+        assert!(check_sink_args("serial", &sink).is_ok());
+
+        sink.args.insert("framing".to_string(), "slip".into());
+
+        assert!(check_sink_args("serial", &sink).is_err());
+    }
+
+    #[test]
+    fn serial_format_must_be_known() {
+        let mut args = IndexMap::new();
+        args.insert("format".to_string(), "cbor".into());
+        let mut sink = Sink {
+            kind: SinkKind::Serial,
+            args,
+        };
+
+        assert!(check_sink_args("serial", &sink).is_ok());
+
+        sink.args.insert("format".to_string(), "xml".into());
+
+        assert!(check_sink_args("serial", &sink).is_err());
+    }
+
+    #[test]
+    fn onnx_models_are_detected_by_their_extension() {
+        let (mimetype, _) = model_format_and_args(
+            "model",
+            &IndexMap::new(),
+            Some(Path::new("models/mobilenet.onnx")),
+            |_| None,
+        )
+        .unwrap();
+        assert_eq!(mimetype, Mimetype::ONNX);
+
+        let mut args = IndexMap::new();
+        args.insert("format".to_string(), "tensorflow-lite".into());
+        let (mimetype, _) = model_format_and_args(
+            "model",
+            &args,
+            Some(Path::new("model.onnx")),
+            |_| None,
+        )
+        .unwrap();
+        assert_eq!(mimetype, Mimetype::default());
+    }
+
     #[test]
     fn register_all_stages() {
         let mut world = World::default();
@@ -437,6 +647,7 @@ mod tests {
                 Model {
                     model_file: ModelFile::FromDisk("model.tflite".into()),
                     args: IndexMap::new(),
+                    variants: IndexMap::new(),
                 },
             ),
             (
@@ -449,6 +660,7 @@ mod tests {
                             .unwrap(),
                     ),
                     args: IndexMap::new(),
+                    variants: IndexMap::new(),
                 },
             ),
         ];
@@ -5,23 +5,34 @@ use hotg_rune_core::{ElementType, Shape};
 use legion::{systems::CommandBuffer, Entity};
 
 use crate::{
-    lowering::{Inputs, NameTable, Outputs, Tensor},
-    parse::{self, DocumentV1},
+    lowering::{
+        Condition, InferredShape, Inputs, NameTable, Outputs,
+        ProcBlockDescriptors, Tensor,
+    },
+    parse::{self, DocumentV1, ProcBlockStage},
     Diagnostics,
 };
 
 /// Register all [`Tensor`]s and associate them as node [`Inputs`] or
 /// [`Outputs`].
+///
+/// Proc-blocks which don't declare their outputs will get an [`InferredShape`]
+/// for each output in their descriptor instead, and stages with an
+/// `enabled-when` tensor are given a [`Condition`].
 #[legion::system]
 pub(crate) fn run(
     cmd: &mut CommandBuffer,
     #[resource] names: &NameTable,
     #[resource] doc: &DocumentV1,
+    #[resource] descriptors: &ProcBlockDescriptors,
     #[resource] diags: &mut Diagnostics,
 ) {
-    let node_outputs = register_node_outputs(cmd, names, doc, diags);
+    let node_outputs =
+        register_node_outputs(cmd, names, doc, descriptors, diags);
     let node_inputs =
         register_node_inputs(doc, names, &node_outputs, cmd, diags);
+    let node_conditions =
+        register_node_conditions(doc, names, &node_outputs, cmd, diags);
 
     for (&node, outputs) in &node_outputs {
         for &tensor in &outputs.tensors {
@@ -38,6 +49,12 @@ pub(crate) fn run(
                         None
                     }
                 })
+                .chain(
+                    node_conditions
+                        .iter()
+                        .filter(|(_, &condition)| condition == tensor)
+                        .map(|(&ent, _)| ent),
+                )
                 .collect();
 
             cmd.add_component(tensor, inputs);
@@ -64,6 +81,7 @@ fn register_node_inputs(
         match register_stage_inputs(
             name,
             stage.inputs(),
+            doc,
             names,
             output_tensors_by_node,
         ) {
@@ -79,9 +97,38 @@ fn register_node_inputs(
     outputs
 }
 
+fn register_node_conditions(
+    doc: &DocumentV1,
+    names: &NameTable,
+    output_tensors_by_node: &HashMap<Entity, Outputs>,
+    cmd: &mut CommandBuffer,
+    diags: &mut Diagnostics,
+) -> HashMap<Entity, Entity> {
+    let mut conditions = HashMap::new();
+
+    for (name, stage) in &doc.pipeline {
+        let (ent, input) = match (names.get(name), stage.enabled_when()) {
+            (Some(&e), Some(input)) => (e, input),
+            _ => continue,
+        };
+
+        match get_input_tensor(name, input, doc, names, output_tensors_by_node)
+        {
+            Ok(tensor) => {
+                cmd.add_component(ent, Condition { tensor });
+                conditions.insert(ent, tensor);
+            },
+            Err(diag) => diags.push(diag),
+        }
+    }
+
+    conditions
+}
+
 fn register_stage_inputs(
     parent_name: &str,
     inputs: &[parse::Input],
+    doc: &DocumentV1,
     names: &NameTable,
     output_tensors_by_node: &HashMap<Entity, Outputs>,
 ) -> Result<Inputs, Diagnostic<()>> {
@@ -91,6 +138,7 @@ fn register_stage_inputs(
         let tensor = get_input_tensor(
             parent_name,
             input,
+            doc,
             names,
             output_tensors_by_node,
         )?;
@@ -103,6 +151,7 @@ fn register_stage_inputs(
 fn get_input_tensor(
     parent_name: &str,
     input: &parse::Input,
+    doc: &DocumentV1,
     names: &NameTable,
     output_tensors_by_node: &HashMap<Entity, Outputs>,
 ) -> Result<Entity, Diagnostic<()>> {
@@ -117,10 +166,20 @@ fn get_input_tensor(
         .get(&input_node)
         .ok_or_else(|| node_has_no_outputs_diagnostic(parent_name, input))?;
 
+    // Outputs referred to by name need to be mapped back to their position
+    let index = match &input.output {
+        Some(label) => doc
+            .pipeline
+            .get(&input.name)
+            .and_then(|stage| stage.output_index(label))
+            .ok_or_else(|| no_such_named_output_diagnostic(input, label))?,
+        None => input.index.unwrap_or(0),
+    };
+
     // Finally, get the Entity for the index'th item
     let tensor = output_tensors
         .tensors
-        .get(input.index.unwrap_or(0))
+        .get(index)
         .copied()
         .ok_or_else(|| no_such_output_diagnostic(input))?;
 
@@ -135,6 +194,16 @@ fn no_such_output_diagnostic(input: &parse::Input) -> Diagnostic<()> {
     ))
 }
 
+fn no_such_named_output_diagnostic(
+    input: &parse::Input,
+    label: &str,
+) -> Diagnostic<()> {
+    Diagnostic::error().with_message(format!(
+        "The \"{}\" node has no output called \"{}\"",
+        input.name, label
+    ))
+}
+
 fn node_has_no_outputs_diagnostic(
     parent_name: &str,
     input: &parse::Input,
@@ -159,6 +228,7 @@ fn register_node_outputs(
     cmd: &mut CommandBuffer,
     names: &NameTable,
     doc: &DocumentV1,
+    descriptors: &ProcBlockDescriptors,
     diags: &mut Diagnostics,
 ) -> HashMap<Entity, Outputs> {
     let mut node_to_output_tensors = HashMap::new();
@@ -169,7 +239,22 @@ fn register_node_outputs(
             None => continue,
         };
 
-        match allocate_output_tensors(cmd, stage.output_types()) {
+        let outputs = match stage {
+            parse::Stage::ProcBlock(ProcBlockStage {
+                proc_block,
+                outputs,
+                ..
+            }) if outputs.is_empty() => match descriptors.get(proc_block) {
+                Some(descriptor) => Ok(allocate_inferred_tensors(
+                    cmd,
+                    &descriptor.output_shapes,
+                )),
+                None => Ok(Outputs::default()),
+            },
+            _ => allocate_output_tensors(cmd, name, stage.output_types()),
+        };
+
+        match outputs {
             Ok(outputs) if outputs.tensors.is_empty() => {},
             Ok(outputs) => {
                 node_to_output_tensors.insert(ent, outputs.clone());
@@ -185,11 +270,21 @@ fn register_node_outputs(
 /// Allocate a new [`Tensor`] entity for each output that a node may have.
 fn allocate_output_tensors(
     cmd: &mut CommandBuffer,
+    node_name: &str,
     output_types: &[parse::Type],
 ) -> Result<Outputs, Diagnostic<()>> {
     let mut outputs = Vec::new();
 
-    for ty in output_types {
+    for (i, ty) in output_types.iter().enumerate() {
+        if let Some(label) = &ty.label {
+            if output_types[..i]
+                .iter()
+                .any(|t| t.label.as_ref() == Some(label))
+            {
+                return Err(duplicate_output_name_diagnostic(node_name, label));
+            }
+        }
+
         let tensor = shape(ty)?;
         outputs.push(cmd.push((tensor,)));
     }
@@ -197,6 +292,20 @@ fn allocate_output_tensors(
     Ok(Outputs { tensors: outputs })
 }
 
+/// Allocate a [`Tensor`] entity for each output, deferring the calculation
+/// of its shape until type checking.
+fn allocate_inferred_tensors(
+    cmd: &mut CommandBuffer,
+    output_shapes: &[hotg_rune_proc_blocks::ShapeTransfer<'static>],
+) -> Outputs {
+    let tensors = output_shapes
+        .iter()
+        .map(|transfer| cmd.push((InferredShape(transfer.clone()),)))
+        .collect();
+
+    Outputs { tensors }
+}
+
 fn shape(ty: &parse::Type) -> Result<Tensor, Diagnostic<()>> {
     let element_type: ElementType = ty
         .name
@@ -210,6 +319,16 @@ fn shape(ty: &parse::Type) -> Result<Tensor, Diagnostic<()>> {
     )))
 }
 
+fn duplicate_output_name_diagnostic(
+    node_name: &str,
+    label: &str,
+) -> Diagnostic<()> {
+    Diagnostic::error().with_message(format!(
+        "The \"{}\" node has more than one output called \"{}\"",
+        node_name, label
+    ))
+}
+
 fn unknown_element_type_diagnostic(name: &str) -> Diagnostic<()> {
     Diagnostic::error()
         .with_message(format!("Unknown element type, \"{}\"", name))
@@ -229,6 +348,13 @@ mod tests {
         BuildContext,
     };
 
+    fn labelled(ty: parse::Type, label: &str) -> parse::Type {
+        parse::Type {
+            label: Some(label.to_string()),
+            ..ty
+        }
+    }
+
     fn doc() -> DocumentV1 {
         DocumentV1 {
             version: 1,
@@ -251,6 +377,7 @@ mod tests {
                         ty!(u8[2]),
                     ],
                     args: map! {},
+                    enabled_when: None,
                 }),
                 output: parse::Stage::Out(OutStage {
                     out: "SERIAL".to_string(),
@@ -259,9 +386,32 @@ mod tests {
                         "transform.0".parse().unwrap(),
                     ],
                     args: map! {},
+                    enabled_when: None,
+                }),
+                labelled: parse::Stage::ProcBlock(ProcBlockStage {
+                    proc_block: "proc-block@1.0".parse().unwrap(),
+                    inputs: vec![
+                        "rand".parse().unwrap(),
+                    ],
+                    outputs: vec![
+                        labelled(ty!(i32[3]), "boxes"),
+                        labelled(ty!(i32[4]), "scores"),
+                    ],
+                    args: map! {},
+                    enabled_when: None,
+                }),
+                scores: parse::Stage::Out(OutStage {
+                    out: "SERIAL".to_string(),
+                    inputs: vec![
+                        "labelled.scores".parse().unwrap(),
+                    ],
+                    args: map! {},
+                    enabled_when: None,
                 })
             },
             resources: map! {},
+            tests: map! {},
+            memory: None,
         }
     }
 
@@ -271,6 +421,7 @@ mod tests {
         let mut res = Resources::default();
         res.insert(BuildContext::from_doc(doc().into()));
         res.insert(NameTable::default());
+        res.insert(ProcBlockDescriptors::default());
         crate::parse::phase().run(&mut world, &mut res);
 
         Phase::new()
@@ -288,6 +439,7 @@ mod tests {
             (("rand", 0), ("transform", 0), "f32[128]"),
             (("transform", 0), ("output", 1), "u8[1]"),
             (("transform", 1), ("output", 0), "u8[2]"),
+            (("labelled", 1), ("scores", 0), "i32[4]"),
         ];
         let mut inputs =
             <&Inputs>::query().filter(legion::component::<PipelineNode>());
@@ -0,0 +1,680 @@
+//! Optimization passes over the lowered pipeline.
+//!
+//! After lowering, the pipeline is a graph where each [`PipelineNode`] has
+//! [`Inputs`] and [`Outputs`] pointing at [`Tensor`] entities, and each
+//! tensor's [`Inputs`] and [`Outputs`] point back at the node which produces
+//! it and the nodes which consume it.
+//!
+//! A [`Pass`] is anything that can transform this graph, and a
+//! [`PassManager`] runs a set of passes until none of them make any more
+//! changes. Most optimizations are easiest to write as a [`RewriteRule`],
+//! which looks for a chain of nodes matching a [`NodePattern`] and is given a
+//! [`Graph`] to rewrite each match with.
+//!
+//! For example, a rule which folds a `normalize` proc-block into the image
+//! capability in front of it could look like this:
+//!
+//! ```rust
+//! use hotg_rune_compiler::lowering::{
+//!     rewrite::{Graph, NodePattern, RewriteRule},
+//!     ResourceOrString, Source, SourceKind,
+//! };
+//! use legion::Entity;
+//!
+//! struct FuseNormalize;
+//!
+//! impl RewriteRule for FuseNormalize {
+//!     fn name(&self) -> &str { "fuse-normalize" }
+//!
+//!     fn pattern(&self) -> Vec<NodePattern> {
+//!         vec![
+//!             NodePattern::Source(Some(SourceKind::Image)),
+//!             NodePattern::proc_block("normalize"),
+//!         ]
+//!     }
+//!
+//!     fn rewrite(&mut self, graph: &mut Graph<'_>, nodes: &[Entity]) -> bool {
+//!         let (image, normalize) = (nodes[0], nodes[1]);
+//!
+//!         if !graph.bypass(normalize) {
+//!             return false;
+//!         }
+//!
+//!         graph.update_component(image, |source: &mut Source| {
+//!             source.parameters.insert(
+//!                 "normalize".to_string(),
+//!                 ResourceOrString::from("1"),
+//!             );
+//!         });
+//!
+//!         true
+//!     }
+//! }
+//! ```
+//!
+//! Compiler plugins can run their own passes from
+//! [`crate::hooks::Hooks::after_lowering()`] using
+//! [`crate::hooks::Context::world_and_resources()`].
+
+use std::fmt::{self, Debug, Formatter};
+
+use legion::{
+    storage::Component, Entity, EntityStore, IntoQuery, Resources, World,
+};
+
+use crate::{
+    lowering::{
+        Inputs, Model, Name, NameTable, Outputs, PipelineNode, ProcBlock, Sink,
+        SinkKind, Source, SourceKind, Tensor,
+    },
+    FeatureFlags,
+};
+
+/// A transformation applied to the lowered pipeline.
+pub trait Pass {
+    /// A short name used in log messages.
+    fn name(&self) -> &str;
+
+    /// Run the pass, returning `true` if anything was changed.
+    fn run(&mut self, world: &mut World, res: &mut Resources) -> bool;
+}
+
+/// Runs a sequence of [`Pass`]es until they stop changing the pipeline.
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    max_iterations: usize,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        PassManager {
+            passes: Vec::new(),
+            max_iterations: 16,
+        }
+    }
+
+    /// The passes the compiler runs when optimizations are enabled.
+    pub fn optimizations() -> Self {
+        PassManager::new().with_pass(DeadCodeElimination)
+    }
+
+    pub fn with_pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.add_pass(pass);
+        self
+    }
+
+    pub fn add_pass(&mut self, pass: impl Pass + 'static) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// The most times the passes will be run, in case two passes keep undoing
+    /// each other's changes.
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Run every pass in order, repeating until none of them make changes.
+    ///
+    /// Returns the number of times the passes were run.
+    pub fn run(&mut self, world: &mut World, res: &mut Resources) -> usize {
+        for iteration in 1..=self.max_iterations {
+            let mut changed = false;
+
+            for pass in &mut self.passes {
+                if pass.run(world, res) {
+                    log::debug!(
+                        "The \"{}\" pass updated the pipeline",
+                        pass.name()
+                    );
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                return iteration;
+            }
+        }
+
+        log::warn!(
+            "The pipeline was still changing after {} iterations",
+            self.max_iterations
+        );
+        self.max_iterations
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self { PassManager::new() }
+}
+
+impl Debug for PassManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.passes.iter().map(|p| p.name()).collect();
+
+        f.debug_struct("PassManager")
+            .field("passes", &names)
+            .field("max_iterations", &self.max_iterations)
+            .finish()
+    }
+}
+
+/// Run the [`PassManager::optimizations()`], if they were enabled with
+/// [`FeatureFlags::set_optimizations()`].
+pub(crate) fn optimize(world: &mut World, res: &mut Resources) {
+    let enabled = res
+        .get::<FeatureFlags>()
+        .map(|f| f.optimizations)
+        .unwrap_or(false);
+
+    if enabled {
+        PassManager::optimizations().run(world, res);
+    }
+}
+
+/// Something a pipeline node is matched against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodePattern {
+    Any,
+    /// A capability, optionally of a particular kind.
+    Source(Option<SourceKind>),
+    /// A proc-block, optionally with a particular crate name.
+    ProcBlock(Option<String>),
+    Model,
+    /// An output, optionally of a particular kind.
+    Sink(Option<SinkKind>),
+}
+
+impl NodePattern {
+    pub fn proc_block(name: impl Into<String>) -> Self {
+        NodePattern::ProcBlock(Some(name.into()))
+    }
+
+    pub fn matches(&self, world: &World, node: Entity) -> bool {
+        let entry = match world.entry_ref(node) {
+            Ok(entry) => entry,
+            Err(_) => return false,
+        };
+
+        if entry.get_component::<PipelineNode>().is_err() {
+            return false;
+        }
+
+        match self {
+            NodePattern::Any => true,
+            NodePattern::Source(kind) => {
+                match entry.get_component::<Source>() {
+                    Ok(source) => kind.iter().all(|k| *k == source.kind),
+                    Err(_) => false,
+                }
+            },
+            NodePattern::ProcBlock(name) => {
+                match entry.get_component::<ProcBlock>() {
+                    Ok(pb) => name.iter().all(|n| n == pb.name()),
+                    Err(_) => false,
+                }
+            },
+            NodePattern::Model => entry.get_component::<Model>().is_ok(),
+            NodePattern::Sink(kind) => match entry.get_component::<Sink>() {
+                Ok(sink) => kind.iter().all(|k| *k == sink.kind),
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+/// A match-and-replace optimization.
+pub trait RewriteRule {
+    fn name(&self) -> &str;
+
+    /// The chain of nodes to look for.
+    ///
+    /// Each node in a match has a single output tensor, which is only
+    /// consumed by the next node in the chain.
+    fn pattern(&self) -> Vec<NodePattern>;
+
+    /// Rewrite the matched nodes, returning `false` if the rule decided to
+    /// leave them alone.
+    fn rewrite(&mut self, graph: &mut Graph<'_>, nodes: &[Entity]) -> bool;
+}
+
+/// A [`Pass`] which applies a [`RewriteRule`] everywhere its pattern matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rewrite<R>(pub R);
+
+impl<R: RewriteRule> Pass for Rewrite<R> {
+    fn name(&self) -> &str { self.0.name() }
+
+    fn run(&mut self, world: &mut World, res: &mut Resources) -> bool {
+        let pattern = self.0.pattern();
+        let matches = find_matches(world, &pattern);
+        let mut changed = false;
+
+        for nodes in matches {
+            // An earlier rewrite may have removed part of this match
+            if !nodes.iter().all(|&n| world.contains(n)) {
+                continue;
+            }
+
+            let mut graph = Graph::new(world, res);
+            changed |= self.0.rewrite(&mut graph, &nodes);
+        }
+
+        changed
+    }
+}
+
+fn find_matches(world: &World, pattern: &[NodePattern]) -> Vec<Vec<Entity>> {
+    let (first, rest) = match pattern.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+    let graph = GraphRef { world };
+
+    let mut candidates: Vec<Entity> = <(Entity, &PipelineNode)>::query()
+        .iter(world)
+        .map(|(&e, _)| e)
+        .filter(|&e| first.matches(world, e))
+        .collect();
+    // Make the order matches are visited in deterministic
+    candidates.sort_by_key(|&e| graph.name(e).map(|n| n.to_string()));
+
+    candidates
+        .into_iter()
+        .filter_map(|start| {
+            let mut nodes = vec![start];
+
+            for step in rest {
+                let previous = *nodes.last().unwrap();
+                let next = match graph.outputs(previous).as_slice() {
+                    [tensor] => match graph.consumers(*tensor).as_slice() {
+                        [next] => *next,
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+
+                if !step.matches(world, next) {
+                    return None;
+                }
+                nodes.push(next);
+            }
+
+            Some(nodes)
+        })
+        .collect()
+}
+
+/// Read-only helpers shared by [`Graph`] and the pattern matcher.
+struct GraphRef<'a> {
+    world: &'a World,
+}
+
+impl<'a> GraphRef<'a> {
+    fn component<T: Component + Clone>(&self, entity: Entity) -> Option<T> {
+        self.world
+            .entry_ref(entity)
+            .ok()?
+            .get_component::<T>()
+            .ok()
+            .cloned()
+    }
+
+    fn name(&self, entity: Entity) -> Option<Name> { self.component(entity) }
+
+    fn inputs(&self, entity: Entity) -> Vec<Entity> {
+        self.component::<Inputs>(entity)
+            .map(|i| i.tensors)
+            .unwrap_or_default()
+    }
+
+    fn outputs(&self, entity: Entity) -> Vec<Entity> {
+        self.component::<Outputs>(entity)
+            .map(|o| o.tensors)
+            .unwrap_or_default()
+    }
+
+    fn consumers(&self, tensor: Entity) -> Vec<Entity> {
+        if self.component::<Tensor>(tensor).is_none() {
+            return Vec::new();
+        }
+
+        self.outputs(tensor)
+    }
+}
+
+/// A mutable view of the lowered pipeline, with helpers for keeping the
+/// links between nodes and tensors consistent while rewriting it.
+pub struct Graph<'a> {
+    world: &'a mut World,
+    res: &'a mut Resources,
+}
+
+impl<'a> Graph<'a> {
+    pub fn new(world: &'a mut World, res: &'a mut Resources) -> Self {
+        Graph { world, res }
+    }
+
+    pub fn world(&self) -> &World { self.world }
+
+    pub fn world_mut(&mut self) -> &mut World { self.world }
+
+    pub fn resources(&self) -> &Resources { self.res }
+
+    fn view(&self) -> GraphRef<'_> { GraphRef { world: self.world } }
+
+    pub fn name(&self, node: Entity) -> Option<Name> { self.view().name(node) }
+
+    /// Update one of an entity's components (e.g. a proc-block's arguments),
+    /// returning `false` if the entity doesn't have that component.
+    pub fn update_component<T: Component>(
+        &mut self,
+        entity: Entity,
+        update: impl FnOnce(&mut T),
+    ) -> bool {
+        let mut entry = match self.world.entry(entity) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        match entry.get_component_mut::<T>() {
+            Ok(component) => {
+                update(component);
+                true
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// The tensors a node reads from.
+    pub fn inputs(&self, node: Entity) -> Vec<Entity> {
+        self.view().inputs(node)
+    }
+
+    /// The tensors a node writes to.
+    pub fn outputs(&self, node: Entity) -> Vec<Entity> {
+        self.view().outputs(node)
+    }
+
+    /// The node which writes to a tensor.
+    pub fn producer(&self, tensor: Entity) -> Option<Entity> {
+        self.view().inputs(tensor).first().copied()
+    }
+
+    /// The nodes which read from a tensor.
+    pub fn consumers(&self, tensor: Entity) -> Vec<Entity> {
+        self.view().consumers(tensor)
+    }
+
+    /// Remove a node and the tensors it outputs, disconnecting it from the
+    /// rest of the pipeline.
+    pub fn remove_node(&mut self, node: Entity) {
+        for tensor in self.inputs(node) {
+            self.update_links::<Outputs>(tensor, |nodes| {
+                nodes.retain(|&n| n != node)
+            });
+        }
+
+        for tensor in self.outputs(node) {
+            for consumer in self.consumers(tensor) {
+                self.update_links::<Inputs>(consumer, |tensors| {
+                    tensors.retain(|&t| t != tensor)
+                });
+            }
+            self.world.remove(tensor);
+        }
+
+        if let Some(name) = self.name(node) {
+            if let Some(mut names) = self.res.get_mut::<NameTable>() {
+                names.remove(&name);
+            }
+        }
+
+        self.world.remove(node);
+    }
+
+    /// Remove a node with one input and one output, connecting anything that
+    /// consumed its output directly to its input.
+    ///
+    /// Returns `false` (and leaves the graph alone) if the node doesn't have
+    /// exactly one input and one output.
+    pub fn bypass(&mut self, node: Entity) -> bool {
+        let (input, output) =
+            match (self.inputs(node).as_slice(), self.outputs(node).as_slice())
+            {
+                ([input], [output]) => (*input, *output),
+                _ => return false,
+            };
+        let consumers = self.consumers(output);
+
+        for &consumer in &consumers {
+            self.update_links::<Inputs>(consumer, |tensors| {
+                for t in tensors.iter_mut().filter(|t| **t == output) {
+                    *t = input;
+                }
+            });
+        }
+        self.update_links::<Outputs>(input, |nodes| {
+            nodes.retain(|&n| n != node);
+            nodes.extend(consumers.iter().copied());
+        });
+
+        self.update_links::<Outputs>(output, |nodes| nodes.clear());
+        self.remove_node(node);
+
+        true
+    }
+
+    fn update_links<T>(
+        &mut self,
+        entity: Entity,
+        update: impl FnOnce(&mut Vec<Entity>),
+    ) where
+        T: Component + Links,
+    {
+        self.update_component(entity, |links: &mut T| {
+            update(links.entities_mut())
+        });
+    }
+}
+
+/// Components which link nodes and tensors together.
+trait Links {
+    fn entities_mut(&mut self) -> &mut Vec<Entity>;
+}
+
+impl Links for Inputs {
+    fn entities_mut(&mut self) -> &mut Vec<Entity> { &mut self.tensors }
+}
+
+impl Links for Outputs {
+    fn entities_mut(&mut self) -> &mut Vec<Entity> { &mut self.tensors }
+}
+
+/// Remove any proc-blocks and models whose outputs are never used.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &str { "dead-code-elimination" }
+
+    fn run(&mut self, world: &mut World, res: &mut Resources) -> bool {
+        let mut graph = Graph::new(world, res);
+
+        let dead: Vec<Entity> = <(Entity, &PipelineNode)>::query()
+            .iter(graph.world())
+            .map(|(&e, _)| e)
+            .filter(|&e| {
+                let world = graph.world();
+                NodePattern::ProcBlock(None).matches(world, e)
+                    || NodePattern::Model.matches(world, e)
+            })
+            .filter(|&e| {
+                let outputs = graph.outputs(e);
+                !outputs.is_empty()
+                    && outputs.iter().all(|&t| graph.consumers(t).is_empty())
+            })
+            .collect();
+
+        for &node in &dead {
+            log::debug!(
+                "Removing \"{}\" because its outputs are never used",
+                graph.name(node).map(|n| n.to_string()).unwrap_or_default()
+            );
+            graph.remove_node(node);
+        }
+
+        !dead.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hotg_rune_core::{ElementType, Shape};
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    struct Pipeline {
+        world: World,
+        res: Resources,
+    }
+
+    impl Pipeline {
+        fn new() -> Self {
+            let mut res = Resources::default();
+            res.insert(NameTable::default());
+
+            Pipeline {
+                world: World::default(),
+                res,
+            }
+        }
+
+        fn tensor(&mut self) -> Entity {
+            let shape = Shape::new(ElementType::F32, vec![1]);
+            self.world.push((
+                Tensor(shape),
+                Inputs::default(),
+                Outputs::default(),
+            ))
+        }
+
+        /// Add a node, wiring it up to the tensors it reads from and returning
+        /// its output tensor.
+        fn node(
+            &mut self,
+            name: &str,
+            component: impl Component,
+            inputs: &[Entity],
+        ) -> (Entity, Entity) {
+            let output = self.tensor();
+            let node = self.world.push((
+                Name::from(name),
+                PipelineNode,
+                component,
+                Inputs {
+                    tensors: inputs.to_vec(),
+                },
+                Outputs {
+                    tensors: vec![output],
+                },
+            ));
+            self.res
+                .get_mut::<NameTable>()
+                .unwrap()
+                .insert(Name::from(name), node);
+
+            let mut graph = Graph::new(&mut self.world, &mut self.res);
+            graph.update_links::<Inputs>(output, |t| t.push(node));
+            for &input in inputs {
+                graph.update_links::<Outputs>(input, |n| n.push(node));
+            }
+
+            (node, output)
+        }
+
+        fn proc_block(&mut self, name: &str, inputs: &[Entity]) -> Entity {
+            let pb = ProcBlock {
+                path: crate::parse::Path::new(name, None, None),
+                parameters: IndexMap::new(),
+            };
+
+            self.node(name, pb, inputs).1
+        }
+
+        fn names(&self) -> Vec<String> {
+            self.res
+                .get::<NameTable>()
+                .unwrap()
+                .keys()
+                .map(|n| n.to_string())
+                .collect()
+        }
+    }
+
+    fn source() -> Source {
+        Source {
+            kind: SourceKind::Image,
+            parameters: IndexMap::new(),
+        }
+    }
+
+    #[test]
+    fn unused_stages_are_removed() {
+        let mut p = Pipeline::new();
+        let (_, image) = p.node("image", source(), &[]);
+        let used = p.proc_block("used", &[image]);
+        let unused = p.proc_block("unused", &[image]);
+        p.proc_block("also_unused", &[unused]);
+        let sink = Sink {
+            kind: SinkKind::Serial,
+            args: IndexMap::new(),
+        };
+        p.node("serial", sink, &[used]);
+
+        let iterations =
+            PassManager::optimizations().run(&mut p.world, &mut p.res);
+
+        assert_eq!(iterations, 3);
+        assert_eq!(p.names(), ["image", "used", "serial"]);
+        let graph = Graph::new(&mut p.world, &mut p.res);
+        assert_eq!(graph.consumers(image).len(), 1);
+    }
+
+    #[test]
+    fn rewrite_rules_can_bypass_nodes() {
+        struct RemoveIdentity;
+
+        impl RewriteRule for RemoveIdentity {
+            fn name(&self) -> &str { "remove-identity" }
+
+            fn pattern(&self) -> Vec<NodePattern> {
+                vec![NodePattern::proc_block("identity"), NodePattern::Any]
+            }
+
+            fn rewrite(
+                &mut self,
+                graph: &mut Graph<'_>,
+                nodes: &[Entity],
+            ) -> bool {
+                graph.bypass(nodes[0])
+            }
+        }
+
+        let mut p = Pipeline::new();
+        let (_, image) = p.node("image", source(), &[]);
+        let identity = p.proc_block("identity", &[image]);
+        p.proc_block("normalize", &[identity]);
+
+        let changed = Rewrite(RemoveIdentity).run(&mut p.world, &mut p.res);
+
+        assert!(changed);
+        assert_eq!(p.names(), ["image", "normalize"]);
+        let graph = Graph::new(&mut p.world, &mut p.res);
+        let normalize = graph.consumers(image)[0];
+        assert_eq!(graph.name(normalize).unwrap().to_string(), "normalize");
+        assert_eq!(graph.inputs(normalize), [image]);
+    }
+}
@@ -18,12 +18,14 @@ macro_rules! ty {
             crate::parse::Type {
                 name: String::from(stringify!($type)),
                 dimensions: vec![ $($dim),*],
+                label: None,
             }
         };
         ($type:ident) => {
             crate::parse::Type {
                 name: String::from(stringify!($type)),
                 dimensions: vec![],
+                label: None,
             }
         }
     }
@@ -23,8 +23,13 @@ use serde::{
     ser::{Serialize, Serializer},
 };
 
+use crate::download::RemoteFile;
+
 static RESOURCE_NAME_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^\$[_a-zA-Z][_a-zA-Z0-9]*$").unwrap());
+static BYTE_SIZE_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s*(\d+)\s*([kKmMgG]([iI]?[bB])?|[bB])?\s*$").unwrap()
+});
 
 /// The top level Runefile type.
 #[derive(Debug, Clone, PartialEq, JsonSchema)]
@@ -148,6 +153,13 @@ pub struct DocumentV1 {
     /// Any resources that can be accessed by pipeline stages.
     #[serde(default)]
     pub resources: IndexMap<String, ResourceDeclaration>,
+    /// Named test cases which check that the Rune produces the expected
+    /// outputs for a particular set of inputs.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tests: IndexMap<String, TestCase>,
+    /// How much memory the Rune expects to need while it is running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryConfig>,
 }
 
 impl Document {
@@ -201,6 +213,10 @@ The full syntax is `base@version#sub_path` where
 - `sub_path` is an optional field which is useful when pointing to
   repositories with multiple relevant items because it lets you specify
   which directory the specified item is in.
+
+A proc-block can also be downloaded as a zip archive named after its crate
+by using `https://example.com/my_proc_block.zip#sha256=<hash>`, where
+`<hash>` is the SHA-256 hash of the archive.
 "#
 );
 
@@ -218,6 +234,23 @@ impl Path {
     }
 }
 
+impl Path {
+    /// The zip archive this proc-block should be downloaded from, if it is
+    /// a `https://...#sha256=<hash>` URL.
+    pub(crate) fn remote_archive(&self) -> Option<RemoteFile<'_>> {
+        let sha256 = self.sub_path.as_deref()?.strip_prefix("sha256=")?;
+
+        if self.base.starts_with("https://") && self.version.is_none() {
+            Some(RemoteFile {
+                url: &self.base,
+                sha256,
+            })
+        } else {
+            None
+        }
+    }
+}
+
 impl Display for Path {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let Path {
@@ -243,7 +276,7 @@ static PATH_PATTERN: Lazy<Regex> = Lazy::new(|| {
         r"(?x)
         (?P<base>[\w\d:/_.-]+)
         (?:@(?P<version>[\w\d./-]+))?
-        (?:\#(?P<sub_path>[\w\d._/-]+))?
+        (?:\#(?P<sub_path>[\w\d._/=-]+))?
         ",
     )
     .unwrap()
@@ -310,6 +343,9 @@ impl std::error::Error for PathParseError {}
 )]
 pub struct ModelStage {
     /// The model to use, or a resource which specifies the model to use.
+    ///
+    /// Models can be downloaded with a `https://...#sha256=<hash>` URL,
+    /// where `<hash>` is the SHA-256 hash of the model file.
     #[schemars(required)]
     pub model: ResourceOrString,
     /// Tensors to use as input to this model.
@@ -320,6 +356,21 @@ pub struct ModelStage {
     pub outputs: Vec<Type>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     pub args: IndexMap<String, Argument>,
+    /// Alternative versions of the model (e.g. `low_mem: ./model_int8.tflite`)
+    /// which the runtime may pick between based on how much memory is
+    /// available.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub variants: IndexMap<String, String>,
+    /// Only run this stage when the tensor has at least one non-zero
+    /// element (e.g. a flag from a cheap wake-word detector).
+    ///
+    /// Stages which use this stage's outputs are skipped as well.
+    #[serde(
+        rename = "enabled-when",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub enabled_when: Option<Input>,
 }
 
 /// A stage which executes a procedural block.
@@ -342,6 +393,16 @@ pub struct ProcBlockStage {
     pub outputs: Vec<Type>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     pub args: IndexMap<String, Argument>,
+    /// Only run this stage when the tensor has at least one non-zero
+    /// element (e.g. a flag from a cheap wake-word detector).
+    ///
+    /// Stages which use this stage's outputs are skipped as well.
+    #[serde(
+        rename = "enabled-when",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub enabled_when: Option<Input>,
 }
 
 /// A stage which reads inputs from the runtime.
@@ -380,6 +441,16 @@ pub struct OutStage {
     pub inputs: Vec<Input>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     pub args: IndexMap<String, Argument>,
+    /// Only run this stage when the tensor has at least one non-zero
+    /// element (e.g. a flag from a cheap wake-word detector).
+    ///
+    /// Stages which use this stage's outputs are skipped as well.
+    #[serde(
+        rename = "enabled-when",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub enabled_when: Option<Input>,
 }
 
 /// A stage in the Rune's pipeline.
@@ -421,6 +492,25 @@ impl Stage {
         }
     }
 
+    /// Find the position of the output with this [`Type::label`].
+    pub fn output_index(&self, label: &str) -> Option<usize> {
+        self.output_types()
+            .iter()
+            .position(|ty| ty.label.as_deref() == Some(label))
+    }
+
+    /// The tensor which decides whether this stage should run, if any.
+    pub fn enabled_when(&self) -> Option<&Input> {
+        match self {
+            Stage::Model(ModelStage { enabled_when, .. })
+            | Stage::ProcBlock(ProcBlockStage { enabled_when, .. })
+            | Stage::Out(OutStage { enabled_when, .. }) => {
+                enabled_when.as_ref()
+            },
+            Stage::Capability(_) => None,
+        }
+    }
+
     pub fn output_types(&self) -> &[Type] {
         match self {
             Stage::Model(ModelStage { outputs, .. })
@@ -498,7 +588,17 @@ impl<'de> Deserialize<'de> for ResourceOrString {
             type Value = ResourceOrString;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                write!(formatter, "a number, string, or \"$RESOURCE_NAME\"")
+                write!(
+                    formatter,
+                    "a boolean, number, string, or \"$RESOURCE_NAME\""
+                )
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(ResourceOrString::String(v.to_string()))
             }
 
             fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
@@ -534,6 +634,11 @@ impl<'de> Deserialize<'de> for ResourceOrString {
 
                 match ResourceName::from_str(v) {
                     Ok(name) => Ok(ResourceOrString::Resource(name)),
+                    // Expressions like "$audio.hz / 100" are evaluated during
+                    // lowering
+                    Err(_) if v.contains('.') => {
+                        Ok(ResourceOrString::String(v.to_string()))
+                    },
                     Err(e) => Err(E::custom(e)),
                 }
             }
@@ -578,9 +683,15 @@ impl JsonSchema for Argument {
 
     fn json_schema(gen: &mut SchemaGenerator) -> Schema {
         let number = gen.subschema_for::<serde_json::Number>();
+        let boolean = gen.subschema_for::<bool>();
 
         let mut schema = ResourceOrString::json_schema(gen).into_object();
-        schema.subschemas().any_of.as_mut().unwrap().push(number);
+        schema
+            .subschemas()
+            .any_of
+            .as_mut()
+            .unwrap()
+            .extend([number, boolean]);
 
         schema.into()
     }
@@ -612,16 +723,24 @@ pub struct Type {
     pub name: String,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub dimensions: Vec<usize>,
+    /// A name later stages can use to refer to this output (e.g.
+    /// `detect.boxes`) instead of its position.
+    #[serde(rename = "name", default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 /// The name of a tensor.
 ///
 /// Typically something like "stage", or "stage.2" if the stage has multiple
-/// outputs.
+/// outputs. Outputs which were given a name can also be referred to by that
+/// name (e.g. "detect.boxes").
 #[derive(Debug, Clone, PartialEq, Hash, Eq, Ord, PartialOrd)]
 pub struct Input {
     pub name: String,
     pub index: Option<usize>,
+    /// The name of the output to use, if it was referred to by name rather
+    /// than position.
+    pub output: Option<String>,
 }
 
 impl_json_schema_via_regex!(
@@ -630,7 +749,7 @@ impl_json_schema_via_regex!(
     r#"
 The name of a tensor.
 
-Typically something like "stage", or "stage.2" if the stage has multiple outputs.
+Typically something like "stage", or "stage.2" if the stage has multiple outputs. Outputs which were given a name can also be referred to by that name (e.g. "detect.boxes").
 "#
 );
 
@@ -642,23 +761,41 @@ impl Input {
         Input {
             name: name.into(),
             index: index.into(),
+            output: None,
+        }
+    }
+
+    /// Refer to one of a stage's outputs by name.
+    pub fn named(name: impl Into<String>, output: impl Into<String>) -> Self {
+        Input {
+            name: name.into(),
+            index: None,
+            output: Some(output.into()),
         }
     }
 }
 
 static INPUT_PATTERN: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^(?P<name>[a-zA-Z_][\w-]*)(?:\.(?P<index>\d+))?$").unwrap()
+    Regex::new(
+        r"^(?P<name>[a-zA-Z_][\w-]*)(?:\.(?:(?P<index>\d+)|(?P<output>[a-zA-Z_][\w-]*)))?$",
+    )
+    .unwrap()
 });
 
 impl FromStr for Input {
     type Err = Box<dyn std::error::Error>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let captures = INPUT_PATTERN
-            .captures(s)
-            .ok_or("Expected something like \"fft\" or \"fft.2\"")?;
+        let captures = INPUT_PATTERN.captures(s).ok_or(
+            "Expected something like \"fft\", \"fft.2\", or \"fft.bins\"",
+        )?;
 
         let name = &captures["name"];
+
+        if let Some(output) = captures.name("output") {
+            return Ok(Input::named(name, output.as_str()));
+        }
+
         let index = captures.name("index").map(|m| {
             m.as_str()
                 .parse::<usize>()
@@ -671,9 +808,10 @@ impl FromStr for Input {
 
 impl Display for Input {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self.index {
-            Some(index) => write!(f, "{}.{}", self.name, index),
-            None => write!(f, "{}", self.name),
+        match (self.index, &self.output) {
+            (Some(index), _) => write!(f, "{}.{}", self.name, index),
+            (None, Some(output)) => write!(f, "{}.{}", self.name, output),
+            (None, None) => write!(f, "{}", self.name),
         }
     }
 }
@@ -718,6 +856,202 @@ pub struct ResourceDeclaration {
     pub ty: ResourceType,
 }
 
+/// A test case that is embedded in the Rune and can be executed with
+/// `hotg_rune_runtime::embedded_tests::run_embedded_tests()`.
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(deny_unknown_fields)]
+pub struct TestCase {
+    /// The file containing each capability's raw input tensor, keyed by
+    /// capability name and relative to the Runefile.
+    pub inputs: IndexMap<String, String>,
+    /// The values each output is expected to receive, keyed by output name.
+    pub outputs: IndexMap<String, ExpectedOutput>,
+}
+
+impl TestCase {
+    pub fn span(&self) -> Span {
+        // TODO: Get span from serde_yaml
+        Span::default()
+    }
+}
+
+/// The tensor an output is expected to receive during a [`TestCase`].
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedOutput {
+    /// The tensor's elements, in row-major order.
+    pub values: Vec<f64>,
+    /// How far each element may be from its expected value.
+    #[serde(default)]
+    pub tolerance: f64,
+}
+
+/// The memory a Rune needs at runtime.
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryConfig {
+    /// The most memory the Rune's allocator may hand out at any one time.
+    pub heap: Option<ByteSize>,
+    /// The size of the Rune's stack (defaults to 1MB).
+    pub stack: Option<ByteSize>,
+}
+
+impl MemoryConfig {
+    /// The stack size Rust uses for `wasm32-unknown-unknown` by default.
+    pub const DEFAULT_STACK_SIZE: u64 = 1024 * 1024;
+
+    pub fn stack_size(&self) -> u64 {
+        self.stack.map_or(Self::DEFAULT_STACK_SIZE, |s| s.0)
+    }
+
+    /// The peak memory a Rune with `embedded` bytes of models and resources
+    /// is expected to use.
+    pub fn peak_usage(&self, embedded: u64) -> u64 {
+        let heap = self.heap.map_or(0, |h| h.0);
+        heap.saturating_add(self.stack_size())
+            .saturating_add(embedded)
+    }
+
+    pub fn span(&self) -> Span {
+        // TODO: Get span from serde_yaml
+        Span::default()
+    }
+}
+
+/// A number of bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    const UNITS: [(&'static str, u64); 3] =
+        [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10)];
+}
+
+impl JsonSchema for ByteSize {
+    fn schema_name() -> String { String::from("ByteSize") }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let mut schema = SchemaObject {
+            instance_type: Some(
+                vec![InstanceType::String, InstanceType::Integer].into(),
+            ),
+            metadata: Some(Box::new(Metadata {
+                description: Some(String::from(
+                    "A number of bytes, either as an integer or with a unit \
+                     (e.g. `64KB` or `4MiB`). Units are powers of 1024.",
+                )),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        schema.string().pattern = Some(BYTE_SIZE_PATTERN.to_string());
+
+        schema.into()
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = ByteSizeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let captures =
+            BYTE_SIZE_PATTERN.captures(s).ok_or(ByteSizeParseError)?;
+
+        let value: u64 = captures[1].parse().map_err(|_| ByteSizeParseError)?;
+        let multiplier = match captures
+            .get(2)
+            .and_then(|unit| unit.as_str().chars().next())
+            .map(|c| c.to_ascii_uppercase())
+        {
+            Some('K') => 1 << 10,
+            Some('M') => 1 << 20,
+            Some('G') => 1 << 30,
+            _ => 1,
+        };
+
+        value
+            .checked_mul(multiplier)
+            .map(ByteSize)
+            .ok_or(ByteSizeParseError)
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (unit, size) in ByteSize::UNITS {
+            if self.0 != 0 && self.0 % size == 0 {
+                return write!(f, "{}{}", self.0 / size, unit);
+            }
+        }
+
+        write!(f, "{}B", self.0)
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Bytes(u64),
+            Text(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Bytes(bytes) => Ok(ByteSize(bytes)),
+            Repr::Text(text) => text.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ByteSizeParseError;
+
+impl Display for ByteSizeParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected a number of bytes (e.g. \"64KB\" or \"4MB\")")
+    }
+}
+
+impl std::error::Error for ByteSizeParseError {}
+
 impl ResourceDeclaration {
     pub fn span(&self) -> Span {
         // TODO: Get span from serde_yaml
@@ -882,6 +1216,60 @@ mod tests {
         assert_eq!(got.to_string(), src);
     }
 
+    #[test]
+    fn input_specifier_with_named_output() {
+        let src = "detect.boxes";
+        let should_be = Input::named("detect", "boxes");
+
+        let got = Input::from_str(src).unwrap();
+
+        assert_eq!(got, should_be);
+        assert_eq!(got.to_string(), src);
+    }
+
+    #[test]
+    fn parse_named_outputs() {
+        let src = r#"
+              model: ./ssd.tflite
+              inputs: [image]
+              outputs:
+              - type: f32
+                dimensions: [1, 10, 4]
+                name: boxes
+              - type: f32
+                dimensions: [1, 10]
+                name: scores
+        "#;
+
+        let got: Stage = serde_yaml::from_str(src).unwrap();
+
+        assert_eq!(got.output_index("scores"), Some(1));
+        assert_eq!(got.output_index("classes"), None);
+    }
+
+    #[test]
+    fn parse_enabled_when() {
+        let src = r#"
+              model: ./classifier.tflite
+              inputs: [audio]
+              enabled-when: wake_word.detected
+        "#;
+
+        let got: Stage = serde_yaml::from_str(src).unwrap();
+
+        assert_eq!(
+            got.enabled_when(),
+            Some(&Input::named("wake_word", "detected"))
+        );
+    }
+
+    #[test]
+    fn expressions_are_kept_for_lowering() {
+        let got: Argument = serde_yaml::from_str("$audio.hz / 100").unwrap();
+
+        assert_eq!(got, Argument::from("$audio.hz / 100"));
+    }
+
     #[test]
     fn parse_paths() {
         let inputs = vec![
@@ -1051,6 +1439,7 @@ mod tests {
             outputs: vec![Type {
                 name: String::from("u8"),
                 dimensions: vec![1],
+                label: None,
             }],
             args: vec![(
                 "word-list".to_string(),
@@ -1058,6 +1447,7 @@ mod tests {
             )]
             .into_iter()
             .collect(),
+            enabled_when: None,
         });
 
         let got: IndexMap<String, Stage> = serde_yaml::from_str(src).unwrap();
@@ -1131,28 +1521,35 @@ pipeline:
                     inputs: vec!["audio".parse().unwrap()],
                     outputs: vec![ty!(i8[1960])],
                     args: IndexMap::new(),
+                    enabled_when: None,
                 }),
                 model: Stage::Model(ModelStage {
                     model: "./model.tflite".into(),
                     inputs: vec!["fft".parse().unwrap()],
                     outputs: vec![ty!(i8[6])],
                     args: IndexMap::new(),
+                    variants: IndexMap::new(),
+                    enabled_when: None,
                 }),
                 label: Stage::ProcBlock(ProcBlockStage {
                     proc_block: "hotg-ai/rune#proc_blocks/ohv_label".parse().unwrap(),
                     inputs: vec!["model".parse().unwrap()],
-                    outputs: vec![Type { name: String::from("utf8"), dimensions: Vec::new() }],
+                    outputs: vec![Type { name: String::from("utf8"), dimensions: Vec::new(), label: None }],
                     args: map! {
                         labels: "silence\nunknown\nup\ndown\nleft\nright".into()
                     },
+                    enabled_when: None,
                 }),
                 output: Stage::Out(OutStage {
                     out: String::from("SERIAL"),
                     args: IndexMap::new(),
                     inputs: vec!["label".parse().unwrap()],
+                    enabled_when: None,
                 }),
             },
             resources: map![],
+            tests: map![],
+            memory: None,
         });
 
         let got = Document::parse(src).unwrap();
@@ -1175,6 +1572,7 @@ pipeline:
             outputs: vec![Type {
                 name: String::from("i16"),
                 dimensions: vec![16000],
+                label: None,
             }],
             args: map! { hz: "16000".into() },
         });
@@ -1184,6 +1582,79 @@ pipeline:
         assert_eq!(got, should_be);
     }
 
+    #[test]
+    fn capability_arguments_round_trip_to_values() {
+        let src = r#"
+              capability: RAND
+              outputs:
+              - type: f64
+                dimensions: [1]
+              args:
+                count: 3
+                scale: 0.5
+                offset: 3.141592653589793
+        "#;
+
+        let stage: Stage = serde_yaml::from_str(src).unwrap();
+        let args = match stage {
+            Stage::Capability(CapabilityStage { args, .. }) => args,
+            other => panic!("Expected a capability, found {:?}", other),
+        };
+        let value = |name: &str| match &args[name].0 {
+            ResourceOrString::String(s) => {
+                s.parse::<hotg_rune_core::Value>().unwrap()
+            },
+            other => panic!("Expected a string, found {:?}", other),
+        };
+
+        assert_eq!(value("count"), hotg_rune_core::Value::Integer(3));
+        assert_eq!(value("scale"), hotg_rune_core::Value::Float(0.5));
+        assert_eq!(
+            value("offset"),
+            hotg_rune_core::Value::Double(std::f64::consts::PI)
+        );
+    }
+
+    #[test]
+    fn parse_model_with_variants() {
+        let src = r#"
+              model: ./model_f32.tflite
+              inputs:
+              - audio
+              outputs:
+              - type: f32
+                dimensions: [6]
+              variants:
+                low_mem: ./model_int8.tflite
+        "#;
+        let should_be = Stage::Model(ModelStage {
+            model: "./model_f32.tflite".into(),
+            inputs: vec!["audio".parse().unwrap()],
+            outputs: vec![ty!(f32[6])],
+            args: IndexMap::new(),
+            variants: map! { low_mem: "./model_int8.tflite".to_string() },
+            enabled_when: None,
+        });
+
+        let got: Stage = serde_yaml::from_str(src).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn parse_memory_config() {
+        let src = "heap: 4MB\nstack: 64 KiB\n";
+
+        let got: MemoryConfig = serde_yaml::from_str(src).unwrap();
+
+        assert_eq!(got.heap, Some(ByteSize(4 * 1024 * 1024)));
+        assert_eq!(got.stack, Some(ByteSize(64 * 1024)));
+        assert_eq!(got.heap.unwrap().to_string(), "4MB");
+        assert_eq!("1000".parse(), Ok(ByteSize(1000)));
+        assert_eq!(ByteSize(1000).to_string(), "1000B");
+        assert!("4 parsecs".parse::<ByteSize>().is_err());
+    }
+
     #[test]
     fn schema_is_in_sync_with_version_on_disk() {
         let existing_schema = include_str!("../../runefile-schema.json");
@@ -3,7 +3,8 @@ use legion::{systems::Runnable, Resources, World};
 use crate::{
     codegen, compile,
     hooks::{Continuation, Ctx, Hooks},
-    lowering, parse, type_check, BuildContext, FeatureFlags,
+    lowering::{self, ProcBlockDescriptors},
+    parse, type_check, BuildContext, FeatureFlags,
 };
 
 /// Execute the `rune build` process.
@@ -26,6 +27,7 @@ pub fn build_with_hooks(
 
     res.insert(ctx);
     res.insert(features);
+    res.insert(ProcBlockDescriptors::default());
 
     if hooks.before_parse(&mut c(&mut world, &mut res))
         != Continuation::Continue
@@ -43,6 +45,7 @@ pub fn build_with_hooks(
 
     log::debug!("Beginning the \"lowering\" phase");
     lowering::phase().run(&mut world, &mut res);
+    lowering::rewrite::optimize(&mut world, &mut res);
 
     if hooks.after_lowering(&mut c(&mut world, &mut res))
         != Continuation::Continue
@@ -68,6 +71,21 @@ pub fn build_with_hooks(
         return (world, res);
     }
 
+    let emit_project = res
+        .get::<BuildContext>()
+        .map_or(false, |ctx| ctx.emit_project.is_some());
+
+    if emit_project {
+        log::debug!("Emitting the generated project");
+        compile::emit_project_phase().run(&mut world, &mut res);
+
+        if hooks.after_emit_project(&mut c(&mut world, &mut res))
+            != Continuation::Continue
+        {
+            return (world, res);
+        }
+    }
+
     compile::phase().run(&mut world, &mut res);
 
     if hooks.after_compile(&mut c(&mut world, &mut res))
@@ -0,0 +1,140 @@
+//! Using prebuilt copies of proc-blocks instead of compiling them from
+//! source.
+//!
+//! Before a Runefile can be type checked, every proc-block needs to be
+//! compiled to WebAssembly so its
+//! [`hotg_rune_proc_blocks::ProcBlockDescriptor`] can be read. For the builtin
+//! proc-blocks this is the same work on every machine, so a release can publish
+//! the compiled `*.wasm` files and users can fetch those instead.
+//!
+//! Every prebuilt proc-block is pinned to the SHA-256 hash of its contents
+//! (e.g. `https://example.com/fft.wasm#sha256=e3b0c442...`), and anything which
+//! can't be downloaded or doesn't match its hash falls back to being compiled
+//! from source.
+//!
+//! The Rune itself is always compiled from source, because a prebuilt `rlib`
+//! can only be linked against the exact dependency graph and compiler it was
+//! built with.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    path::Path as FilePath,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{download::RemoteFile, parse::Path};
+
+/// Where to find the prebuilt WebAssembly for each proc-block, keyed by the
+/// proc-block's path (e.g. `hotg-ai/proc-blocks@v0.11.3#fft`).
+///
+/// This can be loaded from a JSON file mapping paths to URLs.
+///
+/// ```json
+/// {
+///   "hotg-ai/proc-blocks@v0.11.3#fft": "https://example.com/v0.11.3/fft.wasm#sha256=..."
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PrebuiltProcBlocks {
+    urls: BTreeMap<String, String>,
+}
+
+impl PrebuiltProcBlocks {
+    pub fn new() -> Self { PrebuiltProcBlocks::default() }
+
+    /// Read the pinned URLs from a JSON file.
+    pub fn load(path: &FilePath) -> Result<Self, PrebuiltError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| PrebuiltError(format!("{}: {}", path.display(), e)))?;
+        let prebuilt: PrebuiltProcBlocks = serde_json::from_str(&json)
+            .map_err(|e| PrebuiltError(format!("{}: {}", path.display(), e)))?;
+
+        for url in prebuilt.urls.values() {
+            prebuilt_file(url)?;
+        }
+
+        Ok(prebuilt)
+    }
+
+    /// Use a prebuilt copy of the proc-block at `path`. The `url` must use
+    /// HTTPS and contain a `#sha256=...` fragment.
+    pub fn insert(
+        &mut self,
+        path: &Path,
+        url: impl Into<String>,
+    ) -> Result<&mut Self, PrebuiltError> {
+        let url = url.into();
+        prebuilt_file(&url)?;
+        self.urls.insert(path.to_string(), url);
+
+        Ok(self)
+    }
+
+    pub(crate) fn get(&self, path: &Path) -> Option<RemoteFile<'_>> {
+        let url = self.urls.get(&path.to_string())?;
+        prebuilt_file(url).ok()
+    }
+}
+
+fn prebuilt_file(url: &str) -> Result<RemoteFile<'_>, PrebuiltError> {
+    match RemoteFile::parse(url) {
+        Some(Ok(file)) => Ok(file),
+        Some(Err(e)) => Err(PrebuiltError(e.to_string())),
+        None => Err(PrebuiltError(format!("\"{}\" isn't a URL", url))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrebuiltError(String);
+
+impl Display for PrebuiltError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid prebuilt proc-block: {}", self.0)
+    }
+}
+
+impl std::error::Error for PrebuiltError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str =
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn prebuilt_proc_blocks_must_be_pinned() {
+        let fft: Path = "hotg-ai/proc-blocks@v0.11.3#fft".parse().unwrap();
+        let label: Path = "hotg-ai/proc-blocks@v0.11.3#label".parse().unwrap();
+        let url = format!("https://example.com/fft.wasm#sha256={}", HASH);
+        let mut prebuilt = PrebuiltProcBlocks::new();
+
+        prebuilt.insert(&fft, &url).unwrap();
+
+        let file = prebuilt.get(&fft).unwrap();
+        assert_eq!(file.url, "https://example.com/fft.wasm");
+        assert_eq!(file.sha256, HASH);
+        assert!(prebuilt.get(&label).is_none());
+        assert!(prebuilt
+            .insert(&label, "https://example.com/label.wasm")
+            .is_err());
+    }
+
+    #[test]
+    fn load_from_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prebuilt.json");
+        let url = format!("https://example.com/fft.wasm#sha256={}", HASH);
+        let json =
+            serde_json::json!({ "hotg-ai/proc-blocks@v0.11.3#fft": url });
+        std::fs::write(&path, json.to_string()).unwrap();
+
+        let prebuilt = PrebuiltProcBlocks::load(&path).unwrap();
+
+        let fft = "hotg-ai/proc-blocks@v0.11.3#fft".parse().unwrap();
+        assert!(prebuilt.get(&fft).is_some());
+    }
+}
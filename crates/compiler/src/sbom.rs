@@ -0,0 +1,433 @@
+//! Generate a *Software Bill of Materials* listing every external artifact a
+//! Rune pulls in.
+//!
+//! The [`BillOfMaterials`] follows the [CycloneDX 1.4][spec] JSON format so it
+//! can be fed into existing supply-chain tooling.
+//!
+//! [spec]: https://cyclonedx.org/docs/1.4/json/
+
+use std::collections::BTreeMap;
+
+use legion::{IntoQuery, World};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    lowering::{
+        Model, ModelData, ModelFile, ModelVariantData, Name, ProcBlock,
+        Resource, ResourceData, ResourceSource,
+    },
+    parse::Path,
+};
+
+const BOM_REF_RUNE: &str = "rune";
+
+/// A CycloneDX document describing a Rune's dependencies.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BillOfMaterials {
+    pub bom_format: String,
+    pub spec_version: String,
+    pub version: u32,
+    pub metadata: Metadata,
+    pub components: Vec<Component>,
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Metadata {
+    pub tools: Vec<Tool>,
+    /// The Rune itself.
+    pub component: Component,
+}
+
+/// The tool which generated the [`BillOfMaterials`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Tool {
+    pub vendor: String,
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Component {
+    #[serde(rename = "type")]
+    pub kind: ComponentType,
+    #[serde(rename = "bom-ref")]
+    pub bom_ref: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// A [Package URL](https://github.com/package-url/purl-spec) for
+    /// dependencies that come from a package registry or repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purl: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hashes: Vec<Hash>,
+    #[serde(
+        default,
+        rename = "externalReferences",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub external_references: Vec<ExternalReference>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Property>,
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ComponentType {
+    Application,
+    Library,
+    File,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Hash {
+    pub alg: String,
+    pub content: String,
+}
+
+impl Hash {
+    fn sha256(data: &[u8]) -> Self {
+        Hash {
+            alg: String::from("SHA-256"),
+            content: format!("{:x}", Sha256::digest(data)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ExternalReference {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: String,
+}
+
+/// Rune-specific information attached to a [`Component`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+}
+
+impl Property {
+    fn new(name: &str, value: impl Into<String>) -> Self {
+        Property {
+            name: format!("hotg:{}", name),
+            value: value.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Dependency {
+    #[serde(rename = "ref")]
+    pub reference: String,
+    #[serde(rename = "dependsOn")]
+    pub depends_on: Vec<String>,
+}
+
+/// Find all the proc-blocks, models, and resources used by the Rune.
+///
+/// This needs to be run after [`crate::lowering`] so model and resource
+/// files have been loaded and can be hashed.
+pub fn bill_of_materials(world: &World, rune_name: &str) -> BillOfMaterials {
+    let mut components = Vec::new();
+    components.extend(proc_blocks(world));
+    components.extend(models(world));
+    components.extend(resources(world));
+    components.sort_by(|a, b| a.bom_ref.cmp(&b.bom_ref));
+
+    let rune = Component {
+        kind: ComponentType::Application,
+        bom_ref: BOM_REF_RUNE.to_string(),
+        name: rune_name.to_string(),
+        version: None,
+        purl: None,
+        hashes: Vec::new(),
+        external_references: Vec::new(),
+        properties: Vec::new(),
+    };
+
+    let dependencies = vec![Dependency {
+        reference: BOM_REF_RUNE.to_string(),
+        depends_on: components.iter().map(|c| c.bom_ref.clone()).collect(),
+    }];
+
+    BillOfMaterials {
+        bom_format: String::from("CycloneDX"),
+        spec_version: String::from("1.4"),
+        version: 1,
+        metadata: Metadata {
+            tools: vec![Tool {
+                vendor: String::from("hotg.ai"),
+                name: String::from("rune"),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            }],
+            component: rune,
+        },
+        components,
+        dependencies,
+    }
+}
+
+fn proc_blocks(world: &World) -> Vec<Component> {
+    // The same proc-block may be used by several stages
+    let mut stages: BTreeMap<String, (&ProcBlock, Vec<&str>)> = BTreeMap::new();
+
+    for (name, proc_block) in <(&Name, &ProcBlock)>::query().iter(world) {
+        stages
+            .entry(proc_block.path.to_string())
+            .or_insert_with(|| (proc_block, Vec::new()))
+            .1
+            .push(name.as_str());
+    }
+
+    stages
+        .into_iter()
+        .map(|(path, (proc_block, mut stages))| {
+            stages.sort_unstable();
+
+            let mut component = Component {
+                kind: ComponentType::Library,
+                bom_ref: format!("proc-block:{}", path),
+                name: proc_block.name().to_string(),
+                version: proc_block.path.version.clone(),
+                purl: purl(&proc_block.path),
+                hashes: Vec::new(),
+                external_references: Vec::new(),
+                properties: vec![Property::new("stages", stages.join(","))],
+            };
+
+            if let Some(archive) = proc_block.path.remote_archive() {
+                component.hashes.push(Hash {
+                    alg: String::from("SHA-256"),
+                    content: archive.sha256.to_ascii_lowercase(),
+                });
+                component.external_references.push(ExternalReference {
+                    kind: String::from("distribution"),
+                    url: archive.url.to_string(),
+                });
+            } else if proc_block.path.base.starts_with('.') {
+                component
+                    .properties
+                    .push(Property::new("path", &proc_block.path.base));
+            } else if !is_from_crates_io(&proc_block.path) {
+                component.external_references.push(ExternalReference {
+                    kind: String::from("vcs"),
+                    url: format!("https://github.com/{}", proc_block.path.base),
+                });
+            }
+
+            component
+        })
+        .collect()
+}
+
+/// Figure out the Package URL for a proc-block, using the same rules as
+/// `generate_cargo_toml` for deciding where it comes from.
+fn purl(path: &Path) -> Option<String> {
+    let Path {
+        base,
+        sub_path,
+        version,
+    } = path;
+
+    if base.starts_with('.') || path.remote_archive().is_some() {
+        // Local and downloaded proc-blocks don't have a package URL
+        return None;
+    }
+
+    let mut purl = if is_from_crates_io(path) {
+        format!("pkg:cargo/{}", base)
+    } else {
+        format!("pkg:github/{}", base)
+    };
+
+    if let Some(version) = version {
+        purl.push('@');
+        purl.push_str(version);
+    }
+    if let Some(sub_path) = sub_path {
+        purl.push('#');
+        purl.push_str(sub_path);
+    }
+
+    Some(purl)
+}
+
+fn is_from_crates_io(path: &Path) -> bool {
+    path.sub_path.is_none() && !path.base.contains('/')
+}
+
+fn models(world: &World) -> Vec<Component> {
+    let mut components = Vec::new();
+    let mut query = <(
+        &Name,
+        &Model,
+        Option<&ModelData>,
+        Option<&ModelVariantData>,
+    )>::query();
+
+    for (name, model, data, variants) in query.iter(world) {
+        // Models loaded from a resource are listed with the other resources
+        if let ModelFile::FromDisk(path) = &model.model_file {
+            components.push(file(
+                format!("model:{}", name),
+                path.display().to_string(),
+                data.map(|d| &d[..]),
+                vec![Property::new("stage", name.as_str())],
+            ));
+        }
+
+        for (variant, path) in &model.variants {
+            let data = variants.and_then(|v| v.get(variant)).map(|d| &d[..]);
+
+            components.push(file(
+                format!("model:{}:{}", name, variant),
+                path.display().to_string(),
+                data,
+                vec![
+                    Property::new("stage", name.as_str()),
+                    Property::new("variant", variant),
+                ],
+            ));
+        }
+    }
+
+    components
+}
+
+fn resources(world: &World) -> Vec<Component> {
+    let mut query = <(&Name, &Resource, Option<&ResourceData>)>::query();
+
+    query
+        .iter(world)
+        .map(|(name, resource, data)| {
+            let (file_name, source) = match &resource.default_value {
+                Some(ResourceSource::FromDisk(path)) => {
+                    (path.display().to_string(), "file")
+                },
+                Some(ResourceSource::Inline(_)) => (name.to_string(), "inline"),
+                // The value will be provided when the Rune is loaded
+                None => (name.to_string(), "runtime"),
+            };
+
+            file(
+                format!("resource:{}", name),
+                file_name,
+                data.map(|d| &d[..]),
+                vec![
+                    Property::new("resource", name.as_str()),
+                    Property::new("source", source),
+                ],
+            )
+        })
+        .collect()
+}
+
+fn file(
+    bom_ref: String,
+    name: String,
+    data: Option<&[u8]>,
+    properties: Vec<Property>,
+) -> Component {
+    Component {
+        kind: ComponentType::File,
+        bom_ref,
+        name,
+        version: None,
+        purl: None,
+        hashes: data.map(Hash::sha256).into_iter().collect(),
+        external_references: Vec::new(),
+        properties,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::parse::ResourceType;
+
+    #[test]
+    fn package_urls() {
+        let inputs = vec![
+            (
+                Path::new("fft", None, Some("1.2".to_string())),
+                Some("pkg:cargo/fft@1.2"),
+            ),
+            (
+                Path::new(
+                    "hotg-ai/proc-blocks",
+                    Some("normalize".to_string()),
+                    Some("v0.11.3".to_string()),
+                ),
+                Some("pkg:github/hotg-ai/proc-blocks@v0.11.3#normalize"),
+            ),
+            (Path::new("./my-proc-block", None, None), None),
+        ];
+
+        for (path, should_be) in inputs {
+            assert_eq!(purl(&path).as_deref(), should_be, "{}", path);
+        }
+    }
+
+    #[test]
+    fn list_every_external_artifact() {
+        let mut world = World::default();
+        let proc_block = ProcBlock {
+            path: Path::new(
+                "hotg-ai/proc-blocks",
+                Some("normalize".to_string()),
+                None,
+            ),
+            parameters: IndexMap::new(),
+        };
+        world.push((Name::from("first"), proc_block.clone()));
+        world.push((Name::from("second"), proc_block));
+        world.push((
+            Name::from("model"),
+            Model {
+                model_file: ModelFile::FromDisk(PathBuf::from("model.tflite")),
+                args: IndexMap::new(),
+                variants: IndexMap::new(),
+            },
+            ModelData::from(b"model".to_vec()),
+        ));
+        world.push((
+            Name::from("labels"),
+            Resource {
+                default_value: Some(ResourceSource::FromDisk(PathBuf::from(
+                    "labels.txt",
+                ))),
+                ty: ResourceType::String,
+            },
+            ResourceData::from(b"up\ndown".to_vec()),
+        ));
+
+        let bom = bill_of_materials(&world, "my-rune");
+
+        let refs: Vec<_> =
+            bom.components.iter().map(|c| c.bom_ref.as_str()).collect();
+        assert_eq!(
+            refs,
+            [
+                "model:model",
+                "proc-block:hotg-ai/proc-blocks#normalize",
+                "resource:labels",
+            ]
+        );
+        assert_eq!(bom.components[1].properties[0].value, "first,second");
+        assert_eq!(
+            bom.components[0].hashes[0].content,
+            format!("{:x}", Sha256::digest(b"model"))
+        );
+        assert_eq!(bom.dependencies[0].depends_on, refs);
+    }
+}
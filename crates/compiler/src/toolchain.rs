@@ -11,6 +11,25 @@ pub fn rust_toolchain() -> Value {
     }
 }
 
+/// The `rust-toolchain.toml` needed to compile for a particular target.
+///
+/// There are no pre-compiled standard libraries for `wasm64`, so this adds
+/// the `rust-src` component needed to build it from source.
+pub fn rust_toolchain_for_target(target: &str) -> Value {
+    let mut rust_toolchain = rust_toolchain();
+
+    if target.starts_with("wasm64") {
+        if let Some(Value::Array(components)) = rust_toolchain
+            .get_mut("toolchain")
+            .and_then(|t| t.get_mut("components"))
+        {
+            components.push(Value::String("rust-src".to_string()));
+        }
+    }
+
+    rust_toolchain
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,81 @@
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::ElementType;
+use legion::{world::SubWorld, Entity, Query};
+
+use crate::{
+    lowering::{Inputs, Name, Outputs, Tensor},
+    Diagnostics,
+};
+
+/// Emit a warning when `f64` tensors are passed to a stage that only produces
+/// `f32` tensors, because the conversion will probably lose precision.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] diags: &mut Diagnostics,
+    stages: &mut Query<(&Name, &Span, &Inputs, &Outputs)>,
+    tensors: &mut Query<&Tensor>,
+) {
+    let mut element_types = |ents: &[Entity]| -> Vec<ElementType> {
+        ents.iter()
+            .filter_map(|&ent| tensors.get(world, ent).ok())
+            .map(|Tensor(shape)| shape.element_type())
+            .collect()
+    };
+
+    for (name, &span, inputs, outputs) in stages.iter(world) {
+        let inputs = element_types(&inputs.tensors);
+        let outputs = element_types(&outputs.tensors);
+
+        if is_narrowing(&inputs, &outputs) {
+            diags.push(narrowing_diagnostic(name, span));
+        }
+    }
+}
+
+fn is_narrowing(inputs: &[ElementType], outputs: &[ElementType]) -> bool {
+    inputs.contains(&ElementType::F64)
+        && outputs.contains(&ElementType::F32)
+        && !outputs.contains(&ElementType::F64)
+}
+
+fn narrowing_diagnostic(name: &Name, span: Span) -> Diagnostic<()> {
+    Diagnostic::warning()
+        .with_message(format!(
+            "The \"{}\" stage converts f64 inputs to f32, which may lose \
+             precision",
+            name
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_narrowing_conversions() {
+        let inputs = vec![
+            (vec![ElementType::F64], vec![ElementType::F32], true),
+            (
+                vec![ElementType::F64, ElementType::U8],
+                vec![ElementType::F32, ElementType::U8],
+                true,
+            ),
+            (vec![ElementType::F64], vec![ElementType::F64], false),
+            (
+                vec![ElementType::F64],
+                vec![ElementType::F32, ElementType::F64],
+                false,
+            ),
+            (vec![ElementType::F32], vec![ElementType::F32], false),
+            (vec![ElementType::F64], vec![ElementType::I16], false),
+        ];
+
+        for (inputs, outputs, should_be) in inputs {
+            let got = is_narrowing(&inputs, &outputs);
+            assert_eq!(got, should_be, "{:?} => {:?}", inputs, outputs);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use legion::{world::SubWorld, Query};
+
+use crate::{
+    lowering::{embedded_data_size, ModelData, ModelVariantData, ResourceData},
+    parse::{ByteSize, DocumentV1, MemoryConfig},
+    BuildContext, Diagnostics,
+};
+
+/// Warn when the Rune's embedded models and resources, plus the memory
+/// declared in its `memory` section, won't fit on the device being targeted.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] doc: &DocumentV1,
+    #[resource] ctx: &BuildContext,
+    #[resource] diags: &mut Diagnostics,
+    models: &mut Query<&ModelData>,
+    variants: &mut Query<&ModelVariantData>,
+    resources: &mut Query<&ResourceData>,
+) {
+    let device_memory = match ctx.device_memory {
+        Some(bytes) => bytes,
+        None => return,
+    };
+
+    let embedded = embedded_data_size(
+        models.iter(world),
+        variants.iter(world),
+        resources.iter(world),
+    );
+    let memory = doc.memory.clone().unwrap_or_default();
+
+    if let Some(diag) = check_budget(&memory, embedded, device_memory) {
+        diags.push(diag);
+    }
+}
+
+fn check_budget(
+    memory: &MemoryConfig,
+    embedded: u64,
+    device_memory: ByteSize,
+) -> Option<Diagnostic<()>> {
+    let required = memory.peak_usage(embedded);
+
+    if required <= device_memory.0 {
+        return None;
+    }
+
+    let heap = memory.heap.unwrap_or(ByteSize(0));
+    let stack = ByteSize(memory.stack_size());
+
+    let diag = Diagnostic::warning()
+        .with_message(format!(
+            "The Rune needs about {} bytes of memory, but the device only has \
+             {}",
+            required, device_memory,
+        ))
+        .with_labels(vec![Label::primary((), memory.span())])
+        .with_notes(vec![format!(
+            "{} bytes of models and resources, a {} heap, and a {} stack",
+            embedded, heap, stack,
+        )]);
+
+    Some(diag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warn_when_models_and_heap_dont_fit() {
+        let memory = MemoryConfig {
+            heap: Some(ByteSize(3 << 20)),
+            stack: Some(ByteSize(64 << 10)),
+        };
+
+        let diag = check_budget(&memory, 2 << 20, ByteSize(4 << 20)).unwrap();
+
+        assert_eq!(
+            diag.message,
+            "The Rune needs about 5308416 bytes of memory, but the device \
+             only has 4MB"
+        );
+        assert_eq!(
+            diag.notes,
+            vec!["2097152 bytes of models and resources, a 3MB heap, and a \
+                  64KB stack"
+                .to_string()]
+        );
+        assert!(check_budget(&memory, 0, ByteSize(4 << 20)).is_none());
+    }
+}
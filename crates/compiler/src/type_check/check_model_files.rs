@@ -0,0 +1,437 @@
+//! Compare the tensors a Runefile declares for each TensorFlow Lite model
+//! with the tensors the model actually uses.
+//!
+//! This only needs a tiny subset of the [TensorFlow Lite schema][schema], so
+//! instead of pulling in a full [FlatBuffers][flatbuffers] implementation we
+//! read the handful of fields we care about directly. Field numbers come from
+//! the order fields are declared in `schema.fbs`.
+//!
+//! [flatbuffers]: https://google.github.io/flatbuffers/flatbuffers_internals.html
+//! [schema]: https://github.com/tensorflow/tensorflow/blob/master/tensorflow/lite/schema/schema.fbs
+
+use std::{
+    convert::TryInto,
+    fmt::{self, Display, Formatter},
+};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::ElementType;
+use legion::{world::SubWorld, Entity, Query};
+
+use crate::{
+    lowering::{Inputs, Mimetype, ModelData, Name, Outputs, Tensor},
+    Diagnostics,
+};
+
+/// Emit an error when a model stage's `inputs` or `outputs` don't match the
+/// tensors in its TensorFlow Lite model.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] diags: &mut Diagnostics,
+    models: &mut Query<(
+        &Name,
+        &Span,
+        &Mimetype,
+        &ModelData,
+        &Inputs,
+        &Outputs,
+    )>,
+    tensors: &mut Query<&Tensor>,
+) {
+    for (name, &span, mimetype, data, inputs, outputs) in models.iter(world) {
+        if *mimetype != Mimetype::TENSORFLOW_LITE {
+            continue;
+        }
+
+        let signature = match Signature::parse(data) {
+            Some(s) => s,
+            None => {
+                diags.push(unreadable_model_diagnostic(name, span));
+                continue;
+            },
+        };
+
+        let mut declared = |ents: &[Entity]| -> Option<Vec<Tensor>> {
+            ents.iter()
+                .map(|&ent| tensors.get(world, ent).ok().cloned())
+                .collect()
+        };
+
+        // Tensors without a declared shape will have it inferred later, so
+        // there's nothing to compare against.
+        if let Some(declared) = declared(&inputs.tensors) {
+            diags.extend(check_tensors(
+                name,
+                span,
+                Direction::Input,
+                &declared,
+                &signature.inputs,
+            ));
+        }
+        if let Some(declared) = declared(&outputs.tensors) {
+            diags.extend(check_tensors(
+                name,
+                span,
+                Direction::Output,
+                &declared,
+                &signature.outputs,
+            ));
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Direction {
+    Input,
+    Output,
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Input => write!(f, "input"),
+            Direction::Output => write!(f, "output"),
+        }
+    }
+}
+
+fn check_tensors(
+    name: &Name,
+    span: Span,
+    direction: Direction,
+    declared: &[Tensor],
+    actual: &[TensorInfo],
+) -> Vec<Diagnostic<()>> {
+    if declared.len() != actual.len() {
+        return vec![tensor_count_diagnostic(
+            name,
+            span,
+            direction,
+            declared.len(),
+            actual.len(),
+        )];
+    }
+
+    declared
+        .iter()
+        .zip(actual)
+        .enumerate()
+        .filter(|(_, (Tensor(shape), info))| {
+            !info.is_compatible(shape.element_type(), shape.dimensions())
+        })
+        .map(|(i, (Tensor(shape), info))| {
+            Diagnostic::error()
+                .with_message(format!(
+                    "The \"{}\" model's {} {} is {}, but the Runefile \
+                     declares {}",
+                    name, direction, i, info, shape
+                ))
+                .with_labels(vec![Label::primary((), span)])
+        })
+        .collect()
+}
+
+fn tensor_count_diagnostic(
+    name: &Name,
+    span: Span,
+    direction: Direction,
+    declared: usize,
+    actual: usize,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" model has {} {}s, but the Runefile declares {}",
+            name, actual, direction, declared
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn unreadable_model_diagnostic(name: &Name, span: Span) -> Diagnostic<()> {
+    Diagnostic::warning()
+        .with_message(format!(
+            "Unable to read the \"{}\" model, so its inputs and outputs can't \
+             be checked",
+            name
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+/// The inputs and outputs of a TensorFlow Lite model's main subgraph.
+#[derive(Debug, Clone, PartialEq)]
+struct Signature {
+    inputs: Vec<TensorInfo>,
+    outputs: Vec<TensorInfo>,
+}
+
+impl Signature {
+    fn parse(buffer: &[u8]) -> Option<Signature> {
+        if buffer.get(4..8) != Some(b"TFL3") {
+            return None;
+        }
+
+        // Model.subgraphs
+        let model = Table::root(buffer)?;
+        let subgraph = *model.tables(2)?.first()?;
+
+        // SubGraph.tensors, SubGraph.inputs, and SubGraph.outputs
+        let tensors = subgraph.tables(0)?;
+        let lookup = |indices: Vec<i32>| -> Option<Vec<TensorInfo>> {
+            indices
+                .into_iter()
+                .map(|ix| {
+                    let ix: usize = ix.try_into().ok()?;
+                    TensorInfo::parse(tensors.get(ix)?)
+                })
+                .collect()
+        };
+
+        Some(Signature {
+            inputs: lookup(subgraph.scalars(1)?)?,
+            outputs: lookup(subgraph.scalars(2)?)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TensorInfo {
+    /// `None` when the tensor uses a type Rune doesn't support (e.g.
+    /// `COMPLEX64`).
+    element_type: Option<ElementType>,
+    /// The tensor's dimensions, where negative numbers are dynamic.
+    dimensions: Vec<i32>,
+}
+
+impl TensorInfo {
+    fn parse(tensor: &Table<'_>) -> Option<TensorInfo> {
+        // Tensor.shape_signature includes dynamic dimensions, while
+        // Tensor.shape only contains placeholder values.
+        let mut dimensions = tensor.scalars(5)?;
+        if dimensions.is_empty() {
+            dimensions = tensor.scalars(0)?;
+        }
+
+        Some(TensorInfo {
+            element_type: element_type(tensor.scalar(1)?.unwrap_or(0)),
+            dimensions,
+        })
+    }
+
+    fn is_compatible(
+        &self,
+        element_type: ElementType,
+        dimensions: &[usize],
+    ) -> bool {
+        let types_match = self.element_type.map_or(true, |e| e == element_type);
+
+        let dimensions_match = self.dimensions.len() == dimensions.len()
+            && self.dimensions.iter().zip(dimensions).all(
+                |(&actual, &declared)| {
+                    actual < 0 || actual as usize == declared
+                },
+            );
+
+        types_match && dimensions_match
+    }
+}
+
+impl Display for TensorInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.element_type {
+            Some(e) => write!(f, "{}", e)?,
+            None => write!(f, "?")?,
+        }
+
+        write!(f, "[")?;
+        for (i, dim) in self.dimensions.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            if *dim < 0 {
+                write!(f, "_")?;
+            } else {
+                write!(f, "{}", dim)?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// Convert the schema's `TensorType` enum to an [`ElementType`].
+fn element_type(tensor_type: i8) -> Option<ElementType> {
+    match tensor_type {
+        0 => Some(ElementType::F32),
+        1 => Some(ElementType::F16),
+        2 => Some(ElementType::I32),
+        3 => Some(ElementType::U8),
+        4 => Some(ElementType::I64),
+        5 => Some(ElementType::String),
+        7 => Some(ElementType::I16),
+        9 => Some(ElementType::I8),
+        10 => Some(ElementType::F64),
+        12 => Some(ElementType::U64),
+        15 => Some(ElementType::U32),
+        16 => Some(ElementType::U16),
+        18 => Some(ElementType::BF16),
+        _ => None,
+    }
+}
+
+/// A table somewhere inside a flatbuffer.
+#[derive(Debug, Copy, Clone)]
+struct Table<'a> {
+    buffer: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Table<'a> {
+    fn root(buffer: &'a [u8]) -> Option<Self> {
+        let position = read_u32(buffer, 0)? as usize;
+        Some(Table { buffer, position })
+    }
+
+    /// Find where a field is stored, returning `Some(None)` if it was left
+    /// out (e.g. because it was set to its default value).
+    fn field_position(&self, field: usize) -> Option<Option<usize>> {
+        let vtable_offset = read_u32(self.buffer, self.position)? as i32;
+        let vtable: usize = (self.position as i64 - i64::from(vtable_offset))
+            .try_into()
+            .ok()?;
+
+        let vtable_length = usize::from(read_u16(self.buffer, vtable)?);
+        let entry = 4 + 2 * field;
+
+        if entry + 2 > vtable_length {
+            return Some(None);
+        }
+
+        match read_u16(self.buffer, vtable + entry)? {
+            0 => Some(None),
+            offset => Some(Some(self.position + usize::from(offset))),
+        }
+    }
+
+    fn scalar(&self, field: usize) -> Option<Option<i8>> {
+        match self.field_position(field)? {
+            Some(position) => Some(Some(*self.buffer.get(position)? as i8)),
+            None => Some(None),
+        }
+    }
+
+    /// Get the start and length of a vector, treating a missing vector as
+    /// empty.
+    fn vector(&self, field: usize) -> Option<(usize, usize)> {
+        match self.field_position(field)? {
+            Some(position) => {
+                let start =
+                    position + read_u32(self.buffer, position)? as usize;
+                let len = read_u32(self.buffer, start)? as usize;
+                Some((start + 4, len))
+            },
+            None => Some((0, 0)),
+        }
+    }
+
+    fn scalars(&self, field: usize) -> Option<Vec<i32>> {
+        let (start, len) = self.vector(field)?;
+
+        (0..len)
+            .map(|i| read_u32(self.buffer, start + i * 4).map(|n| n as i32))
+            .collect()
+    }
+
+    fn tables(&self, field: usize) -> Option<Vec<Table<'a>>> {
+        let (start, len) = self.vector(field)?;
+
+        (0..len)
+            .map(|i| {
+                let element = start + i * 4;
+                let offset = read_u32(self.buffer, element)? as usize;
+                Some(Table {
+                    buffer: self.buffer,
+                    position: element + offset,
+                })
+            })
+            .collect()
+    }
+}
+
+fn read_u32(buffer: &[u8], position: usize) -> Option<u32> {
+    let bytes = buffer.get(position..position + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u16(buffer: &[u8], position: usize) -> Option<u16> {
+    let bytes = buffer.get(position..position + 2)?;
+    Some(u16::from_le_bytes(bytes.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use hotg_rune_core::Shape;
+
+    use super::*;
+
+    const SINE_MODEL: &[u8] =
+        include_bytes!("../../../../examples/sine/sinemodel.tflite");
+
+    #[test]
+    fn read_the_sine_model() {
+        let signature = Signature::parse(SINE_MODEL).unwrap();
+
+        let f32_1x1 = TensorInfo {
+            element_type: Some(ElementType::F32),
+            dimensions: vec![1, 1],
+        };
+        assert_eq!(signature.inputs, vec![f32_1x1.clone()]);
+        assert_eq!(signature.outputs, vec![f32_1x1]);
+        assert!(Signature::parse(b"not a model").is_none());
+    }
+
+    #[test]
+    fn mismatched_tensors_are_errors() {
+        let name = Name::from("sine");
+        let actual = vec![TensorInfo {
+            element_type: Some(ElementType::F32),
+            dimensions: vec![-1, 28, 28],
+        }];
+        let tensor = |e, dims: &[usize]| Tensor(Shape::new(e, dims.to_vec()));
+
+        let ok = [tensor(ElementType::F32, &[4, 28, 28])];
+        assert!(check_tensors(
+            &name,
+            Span::default(),
+            Direction::Input,
+            &ok,
+            &actual
+        )
+        .is_empty());
+
+        let wrong_type = [tensor(ElementType::U8, &[1, 28, 28])];
+        let diags = check_tensors(
+            &name,
+            Span::default(),
+            Direction::Input,
+            &wrong_type,
+            &actual,
+        );
+        assert_eq!(
+            diags[0].message,
+            "The \"sine\" model's input 0 is f32[_, 28, 28], but the Runefile \
+             declares u8[1, 28, 28]"
+        );
+
+        let diags = check_tensors(
+            &name,
+            Span::default(),
+            Direction::Output,
+            &[],
+            &actual,
+        );
+        assert_eq!(
+            diags[0].message,
+            "The \"sine\" model has 1 outputs, but the Runefile declares 0"
+        );
+    }
+}
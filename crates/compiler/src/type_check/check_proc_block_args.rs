@@ -0,0 +1,273 @@
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_proc_blocks::ParameterDescriptor;
+use legion::{world::SubWorld, Query};
+
+use crate::{
+    lowering::{Name, ProcBlock, ProcBlockDescriptors, ResourceOrString},
+    Diagnostics,
+};
+
+/// Check each proc block's arguments against the parameters declared in its
+/// [`hotg_rune_proc_blocks::ProcBlockDescriptor`], when one is available.
+///
+/// Without this, a typo in an argument's name is only caught when the
+/// generated crate fails to compile because the setter doesn't exist.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] descriptors: &ProcBlockDescriptors,
+    #[resource] diags: &mut Diagnostics,
+    proc_blocks: &mut Query<(&Name, &Span, &ProcBlock)>,
+) {
+    for (name, &span, proc_block) in proc_blocks.iter(world) {
+        let descriptor = match descriptors.get(&proc_block.path) {
+            Some(d) => d,
+            None => continue,
+        };
+
+        diags.extend(check_arguments(
+            name,
+            span,
+            proc_block,
+            &descriptor.parameters,
+        ));
+    }
+}
+
+fn check_arguments(
+    name: &Name,
+    span: Span,
+    proc_block: &ProcBlock,
+    parameters: &[ParameterDescriptor<'_>],
+) -> Vec<Diagnostic<()>> {
+    let mut diags = Vec::new();
+
+    for (key, value) in &proc_block.parameters {
+        let parameter = parameters
+            .iter()
+            .find(|p| normalize(&p.name) == normalize(key));
+
+        match (parameter, value) {
+            (None, _) => {
+                diags.push(unknown_argument_diagnostic(
+                    name, span, key, parameters,
+                ));
+            },
+            (Some(p), ResourceOrString::String(value)) => {
+                if !is_valid_literal(&p.type_name, value) {
+                    diags.push(invalid_argument_diagnostic(
+                        name,
+                        span,
+                        key,
+                        value,
+                        &p.type_name,
+                    ));
+                }
+            },
+            // Resources are only known at runtime
+            (Some(_), ResourceOrString::Resource(_)) => {},
+        }
+    }
+
+    diags
+}
+
+/// The generated code calls `set_xxx()` for an argument called `xxx` with
+/// any `-`'s replaced by `_`'s.
+fn normalize(name: &str) -> String { name.replace('-', "_") }
+
+/// Can `value` be parsed as a `type_name`?
+///
+/// Only primitives are checked, because more complicated types (e.g. lists or
+/// enums) are parsed by the proc block itself.
+fn is_valid_literal(type_name: &str, value: &str) -> bool {
+    let value = value.trim();
+
+    match type_name {
+        "u8" => value.parse::<u8>().is_ok(),
+        "i8" => value.parse::<i8>().is_ok(),
+        "u16" => value.parse::<u16>().is_ok(),
+        "i16" => value.parse::<i16>().is_ok(),
+        "u32" => value.parse::<u32>().is_ok(),
+        "i32" => value.parse::<i32>().is_ok(),
+        "u64" => value.parse::<u64>().is_ok(),
+        "i64" => value.parse::<i64>().is_ok(),
+        "usize" => value.parse::<usize>().is_ok(),
+        "isize" => value.parse::<isize>().is_ok(),
+        "f32" | "f64" => value.parse::<f64>().is_ok(),
+        "bool" => value.parse::<bool>().is_ok(),
+        _ => true,
+    }
+}
+
+fn unknown_argument_diagnostic(
+    name: &Name,
+    span: Span,
+    key: &str,
+    parameters: &[ParameterDescriptor<'_>],
+) -> Diagnostic<()> {
+    let mut notes = Vec::new();
+
+    if let Some(suggestion) = closest_match(key, parameters) {
+        notes.push(format!("hint: did you mean \"{}\"?", suggestion));
+    }
+
+    if parameters.is_empty() {
+        notes.push("This proc block doesn't take any arguments".to_string());
+    } else {
+        let names: Vec<_> = parameters.iter().map(|p| &*p.name).collect();
+        notes.push(format!("The valid arguments are {}", names.join(", ")));
+    }
+
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" proc block has no \"{}\" argument",
+            name, key
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(notes)
+}
+
+fn invalid_argument_diagnostic(
+    name: &Name,
+    span: Span,
+    key: &str,
+    value: &str,
+    type_name: &str,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" argument for \"{}\" should be a {}, but \"{}\" isn't \
+             valid",
+            key, name, type_name, value
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+/// Find the parameter with the most similar name, as long as it is close
+/// enough to plausibly be a typo.
+fn closest_match<'a>(
+    key: &str,
+    parameters: &'a [ParameterDescriptor<'_>],
+) -> Option<&'a str> {
+    let key = normalize(key);
+
+    parameters
+        .iter()
+        .map(|p| (edit_distance(&key, &normalize(&p.name)), &*p.name))
+        .filter(|&(distance, name)| distance <= name.len().max(3) / 3)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, name)| name)
+}
+
+/// The Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            let insertion = current[j] + 1;
+            let deletion = previous[j + 1] + 1;
+            current.push(substitution.min(insertion).min(deletion));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    fn parameter(
+        name: &'static str,
+        type_name: &'static str,
+    ) -> ParameterDescriptor<'static> {
+        ParameterDescriptor {
+            name: Cow::Borrowed(name),
+            type_name: Cow::Borrowed(type_name),
+            description: Cow::Borrowed(""),
+            default_value: None,
+            range: None,
+        }
+    }
+
+    #[test]
+    fn detect_unknown_and_invalid_arguments() {
+        let parameters = vec![
+            parameter("ceiling", "f32"),
+            parameter("frame_size", "usize"),
+        ];
+        let proc_block = ProcBlock {
+            path: "normalize".parse().unwrap(),
+            parameters: map! {
+                cieling: "1.0".into(),
+                frame_size: "-1".into(),
+            },
+        };
+
+        let diags = check_arguments(
+            &Name::from("normalize"),
+            Span::default(),
+            &proc_block,
+            &parameters,
+        );
+
+        assert_eq!(diags.len(), 2);
+        assert_eq!(
+            diags[0].message,
+            "The \"normalize\" proc block has no \"cieling\" argument"
+        );
+        assert_eq!(
+            diags[0].notes,
+            vec![
+                "hint: did you mean \"ceiling\"?".to_string(),
+                "The valid arguments are ceiling, frame_size".to_string(),
+            ]
+        );
+        assert_eq!(
+            diags[1].message,
+            "The \"frame_size\" argument for \"normalize\" should be a usize, \
+             but \"-1\" isn't valid"
+        );
+    }
+
+    #[test]
+    fn hyphenated_arguments_match_their_setters() {
+        let parameters = vec![parameter("frame_size", "usize")];
+        let proc_block = ProcBlock {
+            path: "fft".parse().unwrap(),
+            parameters: std::iter::once((
+                "frame-size".to_string(),
+                "320".into(),
+            ))
+            .collect(),
+        };
+
+        let diags = check_arguments(
+            &Name::from("fft"),
+            Span::default(),
+            &proc_block,
+            &parameters,
+        );
+
+        assert!(diags.is_empty());
+    }
+
+    #[test]
+    fn suggestions_are_only_given_for_typos() {
+        let parameters = vec![parameter("ceiling", "f32")];
+
+        assert_eq!(closest_match("cieling", &parameters), Some("ceiling"));
+        assert_eq!(closest_match("labels", &parameters), None);
+    }
+}
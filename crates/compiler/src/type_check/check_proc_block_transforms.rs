@@ -0,0 +1,180 @@
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::Shape;
+use hotg_rune_proc_blocks::TransformDescriptor;
+use legion::{world::SubWorld, Entity, Query};
+
+use crate::{
+    lowering::{
+        Inputs, Name, Outputs, ProcBlock, ProcBlockDescriptors, Tensor,
+    },
+    Diagnostics,
+};
+
+/// Make sure one of the transforms in a proc block's
+/// [`hotg_rune_proc_blocks::ProcBlockDescriptor`] accepts the tensors it is
+/// given and returns the tensors the Runefile expects.
+///
+/// A stage with several inputs or outputs passes them to
+/// [`hotg_rune_proc_blocks::Transform::transform()`] as a tuple, so the number
+/// of tensors is checked as well as their shapes.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] descriptors: &ProcBlockDescriptors,
+    #[resource] diags: &mut Diagnostics,
+    proc_blocks: &mut Query<(
+        &Name,
+        &Span,
+        &ProcBlock,
+        Option<&Inputs>,
+        &Outputs,
+    )>,
+    tensors: &mut Query<&Tensor>,
+) {
+    for (name, &span, proc_block, inputs, outputs) in proc_blocks.iter(world) {
+        let transforms = match descriptors.get(&proc_block.path) {
+            Some(d) if !d.available_transforms.is_empty() => {
+                &d.available_transforms
+            },
+            _ => continue,
+        };
+
+        let mut shapes = |ents: &[Entity]| -> Option<Vec<Shape<'static>>> {
+            ents.iter()
+                .map(|&ent| tensors.get(world, ent).ok().map(|t| t.0.clone()))
+                .collect()
+        };
+
+        // Tensors whose shape couldn't be determined have already been
+        // reported elsewhere.
+        let inputs =
+            shapes(inputs.map(|i| i.tensors.as_slice()).unwrap_or(&[]));
+        let outputs = shapes(&outputs.tensors);
+
+        if let (Some(inputs), Some(outputs)) = (inputs, outputs) {
+            diags.extend(check_transforms(
+                name, span, transforms, &inputs, &outputs,
+            ));
+        }
+    }
+}
+
+fn check_transforms(
+    name: &Name,
+    span: Span,
+    transforms: &[TransformDescriptor<'_>],
+    inputs: &[Shape<'_>],
+    outputs: &[Shape<'_>],
+) -> Option<Diagnostic<()>> {
+    if transforms.iter().any(|t| t.is_compatible(inputs, outputs)) {
+        return None;
+    }
+
+    let mut notes = vec!["The available transforms are:".to_string()];
+    notes.extend(transforms.iter().map(|t| format!("  {}", t)));
+
+    let diag = Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" proc block has no transform from {} to {}",
+            name,
+            format_shapes(inputs),
+            format_shapes(outputs)
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(notes);
+
+    Some(diag)
+}
+
+/// Format shapes the same way as
+/// [`hotg_rune_proc_blocks::TensorDescriptors`].
+fn format_shapes(shapes: &[Shape<'_>]) -> String {
+    match shapes {
+        [shape] => shape.to_string(),
+        shapes => {
+            let shapes: Vec<_> = shapes.iter().map(|s| s.to_string()).collect();
+            format!("({})", shapes.join(", "))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hotg_rune_core::ElementType;
+    use hotg_rune_proc_blocks::{Dimension, Dimensions, TensorDescriptor};
+
+    use super::*;
+
+    fn transform(
+        inputs: Vec<TensorDescriptor<'static>>,
+        outputs: Vec<TensorDescriptor<'static>>,
+    ) -> TransformDescriptor<'static> {
+        TransformDescriptor {
+            inputs: inputs.into_iter().collect(),
+            outputs: outputs.into_iter().collect(),
+        }
+    }
+
+    fn image() -> TensorDescriptor<'static> {
+        TensorDescriptor {
+            element_type: ElementType::F32,
+            dimensions: Dimensions::Arbitrary,
+        }
+    }
+
+    fn mask() -> TensorDescriptor<'static> {
+        TensorDescriptor {
+            element_type: ElementType::U8,
+            dimensions: vec![Dimension::Any, Dimension::Value(3)].into(),
+        }
+    }
+
+    #[test]
+    fn multiple_inputs_and_outputs_are_matched_as_tuples() {
+        let transforms = vec![transform(vec![image(), mask()], vec![image()])];
+
+        let got = check_transforms(
+            &Name::from("fuse"),
+            Span::default(),
+            &transforms,
+            &[
+                "f32[1, 28, 28]".parse().unwrap(),
+                "u8[4, 3]".parse().unwrap(),
+            ],
+            &["f32[1, 28, 28]".parse().unwrap()],
+        );
+
+        assert!(got.is_none());
+    }
+
+    #[test]
+    fn mismatched_tensors_are_an_error() {
+        let transforms = vec![transform(vec![image(), mask()], vec![image()])];
+
+        let diag = check_transforms(
+            &Name::from("fuse"),
+            Span::default(),
+            &transforms,
+            &[
+                "f32[1, 28, 28]".parse().unwrap(),
+                "u8[4, 2]".parse().unwrap(),
+            ],
+            &["f32[1, 28, 28]".parse().unwrap()],
+        )
+        .unwrap();
+
+        assert_eq!(
+            diag.message,
+            "The \"fuse\" proc block has no transform from (f32[1, 28, 28], \
+             u8[4, 2]) to f32[1, 28, 28]"
+        );
+        assert_eq!(
+            diag.notes,
+            vec![
+                "The available transforms are:".to_string(),
+                "  (f32[..], u8[_, 3]) -> f32[..]".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::{HashMap, HashSet};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::Shape;
+use hotg_rune_proc_blocks::ShapeTransferError;
+use indexmap::IndexMap;
+use legion::{systems::CommandBuffer, world::SubWorld, Entity, Query};
+
+use crate::{
+    lowering::{
+        InferredShape, Inputs, Name, Outputs, ProcBlock, ResourceOrString,
+        Tensor,
+    },
+    Diagnostics,
+};
+
+/// Calculate the [`Tensor`] for each of a proc-block's outputs using the
+/// [`InferredShape`] from its descriptor.
+///
+/// Shapes are propagated through the pipeline until nothing else can be
+/// inferred, so a proc-block's inputs may also have been inferred.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] diags: &mut Diagnostics,
+    stages: &mut Query<(&Name, &Span, &ProcBlock, Option<&Inputs>, &Outputs)>,
+    tensors: &mut Query<&Tensor>,
+    inferred: &mut Query<&InferredShape>,
+) {
+    let mut known: HashMap<Entity, Shape<'static>> = HashMap::new();
+    let mut failed: HashSet<Entity> = HashSet::new();
+
+    loop {
+        let mut made_progress = false;
+
+        for (name, &span, proc_block, inputs, outputs) in stages.iter(world) {
+            let input_tensors =
+                inputs.map(|i| i.tensors.as_slice()).unwrap_or(&[]);
+
+            for (i, &tensor) in outputs.tensors.iter().enumerate() {
+                if known.contains_key(&tensor) || failed.contains(&tensor) {
+                    continue;
+                }
+
+                let InferredShape(transfer) = match inferred.get(world, tensor)
+                {
+                    Ok(inferred) => inferred,
+                    Err(_) => continue,
+                };
+
+                // Wait until all of our inputs are known
+                let input_shapes: Option<Vec<Shape<'static>>> = input_tensors
+                    .iter()
+                    .map(|ent| match tensors.get(world, *ent) {
+                        Ok(Tensor(shape)) => Some(shape.clone()),
+                        Err(_) => known.get(ent).cloned(),
+                    })
+                    .collect();
+                let input_shapes = match input_shapes {
+                    Some(s) => s,
+                    None => continue,
+                };
+
+                match transfer.evaluate(&input_shapes, |arg| {
+                    integer_argument(&proc_block.parameters, arg)
+                }) {
+                    Ok(shape) => {
+                        known.insert(tensor, shape);
+                    },
+                    Err(e) => {
+                        diags.push(inference_failed_diagnostic(
+                            name, span, i, &e,
+                        ));
+                        failed.insert(tensor);
+                    },
+                }
+
+                made_progress = true;
+            }
+        }
+
+        if !made_progress {
+            break;
+        }
+    }
+
+    for (tensor, shape) in known {
+        cmd.add_component(tensor, Tensor(shape));
+    }
+}
+
+fn integer_argument(
+    parameters: &IndexMap<String, ResourceOrString>,
+    name: &str,
+) -> Option<usize> {
+    match parameters.get(name)? {
+        ResourceOrString::String(value) => value.trim().parse().ok(),
+        ResourceOrString::Resource(_) => None,
+    }
+}
+
+fn inference_failed_diagnostic(
+    name: &Name,
+    span: Span,
+    output: usize,
+    error: &ShapeTransferError,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "Unable to infer the shape of \"{}\"'s output {} ({}), please add \
+             an explicit \"outputs\" annotation",
+            name, output, error
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+#[cfg(test)]
+mod tests {
+    use hotg_rune_core::ElementType;
+    use hotg_rune_proc_blocks::{DimensionExpr, ShapeTransfer};
+    use legion::{IntoQuery, Resources, World};
+
+    use super::*;
+    use crate::phases::Phase;
+
+    fn proc_block(args: &[(&str, &str)]) -> ProcBlock {
+        ProcBlock {
+            path: "proc-block@1.0".parse().unwrap(),
+            parameters: args
+                .iter()
+                .map(|(k, v)| {
+                    (k.to_string(), ResourceOrString::String(v.to_string()))
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn propagate_shapes_through_several_proc_blocks() {
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(Diagnostics::new());
+
+        let input =
+            world.push((Tensor(Shape::new(ElementType::I16, vec![1, 16000])),));
+        let first = world.push((InferredShape(ShapeTransfer::SameAsInput(0)),));
+        let second = world.push((InferredShape(ShapeTransfer::Computed {
+            element_type: ElementType::F32,
+            dimensions: vec![
+                DimensionExpr::Input {
+                    input: 0,
+                    dimension: 0,
+                },
+                DimensionExpr::Argument("bins".into()),
+            ]
+            .into(),
+        }),));
+        // Deliberately register the downstream stage first
+        world.push((
+            Name::from("hist"),
+            Span::default(),
+            proc_block(&[("bins", "32")]),
+            Inputs {
+                tensors: vec![first],
+            },
+            Outputs {
+                tensors: vec![second],
+            },
+        ));
+        world.push((
+            Name::from("identity"),
+            Span::default(),
+            proc_block(&[]),
+            Inputs {
+                tensors: vec![input],
+            },
+            Outputs {
+                tensors: vec![first],
+            },
+        ));
+
+        Phase::new().and_then(run_system).run(&mut world, &mut res);
+
+        assert!(res.get::<Diagnostics>().unwrap().is_empty());
+        let mut tensors = <&Tensor>::query();
+        assert_eq!(
+            tensors.get(&world, first).unwrap(),
+            &Tensor(Shape::new(ElementType::I16, vec![1, 16000]))
+        );
+        assert_eq!(
+            tensors.get(&world, second).unwrap(),
+            &Tensor(Shape::new(ElementType::F32, vec![1, 32]))
+        );
+    }
+
+    #[test]
+    fn missing_arguments_need_an_explicit_annotation() {
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(Diagnostics::new());
+        let output = world.push((InferredShape(ShapeTransfer::Computed {
+            element_type: ElementType::U8,
+            dimensions: vec![DimensionExpr::Argument("length".into())].into(),
+        }),));
+        world.push((
+            Name::from("stage"),
+            Span::default(),
+            proc_block(&[("length", "lots")]),
+            Outputs {
+                tensors: vec![output],
+            },
+        ));
+
+        Phase::new().and_then(run_system).run(&mut world, &mut res);
+
+        let diags = res.get::<Diagnostics>().unwrap();
+        assert!(diags.has_errors());
+        assert!(<&Tensor>::query().get(&world, output).is_err());
+    }
+}
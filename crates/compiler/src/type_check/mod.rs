@@ -1,7 +1,13 @@
 //! The type checking phase.
 
 mod check_for_loops;
+mod check_for_narrowing;
+mod check_memory_budget;
+mod check_model_files;
+mod check_proc_block_args;
+mod check_proc_block_transforms;
 mod components;
+mod infer_shapes;
 mod model_args_are_consumed;
 
 pub use components::*;
@@ -10,9 +16,20 @@ use legion::Registry;
 use crate::phases::Phase;
 
 pub fn phase() -> Phase {
-    Phase::new()
+    let phase = Phase::new()
+        .and_then(infer_shapes::run_system)
         .and_then(check_for_loops::run_system)
+        .and_then(check_for_narrowing::run_system)
         .and_then(model_args_are_consumed::run_system)
+        .and_then(check_proc_block_args::run_system)
+        .and_then(check_proc_block_transforms::run_system)
+        .and_then(check_memory_budget::run_system);
+
+    if cfg!(feature = "tflite") {
+        phase.and_then(check_model_files::run_system)
+    } else {
+        phase
+    }
 }
 
 pub(crate) fn register_components(_registry: &mut Registry<String>) {}
@@ -0,0 +1,402 @@
+//! Generate typed wrappers so a Rune can be used from TypeScript without
+//! hand-writing the glue between named inputs/outputs and the
+//! `@hotg-ai/rune` bindings.
+//!
+//! The [`TypeScriptBindings`] are generated from the [`RuneGraph`] and consist
+//! of an ES module (`wrapper`) and its type declarations (`declarations`).
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter, Write},
+};
+
+use hotg_rune_core::{ElementType, Shape};
+
+use crate::{
+    codegen::{RuneGraph, TensorId},
+    lowering::SourceKind,
+    parse::ResourceOrString,
+};
+
+const HEADER: &str = "// Generated by rune. Do not edit.\n";
+
+/// A JavaScript wrapper around a Rune and its type declarations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeScriptBindings {
+    /// The contents of the `*.d.ts` file.
+    pub declarations: String,
+    /// The contents of the `*.js` file.
+    pub wrapper: String,
+}
+
+/// Generate [`TypeScriptBindings`] for the inputs and outputs in a
+/// [`RuneGraph`].
+pub fn generate(graph: &RuneGraph) -> TypeScriptBindings {
+    let inputs: BTreeMap<&str, Input<'_>> = graph
+        .capabilities
+        .iter()
+        .filter_map(|(name, cap)| {
+            let shape =
+                cap.outputs.first().and_then(|t| graph.tensors.get(t))?;
+            let input = Input {
+                kind: capability_type(&cap.kind),
+                args: cap
+                    .args
+                    .iter()
+                    .filter_map(|(key, value)| match value {
+                        ResourceOrString::String(s) => {
+                            s.parse::<f64>().ok().map(|v| (key.as_str(), v))
+                        },
+                        ResourceOrString::Resource(_) => None,
+                    })
+                    .collect(),
+                shape,
+            };
+            Some((name.as_str(), input))
+        })
+        .collect();
+
+    let outputs: BTreeMap<&str, Vec<&Shape<'static>>> = graph
+        .outputs
+        .iter()
+        .map(|(name, out)| (name.as_str(), shapes(&out.inputs, graph)))
+        .collect();
+
+    TypeScriptBindings {
+        declarations: declarations(&graph.rune.name, &inputs, &outputs),
+        wrapper: wrapper(&inputs, &outputs),
+    }
+}
+
+struct Input<'a> {
+    kind: String,
+    args: BTreeMap<&'a str, f64>,
+    shape: &'a Shape<'static>,
+}
+
+fn shapes<'a>(
+    tensors: &[TensorId],
+    graph: &'a RuneGraph,
+) -> Vec<&'a Shape<'static>> {
+    tensors
+        .iter()
+        .filter_map(|t| graph.tensors.get(t))
+        .collect()
+}
+
+/// The name a capability's type is given in the `Capabilities` map from
+/// `@hotg-ai/rune` (e.g. `"float-image"`).
+fn capability_type(kind: &SourceKind) -> String {
+    match kind.as_capability_name() {
+        Some(name) => name.to_lowercase().replace('_', "-"),
+        None => kind.to_string(),
+    }
+}
+
+fn declarations(
+    rune: &str,
+    inputs: &BTreeMap<&str, Input<'_>>,
+    outputs: &BTreeMap<&str, Vec<&Shape<'static>>>,
+) -> String {
+    let mut dts = String::from(HEADER);
+
+    dts.push_str("import { Builder } from \"@hotg-ai/rune\";\n\n");
+
+    writeln!(dts, "/**\n * The inputs for the \"{}\" Rune.\n */", rune)
+        .unwrap();
+    dts.push_str("export type Inputs = {\n");
+    for (name, input) in inputs {
+        writeln!(
+            dts,
+            "    /** A `{}` capability producing `{}`. */",
+            input.kind, input.shape
+        )
+        .unwrap();
+        writeln!(
+            dts,
+            "    {}: {},",
+            quoted(name),
+            typed_array(input.shape.element_type())
+        )
+        .unwrap();
+    }
+    dts.push_str("};\n\n");
+
+    writeln!(dts, "/**\n * The outputs from the \"{}\" Rune.\n */", rune)
+        .unwrap();
+    dts.push_str("export type Outputs = {\n");
+    for (name, shapes) in outputs {
+        let description = shapes
+            .iter()
+            .map(|s| format!("`{}`", s))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(dts, "    /** Receives {}. */", description).unwrap();
+
+        let values = shapes
+            .iter()
+            .map(|s| output_value(s.element_type()).to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(dts, "    {}: [{}],", quoted(name), values).unwrap();
+    }
+    dts.push_str("};\n\n");
+
+    dts.push_str(DECLARATIONS);
+
+    dts
+}
+
+/// Declarations for everything the JavaScript wrapper exports.
+const DECLARATIONS: &str = r#"export type TensorDescription = {
+    elementType: string,
+    dimensions: number[],
+};
+
+export declare const inputs: Record<keyof Inputs, TensorDescription & {
+    type: string,
+    args: Record<string, number>,
+}>;
+export declare const outputs: Record<keyof Outputs, TensorDescription[]>;
+
+/**
+ * Load the Rune, returning a function which evaluates it.
+ *
+ * @param rune The Rune's bytes, or a URL to fetch it from.
+ * @param configure Configure the builder (e.g. to register model handlers).
+ */
+export declare function load(
+    rune: ArrayBuffer | string,
+    configure?: (builder: Builder) => Builder,
+): Promise<(inputs: Inputs) => Outputs>;
+"#;
+
+fn wrapper(
+    inputs: &BTreeMap<&str, Input<'_>>,
+    outputs: &BTreeMap<&str, Vec<&Shape<'static>>>,
+) -> String {
+    let mut js = String::from(HEADER);
+
+    js.push_str("import { builder, Tensor } from \"@hotg-ai/rune\";\n\n");
+
+    js.push_str("export const inputs = {\n");
+    for (name, input) in inputs {
+        let args = input
+            .args
+            .iter()
+            .map(|(key, value)| format!("{}: {}", quoted(key), value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            js,
+            "    {}: {{ type: {}, args: {{ {} }}, {} }},",
+            quoted(name),
+            quoted(&input.kind),
+            args,
+            TensorDescription(input.shape),
+        )
+        .unwrap();
+    }
+    js.push_str("};\n\n");
+
+    js.push_str("export const outputs = {\n");
+    for (name, shapes) in outputs {
+        let descriptions = shapes
+            .iter()
+            .map(|s| format!("{{ {} }}", TensorDescription(s)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(js, "    {}: [{}],", quoted(name), descriptions).unwrap();
+    }
+    js.push_str("};\n\n");
+
+    js.push_str(WRAPPER_RUNTIME);
+
+    js
+}
+
+/// The hand-written half of the wrapper, which maps between the Rune's
+/// positional inputs/outputs and the names used in the Runefile.
+const WRAPPER_RUNTIME: &str = r#"function sameArgs(expected, actual) {
+    const keys = Object.keys(expected);
+    return keys.every(key => actual[key] === expected[key]);
+}
+
+function sameDimensions(a, b) {
+    return a.length == b.length && a.every((dim, i) => dim == b[i]);
+}
+
+export async function load(rune, configure = b => b) {
+    const evaluate = await configure(builder()).build(rune);
+
+    return values => {
+        const used = new Set();
+
+        const { outputs: results } = evaluate(description => {
+            const name = Object.keys(inputs).find(name =>
+                !used.has(name) &&
+                inputs[name].type == description.type &&
+                sameArgs(inputs[name].args, description.args));
+
+            if (!name) {
+                throw new Error(`The Rune requested an unknown "${description.type}" input`);
+            }
+            used.add(name);
+
+            const { elementType, dimensions } = inputs[name];
+            return Tensor.fromTypedArray(elementType, dimensions, values[name]);
+        });
+
+        const consumed = new Set();
+        const named = {};
+
+        for (const [name, expected] of Object.entries(outputs)) {
+            named[name] = expected.map(({ elementType, dimensions }) => {
+                const index = results.findIndex((value, i) =>
+                    !consumed.has(i) &&
+                    value.type_name == elementType &&
+                    sameDimensions(value.dimensions, dimensions));
+
+                if (index < 0) {
+                    throw new Error(`The "${name}" output didn't receive a ${elementType}[${dimensions}]`);
+                }
+                consumed.add(index);
+
+                return results[index].elements;
+            });
+        }
+
+        return named;
+    };
+}
+"#;
+
+struct TensorDescription<'a>(&'a Shape<'a>);
+
+impl Display for TensorDescription<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "elementType: {}, dimensions: {:?}",
+            quoted(self.0.element_type().rune_name()),
+            self.0.dimensions()
+        )
+    }
+}
+
+fn quoted(s: &str) -> String {
+    serde_json::to_string(s).expect("Strings are always serializable")
+}
+
+/// The typed array accepted by `Tensor.fromTypedArray()`.
+fn typed_array(element_type: ElementType) -> &'static str {
+    match element_type {
+        ElementType::U8 => "Uint8ClampedArray",
+        ElementType::I8 => "Int8Array",
+        ElementType::U16 => "Uint16Array",
+        ElementType::I16 => "Int16Array",
+        ElementType::U32 => "Uint32Array",
+        ElementType::I32 => "Int32Array",
+        ElementType::F32 => "Float32Array",
+        ElementType::U64 => "BigUint64Array",
+        ElementType::I64 => "BigInt64Array",
+        ElementType::F64 => "Float64Array",
+        // JavaScript doesn't have a Float16Array, so pass the raw bits
+        ElementType::F16 | ElementType::BF16 => "Uint16Array",
+        ElementType::String => "Uint8Array",
+    }
+}
+
+/// The type of `OutputValue.elements` for a particular element type.
+fn output_value(element_type: ElementType) -> &'static str {
+    match element_type {
+        ElementType::String => "string[]",
+        _ => "number[]",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::{
+        codegen::{CapabilitySummary, OutputSummary, RuneSummary},
+        lowering::SinkKind,
+    };
+
+    fn graph() -> RuneGraph {
+        let mut capabilities = IndexMap::new();
+        let mut args = HashMap::new();
+        args.insert("hz".to_string(), ResourceOrString::String("16000".into()));
+        capabilities.insert(
+            "audio".into(),
+            CapabilitySummary {
+                kind: SourceKind::Sound,
+                args,
+                outputs: vec![TensorId("audio".into())],
+            },
+        );
+
+        let mut outputs = IndexMap::new();
+        outputs.insert(
+            "serial".into(),
+            OutputSummary {
+                kind: SinkKind::Serial,
+                args: HashMap::new(),
+                inputs: vec![TensorId("label".into())],
+                enabled_when: None,
+            },
+        );
+
+        let mut tensors = HashMap::new();
+        tensors.insert(
+            TensorId("audio".into()),
+            Shape::new(ElementType::I16, vec![1, 16000]),
+        );
+        tensors.insert(
+            TensorId("label".into()),
+            Shape::new(ElementType::String, vec![1]),
+        );
+
+        RuneGraph {
+            rune: RuneSummary {
+                name: "microspeech".into(),
+            },
+            capabilities,
+            models: IndexMap::new(),
+            proc_blocks: IndexMap::new(),
+            outputs,
+            resources: HashMap::new(),
+            tensors,
+        }
+    }
+
+    #[test]
+    fn declarations_use_names_from_the_runefile() {
+        let bindings = generate(&graph());
+
+        let dts = &bindings.declarations;
+        assert!(dts.contains("A `sound` capability producing `i16[1, 16000]`"));
+        assert!(dts.contains("    \"audio\": Int16Array,\n"));
+        assert!(dts.contains("    \"serial\": [string[]],\n"));
+        assert!(dts.contains("Promise<(inputs: Inputs) => Outputs>"));
+    }
+
+    #[test]
+    fn wrapper_describes_each_tensor() {
+        let bindings = generate(&graph());
+
+        let js = &bindings.wrapper;
+        assert!(js.starts_with(HEADER));
+        assert!(js.contains(
+            "    \"audio\": { type: \"sound\", args: { \"hz\": 16000 }, \
+             elementType: \"i16\", dimensions: [1, 16000] },\n"
+        ));
+        assert!(js.contains(
+            "    \"serial\": [{ elementType: \"utf8\", dimensions: [1] }],\n"
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+//! Rebuild a Rune whenever its inputs change.
+//!
+//! [`build_watch()`] runs the normal build process, then keeps an eye on the
+//! Runefile and every model, resource, and local proc-block it refers to. As
+//! soon as one of them is modified the Rune is rebuilt and the callback is
+//! given the new artifact (or diagnostics explaining why the build failed).
+//!
+//! Because the same [`BuildContext::working_directory`] is reused, cargo only
+//! needs to recompile the parts of the Rune which actually changed. If the
+//! build cache is enabled, going back to a previous version of the project
+//! (e.g. undoing an edit) reuses the Rune from the [`crate::artifacts`] store
+//! without recompiling at all.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use legion::{IntoQuery, Resources, World};
+
+use crate::{
+    artifacts::ArtifactKey,
+    compile::CompilationResult,
+    hooks::{Continuation, Hooks},
+    lowering::{Model, ModelFile, ProcBlock, Resource, ResourceSource},
+    BuildContext, Diagnostics, FeatureFlags,
+};
+
+/// How often the filesystem is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The outcome of a single build started by [`build_watch()`].
+#[derive(Debug)]
+pub struct Rebuild {
+    /// Any diagnostics emitted while building the Rune.
+    pub diagnostics: Diagnostics,
+    /// The compiled Rune, or `None` if the build stopped early due to errors
+    /// in the Runefile.
+    pub result: Option<CompilationResult>,
+    /// The key this build was stored under, if it got as far as compiling.
+    pub artifact: Option<ArtifactKey>,
+    /// The files which triggered this build (empty for the first build).
+    pub changed: Vec<PathBuf>,
+}
+
+/// Build the Rune, then rebuild it every time the Runefile at `runefile` or
+/// one of the files it depends on changes.
+///
+/// This only returns once the `callback` returns [`Continuation::Halt`] or
+/// the Runefile can't be read.
+pub fn build_watch(
+    runefile: &Path,
+    mut ctx: BuildContext,
+    features: FeatureFlags,
+    mut callback: impl FnMut(Rebuild) -> Continuation,
+) -> Result<(), std::io::Error> {
+    let mut changed = Vec::new();
+
+    loop {
+        ctx.runefile = std::fs::read_to_string(runefile)?;
+
+        let (world, mut res) = build_once(ctx.clone(), features.clone());
+        let snapshot = Snapshot::take(watched_paths(&world, &ctx, runefile));
+
+        let rebuild = Rebuild {
+            diagnostics: res.remove().unwrap_or_default(),
+            result: res.remove(),
+            artifact: res.remove(),
+            changed: std::mem::take(&mut changed),
+        };
+
+        if callback(rebuild) == Continuation::Halt {
+            return Ok(());
+        }
+
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+
+            changed = snapshot.changed(&Snapshot::take(snapshot.paths()));
+            if !changed.is_empty() {
+                log::debug!("Rebuilding because {:?} changed", changed);
+                break;
+            }
+        }
+    }
+}
+
+fn build_once(ctx: BuildContext, features: FeatureFlags) -> (World, Resources) {
+    struct NopHooks;
+    impl Hooks for NopHooks {}
+
+    crate::build_with_hooks(ctx, features, &mut NopHooks)
+}
+
+/// Find every file the build depends on.
+///
+/// Local proc-blocks are directories, so everything inside them (except for
+/// `target/` and hidden files) is watched.
+fn watched_paths(
+    world: &World,
+    ctx: &BuildContext,
+    runefile: &Path,
+) -> Vec<PathBuf> {
+    let root = &ctx.current_directory;
+    let mut paths = vec![runefile.to_path_buf()];
+
+    for model in <&Model>::query().iter(world) {
+        if let ModelFile::FromDisk(path) = &model.model_file {
+            paths.push(root.join(path));
+        }
+        paths.extend(model.variants.values().map(|path| root.join(path)));
+    }
+
+    for resource in <&Resource>::query().iter(world) {
+        if let Some(ResourceSource::FromDisk(path)) = &resource.default_value {
+            walk(&root.join(path), &mut paths);
+        }
+    }
+
+    for proc_block in <&ProcBlock>::query().iter(world) {
+        if proc_block.path.base.starts_with('.') {
+            walk(&root.join(&proc_block.path.base), &mut paths);
+        }
+    }
+
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Add a path to the list, recursing into it if it's a directory.
+fn walk(path: &Path, paths: &mut Vec<PathBuf>) {
+    let entries = match path.read_dir() {
+        Ok(entries) => entries,
+        Err(_) => {
+            // Not a directory (or it doesn't exist yet), so watch the path
+            // itself.
+            paths.push(path.to_path_buf());
+            return;
+        },
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+
+        walk(&entry.path(), paths);
+    }
+}
+
+/// The last-modified time for a set of files, where `None` means the file
+/// didn't exist.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Snapshot(BTreeMap<PathBuf, Option<SystemTime>>);
+
+impl Snapshot {
+    fn take(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Snapshot(
+            paths
+                .into_iter()
+                .map(|path| {
+                    let modified = std::fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .ok();
+                    (path, modified)
+                })
+                .collect(),
+        )
+    }
+
+    fn paths(&self) -> Vec<PathBuf> { self.0.keys().cloned().collect() }
+
+    /// Which files are different in the `other` snapshot?
+    fn changed(&self, other: &Snapshot) -> Vec<PathBuf> {
+        other
+            .0
+            .iter()
+            .filter(|(path, modified)| self.0.get(*path) != Some(modified))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::{lowering::Name, parse::Path as ProcBlockPath};
+
+    #[test]
+    fn watch_the_runefile_and_models() {
+        let mut world = World::default();
+        world.push((
+            Name::from("model"),
+            Model {
+                model_file: ModelFile::FromDisk(PathBuf::from("model.tflite")),
+                args: IndexMap::new(),
+                variants: vec![(
+                    "int8".to_string(),
+                    PathBuf::from("model.int8.tflite"),
+                )]
+                .into_iter()
+                .collect(),
+            },
+        ));
+        world.push((
+            Name::from("fft"),
+            ProcBlock {
+                path: ProcBlockPath::new("hotg-ai/proc-blocks", None, None),
+                parameters: IndexMap::new(),
+            },
+        ));
+        let mut ctx = BuildContext::from_doc(
+            crate::parse::Document::parse(
+                "version: 1\nimage: runicos/base\npipeline: {}",
+            )
+            .unwrap(),
+        );
+        ctx.current_directory = PathBuf::from("/project");
+
+        let got =
+            watched_paths(&world, &ctx, Path::new("/project/Runefile.yml"));
+
+        assert_eq!(
+            got,
+            vec![
+                PathBuf::from("/project/Runefile.yml"),
+                PathBuf::from("/project/model.int8.tflite"),
+                PathBuf::from("/project/model.tflite"),
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_modified_and_deleted_files() {
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(1);
+        let before = Snapshot(
+            vec![
+                (PathBuf::from("a"), Some(now)),
+                (PathBuf::from("b"), Some(now)),
+                (PathBuf::from("c"), Some(now)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let after = Snapshot(
+            vec![
+                (PathBuf::from("a"), Some(now)),
+                (PathBuf::from("b"), Some(later)),
+                (PathBuf::from("c"), None),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        assert_eq!(
+            before.changed(&after),
+            vec![PathBuf::from("b"), PathBuf::from("c")]
+        );
+        assert!(before.changed(&before).is_empty());
+    }
+}
@@ -113,6 +113,12 @@ macro_rules! parse_and_analyse {
                     working_directory: PATH.into(),
                     current_directory: PATH.into(),
                     optimized: false,
+                    debug_symbols: false,
+                    simd: false,
+                    memory64: false,
+                    locked: false,
+                    device_memory: None,
+                    emit_project: None,
                     verbosity: Verbosity::Normal,
                     rune_version: Some(RuneVersion {
                         version: env!("CARGO_PKG_VERSION").to_string(),
@@ -120,6 +126,14 @@ macro_rules! parse_and_analyse {
                 }
             }
 
+            /// Don't fetch and compile every proc-block just to read its
+            /// descriptor.
+            fn feature_flags() -> FeatureFlags {
+                let mut features = FeatureFlags::development();
+                features.set_load_proc_block_descriptors(false);
+                features
+            }
+
             #[test]
             fn analyse() {
                 let file = SimpleFile::new("Runefile", SRC);
@@ -128,7 +142,7 @@ macro_rules! parse_and_analyse {
 
                 hotg_rune_compiler::build_with_hooks(
                     ctx,
-                    FeatureFlags::development(),
+                    feature_flags(),
                     &mut hooks,
                 );
 
@@ -143,7 +157,7 @@ macro_rules! parse_and_analyse {
 
                 hotg_rune_compiler::build_with_hooks(
                     ctx,
-                    FeatureFlags::development(),
+                    feature_flags(),
                     &mut hooks,
                 );
 
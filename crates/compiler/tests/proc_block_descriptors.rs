@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use codespan_reporting::diagnostic::Severity;
+use hotg_rune_compiler::{
+    codegen::RuneVersion,
+    hooks::{AfterTypeCheckingContext, Continuation, Hooks},
+    lowering::{Name, Outputs, Tensor},
+    BuildContext, FeatureFlags, Verbosity,
+};
+use hotg_rune_core::Shape;
+use legion::{EntityStore, IntoQuery};
+
+const RUNEFILE: &str = r#"
+version: 1
+image: runicos/base
+
+pipeline:
+  audio:
+    capability: SOUND
+    outputs:
+      - type: i16
+        dimensions: [16000]
+    args:
+      hz: 16000
+
+  mfcc:
+    proc-block: "./proc_blocks/mfcc"
+    inputs:
+      - audio
+    args:
+      sample_rate: 16000
+      frame_length: 400
+      hop: 160
+      pad: 0
+      n_mels: 40
+      n_coeffs: 13
+
+  serial:
+    out: SERIAL
+    inputs:
+      - mfcc
+"#;
+
+#[derive(Default)]
+struct CheckMfccOutputs {
+    shape: Option<Shape<'static>>,
+    errors: Vec<String>,
+}
+
+impl Hooks for CheckMfccOutputs {
+    fn after_type_checking(
+        &mut self,
+        ctx: &mut dyn AfterTypeCheckingContext,
+    ) -> Continuation {
+        self.errors = ctx
+            .diagnostics()
+            .iter_severity(Severity::Error)
+            .map(|diag| diag.message.clone())
+            .collect();
+
+        let world = ctx.world();
+        let outputs = <(&Name, &Outputs)>::query()
+            .iter(world)
+            .find(|(name, _)| name.as_str() == "mfcc")
+            .map(|(_, outputs)| outputs.tensors[0]);
+
+        self.shape = outputs.and_then(|tensor| {
+            let entry = world.entry_ref(tensor).ok()?;
+            entry.get_component::<Tensor>().ok().map(|t| t.0.clone())
+        });
+
+        Continuation::Halt
+    }
+}
+
+#[test]
+fn infer_output_shapes_from_the_compiled_proc_block() {
+    let repo_root = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .and_then(Path::parent)
+        .unwrap();
+    let working_directory = tempfile::tempdir().unwrap();
+    let ctx = BuildContext {
+        name: "mfcc".to_string(),
+        runefile: RUNEFILE.to_string(),
+        working_directory: working_directory.path().to_path_buf(),
+        current_directory: repo_root.to_path_buf(),
+        optimized: false,
+        debug_symbols: false,
+        simd: false,
+        memory64: false,
+        locked: false,
+        device_memory: None,
+        emit_project: None,
+        verbosity: Verbosity::Normal,
+        rune_version: Some(RuneVersion {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }),
+    };
+    let mut features = FeatureFlags::development();
+    features.set_rune_repo_dir(repo_root.to_path_buf());
+    let mut hooks = CheckMfccOutputs::default();
+
+    hotg_rune_compiler::build_with_hooks(ctx, features, &mut hooks);
+
+    assert!(hooks.errors.is_empty(), "{:?}", hooks.errors);
+    // (16000 - 400) / 160 + 1 frames, each with 13 coefficients
+    let expected: Shape<'static> = "f32[98, 13]".parse().unwrap();
+    assert_eq!(hooks.shape, Some(expected));
+}
@@ -0,0 +1,100 @@
+use codespan_reporting::diagnostic::Severity;
+use hotg_rune_compiler::{
+    codegen::{File, RuneVersion},
+    hooks::{AfterCodegenContext, Continuation, Hooks},
+    BuildContext, FeatureFlags, Verbosity,
+};
+use legion::IntoQuery;
+
+const RUNEFILE: &str = r#"
+version: 1
+image: runicos/base
+
+pipeline:
+  audio:
+    capability: SOUND
+    outputs:
+      - type: i16
+        dimensions: [16000]
+    args:
+      hz: 16000
+      sample_duration_ms: 1000
+      hop_length_ms: 250
+      stream: true
+
+  serial:
+    out: SERIAL
+    inputs:
+      - audio
+"#;
+
+#[derive(Default)]
+struct GeneratedLibRs {
+    lib_rs: Option<String>,
+    errors: Vec<String>,
+}
+
+impl Hooks for GeneratedLibRs {
+    fn after_codegen(
+        &mut self,
+        ctx: &mut dyn AfterCodegenContext,
+    ) -> Continuation {
+        self.errors = ctx
+            .diagnostics()
+            .iter_severity(Severity::Error)
+            .map(|diag| diag.message.clone())
+            .collect();
+
+        self.lib_rs = <&File>::query()
+            .iter(ctx.world())
+            .find(|f| f.path.ends_with("lib.rs"))
+            .map(|f| String::from_utf8_lossy(&f.data).into_owned());
+
+        Continuation::Halt
+    }
+}
+
+#[test]
+fn streaming_arguments_are_passed_to_the_runtime_as_integers() {
+    let working_directory = tempfile::tempdir().unwrap();
+    let ctx = BuildContext {
+        name: "streaming".to_string(),
+        runefile: RUNEFILE.to_string(),
+        working_directory: working_directory.path().to_path_buf(),
+        current_directory: working_directory.path().to_path_buf(),
+        optimized: false,
+        debug_symbols: false,
+        simd: false,
+        memory64: false,
+        locked: false,
+        device_memory: None,
+        emit_project: None,
+        verbosity: Verbosity::Normal,
+        rune_version: Some(RuneVersion {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }),
+    };
+    let mut features = FeatureFlags::development();
+    features.set_load_proc_block_descriptors(false);
+    let mut hooks = GeneratedLibRs::default();
+
+    hotg_rune_compiler::build_with_hooks(ctx, features, &mut hooks);
+
+    assert!(hooks.errors.is_empty(), "{:?}", hooks.errors);
+    let lib_rs: String = hooks
+        .lib_rs
+        .unwrap()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    assert!(
+        lib_rs.contains(r#"audio.set_parameter("stream",1i32);"#),
+        "{}",
+        lib_rs
+    );
+    assert!(
+        lib_rs.contains(r#"audio.set_parameter("hop_length_ms",250i32);"#),
+        "{}",
+        lib_rs
+    );
+}
@@ -1,25 +1,26 @@
 use std::str::FromStr;
 
 use hotg_rune_core::ElementType;
-use proc_macro2::{Group, Ident};
+use proc_macro2::{Group, Ident, TokenStream, TokenTree};
 use quote::quote;
 use syn::{
     parse::{Parse, ParseStream, Parser},
     punctuated::Punctuated,
     spanned::Spanned,
-    Attribute, DeriveInput, Error, ExprLit, Lit, LitStr, Path, Token,
+    Attribute, DeriveInput, Error, ExprLit, Generics, Lit, LitStr, Path, Token,
     TypeArray, TypePath, TypeReference,
 };
 
 use crate::{
     descriptor::{
-        Dimension, Dimensions, ProcBlockDescriptor, TensorDescriptor,
+        Dimension, DimensionExpr, Dimensions, ParameterDescriptor,
+        ParameterRange, ProcBlockDescriptor, ShapeTransfer, TensorDescriptor,
         TensorDescriptors, TransformDescriptor,
     },
     types::{
-        Assertions, CustomSection, DeriveOutput, ProcBlockImpl, Setter,
-        SetterAssertion, SetterAssertions, Setters, TransformAssertion,
-        TransformAssertions,
+        Assertions, CustomSection, DeriveOutput, DescribeFunction,
+        ProcBlockImpl, Setter, SetterAssertion, SetterAssertions, Setters,
+        TransformAssertion, TransformAssertions,
     },
 };
 
@@ -28,19 +29,28 @@ pub(crate) fn analyse(input: &DeriveInput) -> Result<DeriveOutput, Error> {
     let exports = export_path(&input.attrs)?;
 
     let (description, available_transforms, transform_assertions) =
-        analyse_struct_attributes(&input.ident, &exports, &input.attrs)?;
+        analyse_struct_attributes(
+            &input.ident,
+            &input.generics,
+            &exports,
+            &input.attrs,
+        )?;
 
-    let (setters, setter_assertions) = analyse_properties(input)?;
+    let (setters, parameters, setter_assertions) = analyse_properties(input)?;
 
     let descriptor = ProcBlockDescriptor {
         type_name: type_name.to_string().into(),
         description: description.into(),
+        version: crate_version().into(),
+        parameters: parameters.into(),
         available_transforms: available_transforms.into(),
+        output_shapes: output_shapes(&input.attrs)?.into(),
     };
 
     Ok(DeriveOutput {
         setters,
         custom_section: make_custom_section(&input.ident, &descriptor)?,
+        describe_function: make_describe_function(&input.ident, &descriptor),
         trait_impl: ProcBlockImpl {
             exports,
             type_name,
@@ -56,6 +66,7 @@ pub(crate) fn analyse(input: &DeriveInput) -> Result<DeriveOutput, Error> {
 
 fn analyse_struct_attributes(
     proc_block_type: &Ident,
+    generics: &Generics,
     exports: &Path,
     attrs: &[syn::Attribute],
 ) -> Result<
@@ -67,23 +78,49 @@ fn analyse_struct_attributes(
     Error,
 > {
     let description = doc_comments(attrs)?;
-    let transforms = transforms(attrs)?;
+    let parameters = element_type_parameters(generics, attrs)?;
 
+    // We can only check a generic proc block's transforms when we know every
+    // generic parameter's possible values.
+    let can_assert = generics.params.len() == parameters.len();
+
+    let mut transforms = Vec::new();
     let mut assertions = Vec::new();
 
-    for transform in &transforms {
-        let TransformDescriptor { inputs, outputs } = transform;
-        let inputs: Vec<_> = inputs
-            .iter()
-            .map(|t| to_rust_tensor(exports, &t.element_type))
-            .collect();
-        let outputs: Vec<_> = outputs
-            .iter()
-            .map(|t| to_rust_tensor(exports, &t.element_type))
-            .collect();
+    for instantiation in instantiations(&parameters) {
+        for transform in transforms_for(attrs, &instantiation)? {
+            if can_assert {
+                let TransformDescriptor { inputs, outputs } = &transform;
+                let inputs: Vec<_> = inputs
+                    .iter()
+                    .map(|t| to_rust_tensor(exports, &t.element_type))
+                    .collect();
+                let outputs: Vec<_> = outputs
+                    .iter()
+                    .map(|t| to_rust_tensor(exports, &t.element_type))
+                    .collect();
+                let generics = generics
+                    .type_params()
+                    .map(|p| {
+                        let (_, element_type) = instantiation
+                            .iter()
+                            .find(|(name, _)| *name == p.ident)
+                            .expect("Checked by can_assert");
+                        to_rust_element_type(exports, element_type)
+                    })
+                    .collect();
+
+                assertions.push(TransformAssertion {
+                    generics,
+                    inputs,
+                    outputs,
+                });
+            }
 
-        let assert = TransformAssertion { inputs, outputs };
-        assertions.push(assert);
+            if !transforms.contains(&transform) {
+                transforms.push(transform);
+            }
+        }
     }
 
     Ok((
@@ -98,6 +135,13 @@ fn analyse_struct_attributes(
 }
 
 fn to_rust_tensor(exports: &Path, ty: &ElementType) -> syn::Type {
+    let element_type = to_rust_element_type(exports, ty);
+
+    syn::parse2(quote!(#exports::Tensor<#element_type>))
+        .expect("We should always be able to parse a type")
+}
+
+fn to_rust_element_type(exports: &Path, ty: &ElementType) -> syn::Type {
     let element_type = match ty {
         ElementType::U8 => quote!(u8),
         ElementType::I8 => quote!(i8),
@@ -109,15 +153,19 @@ fn to_rust_tensor(exports: &Path, ty: &ElementType) -> syn::Type {
         ElementType::U64 => quote!(u64),
         ElementType::F64 => quote!(f64),
         ElementType::I64 => quote!(i64),
+        ElementType::F16 => quote!(#exports::f16),
+        ElementType::BF16 => quote!(#exports::bf16),
         ElementType::String => quote!(#exports::Cow<'static, str>),
     };
 
-    syn::parse2(quote!(#exports::Tensor<#element_type>))
-        .expect("We should always be able to parse a type")
+    syn::parse2(element_type).expect("We should always be able to parse a type")
 }
 
-fn transforms(
+/// Parse every `#[transform(...)]` attribute, replacing any generic
+/// parameters with the element types in this `instantiation`.
+fn transforms_for(
     attrs: &[Attribute],
+    instantiation: &[(Ident, ElementType)],
 ) -> Result<Vec<TransformDescriptor<'static>>, Error> {
     let mut transforms = Vec::new();
 
@@ -126,10 +174,13 @@ fn transforms(
             if name == "transform" {
                 let parenthesized_key_values: Group =
                     syn::parse2(attr.tokens.clone())?;
+                let tokens = substitute(
+                    parenthesized_key_values.stream(),
+                    instantiation,
+                );
 
-                let transform = parse_transform_descriptor
-                    .parse2(parenthesized_key_values.stream())?;
-                transforms.push(transform);
+                let parsed = parse_transform_attribute.parse2(tokens)?;
+                transforms.extend(parsed);
             }
         }
     }
@@ -137,6 +188,198 @@ fn transforms(
     Ok(transforms)
 }
 
+/// Replace each generic parameter (e.g. the `T` in `[T; _]`) with the name of
+/// an element type.
+fn substitute(
+    tokens: TokenStream,
+    instantiation: &[(Ident, ElementType)],
+) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|tt| match tt {
+            TokenTree::Ident(ident) => {
+                match instantiation.iter().find(|(name, _)| *name == ident) {
+                    Some((_, element_type)) => TokenTree::Ident(Ident::new(
+                        element_type.rune_name(),
+                        ident.span(),
+                    )),
+                    None => TokenTree::Ident(ident),
+                }
+            },
+            TokenTree::Group(group) => {
+                let mut substituted = Group::new(
+                    group.delimiter(),
+                    substitute(group.stream(), instantiation),
+                );
+                substituted.set_span(group.span());
+                TokenTree::Group(substituted)
+            },
+            other => other,
+        })
+        .collect()
+}
+
+/// Find the element types each generic parameter may be instantiated with,
+/// as declared by `#[proc_block(element_type(T = [f32, i16]))]`.
+fn element_type_parameters(
+    generics: &Generics,
+    attrs: &[Attribute],
+) -> Result<Vec<(Ident, Vec<ElementType>)>, Error> {
+    let mut parameters: Vec<(Ident, Vec<ElementType>)> = Vec::new();
+
+    for attr in attrs {
+        if !attr.path.is_ident("proc_block") {
+            continue;
+        }
+
+        for (name, element_types) in
+            attr.parse_args_with(parse_element_type_parameters)?
+        {
+            if !generics.type_params().any(|p| p.ident == name) {
+                return Err(Error::new(
+                    name.span(),
+                    format!("\"{}\" isn't a generic type parameter", name),
+                ));
+            }
+            if parameters.iter().any(|(existing, _)| *existing == name) {
+                return Err(Error::new(
+                    name.span(),
+                    format!(
+                        "The element types for \"{}\" were already declared",
+                        name
+                    ),
+                ));
+            }
+
+            parameters.push((name, element_types));
+        }
+    }
+
+    Ok(parameters)
+}
+
+/// Parse the `element_type(T = [f32, i16])` from `#[proc_block(...)]`.
+fn parse_element_type_parameters(
+    tokens: ParseStream,
+) -> Result<Vec<(Ident, Vec<ElementType>)>, Error> {
+    let mut parameters = Vec::new();
+
+    while !tokens.is_empty() {
+        let ident: Ident = tokens.parse()?;
+        if ident != "element_type" {
+            return Err(Error::new(ident.span(), "Expected \"element_type\""));
+        }
+
+        let inner;
+        let _ = syn::parenthesized!(inner in tokens);
+        let name: Ident = inner.parse()?;
+        let _: Token![=] = inner.parse()?;
+        parameters.push((name, parse_element_types(&inner)?));
+
+        if !tokens.is_empty() {
+            let _: Token![,] = tokens.parse()?;
+        }
+    }
+
+    Ok(parameters)
+}
+
+/// Every combination of the element types each generic parameter may take.
+///
+/// A proc block without any generic parameters has a single instantiation
+/// where nothing gets substituted.
+fn instantiations(
+    parameters: &[(Ident, Vec<ElementType>)],
+) -> Vec<Vec<(Ident, ElementType)>> {
+    let mut instantiations = vec![Vec::new()];
+
+    for (name, element_types) in parameters {
+        instantiations = instantiations
+            .into_iter()
+            .flat_map(|partial| {
+                element_types.iter().map(move |&element_type| {
+                    let mut next = partial.clone();
+                    next.push((name.clone(), element_type));
+                    next
+                })
+            })
+            .collect();
+    }
+
+    instantiations
+}
+
+/// Parse the contents of a `#[transform(...)]` attribute, which may expand to
+/// several [`TransformDescriptor`]s.
+fn parse_transform_attribute(
+    tokens: ParseStream,
+) -> Result<Vec<TransformDescriptor<'static>>, Error> {
+    let fork = tokens.fork();
+    let ident: Ident = fork.parse()?;
+
+    if ident == "input" {
+        parse_element_type_transforms(tokens)
+    } else {
+        parse_transform_descriptor(tokens).map(|t| vec![t])
+    }
+}
+
+/// Parse the `input = [i16, f32], output = f32` shorthand, which declares a
+/// transform with arbitrary dimensions for every combination of input and
+/// output element types.
+fn parse_element_type_transforms(
+    tokens: ParseStream,
+) -> Result<Vec<TransformDescriptor<'static>>, Error> {
+    let ident: Ident = tokens.parse()?;
+    if ident != "input" {
+        return Err(Error::new(ident.span(), "Expected \"input\""));
+    }
+    let _: Token![=] = tokens.parse()?;
+    let inputs = parse_element_types(tokens)?;
+    let _: Token![,] = tokens.parse()?;
+
+    let ident: Ident = tokens.parse()?;
+    if ident != "output" {
+        return Err(Error::new(ident.span(), "Expected \"output\""));
+    }
+    let _: Token![=] = tokens.parse()?;
+    let outputs = parse_element_types(tokens)?;
+
+    let any_shape = |element_type: &ElementType| TensorDescriptor {
+        element_type: *element_type,
+        dimensions: Dimensions::Arbitrary,
+    };
+
+    let mut transforms = Vec::new();
+
+    for input in &inputs {
+        for output in &outputs {
+            transforms.push(TransformDescriptor {
+                inputs: any_shape(input).into(),
+                outputs: any_shape(output).into(),
+            });
+        }
+    }
+
+    Ok(transforms)
+}
+
+/// Parse either a single element type (`f32`) or a list of them
+/// (`[i16, f32]`).
+fn parse_element_types(tokens: ParseStream) -> Result<Vec<ElementType>, Error> {
+    if tokens.peek(syn::token::Bracket) {
+        let inner;
+        let _ = syn::bracketed!(inner in tokens);
+        let types: Punctuated<syn::Type, Token![,]> =
+            Punctuated::parse_separated_nonempty(&inner)?;
+
+        types.iter().map(known_type_from_syn_type).collect()
+    } else {
+        let ty: syn::Type = tokens.parse()?;
+        known_type_from_syn_type(&ty).map(|t| vec![t])
+    }
+}
+
 /// Parse the `inputs = ..., outputs = ...` from
 /// `#[transform(inputs =..., outputs = ...)]`.
 fn parse_transform_descriptor(
@@ -160,6 +403,121 @@ fn parse_transform_descriptor(
     Ok(TransformDescriptor { inputs, outputs })
 }
 
+fn output_shapes(
+    attrs: &[Attribute],
+) -> Result<Vec<ShapeTransfer<'static>>, Error> {
+    let mut shapes = Vec::new();
+
+    for attr in attrs {
+        if attr.path.is_ident("output_shape") {
+            shapes.push(attr.parse_args_with(parse_shape_transfer)?);
+        }
+    }
+
+    Ok(shapes)
+}
+
+/// Parse the `input = 0` or `f32, [1, input(0, 1), "bins"]` from
+/// `#[output_shape(...)]`.
+fn parse_shape_transfer(
+    tokens: ParseStream,
+) -> Result<ShapeTransfer<'static>, Error> {
+    if tokens.peek(syn::Ident) && tokens.peek2(Token![=]) {
+        let ident: Ident = tokens.parse()?;
+        if ident != "input" {
+            return Err(Error::new(ident.span(), "Expected \"input\""));
+        }
+        let _: Token![=] = tokens.parse()?;
+        let index: syn::LitInt = tokens.parse()?;
+
+        return Ok(ShapeTransfer::SameAsInput(index.base10_parse()?));
+    }
+
+    let element: syn::Type = tokens.parse()?;
+    let element_type = known_type_from_syn_type(&element)?;
+    let _: Token![,] = tokens.parse()?;
+    let dimensions: syn::ExprArray = tokens.parse()?;
+
+    let dimensions = dimensions
+        .elems
+        .iter()
+        .map(parse_dimension_expr)
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(ShapeTransfer::Computed {
+        element_type,
+        dimensions: dimensions.into(),
+    })
+}
+
+fn parse_dimension_expr(
+    expr: &syn::Expr,
+) -> Result<DimensionExpr<'static>, Error> {
+    match expr {
+        syn::Expr::Lit(ExprLit {
+            lit: Lit::Int(int), ..
+        }) => Ok(DimensionExpr::Value(int.base10_parse()?)),
+        syn::Expr::Lit(ExprLit {
+            lit: Lit::Str(name),
+            ..
+        }) => Ok(DimensionExpr::Argument(name.value().into())),
+        syn::Expr::Call(syn::ExprCall { func, args, .. })
+            if matches!(&**func, syn::Expr::Path(p) if p.path.is_ident("input"))
+                && args.len() == 2 =>
+        {
+            let index = |e: &syn::Expr| match e {
+                syn::Expr::Lit(ExprLit {
+                    lit: Lit::Int(int), ..
+                }) => int.base10_parse(),
+                _ => Err(Error::new(e.span(), "Expected an integer")),
+            };
+
+            Ok(DimensionExpr::Input {
+                input: index(&args[0])?,
+                dimension: index(&args[1])?,
+            })
+        },
+        syn::Expr::Call(syn::ExprCall { func, args, .. })
+            if matches!(&**func, syn::Expr::Path(p) if p.path.is_ident("frames"))
+                && args.len() == 4 =>
+        {
+            let (input, dimension) = match parse_dimension_expr(&args[0])? {
+                DimensionExpr::Input { input, dimension } => (input, dimension),
+                _ => {
+                    return Err(Error::new(
+                        args[0].span(),
+                        "Expected input(index, dimension)",
+                    ))
+                },
+            };
+            let argument = |e: &syn::Expr| match e {
+                syn::Expr::Lit(ExprLit {
+                    lit: Lit::Str(name),
+                    ..
+                }) => Ok(name.value().into()),
+                _ => Err(Error::new(
+                    e.span(),
+                    "Expected the name of an argument",
+                )),
+            };
+
+            Ok(DimensionExpr::Frames {
+                input,
+                dimension,
+                window_size: argument(&args[1])?,
+                hop: argument(&args[2])?,
+                pad: argument(&args[3])?,
+            })
+        },
+        _ => Err(Error::new(
+            expr.span(),
+            "Expected an integer, the name of an argument, input(index, \
+             dimension), or frames(input(index, dimension), \"window_size\", \
+             \"hop\", \"pad\")",
+        )),
+    }
+}
+
 /// Parse either a single tensor descriptor (`[f32; 1]`) or multiple tensor
 /// descriptors inside parens (`([f32; 1], [u8; 1024])`).
 fn parse_tensor_descriptors(
@@ -291,15 +649,25 @@ impl Parse for DocAttr {
 
 fn remove_leading_space(s: &str) -> &str { s.strip_prefix(' ').unwrap_or(s) }
 
+/// The version of the crate currently being compiled.
+///
+/// Cargo sets `$CARGO_PKG_VERSION` for each crate it compiles, and that is
+/// visible to procedural macros running inside `rustc`.
+fn crate_version() -> String {
+    std::env::var("CARGO_PKG_VERSION").unwrap_or_default()
+}
+
 fn analyse_properties(
     input: &DeriveInput,
-) -> Result<(Setters, SetterAssertions), Error> {
+) -> Result<(Setters, Vec<ParameterDescriptor<'static>>, SetterAssertions), Error>
+{
     let data = match &input.data {
         syn::Data::Struct(s) => s,
         _ => return Err(Error::new(input.span(), "")),
     };
 
     let mut setters = Vec::new();
+    let mut parameters = Vec::new();
     let mut assertions = Vec::new();
 
     for field in &data.fields {
@@ -308,11 +676,23 @@ fn analyse_properties(
                 property,
                 property_type,
                 possible_types,
+                description,
+                range,
             } = parsed;
 
+            parameters.push(ParameterDescriptor {
+                name: property.to_string().into(),
+                type_name: type_name(&property_type).into(),
+                description: description.into(),
+                // Note: defaults come from the Default impl at runtime
+                default_value: None,
+                range,
+            });
+
             let new_assertions =
                 possible_types.into_iter().map(|ty| SetterAssertion {
                     proc_block_type: input.ident.clone(),
+                    generics: input.generics.clone(),
                     property: property.clone(),
                     setter_argument: ty,
                 });
@@ -333,14 +713,30 @@ fn analyse_properties(
             setters,
             generics: input.generics.clone(),
         },
+        parameters,
         SetterAssertions(assertions),
     ))
 }
 
+/// Get a human-friendly version of a type's name (i.e. `Vec<String>` instead
+/// of `Vec < String >`).
+fn type_name(ty: &syn::Type) -> String {
+    quote!(#ty)
+        .to_string()
+        .replace(" < ", "<")
+        .replace(" >", ">")
+        .replace(" , ", ", ")
+        .replace(" ; ", "; ")
+        .replace("[ ", "[")
+        .replace(" ]", "]")
+}
+
 struct ParsedField {
     property: Ident,
     property_type: syn::Type,
     possible_types: Vec<syn::Type>,
+    description: String,
+    range: Option<ParameterRange>,
 }
 
 fn parse_parameter(field: &syn::Field) -> Result<Option<ParsedField>, Error> {
@@ -363,11 +759,30 @@ fn parse_parameter(field: &syn::Field) -> Result<Option<ParsedField>, Error> {
     // TODO: Let people specify which other types can be used to set this
     // propert (e.g. because there is a From impl)
     let possible_types = vec![field.ty.clone()];
+    let description = doc_comments(&field.attrs)?;
+
+    let mut range = ParameterRange::default();
+
+    for attr in attrs {
+        match attr {
+            FieldAttribute::Skipped => {},
+            FieldAttribute::Min(min) => range.min = Some(min),
+            FieldAttribute::Max(max) => range.max = Some(max),
+        }
+    }
+
+    let range = if range == ParameterRange::default() {
+        None
+    } else {
+        Some(range)
+    };
 
     Ok(Some(ParsedField {
         property,
         property_type,
         possible_types,
+        description,
+        range,
     }))
 }
 
@@ -386,24 +801,52 @@ fn field_attributes(attrs: &[Attribute]) -> Result<Vec<FieldAttribute>, Error> {
 
 enum FieldAttribute {
     Skipped,
+    /// `#[proc_block(min = ...)]`
+    Min(f64),
+    /// `#[proc_block(max = ...)]`
+    Max(f64),
 }
 
 impl Parse for FieldAttribute {
     fn parse(input: ParseStream) -> Result<Self, Error> {
         let ident: Ident = input.parse()?;
 
-        if input.peek(Token![=]) {
-            todo!("Handle key=value attributes: {:?}", input);
+        if !input.peek(Token![=]) {
+            return if ident == "skip" {
+                Ok(FieldAttribute::Skipped)
+            } else {
+                Err(Error::new(ident.span(), "Unknown attribute"))
+            };
         }
 
-        if ident == "skip" {
-            Ok(FieldAttribute::Skipped)
+        let _: Token![=] = input.parse()?;
+        let negative: Option<Token![-]> = input.parse()?;
+        let value: Lit = input.parse()?;
+        let sign = if negative.is_some() { -1.0 } else { 1.0 };
+
+        if ident == "default" {
+            Err(Error::new(
+                ident.span(),
+                "The default value comes from the proc block's Default impl",
+            ))
+        } else if ident == "min" {
+            literal_as_number(&value).map(|v| FieldAttribute::Min(sign * v))
+        } else if ident == "max" {
+            literal_as_number(&value).map(|v| FieldAttribute::Max(sign * v))
         } else {
-            todo!("Handle #[proc_block({})]", ident)
+            Err(Error::new(ident.span(), "Unknown attribute"))
         }
     }
 }
 
+fn literal_as_number(lit: &Lit) -> Result<f64, Error> {
+    match lit {
+        Lit::Int(i) => i.base10_parse(),
+        Lit::Float(f) => f.base10_parse(),
+        _ => Err(Error::new(lit.span(), "Expected a number")),
+    }
+}
+
 struct NameValuePairs(Vec<FieldAttribute>);
 
 impl Parse for NameValuePairs {
@@ -428,6 +871,19 @@ fn make_custom_section(
     })
 }
 
+fn make_describe_function(
+    proc_block_type: &Ident,
+    descriptor: &ProcBlockDescriptor<'_>,
+) -> DescribeFunction {
+    let payload =
+        serde_json::to_vec(descriptor).expect("Serializing should never fail");
+
+    DescribeFunction {
+        type_name: proc_block_type.clone(),
+        payload,
+    }
+}
+
 fn export_path(_attrs: &[syn::Attribute]) -> Result<Path, Error> {
     let default = syn::parse_str("hotg_rune_proc_blocks::internal")
         .expect("Hard-coded values should always parse");
@@ -488,6 +944,7 @@ mod tests {
             exports: exports.clone(),
             assertions: vec![
                 TransformAssertion {
+                    generics: Vec::new(),
                     inputs: vec![syn::parse_str(
                         "hotg_rune_proc_blocks::internal::Tensor<f32>",
                     )
@@ -498,6 +955,7 @@ mod tests {
                     .unwrap()],
                 },
                 TransformAssertion {
+                    generics: Vec::new(),
                     inputs: vec![syn::parse_str(
                         "hotg_rune_proc_blocks::internal::Tensor<f32>",
                     )
@@ -511,14 +969,153 @@ mod tests {
         };
 
         let (description, available_transforms, transform_assertions) =
-            analyse_struct_attributes(&input.ident, &exports, &input.attrs)
-                .unwrap();
+            analyse_struct_attributes(
+                &input.ident,
+                &input.generics,
+                &exports,
+                &input.attrs,
+            )
+            .unwrap();
 
         assert_eq!(description, expected_description);
         assert_eq!(available_transforms, expected_transforms);
         assert_eq!(transform_assertions, expected_assertions);
     }
 
+    #[test]
+    fn generic_transforms_are_instantiated_for_each_element_type() {
+        let tokens = quote! {
+            #[derive(ProcBlock)]
+            #[proc_block(element_type(T = [f32, i16]))]
+            #[transform(inputs = [T; _], outputs = [T; _])]
+            struct Normalize<T> {
+                #[proc_block(skip)]
+                _element: PhantomData<T>,
+            }
+        };
+        let input: DeriveInput = syn::parse2(tokens).unwrap();
+        let exports: Path =
+            syn::parse_str("hotg_rune_proc_blocks::internal").unwrap();
+        let any_shape = |element_type| -> TensorDescriptors<'static> {
+            TensorDescriptor {
+                element_type,
+                dimensions: Dimensions::Arbitrary,
+            }
+            .into()
+        };
+
+        let (_, available_transforms, transform_assertions) =
+            analyse_struct_attributes(
+                &input.ident,
+                &input.generics,
+                &exports,
+                &input.attrs,
+            )
+            .unwrap();
+
+        assert_eq!(
+            available_transforms,
+            vec![
+                TransformDescriptor {
+                    inputs: any_shape(ElementType::F32),
+                    outputs: any_shape(ElementType::F32),
+                },
+                TransformDescriptor {
+                    inputs: any_shape(ElementType::I16),
+                    outputs: any_shape(ElementType::I16),
+                },
+            ]
+        );
+        let generics: Vec<Vec<syn::Type>> = transform_assertions
+            .assertions
+            .into_iter()
+            .map(|a| a.generics)
+            .collect();
+        assert_eq!(
+            generics,
+            vec![
+                vec![syn::parse_str("f32").unwrap()],
+                vec![syn::parse_str("i16").unwrap()],
+            ]
+        );
+    }
+
+    #[test]
+    fn element_types_must_be_for_a_type_parameter() {
+        let tokens = quote! {
+            #[derive(ProcBlock)]
+            #[proc_block(element_type(U = f32))]
+            struct Normalize<T> {
+                #[proc_block(skip)]
+                _element: PhantomData<T>,
+            }
+        };
+        let input: DeriveInput = syn::parse2(tokens).unwrap();
+
+        let err =
+            element_type_parameters(&input.generics, &input.attrs).unwrap_err();
+
+        assert_eq!(err.to_string(), "\"U\" isn't a generic type parameter");
+    }
+
+    #[test]
+    fn output_shape_attributes() {
+        let tokens = quote! {
+            #[derive(ProcBlock)]
+            #[output_shape(input = 0)]
+            #[output_shape(f32, [1, input(0, 1), "bins"])]
+            struct Proc {}
+        };
+        let input: DeriveInput = syn::parse2(tokens).unwrap();
+        let expected = vec![
+            ShapeTransfer::SameAsInput(0),
+            ShapeTransfer::Computed {
+                element_type: ElementType::F32,
+                dimensions: vec![
+                    DimensionExpr::Value(1),
+                    DimensionExpr::Input {
+                        input: 0,
+                        dimension: 1,
+                    },
+                    DimensionExpr::Argument("bins".into()),
+                ]
+                .into(),
+            },
+        ];
+
+        let got = output_shapes(&input.attrs).unwrap();
+
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn frames_output_shape() {
+        let tokens = quote! {
+            #[derive(ProcBlock)]
+            #[output_shape(f32, [frames(input(0, 0), "size", "hop", "pad"), "size"])]
+            struct Proc {}
+        };
+        let input: DeriveInput = syn::parse2(tokens).unwrap();
+        let expected = vec![ShapeTransfer::Computed {
+            element_type: ElementType::F32,
+            dimensions: vec![
+                DimensionExpr::Frames {
+                    input: 0,
+                    dimension: 0,
+                    window_size: "size".into(),
+                    hop: "hop".into(),
+                    pad: "pad".into(),
+                },
+                DimensionExpr::Argument("size".into()),
+            ]
+            .into(),
+        }];
+
+        let got = output_shapes(&input.attrs).unwrap();
+
+        assert_eq!(got, expected);
+    }
+
     #[test]
     fn properties() {
         let tokens = quote! {
@@ -540,16 +1137,64 @@ mod tests {
         };
         let expected_assertions = SetterAssertions(vec![SetterAssertion {
             proc_block_type: syn::parse_str("Proc").unwrap(),
+            generics: Generics::default(),
             property: syn::parse_str("first").unwrap(),
             setter_argument: syn::parse_str("u32").unwrap(),
         }]);
 
-        let (setters, assertions) = analyse_properties(&input).unwrap();
+        let expected_parameters = vec![ParameterDescriptor {
+            name: "first".into(),
+            type_name: "u32".into(),
+            description: "The first item.".into(),
+            default_value: None,
+            range: None,
+        }];
+
+        let (setters, parameters, assertions) =
+            analyse_properties(&input).unwrap();
 
         assert_eq!(setters, expected_setters);
+        assert_eq!(parameters, expected_parameters);
         assert_eq!(assertions, expected_assertions);
     }
 
+    #[test]
+    fn property_ranges() {
+        let tokens = quote! {
+            struct Proc {
+                #[proc_block(min = -1, max = 1.0)]
+                gain: f32,
+                window: String,
+            }
+        };
+        let input: DeriveInput = syn::parse2(tokens).unwrap();
+
+        let (_, parameters, _) = analyse_properties(&input).unwrap();
+
+        assert_eq!(parameters[0].default_value, None);
+        assert_eq!(
+            parameters[0].range,
+            Some(ParameterRange {
+                min: Some(-1.0),
+                max: Some(1.0)
+            })
+        );
+        assert_eq!(parameters[1].range, None);
+    }
+
+    #[test]
+    fn defaults_come_from_the_default_impl() {
+        let tokens = quote! {
+            struct Proc {
+                #[proc_block(default = 0.5)]
+                gain: f32,
+            }
+        };
+        let input: DeriveInput = syn::parse2(tokens).unwrap();
+
+        assert!(analyse_properties(&input).is_err());
+    }
+
     #[test]
     fn transform_assertion_automatically_wraps_in_tensor() {
         let inputs = vec![
@@ -652,6 +1297,34 @@ mod type_tests {
         },
     );
 
+    #[test]
+    fn element_type_shorthand_declares_each_combination() {
+        let tokens = quote!(input = [i16, f32], output = f32);
+        let any_shape = |element_type| -> TensorDescriptors<'static> {
+            TensorDescriptor {
+                element_type,
+                dimensions: Dimensions::Arbitrary,
+            }
+            .into()
+        };
+
+        let got = parse_transform_attribute.parse2(tokens).unwrap();
+
+        assert_eq!(
+            got,
+            vec![
+                TransformDescriptor {
+                    inputs: any_shape(ElementType::I16),
+                    outputs: any_shape(ElementType::F32),
+                },
+                TransformDescriptor {
+                    inputs: any_shape(ElementType::F32),
+                    outputs: any_shape(ElementType::F32),
+                },
+            ]
+        );
+    }
+
     parse_transform_attribute!(transform_attribute_with_multiple_inputs,
         #[transform(inputs = ([f32; 1], [u8; 2]), outputs = [f32; 1])] =>
         TransformDescriptor {
@@ -1,17 +1,18 @@
 use hotg_rune_core::ElementType;
-use proc_macro2::{Ident, Span, TokenStream};
+use proc_macro2::{Ident, Literal, Span, TokenStream};
 use quote::{quote, ToTokens};
-use syn::{Generics, LitByteStr, Path, Type};
+use syn::{LitByteStr, Path, Type};
 
 use crate::{
     descriptor::{
-        Dimension, Dimensions, ProcBlockDescriptor, TensorDescriptor,
+        Dimension, DimensionExpr, Dimensions, ParameterDescriptor,
+        ParameterRange, ProcBlockDescriptor, ShapeTransfer, TensorDescriptor,
         TransformDescriptor,
     },
     types::{
-        Assertions, CustomSection, DeriveOutput, ProcBlockImpl, Setter,
-        SetterAssertion, SetterAssertions, Setters, TransformAssertion,
-        TransformAssertions,
+        Assertions, CustomSection, DeriveOutput, DescribeFunction,
+        ProcBlockImpl, Setter, SetterAssertion, SetterAssertions, Setters,
+        TransformAssertion, TransformAssertions,
     },
 };
 
@@ -20,31 +21,19 @@ impl ToTokens for DeriveOutput {
         let DeriveOutput {
             trait_impl,
             custom_section,
+            describe_function,
             setters,
             assertions,
         } = self;
 
         trait_impl.to_tokens(tokens);
         custom_section.to_tokens(tokens);
+        describe_function.to_tokens(tokens);
         setters.to_tokens(tokens);
         assertions.to_tokens(tokens);
     }
 }
 
-fn generic_parameters(g: &Generics) -> (TokenStream, TokenStream) {
-    if g.params.is_empty() {
-        return (TokenStream::new(), TokenStream::new());
-    }
-
-    let params = g.params.iter();
-    let impl_generics = quote!(<#(#params),*>);
-
-    let params = g.type_params().map(|p| &p.ident);
-    let type_generics = quote!(<#(#params),*>);
-
-    (impl_generics, type_generics)
-}
-
 impl ToTokens for Setters {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let Setters {
@@ -53,10 +42,11 @@ impl ToTokens for Setters {
             generics,
         } = self;
 
-        let (impl_generics, type_generics) = generic_parameters(generics);
+        let (impl_generics, type_generics, where_clause) =
+            generics.split_for_impl();
 
         let t = quote! {
-            impl #impl_generics #type_name #type_generics  {
+            impl #impl_generics #type_name #type_generics #where_clause {
                 #( #setters )*
             }
         };
@@ -85,9 +75,12 @@ impl ToTokens for SetterAssertion {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let SetterAssertion {
             proc_block_type,
+            generics,
             property,
             ..
         } = self;
+        let (impl_generics, type_generics, where_clause) =
+            generics.split_for_impl();
 
         let assertion_name = format!("_assert_{}_is_settable", property);
         let assertion_name = Ident::new(&assertion_name, property.span());
@@ -96,7 +89,7 @@ impl ToTokens for SetterAssertion {
 
         let t = quote! {
             const _: () = {
-                fn #assertion_name(proc_block: &mut #proc_block_type, #property: &str) {
+                fn #assertion_name #impl_generics (proc_block: &mut #proc_block_type #type_generics, #property: &str) #where_clause {
                     fn assert_return_is_result_debug(_: Result<(), impl core::fmt::Debug>) {}
 
                     let result = proc_block.#setter_name(#property);
@@ -131,7 +124,7 @@ impl ToTokens for CustomSection {
         let t = quote! {
             #[doc(hidden)]
             #[no_mangle]
-            #[cfg(target_arch = "wasm32")]
+            #[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
             #[link_section = #section_name]
             pub static #name: [u8; #len] = *#payload;
         };
@@ -139,6 +132,51 @@ impl ToTokens for CustomSection {
     }
 }
 
+impl ToTokens for DescribeFunction {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let DescribeFunction { type_name, payload } = self;
+        let len = payload.len();
+        let payload = LitByteStr::new(payload, Span::call_site());
+
+        let name = format!(
+            "{}{}",
+            ProcBlockDescriptor::DESCRIBE_FUNCTION_PREFIX,
+            type_name
+        );
+        let name = Ident::new(&name, type_name.span());
+
+        // Note: Unlike the custom section, this copy of the descriptor lives
+        // in linear memory so the host can read it after calling the
+        // function. We return a pointer to a (pointer, length) pair instead
+        // of packing them into an integer so it works for wasm64 too.
+
+        let t = quote! {
+            #[doc(hidden)]
+            #[no_mangle]
+            #[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
+            pub extern "C" fn #name() -> *const u8 {
+                #[repr(C)]
+                struct Descriptor {
+                    data: *const u8,
+                    len: usize,
+                }
+
+                // Safety: The descriptor is never modified.
+                unsafe impl Sync for Descriptor {}
+
+                static DESCRIPTOR: [u8; #len] = *#payload;
+                static DESCRIPTOR_REF: Descriptor = Descriptor {
+                    data: DESCRIPTOR.as_ptr(),
+                    len: #len,
+                };
+
+                &DESCRIPTOR_REF as *const Descriptor as *const u8
+            }
+        };
+        tokens.extend(t);
+    }
+}
+
 impl ToTokens for Setter {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let Setter {
@@ -175,7 +213,12 @@ impl ToTokens for TransformAssertions {
 
         let assertions = assertions
             .iter()
-            .map(|TransformAssertion { inputs, outputs }| {
+            .map(|TransformAssertion { generics, inputs, outputs }| {
+                let proc_block_type = if generics.is_empty() {
+                    quote!(#proc_block_type)
+                } else {
+                    quote!(#proc_block_type<#(#generics),*>)
+                };
                 let inputs = transform_assertion_type(inputs);
                 let outputs = transform_assertion_type(outputs);
                 quote! {
@@ -217,17 +260,76 @@ impl ToTokens for ProcBlockImpl {
 
         let descriptor = descriptor_to_tokens(exports, descriptor);
 
-        let (impl_generics, type_generics) = generic_parameters(generics);
+        // The ProcBlock trait requires Default + 'static, which a generic
+        // proc block only satisfies for some type parameters.
+        let mut generics = generics.clone();
+        if generics.type_params().next().is_some() {
+            generics
+                .make_where_clause()
+                .predicates
+                .push(syn::parse_quote!(Self: Default + 'static));
+        }
+
+        let (impl_generics, type_generics, where_clause) =
+            generics.split_for_impl();
+
+        let accessors = parameter_accessors(exports, &self.descriptor);
 
         let t = quote! {
-            impl #impl_generics #exports::ProcBlock for #type_name #type_generics  {
+            impl #impl_generics #exports::ProcBlock for #type_name #type_generics #where_clause {
                 const DESCRIPTOR: #exports::ProcBlockDescriptor<'static> = #descriptor;
+                #accessors
             }
         };
         tokens.extend(t);
     }
 }
 
+/// Dispatch `ProcBlock::set_parameter()` and `ProcBlock::get_parameter()` to
+/// each field, falling back to the trait's defaults when there are no
+/// parameters.
+fn parameter_accessors(
+    exports: &Path,
+    d: &ProcBlockDescriptor<'_>,
+) -> TokenStream {
+    if d.parameters.is_empty() {
+        return TokenStream::new();
+    }
+
+    let names: Vec<&str> =
+        d.parameters.iter().map(|p| p.name.as_ref()).collect();
+    let fields = names.iter().map(|name| Ident::new(name, Span::call_site()));
+    let setters = names
+        .iter()
+        .map(|name| Ident::new(&format!("set_{}", name), Span::call_site()));
+
+    quote! {
+        fn set_parameter(
+            &mut self,
+            name: &str,
+            value: &str,
+        ) -> Result<(), #exports::SetParameterError> {
+            match name {
+                #(
+                    #names => self.#setters(value).map_err(#exports::SetParameterError::invalid_value),
+                )*
+                _ => Err(#exports::SetParameterError::UnknownParameter),
+            }
+        }
+
+        fn get_parameter(&self, name: &str) -> Option<#exports::String> {
+            use #exports::{DisplayParameter as _, OpaqueParameter as _};
+
+            match name {
+                #(
+                    #names => (&&#exports::Parameter(&self.#fields)).parameter_value(),
+                )*
+                _ => None,
+            }
+        }
+    }
+}
+
 fn descriptor_to_tokens<'a, 'b: 'a>(
     exports: &'a Path,
     d: &'a ProcBlockDescriptor<'b>,
@@ -235,24 +337,93 @@ fn descriptor_to_tokens<'a, 'b: 'a>(
     let ProcBlockDescriptor {
         type_name,
         description,
+        version,
+        parameters,
         available_transforms,
+        output_shapes,
     } = d;
 
+    let parameters = parameters
+        .iter()
+        .map(|parameter| parameter_to_tokens(exports, parameter));
     let available_transforms = available_transforms
         .iter()
         .map(|transform| transform_to_tokens(exports, transform));
+    let output_shapes = output_shapes
+        .iter()
+        .map(|shape| shape_transfer_to_tokens(exports, shape));
 
     quote! {
         #exports::ProcBlockDescriptor {
             type_name: #exports::Cow::Borrowed(#type_name),
             description: #exports::Cow::Borrowed(#description),
+            version: #exports::Cow::Borrowed(#version),
+            parameters: #exports::Cow::Borrowed(&[
+                #( #parameters ),*
+            ]),
             available_transforms: #exports::Cow::Borrowed(&[
                 #( #available_transforms ),*
             ]),
+            output_shapes: #exports::Cow::Borrowed(&[
+                #( #output_shapes ),*
+            ]),
         }
     }
 }
 
+fn parameter_to_tokens(
+    exports: &Path,
+    parameter: &ParameterDescriptor<'_>,
+) -> TokenStream {
+    let ParameterDescriptor {
+        name,
+        type_name,
+        description,
+        default_value,
+        range,
+    } = parameter;
+
+    let default_value = match default_value {
+        Some(value) => {
+            let value: &str = value;
+            quote!(Some(#exports::Cow::Borrowed(#value)))
+        },
+        None => quote!(None),
+    };
+    let range = match range {
+        Some(ParameterRange { min, max }) => {
+            let min = optional_f64_to_tokens(*min);
+            let max = optional_f64_to_tokens(*max);
+            quote!(Some(#exports::ParameterRange { min: #min, max: #max }))
+        },
+        None => quote!(None),
+    };
+
+    quote! {
+        #exports::ParameterDescriptor {
+            name: #exports::Cow::Borrowed(#name),
+            type_name: #exports::Cow::Borrowed(#type_name),
+            description: #exports::Cow::Borrowed(#description),
+            default_value: #default_value,
+            range: #range,
+        }
+    }
+}
+
+fn optional_f64_to_tokens(value: Option<f64>) -> TokenStream {
+    match value {
+        Some(v) if v < 0.0 => {
+            let v = Literal::f64_suffixed(-v);
+            quote!(Some(-#v))
+        },
+        Some(v) => {
+            let v = Literal::f64_suffixed(v);
+            quote!(Some(#v))
+        },
+        None => quote!(None),
+    }
+}
+
 fn transform_to_tokens(
     exports: &Path,
     transform: &TransformDescriptor<'_>,
@@ -310,6 +481,8 @@ fn element_type_to_tokens(exports: &Path, ty: ElementType) -> TokenStream {
         ElementType::U64 => "U64",
         ElementType::F64 => "F64",
         ElementType::I64 => "I64",
+        ElementType::F16 => "F16",
+        ElementType::BF16 => "BF16",
         ElementType::String => "String",
     };
     let ident = Ident::new(name, Span::call_site());
@@ -335,6 +508,71 @@ fn dimensions_to_tokens(
     }
 }
 
+fn shape_transfer_to_tokens(
+    exports: &Path,
+    shape: &ShapeTransfer<'_>,
+) -> TokenStream {
+    match shape {
+        ShapeTransfer::SameAsInput(index) => {
+            quote!(#exports::ShapeTransfer::SameAsInput(#index))
+        },
+        ShapeTransfer::Computed {
+            element_type,
+            dimensions,
+        } => {
+            let element_type = element_type_to_tokens(exports, *element_type);
+            let dimensions = dimensions.iter().map(|d| match d {
+                DimensionExpr::Value(v) => {
+                    quote!(#exports::DimensionExpr::Value(#v))
+                },
+                DimensionExpr::Input { input, dimension } => quote! {
+                    #exports::DimensionExpr::Input {
+                        input: #input,
+                        dimension: #dimension,
+                    }
+                },
+                DimensionExpr::Argument(name) => {
+                    let name: &str = name;
+                    quote! {
+                        #exports::DimensionExpr::Argument(
+                            #exports::Cow::Borrowed(#name),
+                        )
+                    }
+                },
+                DimensionExpr::Frames {
+                    input,
+                    dimension,
+                    window_size,
+                    hop,
+                    pad,
+                } => {
+                    let window_size: &str = window_size;
+                    let hop: &str = hop;
+                    let pad: &str = pad;
+                    quote! {
+                        #exports::DimensionExpr::Frames {
+                            input: #input,
+                            dimension: #dimension,
+                            window_size: #exports::Cow::Borrowed(#window_size),
+                            hop: #exports::Cow::Borrowed(#hop),
+                            pad: #exports::Cow::Borrowed(#pad),
+                        }
+                    }
+                },
+            });
+
+            quote! {
+                #exports::ShapeTransfer::Computed {
+                    element_type: #element_type,
+                    dimensions: #exports::Cow::Borrowed(&[
+                        #( #dimensions ),*
+                    ]),
+                }
+            }
+        },
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 struct DimensionProxy<'a> {
     exports: &'a Path,
@@ -417,6 +655,7 @@ mod tests {
     fn setter_assertion() {
         let assertion = SetterAssertion {
             proc_block_type: syn::parse_str("Proc").unwrap(),
+            generics: Generics::default(),
             property: syn::parse_str("first").unwrap(),
             setter_argument: syn::parse_str("f32").unwrap(),
         };
@@ -446,7 +685,7 @@ mod tests {
         let should_be = quote! {
             #[doc(hidden)]
             #[no_mangle]
-            #[cfg(target_arch = "wasm32")]
+            #[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
             #[link_section = ".rune_proc_block"]
             pub static PROC_BLOCK_DESCRIPTOR_FOR_Proc: [u8; 13usize] = *b"Hello, World!";
         };
@@ -456,6 +695,66 @@ mod tests {
         assert_eq_tok!(got, should_be);
     }
 
+    #[test]
+    fn describe_function() {
+        let function = DescribeFunction {
+            type_name: syn::parse_str("Proc").unwrap(),
+            payload: b"{}".to_vec(),
+        };
+        let should_be = quote! {
+            #[doc(hidden)]
+            #[no_mangle]
+            #[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
+            pub extern "C" fn _rune_proc_block_describe_Proc() -> *const u8 {
+                #[repr(C)]
+                struct Descriptor {
+                    data: *const u8,
+                    len: usize,
+                }
+
+                // Safety: The descriptor is never modified.
+                unsafe impl Sync for Descriptor {}
+
+                static DESCRIPTOR: [u8; 2usize] = *b"{}";
+                static DESCRIPTOR_REF: Descriptor = Descriptor {
+                    data: DESCRIPTOR.as_ptr(),
+                    len: 2usize,
+                };
+
+                &DESCRIPTOR_REF as *const Descriptor as *const u8
+            }
+        };
+
+        let got = function.to_token_stream();
+
+        assert_eq_tok!(got, should_be);
+    }
+
+    #[test]
+    fn parameter() {
+        let exports = syn::parse_str("exports").unwrap();
+        let parameter = ParameterDescriptor {
+            name: "first".into(),
+            type_name: "u32".into(),
+            description: "The first item.".into(),
+            default_value: None,
+            range: None,
+        };
+        let should_be = quote! {
+            exports::ParameterDescriptor {
+                name: exports::Cow::Borrowed("first"),
+                type_name: exports::Cow::Borrowed("u32"),
+                description: exports::Cow::Borrowed("The first item."),
+                default_value: None,
+                range: None,
+            }
+        };
+
+        let got = parameter_to_tokens(&exports, &parameter);
+
+        assert_eq_tok!(got, should_be);
+    }
+
     #[test]
     fn setter_implementation() {
         let setter = Setter {
@@ -483,6 +782,7 @@ mod tests {
             proc_block_type: syn::parse_str("Proc").unwrap(),
             exports: syn::parse_str("exports").unwrap(),
             assertions: vec![TransformAssertion {
+                generics: Vec::new(),
                 inputs: vec![syn::parse_str(
                     "exports::Tensor<Cow<'static, str>>",
                 )
@@ -517,6 +817,7 @@ mod tests {
             proc_block_type: syn::parse_str("Proc").unwrap(),
             exports: syn::parse_str("exports").unwrap(),
             assertions: vec![TransformAssertion {
+                generics: Vec::new(),
                 inputs: vec![syn::parse_str("exports::Tensor<f32>").unwrap()],
                 outputs: vec![syn::parse_str("exports::Tensor<u8>").unwrap()],
             }],
@@ -547,7 +848,10 @@ mod tests {
             descriptor: ProcBlockDescriptor {
                 type_name: "Proc".into(),
                 description: "Hello, World!".into(),
+                version: "1.2.3".into(),
+                parameters: Cow::default(),
                 available_transforms: Cow::default(),
+                output_shapes: Cow::default(),
             },
             generics: Generics::default(),
         };
@@ -556,7 +860,10 @@ mod tests {
                 const DESCRIPTOR: exports::ProcBlockDescriptor<'static> = exports::ProcBlockDescriptor {
                     type_name: exports::Cow::Borrowed("Proc"),
                     description: exports::Cow::Borrowed("Hello, World!"),
+                    version: exports::Cow::Borrowed("1.2.3"),
+                    parameters: exports::Cow::Borrowed(&[]),
                     available_transforms: exports::Cow::Borrowed(&[]),
+                    output_shapes: exports::Cow::Borrowed(&[]),
                 };
             }
         };
@@ -608,4 +915,40 @@ mod tests {
 
         assert_eq_tok!(got, should_be);
     }
+
+    #[test]
+    fn computed_shape_transfer() {
+        let exports = syn::parse_str("exports").unwrap();
+        let shape = ShapeTransfer::Computed {
+            element_type: ElementType::F32,
+            dimensions: vec![
+                DimensionExpr::Value(1),
+                DimensionExpr::Input {
+                    input: 0,
+                    dimension: 1,
+                },
+                DimensionExpr::Argument("bins".into()),
+            ]
+            .into(),
+        };
+        let should_be = quote! {
+            exports::ShapeTransfer::Computed {
+                element_type: exports::ElementType::F32,
+                dimensions: exports::Cow::Borrowed(&[
+                    exports::DimensionExpr::Value(1usize),
+                    exports::DimensionExpr::Input {
+                        input: 0usize,
+                        dimension: 1usize,
+                    },
+                    exports::DimensionExpr::Argument(
+                        exports::Cow::Borrowed("bins"),
+                    ),
+                ]),
+            }
+        };
+
+        let got = shape_transfer_to_tokens(&exports, &shape);
+
+        assert_eq_tok!(got, should_be);
+    }
 }
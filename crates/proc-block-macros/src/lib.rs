@@ -17,7 +17,7 @@ use quote::ToTokens;
 use syn::DeriveInput;
 
 /// Derive the `ProcBlock` trait for a particular type.
-#[proc_macro_derive(ProcBlock, attributes(transform, proc_block))]
+#[proc_macro_derive(ProcBlock, attributes(transform, proc_block, output_shape))]
 pub fn proc_block(input: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(input as DeriveInput);
 
@@ -7,6 +7,7 @@ use crate::descriptor::ProcBlockDescriptor;
 pub(crate) struct DeriveOutput {
     pub trait_impl: ProcBlockImpl,
     pub custom_section: CustomSection,
+    pub describe_function: DescribeFunction,
     pub setters: Setters,
     pub assertions: Assertions,
 }
@@ -25,6 +26,14 @@ pub(crate) struct CustomSection {
     pub payload: Vec<u8>,
 }
 
+/// An exported function which lets a host ask the proc block for its
+/// serialized [`ProcBlockDescriptor`].
+#[derive(Debug)]
+pub(crate) struct DescribeFunction {
+    pub type_name: Ident,
+    pub payload: Vec<u8>,
+}
+
 #[derive(Debug, PartialEq)]
 pub(crate) struct Setters {
     pub type_name: Ident,
@@ -50,6 +59,7 @@ pub(crate) struct SetterAssertions(pub Vec<SetterAssertion>);
 #[derive(Debug, PartialEq)]
 pub(crate) struct SetterAssertion {
     pub proc_block_type: Ident,
+    pub generics: Generics,
     pub property: Ident,
     pub setter_argument: Type,
 }
@@ -64,6 +74,8 @@ pub(crate) struct TransformAssertions {
 /// An assertion that our type implements `Transform<$input, Output=$output>`.
 #[derive(Debug, PartialEq)]
 pub(crate) struct TransformAssertion {
+    /// The concrete types to use for the proc block's generic parameters.
+    pub generics: Vec<Type>,
     pub inputs: Vec<Type>,
     pub outputs: Vec<Type>,
 }
@@ -0,0 +1,225 @@
+//! Assertions which give the user more context than a plain `assert!()`.
+//!
+//! When a [`rune_assert!()`][crate::rune_assert] fails inside a Rune, the
+//! expression, an optional message, and any captured values are sent to the
+//! runtime before the Rune aborts. The runtime combines that with the name of
+//! the stage that was running to produce a structured error instead of a
+//! generic trap.
+//!
+//! Outside of WebAssembly (e.g. in your proc block's unit tests) these
+//! behave just like the standard library's assertions.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::{Arguments, Debug};
+
+/// Check that a condition holds, aborting the Rune with a descriptive error
+/// if it doesn't.
+///
+/// The syntax is the same as [`assert!()`].
+///
+/// ```rust
+/// use hotg_rune_proc_blocks::rune_assert;
+///
+/// let frame_width = 3;
+/// let input = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+///
+/// rune_assert!(
+///     input.len() % frame_width == 0,
+///     "The input should contain whole frames of {} values",
+///     frame_width,
+/// );
+/// ```
+#[macro_export]
+macro_rules! rune_assert {
+    ($condition:expr $(,)?) => {
+        if !$condition {
+            $crate::assertions::failed(
+                ::core::stringify!($condition),
+                ::core::option::Option::None,
+                &[],
+            );
+        }
+    };
+    ($condition:expr, $($arg:tt)+) => {
+        if !$condition {
+            $crate::assertions::failed(
+                ::core::stringify!($condition),
+                ::core::option::Option::Some(::core::format_args!($($arg)+)),
+                &[],
+            );
+        }
+    };
+}
+
+/// Check that two values are equal, aborting the Rune with a descriptive
+/// error containing both values if they aren't.
+///
+/// The syntax is the same as [`assert_eq!()`].
+///
+/// ```rust
+/// use hotg_rune_proc_blocks::rune_assert_eq;
+///
+/// let dimensions = [1, 3];
+///
+/// rune_assert_eq!(dimensions.len(), 2, "Expected a 2D tensor");
+/// ```
+#[macro_export]
+macro_rules! rune_assert_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::__rune_assert_cmp!(==, $left, $right, ::core::option::Option::None)
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::__rune_assert_cmp!(
+            ==,
+            $left,
+            $right,
+            ::core::option::Option::Some(::core::format_args!($($arg)+))
+        )
+    };
+}
+
+/// Check that two values are not equal, aborting the Rune with a descriptive
+/// error containing both values if they are.
+///
+/// The syntax is the same as [`assert_ne!()`].
+#[macro_export]
+macro_rules! rune_assert_ne {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::__rune_assert_cmp!(!=, $left, $right, ::core::option::Option::None)
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::__rune_assert_cmp!(
+            !=,
+            $left,
+            $right,
+            ::core::option::Option::Some(::core::format_args!($($arg)+))
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rune_assert_cmp {
+    ($op:tt, $left:expr, $right:expr, $message:expr) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if !(*left $op *right) {
+                    $crate::assertions::failed(
+                        ::core::concat!(
+                            ::core::stringify!($left),
+                            " ",
+                            ::core::stringify!($op),
+                            " ",
+                            ::core::stringify!($right),
+                        ),
+                        $message,
+                        &[
+                            ("left", left as &dyn ::core::fmt::Debug),
+                            ("right", right as &dyn ::core::fmt::Debug),
+                        ],
+                    );
+                }
+            },
+        }
+    };
+}
+
+/// Report a failed assertion and abort.
+///
+/// This is an implementation detail of the `rune_assert!()` macros.
+#[doc(hidden)]
+#[track_caller]
+#[cold]
+pub fn failed(
+    expression: &str,
+    message: Option<Arguments<'_>>,
+    values: &[(&str, &dyn Debug)],
+) -> ! {
+    let message = message.map(|m| format!("{}", m)).unwrap_or_default();
+    let values: Vec<(&str, String)> = values
+        .iter()
+        .map(|(name, value)| (*name, format!("{:?}", value)))
+        .collect();
+
+    report(expression, &message, &values);
+
+    if message.is_empty() {
+        panic!("assertion failed: {}", expression);
+    } else {
+        panic!("assertion failed: {}: {}", expression, message);
+    }
+}
+
+#[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
+fn report(expression: &str, message: &str, values: &[(&str, String)]) {
+    #[repr(C)]
+    struct StringRef {
+        data: *const u8,
+        len: usize,
+    }
+
+    impl From<&str> for StringRef {
+        fn from(s: &str) -> StringRef {
+            StringRef {
+                data: s.as_ptr(),
+                len: s.len(),
+            }
+        }
+    }
+
+    extern "C" {
+        fn rune_assertion_failed(
+            expression: *const u8,
+            expression_len: usize,
+            message: *const u8,
+            message_len: usize,
+            names: *const StringRef,
+            values: *const StringRef,
+            count: u32,
+        ) -> u32;
+    }
+
+    let names: Vec<StringRef> = values
+        .iter()
+        .map(|(name, _)| StringRef::from(*name))
+        .collect();
+    let values: Vec<StringRef> = values
+        .iter()
+        .map(|(_, value)| StringRef::from(value.as_str()))
+        .collect();
+
+    // Safety: All pointers are valid for the duration of this call and the
+    // runtime only reads from them.
+    unsafe {
+        rune_assertion_failed(
+            expression.as_ptr(),
+            expression.len(),
+            message.as_ptr(),
+            message.len(),
+            names.as_ptr(),
+            values.as_ptr(),
+            names.len() as u32,
+        );
+    }
+}
+
+#[cfg(not(any(target_arch = "wasm32", target_arch = "wasm64")))]
+fn report(_expression: &str, _message: &str, _values: &[(&str, String)]) {
+    // There is no runtime to tell, so the panic message is all we've got.
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[should_panic(expected = "assertion failed: 1 + 1 == 3: bad maths")]
+    fn failed_assertions_panic_natively() {
+        crate::rune_assert_eq!(1 + 1, 3, "bad maths");
+    }
+
+    #[test]
+    fn passing_assertions_do_nothing() {
+        crate::rune_assert!(1 + 1 == 2);
+        crate::rune_assert_eq!(1 + 1, 2);
+        crate::rune_assert_ne!(1 + 1, 3, "{}", "unused");
+    }
+}
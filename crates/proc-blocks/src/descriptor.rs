@@ -1,4 +1,4 @@
-use alloc::borrow::Cow;
+use alloc::{borrow::Cow, string::String, vec::Vec};
 use core::{
     fmt::{self, Display, Formatter},
     iter::FromIterator,
@@ -16,11 +16,109 @@ pub struct ProcBlockDescriptor<'a> {
     /// of a short one-line summary with more information in subsequent
     /// paragraphs.
     pub description: Cow<'a, str>,
+    /// The version of the crate this proc block was defined in.
+    #[serde(default)]
+    pub version: Cow<'a, str>,
+    /// The properties that can be set on this proc block.
+    #[serde(default)]
+    pub parameters: Cow<'a, [ParameterDescriptor<'a>]>,
     pub available_transforms: Cow<'a, [TransformDescriptor<'a>]>,
+    /// How to calculate the shape of each output, letting a Runefile leave
+    /// out the proc block's `outputs`.
+    ///
+    /// This is empty when the proc block doesn't declare any
+    /// [`ShapeTransfer`]s.
+    #[serde(default)]
+    pub output_shapes: Cow<'a, [ShapeTransfer<'a>]>,
 }
 
 impl<'a> ProcBlockDescriptor<'a> {
     pub const CUSTOM_SECTION_NAME: &'static str = ".rune_proc_block";
+    /// The prefix used for the exported function which returns a proc block's
+    /// serialized descriptor (e.g. `_rune_proc_block_describe_Normalize`).
+    ///
+    /// The function takes no arguments and returns a pointer to a pair of
+    /// pointer-sized integers, the address of the JSON-encoded
+    /// [`ProcBlockDescriptor`] followed by its length (i.e. the same layout a
+    /// `StringRef` uses), so it works for both wasm32 and wasm64 Runes.
+    pub const DESCRIBE_FUNCTION_PREFIX: &'static str =
+        "_rune_proc_block_describe_";
+
+    /// Every [`ElementType`] accepted or generated by one of this proc
+    /// block's transforms, in the order they first appear.
+    ///
+    /// [`ElementType`]: hotg_rune_core::ElementType
+    pub fn supported_element_types(
+        &self,
+    ) -> alloc::vec::Vec<hotg_rune_core::ElementType> {
+        let mut element_types = alloc::vec::Vec::new();
+
+        for transform in self.available_transforms.iter() {
+            let tensors =
+                transform.inputs.iter().chain(transform.outputs.iter());
+
+            for tensor in tensors {
+                if !element_types.contains(&tensor.element_type) {
+                    element_types.push(tensor.element_type);
+                }
+            }
+        }
+
+        element_types
+    }
+}
+
+/// A property which can be set on a proc block.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ParameterDescriptor<'a> {
+    pub name: Cow<'a, str>,
+    /// The Rust type used to store this property (e.g. `f32`).
+    pub type_name: Cow<'a, str>,
+    /// The property's doc-comments.
+    pub description: Cow<'a, str>,
+    /// The value used when the Runefile doesn't set this property, as it
+    /// would be written in the Runefile.
+    ///
+    /// This comes from the proc block's [`Default`] impl, so it is only known
+    /// at runtime (see [`crate::ProcBlock::descriptor()`]) and is always
+    /// `None` in [`crate::ProcBlock::DESCRIPTOR`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_value: Option<Cow<'a, str>>,
+    /// The values a numeric property may take.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<ParameterRange>,
+}
+
+/// The (inclusive) bounds on a numeric [`ParameterDescriptor`].
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct ParameterRange {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+}
+
+impl ParameterRange {
+    pub fn contains(&self, value: f64) -> bool {
+        self.min.map_or(true, |min| min <= value)
+            && self.max.map_or(true, |max| value <= max)
+    }
+}
+
+impl Display for ParameterRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(min) = self.min {
+            write!(f, "{}", min)?;
+        }
+        write!(f, "..=")?;
+        if let Some(max) = self.max {
+            write!(f, "{}", max)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -29,6 +127,27 @@ pub struct TransformDescriptor<'a> {
     pub outputs: TensorDescriptors<'a>,
 }
 
+impl<'a> TransformDescriptor<'a> {
+    /// Could this transform be used to turn tensors with the `inputs` shapes
+    /// into tensors with the `outputs` shapes?
+    ///
+    /// Proc blocks with several inputs or outputs receive or return them as
+    /// a tuple, so the number of tensors needs to match exactly.
+    pub fn is_compatible(
+        &self,
+        inputs: &[hotg_rune_core::Shape<'_>],
+        outputs: &[hotg_rune_core::Shape<'_>],
+    ) -> bool {
+        self.inputs.is_compatible(inputs) && self.outputs.is_compatible(outputs)
+    }
+}
+
+impl<'a> Display for TransformDescriptor<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.inputs, self.outputs)
+    }
+}
+
 #[derive(
     Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
 )]
@@ -48,6 +167,31 @@ impl<'a> From<TensorDescriptor<'a>> for TensorDescriptors<'a> {
     }
 }
 
+impl<'a> TensorDescriptors<'a> {
+    fn is_compatible(&self, shapes: &[hotg_rune_core::Shape<'_>]) -> bool {
+        self.len() == shapes.len()
+            && self.iter().zip(shapes).all(|(t, s)| t.is_compatible(s))
+    }
+}
+
+impl<'a> Display for TensorDescriptors<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0.as_ref() {
+            [tensor] => write!(f, "{}", tensor),
+            tensors => {
+                write!(f, "(")?;
+                for (i, tensor) in tensors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", tensor)?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
+
 impl<'a> Deref for TensorDescriptors<'a> {
     type Target = Cow<'a, [TensorDescriptor<'a>]>;
 
@@ -60,12 +204,43 @@ pub struct TensorDescriptor<'a> {
     pub dimensions: Dimensions<'a>,
 }
 
+impl<'a> TensorDescriptor<'a> {
+    /// Does a tensor with this [`hotg_rune_core::Shape`] match the
+    /// descriptor?
+    pub fn is_compatible(&self, shape: &hotg_rune_core::Shape<'_>) -> bool {
+        self.element_type == shape.element_type()
+            && self.dimensions.is_compatible(shape.dimensions())
+    }
+}
+
+impl<'a> Display for TensorDescriptor<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]", self.element_type, self.dimensions)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Dimensions<'a> {
     Finite(Cow<'a, [Dimension]>),
     Arbitrary,
 }
 
+impl<'a> Dimensions<'a> {
+    /// Is a tensor with these dimensions allowed?
+    pub fn is_compatible(&self, dimensions: &[usize]) -> bool {
+        match self {
+            Dimensions::Arbitrary => true,
+            Dimensions::Finite(expected) => {
+                expected.len() == dimensions.len()
+                    && expected.iter().zip(dimensions).all(|(e, &d)| match e {
+                        Dimension::Any => true,
+                        Dimension::Value(value) => *value == d,
+                    })
+            },
+        }
+    }
+}
+
 impl<'a> Display for Dimensions<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -104,3 +279,213 @@ impl Display for Dimension {
         }
     }
 }
+
+/// A function for calculating the shape of one of a proc block's outputs from
+/// the shapes of its inputs and the arguments it was given.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum ShapeTransfer<'a> {
+    /// The output has the same element type and dimensions as an input.
+    SameAsInput(usize),
+    /// The output's dimensions are calculated one at a time.
+    Computed {
+        element_type: hotg_rune_core::ElementType,
+        dimensions: Cow<'a, [DimensionExpr<'a>]>,
+    },
+}
+
+impl<'a> ShapeTransfer<'a> {
+    /// Calculate an output's shape, using `argument` to look up the value of
+    /// an integer argument.
+    pub fn evaluate(
+        &self,
+        inputs: &[hotg_rune_core::Shape<'_>],
+        mut argument: impl FnMut(&str) -> Option<usize>,
+    ) -> Result<hotg_rune_core::Shape<'static>, ShapeTransferError> {
+        match self {
+            ShapeTransfer::SameAsInput(index) => inputs
+                .get(*index)
+                .map(|shape| shape.to_owned())
+                .ok_or(ShapeTransferError::MissingInput(*index)),
+            ShapeTransfer::Computed {
+                element_type,
+                dimensions,
+            } => {
+                let dimensions = dimensions
+                    .iter()
+                    .map(|d| d.evaluate(inputs, &mut argument))
+                    .collect::<Result<Vec<usize>, _>>()?;
+
+                Ok(hotg_rune_core::Shape::new(*element_type, dimensions))
+            },
+        }
+    }
+}
+
+impl<'a> Display for ShapeTransfer<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ShapeTransfer::SameAsInput(index) => write!(f, "input {}", index),
+            ShapeTransfer::Computed {
+                element_type,
+                dimensions,
+            } => {
+                write!(f, "{}[", element_type)?;
+                for (i, dim) in dimensions.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", dim)?;
+                }
+                write!(f, "]")
+            },
+        }
+    }
+}
+
+/// One of the dimensions in a [`ShapeTransfer::Computed`] shape.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum DimensionExpr<'a> {
+    /// A fixed size.
+    Value(usize),
+    /// The size of one of an input's dimensions.
+    Input { input: usize, dimension: usize },
+    /// The value of an integer argument.
+    Argument(Cow<'a, str>),
+    /// The number of complete frames produced by sliding a window along one
+    /// of an input's dimensions, after padding both ends with zeroes.
+    ///
+    /// The window's size, the hop between frames, and the amount of padding
+    /// are all integer arguments.
+    Frames {
+        input: usize,
+        dimension: usize,
+        window_size: Cow<'a, str>,
+        hop: Cow<'a, str>,
+        pad: Cow<'a, str>,
+    },
+}
+
+impl<'a> DimensionExpr<'a> {
+    fn evaluate(
+        &self,
+        inputs: &[hotg_rune_core::Shape<'_>],
+        argument: &mut impl FnMut(&str) -> Option<usize>,
+    ) -> Result<usize, ShapeTransferError> {
+        match self {
+            DimensionExpr::Value(value) => Ok(*value),
+            DimensionExpr::Input { input, dimension } => {
+                input_dimension(inputs, *input, *dimension)
+            },
+            DimensionExpr::Argument(name) => {
+                integer_argument(argument, name, false)
+            },
+            DimensionExpr::Frames {
+                input,
+                dimension,
+                window_size,
+                hop,
+                pad,
+            } => {
+                let length = input_dimension(inputs, *input, *dimension)?;
+                let window_size =
+                    integer_argument(argument, window_size, true)?;
+                let hop = integer_argument(argument, hop, true)?;
+                let pad = integer_argument(argument, pad, false)?;
+
+                let padded_length = length + 2 * pad;
+
+                if padded_length < window_size {
+                    Ok(0)
+                } else {
+                    Ok((padded_length - window_size) / hop + 1)
+                }
+            },
+        }
+    }
+}
+
+fn input_dimension(
+    inputs: &[hotg_rune_core::Shape<'_>],
+    input: usize,
+    dimension: usize,
+) -> Result<usize, ShapeTransferError> {
+    let shape = inputs
+        .get(input)
+        .ok_or(ShapeTransferError::MissingInput(input))?;
+
+    shape
+        .dimensions()
+        .get(dimension)
+        .copied()
+        .ok_or(ShapeTransferError::MissingDimension { input, dimension })
+}
+
+fn integer_argument(
+    argument: &mut impl FnMut(&str) -> Option<usize>,
+    name: &str,
+    non_zero: bool,
+) -> Result<usize, ShapeTransferError> {
+    match argument(name) {
+        Some(0) if non_zero => {
+            Err(ShapeTransferError::ZeroArgument(String::from(name)))
+        },
+        Some(value) => Ok(value),
+        None => Err(ShapeTransferError::MissingArgument(String::from(name))),
+    }
+}
+
+impl<'a> Display for DimensionExpr<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DimensionExpr::Value(value) => write!(f, "{}", value),
+            DimensionExpr::Input { input, dimension } => {
+                write!(f, "input({}, {})", input, dimension)
+            },
+            DimensionExpr::Argument(name) => write!(f, "{:?}", name),
+            DimensionExpr::Frames {
+                input,
+                dimension,
+                window_size,
+                hop,
+                pad,
+            } => write!(
+                f,
+                "frames(input({}, {}), {:?}, {:?}, {:?})",
+                input, dimension, window_size, hop, pad
+            ),
+        }
+    }
+}
+
+/// The reasons a [`ShapeTransfer`] may fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShapeTransferError {
+    MissingInput(usize),
+    MissingDimension {
+        input: usize,
+        dimension: usize,
+    },
+    /// The argument wasn't provided or isn't an integer.
+    MissingArgument(String),
+    /// The argument needs to be greater than zero.
+    ZeroArgument(String),
+}
+
+impl Display for ShapeTransferError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ShapeTransferError::MissingInput(index) => {
+                write!(f, "There is no input {}", index)
+            },
+            ShapeTransferError::MissingDimension { input, dimension } => {
+                write!(f, "Input {} has no dimension {}", input, dimension)
+            },
+            ShapeTransferError::MissingArgument(name) => {
+                write!(f, "The \"{}\" argument should be an integer", name)
+            },
+            ShapeTransferError::ZeroArgument(name) => {
+                write!(f, "The \"{}\" argument can't be zero", name)
+            },
+        }
+    }
+}
@@ -12,10 +12,12 @@
 
 extern crate alloc;
 
+pub mod assertions;
 mod descriptor;
+pub mod testing;
 
 pub use descriptor::*;
-pub use hotg_rune_core::Tensor;
+pub use hotg_rune_core::{Shape, Tensor};
 #[cfg(feature = "derive")]
 pub use hotg_rune_proc_block_macros::ProcBlock;
 
@@ -23,6 +25,36 @@ pub use hotg_rune_proc_block_macros::ProcBlock;
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 /// Process some data, transforming it from one form to another.
+///
+/// A proc block with several inputs receives them as a tuple, in the order
+/// they are listed in the Runefile, and several outputs are returned as a
+/// tuple.
+///
+/// ```rust
+/// use hotg_rune_core::Tensor;
+/// use hotg_rune_proc_blocks::{ProcBlock, Transform};
+///
+/// #[derive(Default, hotg_rune_proc_block_macros::ProcBlock)]
+/// #[transform(inputs = ([f32; 3], [u8; 2]), outputs = ([f32; 3], [u8; 2]))]
+/// struct ApplyMask {}
+///
+/// impl Transform<(Tensor<f32>, Tensor<u8>)> for ApplyMask {
+///     type Output = (Tensor<f32>, Tensor<u8>);
+///
+///     fn transform(
+///         &mut self,
+///         (image, mask): (Tensor<f32>, Tensor<u8>),
+///     ) -> Self::Output {
+///         (image, mask)
+///     }
+/// }
+///
+/// let transform = &ApplyMask::DESCRIPTOR.available_transforms[0];
+/// assert_eq!(
+///     transform.to_string(),
+///     "(f32[_, _, _], u8[_, _]) -> (f32[_, _, _], u8[_, _])",
+/// );
+/// ```
 pub trait Transform<Input>: ProcBlock {
     type Output;
 
@@ -107,6 +139,89 @@ pub trait Transform<Input>: ProcBlock {
 /// }
 /// ```
 ///
+/// When a proc block works with several element types and doesn't care about
+/// the number of dimensions, the `input` and `output` shorthand declares a
+/// transform for every combination of element types. A single generic
+/// `Transform` implementation can then cover all of them.
+///
+/// ```rust
+/// use hotg_rune_core::Tensor;
+/// use hotg_rune_proc_blocks::{ProcBlock, Transform};
+///
+/// #[derive(Default, hotg_rune_proc_block_macros::ProcBlock)]
+/// #[transform(input = [i16, f32], output = f32)]
+/// struct ToFloat {}
+///
+/// impl<T: Copy + Into<f32>> Transform<Tensor<T>> for ToFloat {
+///     type Output = Tensor<f32>;
+///
+///     fn transform(&mut self, input: Tensor<T>) -> Self::Output {
+///         input.map(|_, &value| value.into())
+///     }
+/// }
+///
+/// assert_eq!(ToFloat::DESCRIPTOR.available_transforms.len(), 2);
+/// ```
+///
+/// Generic proc blocks can use `#[proc_block(element_type(...))]` to say which
+/// element types a type parameter may be. Any `#[transform(...)]` mentioning
+/// that parameter is then declared once for each element type.
+///
+/// ```rust
+/// use std::marker::PhantomData;
+///
+/// use hotg_rune_core::Tensor;
+/// use hotg_rune_proc_blocks::{ProcBlock, Transform};
+///
+/// #[derive(Default, hotg_rune_proc_block_macros::ProcBlock)]
+/// #[proc_block(element_type(T = [f32, i16]))]
+/// #[transform(inputs = [T; _], outputs = [T; _])]
+/// struct Identity<T> {
+///     #[proc_block(skip)]
+///     _type: PhantomData<T>,
+/// }
+///
+/// impl<T: Default + 'static> Transform<Tensor<T>> for Identity<T> {
+///     type Output = Tensor<T>;
+///
+///     fn transform(&mut self, input: Tensor<T>) -> Self::Output { input }
+/// }
+///
+/// assert_eq!(Identity::<f32>::DESCRIPTOR.available_transforms.len(), 2);
+/// ```
+///
+/// The `#[output_shape(...)]` attribute lets the compiler infer the shape of
+/// each output so the Runefile doesn't need to spell it out. An output can
+/// either have the same shape as one of the inputs, or be made up of
+/// dimensions which are fixed, copied from an input's dimension (using
+/// `input(index, dimension)`), or taken from an integer argument. Proc blocks
+/// which split their input into overlapping frames can use
+/// `frames(input(index, dimension), "window_size", "hop", "pad")` for the
+/// number of frames, where each string is the name of an integer argument.
+///
+/// ```rust
+/// use hotg_rune_core::Tensor;
+/// use hotg_rune_proc_blocks::{ProcBlock, ShapeTransfer, Transform};
+///
+/// #[derive(Default, hotg_rune_proc_block_macros::ProcBlock)]
+/// #[transform(inputs = [f32; 2], outputs = [f32; 2])]
+/// #[output_shape(f32, [input(0, 0), "bins"])]
+/// struct Histogram {
+///     bins: usize,
+/// }
+///
+/// impl Transform<Tensor<f32>> for Histogram {
+///     type Output = Tensor<f32>;
+///
+///     fn transform(&mut self, _input: Tensor<f32>) -> Self::Output {
+///         unimplemented!()
+///     }
+/// }
+///
+/// let shape = &Histogram::DESCRIPTOR.output_shapes[0];
+/// assert_eq!(shape.to_string(), r#"f32[input(0, 0), "bins"]"#);
+/// ```
+///
 /// ## Field Attributes
 ///
 /// By default, all fields in a proc block struct will be registered as
@@ -129,6 +244,29 @@ pub trait Transform<Input>: ProcBlock {
 /// assert_eq!(foo.property, 42.0);
 /// ```
 ///
+/// Hosts can discover the range of values a property accepts using the
+/// `#[proc_block(min = ..., max = ...)]` attribute. Its default value is
+/// whatever the [`Default`] impl sets, formatted with [`core::fmt::Display`]
+/// (properties that don't implement [`core::fmt::Display`] have no default).
+///
+/// ```rust
+/// use hotg_rune_proc_blocks::{ParameterRange, ProcBlock};
+///
+/// #[derive(hotg_rune_proc_block_macros::ProcBlock)]
+/// struct Foo {
+///     #[proc_block(min = 0, max = 1)]
+///     gain: f32,
+/// }
+///
+/// impl Default for Foo {
+///     fn default() -> Self { Foo { gain: 0.5 } }
+/// }
+///
+/// let gain = &Foo::descriptor().parameters[0];
+/// assert_eq!(gain.default_value.as_deref(), Some("0.5"));
+/// assert!(gain.range.unwrap().contains(0.25));
+/// ```
+///
 /// A parameter can opt-out of this with the `#[proc_block(skip)]` attribute.
 ///
 /// ```rust,compile_fail
@@ -148,15 +286,131 @@ pub trait Transform<Input>: ProcBlock {
 pub trait ProcBlock: Default + 'static {
     /// A description of the proc block.
     const DESCRIPTOR: ProcBlockDescriptor<'static>;
+
+    /// Set one of the [`ProcBlockDescriptor::parameters`] by name, parsing
+    /// the `value` with its generated setter.
+    ///
+    /// This lets a host set parameters without knowing the proc block's
+    /// concrete type.
+    ///
+    /// ```rust
+    /// use hotg_rune_proc_blocks::{ProcBlock, SetParameterError};
+    ///
+    /// #[derive(Default, hotg_rune_proc_block_macros::ProcBlock)]
+    /// struct Foo {
+    ///     gain: f32,
+    /// }
+    ///
+    /// let mut foo = Foo::default();
+    ///
+    /// foo.set_parameter("gain", "0.5").unwrap();
+    /// assert_eq!(foo.gain, 0.5);
+    /// assert!(matches!(
+    ///     foo.set_parameter("gain", "loud"),
+    ///     Err(SetParameterError::InvalidValue(_))
+    /// ));
+    /// assert_eq!(
+    ///     foo.set_parameter("volume", "11"),
+    ///     Err(SetParameterError::UnknownParameter)
+    /// );
+    /// ```
+    fn set_parameter(
+        &mut self,
+        name: &str,
+        value: &str,
+    ) -> Result<(), SetParameterError> {
+        let _ = (name, value);
+        Err(SetParameterError::UnknownParameter)
+    }
+
+    /// Get the current value of one of the
+    /// [`ProcBlockDescriptor::parameters`], formatted the way it would be
+    /// written in a Runefile.
+    ///
+    /// This returns `None` for unknown parameters and for parameters whose
+    /// type doesn't implement [`core::fmt::Display`].
+    fn get_parameter(&self, name: &str) -> Option<alloc::string::String> {
+        let _ = name;
+        None
+    }
+
+    /// The [`ProcBlock::DESCRIPTOR`], with each parameter's
+    /// [`ParameterDescriptor::default_value`] read from [`Default`].
+    fn descriptor() -> ProcBlockDescriptor<'static> {
+        let defaults = Self::default();
+        let mut descriptor = Self::DESCRIPTOR;
+
+        for parameter in descriptor.parameters.to_mut() {
+            parameter.default_value =
+                defaults.get_parameter(&parameter.name).map(Into::into);
+        }
+
+        descriptor
+    }
+}
+
+/// The error returned by [`ProcBlock::set_parameter()`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetParameterError {
+    /// The proc block doesn't have a parameter with that name.
+    UnknownParameter,
+    /// The value couldn't be parsed.
+    InvalidValue(alloc::string::String),
+}
+
+impl SetParameterError {
+    #[doc(hidden)]
+    pub fn invalid_value(error: impl core::fmt::Debug) -> Self {
+        SetParameterError::InvalidValue(alloc::format!("{:?}", error))
+    }
+}
+
+impl core::fmt::Display for SetParameterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SetParameterError::UnknownParameter => {
+                write!(f, "Unknown parameter")
+            },
+            SetParameterError::InvalidValue(e) => {
+                write!(f, "Invalid value: {}", e)
+            },
+        }
+    }
 }
 
 /// An internal module used by the `hotg_rune_proc_block_macros` crate
 /// so it has access to all the types it will need.
 #[doc(hidden)]
 pub mod internal {
-    pub use alloc::borrow::Cow;
+    use alloc::string::ToString;
+    pub use alloc::{borrow::Cow, string::String};
+    use core::fmt::Display;
+
+    pub use hotg_rune_core::{bf16, f16, ElementType, Tensor};
+
+    pub use crate::{descriptor::*, ProcBlock, SetParameterError, Transform};
+
+    /// Used by `ProcBlock::get_parameter()` to format a parameter with
+    /// [`Display`] if it implements it, and return `None` otherwise.
+    ///
+    /// Calling `(&&Parameter(&value)).parameter_value()` picks
+    /// [`DisplayParameter`] when `T: Display` because it needs one less
+    /// auto-deref, falling back to [`OpaqueParameter`].
+    pub struct Parameter<'a, T>(pub &'a T);
+
+    pub trait DisplayParameter {
+        fn parameter_value(&self) -> Option<String>;
+    }
+
+    impl<T: Display> DisplayParameter for &Parameter<'_, T> {
+        fn parameter_value(&self) -> Option<String> { Some(self.0.to_string()) }
+    }
 
-    pub use hotg_rune_core::{ElementType, Tensor};
+    pub trait OpaqueParameter {
+        fn parameter_value(&self) -> Option<String>;
+    }
 
-    pub use crate::{descriptor::*, ProcBlock, Transform};
+    impl<T> OpaqueParameter for Parameter<'_, T> {
+        fn parameter_value(&self) -> Option<String> { None }
+    }
 }
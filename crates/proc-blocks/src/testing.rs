@@ -0,0 +1,171 @@
+//! Helpers for unit testing proc blocks.
+//!
+//! ```rust
+//! use hotg_rune_proc_blocks::{
+//!     testing::{self, assert_tensor_close},
+//!     Tensor, Transform,
+//! };
+//!
+//! #[derive(Default, hotg_rune_proc_block_macros::ProcBlock)]
+//! #[transform(inputs = [f32; _], outputs = [f32; _])]
+//! struct Halve {}
+//!
+//! impl Transform<Tensor<f32>> for Halve {
+//!     type Output = Tensor<f32>;
+//!
+//!     fn transform(&mut self, input: Tensor<f32>) -> Tensor<f32> {
+//!         input.map(|_, &value| value / 2.0)
+//!     }
+//! }
+//!
+//! let input = testing::tensor([[1.0, 2.0], [3.0, 4.0]]);
+//! let got = testing::run(Halve::default(), input);
+//! assert_tensor_close(&got, &testing::tensor([[0.5, 1.0], [1.5, 2.0]]), 1e-6);
+//!
+//! // Edge cases like empty inputs are just as easy to check
+//! let got = testing::run(Halve::default(), testing::empty::<f32>(&[2, 0]));
+//! assert_eq!(got.dimensions(), &[2, 0]);
+//! ```
+
+use alloc::{string::ToString, sync::Arc, vec::Vec};
+use core::fmt::{self, Debug, Display, Formatter};
+
+use hotg_rune_core::{AsElementType, Shape, Tensor};
+
+use crate::Transform;
+
+/// Create a [`Tensor`] from an array, where nested arrays become extra
+/// dimensions (e.g. `[[1, 2, 3], [4, 5, 6]]` is a `2x3` tensor).
+pub fn tensor<T>(array: impl Into<Tensor<T>>) -> Tensor<T> { array.into() }
+
+/// Create a [`Tensor`] with no elements where at least one of the
+/// `dimensions` is `0`.
+#[track_caller]
+pub fn empty<T>(dimensions: &[usize]) -> Tensor<T> {
+    Tensor::new_row_major(Arc::from(Vec::new()), dimensions.to_vec())
+}
+
+/// Run a proc block's [`Transform`], checking that the shapes of its
+/// inputs and outputs match one of the transforms in its
+/// [`crate::ProcBlock::DESCRIPTOR`].
+///
+/// This catches `#[transform(...)]` attributes which don't line up with what
+/// the proc block actually does, which would otherwise only be noticed when
+/// a Runefile is type checked.
+#[track_caller]
+pub fn run<P, Input>(mut proc_block: P, input: Input) -> P::Output
+where
+    P: Transform<Input>,
+    Input: Shapes,
+    P::Output: Shapes,
+{
+    let inputs = input.shapes();
+    let output = proc_block.transform(input);
+    let outputs = output.shapes();
+
+    let transforms = &P::DESCRIPTOR.available_transforms;
+
+    if !transforms
+        .iter()
+        .any(|t| t.is_compatible(&inputs, &outputs))
+    {
+        panic!(
+            "{} doesn't declare a transform from {} to {} (available: {:?})",
+            P::DESCRIPTOR.type_name,
+            DisplayShapes(&inputs),
+            DisplayShapes(&outputs),
+            transforms.iter().map(|t| t.to_string()).collect::<Vec<_>>(),
+        );
+    }
+
+    output
+}
+
+/// Assert that two tensors have the same dimensions and that each element is
+/// within `tolerance` of its expected value.
+#[track_caller]
+pub fn assert_tensor_close<T>(
+    actual: &Tensor<T>,
+    expected: &Tensor<T>,
+    tolerance: f64,
+) where
+    T: Copy + Debug + Into<f64>,
+{
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "The tensors have different dimensions"
+    );
+
+    let elements = actual.elements().iter().zip(expected.elements());
+
+    for (i, (&a, &e)) in elements.enumerate() {
+        let (a_value, e_value): (f64, f64) = (a.into(), e.into());
+        let difference = if a_value > e_value {
+            a_value - e_value
+        } else {
+            e_value - a_value
+        };
+
+        if difference.is_nan() || difference > tolerance {
+            panic!(
+                "Element {} differs by more than {}: {:?} != {:?}\n actual: \
+                 {:?}\nexpected: {:?}",
+                i,
+                tolerance,
+                a,
+                e,
+                actual.elements(),
+                expected.elements(),
+            );
+        }
+    }
+}
+
+/// Something which can be passed to or returned from a proc block (i.e. a
+/// [`Tensor`] or a tuple of tensors).
+pub trait Shapes {
+    fn shapes(&self) -> Vec<Shape<'static>>;
+}
+
+impl<T: AsElementType> Shapes for Tensor<T> {
+    fn shapes(&self) -> Vec<Shape<'static>> {
+        alloc::vec![self.shape().to_owned()]
+    }
+}
+
+macro_rules! tuple_shapes {
+    ($($name:ident),*) => {
+        impl<$($name: AsElementType),*> Shapes for ($(Tensor<$name>,)*) {
+            #[allow(non_snake_case)]
+            fn shapes(&self) -> Vec<Shape<'static>> {
+                let ($($name,)*) = self;
+                alloc::vec![$($name.shape().to_owned()),*]
+            }
+        }
+    };
+}
+
+tuple_shapes!(A, B);
+tuple_shapes!(A, B, C);
+tuple_shapes!(A, B, C, D);
+
+struct DisplayShapes<'a>(&'a [Shape<'static>]);
+
+impl Display for DisplayShapes<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            [shape] => write!(f, "{}", shape),
+            shapes => {
+                write!(f, "(")?;
+                for (i, shape) in shapes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", shape)?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
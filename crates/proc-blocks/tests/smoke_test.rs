@@ -1,12 +1,12 @@
 #[macro_use]
 extern crate pretty_assertions;
 
-use std::marker::PhantomData;
+use std::{marker::PhantomData, str::FromStr};
 
 use hotg_rune_core::{ElementType, Tensor};
 use hotg_rune_proc_blocks::{
-    Dimension, Dimensions, ProcBlock, ProcBlockDescriptor, TensorDescriptor,
-    Transform, TransformDescriptor,
+    Dimension, Dimensions, ParameterDescriptor, ParameterRange, ProcBlock,
+    ProcBlockDescriptor, TensorDescriptor, Transform, TransformDescriptor,
 };
 
 /// A dummy proc block.
@@ -18,6 +18,9 @@ use hotg_rune_proc_blocks::{
 struct Foo {
     /// Some parameter.
     a: u32,
+    /// A parameter with a range.
+    #[proc_block(min = 0, max = 1)]
+    b: f32,
     #[proc_block(skip)]
     skipped: Vec<String>,
 }
@@ -48,6 +51,31 @@ impl<T> Default for Generic<T> {
     }
 }
 
+/// Something which can be parsed, but not displayed.
+#[derive(Debug, Default, PartialEq)]
+struct Opaque;
+
+impl FromStr for Opaque {
+    type Err = ();
+
+    fn from_str(_: &str) -> Result<Self, Self::Err> { Ok(Opaque) }
+}
+
+#[derive(ProcBlock)]
+struct Gain {
+    gain: f32,
+    opaque: Opaque,
+}
+
+impl Default for Gain {
+    fn default() -> Self {
+        Gain {
+            gain: 0.5,
+            opaque: Opaque,
+        }
+    }
+}
+
 #[test]
 fn generate_expected_descriptor() {
     let should_be = ProcBlockDescriptor {
@@ -55,6 +83,27 @@ fn generate_expected_descriptor() {
         description: "A dummy proc block.\n\nCan it handle multiple lines of \
                       input?"
             .into(),
+        version: env!("CARGO_PKG_VERSION").into(),
+        parameters: vec![
+            ParameterDescriptor {
+                name: "a".into(),
+                type_name: "u32".into(),
+                description: "Some parameter.".into(),
+                default_value: None,
+                range: None,
+            },
+            ParameterDescriptor {
+                name: "b".into(),
+                type_name: "f32".into(),
+                description: "A parameter with a range.".into(),
+                default_value: None,
+                range: Some(ParameterRange {
+                    min: Some(0.0),
+                    max: Some(1.0),
+                }),
+            },
+        ]
+        .into(),
         available_transforms: vec![
             TransformDescriptor {
                 inputs: TensorDescriptor {
@@ -82,9 +131,37 @@ fn generate_expected_descriptor() {
             },
         ]
         .into(),
+        output_shapes: Default::default(),
     };
 
     let got = <Foo as ProcBlock>::DESCRIPTOR;
 
     assert_eq!(got, should_be);
+    assert_eq!(
+        got.supported_element_types(),
+        vec![ElementType::F32, ElementType::U8]
+    );
+}
+
+#[test]
+fn defaults_come_from_the_default_impl() {
+    let descriptor = Gain::descriptor();
+
+    assert_eq!(
+        descriptor.parameters[0].default_value.as_deref(),
+        Some("0.5")
+    );
+    assert_eq!(descriptor.parameters[1].default_value, None);
+    assert_eq!(Gain::DESCRIPTOR.parameters[0].default_value, None);
+}
+
+#[test]
+fn get_and_set_parameters_by_name() {
+    let mut gain = Gain::default();
+
+    gain.set_parameter("gain", "2").unwrap();
+
+    assert_eq!(gain.get_parameter("gain").as_deref(), Some("2"));
+    assert_eq!(gain.get_parameter("opaque"), None);
+    assert_eq!(gain.get_parameter("volume"), None);
 }
@@ -0,0 +1,38 @@
+use hotg_rune_core::Tensor;
+use hotg_rune_proc_blocks::{
+    testing::{assert_tensor_close, run, tensor},
+    Transform,
+};
+
+#[derive(Default, hotg_rune_proc_block_macros::ProcBlock)]
+#[transform(inputs = [f32; 1], outputs = [f32; 1])]
+struct Double {}
+
+impl Transform<Tensor<f32>> for Double {
+    type Output = Tensor<f32>;
+
+    fn transform(&mut self, input: Tensor<f32>) -> Tensor<f32> {
+        input.map(|_, &value| value * 2.0)
+    }
+}
+
+#[test]
+fn run_a_declared_transform() {
+    let got = run(Double::default(), tensor([1.0, 2.0, 3.0]));
+
+    assert_tensor_close(&got, &tensor([2.0, 4.0, 6.0]), 0.0);
+}
+
+#[test]
+#[should_panic(
+    expected = "Double doesn't declare a transform from f32[2, 2] to f32[2, 2]"
+)]
+fn undeclared_shapes_are_rejected() {
+    run(Double::default(), tensor([[1.0, 2.0], [3.0, 4.0]]));
+}
+
+#[test]
+#[should_panic(expected = "Element 1 differs by more than 0.01")]
+fn detect_elements_which_are_too_far_apart() {
+    assert_tensor_close(&tensor([1.0_f32, 2.0]), &tensor([1.0_f32, 2.5]), 0.01);
+}
@@ -10,13 +10,17 @@ use codespan_reporting::{
     },
 };
 use hotg_rune_compiler::{
-    codegen::RuneVersion,
-    compile::{CompilationResult, CompiledBinary},
+    codegen::{RuneGraph, RuneVersion},
+    compile::{CompilationResult, CompiledBinary, DebugSymbols, SizeReport},
+    config::Config as BuildConfig,
+    graph::PipelineGraph,
     hooks::{
         AfterCodegenContext, AfterLoweringContext, AfterParseContext,
         AfterTypeCheckingContext, Continuation,
     },
-    BuildContext, Verbosity,
+    sbom::BillOfMaterials,
+    typescript::TypeScriptBindings,
+    BuildContext, Diagnostics, FeatureFlags, Verbosity,
 };
 use once_cell::sync::Lazy;
 
@@ -27,7 +31,8 @@ pub struct Build {
     /// The Runefile to compile.
     #[structopt(parse(from_os_str), default_value = "Runefile.yml")]
     runefile: PathBuf,
-    /// Where to write the generated Rune.
+    /// Where to write the generated Rune (or project, when using `--emit
+    /// project`).
     #[structopt(short, long, parse(from_os_str))]
     output: Option<PathBuf>,
     /// The directory to use when caching builds.
@@ -49,6 +54,142 @@ pub struct Build {
     /// Compile the Rune without optimisations.
     #[structopt(long)]
     debug: bool,
+    /// Keep debug info in the Rune and write a map from each stage to its
+    /// functions alongside it.
+    #[structopt(long)]
+    debug_symbols: bool,
+    /// Use WebAssembly SIMD instructions, also writing a scalar
+    /// `*.scalar.rune` for runtimes which don't support them.
+    #[structopt(long, conflicts_with = "debug")]
+    simd: bool,
+    /// Compile for 64-bit WebAssembly (memory64) so the Rune can use more
+    /// than 4 GB of memory. These Runes can only be run by Wasmtime.
+    #[structopt(long)]
+    memory64: bool,
+    /// Fail if any proc-block doesn't match what is pinned in the
+    /// `Runefile.lock`, instead of re-resolving it and updating the lockfile.
+    #[structopt(long)]
+    locked: bool,
+    /// Write a CycloneDX bill of materials listing the Rune's proc-blocks,
+    /// models, and resources alongside it.
+    #[structopt(long)]
+    sbom: bool,
+    /// Write TypeScript declarations and a JavaScript wrapper for using the
+    /// Rune from the web bindings alongside it.
+    #[structopt(long)]
+    typescript: bool,
+    /// Write the pipeline graph alongside the Rune, both as a Graphviz
+    /// `*.dot` file and as `*.graph.json`.
+    #[structopt(long)]
+    graph: bool,
+    /// Which profile from the project's `rune.toml` to build with (defaults
+    /// to its `default-profile`).
+    #[structopt(long)]
+    profile: Option<String>,
+    /// What to build. The "native" target skips WebAssembly entirely and
+    /// writes the pipeline graph for the runtime's native engine instead.
+    #[structopt(
+        long,
+        possible_values = Target::VARIANTS,
+        default_value = "wasm",
+    )]
+    target: Target,
+    /// Also write the generated project (`lib.rs`, `Cargo.toml`, etc.) and
+    /// the compiled `.wasm` to this directory, for debugging codegen. Use a
+    /// tool like `wasm2wat` to get the text format.
+    #[structopt(long, parse(from_os_str))]
+    emit_dir: Option<PathBuf>,
+    /// What to produce. With "project", the generated Rust project (including
+    /// any models) is written to the `--output` directory without being
+    /// compiled, so it can be vendored, inspected, or built separately.
+    #[structopt(
+        long,
+        possible_values = Emit::VARIANTS,
+        default_value = "rune",
+    )]
+    emit: Emit,
+    /// How diagnostics should be reported. With "json", each diagnostic is
+    /// printed to stdout as a single line of JSON for editors and other
+    /// tools to consume.
+    #[structopt(
+        long,
+        possible_values = MessageFormat::VARIANTS,
+        default_value = "human",
+    )]
+    message_format: MessageFormat,
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, strum::EnumVariantNames, strum::EnumString,
+)]
+#[strum(serialize_all = "kebab-case")]
+enum MessageFormat {
+    /// Pretty-print diagnostics to stderr.
+    Human,
+    /// Print each diagnostic to stdout as a line of JSON.
+    Json,
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, strum::EnumVariantNames, strum::EnumString,
+)]
+#[strum(serialize_all = "kebab-case")]
+enum Emit {
+    /// Compile the Rune.
+    Rune,
+    /// Write the generated Rust project and stop before compiling it.
+    Project,
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, strum::EnumVariantNames, strum::EnumString,
+)]
+#[strum(serialize_all = "kebab-case")]
+enum Target {
+    /// A WebAssembly module which can be run by any runtime.
+    Wasm,
+    /// The `.rune_graph.json` for running the pipeline natively.
+    Native,
+}
+
+impl Target {
+    fn extension(self) -> &'static str {
+        match self {
+            Target::Wasm => "rune",
+            Target::Native => "rune_graph.json",
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compile(
+    ctx: BuildContext,
+    features: FeatureFlags,
+    dest: PathBuf,
+    color: ColorChoice,
+    runefile: &Path,
+    sbom: bool,
+    typescript: bool,
+    graph: bool,
+    target: Target,
+    emit: Emit,
+    emit_dir: Option<PathBuf>,
+    message_format: MessageFormat,
+) -> Result<(), Error> {
+    let mut hooks = Hooks::new(dest, color, runefile.to_path_buf());
+    hooks.sbom = sbom;
+    hooks.typescript = typescript;
+    hooks.graph = graph;
+    hooks.target = target;
+    hooks.emit_only = emit == Emit::Project;
+    hooks.emit_dir = emit_dir;
+    hooks.message_format = message_format;
+    hotg_rune_compiler::build_with_hooks(ctx, features, &mut hooks);
+
+    match hooks.error {
+        None => Ok(()),
+        Some(e) => Err(e),
+    }
 }
 
 impl Build {
@@ -57,8 +198,12 @@ impl Build {
         color: ColorChoice,
         unstable: Unstable,
     ) -> Result<(), Error> {
-        let ctx = self.build_context()?;
-        let features = unstable.feature_flags();
+        let config = self.config()?;
+        let mut ctx = self.build_context(&config)?;
+
+        let mut features = FeatureFlags::default();
+        config.apply_features(&mut features);
+        unstable.apply(&mut features);
 
         log::debug!(
             "Compiling {} in \"{}\"",
@@ -66,20 +211,102 @@ impl Build {
             ctx.working_directory.display()
         );
 
+        if self.target == Target::Native && ctx.simd {
+            anyhow::bail!("SIMD can only be used when targeting WebAssembly");
+        }
+        if self.target == Target::Native && ctx.memory64 {
+            anyhow::bail!(
+                "memory64 can only be used when targeting WebAssembly"
+            );
+        }
+
+        if self.emit == Emit::Project {
+            if self.target == Target::Native {
+                anyhow::bail!(
+                    "Only WebAssembly targets generate a project to emit"
+                );
+            }
+
+            let dir = self.output.clone().unwrap_or_else(|| {
+                ctx.current_directory.join(format!("{}-project", ctx.name))
+            });
+            ctx.emit_project = Some(dir);
+        } else if let Some(dir) = &self.emit_dir {
+            ctx.emit_project = Some(dir.clone());
+        }
+
         let dest = self.output.unwrap_or_else(|| {
-            ctx.current_directory.join(&ctx.name).with_extension("rune")
+            ctx.current_directory
+                .join(&ctx.name)
+                .with_extension(self.target.extension())
         });
 
-        let mut hooks = Hooks::new(dest, color, self.runefile);
-        hotg_rune_compiler::build_with_hooks(ctx, features, &mut hooks);
+        if ctx.simd && self.emit != Emit::Project {
+            let scalar = BuildContext {
+                simd: false,
+                emit_project: None,
+                ..ctx.clone()
+            };
+            let scalar_dest = dest.with_extension("scalar.rune");
+            compile(
+                scalar,
+                features.clone(),
+                scalar_dest,
+                color,
+                &self.runefile,
+                false,
+                false,
+                false,
+                Target::Wasm,
+                Emit::Rune,
+                None,
+                self.message_format,
+            )?;
+        }
+
+        compile(
+            ctx,
+            features,
+            dest,
+            color,
+            &self.runefile,
+            self.sbom,
+            self.typescript,
+            self.graph,
+            self.target,
+            self.emit,
+            self.emit_dir,
+            self.message_format,
+        )
+    }
+
+    /// Load the `rune.toml` (or `[package.metadata.rune]`) for this project,
+    /// if there is one.
+    fn config(&self) -> Result<BuildConfig, Error> {
+        let current_directory = self.current_directory()?;
 
-        match hooks.error {
-            None => Ok(()),
-            Some(e) => Err(e),
+        let config =
+            BuildConfig::load(&current_directory).with_context(|| {
+                format!(
+                    "Unable to load the build config from \"{}\"",
+                    current_directory.display()
+                )
+            })?;
+
+        if config.is_none() && self.profile.is_some() {
+            anyhow::bail!(
+                "The --profile flag was used, but \"{}\" has no rune.toml",
+                current_directory.display()
+            );
         }
+
+        Ok(config.unwrap_or_default())
     }
 
-    fn build_context(&self) -> Result<BuildContext, Error> {
+    fn build_context(
+        &self,
+        config: &BuildConfig,
+    ) -> Result<BuildContext, Error> {
         let verbosity =
             Verbosity::from_quiet_and_verbose(self.quiet, self.verbose)
                 .context(
@@ -98,15 +325,42 @@ impl Build {
                 format!("Unable to read \"{}\"", self.runefile.display())
             })?;
 
-        Ok(BuildContext {
+        let mut ctx = BuildContext {
             name,
             current_directory,
             runefile,
             verbosity,
             working_directory,
-            optimized: !self.debug,
+            optimized: true,
+            debug_symbols: false,
+            simd: false,
+            memory64: false,
+            locked: false,
+            device_memory: None,
+            emit_project: None,
             rune_version: Some(RuneVersion::new(env!("CARGO_PKG_VERSION"))),
-        })
+        };
+
+        config.apply(&mut ctx, self.profile.as_deref())?;
+
+        // Flags on the command-line always win
+        if self.debug {
+            ctx.optimized = false;
+        }
+        if self.debug_symbols {
+            ctx.debug_symbols = true;
+        }
+        if self.simd {
+            ctx.simd = true;
+        }
+        if self.memory64 {
+            ctx.memory64 = true;
+        }
+        if self.locked {
+            ctx.locked = true;
+        }
+
+        Ok(ctx)
     }
 
     fn current_directory(&self) -> Result<PathBuf, Error> {
@@ -156,6 +410,14 @@ struct Hooks {
     dest: PathBuf,
     runefile_path: PathBuf,
     color: ColorChoice,
+    sbom: bool,
+    typescript: bool,
+    graph: bool,
+    target: Target,
+    /// Stop once the generated project has been written (`--emit project`).
+    emit_only: bool,
+    emit_dir: Option<PathBuf>,
+    message_format: MessageFormat,
     error: Option<Error>,
 }
 
@@ -165,6 +427,13 @@ impl Hooks {
             dest,
             color,
             runefile_path,
+            sbom: false,
+            typescript: false,
+            graph: false,
+            target: Target::Wasm,
+            emit_only: false,
+            emit_dir: None,
+            message_format: MessageFormat::Human,
             error: None,
         }
     }
@@ -188,11 +457,133 @@ impl Hooks {
         Ok(())
     }
 
-    fn check_diagnostics(
-        &mut self,
-        diags: impl Iterator<Item = Diagnostic<()>>,
+    /// Write intermediate build artifacts to the `--emit-dir`, if one was
+    /// requested.
+    fn emit<'a>(
+        &self,
+        files: impl IntoIterator<Item = (PathBuf, &'a [u8])>,
+    ) -> Result<(), Error> {
+        let emit_dir = match &self.emit_dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+
+        for (path, data) in files {
+            let dest = emit_dir.join(path);
+
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent).with_context(|| {
+                    format!(
+                        "Unable to create the \"{}\" directory",
+                        parent.display()
+                    )
+                })?;
+            }
+
+            std::fs::write(&dest, data).with_context(|| {
+                format!("Unable to write to \"{}\"", dest.display())
+            })?;
+
+            log::debug!("Emitted \"{}\"", dest.display());
+        }
+
+        Ok(())
+    }
+
+    fn save_rune_graph(&self, graph: &RuneGraph) -> Result<(), Error> {
+        let json = serde_json::to_vec_pretty(graph)
+            .context("Unable to serialize the pipeline graph")?;
+
+        std::fs::write(&self.dest, json).with_context(|| {
+            format!("Unable to write to \"{}\"", self.dest.display())
+        })?;
+
+        log::info!(
+            "The pipeline graph was written to \"{}\"",
+            self.dest.display()
+        );
+
+        Ok(())
+    }
+
+    fn save_debug_symbols(&self, symbols: &DebugSymbols) -> Result<(), Error> {
+        let dest = self.dest.with_extension("symbols.json");
+        let json = serde_json::to_vec_pretty(symbols)
+            .context("Unable to serialize the debug symbols")?;
+
+        std::fs::write(&dest, json).with_context(|| {
+            format!("Unable to write to \"{}\"", dest.display())
+        })?;
+
+        log::info!("Debug symbols were written to \"{}\"", dest.display());
+
+        Ok(())
+    }
+
+    fn save_bill_of_materials(
+        &self,
+        bom: &BillOfMaterials,
+    ) -> Result<(), Error> {
+        let dest = self.dest.with_extension("cdx.json");
+        let json = serde_json::to_vec_pretty(bom)
+            .context("Unable to serialize the bill of materials")?;
+
+        std::fs::write(&dest, json).with_context(|| {
+            format!("Unable to write to \"{}\"", dest.display())
+        })?;
+
+        log::info!(
+            "The bill of materials was written to \"{}\"",
+            dest.display()
+        );
+
+        Ok(())
+    }
+
+    fn save_typescript_bindings(
+        &self,
+        bindings: &TypeScriptBindings,
+    ) -> Result<(), Error> {
+        let files = [
+            (self.dest.with_extension("d.ts"), &bindings.declarations),
+            (self.dest.with_extension("js"), &bindings.wrapper),
+        ];
+
+        for (dest, contents) in &files {
+            std::fs::write(dest, contents).with_context(|| {
+                format!("Unable to write to \"{}\"", dest.display())
+            })?;
+
+            log::info!("Wrote \"{}\"", dest.display());
+        }
+
+        Ok(())
+    }
+
+    fn save_pipeline_graph(&self, graph: &PipelineGraph) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(graph)
+            .context("Unable to serialize the pipeline graph")?;
+        let files = [
+            (self.dest.with_extension("dot"), graph.to_dot()),
+            (self.dest.with_extension("graph.json"), json),
+        ];
+
+        for (dest, contents) in &files {
+            std::fs::write(dest, contents).with_context(|| {
+                format!("Unable to write to \"{}\"", dest.display())
+            })?;
+
+            log::info!("Wrote \"{}\"", dest.display());
+        }
+
+        Ok(())
+    }
+
+    fn print_diagnostics(
+        &self,
+        diags: &Diagnostics,
         ctx: &BuildContext,
-    ) -> Continuation {
+    ) -> Result<(), Error> {
         let mut writer = StandardStream::stderr(self.color);
         let config = Config::default();
 
@@ -201,27 +592,31 @@ impl Hooks {
             &ctx.runefile,
         );
 
-        let mut errors = 0;
-
         for diag in diags {
-            if diag.severity >= Severity::Error {
-                errors += 1;
-            }
+            codespan_reporting::term::emit(&mut writer, &config, &file, diag)
+                .context("Unable to print the diagnostic")?;
+        }
 
-            match codespan_reporting::term::emit(
-                &mut writer,
-                &config,
-                &file,
-                &diag,
-            )
-            .context("Unable to print the diagnostic")
-            {
-                Ok(_) => {},
-                Err(e) => {
-                    self.error = Some(e);
-                    return Continuation::Halt;
-                },
-            }
+        Ok(())
+    }
+
+    fn check_diagnostics(
+        &mut self,
+        diags: impl Iterator<Item = Diagnostic<()>>,
+        ctx: &BuildContext,
+    ) -> Continuation {
+        let mut collected = Diagnostics::new();
+        collected.extend(diags);
+        let errors = collected.iter_severity(Severity::Error).count();
+
+        let result = match self.message_format {
+            MessageFormat::Human => self.print_diagnostics(&collected, ctx),
+            MessageFormat::Json => print_json_diagnostics(&collected),
+        };
+
+        if let Err(e) = result {
+            self.error = Some(e);
+            return Continuation::Halt;
         }
 
         match errors {
@@ -239,6 +634,18 @@ impl Hooks {
     }
 }
 
+fn print_json_diagnostics(diags: &Diagnostics) -> Result<(), Error> {
+    if let serde_json::Value::Array(diags) = diags.to_json() {
+        for diag in diags {
+            let line = serde_json::to_string(&diag)
+                .context("Unable to serialize the diagnostic")?;
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
 impl hotg_rune_compiler::hooks::Hooks for Hooks {
     fn after_type_checking(
         &mut self,
@@ -261,20 +668,101 @@ impl hotg_rune_compiler::hooks::Hooks for Hooks {
         &mut self,
         ctx: &mut dyn AfterLoweringContext,
     ) -> Continuation {
-        self.check_diagnostics(
+        let continuation = self.check_diagnostics(
             ctx.diagnostics_mut().drain(),
             &ctx.build_context(),
-        )
+        );
+
+        if self.sbom && continuation == Continuation::Continue {
+            if let Err(err) =
+                self.save_bill_of_materials(&ctx.bill_of_materials())
+            {
+                self.error = Some(err);
+                return Continuation::Halt;
+            }
+        }
+
+        continuation
     }
 
     fn after_codegen(
         &mut self,
         ctx: &mut dyn AfterCodegenContext,
     ) -> Continuation {
-        self.check_diagnostics(
+        let continuation = self.check_diagnostics(
             ctx.diagnostics_mut().drain(),
             &ctx.build_context(),
-        )
+        );
+
+        if self.typescript && continuation == Continuation::Continue {
+            if let Some(bindings) = ctx.typescript_bindings() {
+                if let Err(err) = self.save_typescript_bindings(&bindings) {
+                    self.error = Some(err);
+                    return Continuation::Halt;
+                }
+            }
+        }
+
+        if self.graph && continuation == Continuation::Continue {
+            if let Some(graph) = ctx.pipeline_graph() {
+                if let Err(err) = self.save_pipeline_graph(&graph) {
+                    self.error = Some(err);
+                    return Continuation::Halt;
+                }
+            }
+        }
+
+        if self.target == Target::Native
+            && continuation == Continuation::Continue
+        {
+            // There's no need to compile the generated crate, the native
+            // engine only needs the pipeline graph.
+            let result = ctx
+                .rune_graph()
+                .context("No pipeline graph was generated")
+                .and_then(|graph| self.save_rune_graph(&graph));
+
+            if let Err(err) = result {
+                self.error = Some(err);
+                return Continuation::Halt;
+            }
+
+            // Keep going if --emit-dir wants the generated project, but
+            // we'll stop once it has been written.
+            if ctx.build_context().emit_project.is_none() {
+                return Continuation::Halt;
+            }
+        }
+
+        continuation
+    }
+
+    fn after_emit_project(
+        &mut self,
+        ctx: &mut dyn AfterCodegenContext,
+    ) -> Continuation {
+        let continuation = self.check_diagnostics(
+            ctx.diagnostics_mut().drain(),
+            &ctx.build_context(),
+        );
+
+        if continuation != Continuation::Continue {
+            return continuation;
+        }
+
+        if let Some(dir) = &ctx.build_context().emit_project {
+            log::info!(
+                "The generated project was written to \"{}\"",
+                dir.display()
+            );
+        }
+
+        if self.emit_only || self.target == Target::Native {
+            Continuation::Halt
+        } else {
+            // --emit-dir also wants the compiled Rune
+            Continuation::Continue
+        }
     }
 
     fn after_compile(
@@ -283,13 +771,61 @@ impl hotg_rune_compiler::hooks::Hooks for Hooks {
     ) -> Continuation {
         let CompilationResult(result) = ctx.take_compilation_result();
 
-        if let Err(err) = result
-            .map_err(Error::from)
-            .and_then(|c| self.save_binary(&c))
-        {
+        if let Err(err) = result.map_err(Error::from).and_then(|c| {
+            self.save_binary(&c)?;
+            let name = ctx.build_context().name.clone();
+            self.emit(Some((
+                PathBuf::from(name).with_extension("wasm"),
+                c.as_ref(),
+            )))
+        }) {
             self.error = Some(err);
+            return Continuation::Continue;
+        }
+
+        if let Some(report) = ctx.size_report() {
+            log_size_report(&report);
+        }
+
+        if let Some(symbols) = ctx.take_debug_symbols() {
+            if let Err(err) = self.save_debug_symbols(&symbols) {
+                self.error = Some(err);
+            }
         }
 
         Continuation::Continue
     }
 }
+
+fn log_size_report(report: &SizeReport) {
+    let SizeReport {
+        total,
+        code,
+        debug_info,
+        names,
+        strings,
+    } = *report;
+
+    log::info!("The Rune is {} bytes ({} bytes of code)", total, code);
+
+    if debug_info + names > 0 {
+        log::info!(
+            "Debug info adds {} bytes ({} bytes of DWARF and {} bytes of \
+             function names)",
+            debug_info + names,
+            debug_info,
+            names
+        );
+    }
+
+    if strings.saved() > 0 {
+        log::info!(
+            "Deduplicating {} string constants saved {} bytes ({} bytes \
+             instead of {})",
+            strings.references,
+            strings.saved(),
+            strings.table_bytes,
+            strings.referenced_bytes
+        );
+    }
+}
@@ -22,14 +22,22 @@ pub struct Inspect {
     format: Format,
     #[structopt(help = "The File to inspect", parse(try_from_str))]
     filename: PathBuf,
+    /// Compile proc-blocks for 64-bit WebAssembly (memory64) when
+    /// inspecting them.
+    #[structopt(long)]
+    memory64: bool,
 }
 
 impl Inspect {
     pub fn execute(self) -> Result<(), Error> {
-        let Inspect { format, filename } = self;
+        let Inspect {
+            format,
+            filename,
+            memory64,
+        } = self;
 
         if filename.is_dir() {
-            return proc_block::inspect(format, &filename);
+            return proc_block::inspect(format, &filename, memory64);
         }
 
         match filename.extension().and_then(|s| s.to_str()) {
@@ -5,28 +5,41 @@ use std::{
 
 use anyhow::{Context, Error};
 use hotg_rune_proc_blocks::{
-    ProcBlockDescriptor, TensorDescriptor, TensorDescriptors,
-    TransformDescriptor,
+    ParameterDescriptor, ProcBlockDescriptor, TransformDescriptor,
 };
 
 use crate::{inspect::wasm_custom_sections, Format};
 
-pub fn inspect(format: Format, proc_block_dir: &Path) -> Result<(), Error> {
+pub fn inspect(
+    format: Format,
+    proc_block_dir: &Path,
+    memory64: bool,
+) -> Result<(), Error> {
     log::info!("Inspecting \"{}\"", proc_block_dir.display());
 
     let dest = cache_dir(proc_block_dir);
+    let target = if memory64 {
+        "wasm64-unknown-unknown"
+    } else {
+        "wasm32-unknown-unknown"
+    };
 
     log::debug!("Writing probe to \"{}\"", dest.display());
 
-    generate_project(&dest, proc_block_dir)
+    generate_project(&dest, proc_block_dir, target)
         .context("Unable to generate the probe project")?;
 
     let mut cmd = Command::new("cargo");
     cmd.arg("build")
         .arg("--target")
-        .arg("wasm32-unknown-unknown")
+        .arg(target)
         .current_dir(&dest);
 
+    if memory64 {
+        // There are no pre-compiled standard libraries for wasm64
+        cmd.arg("-Zbuild-std=std,panic_abort");
+    }
+
     log::debug!("Executing {:?}", cmd);
 
     let status = cmd
@@ -39,7 +52,7 @@ pub fn inspect(format: Format, proc_block_dir: &Path) -> Result<(), Error> {
 
     let binary = dest
         .join("target")
-        .join("wasm32-unknown-unknown")
+        .join(target)
         .join("debug")
         .join("probe.wasm");
     let wasm = std::fs::read(&binary)
@@ -75,11 +88,19 @@ fn print_descriptor(metadata: &ProcBlockDescriptor) {
     let ProcBlockDescriptor {
         type_name,
         description,
+        version,
+        parameters,
         available_transforms,
+        output_shapes,
     } = metadata;
 
-    println!("{}", type_name);
-    println!("{}", "-".repeat(type_name.chars().count()));
+    let title = if version.is_empty() {
+        type_name.to_string()
+    } else {
+        format!("{} v{}", type_name, version)
+    };
+    println!("{}", title);
+    println!("{}", "-".repeat(title.chars().count()));
 
     for line in description.lines() {
         println!("{}", line);
@@ -89,6 +110,16 @@ fn print_descriptor(metadata: &ProcBlockDescriptor) {
         println!();
     }
 
+    if !parameters.is_empty() {
+        println!("Parameters:");
+
+        for parameter in parameters.iter() {
+            print_parameter(parameter);
+        }
+
+        println!();
+    }
+
     if available_transforms.is_empty() {
         println!("(no transforms registered)");
     } else {
@@ -98,40 +129,45 @@ fn print_descriptor(metadata: &ProcBlockDescriptor) {
             print_transform(transform);
         }
     }
-}
 
-fn print_transform(transform: &TransformDescriptor) {
-    let TransformDescriptor { inputs, outputs } = transform;
+    if !output_shapes.is_empty() {
+        println!();
+        println!("Output Shapes:");
 
-    print!("  inputs: ");
-    print_tensor_descriptors(inputs);
-    print!("  outputs: ");
-    print_tensor_descriptors(outputs);
+        for (i, shape) in output_shapes.iter().enumerate() {
+            println!("  {}: {}", i, shape);
+        }
+    }
 }
 
-fn print_tensor_descriptors(tensors: &TensorDescriptors) {
-    if tensors.len() == 1 {
-        print_tensor_descriptor(&tensors[0]);
-        println!();
-    } else {
-        print!("(");
-        for (i, tensor) in tensors.iter().enumerate() {
-            if i > 0 {
-                print!(", ");
-            }
+fn print_parameter(parameter: &ParameterDescriptor) {
+    let ParameterDescriptor {
+        name,
+        type_name,
+        description,
+        default_value,
+        range,
+    } = parameter;
 
-            print_tensor_descriptor(tensor);
-        }
-        println!(")");
+    print!("  {}: {}", name, type_name);
+    if let Some(default_value) = default_value {
+        print!(" = {}", default_value);
+    }
+    if let Some(range) = range {
+        print!(" ({})", range);
+    }
+
+    match description.lines().next() {
+        Some(summary) => println!(" - {}", summary),
+        None => println!(),
     }
 }
 
-fn print_tensor_descriptor(tensor: &TensorDescriptor) {
-    let TensorDescriptor {
-        element_type,
-        dimensions,
-    } = tensor;
-    print!("{}[{}]", element_type, dimensions);
+fn print_transform(transform: &TransformDescriptor) {
+    let TransformDescriptor { inputs, outputs } = transform;
+
+    println!("  inputs: {}", inputs);
+    println!("  outputs: {}", outputs);
 }
 
 const CARGO_TOML_TEMPLATE: &str = r#"
@@ -152,7 +188,11 @@ const LIB_RS_TEMPLATE: &str = "
 pub use $NAME::*;
 ";
 
-fn generate_project(dest: &PathBuf, filename: &Path) -> Result<(), Error> {
+fn generate_project(
+    dest: &PathBuf,
+    filename: &Path,
+    target: &str,
+) -> Result<(), Error> {
     let path = filename.to_string_lossy();
     let name = filename
         .file_stem()
@@ -169,10 +209,9 @@ fn generate_project(dest: &PathBuf, filename: &Path) -> Result<(), Error> {
     let name = name.replace("-", "_");
     write(dest.join("lib.rs"), LIB_RS_TEMPLATE.replace("$NAME", &name))?;
 
-    write(
-        dest.join("rust-toolchain.toml"),
-        hotg_rune_compiler::rust_toolchain().to_string(),
-    )?;
+    let rust_toolchain = hotg_rune_compiler::rust_toolchain_for_target(target);
+
+    write(dest.join("rust-toolchain.toml"), rust_toolchain.to_string())?;
 
     Ok(())
 }
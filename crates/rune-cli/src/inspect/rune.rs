@@ -10,6 +10,7 @@ use hotg_rune_compiler::{
     parse::{ResourceOrString, ResourceType},
 };
 use hotg_rune_core::Shape;
+use indexmap::IndexMap;
 use wasmparser::{BinaryReaderError, Parser, Payload};
 
 use crate::Format;
@@ -63,7 +64,7 @@ fn print_rune(rune: &RuneGraph) {
 }
 
 fn print_outputs(
-    outputs: &HashMap<Name, OutputSummary>,
+    outputs: &IndexMap<Name, OutputSummary>,
     tensors: &HashMap<TensorId, Shape<'static>>,
 ) {
     if outputs.is_empty() {
@@ -94,7 +95,7 @@ fn print_resources(resources: &HashMap<Name, Resource>) {
 }
 
 fn print_models(
-    models: &HashMap<Name, ModelSummary>,
+    models: &IndexMap<Name, ModelSummary>,
     tensors: &HashMap<TensorId, Shape<'static>>,
 ) {
     if models.is_empty() {
@@ -104,14 +105,18 @@ fn print_models(
     println!("Models:");
 
     for (name, model) in models {
-        println!("- {}: {}", name, model.file);
+        if model.mimetype.is_empty() {
+            println!("- {}: {}", name, model.file);
+        } else {
+            println!("- {}: {} ({})", name, model.file, model.mimetype);
+        }
         print_tensors("Inputs", &model.inputs, tensors);
         print_tensors("Outputs", &model.outputs, tensors);
     }
 }
 
 fn print_proc_blocks(
-    proc_blocks: &HashMap<Name, ProcBlockSummary>,
+    proc_blocks: &IndexMap<Name, ProcBlockSummary>,
     tensors: &HashMap<TensorId, Shape<'static>>,
 ) {
     if proc_blocks.is_empty() {
@@ -129,7 +134,7 @@ fn print_proc_blocks(
 }
 
 fn print_capabilities(
-    capabilities: &HashMap<Name, CapabilitySummary>,
+    capabilities: &IndexMap<Name, CapabilitySummary>,
     tensors: &HashMap<TensorId, Shape<'static>>,
 ) {
     if capabilities.is_empty() {
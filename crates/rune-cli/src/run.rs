@@ -2,8 +2,8 @@ use std::{collections::HashMap, path::PathBuf, str::FromStr};
 
 use anyhow::{Context, Error};
 use hotg_rune_runtime::{
-    builtins::{self, AccelerometerSamples, Arguments, AudioClip},
-    LoadError, NodeMetadata, Runtime,
+    builtins::{self, Arguments, EndOfStream, InputBinding, StreamSource},
+    LoadError, Runtime,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -39,6 +39,12 @@ pub struct Run {
                 capability"
     )]
     raw: Vec<PathBuf>,
+    #[structopt(
+        long,
+        help = "Where the STREAM capability reads raw bytes from (a file, \
+                \"-\" for stdin, or tcp://host:port)"
+    )]
+    stream: Vec<StreamSource>,
     #[structopt(
         long,
         aliases = &["rand"],
@@ -82,87 +88,54 @@ impl Run {
 
         self.load_resources(runtime.resources())?;
 
-        let caps = runtime.capabilities().clone();
-        log::debug!("Loading capabilities {:?}", caps);
-        runtime.input_tensors().extend(self.load_inputs(caps)?);
-
-        runtime.predict().context("Prediction failed")?;
-
-        let outputs = runtime.output_tensors();
-
-        let serialized = serde_json::to_string(outputs)
-            .context("Unable to serialize the output tensors to JSON")?;
-        println!("{}", serialized);
+        let streams = self.stream.clone();
+        let stream_ids = runtime
+            .register_capability("STREAM", |args| {
+                let args = Arguments(args.clone());
+                builtins::source(&streams, &args)
+                    .and_then(|source| builtins::stream(&args, source))
+            })
+            .context("Unable to open the STREAM capabilities")?;
+
+        log::debug!("Loading capabilities {:?}", runtime.capabilities());
+        self.input_binding().apply(&mut runtime)?;
+
+        if stream_ids.is_empty() {
+            runtime.predict().context("Prediction failed")?;
+            print_outputs(&runtime)?;
+            return Ok(());
+        }
 
-        Ok(())
+        // Keep running until the host runs out of bytes
+        loop {
+            match runtime.predict() {
+                Ok(_) => print_outputs(&runtime)?,
+                Err(e) if EndOfStream::is_cause_of(&e) => return Ok(()),
+                Err(e) => return Err(e.context("Prediction failed")),
+            }
+        }
     }
 
-    fn load_inputs(
-        self,
-        caps: HashMap<u32, NodeMetadata>,
-    ) -> Result<HashMap<u32, hotg_rune_runtime::Tensor>, Error> {
-        let mut inputs = HashMap::new();
-
-        for (id, metadata) in caps {
-            log::debug!("Loading {:?}", metadata);
-            let NodeMetadata {
-                kind, arguments, ..
-            } = metadata;
-            let args = Arguments(arguments);
-
-            let tensor = self.load_input(&kind, &args).with_context(|| {
-                format!("Unable to load the \"{}\" input", kind)
-            })?;
+    fn input_binding(&self) -> InputBinding {
+        let mut binding = InputBinding::new();
 
-            inputs.insert(id, tensor);
+        for path in &self.image {
+            binding = binding.image(path);
         }
-
-        Ok(inputs)
-    }
-
-    fn load_input(
-        &self,
-        kind: &str,
-        args: &Arguments,
-    ) -> Result<hotg_rune_runtime::Tensor, Error> {
-        match kind {
-            "IMAGE" => builtins::source(&self.image, args)
-                .and_then(|path| {
-                    image::open(path).with_context(|| {
-                        format!("Unable to read \"{}\"", path.display())
-                    })
-                })
-                .and_then(|img| builtins::image(args, &img)),
-
-            "SOUND" => builtins::source(&self.sound, args)
-                .and_then(|path| AudioClip::from_wav_file(path))
-                .and_then(|audio| builtins::sound(args, &audio)),
-
-            "ACCEL" => builtins::source(&self.accelerometer, args)
-                .and_then(|path| {
-                    AccelerometerSamples::from_file(path).with_context(|| {
-                        format!("Unable to read \"{}\"", path.display())
-                    })
-                })
-                .and_then(|samples| {
-                    builtins::accelerometer(args, dbg!(&samples))
-                }),
-
-            "RAW" => builtins::source(&self.raw, args)
-                .and_then(|path| {
-                    std::fs::read(path).with_context(|| {
-                        format!("Unable to read \"{}\"", path.display())
-                    })
-                })
-                .and_then(|data| builtins::raw(args, &data)),
-
-            "RAND" => match self.random {
-                Some(seed) => builtins::seeded_random(args, seed),
-                None => builtins::random(args),
-            },
-
-            _ => anyhow::bail!("Unknown input type, \"{}\"", kind),
+        for path in &self.sound {
+            binding = binding.sound(path);
+        }
+        for path in &self.accelerometer {
+            binding = binding.accelerometer(path);
+        }
+        for path in &self.raw {
+            binding = binding.raw(path);
+        }
+        if let Some(seed) = self.random {
+            binding = binding.random_seed(seed);
         }
+
+        binding
     }
 
     pub(crate) fn load_runtime(
@@ -194,6 +167,16 @@ impl Run {
     }
 }
 
+fn print_outputs(runtime: &Runtime) -> Result<(), Error> {
+    let outputs = runtime.output_tensors();
+
+    let serialized = serde_json::to_string(outputs)
+        .context("Unable to serialize the output tensors to JSON")?;
+    println!("{}", serialized);
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct FileResource {
     pub name: String,
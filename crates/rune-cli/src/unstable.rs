@@ -10,9 +10,12 @@
 //! - Set `requires = "enable_unstable"` so you can only use unstable features
 //!   after explicitly opting in.
 
-use std::path::PathBuf;
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
 
-use hotg_rune_compiler::FeatureFlags;
+use hotg_rune_compiler::{prebuilt::PrebuiltProcBlocks, FeatureFlags};
 
 #[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
 pub struct Unstable {
@@ -29,18 +32,56 @@ pub struct Unstable {
         global = true
     )]
     rune_repo_dir: Option<PathBuf>,
+    /// (unstable) Share compiled dependencies (e.g. builtin proc-blocks)
+    /// between Runes so they only need to be compiled once per Rune version.
+    #[structopt(
+        long,
+        env,
+        requires = "unstable",
+        parse(from_os_str),
+        global = true
+    )]
+    build_cache_dir: Option<PathBuf>,
+    /// (unstable) A JSON file mapping proc-block paths to the
+    /// `https://...#sha256=...` URLs of prebuilt copies, used instead of
+    /// compiling them from source when loading their descriptors.
+    #[structopt(
+        long,
+        env,
+        requires = "unstable",
+        parse(try_from_os_str = load_prebuilt_proc_blocks),
+        global = true
+    )]
+    prebuilt_proc_blocks: Option<PrebuiltProcBlocks>,
 }
 
 impl Unstable {
     pub fn feature_flags(&self) -> FeatureFlags {
         let mut features = FeatureFlags::default();
+        self.apply(&mut features);
+        features
+    }
 
+    /// Override any [`FeatureFlags`] that were set on the command-line.
+    pub fn apply(&self, features: &mut FeatureFlags) {
         if !self.unstable {
-            return features;
+            return;
         }
 
-        features.set_rune_repo_dir(self.rune_repo_dir.clone());
-
-        features
+        if let Some(dir) = &self.rune_repo_dir {
+            features.set_rune_repo_dir(dir.clone());
+        }
+        if let Some(dir) = &self.build_cache_dir {
+            features.set_build_cache_dir(dir.clone());
+        }
+        if let Some(prebuilt) = &self.prebuilt_proc_blocks {
+            features.set_prebuilt_proc_blocks(prebuilt.clone());
+        }
     }
 }
+
+fn load_prebuilt_proc_blocks(
+    path: &OsStr,
+) -> Result<PrebuiltProcBlocks, OsString> {
+    PrebuiltProcBlocks::load(Path::new(path)).map_err(|e| e.to_string().into())
+}
@@ -32,6 +32,10 @@ pub enum ElementType {
     U64,
     F64,
     I64,
+    /// A half-precision float, represented using [`half::f16`].
+    F16,
+    /// A "brain floating point" number, represented using [`half::bf16`].
+    BF16,
     String,
 }
 
@@ -87,6 +91,8 @@ impl ElementType {
             ElementType::U64 => Some(core::mem::size_of::<u64>()),
             ElementType::F64 => Some(core::mem::size_of::<f64>()),
             ElementType::I64 => Some(core::mem::size_of::<i64>()),
+            ElementType::F16 => Some(core::mem::size_of::<half::f16>()),
+            ElementType::BF16 => Some(core::mem::size_of::<half::bf16>()),
             ElementType::String => None,
         }
     }
@@ -103,6 +109,8 @@ impl ElementType {
             ElementType::U64 => "u64",
             ElementType::I64 => "i64",
             ElementType::F64 => "f64",
+            ElementType::F16 => "f16",
+            ElementType::BF16 => "bf16",
             ElementType::String => "utf8",
         }
     }
@@ -119,6 +127,8 @@ impl ElementType {
             "u64" => Some(ElementType::U64),
             "i64" => Some(ElementType::I64),
             "f64" => Some(ElementType::F64),
+            "f16" => Some(ElementType::F16),
+            "bf16" => Some(ElementType::BF16),
             "utf8" => Some(ElementType::String),
             _ => None,
         }
@@ -187,6 +197,14 @@ impl AsElementType for i64 {
     const TYPE: ElementType = ElementType::I64;
 }
 
+impl AsElementType for half::f16 {
+    const TYPE: ElementType = ElementType::F16;
+}
+
+impl AsElementType for half::bf16 {
+    const TYPE: ElementType = ElementType::BF16;
+}
+
 impl AsElementType for alloc::borrow::Cow<'static, str> {
     const TYPE: ElementType = ElementType::String;
 }
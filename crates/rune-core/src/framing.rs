@@ -0,0 +1,356 @@
+//! Framing for messages sent over unreliable byte streams (e.g. a UART).
+//!
+//! Each frame is the payload followed by a big-endian CRC-16 checksum,
+//! encoded using [Consistent Overhead Byte Stuffing][cobs] (COBS) and
+//! terminated by a [`FRAME_DELIMITER`]. Because COBS guarantees the delimiter
+//! never appears inside a frame, a receiver that joins the stream part way
+//! through (or loses some bytes to noise) can always resynchronize at the next
+//! delimiter.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use hotg_rune_core::framing::{self, FrameDecoder};
+//!
+//! let mut stream = Vec::new();
+//! framing::encode_frame(b"Hello, World!", &mut stream);
+//!
+//! let mut decoder = FrameDecoder::new();
+//! let frames: Vec<_> = decoder.decode(&stream).collect();
+//!
+//! assert_eq!(frames, vec![Ok(b"Hello, World!".to_vec())]);
+//! ```
+//!
+//! [cobs]: https://en.wikipedia.org/wiki/Consistent_Overhead_Byte_Stuffing
+
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+
+/// The byte used to mark the end of a frame.
+pub const FRAME_DELIMITER: u8 = 0;
+
+/// How messages should be framed when written to a byte stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Framing {
+    /// Send each message as-is.
+    None,
+    /// COBS-encode each message and append a CRC-16 checksum.
+    Cobs,
+}
+
+impl Framing {
+    /// Parse the name used for this [`Framing`] in a Runefile.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Framing::None),
+            "cobs" => Some(Framing::Cobs),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Framing::None => "none",
+            Framing::Cobs => "cobs",
+        }
+    }
+}
+
+impl Default for Framing {
+    fn default() -> Self { Framing::None }
+}
+
+/// Frames longer than this will be discarded by a [`FrameDecoder`] unless
+/// configured otherwise.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 64 * 1024;
+
+/// Calculate the CRC-16/CCITT-FALSE checksum of some data.
+///
+/// ```rust
+/// assert_eq!(hotg_rune_core::framing::crc16(b"123456789"), 0x29B1);
+/// ```
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Append a framed version of `payload` to `dest`, including the trailing
+/// [`FRAME_DELIMITER`].
+pub fn encode_frame(payload: &[u8], dest: &mut Vec<u8>) {
+    let checksum = crc16(payload).to_be_bytes();
+    cobs_encode(payload.iter().chain(checksum.iter()).copied(), dest);
+    dest.push(FRAME_DELIMITER);
+}
+
+/// Decode a single frame (without its trailing [`FRAME_DELIMITER`]),
+/// checking that the payload wasn't corrupted.
+///
+/// ```rust
+/// use hotg_rune_core::framing::{self, FramingError};
+///
+/// let mut frame = Vec::new();
+/// framing::encode_frame(b"Hello", &mut frame);
+/// frame.pop();
+///
+/// assert_eq!(framing::decode_frame(&frame).unwrap(), b"Hello");
+///
+/// // Flip a bit in the payload
+/// frame[2] ^= 0x01;
+/// assert!(matches!(
+///     framing::decode_frame(&frame),
+///     Err(FramingError::ChecksumMismatch { .. })
+/// ));
+/// ```
+pub fn decode_frame(frame: &[u8]) -> Result<Vec<u8>, FramingError> {
+    let mut decoded = cobs_decode(frame)?;
+
+    if decoded.len() < 2 {
+        return Err(FramingError::TooShort);
+    }
+
+    let checksum_start = decoded.len() - 2;
+    let expected = u16::from_be_bytes([
+        decoded[checksum_start],
+        decoded[checksum_start + 1],
+    ]);
+    decoded.truncate(checksum_start);
+    let actual = crc16(&decoded);
+
+    if expected != actual {
+        return Err(FramingError::ChecksumMismatch { expected, actual });
+    }
+
+    Ok(decoded)
+}
+
+fn cobs_encode(data: impl Iterator<Item = u8>, dest: &mut Vec<u8>) {
+    let mut code_index = dest.len();
+    let mut code = 1_u8;
+    dest.push(0);
+
+    for byte in data {
+        if byte == 0 {
+            dest[code_index] = code;
+            code_index = dest.len();
+            code = 1;
+            dest.push(0);
+        } else {
+            dest.push(byte);
+            code += 1;
+
+            if code == 0xFF {
+                dest[code_index] = code;
+                code_index = dest.len();
+                code = 1;
+                dest.push(0);
+            }
+        }
+    }
+
+    dest[code_index] = code;
+}
+
+fn cobs_decode(encoded: &[u8]) -> Result<Vec<u8>, FramingError> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+
+    while i < encoded.len() {
+        let code = encoded[i];
+        if code == 0 {
+            return Err(FramingError::InvalidEncoding);
+        }
+        i += 1;
+
+        let end = i + code as usize - 1;
+        let block = encoded.get(i..end).ok_or(FramingError::InvalidEncoding)?;
+        if block.contains(&0) {
+            return Err(FramingError::InvalidEncoding);
+        }
+        decoded.extend_from_slice(block);
+        i = end;
+
+        if code != 0xFF && i < encoded.len() {
+            decoded.push(0);
+        }
+    }
+
+    Ok(decoded)
+}
+
+/// Incrementally pull frames out of a stream of bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    max_frame_len: usize,
+    overflowed: bool,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        FrameDecoder::with_max_frame_len(DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Create a [`FrameDecoder`] which will reject frames longer than
+    /// `max_frame_len` bytes instead of buffering them.
+    pub fn with_max_frame_len(max_frame_len: usize) -> Self {
+        FrameDecoder {
+            buffer: Vec::new(),
+            max_frame_len,
+            overflowed: false,
+        }
+    }
+
+    /// Process a single byte, returning the decoded frame when it completes
+    /// one.
+    ///
+    /// Empty frames (i.e. consecutive delimiters) are silently skipped so a
+    /// transmitter can send a delimiter to force the receiver to resync.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Vec<u8>, FramingError>> {
+        if byte != FRAME_DELIMITER {
+            if self.buffer.len() < self.max_frame_len {
+                self.buffer.push(byte);
+            } else {
+                self.overflowed = true;
+            }
+            return None;
+        }
+
+        let result = if self.overflowed {
+            Some(Err(FramingError::TooLong))
+        } else if self.buffer.is_empty() {
+            None
+        } else {
+            Some(decode_frame(&self.buffer))
+        };
+
+        self.buffer.clear();
+        self.overflowed = false;
+
+        result
+    }
+
+    /// Process a chunk of bytes, yielding each frame that gets completed.
+    ///
+    /// Bytes after the last delimiter are kept until the next call.
+    pub fn decode<'a>(
+        &'a mut self,
+        bytes: &'a [u8],
+    ) -> impl Iterator<Item = Result<Vec<u8>, FramingError>> + 'a {
+        bytes.iter().filter_map(move |&byte| self.push(byte))
+    }
+
+    /// The number of bytes received since the last complete frame.
+    pub fn pending(&self) -> usize { self.buffer.len() }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self { FrameDecoder::new() }
+}
+
+/// The reasons a frame may be rejected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FramingError {
+    /// The frame isn't valid COBS.
+    InvalidEncoding,
+    /// The frame was too short to contain a checksum.
+    TooShort,
+    /// The frame was longer than the [`FrameDecoder`] allows.
+    TooLong,
+    /// The payload was corrupted.
+    ChecksumMismatch { expected: u16, actual: u16 },
+}
+
+impl Display for FramingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FramingError::InvalidEncoding => {
+                f.write_str("The frame isn't valid COBS")
+            },
+            FramingError::TooShort => {
+                f.write_str("The frame is too short to contain a checksum")
+            },
+            FramingError::TooLong => f.write_str("The frame is too long"),
+            FramingError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch (expected {:#06x} but got {:#06x})",
+                expected, actual
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FramingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(payload: &[u8]) {
+        let mut frame = Vec::new();
+        encode_frame(payload, &mut frame);
+
+        assert_eq!(frame.last(), Some(&FRAME_DELIMITER));
+        assert!(!frame[..frame.len() - 1].contains(&FRAME_DELIMITER));
+
+        let got = decode_frame(&frame[..frame.len() - 1]).unwrap();
+        assert_eq!(got, payload);
+    }
+
+    #[test]
+    fn round_trip_interesting_payloads() {
+        round_trip(b"");
+        round_trip(&[0]);
+        round_trip(&[0, 0, 1, 0]);
+        round_trip(&[0xFF; 253]);
+        round_trip(&[0xFF; 254]);
+        round_trip(&[0xFF; 255]);
+        round_trip(&(0..=255).cycle().take(1000).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn decoder_recovers_from_garbage() {
+        let mut stream = vec![0x42, 0x13, 0x37, FRAME_DELIMITER];
+        encode_frame(b"first", &mut stream);
+        let corrupt_start = stream.len();
+        encode_frame(b"second", &mut stream);
+        stream[corrupt_start + 3] ^= 0xA5;
+        stream.push(FRAME_DELIMITER);
+        encode_frame(b"third", &mut stream);
+
+        let mut decoder = FrameDecoder::new();
+        let got: Vec<_> = decoder.decode(&stream).collect();
+
+        assert_eq!(got.len(), 4);
+        assert!(got[0].is_err());
+        assert_eq!(got[1], Ok(b"first".to_vec()));
+        assert!(got[2].is_err());
+        assert_eq!(got[3], Ok(b"third".to_vec()));
+        assert_eq!(decoder.pending(), 0);
+    }
+
+    #[test]
+    fn decoder_rejects_long_frames() {
+        let mut stream = Vec::new();
+        encode_frame(&[1; 32], &mut stream);
+        encode_frame(b"ok", &mut stream);
+
+        let mut decoder = FrameDecoder::with_max_frame_len(16);
+        let got: Vec<_> = decoder.decode(&stream).collect();
+
+        assert_eq!(got, vec![Err(FramingError::TooLong), Ok(b"ok".to_vec())]);
+    }
+}
@@ -17,18 +17,24 @@ extern crate std;
 extern crate alloc;
 
 mod element_type;
+pub mod framing;
 mod logging;
 mod pixel_format;
+mod resize_filter;
 mod resources;
+pub mod serial_format;
 mod shape;
 mod tensor;
 mod tensor_list;
 mod value;
 
+pub use half::{bf16, f16};
+
 pub use crate::{
     element_type::{AsElementType, ElementType, UnknownElementType},
     logging::SerializableRecord,
     pixel_format::{PixelFormat, PixelFormatConversionError},
+    resize_filter::ResizeFilter,
     resources::{decode_inline_resource, InlineResource},
     shape::Shape,
     tensor::{Tensor, TensorView, TensorViewMut},
@@ -45,6 +51,12 @@ pub const ONNX_MIMETYPE: &str = "application/onnx-model";
 /// The mimetype used for a TensorFlow JS model.
 pub const TFJS_MIMETYPE: &str = "application/tfjs-model";
 
+/// The custom section the compiler embeds a summary of the Rune's pipeline
+/// in.
+pub const GRAPH_CUSTOM_SECTION: &str = ".rune_graph";
+/// The custom section recording which version of `rune` compiled a Rune.
+pub const VERSION_CUSTOM_SECTION: &str = ".rune_version";
+
 /// The version number for this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -99,6 +111,9 @@ constants! {
         IMAGE = 4,
         RAW = 5,
         FLOAT_IMAGE = 6,
+        /// Raw bytes read from a host-provided stream (e.g. a file or
+        /// socket), one tensor's worth at a time.
+        STREAM = 7,
     }
 }
 
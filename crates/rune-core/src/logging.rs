@@ -11,6 +11,9 @@ pub struct SerializableRecord<'a> {
     pub module_path: Option<Cow<'a, str>>,
     pub file: Option<Cow<'a, str>>,
     pub line: Option<u32>,
+    /// The name of the pipeline stage that was running when the message was
+    /// logged.
+    pub node: Option<Cow<'a, str>>,
 }
 
 impl<'a> SerializableRecord<'a> {
@@ -22,6 +25,7 @@ impl<'a> SerializableRecord<'a> {
             module_path,
             file,
             line,
+            node,
         } = self;
 
         SerializableRecord {
@@ -31,6 +35,7 @@ impl<'a> SerializableRecord<'a> {
             module_path: module_path.map(|m| m.into_owned().into()),
             file: file.map(|f| f.into_owned().into()),
             line,
+            node: node.map(|n| n.into_owned().into()),
         }
     }
 
@@ -58,6 +63,7 @@ impl<'a> From<&'a Record<'a>> for SerializableRecord<'a> {
             module_path: r.module_path().map(Cow::Borrowed),
             file: r.file().map(Cow::Borrowed),
             line: r.line(),
+            node: None,
         }
     }
 }
@@ -71,6 +77,7 @@ impl<'a> Default for SerializableRecord<'a> {
             module_path: Default::default(),
             file: Default::default(),
             line: Default::default(),
+            node: Default::default(),
         }
     }
 }
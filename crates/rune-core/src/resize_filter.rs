@@ -0,0 +1,26 @@
+use crate::Value;
+
+/// The algorithm used when an image capability resizes the host's image to
+/// the dimensions a Rune asked for.
+///
+/// In a Runefile, this is set using `resize: "@ResizeFilter::Bilinear"`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub enum ResizeFilter {
+    Nearest = 0,
+    Bilinear = 1,
+    Bicubic = 2,
+    Lanczos = 3,
+}
+
+impl From<ResizeFilter> for i32 {
+    fn from(r: ResizeFilter) -> i32 { r as i32 }
+}
+
+impl From<ResizeFilter> for u32 {
+    fn from(r: ResizeFilter) -> u32 { r as u32 }
+}
+
+impl From<ResizeFilter> for Value {
+    fn from(r: ResizeFilter) -> Value { Value::Integer(r.into()) }
+}
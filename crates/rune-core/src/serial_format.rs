@@ -0,0 +1,377 @@
+//! The encodings a Rune can use for the messages it writes to a SERIAL
+//! output.
+//!
+//! JSON is the default, but it is quite verbose for low-bandwidth links like
+//! BLE or LoRa, so a Runefile can pick a more compact [`SerialFormat`] with
+//! the `format` argument.
+//!
+//! ```yaml
+//! pipeline:
+//!   serial:
+//!     out: SERIAL
+//!     inputs:
+//!       - label
+//!     args:
+//!       format: cbor
+//! ```
+//!
+//! - [`SerialFormat::Json`] - the original JSON messages
+//! - [`SerialFormat::Cbor`] - exactly the same messages as JSON, but encoded
+//!   using [CBOR](https://cbor.io/)
+//! - [`SerialFormat::Protobuf`] - a [`ProtobufMessage`], using the schema in
+//!   [`PROTOBUF_SCHEMA`]
+//!
+//! Runes which don't use JSON tell the runtime which format they chose by
+//! setting the output's `format` argument.
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    convert::TryFrom,
+    fmt::{self, Display, Formatter},
+};
+
+/// How the messages written to a SERIAL output are encoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SerialFormat {
+    Json,
+    Cbor,
+    Protobuf,
+}
+
+impl SerialFormat {
+    /// Every [`SerialFormat`].
+    pub const ALL: [SerialFormat; 3] = [
+        SerialFormat::Json,
+        SerialFormat::Cbor,
+        SerialFormat::Protobuf,
+    ];
+
+    /// Parse the name used for this [`SerialFormat`] in a Runefile.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(SerialFormat::Json),
+            "cbor" => Some(SerialFormat::Cbor),
+            "protobuf" => Some(SerialFormat::Protobuf),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            SerialFormat::Json => "json",
+            SerialFormat::Cbor => "cbor",
+            SerialFormat::Protobuf => "protobuf",
+        }
+    }
+}
+
+impl Default for SerialFormat {
+    fn default() -> Self { SerialFormat::Json }
+}
+
+/// The Protocol Buffers schema used by [`SerialFormat::Protobuf`], for hosts
+/// which want to generate their own decoder.
+pub const PROTOBUF_SCHEMA: &str = r#"syntax = "proto3";
+
+package hotg.rune;
+
+message SerialMessage {
+  uint32 channel = 1;
+  repeated SerialTensor tensors = 2;
+}
+
+message SerialTensor {
+  // The element type (e.g. "f32" or "utf8").
+  string type_name = 1;
+  repeated uint32 dimensions = 2;
+  // The elements in row-major order, as little-endian bytes.
+  bytes elements = 3;
+  // The elements of a "utf8" tensor.
+  repeated string strings = 4;
+  // The stage output this tensor came from, for multi-input outputs.
+  string source = 5;
+}
+"#;
+
+/// A message written to a SERIAL output using [`SerialFormat::Protobuf`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProtobufMessage {
+    pub channel: u32,
+    pub tensors: Vec<ProtobufTensor>,
+}
+
+/// A single tensor in a [`ProtobufMessage`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProtobufTensor {
+    pub type_name: String,
+    pub dimensions: Vec<u32>,
+    /// The tensor's elements as little-endian bytes.
+    pub elements: Vec<u8>,
+    /// The elements of a string tensor.
+    pub strings: Vec<String>,
+    pub source: Option<String>,
+}
+
+const VARINT: u8 = 0;
+const FIXED_64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+const FIXED_32: u8 = 5;
+
+impl ProtobufMessage {
+    /// Append the encoded message to a buffer.
+    pub fn encode(&self, buffer: &mut Vec<u8>) {
+        write_key(buffer, 1, VARINT);
+        write_varint(buffer, self.channel.into());
+
+        let mut tensor = Vec::new();
+
+        for t in &self.tensors {
+            tensor.clear();
+            t.encode(&mut tensor);
+            write_bytes(buffer, 2, &tensor);
+        }
+    }
+
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, ProtobufError> {
+        let mut msg = ProtobufMessage::default();
+
+        while !bytes.is_empty() {
+            match read_field(&mut bytes)? {
+                (1, Field::Varint(channel)) => {
+                    msg.channel = u32::try_from(channel)
+                        .map_err(|_| ProtobufError::Overflow)?;
+                },
+                (2, Field::Bytes(tensor)) => {
+                    msg.tensors.push(ProtobufTensor::decode(tensor)?);
+                },
+                // Ignore unknown fields so the schema can be extended later
+                _ => {},
+            }
+        }
+
+        Ok(msg)
+    }
+}
+
+impl ProtobufTensor {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        write_bytes(buffer, 1, self.type_name.as_bytes());
+
+        let mut dimensions = Vec::new();
+        for &d in &self.dimensions {
+            write_varint(&mut dimensions, d.into());
+        }
+        write_bytes(buffer, 2, &dimensions);
+
+        if !self.elements.is_empty() {
+            write_bytes(buffer, 3, &self.elements);
+        }
+        for s in &self.strings {
+            write_bytes(buffer, 4, s.as_bytes());
+        }
+        if let Some(source) = &self.source {
+            write_bytes(buffer, 5, source.as_bytes());
+        }
+    }
+
+    fn decode(mut bytes: &[u8]) -> Result<Self, ProtobufError> {
+        let mut tensor = ProtobufTensor::default();
+
+        while !bytes.is_empty() {
+            match read_field(&mut bytes)? {
+                (1, Field::Bytes(name)) => tensor.type_name = utf8(name)?,
+                // Repeated numbers may or may not be packed
+                (2, Field::Varint(d)) => tensor.dimensions.push(dimension(d)?),
+                (2, Field::Bytes(mut packed)) => {
+                    while !packed.is_empty() {
+                        let d = read_varint(&mut packed)?;
+                        tensor.dimensions.push(dimension(d)?);
+                    }
+                },
+                (3, Field::Bytes(elements)) => {
+                    tensor.elements.extend_from_slice(elements)
+                },
+                (4, Field::Bytes(s)) => tensor.strings.push(utf8(s)?),
+                (5, Field::Bytes(s)) => tensor.source = Some(utf8(s)?),
+                _ => {},
+            }
+        }
+
+        Ok(tensor)
+    }
+}
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+fn write_key(buffer: &mut Vec<u8>, field: u32, wire_type: u8) {
+    write_varint(buffer, u64::from(field << 3 | u32::from(wire_type)));
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn write_bytes(buffer: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_key(buffer, field, LENGTH_DELIMITED);
+    write_varint(buffer, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, ProtobufError> {
+    let mut value = 0_u64;
+
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) =
+            bytes.split_first().ok_or(ProtobufError::Truncated)?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7F) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ProtobufError::Overflow)
+}
+
+fn read_field<'a>(
+    bytes: &mut &'a [u8],
+) -> Result<(u64, Field<'a>), ProtobufError> {
+    let key = read_varint(bytes)?;
+    let field = key >> 3;
+
+    let value = match (key & 0x7) as u8 {
+        VARINT => Field::Varint(read_varint(bytes)?),
+        LENGTH_DELIMITED => {
+            let len = usize::try_from(read_varint(bytes)?)
+                .map_err(|_| ProtobufError::Overflow)?;
+            if bytes.len() < len {
+                return Err(ProtobufError::Truncated);
+            }
+            let (value, rest) = bytes.split_at(len);
+            *bytes = rest;
+            Field::Bytes(value)
+        },
+        FIXED_64 => skip(bytes, 8)?,
+        FIXED_32 => skip(bytes, 4)?,
+        other => return Err(ProtobufError::UnknownWireType(other)),
+    };
+
+    Ok((field, value))
+}
+
+fn skip<'a>(
+    bytes: &mut &'a [u8],
+    len: usize,
+) -> Result<Field<'a>, ProtobufError> {
+    if bytes.len() < len {
+        return Err(ProtobufError::Truncated);
+    }
+    *bytes = &bytes[len..];
+    Ok(Field::Fixed)
+}
+
+fn dimension(d: u64) -> Result<u32, ProtobufError> {
+    u32::try_from(d).map_err(|_| ProtobufError::Overflow)
+}
+
+fn utf8(bytes: &[u8]) -> Result<String, ProtobufError> {
+    core::str::from_utf8(bytes)
+        .map(String::from)
+        .map_err(|_| ProtobufError::InvalidUtf8)
+}
+
+/// The reasons a [`ProtobufMessage`] may fail to decode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProtobufError {
+    /// The message ended part way through a field.
+    Truncated,
+    /// A number was too big.
+    Overflow,
+    /// A string wasn't valid UTF-8.
+    InvalidUtf8,
+    UnknownWireType(u8),
+}
+
+impl Display for ProtobufError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtobufError::Truncated => {
+                f.write_str("The message ended part way through a field")
+            },
+            ProtobufError::Overflow => f.write_str("A number was too big"),
+            ProtobufError::InvalidUtf8 => {
+                f.write_str("A string wasn't valid UTF-8")
+            },
+            ProtobufError::UnknownWireType(ty) => {
+                write!(f, "Unknown wire type, {}", ty)
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ProtobufError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protobuf_round_trip() {
+        let msg = ProtobufMessage {
+            channel: 2,
+            tensors: vec![
+                ProtobufTensor {
+                    type_name: "f32".into(),
+                    dimensions: vec![1, 2],
+                    elements: [0.5_f32, 1.5]
+                        .iter()
+                        .flat_map(|f| f.to_le_bytes())
+                        .collect(),
+                    strings: Vec::new(),
+                    source: Some("fft".into()),
+                },
+                ProtobufTensor {
+                    type_name: "utf8".into(),
+                    dimensions: vec![1],
+                    elements: Vec::new(),
+                    strings: vec!["up".into()],
+                    source: None,
+                },
+            ],
+        };
+        let mut buffer = Vec::new();
+
+        msg.encode(&mut buffer);
+        let got = ProtobufMessage::decode(&buffer).unwrap();
+
+        assert_eq!(got, msg);
+        assert_eq!(
+            ProtobufMessage::decode(&buffer[..buffer.len() - 1]),
+            Err(ProtobufError::Truncated)
+        );
+    }
+
+    #[test]
+    fn known_protobuf_encoding() {
+        let msg = ProtobufMessage {
+            channel: 150,
+            tensors: Vec::new(),
+        };
+        let mut buffer = Vec::new();
+
+        msg.encode(&mut buffer);
+
+        // The example from the Protocol Buffers encoding guide
+        assert_eq!(buffer, [0x08, 0x96, 0x01]);
+    }
+}
@@ -13,7 +13,7 @@ use crate::element_type::ElementType;
 
 /// A tensor's shape.
 #[derive(
-    Debug, Clone, PartialEq, Hash, serde::Serialize, serde::Deserialize,
+    Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
 )]
 pub struct Shape<'a> {
     element_type: ElementType,
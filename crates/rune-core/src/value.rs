@@ -14,6 +14,7 @@ pub enum Value {
     Integer(i32),
     Float(f32),
     SignedByte(i8),
+    Double(f64),
 }
 
 impl Value {
@@ -59,6 +60,15 @@ impl Value {
                     Value::Float(f32::from_le_bytes(buffer))
                 })
             },
+            Type::Double => {
+                const LEN: usize = core::mem::size_of::<f64>();
+
+                bytes.get(..LEN).map(|bytes| {
+                    let mut buffer = [0; LEN];
+                    buffer.copy_from_slice(bytes);
+                    Value::Double(f64::from_le_bytes(buffer))
+                })
+            },
         }
     }
 
@@ -93,6 +103,11 @@ impl Value {
                 buffer[..bytes.len()].copy_from_slice(&bytes);
                 bytes.len()
             },
+            Value::Double(double) => {
+                let bytes = double.to_le_bytes();
+                buffer[..bytes.len()].copy_from_slice(&bytes);
+                bytes.len()
+            },
         }
     }
 
@@ -103,6 +118,7 @@ impl Value {
             Value::Short(_) => Type::Short,
             Value::Integer(_) => Type::Integer,
             Value::Float(_) => Type::Float,
+            Value::Double(_) => Type::Double,
         }
     }
 }
@@ -115,6 +131,7 @@ impl Display for Value {
             Value::Short(s) => write!(f, "{}_i16", s),
             Value::Integer(i) => write!(f, "{}_i32", i),
             Value::Float(float) => write!(f, "{:.1}", float),
+            Value::Double(double) => write!(f, "{:.1}_f64", double),
         }
     }
 }
@@ -122,12 +139,82 @@ impl Display for Value {
 impl FromStr for Value {
     type Err = core::num::ParseFloatError;
 
+    /// Parse a number, using the smallest type which can represent it.
+    ///
+    /// Integers become [`Value::Integer`]. Floats become [`Value::Float`]
+    /// unless they have a `f64` suffix (e.g. `0.1_f64`) or can't be
+    /// represented as a `f32` without losing precision (e.g.
+    /// `3.141592653589793`), in which case they become [`Value::Double`].
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if let Ok(integer) = s.parse() {
-            Ok(Value::Integer(integer))
+            return Ok(Value::Integer(integer));
+        }
+
+        if let Some(double) =
+            s.strip_suffix("_f64").or_else(|| s.strip_suffix("f64"))
+        {
+            return double.parse().map(Value::Double);
+        }
+        if let Some(float) =
+            s.strip_suffix("_f32").or_else(|| s.strip_suffix("f32"))
+        {
+            return float.parse().map(Value::Float);
+        }
+
+        let double: f64 = s.parse()?;
+
+        if fits_in_f32(double) {
+            Ok(Value::Float(double as f32))
         } else {
-            s.parse().map(Value::Float)
+            Ok(Value::Double(double))
+        }
+    }
+}
+
+/// Would converting this number to a `f32` and back give us the same number
+/// the user wrote?
+///
+/// Casting literally (i.e. `double as f32 as f64 == double`) would reject
+/// values like `0.1`, which aren't exactly representable as a `f64` either, so
+/// we compare the shortest decimal representation of the `f32` instead.
+fn fits_in_f32(double: f64) -> bool {
+    use alloc::string::ToString;
+
+    let float = double as f32;
+
+    if !float.is_finite() {
+        return !double.is_finite();
+    }
+
+    float.to_string().parse::<f64>() == Ok(double)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_the_smallest_type_that_fits() {
+        let inputs = [
+            ("42", Value::Integer(42)),
+            ("-7", Value::Integer(-7)),
+            ("0.5", Value::Float(0.5)),
+            ("0.1", Value::Float(0.1)),
+            ("1e10", Value::Float(1e10)),
+            ("3.141592653589793", Value::Double(core::f64::consts::PI)),
+            ("1e300", Value::Double(1e300)),
+            ("0.1_f64", Value::Double(0.1)),
+            ("0.1f64", Value::Double(0.1)),
+            ("2_f64", Value::Double(2.0)),
+            ("3.141592653589793_f32", Value::Float(core::f32::consts::PI)),
+        ];
+
+        for (src, should_be) in inputs {
+            let got: Value = src.parse().unwrap();
+            assert_eq!(got, should_be, "{}", src);
         }
+
+        assert!("hello".parse::<Value>().is_err());
     }
 }
 
@@ -154,6 +241,8 @@ pub enum Type {
     // prefer not to add a proc-macro dependency to the crate that every
     // single rune or proc block will depend on.
     SignedByte = 7,
+    /// A 64-bit floating point number.
+    Double = 8,
 }
 
 impl From<Type> for u32 {
@@ -169,6 +258,8 @@ impl TryFrom<u32> for Type {
             2 => Ok(Type::Float),
             5 => Ok(Type::Byte),
             6 => Ok(Type::Short),
+            7 => Ok(Type::SignedByte),
+            8 => Ok(Type::Double),
             _ => Err(()),
         }
     }
@@ -215,7 +306,14 @@ macro_rules! impl_as_type {
     }
 }
 
-impl_as_type!(u8 => Byte, i16 => Short, i32 => Integer, f32 => Float, i8 => SignedByte);
+impl_as_type!(
+    u8 => Byte,
+    i16 => Short,
+    i32 => Integer,
+    f32 => Float,
+    i8 => SignedByte,
+    f64 => Double,
+);
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct InvalidConversionError {
@@ -0,0 +1,5 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/runtime.proto")
+        .expect("Unable to generate the gRPC bindings");
+}
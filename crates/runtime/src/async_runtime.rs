@@ -0,0 +1,304 @@
+//! Using a [`Runtime`] from async code.
+//!
+//! WebAssembly engines run a Rune synchronously and a [`Runtime`] can't be
+//! moved between threads, so blocking on [`Runtime::predict()`] inside an
+//! async task would stall every other task on that executor thread. An
+//! [`AsyncRuntime`] instead gives each Rune its own thread and forwards
+//! requests to it, letting the calling task yield until the prediction is
+//! done. Several Runes can be in flight at once from the same thread pool.
+//!
+//! Runes with a `SOUND` capability marked `stream: true` can also be fed
+//! samples incrementally with [`AsyncRuntime::push_samples_async()`], which
+//! resolves to the outputs from every window that was completed.
+//!
+//! ```rust,no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::collections::HashMap;
+//!
+//! use hotg_rune_runtime::{async_runtime::AsyncRuntime, Runtime, Tensor};
+//!
+//! let builder = Runtime::builder().rune(std::fs::read("sine.rune")?);
+//! let runtime = AsyncRuntime::load(builder).await?;
+//!
+//! let mut inputs = HashMap::new();
+//! inputs.insert(1, Tensor::new(&[0.5_f32], &[1, 1]));
+//!
+//! let outputs = runtime.predict_async(inputs).await?;
+//! println!("{:?}", outputs);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+use anyhow::{Context, Error};
+use tokio::sync::oneshot;
+
+use crate::{
+    NodeMetadata, OutputTensor, Runtime, RuntimeBuilder, Tensor, TensorElement,
+};
+
+type Reply<T> = oneshot::Sender<Result<T, Error>>;
+
+enum Command {
+    Predict(HashMap<u32, Tensor>, Reply<HashMap<u32, Vec<OutputTensor>>>),
+    /// Run an arbitrary closure against the [`Runtime`].
+    Run(Box<dyn FnOnce(&mut Runtime) + Send>),
+}
+
+/// A handle to a [`Runtime`] running on its own thread.
+///
+/// Dropping the handle stops the thread once any in-flight prediction has
+/// finished.
+pub struct AsyncRuntime {
+    commands: Mutex<mpsc::Sender<Command>>,
+    capabilities: HashMap<u32, NodeMetadata>,
+    outputs: HashMap<u32, NodeMetadata>,
+}
+
+impl AsyncRuntime {
+    /// Load a Rune on a new thread, waiting until it has been initialized.
+    pub async fn load(builder: RuntimeBuilder) -> Result<Self, Error> {
+        let (commands, receiver) = mpsc::channel();
+        let (loaded, on_loaded) = oneshot::channel();
+
+        thread::Builder::new()
+            .name(String::from("rune-runtime"))
+            .spawn(move || match builder.build() {
+                Ok(runtime) => {
+                    let metadata = (
+                        runtime.capabilities().clone(),
+                        runtime.outputs().clone(),
+                    );
+                    if loaded.send(Ok(metadata)).is_ok() {
+                        worker(runtime, receiver);
+                    }
+                },
+                Err(e) => {
+                    let _ = loaded.send(Err(Error::from(e)));
+                },
+            })
+            .context("Unable to start the runtime thread")?;
+
+        let (capabilities, outputs) = on_loaded
+            .await
+            .context("The runtime thread crashed while loading the Rune")??;
+
+        Ok(AsyncRuntime {
+            commands: Mutex::new(commands),
+            capabilities,
+            outputs,
+        })
+    }
+
+    /// Get a mapping from each capability's ID to its metadata.
+    pub fn capabilities(&self) -> &HashMap<u32, NodeMetadata> {
+        &self.capabilities
+    }
+
+    /// Get a mapping from each output's ID to its metadata.
+    pub fn outputs(&self) -> &HashMap<u32, NodeMetadata> { &self.outputs }
+
+    /// Set the input tensors for each capability, keyed by ID, then run the
+    /// Rune without blocking the current task.
+    ///
+    /// Capabilities which aren't mentioned keep their previous input, just
+    /// like [`Runtime::input_tensors()`]. Returns the tensors written to each
+    /// output during this run.
+    pub async fn predict_async(
+        &self,
+        inputs: HashMap<u32, Tensor>,
+    ) -> Result<HashMap<u32, Vec<OutputTensor>>, Error> {
+        self.send(|reply| Command::Predict(inputs, reply)).await
+    }
+
+    /// Push samples to a streamed capability (see
+    /// [`Runtime::push_samples()`]) without blocking the current task.
+    ///
+    /// Resolves to the outputs written by each run, in order. This is empty if
+    /// the samples didn't complete a window.
+    pub async fn push_samples_async<T>(
+        &self,
+        capability_id: u32,
+        samples: Vec<T>,
+    ) -> Result<Vec<HashMap<u32, Vec<OutputTensor>>>, Error>
+    where
+        T: TensorElement + Send,
+    {
+        self.send(move |reply| {
+            Command::Run(Box::new(move |runtime: &mut Runtime| {
+                let mut runs = Vec::new();
+                let result = runtime
+                    .push_samples_with(capability_id, &samples, |outputs| {
+                        runs.push(outputs)
+                    })
+                    .map(|_| runs);
+                let _ = reply.send(result);
+            }))
+        })
+        .await
+    }
+
+    async fn send<T>(
+        &self,
+        command: impl FnOnce(Reply<T>) -> Command,
+    ) -> Result<T, Error> {
+        let (reply, response) = oneshot::channel();
+
+        self.commands
+            .lock()
+            .expect("Lock was poisoned")
+            .send(command(reply))
+            .map_err(|_| Error::msg("The runtime thread has stopped"))?;
+
+        response.await.context("The runtime thread crashed")?
+    }
+}
+
+/// The loop running on the runtime thread.
+fn worker(mut runtime: Runtime, commands: mpsc::Receiver<Command>) {
+    for command in commands {
+        match command {
+            Command::Predict(inputs, reply) => {
+                let result = runtime
+                    .predict_with_inputs(inputs)
+                    .map(|_| runtime.updated_output_tensors());
+                let _ = reply.send(result);
+            },
+            Command::Run(run) => run(&mut runtime),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+        thread::Thread,
+    };
+
+    use super::*;
+    use crate::{callbacks::Model, HostFunctions, WebAssemblyEngine};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) { self.0.unpark(); }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            match Pin::new(&mut future).poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn load_errors_are_passed_back_to_the_caller() {
+        let result = block_on(AsyncRuntime::load(Runtime::builder()));
+
+        let error = result.err().unwrap();
+        assert_eq!(error.to_string(), "No Rune was provided");
+    }
+
+    /// A fake Rune with two outputs which are both written to on the first
+    /// run, but only the second output is written to afterwards.
+    struct TwoOutputs {
+        host: HostFunctions,
+        outputs: Vec<u32>,
+        runs: usize,
+    }
+
+    impl WebAssemblyEngine for TwoOutputs {
+        fn init(&mut self) -> Result<(), Error> {
+            for _ in 0..2 {
+                let id = self
+                    .host
+                    .request_output(hotg_rune_core::outputs::SERIAL)?;
+                self.outputs.push(id);
+            }
+            self.host.loaded()
+        }
+
+        fn predict(&mut self) -> Result<(), Error> {
+            self.runs += 1;
+            let data = format!(
+                r#"[{{"type_name":"f32","elements":[{}],"dimensions":[1]}}]"#,
+                self.runs
+            );
+            let outputs = if self.runs == 1 {
+                &self.outputs[..]
+            } else {
+                &self.outputs[1..]
+            };
+
+            for &id in outputs {
+                self.host.consume_output(id, data.as_bytes())?;
+            }
+
+            Ok(())
+        }
+
+        fn with_model(
+            &mut self,
+            _model_id: u32,
+            _action: &mut dyn FnMut(&mut dyn Model) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn replace_model(
+            &mut self,
+            _model_id: u32,
+            _model: Box<dyn Model>,
+        ) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn memory_usage(&self) -> usize { 0 }
+    }
+
+    #[test]
+    fn predictions_only_return_outputs_from_that_run() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (func (export "_manifest") (result i32) i32.const 1)
+                (func (export "_call") (param i32 i32 i32) (result i32)
+                    i32.const 0))"#,
+        )
+        .unwrap();
+        let builder = Runtime::builder().rune(wasm).custom_engine(|_, host| {
+            Ok(Box::new(TwoOutputs {
+                host,
+                outputs: Vec::new(),
+                runs: 0,
+            }) as Box<dyn WebAssemblyEngine>)
+        });
+        let runtime = block_on(AsyncRuntime::load(builder)).unwrap();
+        let mut ids: Vec<u32> = runtime.outputs().keys().copied().collect();
+        ids.sort_unstable();
+
+        let first = block_on(runtime.predict_async(HashMap::new())).unwrap();
+        let second = block_on(runtime.predict_async(HashMap::new())).unwrap();
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(second.len(), 1);
+        assert_eq!(
+            second[&ids[1]],
+            vec![OutputTensor::Tensor(Tensor::new(&[2.0_f32], &[1]))]
+        );
+    }
+}
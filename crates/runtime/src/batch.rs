@@ -0,0 +1,198 @@
+//! Splitting inputs apart and stacking outputs back together for
+//! [`crate::Runtime::predict_batch()`].
+
+use std::num::NonZeroUsize;
+
+use anyhow::Error;
+
+use crate::{ElementType, OutputTensor, Tensor};
+
+/// Split a tensor into `batch_size` tensors along its first dimension.
+pub(crate) fn split(
+    tensor: &Tensor,
+    batch_size: usize,
+) -> Result<Vec<Tensor>, Error> {
+    let (first, rest) = match tensor.dimensions().split_first() {
+        Some((first, rest)) if !rest.is_empty() => (first.get(), rest),
+        _ => anyhow::bail!(
+            "A {} tensor doesn't have a batch dimension",
+            tensor.shape()
+        ),
+    };
+
+    anyhow::ensure!(
+        first == batch_size,
+        "Expected a batch of {} but the {} tensor contains {}",
+        batch_size,
+        tensor.shape(),
+        first,
+    );
+
+    if let Some(strings) = tensor.strings() {
+        let item_dimensions: Vec<usize> =
+            rest.iter().map(|d| d.get()).collect();
+        let chunk = strings.len() / batch_size;
+
+        return Ok(strings
+            .chunks(chunk)
+            .map(|s| Tensor::from_strings(s, &item_dimensions))
+            .collect());
+    }
+
+    let chunk = tensor.buffer().len() / batch_size;
+
+    Ok(tensor
+        .buffer()
+        .chunks(chunk)
+        .map(|bytes| {
+            Tensor::new_raw(
+                tensor.element_type(),
+                rest.to_vec(),
+                bytes.to_vec(),
+            )
+        })
+        .collect())
+}
+
+/// Stack the tensors written to an output by each item in the batch, giving
+/// each of them a new first dimension.
+pub(crate) fn stack(
+    items: &[Vec<OutputTensor>],
+) -> Result<Vec<OutputTensor>, Error> {
+    let first = match items.first() {
+        Some(first) => first,
+        None => return Ok(Vec::new()),
+    };
+
+    anyhow::ensure!(
+        items.iter().all(|item| item.len() == first.len()),
+        "Each item in the batch should write the same number of tensors",
+    );
+
+    (0..first.len())
+        .map(|i| {
+            let tensors: Vec<&OutputTensor> =
+                items.iter().map(|item| &item[i]).collect();
+            stack_tensors(&tensors)
+        })
+        .collect()
+}
+
+fn stack_tensors(tensors: &[&OutputTensor]) -> Result<OutputTensor, Error> {
+    let batch_size = tensors.len();
+
+    match tensors[0] {
+        OutputTensor::Tensor(first) => {
+            let mut buffer =
+                Vec::with_capacity(first.buffer().len() * batch_size);
+
+            for tensor in tensors {
+                match tensor {
+                    OutputTensor::Tensor(t)
+                        if t.element_type() == first.element_type()
+                            && t.dimensions() == first.dimensions() =>
+                    {
+                        buffer.extend_from_slice(t.buffer());
+                    },
+                    _ => anyhow::bail!(
+                        "Each item in the batch should output a {} tensor",
+                        first.shape()
+                    ),
+                }
+            }
+
+            let mut dimensions = vec![batch_dimension(batch_size)];
+            dimensions.extend_from_slice(first.dimensions());
+
+            let stacked =
+                Tensor::new_raw(first.element_type(), dimensions, buffer);
+
+            Ok(stacked.into())
+        },
+        OutputTensor::StringTensor { dimensions, .. } => {
+            let mut strings = Vec::new();
+
+            for tensor in tensors {
+                match tensor {
+                    OutputTensor::StringTensor {
+                        dimensions: d,
+                        strings: s,
+                    } if d == dimensions => strings.extend_from_slice(s),
+                    _ => anyhow::bail!(
+                        "Each item in the batch should output a {}{:?} tensor",
+                        ElementType::Utf8,
+                        dimensions
+                    ),
+                }
+            }
+
+            let mut batched = vec![batch_size];
+            batched.extend_from_slice(dimensions);
+
+            Ok(OutputTensor::StringTensor {
+                dimensions: batched,
+                strings,
+            })
+        },
+    }
+}
+
+fn batch_dimension(batch_size: usize) -> NonZeroUsize {
+    NonZeroUsize::new(batch_size).expect("Batches are never empty")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_along_the_first_dimension() {
+        let batch = Tensor::new(&[1_i16, 2, 3, 4, 5, 6], &[3, 2]);
+
+        let got = split(&batch, 3).unwrap();
+
+        assert_eq!(
+            got,
+            vec![
+                Tensor::new(&[1_i16, 2], &[2]),
+                Tensor::new(&[3_i16, 4], &[2]),
+                Tensor::new(&[5_i16, 6], &[2]),
+            ]
+        );
+        assert!(split(&batch, 2).is_err());
+        assert!(split(&Tensor::new(&[1_u8, 2], &[2]), 2).is_err());
+    }
+
+    #[test]
+    fn stack_outputs_from_each_item() {
+        let items = vec![
+            vec![
+                Tensor::new(&[0.5_f32, 1.5], &[2]).into(),
+                OutputTensor::StringTensor {
+                    dimensions: vec![1],
+                    strings: vec!["up".to_string()],
+                },
+            ],
+            vec![
+                Tensor::new(&[2.5_f32, 3.5], &[2]).into(),
+                OutputTensor::StringTensor {
+                    dimensions: vec![1],
+                    strings: vec!["down".to_string()],
+                },
+            ],
+        ];
+
+        let got = stack(&items).unwrap();
+
+        assert_eq!(
+            got,
+            vec![
+                Tensor::new(&[0.5_f32, 1.5, 2.5, 3.5], &[2, 2]).into(),
+                OutputTensor::StringTensor {
+                    dimensions: vec![2, 1],
+                    strings: vec!["up".to_string(), "down".to_string()],
+                },
+            ]
+        );
+    }
+}
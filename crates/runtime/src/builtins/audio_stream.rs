@@ -0,0 +1,165 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Formatter},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{ElementType, StreamingCapability};
+
+/// Create a bounded ring buffer for streaming 16-bit audio into a Rune.
+///
+/// The [`AudioSender`] can be moved to whichever thread is reading from the
+/// microphone, while the [`AudioStream`] is given to
+/// [`crate::Runtime::attach_stream()`]. The buffer holds at most `capacity`
+/// samples, so if the Rune falls behind the sender is told to back off
+/// instead of the buffer growing forever.
+pub fn audio_stream(capacity: usize) -> (AudioSender, AudioStream) {
+    let shared = Arc::new(Mutex::new(Shared {
+        samples: VecDeque::with_capacity(capacity),
+        capacity,
+        dropped: 0,
+    }));
+
+    (
+        AudioSender {
+            shared: Arc::clone(&shared),
+        },
+        AudioStream { shared },
+    )
+}
+
+#[derive(Debug)]
+struct Shared {
+    samples: VecDeque<i16>,
+    capacity: usize,
+    /// The total number of samples which didn't fit in the buffer.
+    dropped: u64,
+}
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().expect("Lock was poisoned")
+}
+
+/// The half of an [`audio_stream()`] used to push in new samples.
+#[derive(Debug, Clone)]
+pub struct AudioSender {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl AudioSender {
+    /// Add samples to the buffer.
+    ///
+    /// If there isn't room for all of them, as many as will fit are added and
+    /// a [`Backpressure`] error says how many were dropped. The caller should
+    /// slow down or skip ahead until [`AudioSender::free_space()`] recovers.
+    pub fn push(&self, samples: &[i16]) -> Result<(), Backpressure> {
+        let mut shared = lock(&self.shared);
+        let free = shared.capacity - shared.samples.len();
+        let accepted = samples.len().min(free);
+
+        shared.samples.extend(&samples[..accepted]);
+
+        let dropped = samples.len() - accepted;
+        if dropped == 0 {
+            Ok(())
+        } else {
+            shared.dropped += dropped as u64;
+            Err(Backpressure { accepted, dropped })
+        }
+    }
+
+    /// How many more samples can be pushed before the buffer is full.
+    pub fn free_space(&self) -> usize {
+        let shared = lock(&self.shared);
+        shared.capacity - shared.samples.len()
+    }
+}
+
+/// The half of an [`audio_stream()`] which is read by the Rune.
+#[derive(Debug)]
+pub struct AudioStream {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl AudioStream {
+    /// The number of samples waiting to be read.
+    pub fn len(&self) -> usize { lock(&self.shared).samples.len() }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// The total number of samples which have been dropped because the
+    /// buffer was full.
+    pub fn dropped(&self) -> u64 { lock(&self.shared).dropped }
+}
+
+impl StreamingCapability for AudioStream {
+    fn element_type(&self) -> ElementType { ElementType::I16 }
+
+    fn read(&mut self, buffer: &mut Vec<u8>) {
+        let mut shared = lock(&self.shared);
+        buffer.reserve(shared.samples.len() * std::mem::size_of::<i16>());
+
+        for sample in shared.samples.drain(..) {
+            buffer.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+}
+
+/// The error returned when an [`AudioSender`] pushes more samples than the
+/// buffer has room for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Backpressure {
+    /// The number of samples which were added to the buffer.
+    pub accepted: usize,
+    /// The number of samples which were dropped.
+    pub dropped: usize,
+}
+
+impl Display for Backpressure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The audio buffer is full, so {} samples were dropped",
+            self.dropped
+        )
+    }
+}
+
+impl std::error::Error for Backpressure {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn senders_are_told_when_the_buffer_is_full() {
+        let (sender, stream) = audio_stream(4);
+
+        sender.push(&[1, 2, 3]).unwrap();
+        let err = sender.push(&[4, 5, 6]).unwrap_err();
+
+        assert_eq!(
+            err,
+            Backpressure {
+                accepted: 1,
+                dropped: 2
+            }
+        );
+        assert_eq!(sender.free_space(), 0);
+        assert_eq!(stream.len(), 4);
+        assert_eq!(stream.dropped(), 2);
+    }
+
+    #[test]
+    fn reading_drains_the_buffer() {
+        let (sender, mut stream) = audio_stream(8);
+        sender.push(&[1, -2]).unwrap();
+        let mut buffer = Vec::new();
+
+        stream.read(&mut buffer);
+
+        assert_eq!(buffer, [1, 0, 0xfe, 0xff]);
+        assert!(stream.is_empty());
+        assert_eq!(sender.free_space(), 8);
+    }
+}
@@ -1,28 +1,60 @@
 use std::{num::NonZeroUsize, str::FromStr};
 
 use anyhow::Error;
-use image::{imageops::FilterType, DynamicImage};
+use image::{
+    imageops::FilterType, DynamicImage, GenericImageView, RgbImage, RgbaImage,
+};
 
 use crate::{builtins::Arguments, ElementType, Tensor};
 
 /// Load an input tensor from an image, applying any transformations requested
 /// by the Rune.
+///
+/// The image will be cropped to the [`RegionOfInterest`] given by the
+/// `roi_x`, `roi_y`, `roi_width`, and `roi_height` arguments (if any) before
+/// being resized using the [`ResizeFilter`] from the `resize` argument.
 pub fn image(args: &Arguments, img: &DynamicImage) -> Result<Tensor, Error> {
     let width: u32 = args.parse("width")?;
     let height: u32 = args.parse("height")?;
     let pixel_format: PixelFormat =
         args.parse_or_default("pixel_format", PixelFormat::RGB8)?;
+    let roi = RegionOfInterest::from_arguments(args)?;
+    let filter: ResizeFilter =
+        args.parse_or_default("resize", ResizeFilter::Bicubic)?;
 
-    Ok(transform(img, width, height, pixel_format))
+    Ok(transform(img, roi, width, height, pixel_format, filter))
+}
+
+/// Load an input tensor from a frame in one of the formats a host typically
+/// gets from a camera or video decoder.
+///
+/// The frame is converted to RGB before being passed to [`image()`], so it
+/// will be cropped, resized, and converted to the Rune's pixel format in the
+/// same way.
+pub fn raw_image(
+    args: &Arguments,
+    frame: &RawImage<'_>,
+) -> Result<Tensor, Error> {
+    let img = frame.to_image()?;
+    image(args, &img)
 }
 
 fn transform(
     img: &DynamicImage,
+    roi: Option<RegionOfInterest>,
     width: u32,
     height: u32,
     pixel_format: PixelFormat,
+    filter: ResizeFilter,
 ) -> Tensor {
-    let resized = img.resize_exact(width, height, FilterType::CatmullRom);
+    let cropped = match roi {
+        Some(roi) => {
+            let (x, y, w, h) = roi.pixels(img.width(), img.height());
+            img.crop_imm(x, y, w, h)
+        },
+        None => img.clone(),
+    };
+    let resized = cropped.resize_exact(width, height, filter.filter_type());
 
     let image = match pixel_format {
         PixelFormat::RGB8 => DynamicImage::ImageRgb8(resized.to_rgb8()),
@@ -86,3 +118,398 @@ impl FromStr for PixelFormat {
 #[derive(Debug, Copy, Clone, PartialEq, thiserror::Error)]
 #[error("Unknown pixel format")]
 pub struct UnknownPixelFormat;
+
+/// How an image is resized to the dimensions a Rune asked for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ResizeFilter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Lanczos,
+}
+
+impl ResizeFilter {
+    fn filter_type(self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Bilinear => FilterType::Triangle,
+            ResizeFilter::Bicubic => FilterType::CatmullRom,
+            ResizeFilter::Lanczos => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl FromStr for ResizeFilter {
+    type Err = UnknownResizeFilter;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Runes pass hotg_rune_core::ResizeFilter's discriminant
+        match s {
+            "nearest" | "@ResizeFilter::Nearest" | "0" => {
+                Ok(ResizeFilter::Nearest)
+            },
+            "bilinear" | "@ResizeFilter::Bilinear" | "1" => {
+                Ok(ResizeFilter::Bilinear)
+            },
+            "bicubic" | "@ResizeFilter::Bicubic" | "2" => {
+                Ok(ResizeFilter::Bicubic)
+            },
+            "lanczos" | "@ResizeFilter::Lanczos" | "3" => {
+                Ok(ResizeFilter::Lanczos)
+            },
+            _ => Err(UnknownResizeFilter),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, thiserror::Error)]
+#[error("Unknown resize filter")]
+pub struct UnknownResizeFilter;
+
+/// The layout of the pixels in a [`RawImage`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RawPixelFormat {
+    /// Red-Green-Blue-Alpha pixels stored as `u8`.
+    RGBA8,
+    /// Blue-Green-Red pixels stored as `u8`.
+    BGR8,
+    /// Planar YUV 4:2:0 (I420), where a full-size Y plane is followed by U
+    /// and V planes at half the width and height.
+    YUV420,
+}
+
+impl RawPixelFormat {
+    /// How many bytes an image with this format and size takes up.
+    pub fn buffer_size(self, width: u32, height: u32) -> usize {
+        let (width, height) = (width as usize, height as usize);
+
+        match self {
+            RawPixelFormat::RGBA8 => width * height * 4,
+            RawPixelFormat::BGR8 => width * height * 3,
+            RawPixelFormat::YUV420 => {
+                let (chroma_width, chroma_height) = chroma_size(width, height);
+                width * height + 2 * chroma_width * chroma_height
+            },
+        }
+    }
+}
+
+/// An uncompressed image provided by the host.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RawImage<'a> {
+    pub format: RawPixelFormat,
+    pub width: u32,
+    pub height: u32,
+    pub data: &'a [u8],
+}
+
+impl<'a> RawImage<'a> {
+    /// Convert the frame to an RGB image.
+    pub fn to_image(&self) -> Result<DynamicImage, Error> {
+        let RawImage {
+            format,
+            width,
+            height,
+            data,
+        } = *self;
+
+        let expected = format.buffer_size(width, height);
+        anyhow::ensure!(
+            data.len() == expected,
+            "A {}x{} {:?} image should be {} bytes, but found {}",
+            width,
+            height,
+            format,
+            expected,
+            data.len()
+        );
+
+        let img = match format {
+            RawPixelFormat::RGBA8 => {
+                RgbaImage::from_raw(width, height, data.to_vec())
+                    .map(DynamicImage::ImageRgba8)
+            },
+            RawPixelFormat::BGR8 => {
+                let rgb = data
+                    .chunks_exact(3)
+                    .flat_map(|bgr| [bgr[2], bgr[1], bgr[0]])
+                    .collect();
+                RgbImage::from_raw(width, height, rgb)
+                    .map(DynamicImage::ImageRgb8)
+            },
+            RawPixelFormat::YUV420 => {
+                RgbImage::from_raw(width, height, yuv420_to_rgb(self))
+                    .map(DynamicImage::ImageRgb8)
+            },
+        };
+
+        // We've already checked the buffer's length
+        Ok(img.expect("The buffer was big enough"))
+    }
+}
+
+fn chroma_size(width: usize, height: usize) -> (usize, usize) {
+    ((width + 1) / 2, (height + 1) / 2)
+}
+
+/// Convert planar YUV 4:2:0 to RGB using the full-range BT.601 coefficients
+/// (i.e. what JPEG uses).
+fn yuv420_to_rgb(frame: &RawImage<'_>) -> Vec<u8> {
+    let width = frame.width as usize;
+    let height = frame.height as usize;
+    let (chroma_width, chroma_height) = chroma_size(width, height);
+
+    let (y_plane, chroma) = frame.data.split_at(width * height);
+    let (u_plane, v_plane) = chroma.split_at(chroma_width * chroma_height);
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+
+    for row in 0..height {
+        for column in 0..width {
+            let chroma_index = (row / 2) * chroma_width + column / 2;
+            let y = f32::from(y_plane[row * width + column]);
+            let u = f32::from(u_plane[chroma_index]) - 128.0;
+            let v = f32::from(v_plane[chroma_index]) - 128.0;
+
+            let r = y + 1.402 * v;
+            let g = y - 0.344_136 * u - 0.714_136 * v;
+            let b = y + 1.772 * u;
+
+            rgb.extend(
+                [r, g, b].iter().map(|c| c.round().clamp(0.0, 255.0) as u8),
+            );
+        }
+    }
+
+    rgb
+}
+
+/// A rectangular part of an image that a Rune is interested in.
+///
+/// In a Runefile, this is set using the `roi_x`, `roi_y`, `roi_width`, and
+/// `roi_height` arguments. These are in pixels unless `roi_normalized` is
+/// `1`, in which case they are fractions of the image's size (e.g.
+/// `roi_x: 0.25` and `roi_width: 0.5` for the middle half of the frame).
+/// Anything left unset extends to the edge of the image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RegionOfInterest {
+    Pixels {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    Normalized {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+}
+
+impl RegionOfInterest {
+    const ARGUMENTS: [&'static str; 4] =
+        ["roi_x", "roi_y", "roi_width", "roi_height"];
+
+    /// Read the region from a capability's arguments, returning `None` if
+    /// it wasn't set.
+    pub fn from_arguments(args: &Arguments) -> Result<Option<Self>, Error> {
+        if !RegionOfInterest::ARGUMENTS
+            .iter()
+            .any(|name| args.0.contains_key(*name))
+        {
+            return Ok(None);
+        }
+
+        let normalized: u8 = args.parse_or_default("roi_normalized", 0)?;
+
+        let roi = if normalized != 0 {
+            let x = args.parse_or_default("roi_x", 0.0)?;
+            let y = args.parse_or_default("roi_y", 0.0)?;
+            let width = args.parse_or_default("roi_width", 1.0)?;
+            let height = args.parse_or_default("roi_height", 1.0)?;
+
+            for value in [x, y, width, height] {
+                if !(0.0..=1.0).contains(&value) {
+                    anyhow::bail!(
+                        "Normalized ROI coordinates must be between 0 and 1, \
+                         found {}",
+                        value
+                    );
+                }
+            }
+
+            RegionOfInterest::Normalized {
+                x,
+                y,
+                width,
+                height,
+            }
+        } else {
+            RegionOfInterest::Pixels {
+                x: args.parse_or_default("roi_x", 0)?,
+                y: args.parse_or_default("roi_y", 0)?,
+                width: args.parse_or_default("roi_width", u32::MAX)?,
+                height: args.parse_or_default("roi_height", u32::MAX)?,
+            }
+        };
+
+        Ok(Some(roi))
+    }
+
+    /// Get the `(x, y, width, height)` this region covers in an image with
+    /// the given size, clamped so it always contains at least one pixel.
+    pub fn pixels(
+        self,
+        image_width: u32,
+        image_height: u32,
+    ) -> (u32, u32, u32, u32) {
+        let (x, y, width, height) = match self {
+            RegionOfInterest::Pixels {
+                x,
+                y,
+                width,
+                height,
+            } => (x, y, width, height),
+            RegionOfInterest::Normalized {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let w = image_width as f32;
+                let h = image_height as f32;
+                (
+                    (x * w).round() as u32,
+                    (y * h).round() as u32,
+                    (width * w).round() as u32,
+                    (height * h).round() as u32,
+                )
+            },
+        };
+
+        let x = x.min(image_width.saturating_sub(1));
+        let y = y.min(image_height.saturating_sub(1));
+        let width = width.min(image_width.saturating_sub(x)).max(1);
+        let height = height.min(image_height.saturating_sub(y)).max(1);
+
+        (x, y, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    fn arguments(args: &[(&str, &str)]) -> Arguments {
+        Arguments(
+            args.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn read_the_region_of_interest() {
+        let pixels = arguments(&[("roi_x", "40"), ("roi_width", "200")]);
+        let normalized = arguments(&[
+            ("roi_x", "0.25"),
+            ("roi_width", "0.5"),
+            ("roi_normalized", "1"),
+        ]);
+
+        let pixels = RegionOfInterest::from_arguments(&pixels).unwrap();
+        let normalized = RegionOfInterest::from_arguments(&normalized)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            pixels,
+            Some(RegionOfInterest::Pixels {
+                x: 40,
+                y: 0,
+                width: 200,
+                height: u32::MAX,
+            })
+        );
+        assert_eq!(pixels.unwrap().pixels(400, 100), (40, 0, 200, 100));
+        assert_eq!(normalized.pixels(400, 100), (100, 0, 200, 100));
+        assert_eq!(
+            RegionOfInterest::from_arguments(&arguments(&[])).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn crop_before_resizing() {
+        // A black image with a white right half
+        let img = RgbImage::from_fn(4, 2, |x, _| {
+            if x >= 2 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        let args = arguments(&[
+            ("width", "1"),
+            ("height", "1"),
+            ("roi_x", "2"),
+            ("roi_width", "2"),
+        ]);
+
+        let tensor = image(&args, &DynamicImage::ImageRgb8(img)).unwrap();
+
+        assert_eq!(tensor.buffer(), &[255, 255, 255]);
+    }
+
+    #[test]
+    fn convert_raw_frames_to_rgb() {
+        let bgr = RawImage {
+            format: RawPixelFormat::BGR8,
+            width: 1,
+            height: 1,
+            data: &[1, 2, 3],
+        };
+        // A 2x2 image is a 2x2 Y plane with a single U and V value
+        let yuv = RawImage {
+            format: RawPixelFormat::YUV420,
+            width: 2,
+            height: 2,
+            data: &[0, 255, 128, 128, 128, 128],
+        };
+
+        assert_eq!(bgr.to_image().unwrap().as_bytes(), &[3, 2, 1]);
+        assert_eq!(
+            yuv.to_image().unwrap().as_bytes(),
+            &[0, 0, 0, 255, 255, 255, 128, 128, 128, 128, 128, 128]
+        );
+        assert!(RawImage {
+            data: &[0; 5],
+            ..yuv
+        }
+        .to_image()
+        .is_err());
+    }
+
+    #[test]
+    fn resize_with_the_requested_filter() {
+        let frame = RawImage {
+            format: RawPixelFormat::RGBA8,
+            width: 2,
+            height: 1,
+            data: &[0, 0, 0, 255, 200, 200, 200, 255],
+        };
+        let args = arguments(&[
+            ("width", "4"),
+            ("height", "1"),
+            ("pixel_format", "2"),
+            ("resize", "0"),
+        ]);
+
+        let tensor = raw_image(&args, &frame).unwrap();
+
+        assert_eq!(tensor.buffer(), &[0, 0, 200, 200]);
+    }
+}
@@ -0,0 +1,202 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{Context, Error};
+
+use crate::{
+    builtins::{self, AccelerometerSamples, Arguments, AudioClip},
+    NodeMetadata, Runtime, Tensor,
+};
+
+/// A builder which maps capability kinds to the files their inputs should be
+/// read from, taking care of decoding and reshaping them into the tensors a
+/// Rune asks for.
+///
+/// ```rust,no_run
+/// # fn load(runtime: &mut hotg_rune_runtime::Runtime) -> Result<(), anyhow::Error> {
+/// use hotg_rune_runtime::builtins::InputBinding;
+///
+/// InputBinding::new()
+///     .image("person.png")
+///     .sound("yes.wav")
+///     .random_seed(42)
+///     .apply(runtime)?;
+///
+/// runtime.predict()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Each method can be called several times for Runes with more than one
+/// capability of the same kind, which choose a file using their `source`
+/// argument.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InputBinding {
+    images: Vec<PathBuf>,
+    sounds: Vec<PathBuf>,
+    accelerometer: Vec<PathBuf>,
+    raw: Vec<PathBuf>,
+    seed: Option<u64>,
+}
+
+impl InputBinding {
+    pub fn new() -> Self { InputBinding::default() }
+
+    /// An image to be returned by the `IMAGE` capability.
+    pub fn image(mut self, path: impl Into<PathBuf>) -> Self {
+        self.images.push(path.into());
+        self
+    }
+
+    /// A WAV file containing samples returned by the `SOUND` capability.
+    pub fn sound(mut self, path: impl Into<PathBuf>) -> Self {
+        self.sounds.push(path.into());
+        self
+    }
+
+    /// A CSV file containing `[X, Y, Z]` vectors to be returned by the
+    /// `ACCEL` capability.
+    pub fn accelerometer(mut self, path: impl Into<PathBuf>) -> Self {
+        self.accelerometer.push(path.into());
+        self
+    }
+
+    /// A file whose bytes will be returned as-is by the `RAW` capability.
+    pub fn raw(mut self, path: impl Into<PathBuf>) -> Self {
+        self.raw.push(path.into());
+        self
+    }
+
+    /// Seed the random number generator used by the `RAND` capability.
+    pub fn random_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Load the input for each capability, keyed by ID.
+    ///
+    /// `STREAM` capabilities are skipped because they produce a new value
+    /// every time the Rune is run. Register them with
+    /// [`Runtime::register_capability()`] and [`builtins::stream()`] instead.
+    pub fn load(
+        &self,
+        capabilities: &HashMap<u32, NodeMetadata>,
+    ) -> Result<HashMap<u32, Tensor>, Error> {
+        let mut inputs = HashMap::new();
+
+        for (&id, metadata) in capabilities {
+            if metadata.kind == "STREAM" {
+                continue;
+            }
+
+            let args = Arguments(metadata.arguments.clone());
+            let tensor =
+                self.load_input(&metadata.kind, &args).with_context(|| {
+                    format!("Unable to load the \"{}\" input", metadata.kind)
+                })?;
+
+            inputs.insert(id, tensor);
+        }
+
+        Ok(inputs)
+    }
+
+    /// Load the input for each of the [`Runtime`]'s capabilities and add them
+    /// to its [`Runtime::input_tensors()`].
+    pub fn apply(&self, runtime: &mut Runtime) -> Result<(), Error> {
+        let inputs = self.load(runtime.capabilities())?;
+        runtime.input_tensors().extend(inputs);
+
+        Ok(())
+    }
+
+    /// Load the input for a single capability.
+    pub fn load_input(
+        &self,
+        kind: &str,
+        args: &Arguments,
+    ) -> Result<Tensor, Error> {
+        match kind {
+            "IMAGE" => builtins::source(&self.images, args)
+                .and_then(|path| {
+                    image::open(path).with_context(|| {
+                        format!("Unable to read \"{}\"", path.display())
+                    })
+                })
+                .and_then(|img| builtins::image(args, &img)),
+
+            "SOUND" => builtins::source(&self.sounds, args)
+                .and_then(AudioClip::from_wav_file)
+                .and_then(|audio| builtins::sound(args, &audio)),
+
+            "ACCEL" => builtins::source(&self.accelerometer, args)
+                .and_then(|path| {
+                    AccelerometerSamples::from_file(path).with_context(|| {
+                        format!("Unable to read \"{}\"", path.display())
+                    })
+                })
+                .and_then(|samples| builtins::accelerometer(args, &samples)),
+
+            "RAW" => builtins::source(&self.raw, args)
+                .and_then(|path| {
+                    std::fs::read(path).with_context(|| {
+                        format!("Unable to read \"{}\"", path.display())
+                    })
+                })
+                .and_then(|data| builtins::raw(args, &data)),
+
+            "RAND" => match self.seed {
+                Some(seed) => builtins::seeded_random(args, seed),
+                None => builtins::random(args),
+            },
+
+            _ => anyhow::bail!("Unknown input type, \"{}\"", kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(kind: &str, args: &[(&str, &str)]) -> NodeMetadata {
+        NodeMetadata {
+            kind: kind.to_string(),
+            arguments: args
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn load_each_capability_from_its_source() {
+        let temp = tempfile::tempdir().unwrap();
+        let first = temp.path().join("first.bin");
+        let second = temp.path().join("second.bin");
+        std::fs::write(&first, [1_u8, 2, 3]).unwrap();
+        std::fs::write(&second, [4_u8, 5, 6]).unwrap();
+        let mut capabilities = HashMap::new();
+        capabilities
+            .insert(1, node("RAW", &[("length", "3"), ("source", "1")]));
+        capabilities.insert(2, node("RAND", &[("amount", "2")]));
+        capabilities.insert(3, node("STREAM", &[]));
+        let binding =
+            InputBinding::new().raw(first).raw(second).random_seed(42);
+
+        let inputs = binding.load(&capabilities).unwrap();
+
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(inputs[&1].buffer(), &[4, 5, 6]);
+        assert_eq!(inputs[&2], binding.load(&capabilities).unwrap()[&2]);
+    }
+
+    #[test]
+    fn missing_sources_are_an_error() {
+        let mut capabilities = HashMap::new();
+        capabilities.insert(1, node("IMAGE", &[]));
+
+        let err = InputBinding::new().load(&capabilities).unwrap_err();
+
+        assert_eq!(err.to_string(), "Unable to load the \"IMAGE\" input");
+    }
+}
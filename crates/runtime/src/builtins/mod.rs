@@ -2,10 +2,14 @@
 
 mod accelerometer;
 mod arguments;
+mod audio_stream;
 mod image;
+mod input_binding;
+pub mod outputs;
 mod random;
 mod raw;
 mod sound;
+mod stream;
 
 use anyhow::Error;
 
@@ -15,10 +19,16 @@ pub use self::{
         AccelerometerSamples,
     },
     arguments::Arguments,
-    image::{image, UnknownPixelFormat},
-    random::{random, seeded_random},
+    audio_stream::{audio_stream, AudioSender, AudioStream, Backpressure},
+    image::{
+        image, raw_image, RawImage, RawPixelFormat, RegionOfInterest,
+        ResizeFilter, UnknownPixelFormat, UnknownResizeFilter,
+    },
+    input_binding::InputBinding,
+    random::{random, seeded_random, SeededRandom},
     raw::raw,
-    sound::{sound, AudioClip},
+    sound::{sound, AudioClip, ChannelSelection, UnknownChannelSelection},
+    stream::{stream, ByteStream, EndOfStream, StreamSource},
 };
 
 /// Use the `"source"` argument to figure out which input to read.
@@ -0,0 +1,685 @@
+//! Sinks which save a Rune's outputs on the host.
+
+mod audio;
+
+use std::{
+    convert::TryInto,
+    fmt::{self, Display, Formatter},
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+};
+
+use anyhow::{Context, Error};
+
+pub use self::audio::{
+    negotiate_sample_rate, Audio, AudioBuffer, AudioFormat, AudioMode,
+};
+use crate::{ElementType, OutputTensor, Runtime, Tensor};
+
+/// A table which outputs are appended to, one row per prediction, so a Rune
+/// can collect (labelled) field data for retraining.
+///
+/// The schema is derived from the first row. Metadata (e.g. labels or
+/// timestamps) become string columns, followed by a column for each element
+/// of each tensor.
+///
+/// ```rust,no_run
+/// use hotg_rune_runtime::{builtins::outputs::Dataset, Runtime};
+///
+/// fn collect(
+///     runtime: &mut Runtime,
+///     label: &str,
+/// ) -> Result<(), anyhow::Error> {
+///     let mut dataset = Dataset::append_csv("samples.csv")?;
+///
+///     runtime.predict()?;
+///     dataset.write_outputs(runtime, &[("label", label)])?;
+///
+///     dataset.finish()
+/// }
+/// ```
+pub struct Dataset {
+    writer: Writer,
+    schema: Option<Vec<Column>>,
+}
+
+impl Dataset {
+    /// Write CSV rows to a [`Write`]r, starting with a header.
+    pub fn csv<W>(writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        Dataset::csv_with_header(writer, false)
+    }
+
+    fn csv_with_header<W>(writer: W, header_written: bool) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        let writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(Box::new(writer) as Box<dyn Write + Send>);
+
+        Dataset {
+            writer: Writer::Csv {
+                writer,
+                header_written,
+            },
+            schema: None,
+        }
+    }
+
+    /// Append CSV rows to a file, only writing a header if the file is new.
+    pub fn append_csv(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| {
+                format!("Unable to open \"{}\" for writing", path.display())
+            })?;
+        let is_empty = file.metadata()?.len() == 0;
+
+        Ok(Dataset::csv_with_header(file, !is_empty))
+    }
+
+    /// Write rows to a new Parquet file.
+    ///
+    /// Rows are buffered in memory and written as a row group each time
+    /// [`Dataset::flush()`] is called, so the file is only valid after
+    /// [`Dataset::finish()`].
+    #[cfg(feature = "parquet")]
+    #[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "parquet")))]
+    pub fn parquet(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path).with_context(|| {
+            format!("Unable to create \"{}\"", path.display())
+        })?;
+
+        Ok(Dataset {
+            writer: Writer::Parquet(parquet_writer::ParquetWriter::new(file)),
+            schema: None,
+        })
+    }
+
+    /// The columns in this dataset, if a row has been written.
+    pub fn schema(&self) -> Option<&[Column]> { self.schema.as_deref() }
+
+    /// Append a row containing some metadata followed by the elements of
+    /// each named tensor.
+    pub fn write(
+        &mut self,
+        tensors: &[(&str, &OutputTensor)],
+        metadata: &[(&str, &str)],
+    ) -> Result<(), Error> {
+        let (columns, cells) = row(tensors, metadata)?;
+
+        if let Some(schema) = &self.schema {
+            if *schema != columns {
+                anyhow::bail!(
+                    "The row ({}) doesn't match the dataset's schema ({})",
+                    describe(&columns),
+                    describe(schema)
+                );
+            }
+        } else {
+            self.schema = Some(columns);
+        }
+
+        let schema = self.schema.as_deref().unwrap_or_default();
+        self.writer.write(schema, cells)
+    }
+
+    /// Append a row containing every tensor the [`Runtime`]'s outputs
+    /// received during the last prediction.
+    ///
+    /// Tensors are named after the ID of the output they came from (e.g.
+    /// `output_1`), with a suffix when an output receives more than one
+    /// tensor (e.g. `output_2.0`).
+    pub fn write_outputs(
+        &mut self,
+        runtime: &Runtime,
+        metadata: &[(&str, &str)],
+    ) -> Result<(), Error> {
+        let mut outputs: Vec<_> = runtime.output_tensors().iter().collect();
+        outputs.sort_by_key(|(&id, _)| id);
+
+        let mut names = Vec::new();
+        let mut tensors = Vec::new();
+
+        for (id, output_tensors) in outputs {
+            for (i, tensor) in output_tensors.iter().enumerate() {
+                let name = if output_tensors.len() == 1 {
+                    format!("output_{}", id)
+                } else {
+                    format!("output_{}.{}", id, i)
+                };
+                names.push(name);
+                tensors.push(tensor);
+            }
+        }
+
+        let named: Vec<_> =
+            names.iter().map(|n| n.as_str()).zip(tensors).collect();
+
+        self.write(&named, metadata)
+    }
+
+    /// Make sure everything written so far has been saved.
+    pub fn flush(&mut self) -> Result<(), Error> { self.writer.flush() }
+
+    /// Flush any remaining rows and finish writing the file.
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.flush()?;
+        self.writer.finish()
+    }
+}
+
+/// A column in a [`Dataset`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub column_type: ColumnType,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColumnType {
+    String,
+    Element(ElementType),
+}
+
+impl Display for ColumnType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnType::String => write!(f, "string"),
+            ColumnType::Element(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// A single value in a [`Dataset`].
+#[derive(Debug, Clone, PartialEq)]
+enum Cell {
+    String(String),
+    Integer(i64),
+    Unsigned(u64),
+    Single(f32),
+    Double(f64),
+}
+
+impl Display for Cell {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::String(s) => write!(f, "{}", s),
+            Cell::Integer(i) => write!(f, "{}", i),
+            Cell::Unsigned(u) => write!(f, "{}", u),
+            Cell::Single(x) => write!(f, "{}", x),
+            Cell::Double(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+fn describe(columns: &[Column]) -> String {
+    columns
+        .iter()
+        .map(|c| format!("{}: {}", c.name, c.column_type))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Flatten some metadata and tensors into a single row.
+fn row(
+    tensors: &[(&str, &OutputTensor)],
+    metadata: &[(&str, &str)],
+) -> Result<(Vec<Column>, Vec<Cell>), Error> {
+    let mut columns = Vec::new();
+    let mut cells = Vec::new();
+
+    for &(key, value) in metadata {
+        columns.push(Column {
+            name: key.to_string(),
+            column_type: ColumnType::String,
+        });
+        cells.push(Cell::String(value.to_string()));
+    }
+
+    for &(name, tensor) in tensors {
+        let (column_type, values) = match tensor {
+            OutputTensor::Tensor(t) => {
+                (ColumnType::Element(t.element_type()), tensor_cells(t)?)
+            },
+            OutputTensor::StringTensor { strings, .. } => (
+                ColumnType::String,
+                strings.iter().cloned().map(Cell::String).collect(),
+            ),
+        };
+
+        let single = values.len() == 1;
+
+        for (i, value) in values.into_iter().enumerate() {
+            let name = if single {
+                name.to_string()
+            } else {
+                format!("{}_{}", name, i)
+            };
+            columns.push(Column { name, column_type });
+            cells.push(value);
+        }
+    }
+
+    Ok((columns, cells))
+}
+
+fn tensor_cells(tensor: &Tensor) -> Result<Vec<Cell>, Error> {
+    macro_rules! cells {
+        ($ty:ty, $cell:expr) => {
+            tensor
+                .buffer()
+                .chunks_exact(std::mem::size_of::<$ty>())
+                .map(|bytes| {
+                    <$ty>::from_le_bytes(bytes.try_into().expect("Unreachable"))
+                })
+                .map($cell)
+                .collect()
+        };
+    }
+
+    let cells: Vec<Cell> = match tensor.element_type() {
+        ElementType::U8 => cells!(u8, |v| Cell::Unsigned(v.into())),
+        ElementType::I8 => cells!(i8, |v| Cell::Integer(v.into())),
+        ElementType::U16 => cells!(u16, |v| Cell::Unsigned(v.into())),
+        ElementType::I16 => cells!(i16, |v| Cell::Integer(v.into())),
+        ElementType::U32 => cells!(u32, |v| Cell::Unsigned(v.into())),
+        ElementType::I32 => cells!(i32, |v| Cell::Integer(v.into())),
+        ElementType::F32 => cells!(f32, Cell::Single),
+        ElementType::U64 => cells!(u64, Cell::Unsigned),
+        ElementType::I64 => cells!(i64, Cell::Integer),
+        ElementType::F64 => cells!(f64, Cell::Double),
+        ElementType::F16 => cells!(half::f16, |v| Cell::Single(v.to_f32())),
+        ElementType::BF16 => cells!(half::bf16, |v| Cell::Single(v.to_f32())),
+        ElementType::Utf8 => tensor
+            .strings()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect(),
+    };
+
+    Ok(cells)
+}
+
+enum Writer {
+    Csv {
+        writer: csv::Writer<Box<dyn Write + Send>>,
+        header_written: bool,
+    },
+    #[cfg(feature = "parquet")]
+    Parquet(parquet_writer::ParquetWriter),
+}
+
+impl Writer {
+    fn write(
+        &mut self,
+        schema: &[Column],
+        cells: Vec<Cell>,
+    ) -> Result<(), Error> {
+        match self {
+            Writer::Csv {
+                writer,
+                header_written,
+            } => {
+                if !*header_written {
+                    writer.write_record(schema.iter().map(|c| &c.name))?;
+                    *header_written = true;
+                }
+
+                writer.write_record(cells.iter().map(|c| c.to_string()))?;
+                Ok(())
+            },
+            #[cfg(feature = "parquet")]
+            Writer::Parquet(p) => {
+                p.push(schema, cells);
+                Ok(())
+            },
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        match self {
+            Writer::Csv { writer, .. } => writer.flush().map_err(Error::from),
+            #[cfg(feature = "parquet")]
+            Writer::Parquet(p) => p.write_row_group(),
+        }
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self {
+            Writer::Csv { .. } => Ok(()),
+            #[cfg(feature = "parquet")]
+            Writer::Parquet(p) => p.finish(),
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_writer {
+    use std::{fs::File, sync::Arc};
+
+    use anyhow::{Context, Error};
+    use parquet::{
+        basic::{ConvertedType, Repetition, Type as PhysicalType},
+        column::writer::ColumnWriter,
+        data_type::ByteArray,
+        file::{
+            properties::WriterProperties,
+            writer::{FileWriter, RowGroupWriter, SerializedFileWriter},
+        },
+        schema::types::{Type, TypePtr},
+    };
+
+    use super::{Cell, Column, ColumnType};
+    use crate::ElementType;
+
+    pub(super) struct ParquetWriter {
+        file: Option<File>,
+        writer: Option<SerializedFileWriter<File>>,
+        schema: Vec<Column>,
+        rows: Vec<Vec<Cell>>,
+    }
+
+    impl ParquetWriter {
+        pub(super) fn new(file: File) -> Self {
+            ParquetWriter {
+                file: Some(file),
+                writer: None,
+                schema: Vec::new(),
+                rows: Vec::new(),
+            }
+        }
+
+        pub(super) fn push(&mut self, schema: &[Column], cells: Vec<Cell>) {
+            if self.schema.is_empty() {
+                self.schema = schema.to_vec();
+            }
+
+            self.rows.push(cells);
+        }
+
+        pub(super) fn write_row_group(&mut self) -> Result<(), Error> {
+            let ParquetWriter {
+                file,
+                writer,
+                schema,
+                rows,
+            } = self;
+
+            if rows.is_empty() {
+                return Ok(());
+            }
+
+            // We can only create the writer once the schema is known.
+            if writer.is_none() {
+                let file = file.take().context("The file was closed")?;
+                let props = Arc::new(WriterProperties::builder().build());
+                *writer = Some(SerializedFileWriter::new(
+                    file,
+                    Arc::new(message_type(schema)?),
+                    props,
+                )?);
+            }
+
+            let writer = writer.as_mut().expect("Initialized above");
+            let mut row_group = writer.next_row_group()?;
+
+            for (i, column) in schema.iter().enumerate() {
+                let mut column_writer =
+                    row_group.next_column()?.with_context(|| {
+                        format!("No \"{}\" column", column.name)
+                    })?;
+                let values = rows.iter().map(|row| &row[i]);
+                write_column(&mut column_writer, values)?;
+                row_group.close_column(column_writer)?;
+            }
+
+            writer.close_row_group(row_group)?;
+            rows.clear();
+
+            Ok(())
+        }
+
+        pub(super) fn finish(mut self) -> Result<(), Error> {
+            if let Some(mut writer) = self.writer.take() {
+                writer.close()?;
+            }
+
+            Ok(())
+        }
+    }
+
+    fn message_type(schema: &[Column]) -> Result<Type, Error> {
+        let mut fields = schema
+            .iter()
+            .map(|column| {
+                let (physical, converted) = parquet_type(column.column_type);
+
+                Type::primitive_type_builder(&column.name, physical)
+                    .with_repetition(Repetition::REQUIRED)
+                    .with_converted_type(converted)
+                    .build()
+                    .map(Arc::new)
+            })
+            .collect::<Result<Vec<TypePtr>, _>>()?;
+
+        Type::group_type_builder("rune_dataset")
+            .with_fields(&mut fields)
+            .build()
+            .map_err(Error::from)
+    }
+
+    fn parquet_type(column_type: ColumnType) -> (PhysicalType, ConvertedType) {
+        match column_type {
+            ColumnType::String => {
+                (PhysicalType::BYTE_ARRAY, ConvertedType::UTF8)
+            },
+            ColumnType::Element(e) => match e {
+                ElementType::U8 => (PhysicalType::INT32, ConvertedType::UINT_8),
+                ElementType::I8 => (PhysicalType::INT32, ConvertedType::INT_8),
+                ElementType::U16 => {
+                    (PhysicalType::INT32, ConvertedType::UINT_16)
+                },
+                ElementType::I16 => {
+                    (PhysicalType::INT32, ConvertedType::INT_16)
+                },
+                ElementType::U32 => {
+                    (PhysicalType::INT32, ConvertedType::UINT_32)
+                },
+                ElementType::I32 => (PhysicalType::INT32, ConvertedType::NONE),
+                ElementType::U64 => {
+                    (PhysicalType::INT64, ConvertedType::UINT_64)
+                },
+                ElementType::I64 => (PhysicalType::INT64, ConvertedType::NONE),
+                ElementType::F32 => (PhysicalType::FLOAT, ConvertedType::NONE),
+                ElementType::F64 => (PhysicalType::DOUBLE, ConvertedType::NONE),
+                ElementType::F16 | ElementType::BF16 => {
+                    (PhysicalType::FLOAT, ConvertedType::NONE)
+                },
+                ElementType::Utf8 => {
+                    (PhysicalType::BYTE_ARRAY, ConvertedType::UTF8)
+                },
+            },
+        }
+    }
+
+    fn write_column<'a>(
+        writer: &mut ColumnWriter,
+        values: impl Iterator<Item = &'a Cell>,
+    ) -> Result<(), Error> {
+        // Note: Parquet stores unsigned integers using the same bit pattern
+        // as their signed counterparts, so wrapping casts are intentional.
+        match writer {
+            ColumnWriter::Int32ColumnWriter(w) => {
+                let values: Vec<i32> = values
+                    .map(|cell| match *cell {
+                        Cell::Integer(i) => i as i32,
+                        Cell::Unsigned(u) => u as i32,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                w.write_batch(&values, None, None)?;
+            },
+            ColumnWriter::Int64ColumnWriter(w) => {
+                let values: Vec<i64> = values
+                    .map(|cell| match *cell {
+                        Cell::Integer(i) => i,
+                        Cell::Unsigned(u) => u as i64,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                w.write_batch(&values, None, None)?;
+            },
+            ColumnWriter::FloatColumnWriter(w) => {
+                let values: Vec<f32> = values
+                    .map(|cell| match *cell {
+                        Cell::Single(f) => f,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                w.write_batch(&values, None, None)?;
+            },
+            ColumnWriter::DoubleColumnWriter(w) => {
+                let values: Vec<f64> = values
+                    .map(|cell| match *cell {
+                        Cell::Double(f) => f,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                w.write_batch(&values, None, None)?;
+            },
+            ColumnWriter::ByteArrayColumnWriter(w) => {
+                let values: Vec<ByteArray> = values
+                    .map(|cell| match cell {
+                        Cell::String(s) => ByteArray::from(s.as_str()),
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                w.write_batch(&values, None, None)?;
+            },
+            _ => anyhow::bail!("Unsupported column type"),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn write_csv_rows() {
+        let buffer = SharedBuffer::default();
+        let mut dataset = Dataset::csv(buffer.clone());
+        let confidences =
+            OutputTensor::Tensor(Tensor::new(&[0.25_f32, 0.75], &[1, 2]));
+        let label = OutputTensor::StringTensor {
+            dimensions: vec![1],
+            strings: vec!["wave".to_string()],
+        };
+
+        dataset
+            .write(
+                &[("confidence", &confidences), ("label", &label)],
+                &[("device", "pi")],
+            )
+            .unwrap();
+        dataset
+            .write(
+                &[("confidence", &confidences), ("label", &label)],
+                &[("device", "esp32")],
+            )
+            .unwrap();
+        dataset.finish().unwrap();
+
+        let got = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            got,
+            "device,confidence_0,confidence_1,label\npi,0.25,0.75,wave\nesp32,\
+             0.25,0.75,wave\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn parquet_round_trip() {
+        use parquet::{
+            file::reader::{FileReader, SerializedFileReader},
+            record::RowAccessor,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("samples.parquet");
+        let mut dataset = Dataset::parquet(&path).unwrap();
+        let confidences =
+            OutputTensor::Tensor(Tensor::new(&[0.25_f32, 0.75], &[1, 2]));
+        let class = OutputTensor::Tensor(Tensor::new(&[3_u8], &[1]));
+
+        dataset
+            .write(
+                &[("confidence", &confidences), ("class", &class)],
+                &[("device", "pi")],
+            )
+            .unwrap();
+        // Each flush writes a row group
+        dataset.flush().unwrap();
+        dataset
+            .write(
+                &[("confidence", &confidences), ("class", &class)],
+                &[("device", "esp32")],
+            )
+            .unwrap();
+        dataset.finish().unwrap();
+
+        let reader =
+            SerializedFileReader::new(std::fs::File::open(&path).unwrap())
+                .unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 2);
+        let rows: Vec<_> = reader.get_row_iter(None).unwrap().collect();
+        assert_eq!(rows.len(), 2);
+        for (row, device) in rows.iter().zip(["pi", "esp32"]) {
+            assert_eq!(row.get_string(0).unwrap(), device);
+            assert_eq!(row.get_float(1).unwrap(), 0.25);
+            assert_eq!(row.get_float(2).unwrap(), 0.75);
+            assert_eq!(row.get_ubyte(3).unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn rows_must_match_the_schema() {
+        let mut dataset = Dataset::csv(SharedBuffer::default());
+        let floats = OutputTensor::Tensor(Tensor::new(&[1.0_f32], &[1]));
+        let ints = OutputTensor::Tensor(Tensor::new(&[1_i32], &[1]));
+
+        dataset.write(&[("x", &floats)], &[]).unwrap();
+        let err = dataset.write(&[("x", &ints)], &[]).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "The row (x: i32) doesn't match the dataset's schema (x: f32)"
+        );
+    }
+}
@@ -0,0 +1,386 @@
+use std::{
+    f32::consts::PI,
+    fmt::{self, Debug, Formatter},
+    time::Duration,
+};
+
+use anyhow::{Context, Error};
+
+use crate::{
+    sink::{Delivery, Sink},
+    ElementType, OutputTensor, Tensor,
+};
+
+/// The sample rate used for tones when the host doesn't ask for one.
+const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+/// How long tones take to fade in and out, so they don't click.
+const FADE: Duration = Duration::from_millis(5);
+
+/// The layout of a stream of audio samples.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl AudioFormat {
+    pub const fn mono(sample_rate: u32) -> Self {
+        AudioFormat {
+            sample_rate,
+            channels: 1,
+        }
+    }
+}
+
+/// Interleaved samples in the range `[-1, 1]`, ready to be played.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioBuffer {
+    pub format: AudioFormat,
+    pub samples: Vec<f32>,
+}
+
+impl AudioBuffer {
+    /// How long the buffer takes to play.
+    pub fn duration(&self) -> Duration {
+        let AudioFormat {
+            sample_rate,
+            channels,
+        } = self.format;
+        let frames = self.samples.len() / usize::from(channels.max(1));
+
+        Duration::from_secs_f64(frames as f64 / f64::from(sample_rate.max(1)))
+    }
+}
+
+/// How an [`Audio`] output interprets the tensors it receives.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AudioMode {
+    /// Raw PCM samples (`i16`, `u8`, or `f32`), interleaved if there is more
+    /// than one channel.
+    Pcm(AudioFormat),
+    /// A `f32` tensor where each row is a `[frequency_hz, duration_ms,
+    /// amplitude]` command. A frequency of `0` is a rest, and amplitude
+    /// defaults to `1.0` when there are only two columns.
+    Tones,
+}
+
+/// An output which plays PCM audio or simple tones through a host-provided
+/// callback (e.g. to beep when a keyword is detected).
+///
+/// The playback callback receives [`AudioBuffer`]s in the format chosen by
+/// [`Audio::negotiate()`], with PCM being resampled if the host can't play
+/// the Rune's sample rate directly.
+///
+/// ```rust,no_run
+/// # fn main() -> Result<(), anyhow::Error> {
+/// # let mut runtime: hotg_rune_runtime::Runtime = unimplemented!();
+/// # fn play(_: &[f32]) {}
+/// use hotg_rune_runtime::{builtins::outputs::Audio, sink::RetryPolicy};
+///
+/// let speaker = Audio::tones(|buffer| {
+///     play(&buffer.samples);
+///     Ok(())
+/// })
+/// .negotiate(&[48_000, 44_100]);
+///
+/// runtime.add_sink(2, speaker, RetryPolicy::none())?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Audio {
+    mode: AudioMode,
+    playback: AudioFormat,
+    play: Box<dyn FnMut(&AudioBuffer) -> Result<(), Error> + Send>,
+}
+
+impl Audio {
+    /// Play tensors containing PCM samples in a particular format.
+    pub fn pcm<F>(format: AudioFormat, play: F) -> Self
+    where
+        F: FnMut(&AudioBuffer) -> Result<(), Error> + Send + 'static,
+    {
+        Audio {
+            mode: AudioMode::Pcm(format),
+            playback: format,
+            play: Box::new(play),
+        }
+    }
+
+    /// Synthesize tones from `[frequency_hz, duration_ms, amplitude]`
+    /// commands.
+    pub fn tones<F>(play: F) -> Self
+    where
+        F: FnMut(&AudioBuffer) -> Result<(), Error> + Send + 'static,
+    {
+        Audio {
+            mode: AudioMode::Tones,
+            playback: AudioFormat::mono(DEFAULT_SAMPLE_RATE),
+            play: Box::new(play),
+        }
+    }
+
+    /// Pick the playback sample rate from the ones the host supports.
+    ///
+    /// See [`negotiate_sample_rate()`] for how the rate is chosen. An empty
+    /// list leaves the current rate unchanged.
+    pub fn negotiate(mut self, supported_sample_rates: &[u32]) -> Self {
+        if let Some(rate) = negotiate_sample_rate(
+            self.playback.sample_rate,
+            supported_sample_rates,
+        ) {
+            self.playback.sample_rate = rate;
+        }
+
+        self
+    }
+
+    pub fn mode(&self) -> AudioMode { self.mode }
+
+    /// The format buffers will be given to the playback callback in.
+    pub fn playback_format(&self) -> AudioFormat { self.playback }
+
+    /// Convert an output tensor into something that can be played.
+    pub fn render(&self, output: &OutputTensor) -> Result<AudioBuffer, Error> {
+        let tensor = match output {
+            OutputTensor::Tensor(t) => t,
+            OutputTensor::StringTensor { .. } => {
+                anyhow::bail!("Audio outputs don't accept strings")
+            },
+        };
+
+        match self.mode {
+            AudioMode::Pcm(format) => {
+                let samples = pcm_samples(tensor)?;
+                Ok(resample(
+                    &AudioBuffer { format, samples },
+                    self.playback.sample_rate,
+                ))
+            },
+            AudioMode::Tones => {
+                let commands =
+                    tensor.elements::<f32>().context("Tones must be f32")?;
+                let columns = tensor
+                    .dimensions()
+                    .last()
+                    .map(|d| d.get())
+                    .unwrap_or(commands.len());
+
+                if columns != 2 && columns != 3 {
+                    anyhow::bail!(
+                        "Each tone should be [frequency_hz, duration_ms] or \
+                         [frequency_hz, duration_ms, amplitude], but rows \
+                         have {} columns",
+                        columns
+                    );
+                }
+
+                let mut samples = Vec::new();
+                for command in commands.chunks_exact(columns) {
+                    let amplitude = command.get(2).copied().unwrap_or(1.0);
+                    tone(
+                        command[0],
+                        Duration::from_secs_f32(command[1].max(0.0) / 1000.0),
+                        amplitude,
+                        self.playback.sample_rate,
+                        &mut samples,
+                    );
+                }
+
+                Ok(AudioBuffer {
+                    format: AudioFormat::mono(self.playback.sample_rate),
+                    samples,
+                })
+            },
+        }
+    }
+}
+
+impl Debug for Audio {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Audio")
+            .field("mode", &self.mode)
+            .field("playback", &self.playback)
+            .finish()
+    }
+}
+
+impl Sink for Audio {
+    fn deliver(&mut self, outputs: &[OutputTensor]) -> Delivery {
+        for output in outputs {
+            let buffer = match self.render(output) {
+                Ok(b) => b,
+                Err(e) => return Delivery::Fatal(e),
+            };
+
+            if let Err(e) = (self.play)(&buffer) {
+                return Delivery::Retryable(e);
+            }
+        }
+
+        Delivery::Delivered
+    }
+}
+
+/// Choose the sample rate to play audio at.
+///
+/// The `requested` rate is used if the host supports it, otherwise we pick
+/// the lowest supported rate above it (so nothing is lost by downsampling),
+/// falling back to the highest rate available.
+pub fn negotiate_sample_rate(requested: u32, supported: &[u32]) -> Option<u32> {
+    if supported.contains(&requested) {
+        return Some(requested);
+    }
+
+    supported
+        .iter()
+        .copied()
+        .filter(|&rate| rate > requested)
+        .min()
+        .or_else(|| supported.iter().copied().max())
+}
+
+fn pcm_samples(tensor: &Tensor) -> Result<Vec<f32>, Error> {
+    let samples = match tensor.element_type() {
+        ElementType::I16 => tensor
+            .elements::<i16>()
+            .map(|s| s.iter().map(|&s| f32::from(s) / 32768.0).collect()),
+        ElementType::U8 => tensor.elements::<u8>().map(|s| {
+            s.iter().map(|&s| (f32::from(s) - 128.0) / 128.0).collect()
+        }),
+        ElementType::F32 => tensor
+            .elements::<f32>()
+            .map(|s| s.iter().map(|&s| s.max(-1.0).min(1.0)).collect()),
+        other => anyhow::bail!("Unable to play {} samples", other),
+    };
+
+    samples.context("The tensor's buffer was misaligned")
+}
+
+/// Linearly interpolate between samples to change the sample rate.
+fn resample(buffer: &AudioBuffer, sample_rate: u32) -> AudioBuffer {
+    let AudioFormat {
+        sample_rate: original_rate,
+        channels,
+    } = buffer.format;
+
+    if original_rate == sample_rate || original_rate == 0 {
+        return buffer.clone();
+    }
+
+    let channels = usize::from(channels.max(1));
+    let frames = buffer.samples.len() / channels;
+    let ratio = f64::from(original_rate) / f64::from(sample_rate);
+    let new_frames = (frames as f64 / ratio).round() as usize;
+
+    let mut samples = Vec::with_capacity(new_frames * channels);
+
+    for frame in 0..new_frames {
+        let position = frame as f64 * ratio;
+        let index = (position.floor() as usize).min(frames.saturating_sub(1));
+        let next = (index + 1).min(frames.saturating_sub(1));
+        let fraction = (position - index as f64) as f32;
+
+        for channel in 0..channels {
+            let a = buffer.samples[index * channels + channel];
+            let b = buffer.samples[next * channels + channel];
+            samples.push(a + (b - a) * fraction);
+        }
+    }
+
+    AudioBuffer {
+        format: AudioFormat {
+            sample_rate,
+            channels: channels as u16,
+        },
+        samples,
+    }
+}
+
+/// Append a sine wave, fading in and out to avoid clicks.
+fn tone(
+    frequency: f32,
+    duration: Duration,
+    amplitude: f32,
+    sample_rate: u32,
+    samples: &mut Vec<f32>,
+) {
+    let length = (duration.as_secs_f64() * f64::from(sample_rate)) as usize;
+    let fade = ((FADE.as_secs_f64() * f64::from(sample_rate)) as usize)
+        .min(length / 2)
+        .max(1);
+    let amplitude = amplitude.max(0.0).min(1.0);
+
+    for n in 0..length {
+        let envelope = (n.min(length - 1 - n) as f32 / fade as f32).min(1.0);
+        let t = n as f32 / sample_rate as f32;
+        let value = if frequency > 0.0 {
+            (2.0 * PI * frequency * t).sin()
+        } else {
+            0.0
+        };
+
+        samples.push(amplitude * envelope * value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn pick_a_supported_sample_rate() {
+        assert_eq!(
+            negotiate_sample_rate(16_000, &[16_000, 48_000]),
+            Some(16_000)
+        );
+        assert_eq!(
+            negotiate_sample_rate(16_000, &[8_000, 44_100, 48_000]),
+            Some(44_100)
+        );
+        assert_eq!(
+            negotiate_sample_rate(96_000, &[44_100, 48_000]),
+            Some(48_000)
+        );
+        assert_eq!(negotiate_sample_rate(16_000, &[]), None);
+    }
+
+    #[test]
+    fn resample_pcm_to_the_playback_rate() {
+        let played = Arc::new(Mutex::new(Vec::new()));
+        let p = Arc::clone(&played);
+        let mut audio = Audio::pcm(AudioFormat::mono(8_000), move |buffer| {
+            p.lock().unwrap().push(buffer.clone());
+            Ok(())
+        })
+        .negotiate(&[16_000]);
+        let pcm = Tensor::new(&[0_i16, 16384, -16384, 0], &[1, 4]);
+
+        let delivery = audio.deliver(&[OutputTensor::Tensor(pcm)]);
+
+        assert!(matches!(delivery, Delivery::Delivered));
+        let played = played.lock().unwrap();
+        assert_eq!(played[0].format, AudioFormat::mono(16_000));
+        assert_eq!(
+            played[0].samples,
+            vec![0.0, 0.25, 0.5, 0.0, -0.5, -0.25, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn synthesize_tones() {
+        let audio = Audio::tones(|_| Ok(())).negotiate(&[8_000]);
+        let commands =
+            Tensor::new(&[440.0_f32, 250.0, 0.5, 0.0, 125.0, 1.0], &[2, 3]);
+
+        let buffer = audio.render(&OutputTensor::Tensor(commands)).unwrap();
+
+        assert_eq!(buffer.format, AudioFormat::mono(8_000));
+        assert_eq!(buffer.samples.len(), 3000);
+        assert_eq!(buffer.duration(), Duration::from_millis(375));
+        assert!(buffer.samples[..2000].iter().all(|s| s.abs() <= 0.5));
+        assert!(buffer.samples[..2000].iter().any(|s| s.abs() > 0.4));
+        assert!(buffer.samples[2000..].iter().all(|&s| s == 0.0));
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::Error;
-use rand::{Rng, SeedableRng};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 
-use crate::{builtins::Arguments, Tensor};
+use crate::{builtins::Arguments, Capability, Tensor};
 
 pub fn random(args: &Arguments) -> Result<Tensor, Error> {
     let count: usize = args.parse_or_default("amount", 1)?;
@@ -17,6 +17,31 @@ pub fn seeded_random(args: &Arguments, seed: u64) -> Result<Tensor, Error> {
     random_tensor(count, rng)
 }
 
+/// A `RAND` [`Capability`] which generates a reproducible sequence of random
+/// numbers, a new set for each prediction.
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    count: usize,
+    rng: SmallRng,
+}
+
+impl SeededRandom {
+    pub fn new(args: &Arguments, seed: u64) -> Result<Self, Error> {
+        let count: usize = args.parse_or_default("amount", 1)?;
+
+        Ok(SeededRandom {
+            count,
+            rng: SmallRng::seed_from_u64(seed),
+        })
+    }
+}
+
+impl Capability for SeededRandom {
+    fn generate(&mut self) -> Result<Tensor, Error> {
+        random_tensor(self.count, &mut self.rng)
+    }
+}
+
 fn random_tensor(count: usize, mut rng: impl Rng) -> Result<Tensor, Error> {
     let mut numbers: Vec<u32> = Vec::new();
     for _ in 0..count {
@@ -24,3 +49,27 @@ fn random_tensor(count: usize, mut rng: impl Rng) -> Result<Tensor, Error> {
     }
     Ok(Tensor::new(&numbers, &[1, count]))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn seeded_capabilities_are_reproducible() {
+        let mut args = HashMap::new();
+        args.insert("amount".to_string(), "4".to_string());
+        let args = Arguments(args);
+        let mut first = SeededRandom::new(&args, 42).unwrap();
+        let mut second = SeededRandom::new(&args, 42).unwrap();
+
+        let a = first.generate().unwrap();
+        let b = first.generate().unwrap();
+
+        assert_eq!(a.dimensions()[1].get(), 4);
+        assert_ne!(a, b);
+        assert_eq!(a, second.generate().unwrap());
+        assert_eq!(b, second.generate().unwrap());
+    }
+}
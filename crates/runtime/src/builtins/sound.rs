@@ -4,30 +4,79 @@ use std::{
     fs::File,
     io::Read,
     path::Path,
+    str::FromStr,
     time::Duration,
 };
 
 use anyhow::{Context, Error};
-use hound::{WavReader, WavSpec};
+use hound::{SampleFormat, WavReader, WavSpec};
 
 use crate::{builtins::Arguments, Tensor};
 
 /// Load an input from a sound clip, applying any transformations requested by
 /// the Rune.
+///
+/// Clips with more than one channel are converted according to the
+/// [`ChannelSelection`] in the `channels` argument.
 pub fn sound(args: &Arguments, clip: &AudioClip) -> Result<Tensor, Error> {
     let sample_rate: u32 = args.parse("hz")?;
     let sample_duration_ms = args.parse("sample_duration_ms")?;
     let duration = Duration::from_millis(sample_duration_ms);
+    let selection: ChannelSelection =
+        args.parse_or_default("channels", ChannelSelection::Mixdown)?;
 
     let AudioClip { spec, samples } = clip;
-    transform_samples(sample_rate, duration, spec, samples)
+    let channels = usize::from(spec.channels.max(1));
+
+    match selection {
+        ChannelSelection::Mixdown => {
+            let mono = mixdown(samples, channels);
+            transform_samples(sample_rate, duration, &mono, 1)
+        },
+        ChannelSelection::Channel(index) => {
+            anyhow::ensure!(
+                index < channels,
+                "The Rune asked for channel {}, but the clip only has {}",
+                index,
+                channels,
+            );
+            let selected: Vec<i16> = samples
+                .iter()
+                .skip(index)
+                .step_by(channels)
+                .copied()
+                .collect();
+            transform_samples(sample_rate, duration, &selected, 1)
+        },
+        ChannelSelection::All => {
+            transform_samples(sample_rate, duration, samples, channels)
+        },
+    }
 }
 
+/// Average each frame's samples to get a single channel.
+fn mixdown(samples: &[i16], channels: usize) -> Vec<i16> {
+    if channels == 1 {
+        return samples.to_vec();
+    }
+
+    samples
+        .chunks_exact(channels)
+        .map(|frame| {
+            let sum: i32 = frame.iter().map(|&s| i32::from(s)).sum();
+            (sum / channels as i32) as i16
+        })
+        .collect()
+}
+
+/// Get the first `duration` worth of (interleaved) samples, as a
+/// `[1, samples]` tensor for mono audio or `[1, samples, channels]`
+/// otherwise.
 fn transform_samples(
     sample_rate: u32,
     duration: Duration,
-    _spec: &WavSpec,
     samples: &[i16],
+    channels: usize,
 ) -> Result<Tensor, Error> {
     // TODO: actually resample the audio so it will have the correct sample rate
     // instead of blindly copying across the requested number of samples.
@@ -35,21 +84,57 @@ fn transform_samples(
     let required_samples = usize::try_from(
         (sample_rate as u128) * duration.as_micros() / 1_000_000,
     )?;
+    let frames = samples.len() / channels;
 
-    if samples.len() < required_samples {
+    if frames < required_samples {
         anyhow::bail!(
             "At least {} samples are required to generate this input, but \
              only {} were provided",
             required_samples,
-            samples.len(),
+            frames,
         );
     }
 
-    let samples = &samples[..required_samples];
+    let samples = &samples[..required_samples * channels];
 
-    Ok(Tensor::new(samples, &[1, samples.len()]))
+    if channels == 1 {
+        Ok(Tensor::new(samples, &[1, required_samples]))
+    } else {
+        Ok(Tensor::new(samples, &[1, required_samples, channels]))
+    }
 }
 
+/// How a Rune wants a multi-channel [`AudioClip`] to be converted.
+///
+/// In a Runefile this is the `channels` argument, which can be `mixdown` (the
+/// default) to average the channels together, `all` to keep every channel as
+/// a `[1, samples, channels]` tensor, or the index of a single channel to use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ChannelSelection {
+    Mixdown,
+    Channel(usize),
+    All,
+}
+
+impl FromStr for ChannelSelection {
+    type Err = UnknownChannelSelection;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mixdown" | "mono" => Ok(ChannelSelection::Mixdown),
+            "all" => Ok(ChannelSelection::All),
+            other => other
+                .parse()
+                .map(ChannelSelection::Channel)
+                .map_err(|_| UnknownChannelSelection),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, thiserror::Error)]
+#[error("Expected \"mixdown\", \"all\", or a channel number")]
+pub struct UnknownChannelSelection;
+
 #[derive(Clone, PartialEq)]
 pub struct AudioClip {
     spec: WavSpec,
@@ -75,6 +160,33 @@ impl AudioClip {
             .context("Unable to parse the WAV file")?;
         Ok(AudioClip { spec, samples })
     }
+
+    /// Create a clip from 16-bit samples the host already has in memory,
+    /// where each frame's channels are interleaved (e.g. `[L, R, L, R, ...]`).
+    pub fn from_interleaved(
+        sample_rate: u32,
+        channels: u16,
+        samples: Vec<i16>,
+    ) -> Result<Self, Error> {
+        anyhow::ensure!(channels > 0, "A clip needs at least one channel");
+        anyhow::ensure!(
+            samples.len() % usize::from(channels) == 0,
+            "{} samples can't be split evenly into {} channels",
+            samples.len(),
+            channels,
+        );
+
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+
+        Ok(AudioClip { spec, samples })
+    }
+
+    pub fn channels(&self) -> u16 { self.spec.channels }
 }
 
 impl Debug for AudioClip {
@@ -86,3 +198,39 @@ impl Debug for AudioClip {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(channels: Option<&str>) -> Arguments {
+        let mut args = vec![("hz", "4"), ("sample_duration_ms", "500")];
+        args.extend(channels.map(|c| ("channels", c)));
+
+        Arguments(
+            args.into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn convert_stereo_audio() {
+        let clip =
+            AudioClip::from_interleaved(4, 2, vec![0, 10, 2, 20, 4, 40, 6, 60])
+                .unwrap();
+
+        let mixed = sound(&args(None), &clip).unwrap();
+        assert_eq!(mixed.elements::<i16>().unwrap(), &[5, 11]);
+        assert_eq!(mixed.shape().to_string(), "i16[1, 2]");
+
+        let right = sound(&args(Some("1")), &clip).unwrap();
+        assert_eq!(right.elements::<i16>().unwrap(), &[10, 20]);
+
+        let all = sound(&args(Some("all")), &clip).unwrap();
+        assert_eq!(all.elements::<i16>().unwrap(), &[0, 10, 2, 20]);
+        assert_eq!(all.shape().to_string(), "i16[1, 2, 2]");
+
+        assert!(sound(&args(Some("2")), &clip).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{BufReader, ErrorKind, Read},
+    net::TcpStream,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::{Context, Error};
+
+use crate::{builtins::Arguments, Capability, Tensor};
+
+/// Where the host should read the raw bytes for a `STREAM` capability from.
+///
+/// This is parsed from a string, where `-` means stdin, `tcp://host:port`
+/// connects to a TCP socket, and anything else is treated as a file path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamSource {
+    File(PathBuf),
+    Stdin,
+    Tcp(String),
+}
+
+impl StreamSource {
+    fn open(&self) -> Result<Box<dyn Read + Send>, Error> {
+        match self {
+            StreamSource::File(path) => {
+                let f = File::open(path).with_context(|| {
+                    format!("Unable to open \"{}\"", path.display())
+                })?;
+                Ok(Box::new(BufReader::new(f)))
+            },
+            StreamSource::Stdin => Ok(Box::new(std::io::stdin())),
+            StreamSource::Tcp(address) => {
+                let stream =
+                    TcpStream::connect(address).with_context(|| {
+                        format!("Unable to connect to \"{}\"", address)
+                    })?;
+                Ok(Box::new(stream))
+            },
+        }
+    }
+}
+
+impl FromStr for StreamSource {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            Ok(StreamSource::Stdin)
+        } else if let Some(address) = s.strip_prefix("tcp://") {
+            Ok(StreamSource::Tcp(address.to_string()))
+        } else {
+            Ok(StreamSource::File(PathBuf::from(s)))
+        }
+    }
+}
+
+impl Display for StreamSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamSource::File(path) => write!(f, "{}", path.display()),
+            StreamSource::Stdin => write!(f, "-"),
+            StreamSource::Tcp(address) => write!(f, "tcp://{}", address),
+        }
+    }
+}
+
+/// Create a [`Capability`] which reads raw bytes from a [`StreamSource`],
+/// one chunk for each prediction.
+///
+/// Each chunk is `length` bytes long. The compiler sets `length` to the size
+/// of the capability's output tensor, so the bytes are reinterpreted as
+/// whatever element type the Runefile declares.
+///
+/// Once the source runs out, [`Capability::generate()`] fails with an
+/// [`EndOfStream`] error.
+pub fn stream(
+    args: &Arguments,
+    source: &StreamSource,
+) -> Result<ByteStream, Error> {
+    let length: usize = args.parse("length")?;
+    let reader = source.open()?;
+
+    Ok(ByteStream::new(reader, length))
+}
+
+/// The [`Capability`] returned by [`stream()`].
+pub struct ByteStream {
+    reader: Box<dyn Read + Send>,
+    length: usize,
+}
+
+impl ByteStream {
+    pub fn new(reader: Box<dyn Read + Send>, length: usize) -> Self {
+        ByteStream { reader, length }
+    }
+}
+
+impl Capability for ByteStream {
+    fn generate(&mut self) -> Result<Tensor, Error> {
+        let mut buffer = vec![0; self.length];
+
+        match self.reader.read_exact(&mut buffer) {
+            Ok(_) => Ok(Tensor::new(&buffer, &[1, self.length])),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                Err(EndOfStream.into())
+            },
+            Err(e) => Err(Error::from(e).context("Unable to read the stream")),
+        }
+    }
+}
+
+/// The error returned by a [`ByteStream`] when there aren't enough bytes left
+/// for another chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EndOfStream;
+
+impl EndOfStream {
+    /// Was this error (or one of its causes) an [`EndOfStream`]?
+    pub fn is_cause_of(error: &Error) -> bool {
+        error.chain().any(|e| e.is::<EndOfStream>())
+    }
+}
+
+impl Display for EndOfStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "The stream ended")
+    }
+}
+
+impl std::error::Error for EndOfStream {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn parse_stream_sources() {
+        let inputs = vec![
+            ("-", StreamSource::Stdin),
+            (
+                "tcp://localhost:1234",
+                StreamSource::Tcp("localhost:1234".into()),
+            ),
+            ("dump.bin", StreamSource::File("dump.bin".into())),
+        ];
+
+        for (src, should_be) in inputs {
+            let got: StreamSource = src.parse().unwrap();
+            assert_eq!(got, should_be);
+            assert_eq!(got.to_string(), src);
+        }
+    }
+
+    #[test]
+    fn read_fixed_size_chunks_until_the_stream_ends() {
+        let reader = Cursor::new(vec![1_u8, 2, 3, 4, 5]);
+        let mut stream = ByteStream::new(Box::new(reader), 2);
+
+        assert_eq!(stream.generate().unwrap().buffer(), &[1, 2]);
+        assert_eq!(stream.generate().unwrap().buffer(), &[3, 4]);
+
+        let err = stream.generate().unwrap_err();
+        assert!(EndOfStream::is_cause_of(&err));
+    }
+}
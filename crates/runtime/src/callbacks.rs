@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path, time::SystemTime};
 
 use anyhow::Error;
 use hotg_rune_core::Shape;
-use log::Record;
+use log::{Level, Record};
+
+use crate::{layout::BufferLayout, scheduler::Scheduler, ElementType, Tensor};
 
 pub(crate) trait Callbacks: Send + Sync + 'static {
     /// A callback fired after a Rune is loaded.
@@ -33,10 +35,57 @@ pub(crate) trait Callbacks: Send + Sync + 'static {
         model: &[u8],
     ) -> Result<Box<dyn Model>, Error>;
 
+    /// Pick which of a model's variants should be loaded, returning its index.
+    ///
+    /// The first variant is always the model's default version.
+    fn select_model_variant(
+        &self,
+        _variants: &[ModelVariant<'_>],
+    ) -> Result<usize, Error> {
+        Ok(0)
+    }
+
     /// Get the value of a global resource.
     fn get_resource(&self, name: &str) -> Option<&[u8]>;
 
-    fn log(&self, _record: &Record<'_>);
+    /// Fail with a [`crate::cancellation::Cancelled`] error if the host has
+    /// asked for the current prediction to stop.
+    fn check_cancelled(&self) -> Result<(), Error> { Ok(()) }
+
+    /// The largest tensor, in bytes, that may be copied into or out of the
+    /// Rune.
+    fn max_tensor_bytes(&self) -> Option<usize> { None }
+
+    /// How much fuel the Rune may consume each time the runtime calls into
+    /// it, for engines which support fuel metering.
+    fn fuel_limit(&self) -> Option<u64> { None }
+
+    /// The thread pool independent stages may be run on (see
+    /// [`crate::Runtime::set_parallelism()`]).
+    fn scheduler(&self) -> Scheduler { Scheduler::sequential() }
+
+    /// The number of WebAssembly pages the Rune's linear memory may grow to.
+    fn max_memory_pages(&self) -> Option<u32> { None }
+
+    fn log(&self, _record: &LogRecord<'_>);
+
+    /// The Rune reported the current value of one of a proc-block's
+    /// parameters.
+    fn proc_block_parameter(
+        &self,
+        _node: &str,
+        _type_name: &str,
+        _name: &str,
+        _value: &str,
+    ) {
+    }
+
+    /// Take the parameters which should be changed before the Rune's next
+    /// run (see [`crate::Runtime::set_proc_block_parameter()`]).
+    fn take_parameter_updates(&self) -> Vec<ParameterUpdate> { Vec::new() }
+
+    /// The current time, according to the host's [`crate::clock::Clock`].
+    fn now(&self) -> SystemTime { SystemTime::now() }
 }
 
 /// Metadata for a node in the ML pipeline, typically an input or output.
@@ -51,11 +100,62 @@ pub struct NodeMetadata {
     pub arguments: HashMap<String, String>,
 }
 
+/// A request to change one of a proc-block's parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ParameterUpdate {
+    /// The proc-block's name in the Runefile.
+    pub node: String,
+    pub name: String,
+    pub value: String,
+}
+
+/// A message logged by the Rune.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct LogRecord<'a> {
+    pub level: Level,
+    pub target: &'a str,
+    pub message: &'a str,
+    /// The name of the pipeline stage that was running when the message was
+    /// logged, if known.
+    ///
+    /// This will be `None` for messages logged outside the pipeline or by
+    /// Runes compiled before the stage was passed to the runtime.
+    pub node: Option<&'a str>,
+    /// When the runtime received the message.
+    pub timestamp: SystemTime,
+    pub module_path: Option<&'a str>,
+    pub file: Option<&'a str>,
+    pub line: Option<u32>,
+}
+
+impl<'a> LogRecord<'a> {
+    /// Convert this message to a [`log::Record`] (e.g. to pass it to
+    /// [`log::logger()`]).
+    pub fn with_record<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Record<'_>) -> R,
+    {
+        f(&Record::builder()
+            .level(self.level)
+            .args(format_args!("{}", self.message))
+            .target(self.target)
+            .module_path(self.module_path)
+            .file(self.file)
+            .line(self.line)
+            .build())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
 pub(crate) struct RuneGraph<'a> {
     pub capabilities: &'a HashMap<u32, NodeMetadata>,
     pub outputs: &'a HashMap<u32, NodeMetadata>,
+    /// The ID for each capability and output, keyed by its name in the
+    /// Runefile. Runes compiled before node names were reported will leave
+    /// this empty.
+    pub node_names: &'a HashMap<String, u32>,
 }
 
 /// Metadata for a model node.
@@ -71,6 +171,22 @@ pub struct ModelMetadata<'a> {
     pub inputs: &'a [Shape<'a>],
     /// The output tensors Rune says this model generates.
     pub outputs: &'a [Shape<'a>],
+    /// The name of the variant being loaded, if the model has more than one.
+    pub variant: Option<&'a str>,
+    /// A directory the model handler may use to cache compiled artifacts
+    /// between runs (see [`crate::RuntimeBuilder::cache_dir()`]).
+    pub cache_dir: Option<&'a Path>,
+    /// The name of the pipeline node this model belongs to, for Runes which
+    /// say so.
+    pub node: Option<&'a str>,
+}
+
+/// One of the alternative versions of a model that the Rune may load.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ModelVariant<'a> {
+    pub name: &'a str,
+    /// The size of the model file, in bytes.
+    pub size: usize,
 }
 
 /// An object that can do inference.
@@ -84,4 +200,129 @@ pub trait Model: Send + Sync + 'static {
 
     fn input_shapes(&self) -> &[Shape<'_>];
     fn output_shapes(&self) -> &[Shape<'_>];
+
+    /// The alignment and padding this model needs for the buffers passed to
+    /// [`Model::infer()`].
+    ///
+    /// When this returns a [`BufferLayout`], every input and output slice
+    /// will start on an `alignment` boundary and include the zeroed padding,
+    /// so it is [`BufferLayout::padded_len()`] bytes long. Use
+    /// [`BufferLayout::describe()`] to find where the elements are.
+    fn buffer_layout(&self) -> Option<BufferLayout> { None }
+
+    /// The execution providers (e.g. `"cpu"` or `"edgetpu"`) this model can
+    /// be run on using [`Model::reconfigure()`].
+    fn providers(&self) -> Vec<String> { vec![CPU_PROVIDER.to_string()] }
+
+    /// Change how the model is executed without reloading the Rune.
+    ///
+    /// The default implementation only accepts options that don't change
+    /// anything.
+    fn reconfigure(&mut self, options: &ModelOptions) -> Result<(), Error> {
+        options.ensure_supported(&self.providers(), false)
+    }
+
+    /// Details about each input tensor, including its name if the model
+    /// format records one.
+    fn input_descriptors(&self) -> Vec<TensorDescriptor> {
+        TensorDescriptor::unnamed(self.input_shapes())
+    }
+
+    /// Details about each output tensor, including its name if the model
+    /// format records one.
+    fn output_descriptors(&self) -> Vec<TensorDescriptor> {
+        TensorDescriptor::unnamed(self.output_shapes())
+    }
+}
+
+/// The execution provider every model supports.
+pub const CPU_PROVIDER: &str = "cpu";
+
+/// Settings which can be changed on a loaded model with
+/// [`Model::reconfigure()`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ModelOptions {
+    /// How many threads inference may use, or `None` to leave it unchanged.
+    pub threads: Option<usize>,
+    /// Which of the model's [`Model::providers()`] to run on, or `None` to
+    /// leave it unchanged.
+    pub provider: Option<String>,
+}
+
+impl ModelOptions {
+    /// Check that a model with these `providers` can apply the options.
+    pub fn ensure_supported(
+        &self,
+        providers: &[String],
+        supports_threads: bool,
+    ) -> Result<(), Error> {
+        if let Some(provider) = &self.provider {
+            anyhow::ensure!(
+                providers.contains(provider),
+                "The \"{}\" execution provider isn't supported (expected one \
+                 of {})",
+                provider,
+                providers.join(", ")
+            );
+        }
+
+        anyhow::ensure!(
+            supports_threads || self.threads.is_none(),
+            "This model doesn't let you change the number of threads"
+        );
+
+        Ok(())
+    }
+}
+
+/// Information about one of a model's input or output tensors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorDescriptor {
+    /// The tensor's name inside the model, if it has one.
+    pub name: Option<String>,
+    pub shape: Shape<'static>,
+}
+
+impl TensorDescriptor {
+    fn unnamed(shapes: &[Shape<'_>]) -> Vec<TensorDescriptor> {
+        shapes
+            .iter()
+            .map(|shape| TensorDescriptor {
+                name: None,
+                shape: shape.to_owned(),
+            })
+            .collect()
+    }
+}
+
+/// A host-provided data source for a capability (e.g. a custom sensor or a
+/// message queue).
+///
+/// Capabilities are created by the factory passed to
+/// [`crate::Runtime::register_capability()`] and are asked for a new input
+/// tensor at the start of every prediction.
+pub trait Capability: Send + 'static {
+    /// Produce the tensor the Rune will read during the next prediction.
+    fn generate(&mut self) -> Result<Tensor, Error>;
+}
+
+impl<F> Capability for F
+where
+    F: FnMut() -> Result<Tensor, Error> + Send + 'static,
+{
+    fn generate(&mut self) -> Result<Tensor, Error> { self() }
+}
+
+/// A capability which produces a continuous stream of samples (e.g. a
+/// microphone), rather than a single reading for each prediction.
+///
+/// Sources are attached using [`crate::Runtime::attach_stream()`] and are
+/// drained every time [`crate::Runtime::poll_streams()`] is called.
+pub trait StreamingCapability: Send + 'static {
+    /// The type of samples in the stream.
+    fn element_type(&self) -> ElementType;
+
+    /// Move any samples which have arrived since the last call to the end of
+    /// `buffer`, encoded as little-endian bytes.
+    fn read(&mut self, buffer: &mut Vec<u8>);
 }
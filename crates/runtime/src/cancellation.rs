@@ -0,0 +1,192 @@
+//! Aborting a prediction that is already running.
+//!
+//! A long-running prediction (e.g. a large model on a slow device) may no
+//! longer be needed by the time it finishes, for example because the user
+//! navigated away. Passing a [`CancellationToken`] to
+//! [`crate::Runtime::predict_with_token()`] lets another thread stop the run
+//! early.
+//!
+//! The token is checked whenever the Rune calls back into the runtime (reading
+//! an input, running a model, writing an output, etc.). Once the token has
+//! been cancelled the next host call traps and the prediction fails with a
+//! [`Cancelled`] error.
+//!
+//! A proc block stuck in an infinite loop never calls back into the runtime,
+//! so [`crate::Runtime::interrupt_handle()`] also gives you an
+//! [`InterruptHandle`] which can stop the WebAssembly itself on engines that
+//! support it (currently just Wasmtime). Other engines fall back to stopping
+//! at the next host call. [`crate::Runtime::predict_with_deadline()`] uses
+//! this to put an upper limit on how long a prediction can take.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hotg_rune_runtime::{
+//!     cancellation::{CancellationToken, Cancelled},
+//!     Runtime,
+//! };
+//!
+//! let rune = std::fs::read("sine.rune")?;
+//! let mut runtime = Runtime::builder().rune(rune).build()?;
+//! let token = CancellationToken::new();
+//!
+//! let handle = token.clone();
+//! std::thread::spawn(move || {
+//!     std::thread::sleep(std::time::Duration::from_millis(50));
+//!     handle.cancel();
+//! });
+//!
+//! match runtime.predict_with_token(&token) {
+//!     Ok(()) => println!("Finished"),
+//!     Err(e) if e.is::<Cancelled>() => println!("Cancelled"),
+//!     Err(e) => return Err(e.into()),
+//! }
+//!
+//! // The runtime can be used again afterwards
+//! runtime.predict()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// A handle which can be used to cancel a prediction from another thread.
+///
+/// Clones of a [`CancellationToken`] share the same state, so cancelling one
+/// cancels them all.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self { CancellationToken::default() }
+
+    /// Ask any prediction using this token to stop.
+    pub fn cancel(&self) { self.cancelled.store(true, Ordering::SeqCst); }
+
+    pub fn is_cancelled(&self) -> bool { self.cancelled.load(Ordering::SeqCst) }
+
+    /// Return a [`Cancelled`] error if the token has been cancelled.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Un-cancel the token so it can be used for another prediction.
+    pub(crate) fn reset(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+    }
+}
+
+/// A function which makes the engine stop executing WebAssembly as soon as
+/// possible.
+pub(crate) type EngineInterrupt = Arc<dyn Fn() + Send + Sync>;
+
+/// A handle which can abort the prediction a [`crate::Runtime`] is currently
+/// running from another thread.
+///
+/// Calling [`InterruptHandle::interrupt()`] while the runtime isn't running a
+/// prediction has no effect.
+#[derive(Clone)]
+pub struct InterruptHandle {
+    token: CancellationToken,
+    engine: Option<EngineInterrupt>,
+}
+
+impl InterruptHandle {
+    pub(crate) fn new(
+        token: CancellationToken,
+        engine: Option<EngineInterrupt>,
+    ) -> Self {
+        InterruptHandle { token, engine }
+    }
+
+    /// Stop the current prediction, making it fail with a [`Cancelled`]
+    /// error.
+    pub fn interrupt(&self) {
+        self.token.cancel();
+
+        if let Some(interrupt) = &self.engine {
+            interrupt();
+        }
+    }
+
+    /// Can the engine be stopped in the middle of executing WebAssembly, or
+    /// will it only stop the next time the Rune calls into the runtime?
+    pub fn interrupts_webassembly(&self) -> bool { self.engine.is_some() }
+}
+
+impl Debug for InterruptHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterruptHandle")
+            .field("token", &self.token)
+            .field("interrupts_webassembly", &self.interrupts_webassembly())
+            .finish()
+    }
+}
+
+/// The error returned when a prediction is stopped by its
+/// [`CancellationToken`].
+///
+/// This can be retrieved from the [`anyhow::Error`] returned by
+/// [`crate::Runtime::predict_with_token()`] using
+/// [`anyhow::Error::downcast_ref()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("The prediction was cancelled")]
+pub struct Cancelled;
+
+/// The error returned by [`crate::Runtime::predict_with_deadline()`] when the
+/// prediction took too long and was interrupted.
+///
+/// The [`anyhow::Error`] will also contain a [`Cancelled`] error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("The prediction didn't finish within {}ms", .timeout.as_millis())]
+pub struct DeadlineExceeded {
+    pub timeout: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancelling_a_clone_cancels_the_original() {
+        let token = CancellationToken::new();
+        let handle = token.clone();
+        assert!(token.check().is_ok());
+
+        handle.cancel();
+
+        assert!(token.is_cancelled());
+        assert_eq!(token.check(), Err(Cancelled));
+    }
+
+    #[test]
+    fn interrupting_cancels_the_token_and_the_engine() {
+        let token = CancellationToken::new();
+        let engine_interrupted = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&engine_interrupted);
+        let handle = InterruptHandle::new(
+            token.clone(),
+            Some(Arc::new(move || flag.store(true, Ordering::SeqCst))),
+        );
+
+        handle.interrupt();
+
+        assert!(token.is_cancelled());
+        assert!(engine_interrupted.load(Ordering::SeqCst));
+
+        token.reset();
+        assert!(token.check().is_ok());
+    }
+}
@@ -0,0 +1,108 @@
+//! Controlling the time reported by the runtime.
+//!
+//! Anything the runtime timestamps (e.g. [`crate::LogRecord::timestamp`] or
+//! when a [`crate::flight_recorder::RunProfile`] started) reads the time from
+//! a [`Clock`]. By default this is the [`SystemClock`], but tests which
+//! compare a Rune's behaviour run-to-run can install a [`VirtualClock`] with
+//! [`crate::RuntimeBuilder::clock()`] so the timestamps are identical every
+//! time.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! use hotg_rune_runtime::{clock::VirtualClock, Runtime};
+//!
+//! let clock = VirtualClock::new(UNIX_EPOCH);
+//! let mut runtime = Runtime::builder()
+//!     .rune(std::fs::read("sine.rune")?)
+//!     .clock(clock.clone())
+//!     .random_seed(42)
+//!     .build()?;
+//!
+//! for _ in 0..10 {
+//!     runtime.predict()?;
+//!     clock.advance(Duration::from_millis(100));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Something which can tell the runtime what the time is.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> SystemTime;
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> SystemTime + Send + Sync + 'static,
+{
+    fn now(&self) -> SystemTime { self() }
+}
+
+/// A [`Clock`] which uses the operating system's time.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime { SystemTime::now() }
+}
+
+/// A [`Clock`] which only moves when it is told to.
+///
+/// Clones of a [`VirtualClock`] share the same time, so the host can keep a
+/// copy around to advance the clock while the [`crate::Runtime`] uses it.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl VirtualClock {
+    pub fn new(start: SystemTime) -> Self {
+        VirtualClock {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    /// Move the clock forwards.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("Lock was poisoned");
+        *now += duration;
+    }
+
+    /// Jump to a particular time.
+    pub fn set(&self, time: SystemTime) {
+        *self.now.lock().expect("Lock was poisoned") = time;
+    }
+}
+
+impl Default for VirtualClock {
+    /// A [`VirtualClock`] starting at the [`UNIX_EPOCH`].
+    fn default() -> Self { VirtualClock::new(UNIX_EPOCH) }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> SystemTime { *self.now.lock().expect("Lock was poisoned") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_clocks_share_their_time() {
+        let clock = VirtualClock::default();
+        let copy = clock.clone();
+
+        copy.advance(Duration::from_secs(5));
+
+        assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(5));
+        clock.set(UNIX_EPOCH);
+        assert_eq!(copy.now(), UNIX_EPOCH);
+    }
+}
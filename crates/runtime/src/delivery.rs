@@ -0,0 +1,328 @@
+//! Bounded queues for delivering outputs to slow consumers.
+//!
+//! By default a Rune's outputs are stored in
+//! [`crate::Runtime::output_tensors()`] and overwritten on every run. Hosts
+//! which forward outputs somewhere slow (e.g. over the network) can instead use
+//! [`crate::Runtime::output_queue()`] to get an [`OutputReceiver`] that can be
+//! drained from another thread, with an [`OverflowPolicy`] deciding what
+//! happens when the consumer can't keep up.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::OutputTensor;
+
+/// What to do when an output is generated but its queue is already full.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Throw away the oldest queued output to make room for the new one.
+    DropOldest,
+    /// Throw away the new output.
+    DropNewest,
+    /// Block [`crate::Runtime::predict()`] until there is room in the queue,
+    /// dropping the new output if the timeout elapses first.
+    BlockWithTimeout(Duration),
+}
+
+/// Configuration for a single output's queue.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// The maximum number of outputs that may be waiting to be consumed.
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            capacity: 16,
+            overflow: OverflowPolicy::DropOldest,
+        }
+    }
+}
+
+/// Statistics about how outputs have been delivered to a queue.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct DeliveryStats {
+    /// The number of outputs that were added to the queue.
+    pub enqueued: u64,
+    /// The number of outputs that were taken from the queue by the consumer.
+    pub delivered: u64,
+    /// The number of outputs that were thrown away because the queue was
+    /// full.
+    pub dropped: u64,
+    /// How many times the Rune was blocked waiting for the consumer to make
+    /// room.
+    pub blocked: u64,
+    /// The most items that were ever waiting in the queue at once.
+    pub high_water_mark: usize,
+}
+
+#[derive(Debug)]
+struct Shared {
+    inner: Mutex<Inner>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    items: VecDeque<Vec<OutputTensor>>,
+    stats: DeliveryStats,
+    sender_closed: bool,
+    receiver_closed: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // Note: a poisoned lock just means someone panicked while holding it.
+        // The queue itself is always left in a consistent state.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The runtime's half of an output queue.
+#[derive(Debug)]
+pub(crate) struct OutputQueue {
+    shared: Arc<Shared>,
+    config: QueueConfig,
+}
+
+impl OutputQueue {
+    pub(crate) fn new(config: QueueConfig) -> (OutputQueue, OutputReceiver) {
+        let shared = Arc::new(Shared {
+            inner: Mutex::default(),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        });
+
+        let queue = OutputQueue {
+            shared: Arc::clone(&shared),
+            config,
+        };
+
+        (queue, OutputReceiver { shared })
+    }
+
+    /// Add an output to the queue, applying the [`OverflowPolicy`] if the
+    /// queue is full.
+    pub(crate) fn push(&self, outputs: Vec<OutputTensor>) {
+        let capacity = self.config.capacity.max(1);
+        let mut inner = self.shared.lock();
+
+        if inner.receiver_closed {
+            inner.stats.dropped += 1;
+            return;
+        }
+
+        if inner.items.len() >= capacity {
+            match self.config.overflow {
+                OverflowPolicy::DropOldest => {
+                    inner.items.pop_front();
+                    inner.stats.dropped += 1;
+                },
+                OverflowPolicy::DropNewest => {
+                    inner.stats.dropped += 1;
+                    return;
+                },
+                OverflowPolicy::BlockWithTimeout(timeout) => {
+                    inner.stats.blocked += 1;
+
+                    let (guard, _) = self
+                        .shared
+                        .not_full
+                        .wait_timeout_while(inner, timeout, |inner| {
+                            inner.items.len() >= capacity
+                                && !inner.receiver_closed
+                        })
+                        .unwrap_or_else(|e| e.into_inner());
+                    inner = guard;
+
+                    if inner.items.len() >= capacity || inner.receiver_closed {
+                        inner.stats.dropped += 1;
+                        return;
+                    }
+                },
+            }
+        }
+
+        inner.items.push_back(outputs);
+        inner.stats.enqueued += 1;
+        inner.stats.high_water_mark =
+            inner.stats.high_water_mark.max(inner.items.len());
+
+        drop(inner);
+        self.shared.not_empty.notify_one();
+    }
+
+    pub(crate) fn stats(&self) -> DeliveryStats { self.shared.lock().stats }
+}
+
+impl Drop for OutputQueue {
+    fn drop(&mut self) {
+        self.shared.lock().sender_closed = true;
+        self.shared.not_empty.notify_all();
+    }
+}
+
+/// The consumer's half of an output queue, created with
+/// [`crate::Runtime::output_queue()`].
+///
+/// Each item is the full set of tensors written to the output during a single
+/// run.
+#[derive(Debug)]
+pub struct OutputReceiver {
+    shared: Arc<Shared>,
+}
+
+impl OutputReceiver {
+    /// Take the next output without blocking.
+    pub fn try_recv(&self) -> Option<Vec<OutputTensor>> {
+        let item = self.shared.lock().pop();
+        self.shared.not_full.notify_one();
+        item
+    }
+
+    /// Wait for the next output, returning `None` once the
+    /// [`crate::Runtime`] has been dropped and the queue is empty.
+    pub fn recv(&self) -> Option<Vec<OutputTensor>> {
+        let inner = self
+            .shared
+            .not_empty
+            .wait_while(self.shared.lock(), |inner| {
+                inner.items.is_empty() && !inner.sender_closed
+            })
+            .unwrap_or_else(|e| e.into_inner());
+
+        self.take(inner)
+    }
+
+    /// Wait up to `timeout` for the next output.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Vec<OutputTensor>> {
+        let (inner, _) = self
+            .shared
+            .not_empty
+            .wait_timeout_while(self.shared.lock(), timeout, |inner| {
+                inner.items.is_empty() && !inner.sender_closed
+            })
+            .unwrap_or_else(|e| e.into_inner());
+
+        self.take(inner)
+    }
+
+    /// Get a snapshot of this queue's [`DeliveryStats`].
+    pub fn stats(&self) -> DeliveryStats { self.shared.lock().stats }
+
+    fn take(
+        &self,
+        mut inner: MutexGuard<'_, Inner>,
+    ) -> Option<Vec<OutputTensor>> {
+        let item = inner.pop();
+        drop(inner);
+        self.shared.not_full.notify_one();
+        item
+    }
+}
+
+impl Inner {
+    fn pop(&mut self) -> Option<Vec<OutputTensor>> {
+        let item = self.items.pop_front()?;
+        self.stats.delivered += 1;
+        Some(item)
+    }
+}
+
+impl Drop for OutputReceiver {
+    fn drop(&mut self) {
+        self.shared.lock().receiver_closed = true;
+        self.shared.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::Tensor;
+
+    fn output(value: u8) -> Vec<OutputTensor> {
+        vec![Tensor::new(&[value], &[1]).into()]
+    }
+
+    fn value(outputs: Vec<OutputTensor>) -> u8 {
+        match &outputs[0] {
+            OutputTensor::Tensor(t) => t.buffer()[0],
+            other => panic!("Unexpected output: {:?}", other),
+        }
+    }
+
+    fn config(overflow: OverflowPolicy) -> QueueConfig {
+        QueueConfig {
+            capacity: 2,
+            overflow,
+        }
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_most_recent_outputs() {
+        let (queue, rx) = OutputQueue::new(config(OverflowPolicy::DropOldest));
+
+        for i in 0..4 {
+            queue.push(output(i));
+        }
+
+        assert_eq!(value(rx.try_recv().unwrap()), 2);
+        assert_eq!(value(rx.try_recv().unwrap()), 3);
+        assert!(rx.try_recv().is_none());
+        let stats = rx.stats();
+        assert_eq!(stats.enqueued, 4);
+        assert_eq!(stats.dropped, 2);
+        assert_eq!(stats.delivered, 2);
+        assert_eq!(stats.high_water_mark, 2);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_first_outputs() {
+        let (queue, rx) = OutputQueue::new(config(OverflowPolicy::DropNewest));
+
+        for i in 0..4 {
+            queue.push(output(i));
+        }
+
+        assert_eq!(value(rx.try_recv().unwrap()), 0);
+        assert_eq!(value(rx.try_recv().unwrap()), 1);
+        assert!(rx.try_recv().is_none());
+        assert_eq!(queue.stats().dropped, 2);
+    }
+
+    #[test]
+    fn blocking_gives_up_after_the_timeout() {
+        let timeout = Duration::from_millis(20);
+        let (queue, rx) =
+            OutputQueue::new(config(OverflowPolicy::BlockWithTimeout(timeout)));
+        queue.push(output(0));
+        queue.push(output(1));
+
+        let start = Instant::now();
+        queue.push(output(2));
+
+        assert!(start.elapsed() >= timeout);
+        let stats = rx.stats();
+        assert_eq!(stats.blocked, 1);
+        assert_eq!(stats.dropped, 1);
+    }
+
+    #[test]
+    fn receiver_sees_the_end_of_the_queue() {
+        let (queue, rx) = OutputQueue::new(QueueConfig::default());
+        queue.push(output(42));
+        drop(queue);
+
+        assert_eq!(value(rx.recv().unwrap()), 42);
+        assert!(rx.recv().is_none());
+    }
+}
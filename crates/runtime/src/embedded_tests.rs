@@ -0,0 +1,388 @@
+//! Running the test cases declared in a Runefile's `tests:` section.
+//!
+//! When a Runefile contains something like this,
+//!
+//! ```yaml
+//! tests:
+//!   silence:
+//!     inputs:
+//!       audio: fixtures/silence.bin
+//!     outputs:
+//!       serial:
+//!         values: [0.0, 1.0]
+//!         tolerance: 0.01
+//! ```
+//!
+//! the compiler embeds the cases and their input fixtures in the Rune. The
+//! [`run_embedded_tests()`] function loads a fresh [`Runtime`] for each case,
+//! sets the fixtures as the capabilities' inputs, runs the pipeline, and
+//! compares what was sent to each output with the expected values.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hotg_rune_runtime::embedded_tests::run_embedded_tests;
+//!
+//! let rune = std::fs::read("microspeech.rune")?;
+//!
+//! for outcome in run_embedded_tests(&rune)? {
+//!     if outcome.passed() {
+//!         println!("{} ... ok", outcome.name);
+//!     } else {
+//!         println!("{} ... FAILED", outcome.name);
+//!         for failure in &outcome.failures {
+//!             println!("  {}", failure);
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, num::NonZeroUsize};
+
+use anyhow::{Context, Error};
+use wasmparser::{Parser, Payload};
+
+use crate::{ElementType, OutputTensor, Runtime, Tensor};
+
+const TESTS_CUSTOM_SECTION: &str = ".rune_tests";
+const TEST_FIXTURE_CUSTOM_SECTION: &str = ".rune_test_fixture";
+
+/// The result of running a single embedded test case.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestOutcome {
+    /// The test case's name from the Runefile.
+    pub name: String,
+    /// A human-readable explanation for everything that didn't match.
+    pub failures: Vec<String>,
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool { self.failures.is_empty() }
+}
+
+/// Run every test case embedded in a Rune.
+///
+/// An error is only returned when the Rune can't be loaded or its test cases
+/// can't be read. A test case which fails to run, or which produces the wrong
+/// outputs, is reported in its [`TestOutcome`] instead.
+pub fn run_embedded_tests(rune: &[u8]) -> Result<Vec<TestOutcome>, Error> {
+    let (cases, fixtures) = parse_embedded_tests(rune)?;
+    let mut outcomes = Vec::new();
+
+    for case in cases {
+        let mut runtime = Runtime::builder()
+            .rune(rune)
+            .build()
+            .context("Unable to load the Rune")?;
+
+        let failures = match run_case(&mut runtime, &case, &fixtures) {
+            Ok(failures) => failures,
+            Err(e) => vec![format!("{:#}", e)],
+        };
+
+        outcomes.push(TestOutcome {
+            name: case.name,
+            failures,
+        });
+    }
+
+    Ok(outcomes)
+}
+
+fn run_case(
+    runtime: &mut Runtime,
+    case: &TestCase,
+    fixtures: &HashMap<String, Vec<u8>>,
+) -> Result<Vec<String>, Error> {
+    let capability_ids = sorted_ids(runtime.capabilities().keys());
+
+    for input in &case.inputs {
+        let id =
+            find_node(runtime, &input.capability, input.index, &capability_ids)
+                .with_context(|| {
+                    format!(
+                        "The Rune has no capability for \"{}\"",
+                        input.capability
+                    )
+                })?;
+        let data = fixtures.get(&input.fixture).with_context(|| {
+            format!("The \"{}\" fixture wasn't embedded", input.fixture)
+        })?;
+        let tensor = input_tensor(input, data)?;
+
+        runtime.input_tensors().insert(id, tensor);
+    }
+
+    runtime.predict()?;
+
+    let output_ids = sorted_ids(runtime.outputs().keys());
+    let mut failures = Vec::new();
+
+    for expected in &case.outputs {
+        let written =
+            find_node(runtime, &expected.output, expected.index, &output_ids)
+                .and_then(|id| runtime.output_tensors().get(&id))
+                .and_then(|tensors| tensors.last());
+
+        match written {
+            Some(tensor) => failures.extend(compare(expected, tensor)),
+            None => failures.push(format!(
+                "Nothing was written to \"{}\"",
+                expected.output
+            )),
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Find the capability or output called `name`.
+///
+/// Older Runes don't tell the runtime their node names, so we fall back to
+/// the node's `index` and hope it lines up with the order IDs were handed out
+/// in.
+pub(crate) fn find_node(
+    runtime: &Runtime,
+    name: &str,
+    index: usize,
+    ids: &[u32],
+) -> Option<u32> {
+    let names = runtime.node_names();
+
+    if names.is_empty() {
+        ids.get(index).copied()
+    } else {
+        names.get(name).copied().filter(|id| ids.contains(id))
+    }
+}
+
+/// IDs are handed out in the order nodes are created, which is the order the
+/// compiler used when numbering them.
+pub(crate) fn sorted_ids<'a>(ids: impl Iterator<Item = &'a u32>) -> Vec<u32> {
+    let mut ids: Vec<u32> = ids.copied().collect();
+    ids.sort_unstable();
+    ids
+}
+
+fn input_tensor(input: &TestInput, data: &[u8]) -> Result<Tensor, Error> {
+    raw_tensor(&input.fixture, input.element_type, &input.dimensions, data)
+}
+
+/// Interpret the contents of a file as a tensor, making sure it is the right
+/// size.
+pub(crate) fn raw_tensor(
+    name: &str,
+    element_type: ElementType,
+    dimensions: &[usize],
+    data: &[u8],
+) -> Result<Tensor, Error> {
+    let shape = format!("{}{:?}", element_type, dimensions);
+    let num_elements: usize = dimensions.iter().product();
+    let dimensions = dimensions
+        .iter()
+        .map(|&d| NonZeroUsize::new(d))
+        .collect::<Option<Vec<_>>>()
+        .context("Dimensions must be non-zero")?;
+
+    anyhow::ensure!(
+        num_elements * element_type.byte_size() == data.len(),
+        "\"{}\" should contain a {} tensor, but it is {} bytes long",
+        name,
+        shape,
+        data.len(),
+    );
+
+    Ok(Tensor::new_raw(element_type, dimensions, data.to_vec()))
+}
+
+fn compare(expected: &TestOutput, actual: &OutputTensor) -> Vec<String> {
+    let tensor = match actual {
+        OutputTensor::Tensor(t) => t,
+        OutputTensor::StringTensor { .. } => {
+            return vec![format!(
+                "\"{}\" received a string tensor, but only numeric outputs \
+                 can be checked",
+                expected.output
+            )];
+        },
+    };
+
+    let values = as_f64(tensor);
+
+    if values.len() != expected.values.len() {
+        return vec![format!(
+            "\"{}\" should have {} elements, but it received {}",
+            expected.output,
+            expected.values.len(),
+            values.len()
+        )];
+    }
+
+    values
+        .iter()
+        .zip(&expected.values)
+        .enumerate()
+        .filter(|(_, (actual, expected_value))| {
+            (*actual - *expected_value).abs() > expected.tolerance
+        })
+        .map(|(i, (actual, expected_value))| {
+            format!(
+                "\"{}\"[{}] should be {} ± {}, but it was {}",
+                expected.output, i, expected_value, expected.tolerance, actual
+            )
+        })
+        .collect()
+}
+
+pub(crate) fn as_f64(tensor: &Tensor) -> Vec<f64> {
+    macro_rules! convert {
+        ($type:ty) => {
+            tensor
+                .elements::<$type>()
+                .unwrap_or_default()
+                .iter()
+                .map(|&v| v as f64)
+                .collect()
+        };
+    }
+
+    match tensor.element_type() {
+        ElementType::U8 => convert!(u8),
+        ElementType::I8 => convert!(i8),
+        ElementType::U16 => convert!(u16),
+        ElementType::I16 => convert!(i16),
+        ElementType::U32 => convert!(u32),
+        ElementType::I32 => convert!(i32),
+        ElementType::F32 => convert!(f32),
+        ElementType::U64 => convert!(u64),
+        ElementType::I64 => convert!(i64),
+        ElementType::F64 => {
+            tensor.elements::<f64>().unwrap_or_default().to_vec()
+        },
+        ElementType::F16 => tensor
+            .elements::<half::f16>()
+            .unwrap_or_default()
+            .iter()
+            .map(|v| v.to_f64())
+            .collect(),
+        ElementType::BF16 => tensor
+            .elements::<half::bf16>()
+            .unwrap_or_default()
+            .iter()
+            .map(|v| v.to_f64())
+            .collect(),
+        ElementType::Utf8 => Vec::new(),
+    }
+}
+
+/// The test cases embedded by the compiler.
+///
+/// This mirrors the compiler's `EmbeddedTests` type.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+struct EmbeddedTests {
+    cases: Vec<TestCase>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+struct TestCase {
+    name: String,
+    inputs: Vec<TestInput>,
+    outputs: Vec<TestOutput>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+struct TestInput {
+    capability: String,
+    index: usize,
+    fixture: String,
+    element_type: ElementType,
+    dimensions: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+struct TestOutput {
+    output: String,
+    index: usize,
+    values: Vec<f64>,
+    tolerance: f64,
+}
+
+fn parse_embedded_tests(
+    wasm: &[u8],
+) -> Result<(Vec<TestCase>, HashMap<String, Vec<u8>>), Error> {
+    let mut cases = Vec::new();
+    let mut fixtures = HashMap::new();
+
+    for payload in Parser::default().parse_all(wasm) {
+        let (name, mut data) = match payload {
+            Ok(Payload::CustomSection { name, data, .. }) => (name, data),
+            Ok(_) => continue,
+            Err(e) => {
+                return Err(Error::new(e).context("Unable to parse the Rune"))
+            },
+        };
+
+        match name {
+            TESTS_CUSTOM_SECTION => {
+                let tests: EmbeddedTests = serde_json::from_slice(data)
+                    .context("Unable to read the embedded test cases")?;
+                cases.extend(tests.cases);
+            },
+            TEST_FIXTURE_CUSTOM_SECTION => {
+                while let Some((fixture, value, rest)) =
+                    hotg_rune_core::decode_inline_resource(data)
+                {
+                    fixtures.insert(fixture.to_string(), value.to_vec());
+                    data = rest;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Ok((cases, fixtures))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_cases_and_fixtures_from_custom_sections() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (@custom ".rune_tests" "{\"cases\":[{\"name\":\"silence\",\"inputs\":[{\"capability\":\"audio\",\"index\":0,\"fixture\":\"silence/audio\",\"element_type\":\"i16\",\"dimensions\":[2]}],\"outputs\":[]}]}")
+                (@custom ".rune_test_fixture" "\00\00\00\0dsilence/audio\00\00\00\04\01\00\02\00")
+            )"#,
+        )
+        .unwrap();
+
+        let (cases, fixtures) = parse_embedded_tests(&wasm).unwrap();
+
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].inputs[0].element_type, ElementType::I16);
+        let tensor =
+            input_tensor(&cases[0].inputs[0], &fixtures["silence/audio"])
+                .unwrap();
+        assert_eq!(tensor.elements::<i16>().unwrap(), &[1, 2]);
+    }
+
+    #[test]
+    fn values_outside_the_tolerance_are_failures() {
+        let expected = TestOutput {
+            output: "serial".to_string(),
+            index: 0,
+            values: vec![0.0, 1.0],
+            tolerance: 0.1,
+        };
+        let actual = OutputTensor::Tensor(Tensor::new(&[0.05_f32, 0.5], &[2]));
+
+        let failures = compare(&expected, &actual);
+
+        assert_eq!(
+            failures,
+            vec!["\"serial\"[1] should be 1 ± 0.1, but it was 0.5".to_string()]
+        );
+    }
+}
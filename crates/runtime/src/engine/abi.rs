@@ -0,0 +1,144 @@
+//! Figuring out which version of the host function ABI a Rune was compiled
+//! against.
+//!
+//! Runes compiled before rune 0.5 loaded models with `tfm_preload_model()`
+//! and ran them with `tfm_model_invoke()`, passing a single input and output
+//! buffer without saying anything about their shapes. Newer Runes use
+//! `rune_model_load()` and `rune_model_infer()` instead, but we still want to
+//! be able to run the old binaries, so when a legacy Rune is detected the
+//! [`HostFunctions`][super::host_functions::HostFunctions] map the old
+//! functions onto the new model internals.
+//!
+//! Legacy Runes use the same capability and output functions, but they log
+//! plain text instead of structured records and never name their nodes, so
+//! those get adapted too. Capabilities and outputs are named after their kind
+//! (e.g. `raw` or `serial_2`) and the shapes of a model's tensors are read
+//! from the TensorFlow Lite model itself.
+
+use anyhow::Context;
+use wasmparser::{ExternalKind, ImportSectionEntryType, Parser, Payload};
+
+use crate::{
+    engine::{LoadError, HOST_MODULE},
+    log_filter::debug,
+};
+
+/// Host functions which are only used by Runes compiled before `0.5`.
+pub(crate) const LEGACY_HOST_FUNCTIONS: &[&str] =
+    &["tfm_preload_model", "tfm_model_invoke"];
+
+/// Functions every Rune must export, regardless of its ABI version.
+const REQUIRED_EXPORTS: &[&str] = &["_manifest", "_call"];
+
+/// The calling convention a Rune uses to talk to the runtime.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum AbiVersion {
+    /// Models are loaded with `tfm_preload_model()` and invoked with
+    /// `tfm_model_invoke()`.
+    Legacy,
+    /// The ABI used by Runes compiled with the current version of rune.
+    Current,
+}
+
+/// Inspect a Rune's imports and exports to see which [`AbiVersion`] it was
+/// compiled against.
+pub(crate) fn detect(wasm: &[u8]) -> Result<AbiVersion, LoadError> {
+    let mut version = AbiVersion::Current;
+    let mut missing: Vec<&str> = REQUIRED_EXPORTS.to_vec();
+
+    for payload in Parser::default().parse_all(wasm) {
+        let payload = payload.context("Unable to parse the WebAssembly")?;
+
+        match payload {
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    let import =
+                        import.context("Unable to parse the import section")?;
+                    let name = import.field.unwrap_or_default();
+
+                    if let ImportSectionEntryType::Function(_) = import.ty {
+                        if import.module == HOST_MODULE
+                            && LEGACY_HOST_FUNCTIONS.contains(&name)
+                        {
+                            version = AbiVersion::Legacy;
+                        }
+                    }
+                }
+            },
+            Payload::ExportSection(exports) => {
+                for export in exports {
+                    let export =
+                        export.context("Unable to parse the export section")?;
+
+                    if matches!(export.kind, ExternalKind::Function) {
+                        missing.retain(|&name| name != export.field);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(LoadError::Other(anyhow::anyhow!(
+            "This doesn't look like a Rune because it doesn't export {}",
+            missing.join(" or ")
+        )));
+    }
+
+    if version == AbiVersion::Legacy {
+        debug!("Using the legacy model adapter for a pre-0.5 Rune");
+    }
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORTS: &str = r#"
+        (func (export "_manifest") (result i32) i32.const 0)
+        (func (export "_call") (param i32 i32 i32) (result i32) i32.const 0)
+    "#;
+
+    #[test]
+    fn detect_runes_using_the_old_model_functions() {
+        let legacy = wat::parse_str(format!(
+            r#"(module
+                (import "env" "tfm_preload_model"
+                    (func (param i32 i32 i32 i32) (result i32)))
+                {}
+            )"#,
+            EXPORTS
+        ))
+        .unwrap();
+        let current = wat::parse_str(format!(
+            r#"(module
+                (import "env" "rune_model_infer"
+                    (func (param i32 i32 i32) (result i32)))
+                {}
+            )"#,
+            EXPORTS
+        ))
+        .unwrap();
+
+        assert_eq!(detect(&legacy).unwrap(), AbiVersion::Legacy);
+        assert_eq!(detect(&current).unwrap(), AbiVersion::Current);
+    }
+
+    #[test]
+    fn modules_without_the_rune_exports_are_rejected() {
+        let wasm = wat::parse_str(
+            r#"(module (func (export "_manifest") (result i32) i32.const 0))"#,
+        )
+        .unwrap();
+
+        let err = detect(&wasm).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "This doesn't look like a Rune because it doesn't export _call"
+        );
+    }
+}
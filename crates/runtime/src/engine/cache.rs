@@ -0,0 +1,75 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+/// Where the compiled version of a Rune is cached.
+///
+/// The file name is a SHA-256 hash of `key`, which should contain everything
+/// the machine code depends on (the Rune, the engine's version, the target,
+/// etc.). Each part is length-prefixed so different keys can't collide by
+/// shifting bytes from one part to the next.
+pub(crate) fn cache_path(
+    cache_dir: &Path,
+    extension: &str,
+    key: &[&[u8]],
+) -> PathBuf {
+    let mut hasher = Sha256::new();
+
+    for part in key {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+
+    cache_dir.join(format!("{:x}.{}", hasher.finalize(), extension))
+}
+
+/// Write the compiled Rune to a temporary file next to `path` and move it
+/// into place, so a concurrent reader (or a crash half way through) never
+/// sees a truncated module.
+pub(crate) fn write_atomically(
+    path: &Path,
+    contents: &[u8],
+) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    std::fs::create_dir_all(dir)?;
+
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    temp.write_all(contents)?;
+    temp.as_file().sync_all()?;
+    temp.persist(path).map_err(|e| e.error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_part_of_the_key_changes_the_path() {
+        let dir = Path::new("cache");
+        let original = cache_path(dir, "wasm", &[b"2.2.0", b"rune"]);
+
+        assert_eq!(original, cache_path(dir, "wasm", &[b"2.2.0", b"rune"]));
+        assert_ne!(original, cache_path(dir, "wasm", &[b"2.3.0", b"rune"]));
+        assert_ne!(original, cache_path(dir, "wasm", &[b"2.2.0", b"other"]));
+        assert_ne!(original, cache_path(dir, "wasm", &[b"2.2.0r", b"une"]));
+        assert_eq!(original.extension().unwrap(), "wasm");
+    }
+
+    #[test]
+    fn atomic_writes_replace_the_file_without_leaving_temporaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("module.wasm");
+
+        write_atomically(&path, b"first").unwrap();
+        write_atomically(&path, b"second").unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        let files = std::fs::read_dir(path.parent().unwrap()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+}
@@ -2,39 +2,95 @@
 
 use std::{
     collections::HashMap,
+    convert::TryFrom,
     io::{Cursor, Read},
     sync::Arc,
 };
 
 use anyhow::{Context, Error};
-use hotg_rune_core::{SerializableRecord, Shape};
+use hotg_rune_core::{SerializableRecord, Shape, TFLITE_MIMETYPE};
 
-use crate::callbacks::{
-    Callbacks, Model, ModelMetadata, NodeMetadata, RuneGraph,
+use crate::{
+    callbacks::{
+        Callbacks, LogRecord, Model, ModelMetadata, ModelVariant, NodeMetadata,
+        ParameterUpdate, RuneGraph,
+    },
+    engine::{AbiVersion, AssertionError, TrapError},
+    layout::StagingBuffers,
+    log_filter::{debug, warn},
 };
 
 /// An adapter that exposes functionality from [`Callbacks`] via functions that
 /// the WebAssembly expects.
 ///
 /// This object also manages any objects that are constructed by the Rune.
-pub(crate) struct HostFunctions {
+///
+/// Each method corresponds to one of the functions in
+/// [`super::HOST_FUNCTIONS`], with its arguments already read out of the
+/// Rune's linear memory. A custom [`super::WebAssemblyEngine`] gets one of
+/// these from [`crate::RuntimeBuilder::custom_engine()`].
+pub struct HostFunctions {
     next: u32,
     callbacks: Arc<dyn Callbacks>,
+    abi: AbiVersion,
     capabilities: HashMap<u32, NodeMetadata>,
     outputs: HashMap<u32, NodeMetadata>,
+    /// Capability and output IDs, keyed by their name in the Runefile.
+    node_names: HashMap<String, u32>,
     resources: HashMap<u32, Box<dyn Read + Send + Sync>>,
     models: HashMap<u32, Box<dyn Model>>,
+    /// Aligned buffers for models which asked for a
+    /// [`crate::layout::BufferLayout`].
+    staging: HashMap<u32, Option<StagingBuffers>>,
+    /// The variant chosen by the last call to
+    /// [`HostFunctions::rune_model_select_variant()`], which will be used by
+    /// the next model that gets loaded.
+    pending_variant: Option<String>,
+    /// The node name from the last call to
+    /// [`HostFunctions::rune_model_name()`], which will be attached to the
+    /// next model that gets loaded.
+    pending_model_name: Option<String>,
+    /// Details from the last call to
+    /// [`HostFunctions::rune_assertion_failed()`], which will be attached to
+    /// the panic that follows it.
+    pending_assertion: Option<PendingAssertion>,
+    /// Inputs which are being copied into the Rune a chunk at a time, keyed by
+    /// capability ID.
+    pending_inputs: HashMap<u32, Vec<u8>>,
+    /// Outputs which are being copied out of the Rune a chunk at a time, keyed
+    /// by output ID.
+    pending_outputs: HashMap<u32, Vec<u8>>,
+    /// Parameter updates which have been encoded for
+    /// [`HostFunctions::rune_proc_block_parameter_updates()`] but didn't fit
+    /// in the Rune's buffer yet.
+    pending_parameter_updates: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PendingAssertion {
+    expression: String,
+    message: Option<String>,
+    values: Vec<(String, String)>,
 }
 
 impl HostFunctions {
-    pub fn new(callbacks: Arc<dyn Callbacks>) -> Self {
+    pub(crate) fn new(callbacks: Arc<dyn Callbacks>, abi: AbiVersion) -> Self {
         HostFunctions {
             callbacks,
+            abi,
             next: 1,
             capabilities: HashMap::new(),
             outputs: HashMap::new(),
+            node_names: HashMap::new(),
             resources: HashMap::new(),
             models: HashMap::new(),
+            staging: HashMap::new(),
+            pending_variant: None,
+            pending_model_name: None,
+            pending_assertion: None,
+            pending_inputs: HashMap::new(),
+            pending_outputs: HashMap::new(),
+            pending_parameter_updates: None,
         }
     }
 
@@ -42,13 +98,69 @@ impl HostFunctions {
         RuneGraph {
             capabilities: &self.capabilities,
             outputs: &self.outputs,
+            node_names: &self.node_names,
+        }
+    }
+
+    /// Tell the runtime that the Rune's `_manifest()` function has finished
+    /// declaring its capabilities and outputs.
+    pub fn loaded(&mut self) -> Result<(), Error> {
+        if self.abi == AbiVersion::Legacy && self.node_names.is_empty() {
+            self.name_legacy_nodes();
+        }
+
+        self.callbacks.loaded(&self.graph())
+    }
+
+    /// Legacy Runes never call [`HostFunctions::rune_node_name()`], so name
+    /// each capability and output after its kind (e.g. `raw`, `serial`, and
+    /// `serial_2`) so they can still be looked up by name.
+    fn name_legacy_nodes(&mut self) {
+        let mut nodes: Vec<(u32, &str)> = self
+            .capabilities
+            .iter()
+            .chain(&self.outputs)
+            .map(|(&id, meta)| (id, meta.kind.as_str()))
+            .collect();
+        nodes.sort();
+
+        let mut seen: HashMap<String, usize> = HashMap::new();
+
+        for (id, kind) in nodes {
+            let kind = kind.to_lowercase();
+            let count = seen.entry(kind.clone()).or_default();
+            *count += 1;
+
+            let name = match *count {
+                1 => kind,
+                n => format!("{}_{}", kind, n),
+            };
+            self.node_names.insert(name, id);
         }
     }
 
-    pub(crate) fn model_by_id(&mut self, id: u32) -> Option<&mut dyn Model> {
+    /// Get one of the models loaded by the Rune.
+    pub fn model_by_id(&mut self, id: u32) -> Option<&mut dyn Model> {
         self.models.get_mut(&id).map(|m| &mut **m)
     }
 
+    /// Swap out a model, dropping any staging buffers that were sized for the
+    /// old one.
+    pub fn replace_model(
+        &mut self,
+        id: u32,
+        model: Box<dyn Model>,
+    ) -> Result<(), Error> {
+        let slot = self
+            .models
+            .get_mut(&id)
+            .with_context(|| format!("No model with ID {}", id))?;
+        *slot = model;
+        self.staging.remove(&id);
+
+        Ok(())
+    }
+
     fn next_id(&mut self) -> u32 {
         let id = self.next;
         self.next += 1;
@@ -56,17 +168,38 @@ impl HostFunctions {
     }
 
     pub fn debug(&self, message: &str) -> Result<(), Error> {
-        log::debug!("Received message: {}", message);
+        debug!("Received message: {}", message);
 
         match serde_json::from_str::<SerializableRecord>(message) {
             Ok(record) => {
-                record.with_record(|r| self.callbacks.log(r));
+                self.callbacks.log(&LogRecord {
+                    level: record.level,
+                    target: &record.target,
+                    message: &record.message,
+                    node: record.node.as_deref(),
+                    timestamp: self.callbacks.now(),
+                    module_path: record.module_path.as_deref(),
+                    file: record.file.as_deref(),
+                    line: record.line,
+                });
+            },
+            // Legacy Runes log plain text instead of structured records
+            Err(_) if self.abi == AbiVersion::Legacy => {
+                self.callbacks.log(&LogRecord {
+                    level: log::Level::Info,
+                    target: "rune",
+                    message: message.trim_end(),
+                    node: None,
+                    timestamp: self.callbacks.now(),
+                    module_path: None,
+                    file: None,
+                    line: None,
+                });
             },
             Err(e) => {
-                log::warn!(
+                warn!(
                     "Unable to deserialize {:?} as a log message: {}",
-                    message,
-                    e
+                    message, e
                 );
             },
         }
@@ -74,6 +207,77 @@ impl HostFunctions {
         Ok(())
     }
 
+    /// Turn a panic inside the Rune into a [`TrapError`], or an
+    /// [`AssertionError`] if it was triggered by a failed `rune_assert!()`.
+    pub fn panic(
+        &mut self,
+        message: &str,
+        file: &str,
+        line: u32,
+        column: u32,
+        stage: &str,
+    ) -> Error {
+        let stage = if stage.is_empty() {
+            None
+        } else {
+            Some(stage.to_string())
+        };
+
+        if let Some(PendingAssertion {
+            expression,
+            message,
+            values,
+        }) = self.pending_assertion.take()
+        {
+            return AssertionError {
+                expression,
+                message,
+                values,
+                stage,
+                file: file.to_string(),
+                line,
+                column,
+            }
+            .into();
+        }
+
+        TrapError {
+            message: message.to_string(),
+            stage,
+            file: file.to_string(),
+            line,
+            column,
+        }
+        .into()
+    }
+
+    /// Record the details of a failed `rune_assert!()`.
+    ///
+    /// The Rune will panic immediately afterwards, and
+    /// [`HostFunctions::panic()`] uses these details to create a more
+    /// useful error.
+    pub fn rune_assertion_failed(
+        &mut self,
+        expression: &str,
+        message: &str,
+        values: &[(&str, &str)],
+    ) {
+        let message = if message.is_empty() {
+            None
+        } else {
+            Some(message.to_string())
+        };
+
+        self.pending_assertion = Some(PendingAssertion {
+            expression: expression.to_string(),
+            message,
+            values: values
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        });
+    }
+
     pub fn request_capability(
         &mut self,
         capability_type: u32,
@@ -113,11 +317,100 @@ impl HostFunctions {
         Ok(())
     }
 
+    /// Record which Runefile node a capability or output is for.
+    pub fn rune_node_name(&mut self, id: u32, name: &str) -> Result<(), Error> {
+        anyhow::ensure!(
+            self.capabilities.contains_key(&id)
+                || self.outputs.contains_key(&id),
+            "Trying to name \"{}\", but there is no capability or output with \
+             ID {}",
+            name,
+            id
+        );
+        self.node_names.insert(name.to_string(), id);
+
+        Ok(())
+    }
+
     pub fn request_provider_response(
         &self,
         capability_id: u32,
         buffer: &mut [u8],
     ) -> Result<u32, Error> {
+        let bytes_written = self.read_capability_into(capability_id, buffer)?;
+
+        u32::try_from(bytes_written).with_context(|| {
+            format!(
+                "The input is {} bytes long, which is too big to be returned \
+                 by request_provider_response(). The Rune should use \
+                 request_provider_response_chunk() instead",
+                bytes_written
+            )
+        })
+    }
+
+    /// Copy part of a capability's input into the Rune.
+    ///
+    /// The whole input (`total_len` bytes) is read from the capability when
+    /// the chunk at `offset` 0 is requested, and each chunk is copied out of
+    /// that until everything has been read. This lets inputs use 64-bit
+    /// lengths even though a single copy into 32-bit linear memory can't.
+    pub fn request_provider_response_chunk(
+        &mut self,
+        capability_id: u32,
+        offset: u64,
+        total_len: u64,
+        buffer: &mut [u8],
+    ) -> Result<u32, Error> {
+        if offset == 0 {
+            let total_len = self.checked_length(total_len)?;
+            let mut data = vec![0; total_len];
+            let bytes_written =
+                self.read_capability_into(capability_id, &mut data)?;
+            data.truncate(bytes_written);
+            self.pending_inputs.insert(capability_id, data);
+        } else {
+            self.callbacks.check_cancelled()?;
+        }
+
+        let data =
+            self.pending_inputs.get(&capability_id).with_context(|| {
+                format!(
+                    "Tried to read from offset {} of capability {} without \
+                     first reading from offset 0",
+                    offset, capability_id
+                )
+            })?;
+
+        let start = usize::try_from(offset)
+            .ok()
+            .filter(|&start| start <= data.len())
+            .with_context(|| {
+                format!(
+                    "Offset {} is past the end of the {} byte input",
+                    offset,
+                    data.len()
+                )
+            })?;
+        let end = data.len().min(start + buffer.len());
+        let chunk = &data[start..end];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bytes_written = chunk.len();
+
+        if end == data.len() {
+            self.pending_inputs.remove(&capability_id);
+        }
+
+        Ok(bytes_written as u32)
+    }
+
+    fn read_capability_into(
+        &self,
+        capability_id: u32,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
+        self.callbacks.check_cancelled()?;
+
         let meta =
             self.capabilities.get(&capability_id).with_context(|| {
                 format!(
@@ -126,20 +419,131 @@ impl HostFunctions {
                 )
             })?;
 
-        let bytes_written = self
-            .callbacks
+        self.callbacks
             .read_capability(capability_id, meta, buffer)
-            .context("Unable to read the input")?;
+            .context("Unable to read the input")
+    }
 
-        Ok(bytes_written as u32)
+    /// Make sure a tensor's length (as given by the Rune) fits in this
+    /// machine's address space and the largest tensor the runtime allows.
+    fn checked_length(&self, len: u64) -> Result<usize, Error> {
+        let len = usize::try_from(len).with_context(|| {
+            format!("A {} byte tensor is too big for this platform", len)
+        })?;
+
+        if let Some(limit) = self.callbacks.max_tensor_bytes() {
+            anyhow::ensure!(
+                len <= limit,
+                "A {} byte tensor would exceed the {} byte tensor size limit",
+                len,
+                limit
+            );
+        }
+
+        Ok(len)
     }
 
-    pub fn tfm_model_invoke(&self) -> Result<(), Error> {
-        anyhow::bail!("This feature has been removed")
+    /// Run a model loaded by [`HostFunctions::tfm_preload_model()`].
+    ///
+    /// Only Runes using the [`AbiVersion::Legacy`] ABI are allowed to call
+    /// this, and their models always have a single input and output.
+    pub fn tfm_model_invoke(
+        &mut self,
+        model_id: u32,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), Error> {
+        self.ensure_legacy_abi("tfm_model_invoke")?;
+
+        self.rune_model_infer(model_id, &[input], &mut [output])
     }
 
-    pub fn tfm_preload_model(&self) -> Result<(), Error> {
-        anyhow::bail!("This feature has been removed")
+    /// Load a model for a Rune compiled before `rune_model_load()` existed.
+    ///
+    /// Legacy Runes only supported TensorFlow Lite and didn't say anything
+    /// about the model's tensors, so the model handler is given the shapes
+    /// from the model file itself.
+    pub fn tfm_preload_model(
+        &mut self,
+        model: &[u8],
+        inputs: u32,
+        outputs: u32,
+    ) -> Result<u32, Error> {
+        self.ensure_legacy_abi("tfm_preload_model")?;
+
+        let (input_shapes, output_shapes) =
+            crate::models::tflite_schema::model_shapes(model)
+                .context("Unable to read the model's inputs and outputs")?;
+        if input_shapes.len() != inputs as usize
+            || output_shapes.len() != outputs as usize
+        {
+            anyhow::bail!(
+                "The Rune expected a model with {} inputs and {} outputs, but \
+                 it has inputs {:?} and outputs {:?}",
+                inputs,
+                outputs,
+                input_shapes,
+                output_shapes,
+            );
+        }
+
+        let id = self.next_id();
+        let meta = ModelMetadata {
+            mimetype: TFLITE_MIMETYPE,
+            inputs: &input_shapes,
+            outputs: &output_shapes,
+            variant: None,
+            cache_dir: None,
+            node: None,
+        };
+
+        let model = self
+            .callbacks
+            .load_model(id, &meta, model)
+            .context("Unable to load the model")?;
+
+        self.models.insert(id, model);
+
+        Ok(id)
+    }
+
+    fn ensure_legacy_abi(&self, function: &str) -> Result<(), Error> {
+        match self.abi {
+            AbiVersion::Legacy => Ok(()),
+            AbiVersion::Current => anyhow::bail!(
+                "{}() is only available to Runes compiled before rune 0.5",
+                function
+            ),
+        }
+    }
+
+    pub fn rune_model_select_variant(
+        &mut self,
+        variants: &[ModelVariant<'_>],
+    ) -> Result<u32, Error> {
+        let index = self
+            .callbacks
+            .select_model_variant(variants)
+            .context("Unable to select a model variant")?;
+
+        let chosen = variants.get(index).with_context(|| {
+            format!(
+                "Selected variant {} but there are only {} variants",
+                index,
+                variants.len()
+            )
+        })?;
+        debug!("Selected the \"{}\" model variant", chosen.name);
+        self.pending_variant = Some(chosen.name.to_string());
+
+        Ok(index as u32)
+    }
+
+    /// Record which pipeline node the next call to
+    /// [`HostFunctions::rune_model_load()`] is for.
+    pub fn rune_model_name(&mut self, name: &str) -> Result<(), Error> {
+        self.pending_model_name = Some(name.to_string());
+        Ok(())
     }
 
     pub fn rune_model_load(
@@ -150,11 +554,16 @@ impl HostFunctions {
         outputs: &[Shape<'_>],
     ) -> Result<u32, Error> {
         let id = self.next_id();
+        let variant = self.pending_variant.take();
+        let node = self.pending_model_name.take();
 
         let meta = ModelMetadata {
             mimetype,
             inputs,
             outputs,
+            variant: variant.as_deref(),
+            cache_dir: None,
+            node: node.as_deref(),
         };
 
         let model =
@@ -179,11 +588,26 @@ impl HostFunctions {
         inputs: &[&[u8]],
         outputs: &mut [&mut [u8]],
     ) -> Result<(), Error> {
+        self.callbacks.check_cancelled()?;
+
         let model = self.models.get_mut(&model_id).with_context(|| {
             format!("Tried to access non-existent model with ID {}", model_id)
         })?;
 
-        model.infer(inputs, outputs)?;
+        match model.buffer_layout() {
+            Some(layout) => StagingBuffers::infer(
+                self.staging.entry(model_id).or_default(),
+                layout,
+                &mut **model,
+                inputs,
+                outputs,
+            )?,
+            None => model.infer(inputs, outputs)?,
+        }
+
+        // Models can't be interrupted, so this is our first chance to notice
+        // if the host gave up while inference was running.
+        self.callbacks.check_cancelled()?;
 
         Ok(())
     }
@@ -203,11 +627,30 @@ impl HostFunctions {
         Ok(id)
     }
 
+    pub fn request_output_set_param(
+        &mut self,
+        output_id: u32,
+        key: &str,
+        value: impl Into<String>,
+    ) -> Result<(), Error> {
+        let meta = self.outputs.get_mut(&output_id).with_context(|| {
+            format!(
+                "Trying to set \"{}\" on non-existent output with ID {}",
+                key, output_id
+            )
+        })?;
+        meta.arguments.insert(key.to_string(), value.into());
+
+        Ok(())
+    }
+
     pub fn consume_output(
         &mut self,
         output_id: u32,
         data: &[u8],
     ) -> Result<(), Error> {
+        self.callbacks.check_cancelled()?;
+
         let metadata = self.outputs.get(&output_id).with_context(|| {
             format!(
                 "Tried to write to non-existent output with ID {}",
@@ -222,6 +665,101 @@ impl HostFunctions {
         Ok(())
     }
 
+    /// Receive part of an output's data, passing it to
+    /// [`HostFunctions::consume_output()`] once all `total_len` bytes have
+    /// arrived.
+    ///
+    /// Chunks must be sent in order, starting from `offset` 0.
+    pub fn consume_output_chunk(
+        &mut self,
+        output_id: u32,
+        offset: u64,
+        total_len: u64,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        self.callbacks.check_cancelled()?;
+
+        let total_len = self.checked_length(total_len)?;
+
+        if offset == 0 {
+            self.pending_outputs
+                .insert(output_id, Vec::with_capacity(total_len));
+        }
+
+        let pending =
+            self.pending_outputs.get_mut(&output_id).with_context(|| {
+                format!(
+                    "Tried to write to offset {} of output {} without first \
+                     writing to offset 0",
+                    offset, output_id
+                )
+            })?;
+
+        anyhow::ensure!(
+            offset == pending.len() as u64,
+            "Expected the chunk at offset {} of output {}, but received \
+             offset {}",
+            pending.len(),
+            output_id,
+            offset
+        );
+        anyhow::ensure!(
+            pending.len() + data.len() <= total_len,
+            "Writing {} bytes at offset {} would overflow the {} byte output",
+            data.len(),
+            offset,
+            total_len
+        );
+
+        pending.extend_from_slice(data);
+
+        if pending.len() == total_len {
+            let buffer = self.pending_outputs.remove(&output_id).unwrap();
+            self.consume_output(output_id, &buffer)?;
+        }
+
+        Ok(())
+    }
+
+    /// The Rune is telling us the current value of one of a proc-block's
+    /// parameters.
+    pub fn rune_proc_block_parameter(
+        &mut self,
+        node: &str,
+        type_name: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        self.callbacks
+            .proc_block_parameter(node, type_name, name, value);
+        Ok(())
+    }
+
+    /// Copy any parameter updates into the Rune, returning the number of
+    /// bytes needed to hold them.
+    ///
+    /// If `buffer` is too small nothing is written and the Rune should call
+    /// this again with a buffer that is big enough. Each update is encoded as
+    /// its node, name, and value, with every string prefixed by its length as
+    /// a little-endian `u32`.
+    pub fn rune_proc_block_parameter_updates(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<u32, Error> {
+        let callbacks = &self.callbacks;
+        let encoded = self.pending_parameter_updates.get_or_insert_with(|| {
+            encode_parameter_updates(&callbacks.take_parameter_updates())
+        });
+        let len = encoded.len();
+
+        if len <= buffer.len() {
+            buffer[..len].copy_from_slice(encoded);
+            self.pending_parameter_updates = None;
+        }
+
+        u32::try_from(len).context("The parameter updates are too big")
+    }
+
     pub fn rune_resource_open(&mut self, name: &str) -> Result<u32, Error> {
         let resource = self
             .callbacks
@@ -270,3 +808,246 @@ impl HostFunctions {
         Ok(())
     }
 }
+
+fn encode_parameter_updates(updates: &[ParameterUpdate]) -> Vec<u8> {
+    let mut buffer = Vec::new();
+
+    for update in updates {
+        for s in &[&update.node, &update.name, &update.value] {
+            buffer.extend((s.len() as u32).to_le_bytes());
+            buffer.extend(s.as_bytes());
+        }
+    }
+
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use log::Level;
+
+    use super::*;
+
+    /// Callbacks which serve a fixed input and record every output.
+    #[derive(Default)]
+    struct Fixture {
+        input: Vec<u8>,
+        written: Mutex<Vec<Vec<u8>>>,
+        max_tensor_bytes: Option<usize>,
+        logs: Mutex<Vec<(Level, String, Option<String>)>>,
+        parameter_updates: Mutex<Vec<ParameterUpdate>>,
+    }
+
+    impl Callbacks for Fixture {
+        fn loaded(&self, _rune: &RuneGraph<'_>) -> Result<(), Error> { Ok(()) }
+
+        fn read_capability(
+            &self,
+            _id: u32,
+            _meta: &NodeMetadata,
+            buffer: &mut [u8],
+        ) -> Result<usize, Error> {
+            buffer.copy_from_slice(&self.input);
+            Ok(buffer.len())
+        }
+
+        fn write_output(
+            &self,
+            _id: u32,
+            _meta: &NodeMetadata,
+            data: &[u8],
+        ) -> Result<(), Error> {
+            self.written.lock().unwrap().push(data.to_vec());
+            Ok(())
+        }
+
+        fn load_model(
+            &self,
+            _id: u32,
+            _meta: &ModelMetadata<'_>,
+            _model: &[u8],
+        ) -> Result<Box<dyn Model>, Error> {
+            unimplemented!()
+        }
+
+        fn get_resource(&self, _name: &str) -> Option<&[u8]> { None }
+
+        fn max_tensor_bytes(&self) -> Option<usize> { self.max_tensor_bytes }
+
+        fn log(&self, record: &LogRecord<'_>) {
+            self.logs.lock().unwrap().push((
+                record.level,
+                record.message.to_string(),
+                record.node.map(String::from),
+            ));
+        }
+
+        fn take_parameter_updates(&self) -> Vec<ParameterUpdate> {
+            std::mem::take(&mut *self.parameter_updates.lock().unwrap())
+        }
+    }
+
+    fn host(fixture: Fixture) -> (Arc<Fixture>, HostFunctions) {
+        let fixture = Arc::new(fixture);
+        let host = HostFunctions::new(
+            Arc::clone(&fixture) as Arc<dyn Callbacks>,
+            AbiVersion::Current,
+        );
+        (fixture, host)
+    }
+
+    #[test]
+    fn copy_inputs_and_outputs_in_chunks() {
+        let (fixture, mut host) = host(Fixture {
+            input: vec![1, 2, 3, 4, 5],
+            ..Default::default()
+        });
+        let capability = host
+            .request_capability(hotg_rune_core::capabilities::RAW)
+            .unwrap();
+        let output = host
+            .request_output(hotg_rune_core::outputs::SERIAL)
+            .unwrap();
+        let mut first = [0_u8; 3];
+        let mut second = [0_u8; 3];
+
+        let a = host
+            .request_provider_response_chunk(capability, 0, 5, &mut first)
+            .unwrap();
+        let b = host
+            .request_provider_response_chunk(capability, 3, 5, &mut second)
+            .unwrap();
+        host.consume_output_chunk(output, 0, 5, &first).unwrap();
+        assert!(fixture.written.lock().unwrap().is_empty());
+        host.consume_output_chunk(output, 3, 5, &second[..2])
+            .unwrap();
+
+        assert_eq!((a, b), (3, 2));
+        assert_eq!(first, [1, 2, 3]);
+        assert_eq!(&second[..2], [4, 5]);
+        assert_eq!(*fixture.written.lock().unwrap(), vec![vec![1, 2, 3, 4, 5]]);
+    }
+
+    #[test]
+    fn tensors_must_fit_within_the_size_limit() {
+        let (_, mut host) = host(Fixture {
+            max_tensor_bytes: Some(4),
+            ..Default::default()
+        });
+        let output = host
+            .request_output(hotg_rune_core::outputs::SERIAL)
+            .unwrap();
+
+        let err = host
+            .consume_output_chunk(output, 0, 1 << 40, &[0; 4])
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "A 1099511627776 byte tensor would exceed the 4 byte tensor size \
+             limit"
+        );
+    }
+
+    #[test]
+    fn capabilities_and_outputs_can_be_named() {
+        let (_, mut host) = host(Fixture::default());
+        let capability = host
+            .request_capability(hotg_rune_core::capabilities::RAW)
+            .unwrap();
+        let output = host
+            .request_output(hotg_rune_core::outputs::SERIAL)
+            .unwrap();
+
+        host.rune_node_name(capability, "audio").unwrap();
+        host.rune_node_name(output, "serial").unwrap();
+
+        let names = host.graph().node_names.clone();
+        assert_eq!(names["audio"], capability);
+        assert_eq!(names["serial"], output);
+        assert!(host.rune_node_name(42, "model").is_err());
+    }
+
+    #[test]
+    fn legacy_nodes_are_named_after_their_kind() {
+        let fixture = Arc::new(Fixture::default());
+        let mut host = HostFunctions::new(
+            Arc::clone(&fixture) as Arc<dyn Callbacks>,
+            AbiVersion::Legacy,
+        );
+        let raw = host
+            .request_capability(hotg_rune_core::capabilities::RAW)
+            .unwrap();
+        let first = host
+            .request_output(hotg_rune_core::outputs::SERIAL)
+            .unwrap();
+        let second = host
+            .request_output(hotg_rune_core::outputs::SERIAL)
+            .unwrap();
+
+        host.loaded().unwrap();
+        host.debug("Plain text\n").unwrap();
+
+        let names = host.graph().node_names.clone();
+        assert_eq!(names["raw"], raw);
+        assert_eq!(names["serial"], first);
+        assert_eq!(names["serial_2"], second);
+        assert_eq!(
+            *fixture.logs.lock().unwrap(),
+            vec![(Level::Info, "Plain text".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn log_messages_say_which_node_they_came_from() {
+        let (fixture, host) = host(Fixture::default());
+
+        host.debug(r#"{"level":"WARN","message":"Clipping","target":"rune","module_path":null,"file":null,"line":null,"node":"normalize"}"#).unwrap();
+        // Older Runes don't send the node
+        host.debug(r#"{"level":"INFO","message":"Done","target":"rune","module_path":null,"file":null,"line":null}"#).unwrap();
+
+        assert_eq!(
+            *fixture.logs.lock().unwrap(),
+            vec![
+                (
+                    Level::Warn,
+                    "Clipping".to_string(),
+                    Some("normalize".into())
+                ),
+                (Level::Info, "Done".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parameter_updates_wait_for_a_big_enough_buffer() {
+        let update = ParameterUpdate {
+            node: "fft".to_string(),
+            name: "window".to_string(),
+            value: "hann".to_string(),
+        };
+        let (fixture, mut host) = host(Fixture {
+            parameter_updates: Mutex::new(vec![update]),
+            ..Default::default()
+        });
+        let mut small = [0_u8; 4];
+        let mut buffer = [0_u8; 32];
+
+        let needed =
+            host.rune_proc_block_parameter_updates(&mut small).unwrap();
+        assert!(fixture.parameter_updates.lock().unwrap().is_empty());
+        let written =
+            host.rune_proc_block_parameter_updates(&mut buffer).unwrap();
+        let empty =
+            host.rune_proc_block_parameter_updates(&mut buffer).unwrap();
+
+        assert_eq!(small, [0; 4]);
+        assert_eq!((needed, written, empty), (25, 25, 0));
+        assert_eq!(
+            &buffer[..25],
+            b"\x03\0\0\0fft\x06\0\0\0window\x04\0\0\0hann"
+        );
+    }
+}
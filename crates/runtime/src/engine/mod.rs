@@ -1,37 +1,438 @@
+mod abi;
+#[cfg(any(feature = "wasmer", feature = "wasmtime"))]
+mod cache;
 mod host_functions;
+mod native;
 #[cfg(feature = "wasm3")]
 mod wasm3;
 #[cfg(feature = "wasmer")]
 mod wasmer;
+#[cfg(feature = "wasmtime")]
+mod wasmtime;
 
-use std::sync::Arc;
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+    sync::Arc,
+};
 
-use anyhow::Error;
+use anyhow::{Context, Error};
+use wasmparser::{
+    ImportSectionEntryType, Parser, Payload, Validator, WasmFeatures,
+};
 
+pub use self::host_functions::HostFunctions;
 #[cfg(feature = "wasm3")]
 pub(crate) use self::wasm3::Wasm3Engine;
 #[cfg(feature = "wasmer")]
 pub(crate) use self::wasmer::WasmerEngine;
+#[cfg(feature = "wasi")]
+pub(crate) use self::wasmtime::WasiEngine;
+#[cfg(feature = "wasmtime")]
+pub(crate) use self::wasmtime::WasmtimeEngine;
+pub(crate) use self::{
+    abi::{detect as detect_abi, AbiVersion},
+    native::NativeEngine,
+};
+use crate::{callbacks::Model, cancellation::EngineInterrupt, MemoryStats};
 
-/// A WebAssembly virtual machine that links Rune with
-pub(crate) trait WebAssemblyEngine {
-    fn load(
-        wasm: &[u8],
-        callbacks: Arc<dyn crate::callbacks::Callbacks>,
-    ) -> Result<Self, LoadError>
-    where
-        Self: Sized;
+/// The module all host functions are imported from.
+pub const HOST_MODULE: &str = "env";
+
+/// Every function the runtime provides to a Rune.
+///
+/// This must match the `extern "C"` block in the `hotg-runicos-base-wasm`
+/// crate's `intrinsics` module (a test checks this), and each
+/// [`WebAssemblyEngine`] must link all of them (including the ones only used by
+/// older Runes) to the method with the same name on [`HostFunctions`].
+pub const HOST_FUNCTIONS: &[&str] = &[
+    "_debug",
+    "request_capability",
+    "request_capability_set_param",
+    "request_provider_response",
+    "request_provider_response_chunk",
+    "tfm_model_invoke",
+    "tfm_preload_model",
+    "rune_model_select_variant",
+    "rune_model_name",
+    "rune_model_load",
+    "rune_model_infer",
+    "request_output",
+    "request_output_set_param",
+    "rune_node_name",
+    "consume_output",
+    "consume_output_chunk",
+    "rune_resource_open",
+    "rune_resource_read",
+    "rune_resource_close",
+    "rune_proc_block_parameter",
+    "rune_proc_block_parameter_updates",
+    "rune_panic",
+    "rune_assertion_failed",
+];
+
+/// The modules WASI functions are imported from, for engines which support
+/// WASI.
+pub(crate) const WASI_MODULES: &[&str] =
+    &["wasi_snapshot_preview1", "wasi_unstable"];
+
+/// Scan the Rune's import section and make sure it only imports functions we
+/// know about, before any code gets instantiated.
+///
+/// When `allow_wasi` is set, anything from [`WASI_MODULES`] is accepted too
+/// and left for the engine to resolve.
+pub(crate) fn check_imports(
+    wasm: &[u8],
+    allow_wasi: bool,
+) -> Result<(), LoadError> {
+    let mut unknown = Vec::new();
+
+    for payload in Parser::default().parse_all(wasm) {
+        let payload = payload.context("Unable to parse the WebAssembly")?;
+
+        if let Payload::ImportSection(imports) = payload {
+            for import in imports {
+                let import =
+                    import.context("Unable to parse the import section")?;
+
+                if let ImportSectionEntryType::Function(_) = import.ty {
+                    let name = import.field.unwrap_or_default();
+                    let is_host_function = import.module == HOST_MODULE
+                        && HOST_FUNCTIONS.contains(&name);
+                    let is_wasi =
+                        allow_wasi && WASI_MODULES.contains(&import.module);
+
+                    if !is_host_function && !is_wasi {
+                        unknown.push(Import {
+                            module: import.module.to_string(),
+                            name: name.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(LoadError::UnknownImports(unknown))
+    }
+}
+
+/// Make sure the engine can run the Rune's instructions, checking for SIMD
+/// and 64-bit memories when the engine doesn't support them.
+pub(crate) fn check_features<E: LoadableEngine>(
+    wasm: &[u8],
+) -> Result<(), LoadError> {
+    if !E::supports_simd() && uses_simd(wasm) {
+        return Err(LoadError::SimdNotSupported);
+    }
+    if !E::supports_memory64() && uses_memory64(wasm) {
+        return Err(LoadError::Memory64NotSupported);
+    }
+
+    Ok(())
+}
+
+/// Was this module compiled for wasm64, where linear memory (and therefore
+/// every pointer passed to a host function) uses 64-bit addresses?
+pub(crate) fn uses_memory64(wasm: &[u8]) -> bool {
+    for payload in Parser::default().parse_all(wasm) {
+        match payload {
+            Ok(Payload::MemorySection(memories)) => {
+                for memory in memories.into_iter().flatten() {
+                    if memory.memory64 {
+                        return true;
+                    }
+                }
+            },
+            Ok(Payload::ImportSection(imports)) => {
+                for import in imports.into_iter().flatten() {
+                    if let ImportSectionEntryType::Memory(memory) = import.ty {
+                        if memory.memory64 {
+                            return true;
+                        }
+                    }
+                }
+            },
+            // Any parse errors will be reported by the engine itself.
+            Err(_) => return false,
+            _ => {},
+        }
+    }
+
+    false
+}
+
+/// Does this module use any instructions from the SIMD128 extension?
+fn uses_simd(wasm: &[u8]) -> bool {
+    let mut validator = Validator::new();
+    validator.wasm_features(WasmFeatures {
+        simd: false,
+        ..WasmFeatures::default()
+    });
+
+    // Any other validation errors will be reported by the engine itself.
+    match validator.validate_all(wasm) {
+        Ok(_) => false,
+        Err(e) => e.message().contains("SIMD"),
+    }
+}
+
+/// A function imported by a Rune.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+}
+
+impl Display for Import {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}", self.module, self.name)
+    }
+}
+
+/// The error returned when a Rune panics.
+///
+/// This can be retrieved from the [`anyhow::Error`] returned by
+/// [`crate::Runtime::predict()`] using [`anyhow::Error::downcast_ref()`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub struct TrapError {
+    /// The panic message.
+    pub message: String,
+    /// The stage being executed when the panic happened.
+    pub stage: Option<String>,
+    /// The file the panic happened in.
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Display for TrapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let TrapError {
+            message,
+            stage,
+            file,
+            line,
+            column,
+        } = self;
+
+        write!(f, "{}", message)?;
+
+        if let Some(stage) = stage {
+            write!(f, " in {} block", stage)?;
+        }
+
+        write!(f, " at {}:{}:{}", file, line, column)
+    }
+}
+
+/// The error returned when a `rune_assert!()` inside the Rune fails.
+///
+/// Like [`TrapError`], this can be retrieved from the [`anyhow::Error`]
+/// returned by [`crate::Runtime::predict()`] using
+/// [`anyhow::Error::downcast_ref()`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub struct AssertionError {
+    /// The expression that was checked (e.g. `"input.len() == 3"`).
+    pub expression: String,
+    /// An optional message explaining what went wrong.
+    pub message: Option<String>,
+    /// The values captured by the assertion and their `Debug`
+    /// representation (e.g. `("left", "2")`).
+    pub values: Vec<(String, String)>,
+    /// The stage being executed when the assertion failed.
+    pub stage: Option<String>,
+    /// The file containing the assertion.
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Display for AssertionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let AssertionError {
+            expression,
+            message,
+            values,
+            stage,
+            file,
+            line,
+            column,
+        } = self;
+
+        write!(f, "Assertion failed: {}", expression)?;
+
+        if let Some(message) = message {
+            write!(f, " ({})", message)?;
+        }
+
+        if let Some(stage) = stage {
+            write!(f, " in {} block", stage)?;
+        }
+
+        write!(f, " at {}:{}:{}", file, line, column)?;
+
+        for (name, value) in values {
+            write!(f, "\n  {}: {}", name, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The error returned when a Rune uses up the fuel it was given with
+/// [`crate::RuntimeBuilder::fuel_limit()`].
+///
+/// Like [`TrapError`], this can be retrieved from the [`anyhow::Error`]
+/// returned by [`crate::Runtime::predict()`] using
+/// [`anyhow::Error::downcast_ref()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("The Rune used up all {} units of its fuel", limit)]
+pub struct OutOfFuel {
+    /// How much fuel the Rune was given.
+    pub limit: u64,
+}
+
+/// The size of a WebAssembly page, in bytes.
+pub(crate) const WASM_PAGE_SIZE: usize = 64 * 1024;
+
+/// The error returned when a Rune tries to use more resources than the
+/// [`crate::RuntimeBuilder`] allowed.
+///
+/// Use [`ResourceExhausted::find()`] to check whether a prediction failed
+/// because of a resource limit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ResourceExhausted {
+    /// The Rune's linear memory would have grown past
+    /// [`crate::RuntimeBuilder::max_memory_pages()`].
+    #[error("The Rune tried to use more than {} pages of memory", pages)]
+    Memory { pages: u32 },
+    /// The Rune ran out of fuel (see [`OutOfFuel`]).
+    #[error("The Rune used up all {} units of its fuel", limit)]
+    Fuel { limit: u64 },
+}
+
+impl ResourceExhausted {
+    /// Find the resource limit which caused this error, if any.
+    pub fn find(error: &Error) -> Option<ResourceExhausted> {
+        if let Some(e) = error.downcast_ref::<ResourceExhausted>() {
+            return Some(*e);
+        }
 
+        error
+            .downcast_ref::<OutOfFuel>()
+            .map(|&OutOfFuel { limit }| ResourceExhausted::Fuel { limit })
+    }
+}
+
+/// A WebAssembly virtual machine that links Rune with the runtime.
+///
+/// Engines other than the ones in [`crate::Engine`] can be used by
+/// implementing this trait and passing a function which creates the engine to
+/// [`crate::RuntimeBuilder::custom_engine()`]. That function is given the
+/// Rune and the [`HostFunctions`] it should be linked against.
+///
+/// The engine is expected to:
+///
+/// - Import every function in [`HOST_FUNCTIONS`] from [`HOST_MODULE`], decoding
+///   the arguments from the Rune's linear memory and calling the
+///   [`HostFunctions`] method with the same name
+/// - Turn any errors returned by a host function into a trap, then hand the
+///   original error back from [`WebAssemblyEngine::init()`] or
+///   [`WebAssemblyEngine::predict()`] so the [`TrapError`]s and
+///   [`AssertionError`]s created by the runtime reach the caller
+/// - Call [`HostFunctions::loaded()`] after the Rune's `_manifest()` function
+///   returns
+pub trait WebAssemblyEngine {
     /// Call the `_manifest()` function to initialize the Rune graph.
     fn init(&mut self) -> Result<(), Error>;
 
     /// Call the `_call()` function to run the Rune.
     fn predict(&mut self) -> Result<(), Error>;
+
+    /// Give `action` access to one of the models loaded by the Rune.
+    fn with_model(
+        &mut self,
+        model_id: u32,
+        action: &mut dyn FnMut(&mut dyn Model) -> Result<(), Error>,
+    ) -> Result<(), Error>;
+
+    /// Swap one of the Rune's models for another, without the Rune noticing.
+    fn replace_model(
+        &mut self,
+        model_id: u32,
+        model: Box<dyn Model>,
+    ) -> Result<(), Error>;
+
+    /// The current size of the Rune's linear memory, in bytes.
+    fn memory_usage(&self) -> usize;
+
+    /// Ask the Rune for its allocator statistics, returning `None` if it
+    /// doesn't export [`MemoryStats::EXPORT`] (e.g. because it was compiled
+    /// by an older version of Rune).
+    fn memory_stats(&mut self) -> Result<Option<MemoryStats>, Error> {
+        Ok(None)
+    }
+
+    /// Get a function which stops the Rune in the middle of executing
+    /// WebAssembly, if the engine supports it.
+    ///
+    /// Calling a function that was interrupted must fail with a
+    /// [`crate::cancellation::Cancelled`] error, and an interrupt that
+    /// happens while the Rune isn't running must not affect later calls.
+    fn interrupter(&self) -> Option<EngineInterrupt> { None }
+}
+
+/// A [`WebAssemblyEngine`] which the runtime knows how to load by itself.
+pub(crate) trait LoadableEngine: WebAssemblyEngine + Sized {
+    /// Whether the engine can run Runes compiled with SIMD128 enabled.
+    fn supports_simd() -> bool;
+
+    /// Whether the engine can run Runes compiled for wasm64 (the memory64
+    /// proposal).
+    fn supports_memory64() -> bool { false }
+
+    /// Whether the engine provides the WASI functions in [`WASI_MODULES`].
+    fn supports_wasi() -> bool { false }
+
+    /// Whether the engine can bound how much work the Rune does using
+    /// [`crate::callbacks::Callbacks::fuel_limit()`].
+    fn supports_fuel() -> bool { false }
+
+    /// Load a Rune, optionally reusing compiled code from `cache_dir`.
+    fn load(
+        wasm: &[u8],
+        callbacks: Arc<dyn crate::callbacks::Callbacks>,
+        cache_dir: Option<&Path>,
+    ) -> Result<Self, LoadError>;
 }
 
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum LoadError {
+    /// The Rune imports functions that the runtime doesn't provide.
+    #[error(
+        "The Rune imports unknown host functions: {}",
+        join(.0)
+    )]
+    UnknownImports(Vec<Import>),
+    /// The Rune was compiled with SIMD instructions that the engine can't
+    /// run.
+    #[error(
+        "The Rune uses WebAssembly SIMD instructions, which this engine \
+         doesn't support. Try a scalar build of the Rune or another engine"
+    )]
+    SimdNotSupported,
+    /// The Rune was compiled for wasm64, which the engine can't run.
+    #[error(
+        "The Rune uses 64-bit WebAssembly memory (memory64), which this \
+         engine doesn't support. Try the Wasmtime engine"
+    )]
+    Memory64NotSupported,
     #[error(transparent)]
     Other(#[from] anyhow::Error),
     #[error(transparent)]
@@ -41,3 +442,176 @@ pub enum LoadError {
     #[cfg(feature = "wasmer")]
     WasmerCompile(#[from] ::wasmer::CompileError),
 }
+
+fn join(imports: &[Import]) -> String {
+    imports
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module_with_imports(imports: &[(&str, &str)]) -> Vec<u8> {
+        let imports: String = imports
+            .iter()
+            .map(|(module, name)| {
+                format!("(import \"{}\" \"{}\" (func))", module, name)
+            })
+            .collect();
+
+        wat::parse_str(format!("(module {})", imports)).unwrap()
+    }
+
+    #[test]
+    fn trap_error_mentions_the_stage() {
+        let mut error = TrapError {
+            message: "index out of bounds".to_string(),
+            stage: Some("label".to_string()),
+            file: "src/lib.rs".to_string(),
+            line: 42,
+            column: 9,
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "index out of bounds in label block at src/lib.rs:42:9"
+        );
+
+        error.stage = None;
+        assert_eq!(error.to_string(), "index out of bounds at src/lib.rs:42:9");
+    }
+
+    #[test]
+    fn assertion_error_lists_the_values() {
+        let error = AssertionError {
+            expression: "left == right".to_string(),
+            message: Some("lengths differ".to_string()),
+            values: vec![
+                ("left".to_string(), "2".to_string()),
+                ("right".to_string(), "3".to_string()),
+            ],
+            stage: Some("fft".to_string()),
+            file: "src/lib.rs".to_string(),
+            line: 7,
+            column: 5,
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "Assertion failed: left == right (lengths differ) in fft block at \
+             src/lib.rs:7:5\n  left: 2\n  right: 3"
+        );
+    }
+
+    #[test]
+    fn detect_simd_instructions() {
+        let scalar =
+            wat::parse_str("(module (func (result i32) i32.const 1))").unwrap();
+        let simd = wat::parse_str(
+            "(module (func (result v128) v128.const i32x4 1 2 3 4))",
+        )
+        .unwrap();
+
+        assert!(!uses_simd(&scalar));
+        assert!(uses_simd(&simd));
+    }
+
+    #[test]
+    fn detect_64_bit_memories() {
+        let wasm32 = wat::parse_str("(module (memory 1))").unwrap();
+        let wasm64 = wat::parse_str("(module (memory i64 1))").unwrap();
+        let imported = wat::parse_str(
+            r#"(module (import "env" "memory" (memory i64 1)))"#,
+        )
+        .unwrap();
+
+        assert!(!uses_memory64(&wasm32));
+        assert!(uses_memory64(&wasm64));
+        assert!(uses_memory64(&imported));
+    }
+
+    #[test]
+    fn known_imports_are_allowed() {
+        let wasm = module_with_imports(&[
+            ("env", "_debug"),
+            ("env", "rune_model_load"),
+        ]);
+
+        check_imports(&wasm, false).unwrap();
+    }
+
+    #[test]
+    fn wasi_imports_are_allowed_when_the_engine_supports_them() {
+        let wasm = module_with_imports(&[
+            ("env", "_debug"),
+            ("wasi_snapshot_preview1", "fd_write"),
+        ]);
+
+        check_imports(&wasm, true).unwrap();
+        assert!(check_imports(&wasm, false).is_err());
+    }
+
+    #[test]
+    fn unknown_imports_are_reported() {
+        let wasm = module_with_imports(&[
+            ("env", "_debug"),
+            ("env", "open_socket"),
+            ("wasi_snapshot_preview1", "fd_write"),
+        ]);
+
+        match check_imports(&wasm, false).unwrap_err() {
+            LoadError::UnknownImports(imports) => {
+                let names: Vec<_> =
+                    imports.iter().map(|i| i.to_string()).collect();
+                assert_eq!(
+                    names,
+                    ["env::open_socket", "wasi_snapshot_preview1::fd_write"]
+                );
+            },
+            other => panic!("Unexpected error: {}", other),
+        }
+    }
+
+    #[test]
+    fn out_of_fuel_is_a_resource_limit() {
+        let error = Error::msg("trap").context(OutOfFuel { limit: 42 });
+        assert_eq!(
+            ResourceExhausted::find(&error),
+            Some(ResourceExhausted::Fuel { limit: 42 })
+        );
+
+        let error = Error::from(ResourceExhausted::Memory { pages: 16 })
+            .context("Unable to run the Rune");
+        assert_eq!(
+            ResourceExhausted::find(&error),
+            Some(ResourceExhausted::Memory { pages: 16 })
+        );
+
+        assert_eq!(ResourceExhausted::find(&Error::msg("oops")), None);
+    }
+
+    #[test]
+    fn host_functions_match_the_intrinsics() {
+        let crate_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let intrinsics =
+            crate_dir.join("../../images/runicos-base/wasm/src/intrinsics.rs");
+        let src = std::fs::read_to_string(&intrinsics).unwrap();
+
+        let (_, extern_block) = src.split_once("extern \"C\" {").unwrap();
+        let (extern_block, _) = extern_block.split_once("\n}").unwrap();
+        let mut declared: Vec<&str> = extern_block
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("pub fn "))
+            .filter_map(|line| line.split('(').next())
+            .collect();
+        declared.sort_unstable();
+        let mut linked = HOST_FUNCTIONS.to_vec();
+        linked.sort_unstable();
+
+        assert_eq!(linked, declared);
+    }
+}
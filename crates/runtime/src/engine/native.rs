@@ -0,0 +1,1069 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    num::NonZeroUsize,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{Context, Error};
+use hotg_rune_core::{Shape, TFLITE_MIMETYPE};
+
+use crate::{
+    callbacks::{Callbacks, Model, ModelMetadata, RuneGraph},
+    engine::{LoadError, LoadableEngine, WebAssemblyEngine},
+    native::{Graph, NativePipeline, ProcBlock},
+    ElementType, NodeMetadata, Tensor, TensorElement,
+};
+
+/// An "engine" which runs a [`NativePipeline`] directly instead of
+/// interpreting WebAssembly.
+pub(crate) struct NativeEngine {
+    callbacks: Arc<dyn Callbacks>,
+    pipeline: NativePipeline,
+    capabilities: HashMap<u32, NodeMetadata>,
+    outputs: HashMap<u32, NodeMetadata>,
+    node_names: HashMap<String, u32>,
+    models: HashMap<u32, Box<dyn Model>>,
+    proc_blocks: Vec<Box<dyn ProcBlock>>,
+    /// Every node, grouped into waves which only depend on the nodes from
+    /// earlier waves.
+    waves: Vec<Vec<Step>>,
+    /// The bytes used by the intermediate tensors from the last run.
+    memory_usage: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Capability {
+        id: u32,
+        outputs: Vec<String>,
+    },
+    Model {
+        id: u32,
+        inputs: Vec<String>,
+        outputs: Vec<String>,
+        condition: Option<String>,
+    },
+    ProcBlock {
+        index: usize,
+        name: String,
+        inputs: Vec<String>,
+        outputs: Vec<String>,
+        condition: Option<String>,
+    },
+    Output {
+        id: u32,
+        inputs: Vec<String>,
+        condition: Option<String>,
+    },
+}
+
+impl Step {
+    fn inputs(&self) -> &[String] {
+        match self {
+            Step::Capability { .. } => &[],
+            Step::Model { inputs, .. }
+            | Step::ProcBlock { inputs, .. }
+            | Step::Output { inputs, .. } => inputs,
+        }
+    }
+
+    fn outputs(&self) -> &[String] {
+        match self {
+            Step::Capability { outputs, .. }
+            | Step::Model { outputs, .. }
+            | Step::ProcBlock { outputs, .. } => outputs,
+            Step::Output { .. } => &[],
+        }
+    }
+
+    /// The `enabled-when` tensor which decides whether this step runs.
+    fn condition(&self) -> Option<&str> {
+        match self {
+            Step::Capability { .. } => None,
+            Step::Model { condition, .. }
+            | Step::ProcBlock { condition, .. }
+            | Step::Output { condition, .. } => condition.as_deref(),
+        }
+    }
+
+    /// Every tensor which needs to be produced before this step can run.
+    fn dependencies(&self) -> impl Iterator<Item = &str> {
+        self.inputs()
+            .iter()
+            .map(|s| s.as_str())
+            .chain(self.condition())
+    }
+}
+
+/// Hands out IDs the same way a compiled Rune does, with one counter shared
+/// by every capability, model, output, and resource.
+///
+/// A resource only gets an ID the first time it is opened.
+#[derive(Debug, Default)]
+struct Ids {
+    last: u32,
+    opened_resources: HashSet<String>,
+}
+
+impl Ids {
+    fn next(&mut self) -> u32 {
+        self.last += 1;
+        self.last
+    }
+
+    fn open_resource(&mut self, name: &str) {
+        if self.opened_resources.insert(name.to_string()) {
+            self.next();
+        }
+    }
+}
+
+impl NativeEngine {
+    pub(crate) fn new(
+        pipeline: NativePipeline,
+        callbacks: Arc<dyn Callbacks>,
+    ) -> Self {
+        NativeEngine {
+            callbacks,
+            pipeline,
+            capabilities: HashMap::new(),
+            outputs: HashMap::new(),
+            node_names: HashMap::new(),
+            models: HashMap::new(),
+            proc_blocks: Vec::new(),
+            waves: Vec::new(),
+            memory_usage: 0,
+        }
+    }
+
+    /// Get a string argument, replacing `$resource` references with the
+    /// resource's contents.
+    fn argument(
+        &self,
+        value: &serde_json::Value,
+        ids: &mut Ids,
+    ) -> Result<String, Error> {
+        let value = match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+
+        match value.strip_prefix('$') {
+            Some(name) => {
+                let data =
+                    self.callbacks.get_resource(name).with_context(|| {
+                        format!("The \"{}\" resource wasn't provided", name)
+                    })?;
+                ids.open_resource(name);
+                let text = std::str::from_utf8(data).with_context(|| {
+                    format!("The \"{}\" resource isn't valid UTF-8", name)
+                })?;
+                Ok(text.to_string())
+            },
+            None => Ok(value),
+        }
+    }
+
+    fn read_model(&self, file: &str, ids: &mut Ids) -> Result<Vec<u8>, Error> {
+        match file.strip_prefix('$') {
+            Some(name) => {
+                let data =
+                    self.callbacks.get_resource(name).with_context(|| {
+                        format!("The \"{}\" resource wasn't provided", name)
+                    })?;
+                ids.open_resource(name);
+                Ok(data.to_vec())
+            },
+            None => {
+                let path = self.pipeline.model_dir.join(file);
+                std::fs::read(&path).with_context(|| {
+                    format!("Unable to read \"{}\"", path.display())
+                })
+            },
+        }
+    }
+
+    fn shapes(&self, tensors: &[String]) -> Result<Vec<Shape<'static>>, Error> {
+        tensors
+            .iter()
+            .map(|name| {
+                self.pipeline.graph.tensors.get(name).cloned().with_context(
+                    || format!("The pipeline doesn't define tensor {}", name),
+                )
+            })
+            .collect()
+    }
+
+    fn metadata(
+        &self,
+        kind: String,
+        args: &BTreeMap<String, serde_json::Value>,
+        ids: &mut Ids,
+    ) -> Result<NodeMetadata, Error> {
+        let mut arguments = HashMap::new();
+
+        for (key, value) in args {
+            arguments.insert(key.clone(), self.argument(value, ids)?);
+        }
+
+        Ok(NodeMetadata { kind, arguments })
+    }
+
+    fn run(&mut self) -> Result<(), Error> {
+        let mut tensors: HashMap<&str, Tensor> = HashMap::new();
+        // Tensors which weren't produced because their step was disabled
+        let mut skipped: HashSet<&str> = HashSet::new();
+        let NativeEngine {
+            callbacks,
+            pipeline,
+            capabilities,
+            outputs: output_metadata,
+            node_names: _,
+            models,
+            proc_blocks,
+            waves,
+            memory_usage,
+        } = self;
+        let graph = &pipeline.graph;
+        let scheduler = callbacks.scheduler();
+
+        apply_parameter_updates(&**callbacks, waves, proc_blocks)?;
+
+        for wave in waves.iter() {
+            // The models and proc-blocks in this wave are independent of each
+            // other, so they can be run on the thread pool
+            let mut jobs = Vec::new();
+            let mut available_models: HashMap<u32, &mut Box<dyn Model>> =
+                models.iter_mut().map(|(id, model)| (*id, model)).collect();
+            let mut available_proc_blocks: Vec<
+                Option<&mut Box<dyn ProcBlock>>,
+            > = proc_blocks.iter_mut().map(Some).collect();
+
+            for step in wave {
+                callbacks.check_cancelled()?;
+
+                // Like the compiled Rune, anything downstream of a disabled
+                // step is skipped too
+                let enabled = step.condition().map_or(true, |name| {
+                    tensors.get(name).map_or(false, is_enabled)
+                });
+                if !enabled || step.dependencies().any(|t| skipped.contains(t))
+                {
+                    skipped.extend(step.outputs().iter().map(|s| s.as_str()));
+                    continue;
+                }
+
+                let inputs = step
+                    .inputs()
+                    .iter()
+                    .map(|name| {
+                        tensors.get(name.as_str()).cloned().with_context(|| {
+                            format!("Tensor {} was never produced", name)
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // Capabilities and outputs call back into the host, so they
+                // always run on the current thread
+                let job = match step {
+                    Step::Capability { id, outputs } => {
+                        let meta = &capabilities[id];
+                        let mut tensor = zeroed(graph, &outputs[0])?;
+                        callbacks.read_capability(
+                            *id,
+                            meta,
+                            tensor.buffer_mut(),
+                        )?;
+                        tensors.insert(outputs[0].as_str(), tensor);
+                        continue;
+                    },
+                    Step::Output { id, .. } => {
+                        let data = serialize_for_output(&inputs)?;
+                        callbacks.write_output(
+                            *id,
+                            &output_metadata[id],
+                            &data,
+                        )?;
+                        continue;
+                    },
+                    Step::Model { id, .. } => {
+                        let model = available_models
+                            .remove(id)
+                            .expect("Models are loaded during init");
+                        Job::Model(&mut **model)
+                    },
+                    Step::ProcBlock { index, .. } => {
+                        let proc_block = available_proc_blocks[*index]
+                            .take()
+                            .expect("Each proc-block only appears once");
+                        Job::ProcBlock(&mut **proc_block)
+                    },
+                };
+
+                jobs.push((step, job, inputs));
+            }
+
+            let results = scheduler.map(jobs, |(step, job, inputs)| {
+                job.run(step, graph, inputs).map(|results| (step, results))
+            });
+
+            for result in results {
+                let (step, results) = result?;
+
+                for (name, tensor) in step.outputs().iter().zip(results) {
+                    tensors.insert(name.as_str(), tensor);
+                }
+            }
+        }
+
+        *memory_usage = tensors.values().map(|t| t.buffer().len()).sum();
+
+        Ok(())
+    }
+}
+
+/// A model or proc-block which is ready to run.
+enum Job<'a> {
+    Model(&'a mut dyn Model),
+    ProcBlock(&'a mut dyn ProcBlock),
+}
+
+impl Job<'_> {
+    fn run(
+        self,
+        step: &Step,
+        graph: &Graph,
+        inputs: Vec<Tensor>,
+    ) -> Result<Vec<Tensor>, Error> {
+        match (self, step) {
+            (Job::Model(model), Step::Model { outputs, .. }) => {
+                let mut results = outputs
+                    .iter()
+                    .map(|name| zeroed(graph, name))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let input_buffers: Vec<&[u8]> =
+                    inputs.iter().map(|t| t.buffer()).collect();
+                let mut output_buffers: Vec<&mut [u8]> =
+                    results.iter_mut().map(|t| t.buffer_mut()).collect();
+
+                model.infer(&input_buffers, &mut output_buffers)?;
+
+                Ok(results)
+            },
+            (
+                Job::ProcBlock(proc_block),
+                Step::ProcBlock { name, outputs, .. },
+            ) => {
+                let results =
+                    proc_block.transform(inputs).with_context(|| {
+                        format!("The \"{}\" proc-block failed", name)
+                    })?;
+
+                if results.len() != outputs.len() {
+                    anyhow::bail!(
+                        "The \"{}\" proc-block should have {} outputs, but it \
+                         returned {}",
+                        name,
+                        outputs.len(),
+                        results.len()
+                    );
+                }
+
+                Ok(results)
+            },
+            _ => unreachable!("Jobs are created from their step"),
+        }
+    }
+}
+
+impl LoadableEngine for NativeEngine {
+    fn supports_simd() -> bool { true }
+
+    fn load(
+        _wasm: &[u8],
+        _callbacks: Arc<dyn Callbacks>,
+        _cache_dir: Option<&Path>,
+    ) -> Result<Self, LoadError> {
+        Err(LoadError::Other(Error::msg(
+            "Native pipelines are loaded with RuntimeBuilder::native()",
+        )))
+    }
+}
+
+impl WebAssemblyEngine for NativeEngine {
+    fn init(&mut self) -> Result<(), Error> {
+        let graph = self.pipeline.graph.clone();
+        let mut ids = Ids::default();
+        let mut steps = Vec::new();
+
+        // Note: nodes are initialized in the same order as the compiled
+        // Rune's manifest so they get the same IDs
+
+        for (name, capability) in &graph.capabilities {
+            let id = ids.next();
+
+            let kind = crate::registry::capability_kind(&capability.kind.ty);
+            let meta = self.metadata(kind, &capability.args, &mut ids)?;
+            self.capabilities.insert(id, meta);
+            self.node_names.insert(name.clone(), id);
+
+            if capability.outputs.len() != 1 {
+                anyhow::bail!(
+                    "The \"{}\" capability should have exactly 1 output",
+                    name
+                );
+            }
+
+            steps.push(Step::Capability {
+                id,
+                outputs: capability.outputs.clone(),
+            });
+        }
+
+        for (name, proc_block) in &graph.proc_blocks {
+            let key = proc_block.registry_key();
+            let factory =
+                self.pipeline.proc_blocks.get(&key).with_context(|| {
+                    format!(
+                        "No native implementation was registered for the \
+                         \"{}\" proc-block used by \"{}\"",
+                        key, name
+                    )
+                })?;
+            let mut instance = factory();
+            // Like a compiled Rune, the defaults are reported before any of
+            // the Runefile's arguments are applied
+            report_parameters(&*self.callbacks, name, &*instance);
+
+            for (key, value) in &proc_block.args {
+                let value = self.argument(value, &mut ids)?;
+                instance.set_argument(key, &value).with_context(|| {
+                    format!(
+                        "Unable to set the \"{}\" argument on \"{}\"",
+                        key, name
+                    )
+                })?;
+            }
+            report_parameters(&*self.callbacks, name, &*instance);
+
+            steps.push(Step::ProcBlock {
+                index: self.proc_blocks.len(),
+                name: name.clone(),
+                inputs: proc_block.inputs.clone(),
+                outputs: proc_block.outputs.clone(),
+                condition: proc_block.enabled_when.clone(),
+            });
+            self.proc_blocks.push(instance);
+        }
+
+        for (name, model) in &graph.models {
+            let inputs = self.shapes(&model.inputs)?;
+            let outputs = self.shapes(&model.outputs)?;
+            let mimetype = if model.mimetype.is_empty() {
+                TFLITE_MIMETYPE
+            } else {
+                model.mimetype.as_str()
+            };
+            // The model's resource is opened before the model gets its ID
+            let data = self.read_model(&model.file, &mut ids)?;
+            let id = ids.next();
+
+            let meta = ModelMetadata {
+                mimetype,
+                inputs: &inputs,
+                outputs: &outputs,
+                variant: None,
+                cache_dir: None,
+                node: Some(name),
+            };
+            let loaded =
+                self.callbacks.load_model(id, &meta, &data).with_context(
+                    || format!("Unable to load the \"{}\" model", name),
+                )?;
+            self.models.insert(id, loaded);
+
+            steps.push(Step::Model {
+                id,
+                inputs: model.inputs.clone(),
+                outputs: model.outputs.clone(),
+                condition: model.enabled_when.clone(),
+            });
+        }
+
+        for (name, output) in &graph.outputs {
+            let id = ids.next();
+
+            let meta = self.metadata(
+                output.kind.ty.to_uppercase(),
+                &output.args,
+                &mut ids,
+            )?;
+            self.outputs.insert(id, meta);
+            self.node_names.insert(name.clone(), id);
+
+            steps.push(Step::Output {
+                id,
+                inputs: output.inputs.clone(),
+                condition: output.enabled_when.clone(),
+            });
+        }
+
+        self.waves = execution_waves(steps)?;
+
+        self.callbacks.loaded(&RuneGraph {
+            capabilities: &self.capabilities,
+            outputs: &self.outputs,
+            node_names: &self.node_names,
+        })
+    }
+
+    fn predict(&mut self) -> Result<(), Error> { self.run() }
+
+    fn with_model(
+        &mut self,
+        model_id: u32,
+        action: &mut dyn FnMut(&mut dyn Model) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let model = self.models.get_mut(&model_id).with_context(|| {
+            format!("There is no model with ID {}", model_id)
+        })?;
+
+        action(model.as_mut())
+    }
+
+    fn replace_model(
+        &mut self,
+        model_id: u32,
+        model: Box<dyn Model>,
+    ) -> Result<(), Error> {
+        let slot = self.models.get_mut(&model_id).with_context(|| {
+            format!("There is no model with ID {}", model_id)
+        })?;
+        *slot = model;
+
+        Ok(())
+    }
+
+    fn memory_usage(&self) -> usize { self.memory_usage }
+}
+
+/// Tell the host about each of a proc-block's parameters.
+fn report_parameters(
+    callbacks: &dyn Callbacks,
+    node: &str,
+    proc_block: &dyn ProcBlock,
+) {
+    if let Some(type_name) = proc_block.type_name() {
+        for (name, value) in proc_block.parameters() {
+            callbacks.proc_block_parameter(node, &type_name, &name, &value);
+        }
+    }
+}
+
+/// Pass any changes from [`crate::Runtime::set_proc_block_parameter()`] to
+/// the proc-blocks they were meant for.
+fn apply_parameter_updates(
+    callbacks: &dyn Callbacks,
+    waves: &[Vec<Step>],
+    proc_blocks: &mut [Box<dyn ProcBlock>],
+) -> Result<(), Error> {
+    for update in callbacks.take_parameter_updates() {
+        let index = waves.iter().flatten().find_map(|step| match step {
+            Step::ProcBlock { index, name, .. } if *name == update.node => {
+                Some(*index)
+            },
+            _ => None,
+        });
+        let proc_block = match index {
+            Some(index) => &mut proc_blocks[index],
+            None => continue,
+        };
+
+        proc_block
+            .set_argument(&update.name, &update.value)
+            .with_context(|| {
+                format!(
+                    "Unable to set the \"{}\" parameter on \"{}\"",
+                    update.name, update.node
+                )
+            })?;
+        report_parameters(callbacks, &update.node, &**proc_block);
+    }
+
+    Ok(())
+}
+
+/// Group the steps into "waves" (the same as an
+/// [`crate::scheduler::ExecutionPlan`]),
+/// where each step only depends on steps from earlier waves.
+fn execution_waves(mut pending: Vec<Step>) -> Result<Vec<Vec<Step>>, Error> {
+    let mut available: HashSet<String> = HashSet::new();
+    let mut waves = Vec::new();
+
+    while !pending.is_empty() {
+        let (ready, blocked): (Vec<_>, Vec<_>) =
+            pending.into_iter().partition(|step| {
+                step.dependencies().all(|t| available.contains(t))
+            });
+
+        if ready.is_empty() {
+            anyhow::bail!(
+                "The pipeline contains a cycle or uses a tensor nothing \
+                 produces"
+            );
+        }
+
+        available.extend(ready.iter().flat_map(Step::outputs).cloned());
+        waves.push(ready);
+        pending = blocked;
+    }
+
+    Ok(waves)
+}
+
+/// Should a step guarded by this `enabled-when` tensor run? This matches the
+/// compiled Rune, where any element that isn't the default enables it.
+fn is_enabled(tensor: &Tensor) -> bool {
+    fn any_set<T>(tensor: &Tensor) -> bool
+    where
+        T: TensorElement + Default + PartialEq,
+    {
+        let zero = T::default();
+        tensor
+            .elements::<T>()
+            .map_or(false, |elements| elements.iter().any(|e| *e != zero))
+    }
+
+    match tensor.element_type() {
+        ElementType::U8 => any_set::<u8>(tensor),
+        ElementType::I8 => any_set::<i8>(tensor),
+        ElementType::U16 => any_set::<u16>(tensor),
+        ElementType::I16 => any_set::<i16>(tensor),
+        ElementType::U32 => any_set::<u32>(tensor),
+        ElementType::I32 => any_set::<i32>(tensor),
+        ElementType::F32 => any_set::<f32>(tensor),
+        ElementType::U64 => any_set::<u64>(tensor),
+        ElementType::I64 => any_set::<i64>(tensor),
+        ElementType::F64 => any_set::<f64>(tensor),
+        ElementType::F16 => any_set::<half::f16>(tensor),
+        ElementType::BF16 => any_set::<half::bf16>(tensor),
+        ElementType::Utf8 => tensor
+            .strings()
+            .map_or(false, |strings| strings.iter().any(|s| !s.is_empty())),
+    }
+}
+
+fn zeroed(graph: &Graph, tensor: &str) -> Result<Tensor, Error> {
+    let shape = graph.tensors.get(tensor).with_context(|| {
+        format!("The pipeline doesn't define tensor {}", tensor)
+    })?;
+    let element_type = element_type(shape.element_type())
+        .with_context(|| format!("Unsupported tensor type, {}", shape))?;
+    let dimensions = shape
+        .dimensions()
+        .iter()
+        .map(|&d| NonZeroUsize::new(d))
+        .collect::<Option<Vec<_>>>()
+        .with_context(|| format!("Tensors can't be empty, found {}", shape))?;
+
+    Ok(Tensor::zeroed(element_type, dimensions))
+}
+
+fn element_type(ty: hotg_rune_core::ElementType) -> Option<ElementType> {
+    use hotg_rune_core::ElementType as Core;
+
+    match ty {
+        Core::U8 => Some(ElementType::U8),
+        Core::I8 => Some(ElementType::I8),
+        Core::U16 => Some(ElementType::U16),
+        Core::I16 => Some(ElementType::I16),
+        Core::U32 => Some(ElementType::U32),
+        Core::I32 => Some(ElementType::I32),
+        Core::F32 => Some(ElementType::F32),
+        Core::U64 => Some(ElementType::U64),
+        Core::I64 => Some(ElementType::I64),
+        Core::F64 => Some(ElementType::F64),
+        Core::F16 => Some(ElementType::F16),
+        Core::BF16 => Some(ElementType::BF16),
+        Core::String => Some(ElementType::Utf8),
+    }
+}
+
+/// Encode tensors the same way a Rune's `SERIAL` output does.
+fn serialize_for_output(tensors: &[Tensor]) -> Result<Vec<u8>, Error> {
+    let tensors = tensors
+        .iter()
+        .map(|tensor| {
+            let mut value = serde_json::to_value(tensor.serializable())?;
+            if let Some(object) = value.as_object_mut() {
+                if let Some(ty) = object.remove("element-type") {
+                    object.insert("type_name".to_string(), ty);
+                }
+            }
+            Ok(value)
+        })
+        .collect::<Result<Vec<_>, serde_json::Error>>()?;
+
+    serde_json::to_vec(&tensors).map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use hotg_rune_proc_blocks::Transform;
+
+    use super::*;
+    use crate::{native::Transformer, OutputTensor, Runtime};
+
+    #[derive(Default, hotg_rune_proc_block_macros::ProcBlock)]
+    struct Scale {
+        factor: f32,
+    }
+
+    impl Transform<hotg_rune_core::Tensor<f32>> for Scale {
+        type Output = hotg_rune_core::Tensor<f32>;
+
+        fn transform(
+            &mut self,
+            input: hotg_rune_core::Tensor<f32>,
+        ) -> Self::Output {
+            input.map(|_, &value| value * self.factor)
+        }
+    }
+
+    /// A pipeline where `scale` only runs when the `flag` input is set.
+    fn conditional_pipeline() -> NativePipeline {
+        // Note: a raw string so the nodes aren't sorted by name
+        let json = r#"{
+            "capabilities": {
+                "value": {
+                    "kind": { "type": "raw" },
+                    "args": { "name": "value" },
+                    "outputs": ["0"]
+                },
+                "flag": {
+                    "kind": { "type": "raw" },
+                    "args": { "name": "flag" },
+                    "outputs": ["1"]
+                }
+            },
+            "proc-blocks": {
+                "scale": {
+                    "path": "hotg-ai/rune@v0.11.3#proc_blocks/scale",
+                    "args": { "factor": "2.5" },
+                    "inputs": ["0"],
+                    "outputs": ["2"],
+                    "enabled_when": "1"
+                }
+            },
+            "outputs": {
+                "scaled": {
+                    "kind": { "type": "serial" },
+                    "args": { "name": "scaled" },
+                    "inputs": ["2"]
+                },
+                "original": {
+                    "kind": { "type": "serial" },
+                    "args": { "name": "original" },
+                    "inputs": ["0"]
+                }
+            },
+            "tensors": {
+                "0": { "element_type": "F32", "dimensions": [2] },
+                "1": { "element_type": "U8", "dimensions": [1] },
+                "2": { "element_type": "F32", "dimensions": [2] }
+            }
+        }"#;
+
+        NativePipeline::from_graph(json.as_bytes())
+            .unwrap()
+            .transform::<Scale, _>("hotg-ai/rune#proc_blocks/scale")
+    }
+
+    #[test]
+    fn nodes_get_the_same_ids_as_the_compiled_rune() {
+        let runtime = Runtime::builder()
+            .native(conditional_pipeline())
+            .build()
+            .unwrap();
+
+        // Graph order, not alphabetical, with one counter for every node
+        let names = |nodes: &HashMap<u32, NodeMetadata>| {
+            let mut names: Vec<_> = nodes
+                .iter()
+                .map(|(id, meta)| (*id, meta.arguments["name"].clone()))
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(runtime.capabilities()),
+            [(1, "value".to_string()), (2, "flag".to_string())]
+        );
+        assert_eq!(
+            names(runtime.outputs()),
+            [(3, "scaled".to_string()), (4, "original".to_string())]
+        );
+    }
+
+    #[test]
+    fn disabled_nodes_and_everything_downstream_are_skipped() {
+        let mut runtime = Runtime::builder()
+            .native(conditional_pipeline())
+            .build()
+            .unwrap();
+        let value = Tensor::new(&[1.0_f32, 2.0], &[2]);
+        runtime.input_tensors().insert(1, value.clone());
+
+        runtime
+            .input_tensors()
+            .insert(2, Tensor::new(&[0_u8], &[1]));
+        runtime.predict().unwrap();
+
+        let outputs = runtime.output_tensors();
+        assert!(!outputs.contains_key(&3));
+        assert_eq!(outputs[&4], vec![OutputTensor::Tensor(value.clone())]);
+
+        runtime.input_tensors().insert(1, value);
+        runtime
+            .input_tensors()
+            .insert(2, Tensor::new(&[1_u8], &[1]));
+        runtime.predict().unwrap();
+
+        let scaled = Tensor::new(&[2.5_f32, 5.0], &[2]);
+        assert_eq!(
+            runtime.output_tensors()[&3],
+            vec![OutputTensor::Tensor(scaled)]
+        );
+    }
+
+    #[test]
+    fn transform_arguments_use_the_generated_setters() {
+        let mut scale = Transformer::<Scale, _>::new(Scale::default());
+
+        scale.set_argument("factor", "3").unwrap();
+        assert!(scale.set_argument("factor", "three").is_err());
+        assert!(scale.set_argument("offset", "1").is_err());
+
+        let outputs = scale
+            .transform(vec![Tensor::new(&[1.0_f32, 2.0], &[1, 2])])
+            .unwrap();
+        assert_eq!(outputs, vec![Tensor::new(&[3.0_f32, 6.0], &[1, 2])]);
+        assert!(scale.transform(vec![Tensor::new(&[1_u8], &[1])]).is_err());
+    }
+
+    #[test]
+    fn parameters_can_be_changed_between_runs() {
+        let mut runtime = Runtime::builder()
+            .native(conditional_pipeline())
+            .build()
+            .unwrap();
+        let scale = &runtime.proc_block_nodes()["scale"];
+        assert_eq!(scale.kind, "Scale");
+        assert_eq!(scale.arguments["factor"], "2.5");
+        // The default comes from Scale's Default impl, not the Runefile
+        let factor = &runtime.proc_block_parameters("Scale").unwrap()[0];
+        assert_eq!(factor.default_value.as_deref(), Some("0"));
+
+        assert!(runtime
+            .set_proc_block_parameter("scale", "offset", "1")
+            .is_err());
+        assert!(runtime
+            .set_proc_block_parameter("normalize", "factor", "1")
+            .is_err());
+        runtime
+            .set_proc_block_parameter("scale", "factor", "-1")
+            .unwrap();
+        runtime
+            .input_tensors()
+            .insert(1, Tensor::new(&[1.0_f32, 2.0], &[2]));
+        runtime
+            .input_tensors()
+            .insert(2, Tensor::new(&[1_u8], &[1]));
+        runtime.predict().unwrap();
+
+        let scaled = Tensor::new(&[-1.0_f32, -2.0], &[2]);
+        assert_eq!(
+            runtime.output_tensors()[&3],
+            vec![OutputTensor::Tensor(scaled)]
+        );
+        assert_eq!(
+            runtime.proc_block_nodes()["scale"].arguments["factor"],
+            "-1"
+        );
+    }
+
+    #[test]
+    fn capabilities_and_outputs_are_known_by_name() {
+        let runtime = Runtime::builder()
+            .native(conditional_pipeline())
+            .build()
+            .unwrap();
+
+        let names = runtime.node_names();
+        assert_eq!(names["value"], 1);
+        assert_eq!(names["flag"], 2);
+        assert_eq!(names["scaled"], 3);
+        assert!(runtime.capabilities().contains_key(&names["value"]));
+        assert!(runtime.outputs().contains_key(&names["original"]));
+        assert!(!names.contains_key("scale"));
+    }
+
+    #[test]
+    fn any_element_that_isnt_the_default_enables_a_node() {
+        assert!(!is_enabled(&Tensor::new(&[0.0_f32, -0.0], &[2])));
+        assert!(is_enabled(&Tensor::new(&[0_i16, 3], &[2])));
+        assert!(!is_enabled(&Tensor::from_strings(&["", ""], &[2])));
+        assert!(is_enabled(&Tensor::from_strings(&["", "yes"], &[2])));
+    }
+
+    fn step(inputs: &[&str], outputs: &[&str]) -> Step {
+        Step::ProcBlock {
+            index: 0,
+            name: outputs.join(","),
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            condition: None,
+        }
+    }
+
+    #[test]
+    fn nodes_run_after_their_inputs_are_produced() {
+        let output = Step::Output {
+            id: 3,
+            inputs: vec!["b".to_string()],
+            condition: None,
+        };
+        let capability = Step::Capability {
+            id: 1,
+            outputs: vec!["a".to_string()],
+        };
+        let steps = vec![
+            output.clone(),
+            step(&["a"], &["b"]),
+            capability.clone(),
+            step(&["a"], &["c"]),
+        ];
+
+        let waves = execution_waves(steps).unwrap();
+
+        assert_eq!(
+            waves,
+            vec![
+                vec![capability],
+                vec![step(&["a"], &["b"]), step(&["a"], &["c"])],
+                vec![output],
+            ]
+        );
+        assert!(execution_waves(vec![step(&["missing"], &["c"])]).is_err());
+    }
+
+    /// How many [`Rendezvous`] proc-blocks are running right now.
+    static RUNNING: AtomicUsize = AtomicUsize::new(0);
+    /// Set when two [`Rendezvous`] proc-blocks were running at the same time.
+    static MET: AtomicBool = AtomicBool::new(false);
+
+    /// A proc-block which waits a while for another [`Rendezvous`] to start.
+    #[derive(Default, hotg_rune_proc_block_macros::ProcBlock)]
+    struct Rendezvous {
+        timeout_ms: u32,
+    }
+
+    impl Transform<hotg_rune_core::Tensor<f32>> for Rendezvous {
+        type Output = hotg_rune_core::Tensor<f32>;
+
+        fn transform(
+            &mut self,
+            input: hotg_rune_core::Tensor<f32>,
+        ) -> Self::Output {
+            RUNNING.fetch_add(1, Ordering::SeqCst);
+            let timeout = Duration::from_millis(self.timeout_ms.into());
+            let start = Instant::now();
+
+            while start.elapsed() < timeout && !MET.load(Ordering::SeqCst) {
+                if RUNNING.load(Ordering::SeqCst) >= 2 {
+                    MET.store(true, Ordering::SeqCst);
+                }
+                std::thread::yield_now();
+            }
+
+            RUNNING.fetch_sub(1, Ordering::SeqCst);
+            input
+        }
+    }
+
+    #[test]
+    fn independent_proc_blocks_run_in_parallel() {
+        let json = r#"{
+            "capabilities": {
+                "value": {
+                    "kind": { "type": "raw" },
+                    "args": { "name": "value" },
+                    "outputs": ["0"]
+                }
+            },
+            "proc-blocks": {
+                "left": {
+                    "path": "hotg-ai/rune@v0.11.3#proc_blocks/rendezvous",
+                    "args": { "timeout_ms": "10000" },
+                    "inputs": ["0"],
+                    "outputs": ["1"]
+                },
+                "right": {
+                    "path": "hotg-ai/rune@v0.11.3#proc_blocks/rendezvous",
+                    "args": { "timeout_ms": "10000" },
+                    "inputs": ["0"],
+                    "outputs": ["2"]
+                }
+            },
+            "outputs": {
+                "serial": {
+                    "kind": { "type": "serial" },
+                    "args": { "name": "serial" },
+                    "inputs": ["1", "2"]
+                }
+            },
+            "tensors": {
+                "0": { "element_type": "F32", "dimensions": [2] },
+                "1": { "element_type": "F32", "dimensions": [2] },
+                "2": { "element_type": "F32", "dimensions": [2] }
+            }
+        }"#;
+        let pipeline = NativePipeline::from_graph(json.as_bytes())
+            .unwrap()
+            .transform::<Rendezvous, _>(
+            "hotg-ai/rune#proc_blocks/rendezvous",
+        );
+        let mut runtime = Runtime::builder().native(pipeline).build().unwrap();
+        runtime.set_parallelism(2).unwrap();
+        let value = Tensor::new(&[1.0_f32, 2.0], &[2]);
+        runtime.input_tensors().insert(1, value.clone());
+
+        runtime.predict().unwrap();
+
+        assert_eq!(runtime.parallelism(), 2);
+        assert!(MET.load(Ordering::SeqCst));
+        assert_eq!(
+            runtime.output_tensors()[&2],
+            vec![
+                OutputTensor::Tensor(value.clone()),
+                OutputTensor::Tensor(value)
+            ]
+        );
+    }
+
+    #[test]
+    fn outputs_are_serialized_like_a_serial_output() {
+        let tensor = Tensor::new(&[1.0_f32, 2.0], &[1, 2]);
+
+        let data = serialize_for_output(&[tensor.clone()]).unwrap();
+        let parsed = crate::outputs::parse_serial(&data).unwrap();
+
+        assert_eq!(parsed, vec![crate::OutputTensor::Tensor(tensor)]);
+    }
+}
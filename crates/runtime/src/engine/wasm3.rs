@@ -1,6 +1,7 @@
 use std::{
     alloc::Layout,
     convert::TryInto,
+    path::Path,
     sync::{Arc, Mutex},
 };
 
@@ -12,8 +13,13 @@ use wasm3::{
 };
 
 use crate::{
-    callbacks::Callbacks,
-    engine::{host_functions::HostFunctions, LoadError, WebAssemblyEngine},
+    callbacks::{Callbacks, Model, ModelVariant},
+    engine::{
+        host_functions::HostFunctions, LoadError, LoadableEngine,
+        WebAssemblyEngine,
+    },
+    log_filter::debug,
+    MemoryStats,
 };
 
 const STACK_SIZE: u32 = 1024 * 16;
@@ -22,7 +28,6 @@ pub struct Wasm3Engine {
     runtime: wasm3::Runtime,
     host_functions: Arc<Mutex<HostFunctions>>,
     last_error: Arc<Mutex<Option<Error>>>,
-    callbacks: Arc<dyn Callbacks>,
 }
 
 impl Wasm3Engine {
@@ -64,24 +69,27 @@ impl Wasm3Engine {
     }
 }
 
-impl WebAssemblyEngine for Wasm3Engine {
+impl LoadableEngine for Wasm3Engine {
+    // Note: WASM3 doesn't implement the SIMD proposal.
+    fn supports_simd() -> bool { false }
+
+    // Note: WASM3 is an interpreter, so there's nothing to cache.
     fn load(
         wasm: &[u8],
         callbacks: Arc<dyn Callbacks>,
-    ) -> Result<Self, LoadError>
-    where
-        Self: Sized,
-    {
+        _cache_dir: Option<&Path>,
+    ) -> Result<Self, LoadError> {
+        let abi = crate::engine::detect_abi(wasm)?;
         let env = Environment::new().to_anyhow()?;
         let host_functions =
-            Arc::new(Mutex::new(HostFunctions::new(Arc::clone(&callbacks))));
+            Arc::new(Mutex::new(HostFunctions::new(callbacks, abi)));
 
         let runtime = env
             .create_runtime(STACK_SIZE)
             .to_anyhow()
             .context("Unable to create the runtime")?;
 
-        log::debug!("Instantiating the WebAssembly module");
+        debug!("Instantiating the WebAssembly module");
         let instance = runtime.parse_and_load_module(wasm).to_anyhow()?;
 
         let last_error = Arc::new(Mutex::new(None));
@@ -91,30 +99,44 @@ impl WebAssemblyEngine for Wasm3Engine {
             .link("request_capability", request_capability)?
             .link("request_capability_set_param", request_capability_set_param)?
             .link("request_provider_response", request_provider_response)?
+            .link(
+                "request_provider_response_chunk",
+                request_provider_response_chunk,
+            )?
             .link("tfm_model_invoke", tfm_model_invoke)?
             .link("tfm_preload_model", tfm_preload_model)?
+            .link("rune_model_select_variant", rune_model_select_variant)?
+            .link("rune_model_name", rune_model_name)?
             .link("rune_model_load", rune_model_load)?
             .link("rune_model_infer", rune_model_infer)?
             .link("request_output", request_output)?
+            .link("request_output_set_param", request_output_set_param)?
+            .link("rune_node_name", rune_node_name)?
             .link("consume_output", consume_output)?
+            .link("consume_output_chunk", consume_output_chunk)?
             .link("rune_resource_open", rune_resource_open)?
             .link("rune_resource_read", rune_resource_read)?
-            .link("rune_resource_close", rune_resource_close)?;
+            .link("rune_resource_close", rune_resource_close)?
+            .link("rune_proc_block_parameter", rune_proc_block_parameter)?
+            .link(
+                "rune_proc_block_parameter_updates",
+                rune_proc_block_parameter_updates,
+            )?
+            .link("rune_panic", rune_panic)?
+            .link("rune_assertion_failed", rune_assertion_failed)?;
 
         Ok(Wasm3Engine {
             runtime,
             last_error,
             host_functions,
-            callbacks,
         })
     }
+}
 
+impl WebAssemblyEngine for Wasm3Engine {
     fn init(&mut self) -> Result<(), Error> {
         let _: i32 = self.call("_manifest", (), |f, _| f.call())?;
-        let host_functions = self.host_functions.lock().unwrap();
-        let graph = host_functions.graph();
-
-        self.callbacks.loaded(&graph)
+        self.host_functions.lock().unwrap().loaded()
     }
 
     fn predict(&mut self) -> Result<(), Error> {
@@ -132,6 +154,54 @@ impl WebAssemblyEngine for Wasm3Engine {
 
         Ok(())
     }
+
+    fn with_model(
+        &mut self,
+        model_id: u32,
+        action: &mut dyn FnMut(&mut dyn Model) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut host_functions = self.host_functions.lock().unwrap();
+        let model = host_functions
+            .model_by_id(model_id)
+            .with_context(|| format!("No model with ID {}", model_id))?;
+
+        action(model)
+    }
+
+    fn replace_model(
+        &mut self,
+        model_id: u32,
+        model: Box<dyn Model>,
+    ) -> Result<(), Error> {
+        self.host_functions
+            .lock()
+            .unwrap()
+            .replace_model(model_id, model)
+    }
+
+    fn memory_usage(&self) -> usize {
+        // Safety: we only read the length, and nothing can be resizing the
+        // memory because the Rune isn't running.
+        unsafe { (*self.runtime.memory()).len() }
+    }
+
+    fn memory_stats(&mut self) -> Result<Option<MemoryStats>, Error> {
+        if self
+            .runtime
+            .find_function::<(), i32>(MemoryStats::EXPORT)
+            .is_err()
+        {
+            return Ok(None);
+        }
+
+        let ptr: i32 = self.call(MemoryStats::EXPORT, (), |f, _| f.call())?;
+
+        // Safety: the Rune isn't running, so nothing can be writing to or
+        // resizing its memory.
+        let memory = unsafe { &*self.runtime.memory() };
+
+        MemoryStats::read(memory, ptr as usize).map(Some)
+    }
 }
 
 struct Linker<'rt> {
@@ -251,6 +321,7 @@ fn request_capability_set_param(
         Value::Integer(i) => i.to_string(),
         Value::Float(f) => f.to_string(),
         Value::SignedByte(s) => s.to_string(),
+        Value::Double(d) => d.to_string(),
         _ => anyhow::bail!("Unknown value type: {}", value),
     };
 
@@ -268,18 +339,31 @@ fn request_provider_response(
     host.request_provider_response(capability_id, buffer)
 }
 
+fn request_provider_response_chunk(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (capability_id, offset, buffer, len, total_len): (u32, u64, u32, u32, u64),
+) -> Result<u32, Error> {
+    let buffer = unsafe { cc.array_mut(buffer, len)? };
+    host.request_provider_response_chunk(
+        capability_id,
+        offset,
+        total_len,
+        buffer,
+    )
+}
+
 fn tfm_model_invoke(
-    _cc: CallContext<'_>,
+    cc: CallContext<'_>,
     host: &mut HostFunctions,
-    (_model_id, _inputs, _input_len, _outputs, _output_len): (
-        u32,
-        u32,
-        u32,
-        u32,
-        u32,
-    ),
+    (model_id, input, input_len, output, output_len): (u32, u32, u32, u32, u32),
 ) -> Result<u32, Error> {
-    host.tfm_model_invoke()?;
+    // Note: We copy the input so it can't alias the output buffer
+    let input = unsafe { cc.array::<u8>(input, input_len)?.to_vec() };
+    let output = unsafe { cc.array_mut(output, output_len)? };
+
+    host.tfm_model_invoke(model_id, &input, output)?;
+
     Ok(0)
 }
 
@@ -343,6 +427,37 @@ fn rune_model_load(
     host.rune_model_load(mimetype, model, &inputs, &outputs)
 }
 
+fn rune_model_name(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (name, len): (u32, u32),
+) -> Result<u32, Error> {
+    let name = cc.read_string(name, len)?;
+    host.rune_model_name(name)?;
+    Ok(0)
+}
+
+fn rune_model_select_variant(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (names, sizes, count): (u32, u32, u32),
+) -> Result<u32, Error> {
+    let names: &[StringRef] = unsafe { cc.array(names, count)? };
+    let sizes: &[u32] = unsafe { cc.array(sizes, count)? };
+
+    let mut variants = Vec::new();
+
+    for (name, &size) in names.iter().zip(sizes) {
+        let StringRef { data, len } = *name;
+        variants.push(ModelVariant {
+            name: cc.read_string(data, len)?,
+            size: size as usize,
+        });
+    }
+
+    host.rune_model_select_variant(&variants)
+}
+
 fn read_shapes(
     cc: &CallContext<'_>,
     input_descriptors: u32,
@@ -371,12 +486,13 @@ struct StringRef {
 }
 
 fn tfm_preload_model(
-    _cc: CallContext<'_>,
+    cc: CallContext<'_>,
     host: &mut HostFunctions,
-    (_model, _model_len, _, _): (u32, u32, u32, u32),
+    (model, model_len, inputs, outputs): (u32, u32, u32, u32),
 ) -> Result<u32, Error> {
-    host.tfm_preload_model()?;
-    Ok(0)
+    let model = unsafe { cc.array(model, model_len)? };
+
+    host.tfm_preload_model(model, inputs, outputs)
 }
 
 fn request_output(
@@ -387,6 +503,39 @@ fn request_output(
     host.request_output(output_type)
 }
 
+fn request_output_set_param(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (output_id, key_ptr, key_len, value_ptr, value_len): (
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+    ),
+) -> Result<u32, Error> {
+    let key = cc
+        .read_string(key_ptr, key_len)
+        .context("Unable to read the key")?;
+    let value = cc
+        .read_string(value_ptr, value_len)
+        .context("Unable to read the value")?;
+
+    host.request_output_set_param(output_id, key, value)?;
+
+    Ok(0)
+}
+
+fn rune_node_name(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (id, name, len): (u32, u32, u32),
+) -> Result<u32, Error> {
+    let name = cc.read_string(name, len).context("Invalid node name")?;
+    host.rune_node_name(id, name)?;
+    Ok(0)
+}
+
 fn consume_output(
     cc: CallContext<'_>,
     host: &mut HostFunctions,
@@ -398,6 +547,17 @@ fn consume_output(
     Ok(len)
 }
 
+fn consume_output_chunk(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (output_id, offset, buffer, len, total_len): (u32, u64, u32, u32, u64),
+) -> Result<u32, Error> {
+    let data = unsafe { cc.array(buffer, len)? };
+    host.consume_output_chunk(output_id, offset, total_len, data)?;
+
+    Ok(len)
+}
+
 fn rune_resource_open(
     cc: CallContext<'_>,
     host: &mut HostFunctions,
@@ -425,6 +585,100 @@ fn rune_resource_close(
     Ok(0)
 }
 
+fn rune_proc_block_parameter(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (
+        node,
+        node_len,
+        type_name,
+        type_name_len,
+        name,
+        name_len,
+        value,
+        value_len,
+    ): (u32, u32, u32, u32, u32, u32, u32, u32),
+) -> Result<u32, Error> {
+    let node = cc.read_string(node, node_len)?;
+    let type_name = cc.read_string(type_name, type_name_len)?;
+    let name = cc.read_string(name, name_len)?;
+    let value = cc.read_string(value, value_len)?;
+    host.rune_proc_block_parameter(node, type_name, name, value)?;
+    Ok(0)
+}
+
+fn rune_proc_block_parameter_updates(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (buffer, len): (u32, u32),
+) -> Result<u32, Error> {
+    let buffer = unsafe { cc.array_mut(buffer, len)? };
+    host.rune_proc_block_parameter_updates(buffer)
+}
+
+fn rune_panic(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (msg, msg_len, file, file_len, line, column, stage, stage_len): (
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+    ),
+) -> Result<u32, Error> {
+    let message = cc
+        .read_string(msg, msg_len)
+        .context("Unable to read the panic message")?;
+    let file = cc
+        .read_string(file, file_len)
+        .context("Unable to read the panic location")?;
+    let stage = cc
+        .read_string(stage, stage_len)
+        .context("Unable to read the stage name")?;
+
+    Err(host.panic(message, file, line, column, stage))
+}
+
+fn rune_assertion_failed(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (expression, expression_len, message, message_len, names, values, count): (
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+        u32,
+    ),
+) -> Result<u32, Error> {
+    let expression = cc
+        .read_string(expression, expression_len)
+        .context("Unable to read the assertion's expression")?;
+    let message = cc
+        .read_string(message, message_len)
+        .context("Unable to read the assertion's message")?;
+
+    let names: &[StringRef] = unsafe { cc.array(names, count)? };
+    let values: &[StringRef] = unsafe { cc.array(values, count)? };
+
+    let mut captured = Vec::new();
+
+    for (name, value) in names.iter().zip(values) {
+        let name = cc.read_string(name.data, name.len)?;
+        let value = cc.read_string(value.data, value.len)?;
+        captured.push((name, value));
+    }
+
+    host.rune_assertion_failed(expression, message, &captured);
+
+    Ok(0)
+}
+
 trait Wasm3ResultExt<T> {
     fn to_anyhow(self) -> Result<T, Error>;
 }
@@ -527,8 +781,6 @@ mod tests {
         sync::atomic::{AtomicBool, Ordering},
     };
 
-    use log::Record;
-
     use super::*;
     use crate::callbacks::{Model, ModelMetadata, RuneGraph};
 
@@ -581,7 +833,7 @@ mod tests {
 
         fn get_resource(&self, _name: &str) -> Option<&[u8]> { Some(&[]) }
 
-        fn log(&self, _record: &Record<'_>) {}
+        fn log(&self, _record: &crate::callbacks::LogRecord<'_>) {}
 
         fn loaded(&self, _rune: &RuneGraph<'_>) -> Result<(), Error> {
             self.loaded.store(true, Ordering::SeqCst);
@@ -596,7 +848,7 @@ mod tests {
             let state = Arc::new(Spy::default());
 
             let callbacks = Arc::clone(&state) as Arc<dyn Callbacks>;
-            let mut engine = Wasm3Engine::load(&wasm, callbacks).unwrap();
+            let mut engine = Wasm3Engine::load(&wasm, callbacks, None).unwrap();
 
             engine.init().unwrap();
 
@@ -1,40 +1,50 @@
 use std::{
     convert::TryInto,
     fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
 use anyhow::{Context, Error};
 use hotg_rune_core::Shape;
 use wasmer::{
-    Array, Function, Instance, LazyInit, Memory, Module, NativeFunc,
-    RuntimeError, Store, ValueType, WasmPtr, WasmerEnv,
+    Array, CompileError, CpuFeature, Function, Instance, LazyInit, Memory,
+    Module, NativeFunc, RuntimeError, Store, Triple, ValueType, WasmPtr,
+    WasmerEnv,
 };
 
 use crate::{
-    callbacks::Callbacks,
-    engine::{host_functions::HostFunctions, LoadError, WebAssemblyEngine},
+    callbacks::{Callbacks, Model, ModelVariant},
+    engine::{
+        cache, host_functions::HostFunctions, LoadError, LoadableEngine,
+        WebAssemblyEngine,
+    },
+    log_filter::{debug, warn},
+    MemoryStats,
 };
 
 pub struct WasmerEngine {
     instance: Instance,
     host_functions: Arc<Mutex<HostFunctions>>,
-    callbacks: Arc<dyn Callbacks>,
 }
 
-impl WebAssemblyEngine for WasmerEngine {
+impl LoadableEngine for WasmerEngine {
+    fn supports_simd() -> bool { true }
+
     fn load(
         wasm: &[u8],
         callbacks: Arc<dyn Callbacks>,
-    ) -> Result<Self, LoadError>
-    where
-        Self: Sized,
-    {
+        cache_dir: Option<&Path>,
+    ) -> Result<Self, LoadError> {
         let store = Store::default();
-        let module = Module::from_binary(&store, wasm)?;
+        let module = match cache_dir {
+            Some(dir) => load_cached(&store, wasm, dir)?,
+            None => Module::from_binary(&store, wasm)?,
+        };
 
+        let abi = crate::engine::detect_abi(wasm)?;
         let host_functions =
-            Arc::new(Mutex::new(HostFunctions::new(callbacks.clone())));
+            Arc::new(Mutex::new(HostFunctions::new(callbacks, abi)));
         let env = Env {
             memory: LazyInit::new(),
             host_functions: Arc::clone(&host_functions),
@@ -46,15 +56,25 @@ impl WebAssemblyEngine for WasmerEngine {
                 "request_capability" => Function::new_native_with_env(&store, env.clone(), request_capability),
                 "request_capability_set_param" => Function::new_native_with_env(&store, env.clone(), request_capability_set_param),
                 "request_provider_response" => Function::new_native_with_env(&store, env.clone(), request_provider_response),
+                "request_provider_response_chunk" => Function::new_native_with_env(&store, env.clone(), request_provider_response_chunk),
                 "tfm_model_invoke" => Function::new_native_with_env(&store, env.clone(), tfm_model_invoke),
                 "tfm_preload_model" => Function::new_native_with_env(&store, env.clone(), tfm_preload_model),
+                "rune_model_select_variant" => Function::new_native_with_env(&store, env.clone(), rune_model_select_variant),
+                "rune_model_name" => Function::new_native_with_env(&store, env.clone(), rune_model_name),
                 "rune_model_load" => Function::new_native_with_env(&store, env.clone(), rune_model_load),
                 "rune_model_infer" => Function::new_native_with_env(&store, env.clone(), rune_model_infer),
                 "request_output" => Function::new_native_with_env(&store, env.clone(), request_output),
+                "request_output_set_param" => Function::new_native_with_env(&store, env.clone(), request_output_set_param),
+                "rune_node_name" => Function::new_native_with_env(&store, env.clone(), rune_node_name),
                 "consume_output" => Function::new_native_with_env(&store, env.clone(), consume_output),
+                "consume_output_chunk" => Function::new_native_with_env(&store, env.clone(), consume_output_chunk),
                 "rune_resource_open" => Function::new_native_with_env(&store, env.clone(), rune_resource_open),
                 "rune_resource_read" => Function::new_native_with_env(&store, env.clone(), rune_resource_read),
                 "rune_resource_close" => Function::new_native_with_env(&store, env.clone(), rune_resource_close),
+                "rune_proc_block_parameter" => Function::new_native_with_env(&store, env.clone(), rune_proc_block_parameter),
+                "rune_proc_block_parameter_updates" => Function::new_native_with_env(&store, env.clone(), rune_proc_block_parameter_updates),
+                "rune_panic" => Function::new_native_with_env(&store, env.clone(), rune_panic),
+                "rune_assertion_failed" => Function::new_native_with_env(&store, env.clone(), rune_assertion_failed),
             }
         };
 
@@ -63,10 +83,11 @@ impl WebAssemblyEngine for WasmerEngine {
         Ok(WasmerEngine {
             instance,
             host_functions,
-            callbacks,
         })
     }
+}
 
+impl WebAssemblyEngine for WasmerEngine {
     fn init(&mut self) -> Result<(), Error> {
         let manifest: NativeFunc<(), i32> = self
             .instance
@@ -76,9 +97,7 @@ impl WebAssemblyEngine for WasmerEngine {
 
         manifest.call().map_err(unwrap_anyhow_error)?;
 
-        let host_functions = self.host_functions.lock().unwrap();
-        let graph = host_functions.graph();
-        self.callbacks.loaded(&graph)
+        self.host_functions.lock().unwrap().loaded()
     }
 
     fn predict(&mut self) -> Result<(), Error> {
@@ -92,6 +111,133 @@ impl WebAssemblyEngine for WasmerEngine {
 
         Ok(())
     }
+
+    fn with_model(
+        &mut self,
+        model_id: u32,
+        action: &mut dyn FnMut(&mut dyn Model) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut host_functions = self.host_functions.lock().unwrap();
+        let model = host_functions
+            .model_by_id(model_id)
+            .with_context(|| format!("No model with ID {}", model_id))?;
+
+        action(model)
+    }
+
+    fn replace_model(
+        &mut self,
+        model_id: u32,
+        model: Box<dyn Model>,
+    ) -> Result<(), Error> {
+        self.host_functions
+            .lock()
+            .unwrap()
+            .replace_model(model_id, model)
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.instance
+            .exports
+            .get_memory("memory")
+            .map(|memory| memory.size().bytes().0)
+            .unwrap_or(0)
+    }
+
+    fn memory_stats(&mut self) -> Result<Option<MemoryStats>, Error> {
+        let function: NativeFunc<(), i32> = match self
+            .instance
+            .exports
+            .get_native_function(MemoryStats::EXPORT)
+        {
+            Ok(f) => f,
+            Err(_) => return Ok(None),
+        };
+
+        let ptr = function.call().map_err(unwrap_anyhow_error)?;
+        let memory = self.instance.exports.get_memory("memory")?;
+
+        // Safety: the Rune isn't running, so nothing can be writing to or
+        // resizing its memory.
+        let bytes = unsafe { memory.data_unchecked() };
+
+        MemoryStats::read(bytes, ptr as usize).map(Some)
+    }
+}
+
+/// Compile the Rune, reusing the machine code from a previous run if it was
+/// cached in `cache_dir`.
+///
+/// The cache is only an optimisation, so any problems reading or writing it
+/// are logged and the Rune is compiled from scratch.
+///
+/// Wasmer runs the cached machine code as-is, so `cache_dir` must only be
+/// writable by someone you would trust to run native code in this process.
+fn load_cached(
+    store: &Store,
+    wasm: &[u8],
+    cache_dir: &Path,
+) -> Result<Module, CompileError> {
+    let path = cache_path(cache_dir, wasm);
+
+    if path.exists() {
+        // Safety: the file is written atomically by write_atomically() and
+        // keyed by a hash of the Rune, the wasmer version and the host, so it
+        // contains a module compiled for this machine (assuming nobody else
+        // can write to the cache directory).
+        match unsafe { Module::deserialize_from_file(store, &path) } {
+            Ok(module) => {
+                debug!("Loaded the compiled Rune from \"{}\"", path.display());
+                return Ok(module);
+            },
+            Err(e) => warn!(
+                "Unable to load the cached module from \"{}\": {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    let module = Module::from_binary(store, wasm)?;
+
+    let saved =
+        module
+            .serialize()
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                cache::write_atomically(&path, &bytes)
+                    .map_err(|e| e.to_string())
+            });
+    if let Err(e) = saved {
+        warn!(
+            "Unable to cache the compiled Rune at \"{}\": {}",
+            path.display(),
+            e
+        );
+    }
+
+    Ok(module)
+}
+
+/// Where the compiled version of a Rune is cached.
+///
+/// The compiled code is only valid for the version of wasmer which generated
+/// it and the machine it was compiled on, so those are part of the key.
+fn cache_path(cache_dir: &Path, wasm: &[u8]) -> PathBuf {
+    let triple = Triple::host().to_string();
+    let cpu_features = format!("{:?}", CpuFeature::for_host());
+
+    cache::cache_path(
+        cache_dir,
+        "wasmer",
+        &[
+            wasmer::VERSION.as_bytes(),
+            env!("CARGO_PKG_VERSION").as_bytes(),
+            triple.as_bytes(),
+            cpu_features.as_bytes(),
+            wasm,
+        ],
+    )
 }
 
 #[derive(Debug)]
@@ -222,6 +368,132 @@ fn rune_resource_close(env: &Env, id: u32) -> Result<(), RuntimeError> {
         .map_err(runtime_error)
 }
 
+#[allow(clippy::too_many_arguments)]
+fn rune_proc_block_parameter(
+    env: &Env,
+    node: WasmPtr<u8, Array>,
+    node_len: u32,
+    type_name: WasmPtr<u8, Array>,
+    type_name_len: u32,
+    name: WasmPtr<u8, Array>,
+    name_len: u32,
+    value: WasmPtr<u8, Array>,
+    value_len: u32,
+) -> Result<(), RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    // Safety: this function isn't reentrant, so we don't need to worry about
+    // concurrent mutations.
+    unsafe {
+        let node = node
+            .get_utf8_str(memory, node_len)
+            .context("Invalid node name")
+            .map_err(runtime_error)?;
+        let type_name = type_name
+            .get_utf8_str(memory, type_name_len)
+            .context("Invalid proc-block type name")
+            .map_err(runtime_error)?;
+        let name = name
+            .get_utf8_str(memory, name_len)
+            .context("Invalid parameter name")
+            .map_err(runtime_error)?;
+        let value = value
+            .get_utf8_str(memory, value_len)
+            .context("Invalid parameter value")
+            .map_err(runtime_error)?;
+
+        env.host_functions
+            .lock()
+            .unwrap()
+            .rune_proc_block_parameter(node, type_name, name, value)
+            .map_err(runtime_error)
+    }
+}
+
+fn rune_proc_block_parameter_updates(
+    env: &Env,
+    dest: WasmPtr<u8, Array>,
+    len: u32,
+) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    let mut buffer = vec![0_u8; len as usize];
+
+    let needed = env
+        .host_functions
+        .lock()
+        .unwrap()
+        .rune_proc_block_parameter_updates(&mut buffer)
+        .map_err(runtime_error)?;
+
+    // Nothing gets written when the Rune's buffer is too small
+    if needed == 0 || needed > len {
+        return Ok(needed);
+    }
+
+    let view = memory.view::<u8>();
+    // Safety: Function isn't re-entrant so we don't need to worry about
+    // concurrent mutations.
+    unsafe {
+        view.subarray(dest.offset(), dest.offset() + needed)
+            .copy_from(&buffer[..needed as usize]);
+    }
+
+    Ok(needed)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rune_panic(
+    env: &Env,
+    msg: WasmPtr<u8, Array>,
+    msg_len: u32,
+    file: WasmPtr<u8, Array>,
+    file_len: u32,
+    line: u32,
+    column: u32,
+    stage: WasmPtr<u8, Array>,
+    stage_len: u32,
+) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    // Safety: this function isn't reentrant, so we don't need to worry about
+    // concurrent mutations.
+    unsafe {
+        let message = msg
+            .get_utf8_str(memory, msg_len)
+            .context("Unable to read the panic message")
+            .map_err(runtime_error)?;
+        let file = file
+            .get_utf8_str(memory, file_len)
+            .context("Unable to read the panic location")
+            .map_err(runtime_error)?;
+        let stage = stage
+            .get_utf8_str(memory, stage_len)
+            .context("Unable to read the stage name")
+            .map_err(runtime_error)?;
+
+        let error = env
+            .host_functions
+            .lock()
+            .unwrap()
+            .panic(message, file, line, column, stage);
+
+        Err(runtime_error(error))
+    }
+}
+
 fn request_capability(
     env: &Env,
     capability_type: u32,
@@ -292,6 +564,7 @@ fn stringified(value: hotg_rune_core::Value) -> String {
         hotg_rune_core::Value::Integer(i) => i.to_string(),
         hotg_rune_core::Value::Float(f) => f.to_string(),
         hotg_rune_core::Value::SignedByte(s) => s.to_string(),
+        hotg_rune_core::Value::Double(d) => d.to_string(),
         _ => unreachable!(),
     }
 }
@@ -329,19 +602,84 @@ fn request_provider_response(
     Ok(bytes_written)
 }
 
+fn request_provider_response_chunk(
+    env: &Env,
+    capability_id: u32,
+    offset: u64,
+    dest: WasmPtr<u8, Array>,
+    len: u32,
+    total_len: u64,
+) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    let mut buffer = vec![0_u8; len as usize];
+
+    let bytes_written = env
+        .host_functions
+        .lock()
+        .unwrap()
+        .request_provider_response_chunk(
+            capability_id,
+            offset,
+            total_len,
+            &mut buffer,
+        )
+        .map_err(runtime_error)?;
+
+    let dest = dest
+        .deref(memory, 0, bytes_written)
+        .context("Invalid buffer pointer")
+        .map_err(runtime_error)?;
+
+    for (cell, byte) in dest.iter().zip(buffer) {
+        cell.set(byte);
+    }
+
+    Ok(bytes_written)
+}
+
 fn tfm_model_invoke(
     env: &Env,
-    _model_id: u32,
-    _input: WasmPtr<u8, Array>,
-    _input_len: u32,
-    _output: WasmPtr<u8, Array>,
-    _output_len: u32,
+    model_id: u32,
+    input: WasmPtr<u8, Array>,
+    input_len: u32,
+    output: WasmPtr<u8, Array>,
+    output_len: u32,
 ) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    let input: Vec<u8> = input
+        .deref(memory, 0, input_len)
+        .context("Invalid input buffer")
+        .map_err(runtime_error)?
+        .iter()
+        .map(|cell| cell.get())
+        .collect();
+    let mut buffer = vec![0_u8; output_len as usize];
+
     env.host_functions
         .lock()
         .unwrap()
-        .tfm_model_invoke()
+        .tfm_model_invoke(model_id, &input, &mut buffer)
+        .map_err(runtime_error)?;
+
+    let dest = output
+        .deref(memory, 0, output_len)
+        .context("Invalid output buffer")
         .map_err(runtime_error)?;
+
+    for (cell, byte) in dest.iter().zip(buffer) {
+        cell.set(byte);
+    }
+
     Ok(0)
 }
 
@@ -462,6 +800,76 @@ pub struct StringRef {
 // necessary bounds checks.
 unsafe impl ValueType for StringRef {}
 
+#[allow(clippy::too_many_arguments)]
+fn rune_assertion_failed(
+    env: &Env,
+    expression: WasmPtr<u8, Array>,
+    expression_len: u32,
+    message: WasmPtr<u8, Array>,
+    message_len: u32,
+    names: WasmPtr<StringRef, Array>,
+    values: WasmPtr<StringRef, Array>,
+    count: u32,
+) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    // Safety: This function isn't reentrant so there are no concurrent
+    // modifications.
+    unsafe {
+        let expression = expression
+            .get_utf8_str(memory, expression_len)
+            .context("Unable to read the assertion's expression")
+            .map_err(runtime_error)?;
+        let message = message
+            .get_utf8_str(memory, message_len)
+            .context("Unable to read the assertion's message")
+            .map_err(runtime_error)?;
+        let captured = read_captured_values(memory, names, values, count)
+            .map_err(runtime_error)?;
+
+        env.host_functions
+            .lock()
+            .unwrap()
+            .rune_assertion_failed(expression, message, &captured);
+    }
+
+    Ok(0)
+}
+
+unsafe fn read_captured_values(
+    memory: &Memory,
+    names: WasmPtr<StringRef, Array>,
+    values: WasmPtr<StringRef, Array>,
+    count: u32,
+) -> Result<Vec<(&str, &str)>, Error> {
+    let names = names
+        .deref(memory, 0, count)
+        .context("Invalid value names pointer")?;
+    let values = values
+        .deref(memory, 0, count)
+        .context("Invalid values pointer")?;
+
+    let mut captured = Vec::new();
+
+    for (i, (name, value)) in names.iter().zip(values).enumerate() {
+        let StringRef { data, len } = name.get();
+        let name = data
+            .get_utf8_str(memory, len)
+            .with_context(|| format!("The {}'th value name is invalid", i))?;
+        let StringRef { data, len } = value.get();
+        let value = data
+            .get_utf8_str(memory, len)
+            .with_context(|| format!("The {}'th value is invalid", i))?;
+        captured.push((name, value));
+    }
+
+    Ok(captured)
+}
+
 fn rune_model_load(
     env: &Env,
     mimetype: WasmPtr<u8, Array>,
@@ -515,20 +923,114 @@ fn rune_model_load(
         .map_err(runtime_error)
 }
 
-fn tfm_preload_model(
+fn rune_model_name(
+    env: &Env,
+    name: WasmPtr<u8, Array>,
+    len: u32,
+) -> Result<(), RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    // Safety: this function isn't reentrant, so we don't need to worry about
+    // concurrent mutations.
+    let name = unsafe {
+        name.get_utf8_str(memory, len)
+            .context("Invalid model name")
+            .map_err(runtime_error)?
+    };
+
+    env.host_functions
+        .lock()
+        .unwrap()
+        .rune_model_name(name)
+        .map_err(runtime_error)
+}
+
+fn rune_model_select_variant(
     env: &Env,
-    _model: WasmPtr<u8, Array>,
-    _model_len: u32,
-    _: u32,
-    _: u32,
+    names: WasmPtr<StringRef, Array>,
+    sizes: WasmPtr<u32, Array>,
+    count: u32,
 ) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    // Safety: This function isn't reentrant so there are no concurrent
+    // modifications.
+    let variants = unsafe {
+        read_model_variants(memory, names, sizes, count)
+            .map_err(runtime_error)?
+    };
+    let variants: Vec<_> = variants
+        .iter()
+        .map(|&(name, size)| ModelVariant { name, size })
+        .collect();
+
     env.host_functions
         .lock()
         .unwrap()
-        .tfm_preload_model()
+        .rune_model_select_variant(&variants)
+        .map_err(runtime_error)
+}
+
+unsafe fn read_model_variants(
+    memory: &Memory,
+    names: WasmPtr<StringRef, Array>,
+    sizes: WasmPtr<u32, Array>,
+    count: u32,
+) -> Result<Vec<(&str, usize)>, Error> {
+    let names = names
+        .deref(memory, 0, count)
+        .context("Invalid variant names pointer")?;
+    let sizes = sizes
+        .deref(memory, 0, count)
+        .context("Invalid variant sizes pointer")?;
+
+    let mut variants = Vec::new();
+
+    for (i, (name, size)) in names.iter().zip(sizes).enumerate() {
+        let StringRef { data, len } = name.get();
+        let name = data
+            .get_utf8_str(memory, len)
+            .with_context(|| format!("The {}'th variant name is invalid", i))?;
+        variants.push((name, size.get() as usize));
+    }
+
+    Ok(variants)
+}
+
+fn tfm_preload_model(
+    env: &Env,
+    model: WasmPtr<u8, Array>,
+    model_len: u32,
+    inputs: u32,
+    outputs: u32,
+) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
         .map_err(runtime_error)?;
 
-    Ok(0)
+    let model: Vec<u8> = model
+        .deref(memory, 0, model_len)
+        .context("Invalid model")
+        .map_err(runtime_error)?
+        .iter()
+        .map(|cell| cell.get())
+        .collect();
+
+    env.host_functions
+        .lock()
+        .unwrap()
+        .tfm_preload_model(&model, inputs, outputs)
+        .map_err(runtime_error)
 }
 
 unsafe fn shape_from_descriptors(
@@ -564,6 +1066,69 @@ fn request_output(env: &Env, output_type: u32) -> Result<u32, RuntimeError> {
         .map_err(runtime_error)
 }
 
+fn request_output_set_param(
+    env: &Env,
+    output_id: u32,
+    key_ptr: WasmPtr<u8, Array>,
+    key_len: u32,
+    value_ptr: WasmPtr<u8, Array>,
+    value_len: u32,
+) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    // Safety: this function isn't reentrant, so we don't need to worry about
+    // concurrent mutations.
+    unsafe {
+        let key = key_ptr
+            .get_utf8_str(memory, key_len)
+            .context("Unable to read the key")
+            .map_err(runtime_error)?;
+        let value = value_ptr
+            .get_utf8_str(memory, value_len)
+            .context("Unable to read the value")
+            .map_err(runtime_error)?;
+
+        env.host_functions
+            .lock()
+            .unwrap()
+            .request_output_set_param(output_id, key, value)
+            .map_err(runtime_error)?;
+
+        Ok(0)
+    }
+}
+
+fn rune_node_name(
+    env: &Env,
+    id: u32,
+    name: WasmPtr<u8, Array>,
+    len: u32,
+) -> Result<(), RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    // Safety: this function isn't reentrant, so we don't need to worry about
+    // concurrent mutations.
+    let name = unsafe {
+        name.get_utf8_str(memory, len)
+            .context("Invalid node name")
+            .map_err(runtime_error)?
+    };
+
+    env.host_functions
+        .lock()
+        .unwrap()
+        .rune_node_name(id, name)
+        .map_err(runtime_error)
+}
+
 fn consume_output(
     env: &Env,
     output_id: u32,
@@ -593,3 +1158,34 @@ fn consume_output(
 
     Ok(len)
 }
+
+fn consume_output_chunk(
+    env: &Env,
+    output_id: u32,
+    offset: u64,
+    buffer: WasmPtr<u8, Array>,
+    len: u32,
+    total_len: u64,
+) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    let buffer: Vec<u8> = buffer
+        .deref(memory, 0, len)
+        .context("Invalid input")
+        .map_err(runtime_error)?
+        .iter()
+        .map(|c| c.get())
+        .collect();
+
+    env.host_functions
+        .lock()
+        .unwrap()
+        .consume_output_chunk(output_id, offset, total_len, &buffer)
+        .map_err(runtime_error)?;
+
+    Ok(len)
+}
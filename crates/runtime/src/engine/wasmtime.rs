@@ -0,0 +1,1182 @@
+use std::{
+    convert::{TryFrom, TryInto},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Error};
+use hotg_rune_core::Shape;
+use wasmtime::{
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module,
+    ResourceLimiter, Store, Trap, TrapCode, WasmParams, WasmResults, WasmTy,
+};
+
+use crate::{
+    callbacks::{Callbacks, Model, ModelVariant},
+    cancellation::{Cancelled, EngineInterrupt},
+    engine::{
+        cache, host_functions::HostFunctions, LoadError, LoadableEngine,
+        OutOfFuel, ResourceExhausted, WebAssemblyEngine, HOST_MODULE,
+        WASM_PAGE_SIZE,
+    },
+    log_filter::{debug, warn},
+    MemoryStats,
+};
+
+pub struct WasmtimeEngine {
+    store: Store<Env>,
+    instance: Instance,
+    memory: Option<Memory>,
+    /// How much fuel the Rune gets each time we call into it.
+    fuel_limit: Option<u64>,
+    /// The total amount of fuel that has been added to the [`Store`].
+    fuel_added: u64,
+    /// Was the Rune compiled for wasm64?
+    memory64: bool,
+}
+
+/// The data attached to the [`Store`].
+struct Env {
+    host_functions: HostFunctions,
+    /// The error from a host function, which will be returned instead of the
+    /// [`Trap`] it triggered.
+    error: Option<Error>,
+    limiter: MemoryLimiter,
+    /// The state used by WASI functions, if the Rune was loaded with WASI
+    /// support.
+    #[cfg(feature = "wasi")]
+    wasi: Option<wasmtime_wasi::WasiCtx>,
+}
+
+impl LoadableEngine for WasmtimeEngine {
+    fn supports_simd() -> bool { true }
+
+    fn supports_memory64() -> bool { true }
+
+    fn supports_fuel() -> bool { true }
+
+    fn load(
+        wasm: &[u8],
+        callbacks: Arc<dyn Callbacks>,
+        cache_dir: Option<&Path>,
+    ) -> Result<Self, LoadError> {
+        WasmtimeEngine::load_with(wasm, callbacks, cache_dir, false)
+    }
+}
+
+impl WebAssemblyEngine for WasmtimeEngine {
+    fn init(&mut self) -> Result<(), Error> {
+        if self
+            .instance
+            .get_func(&mut self.store, "_initialize")
+            .is_some()
+        {
+            // WASI reactors need to run their constructors before anything
+            // else gets called.
+            self.call::<(), ()>("_initialize", ())?;
+        }
+
+        self.call::<(), i32>("_manifest", ())?;
+
+        self.store.data_mut().host_functions.loaded()
+    }
+
+    fn predict(&mut self) -> Result<(), Error> {
+        self.call::<(i32, i32, i32), i32>("_call", (0, 0, 0))?;
+
+        Ok(())
+    }
+
+    fn with_model(
+        &mut self,
+        model_id: u32,
+        action: &mut dyn FnMut(&mut dyn Model) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let model = self
+            .store
+            .data_mut()
+            .host_functions
+            .model_by_id(model_id)
+            .with_context(|| format!("No model with ID {}", model_id))?;
+
+        action(model)
+    }
+
+    fn replace_model(
+        &mut self,
+        model_id: u32,
+        model: Box<dyn Model>,
+    ) -> Result<(), Error> {
+        self.store
+            .data_mut()
+            .host_functions
+            .replace_model(model_id, model)
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.memory
+            .map(|memory| memory.data_size(&self.store))
+            .unwrap_or(0)
+    }
+
+    fn memory_stats(&mut self) -> Result<Option<MemoryStats>, Error> {
+        if self
+            .instance
+            .get_func(&mut self.store, MemoryStats::EXPORT)
+            .is_none()
+        {
+            return Ok(None);
+        }
+
+        let ptr = if self.memory64 {
+            self.call::<(), i64>(MemoryStats::EXPORT, ())? as usize
+        } else {
+            self.call::<(), i32>(MemoryStats::EXPORT, ())? as usize
+        };
+        let memory = self
+            .memory
+            .context("The Rune doesn't export its linear memory")?;
+        let memory = memory.data(&self.store);
+
+        if self.memory64 {
+            MemoryStats::read_wasm64(memory, ptr).map(Some)
+        } else {
+            MemoryStats::read(memory, ptr).map(Some)
+        }
+    }
+
+    fn interrupter(&self) -> Option<EngineInterrupt> {
+        let engine = self.store.engine().clone();
+        Some(Arc::new(move || engine.increment_epoch()))
+    }
+}
+
+/// A [`WasmtimeEngine`] which also lets the Rune import
+/// [WASI](https://wasi.dev/) functions, so Runes compiled for the
+/// `wasm32-wasi` target can use the standard library's file, clock, and
+/// random number APIs.
+///
+/// The Rune's stdout and stderr are forwarded to the host's, but it doesn't
+/// get access to any directories or environment variables.
+#[cfg(feature = "wasi")]
+pub struct WasiEngine(WasmtimeEngine);
+
+#[cfg(feature = "wasi")]
+impl LoadableEngine for WasiEngine {
+    fn supports_simd() -> bool { WasmtimeEngine::supports_simd() }
+
+    fn supports_memory64() -> bool { WasmtimeEngine::supports_memory64() }
+
+    fn supports_fuel() -> bool { WasmtimeEngine::supports_fuel() }
+
+    fn supports_wasi() -> bool { true }
+
+    fn load(
+        wasm: &[u8],
+        callbacks: Arc<dyn Callbacks>,
+        cache_dir: Option<&Path>,
+    ) -> Result<Self, LoadError> {
+        WasmtimeEngine::load_with(wasm, callbacks, cache_dir, true)
+            .map(WasiEngine)
+    }
+}
+
+#[cfg(feature = "wasi")]
+impl WebAssemblyEngine for WasiEngine {
+    fn init(&mut self) -> Result<(), Error> { self.0.init() }
+
+    fn predict(&mut self) -> Result<(), Error> { self.0.predict() }
+
+    fn with_model(
+        &mut self,
+        model_id: u32,
+        action: &mut dyn FnMut(&mut dyn Model) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.0.with_model(model_id, action)
+    }
+
+    fn replace_model(
+        &mut self,
+        model_id: u32,
+        model: Box<dyn Model>,
+    ) -> Result<(), Error> {
+        self.0.replace_model(model_id, model)
+    }
+
+    fn memory_usage(&self) -> usize { self.0.memory_usage() }
+
+    fn memory_stats(&mut self) -> Result<Option<MemoryStats>, Error> {
+        self.0.memory_stats()
+    }
+
+    fn interrupter(&self) -> Option<EngineInterrupt> { self.0.interrupter() }
+}
+
+impl WasmtimeEngine {
+    /// Load the Rune, optionally providing WASI functions alongside our own
+    /// host functions.
+    fn load_with(
+        wasm: &[u8],
+        callbacks: Arc<dyn Callbacks>,
+        cache_dir: Option<&Path>,
+        wasi: bool,
+    ) -> Result<Self, LoadError> {
+        let fuel_limit = callbacks.fuel_limit();
+        let memory64 = crate::engine::uses_memory64(wasm);
+
+        if wasi && memory64 {
+            return Err(LoadError::Other(anyhow::anyhow!(
+                "WASI functions aren't available to wasm64 Runes"
+            )));
+        }
+
+        let mut config = Config::new();
+        config
+            .wasm_simd(true)
+            .wasm_memory64(memory64)
+            .consume_fuel(fuel_limit.is_some())
+            .epoch_interruption(true);
+        let engine = Engine::new(&config)?;
+
+        let module = match cache_dir {
+            Some(dir) => load_cached(&engine, wasm, dir, fuel_limit.is_some())?,
+            None => Module::new(&engine, wasm)?,
+        };
+
+        let abi = crate::engine::detect_abi(wasm)?;
+        let env = Env {
+            host_functions: HostFunctions::new(Arc::clone(&callbacks), abi),
+            error: None,
+            limiter: MemoryLimiter::new(callbacks.max_memory_pages()),
+            #[cfg(feature = "wasi")]
+            wasi: if wasi {
+                Some(
+                    wasmtime_wasi::WasiCtxBuilder::new()
+                        .inherit_stdout()
+                        .inherit_stderr()
+                        .build(),
+                )
+            } else {
+                None
+            },
+        };
+        let mut store = Store::new(&engine, env);
+        store.limiter(|env| &mut env.limiter);
+
+        let mut linker = Linker::new(&engine);
+        if memory64 {
+            link_host_functions::<u64>(&mut linker)?;
+        } else {
+            link_host_functions::<u32>(&mut linker)?;
+        }
+
+        if wasi {
+            link_wasi(&mut linker)?;
+        }
+        let instance =
+            linker
+                .instantiate(&mut store, &module)
+                .map_err(|e| match store.data().limiter.exceeded() {
+                    Some(exhausted) => e.context(exhausted),
+                    None => e,
+                })?;
+        let memory = instance.get_memory(&mut store, "memory");
+
+        Ok(WasmtimeEngine {
+            store,
+            instance,
+            memory,
+            fuel_limit,
+            fuel_added: 0,
+            memory64,
+        })
+    }
+
+    /// Call one of the Rune's exported functions, giving it a fresh fuel
+    /// budget first.
+    fn call<Params, Results>(
+        &mut self,
+        name: &str,
+        params: Params,
+    ) -> Result<Results, Error>
+    where
+        Params: WasmParams,
+        Results: WasmResults,
+    {
+        self.refuel()?;
+        self.store.data_mut().error = None;
+        self.store.data_mut().limiter.reset();
+        // Any interrupts from before this call (i.e. when the Rune wasn't
+        // running) are ignored.
+        self.store.set_epoch_deadline(1);
+
+        let function = self
+            .instance
+            .get_typed_func::<Params, Results, _>(&mut self.store, name)
+            .with_context(|| {
+                format!("Unable to get the \"{}\" function", name)
+            })?;
+
+        function
+            .call(&mut self.store, params)
+            .map_err(|trap| self.trap_error(trap))
+    }
+
+    /// Top the [`Store`] back up to [`WasmtimeEngine::fuel_limit`], so each
+    /// call gets the same budget regardless of how much the last one used.
+    fn refuel(&mut self) -> Result<(), Error> {
+        let limit = match self.fuel_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let consumed = self.store.fuel_consumed().unwrap_or(0);
+        let remaining = self.fuel_added.saturating_sub(consumed);
+        let top_up = limit.saturating_sub(remaining);
+
+        if top_up > 0 {
+            self.store
+                .add_fuel(top_up)
+                .context("Unable to add fuel to the store")?;
+            self.fuel_added += top_up;
+        }
+
+        Ok(())
+    }
+
+    /// Figure out why the Rune trapped, preferring the original error if it
+    /// came from one of our host functions.
+    fn trap_error(&mut self, trap: Trap) -> Error {
+        let env = self.store.data_mut();
+
+        if let Some(exhausted) = env.limiter.exceeded() {
+            // The Rune probably panicked because an allocation failed
+            let error = env.error.take().unwrap_or_else(|| trap.into());
+            return error.context(exhausted);
+        }
+
+        if let Some(error) = env.error.take() {
+            return error;
+        }
+
+        if trap.trap_code() == Some(TrapCode::Interrupt) {
+            return Error::from(trap).context(Cancelled);
+        }
+
+        if let Some(limit) = self.fuel_limit {
+            let consumed = self.store.fuel_consumed().unwrap_or(0);
+            if consumed >= self.fuel_added {
+                return Error::from(trap).context(OutOfFuel { limit });
+            }
+        }
+
+        Error::from(trap)
+    }
+}
+
+/// A [`ResourceLimiter`] which stops the Rune's linear memory from growing
+/// past [`Callbacks::max_memory_pages()`].
+#[derive(Debug)]
+struct MemoryLimiter {
+    max_pages: Option<u32>,
+    /// Did the Rune try to grow its memory past `max_pages` during the
+    /// current call?
+    denied: bool,
+}
+
+impl MemoryLimiter {
+    fn new(max_pages: Option<u32>) -> Self {
+        MemoryLimiter {
+            max_pages,
+            denied: false,
+        }
+    }
+
+    fn reset(&mut self) { self.denied = false; }
+
+    fn exceeded(&self) -> Option<ResourceExhausted> {
+        match self.max_pages {
+            Some(pages) if self.denied => {
+                Some(ResourceExhausted::Memory { pages })
+            },
+            _ => None,
+        }
+    }
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> bool {
+        match self.max_pages {
+            Some(pages) if desired > pages as usize * WASM_PAGE_SIZE => {
+                self.denied = true;
+                false
+            },
+            _ => true,
+        }
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: u32,
+        _desired: u32,
+        _maximum: Option<u32>,
+    ) -> bool {
+        true
+    }
+}
+
+/// Compile the Rune, reusing the machine code from a previous run if it was
+/// cached in `cache_dir`.
+///
+/// The cache is only an optimisation, so any problems reading or writing it
+/// are logged and the Rune is compiled from scratch.
+///
+/// Wasmtime runs the cached machine code as-is, so `cache_dir` must only be
+/// writable by someone you would trust to run native code in this process.
+fn load_cached(
+    engine: &Engine,
+    wasm: &[u8],
+    cache_dir: &Path,
+    consume_fuel: bool,
+) -> Result<Module, Error> {
+    let path = cache_path(cache_dir, wasm, consume_fuel);
+
+    if path.exists() {
+        // Safety: the file is written atomically by write_atomically() and
+        // keyed by a hash of the Rune and the engine's configuration. On top
+        // of that, wasmtime checks the module was serialized by the same
+        // wasmtime version with compatible compiler settings and CPU
+        // features before using it.
+        match unsafe { Module::deserialize_file(engine, &path) } {
+            Ok(module) => {
+                debug!("Loaded the compiled Rune from \"{}\"", path.display());
+                return Ok(module);
+            },
+            Err(e) => warn!(
+                "Unable to load the cached module from \"{}\": {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    let module = Module::new(engine, wasm)?;
+
+    let saved = module.serialize().and_then(|bytes| {
+        cache::write_atomically(&path, &bytes).map_err(Error::from)
+    });
+    if let Err(e) = saved {
+        warn!(
+            "Unable to cache the compiled Rune at \"{}\": {}",
+            path.display(),
+            e
+        );
+    }
+
+    Ok(module)
+}
+
+/// Where the compiled version of a Rune is cached.
+///
+/// Fuel metering is compiled into the module, so it needs to be part of the
+/// key alongside the Rune, the runtime's version and the host it was compiled
+/// for.
+fn cache_path(cache_dir: &Path, wasm: &[u8], consume_fuel: bool) -> PathBuf {
+    cache::cache_path(
+        cache_dir,
+        "wasmtime",
+        &[
+            env!("CARGO_PKG_VERSION").as_bytes(),
+            std::env::consts::ARCH.as_bytes(),
+            std::env::consts::OS.as_bytes(),
+            &[consume_fuel as u8],
+            wasm,
+        ],
+    )
+}
+
+/// Give a host function access to the Rune's linear memory and the
+/// [`HostFunctions`], stashing any error so it can be returned from
+/// [`WasmtimeEngine::call()`].
+fn with_host<T>(
+    caller: &mut Caller<'_, Env>,
+    func: impl FnOnce(&mut [u8], &mut HostFunctions) -> Result<T, Error>,
+) -> Result<T, Trap> {
+    let result = match caller.get_export("memory").and_then(Extern::into_memory)
+    {
+        Some(memory) => {
+            let (memory, env) = memory.data_and_store_mut(&mut *caller);
+            func(memory, &mut env.host_functions)
+        },
+        None => Err(Error::msg("The Rune doesn't export its linear memory")),
+    };
+
+    result.map_err(|e| {
+        let trap = Trap::new(format!("{:#}", e));
+        caller.data_mut().error = Some(e);
+        trap
+    })
+}
+
+/// Provide the standard WASI functions (files, clocks, random numbers, etc.).
+#[cfg(feature = "wasi")]
+fn link_wasi(linker: &mut Linker<Env>) -> Result<(), Error> {
+    wasmtime_wasi::add_to_linker(linker, |env: &mut Env| {
+        env.wasi
+            .as_mut()
+            .expect("The WASI context is created when WASI is enabled")
+    })
+}
+
+#[cfg(not(feature = "wasi"))]
+fn link_wasi(_linker: &mut Linker<Env>) -> Result<(), Error> {
+    anyhow::bail!("The runtime was compiled without WASI support")
+}
+
+/// The type a Rune uses for pointers into its linear memory, `u32` for
+/// wasm32 and `u64` for wasm64.
+///
+/// Lengths are pointer-sized too (a `usize` in the Rune) so models and
+/// tensors can be bigger than 4 GB, while IDs and counts are always `u32`s.
+trait Pointer: WasmTy + Copy + Into<u64> + Send + Sync + 'static {
+    /// How many bytes a pointer takes up in linear memory.
+    const SIZE: usize;
+}
+
+impl Pointer for u32 {
+    const SIZE: usize = 4;
+}
+
+impl Pointer for u64 {
+    const SIZE: usize = 8;
+}
+
+fn link_host_functions<P: Pointer>(
+    linker: &mut Linker<Env>,
+) -> Result<(), Error> {
+    linker.func_wrap(
+        HOST_MODULE,
+        "_debug",
+        |mut caller: Caller<'_, Env>, msg: P, len: P| {
+            with_host(&mut caller, |memory, host| {
+                let message = utf8(memory, msg, len)
+                    .context("Unable to read the message")?;
+                host.debug(message)?;
+                Ok(0_u32)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "request_capability",
+        |mut caller: Caller<'_, Env>, capability_type: u32| {
+            with_host(&mut caller, |_, host| {
+                host.request_capability(capability_type)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "request_capability_set_param",
+        |mut caller: Caller<'_, Env>,
+         capability_id: u32,
+         key_ptr: P,
+         key_len: P,
+         value_ptr: P,
+         value_len: P,
+         value_type: u32| {
+            with_host(&mut caller, |memory, host| {
+                let key = utf8(memory, key_ptr, key_len)
+                    .context("Unable to read the key")?;
+                let ty = value_type
+                    .try_into()
+                    .map_err(|()| Error::msg("Invalid key type"))?;
+                let value = bytes(memory, value_ptr, value_len)
+                    .context("Unable to read the value")?;
+                let value = hotg_rune_core::Value::from_le_bytes(ty, value)
+                    .context("Unable to deserialize the value")?;
+
+                host.request_capability_set_param(
+                    capability_id,
+                    key,
+                    stringified(value),
+                )?;
+
+                Ok(0_u32)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "request_provider_response",
+        |mut caller: Caller<'_, Env>, dest: P, len: P, capability_id: u32| {
+            with_host(&mut caller, |memory, host| {
+                let dest = bytes_mut(memory, dest, len)
+                    .context("Invalid buffer pointer")?;
+                host.request_provider_response(capability_id, dest)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "request_provider_response_chunk",
+        |mut caller: Caller<'_, Env>,
+         capability_id: u32,
+         offset: u64,
+         dest: P,
+         len: P,
+         total_len: u64| {
+            with_host(&mut caller, |memory, host| {
+                let dest = bytes_mut(memory, dest, len)
+                    .context("Invalid buffer pointer")?;
+                host.request_provider_response_chunk(
+                    capability_id,
+                    offset,
+                    total_len,
+                    dest,
+                )
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "tfm_model_invoke",
+        |mut caller: Caller<'_, Env>,
+         model_id: u32,
+         input: P,
+         input_len: P,
+         output: P,
+         output_len: P| {
+            with_host(&mut caller, |memory, host| {
+                let input = bytes(memory, input, input_len)
+                    .context("Invalid input buffer")?
+                    .to_vec();
+                let output = bytes_mut(memory, output, output_len)
+                    .context("Invalid output buffer")?;
+                host.tfm_model_invoke(model_id, &input, output)?;
+                Ok(0_u32)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "tfm_preload_model",
+        |mut caller: Caller<'_, Env>,
+         model: P,
+         model_len: P,
+         inputs: u32,
+         outputs: u32| {
+            with_host(&mut caller, |memory, host| {
+                let model =
+                    bytes(memory, model, model_len).context("Invalid model")?;
+                host.tfm_preload_model(model, inputs, outputs)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_model_select_variant",
+        |mut caller: Caller<'_, Env>, names: P, sizes: P, count: u32| {
+            with_host(&mut caller, |memory, host| {
+                let names = strings(memory, names, count)
+                    .context("Invalid variant names")?;
+                let variants = names
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, name)| {
+                        let size = offset(sizes.into(), i, P::SIZE)
+                            .and_then(|address| {
+                                read_pointer::<P>(memory, address)
+                            })
+                            .context("Invalid variant sizes pointer")?;
+                        let size = usize::try_from(size)
+                            .context("The variant is too big")?;
+                        Ok(ModelVariant { name, size })
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                host.rune_model_select_variant(&variants)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_model_name",
+        |mut caller: Caller<'_, Env>, name: P, len: P| {
+            with_host(&mut caller, |memory, host| {
+                let name =
+                    utf8(memory, name, len).context("Invalid model name")?;
+                host.rune_model_name(name)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_model_load",
+        |mut caller: Caller<'_, Env>,
+         mimetype: P,
+         mimetype_len: P,
+         model: P,
+         model_len: P,
+         input_descriptors: P,
+         input_len: P,
+         output_descriptors: P,
+         output_len: P| {
+            with_host(&mut caller, |memory, host| {
+                let mimetype = utf8(memory, mimetype, mimetype_len)
+                    .context("Invalid mimetype string")?;
+                let model =
+                    bytes(memory, model, model_len).context("Invalid model")?;
+                let inputs = shapes(memory, input_descriptors, input_len)?;
+                let outputs = shapes(memory, output_descriptors, output_len)?;
+
+                host.rune_model_load(mimetype, model, &inputs, &outputs)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_model_infer",
+        |mut caller: Caller<'_, Env>, model_id: u32, inputs: P, outputs: P| {
+            with_host(&mut caller, |memory, host| {
+                let model = host.model_by_id(model_id).with_context(|| {
+                    format!("No model with ID {}", model_id)
+                })?;
+                let input_offsets =
+                    tensor_offsets(memory, inputs, model.input_shapes())?;
+                let output_offsets =
+                    tensor_offsets(memory, outputs, model.output_shapes())?;
+
+                // Safety: the offsets were bounds checked, and we assume none
+                // of the output tensor buffers are aliased. This should be
+                // fine because the Rune is written in Rust and the borrow
+                // checker will enforce mutable XOR shared.
+                let (inputs, mut outputs) = unsafe {
+                    let base = memory.as_mut_ptr();
+                    let inputs: Vec<&[u8]> = input_offsets
+                        .iter()
+                        .map(|&(start, end)| {
+                            std::slice::from_raw_parts(
+                                base.add(start),
+                                end - start,
+                            )
+                        })
+                        .collect();
+                    let outputs: Vec<&mut [u8]> = output_offsets
+                        .iter()
+                        .map(|&(start, end)| {
+                            std::slice::from_raw_parts_mut(
+                                base.add(start),
+                                end - start,
+                            )
+                        })
+                        .collect();
+                    (inputs, outputs)
+                };
+
+                host.rune_model_infer(model_id, &inputs, &mut outputs)?;
+                Ok(0_u32)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "request_output",
+        |mut caller: Caller<'_, Env>, output_type: u32| {
+            with_host(&mut caller, |_, host| host.request_output(output_type))
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "request_output_set_param",
+        |mut caller: Caller<'_, Env>,
+         output_id: u32,
+         key_ptr: P,
+         key_len: P,
+         value_ptr: P,
+         value_len: P| {
+            with_host(&mut caller, |memory, host| {
+                let key = utf8(memory, key_ptr, key_len)
+                    .context("Unable to read the key")?;
+                let value = utf8(memory, value_ptr, value_len)
+                    .context("Unable to read the value")?;
+
+                host.request_output_set_param(output_id, key, value)?;
+
+                Ok(0_u32)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_node_name",
+        |mut caller: Caller<'_, Env>, id: u32, name: P, len: P| {
+            with_host(&mut caller, |memory, host| {
+                let name =
+                    utf8(memory, name, len).context("Invalid node name")?;
+                host.rune_node_name(id, name)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "consume_output",
+        |mut caller: Caller<'_, Env>, output_id: u32, buffer: P, len: P| {
+            with_host(&mut caller, |memory, host| {
+                let buffer =
+                    bytes(memory, buffer, len).context("Invalid input")?;
+                host.consume_output(output_id, buffer)?;
+                Ok(len)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "consume_output_chunk",
+        |mut caller: Caller<'_, Env>,
+         output_id: u32,
+         offset: u64,
+         buffer: P,
+         len: P,
+         total_len: u64| {
+            with_host(&mut caller, |memory, host| {
+                let buffer =
+                    bytes(memory, buffer, len).context("Invalid input")?;
+                host.consume_output_chunk(
+                    output_id, offset, total_len, buffer,
+                )?;
+                Ok(len)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_resource_open",
+        |mut caller: Caller<'_, Env>, name: P, len: P| {
+            with_host(&mut caller, |memory, host| {
+                let name = utf8(memory, name, len)
+                    .context("Invalid buffer pointer")?;
+                host.rune_resource_open(name)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_resource_read",
+        |mut caller: Caller<'_, Env>, id: u32, dest: P, len: P| {
+            with_host(&mut caller, |memory, host| {
+                let dest = bytes_mut(memory, dest, len)
+                    .context("Invalid buffer pointer")?;
+                host.rune_resource_read(id, dest)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_resource_close",
+        |mut caller: Caller<'_, Env>, id: u32| {
+            with_host(&mut caller, |_, host| host.rune_resource_close(id))
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_proc_block_parameter",
+        |mut caller: Caller<'_, Env>,
+         node: P,
+         node_len: P,
+         type_name: P,
+         type_name_len: P,
+         name: P,
+         name_len: P,
+         value: P,
+         value_len: P| {
+            with_host(&mut caller, |memory, host| {
+                let node = utf8(memory, node, node_len)
+                    .context("Invalid node name")?;
+                let type_name = utf8(memory, type_name, type_name_len)
+                    .context("Invalid proc-block type name")?;
+                let name = utf8(memory, name, name_len)
+                    .context("Invalid parameter name")?;
+                let value = utf8(memory, value, value_len)
+                    .context("Invalid parameter value")?;
+                host.rune_proc_block_parameter(node, type_name, name, value)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_proc_block_parameter_updates",
+        |mut caller: Caller<'_, Env>, dest: P, len: P| {
+            with_host(&mut caller, |memory, host| {
+                let dest = bytes_mut(memory, dest, len)
+                    .context("Invalid buffer pointer")?;
+                host.rune_proc_block_parameter_updates(dest)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_panic",
+        |mut caller: Caller<'_, Env>,
+         msg: P,
+         msg_len: P,
+         file: P,
+         file_len: P,
+         line: u32,
+         column: u32,
+         stage: P,
+         stage_len: P| {
+            with_host(&mut caller, |memory, host| -> Result<u32, Error> {
+                let message = utf8(memory, msg, msg_len)
+                    .context("Unable to read the panic message")?;
+                let file = utf8(memory, file, file_len)
+                    .context("Unable to read the panic location")?;
+                let stage = utf8(memory, stage, stage_len)
+                    .context("Unable to read the stage name")?;
+
+                Err(host.panic(message, file, line, column, stage))
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "rune_assertion_failed",
+        |mut caller: Caller<'_, Env>,
+         expression: P,
+         expression_len: P,
+         message: P,
+         message_len: P,
+         names: P,
+         values: P,
+         count: u32| {
+            with_host(&mut caller, |memory, host| {
+                let expression = utf8(memory, expression, expression_len)
+                    .context("Unable to read the assertion's expression")?;
+                let message = utf8(memory, message, message_len)
+                    .context("Unable to read the assertion's message")?;
+                let names = strings(memory, names, count)
+                    .context("Invalid value names")?;
+                let values =
+                    strings(memory, values, count).context("Invalid values")?;
+                let captured: Vec<(&str, &str)> =
+                    names.into_iter().zip(values).collect();
+
+                host.rune_assertion_failed(expression, message, &captured);
+                Ok(0_u32)
+            })
+        },
+    )?;
+
+    Ok(())
+}
+
+fn stringified(value: hotg_rune_core::Value) -> String {
+    match value {
+        hotg_rune_core::Value::Byte(b) => b.to_string(),
+        hotg_rune_core::Value::Short(s) => s.to_string(),
+        hotg_rune_core::Value::Integer(i) => i.to_string(),
+        hotg_rune_core::Value::Float(f) => f.to_string(),
+        hotg_rune_core::Value::SignedByte(s) => s.to_string(),
+        hotg_rune_core::Value::Double(d) => d.to_string(),
+        _ => unreachable!(),
+    }
+}
+
+/// The address of the `index`'th element in an array of `size`-byte items.
+fn offset(ptr: u64, index: usize, size: usize) -> Result<u64, Error> {
+    index
+        .checked_mul(size)
+        .and_then(|delta| u64::try_from(delta).ok())
+        .and_then(|delta| ptr.checked_add(delta))
+        .context("Pointer overflow")
+}
+
+fn range(
+    memory: &[u8],
+    ptr: impl Into<u64>,
+    len: impl Into<u64>,
+) -> Result<(usize, usize), Error> {
+    let start = usize::try_from(ptr.into()).context("Pointer out of bounds")?;
+    let end = usize::try_from(len.into())
+        .ok()
+        .and_then(|len| start.checked_add(len))
+        .filter(|&end| end <= memory.len())
+        .context("Pointer out of bounds")?;
+
+    Ok((start, end))
+}
+
+fn bytes(
+    memory: &[u8],
+    ptr: impl Into<u64>,
+    len: impl Into<u64>,
+) -> Result<&[u8], Error> {
+    let (start, end) = range(memory, ptr, len)?;
+    Ok(&memory[start..end])
+}
+
+fn bytes_mut(
+    memory: &mut [u8],
+    ptr: impl Into<u64>,
+    len: impl Into<u64>,
+) -> Result<&mut [u8], Error> {
+    let (start, end) = range(memory, ptr, len)?;
+    Ok(&mut memory[start..end])
+}
+
+fn utf8(
+    memory: &[u8],
+    ptr: impl Into<u64>,
+    len: impl Into<u64>,
+) -> Result<&str, Error> {
+    std::str::from_utf8(bytes(memory, ptr, len)?).context("Invalid UTF-8")
+}
+
+/// Read a pointer (or pointer-sized length) stored in linear memory.
+fn read_pointer<P: Pointer>(memory: &[u8], ptr: u64) -> Result<u64, Error> {
+    let raw = bytes(memory, ptr, P::SIZE as u64)?;
+    let mut word = [0_u8; 8];
+    word[..P::SIZE].copy_from_slice(raw);
+    Ok(u64::from_le_bytes(word))
+}
+
+/// Read an array of `count` `StringRef`s (a pointer followed by a
+/// pointer-sized length).
+fn strings<P: Pointer>(
+    memory: &[u8],
+    ptr: P,
+    count: u32,
+) -> Result<Vec<&str>, Error> {
+    (0..count as usize)
+        .map(|i| {
+            let string_ref = offset(ptr.into(), i, 2 * P::SIZE)?;
+            let data = read_pointer::<P>(memory, string_ref)?;
+            let len = read_pointer::<P>(memory, string_ref + P::SIZE as u64)?;
+            utf8(memory, data, len)
+                .with_context(|| format!("The {}'th string is invalid", i))
+        })
+        .collect()
+}
+
+fn shapes<P: Pointer>(
+    memory: &[u8],
+    descriptors: P,
+    len: P,
+) -> Result<Vec<Shape<'static>>, Error> {
+    let count = u32::try_from(len.into()).context("Too many descriptors")?;
+
+    strings(memory, descriptors, count)
+        .context("Invalid descriptor pointer")?
+        .into_iter()
+        .enumerate()
+        .map(|(i, descriptor)| {
+            descriptor.parse().with_context(|| {
+                format!("Unable to parse the {}'th descriptor", i)
+            })
+        })
+        .collect()
+}
+
+/// Find where each tensor in an array of pointers lives in linear memory.
+fn tensor_offsets<P: Pointer>(
+    memory: &[u8],
+    pointers: P,
+    shapes: &[Shape<'_>],
+) -> Result<Vec<(usize, usize)>, Error> {
+    shapes
+        .iter()
+        .enumerate()
+        .map(|(i, shape)| {
+            let ptr = offset(pointers.into(), i, P::SIZE)
+                .and_then(|address| read_pointer::<P>(memory, address))
+                .context("Pointer out of bounds")?;
+            let size = shape
+                .size()
+                .context("The element type is dynamically sized")?;
+            let len = u64::try_from(size).context("The tensor is too big")?;
+
+            range(memory, ptr, len)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_string_refs_from_linear_memory() {
+        let mut memory = vec![0_u8; 32];
+        memory[16..21].copy_from_slice(b"hello");
+        memory[21..26].copy_from_slice(b"world");
+        // [StringRef { data: 16, len: 5 }, StringRef { data: 21, len: 5 }]
+        for (i, value) in [16_u32, 5, 21, 5].iter().enumerate() {
+            memory[i * 4..(i + 1) * 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        assert_eq!(strings(&memory, 0_u32, 2).unwrap(), ["hello", "world"]);
+        assert!(strings(&memory, 0_u32, 5).is_err());
+        assert!(bytes(&memory, 30_u32, 4_u32).is_err());
+    }
+
+    #[test]
+    fn wasm64_string_refs_use_64_bit_pointers() {
+        let mut memory = vec![0_u8; 48];
+        memory[32..37].copy_from_slice(b"hello");
+        memory[37..42].copy_from_slice(b"world");
+        // Each StringRef is a u64 pointer and a u64 length
+        for (i, value) in [32_u64, 5, 37, 5].iter().enumerate() {
+            memory[i * 8..(i + 1) * 8].copy_from_slice(&value.to_le_bytes());
+        }
+
+        assert_eq!(strings(&memory, 0_u64, 2).unwrap(), ["hello", "world"]);
+    }
+
+    #[test]
+    fn memory_can_not_grow_past_the_limit() {
+        let mut limiter = MemoryLimiter::new(Some(2));
+
+        assert!(limiter.memory_growing(0, 2 * WASM_PAGE_SIZE, None));
+        assert_eq!(limiter.exceeded(), None);
+
+        assert!(!limiter.memory_growing(
+            2 * WASM_PAGE_SIZE,
+            3 * WASM_PAGE_SIZE,
+            None
+        ));
+        assert_eq!(
+            limiter.exceeded(),
+            Some(ResourceExhausted::Memory { pages: 2 })
+        );
+
+        limiter.reset();
+        assert_eq!(limiter.exceeded(), None);
+    }
+}
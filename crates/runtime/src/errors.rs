@@ -0,0 +1,185 @@
+use anyhow::Error;
+
+use crate::{
+    cancellation::{Cancelled, DeadlineExceeded},
+    models::UnsupportedModelFormat,
+    AssertionError, LoadError, OutOfFuel, ResourceExhausted, TrapError,
+};
+
+/// Errors raised by the [`crate::Runtime`] itself, as opposed to the Rune or
+/// one of its models.
+///
+/// Like [`TrapError`], these can be retrieved from the [`anyhow::Error`]
+/// returned by the [`crate::Runtime`] using [`anyhow::Error::downcast_ref()`],
+/// although [`ErrorCode::of()`] is usually more convenient.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum RuntimeError {
+    /// The Rune doesn't have a capability with this ID.
+    #[error("There is no capability with ID {}", id)]
+    UnknownCapability { id: u32 },
+    /// The Rune doesn't have an output with this ID.
+    #[error("There is no output with ID {}", id)]
+    UnknownOutput { id: u32 },
+    /// The Rune asked for a capability's input before it was provided.
+    #[error(
+        "No input tensor provided for the \"{}\" capability with ID {}",
+        kind,
+        id
+    )]
+    MissingInput { kind: String, id: u32 },
+}
+
+impl RuntimeError {
+    /// The [`ErrorCode`] for this error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RuntimeError::UnknownCapability { .. } => {
+                ErrorCode::UnknownCapability
+            },
+            RuntimeError::UnknownOutput { .. } => ErrorCode::UnknownOutput,
+            RuntimeError::MissingInput { .. } => ErrorCode::MissingInput,
+        }
+    }
+}
+
+/// A stable, machine-readable code for the different ways the runtime can
+/// fail.
+///
+/// The numeric values will never change, so they are safe to pass across
+/// the FFI boundary or store in logs.
+///
+/// cbindgen:prefix-with-name
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(u32)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// An error which doesn't fall into any of the other categories.
+    Other = 0,
+    /// The Rune isn't valid WebAssembly.
+    InvalidWebAssembly = 1,
+    /// The Rune imports host functions the runtime doesn't provide.
+    UnknownImports = 2,
+    /// The Rune uses WebAssembly SIMD, but the engine doesn't support it.
+    SimdNotSupported = 3,
+    /// One of the Rune's models is in a format the runtime can't load.
+    UnsupportedModelFormat = 4,
+    /// See [`RuntimeError::UnknownCapability`].
+    UnknownCapability = 5,
+    /// See [`RuntimeError::UnknownOutput`].
+    UnknownOutput = 6,
+    /// See [`RuntimeError::MissingInput`].
+    MissingInput = 7,
+    /// The Rune panicked (see [`TrapError`]).
+    Trap = 8,
+    /// A `rune_assert!()` failed (see [`AssertionError`]).
+    AssertionFailed = 9,
+    /// The Rune hit a resource limit (see [`ResourceExhausted`]).
+    ResourceExhausted = 10,
+    /// The prediction was cancelled (see [`Cancelled`]).
+    Cancelled = 11,
+    /// The prediction took too long (see [`DeadlineExceeded`]).
+    DeadlineExceeded = 12,
+    /// The Rune was compiled for wasm64, but the engine only supports
+    /// 32-bit memories.
+    Memory64NotSupported = 13,
+}
+
+impl ErrorCode {
+    /// Classify an error by looking for well-known error types in its chain
+    /// of causes, starting from the outermost one.
+    pub fn of(error: &Error) -> ErrorCode {
+        // A deadline is attached to the Cancelled error using .context(), and
+        // context values don't show up when walking the chain.
+        if error.is::<DeadlineExceeded>() {
+            return ErrorCode::DeadlineExceeded;
+        }
+
+        error
+            .chain()
+            .find_map(specific_error_code)
+            .unwrap_or(ErrorCode::Other)
+    }
+}
+
+fn specific_error_code(
+    e: &(dyn std::error::Error + 'static),
+) -> Option<ErrorCode> {
+    if let Some(e) = e.downcast_ref::<RuntimeError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = e.downcast_ref::<LoadError>() {
+        return load_error_code(e);
+    }
+    if e.is::<UnsupportedModelFormat>() {
+        return Some(ErrorCode::UnsupportedModelFormat);
+    }
+    if e.is::<DeadlineExceeded>() {
+        return Some(ErrorCode::DeadlineExceeded);
+    }
+    if e.is::<Cancelled>() {
+        return Some(ErrorCode::Cancelled);
+    }
+    if e.is::<ResourceExhausted>() || e.is::<OutOfFuel>() {
+        return Some(ErrorCode::ResourceExhausted);
+    }
+    if e.is::<AssertionError>() {
+        return Some(ErrorCode::AssertionFailed);
+    }
+    if e.is::<TrapError>() {
+        return Some(ErrorCode::Trap);
+    }
+
+    None
+}
+
+fn load_error_code(error: &LoadError) -> Option<ErrorCode> {
+    match error {
+        LoadError::UnknownImports(_) => Some(ErrorCode::UnknownImports),
+        LoadError::SimdNotSupported => Some(ErrorCode::SimdNotSupported),
+        LoadError::Memory64NotSupported => {
+            Some(ErrorCode::Memory64NotSupported)
+        },
+        // Note: transparent errors skip the wrapped error in the chain, so
+        // we need to look inside it ourselves.
+        LoadError::Other(e) => e.chain().find_map(specific_error_code),
+        #[cfg(feature = "wasmer")]
+        LoadError::WasmerInstantiation(::wasmer::InstantiationError::Link(
+            ::wasmer::LinkError::Import(..),
+        )) => Some(ErrorCode::UnknownImports),
+        #[cfg(feature = "wasmer")]
+        LoadError::WasmerCompile(
+            ::wasmer::CompileError::Wasm(
+                ::wasmer::WasmError::InvalidWebAssembly { .. },
+            )
+            | ::wasmer::CompileError::Validate(_),
+        ) => Some(ErrorCode::InvalidWebAssembly),
+        #[cfg(feature = "wasmer")]
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_errors_by_the_first_known_cause() {
+        let missing = Error::from(RuntimeError::MissingInput {
+            kind: "RAW".to_string(),
+            id: 1,
+        })
+        .context("Unable to run the Rune");
+        let deadline = Error::from(Cancelled).context(DeadlineExceeded {
+            timeout: std::time::Duration::from_millis(10),
+        });
+        let model = Error::from(LoadError::Other(
+            UnsupportedModelFormat::new("application/x-unknown").into(),
+        ));
+
+        assert_eq!(ErrorCode::of(&missing), ErrorCode::MissingInput);
+        assert_eq!(ErrorCode::of(&deadline), ErrorCode::DeadlineExceeded);
+        assert_eq!(ErrorCode::of(&model), ErrorCode::UnsupportedModelFormat);
+        assert_eq!(ErrorCode::of(&Error::msg("oops")), ErrorCode::Other);
+    }
+}
@@ -0,0 +1,382 @@
+//! Running several Runes side by side in a single process.
+//!
+//! Gateways often run a handful of Runes against the same sensors (e.g. one
+//! camera feeding a person detector, a pose estimator, and a licence plate
+//! reader). A [`Scheduler`] owns each of the loaded [`Runtime`]s, lets a
+//! single reading from a physical sensor be fanned out to every capability
+//! that is subscribed to it, and decides which Rune gets to run next.
+//!
+//! ```rust,no_run
+//! # fn load(_: &str) -> hotg_rune_runtime::Runtime { unimplemented!() }
+//! # fn read_camera() -> hotg_rune_runtime::Tensor { unimplemented!() }
+//! use hotg_rune_runtime::fleet::{Policy, Scheduler};
+//!
+//! let mut scheduler = Scheduler::new(Policy::Priority);
+//! let people = scheduler.add("people", load("people.rune"), 10);
+//! let poses = scheduler.add("poses", load("poses.rune"), 1);
+//!
+//! // Every IMAGE capability will receive frames from the camera
+//! scheduler.subscribe_kind("camera", "IMAGE");
+//!
+//! loop {
+//!     scheduler.publish("camera", read_camera());
+//!
+//!     for result in scheduler.run_ready() {
+//!         let (rune, outcome) = result;
+//!         if let Err(e) = outcome {
+//!             eprintln!("{} failed: {:?}", scheduler.name(rune), e);
+//!         }
+//!     }
+//! #   let _ = (people, poses);
+//! #   break;
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+
+use crate::{Runtime, Tensor};
+
+/// A handle to a Rune owned by a [`Scheduler`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RuneId(usize);
+
+/// How a [`Scheduler`] chooses which Rune to run next.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Policy {
+    /// Take turns, running each Rune with new input in order.
+    RoundRobin,
+    /// Always run the Rune with the highest priority first, taking turns
+    /// between Runes with the same priority.
+    Priority,
+}
+
+impl Default for Policy {
+    fn default() -> Self { Policy::RoundRobin }
+}
+
+/// Statistics for a single Rune managed by a [`Scheduler`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RuneMetrics {
+    /// The number of successful predictions.
+    pub predictions: usize,
+    /// The number of predictions which returned an error.
+    pub failures: usize,
+    /// The number of inputs which were replaced by a newer reading before
+    /// the Rune got a chance to run.
+    pub dropped_inputs: usize,
+    /// The total time spent running the Rune.
+    pub total_time: Duration,
+    /// How long the most recent prediction took.
+    pub last_duration: Duration,
+}
+
+impl RuneMetrics {
+    /// The mean time taken by each prediction, including failures.
+    pub fn mean_duration(&self) -> Option<Duration> {
+        let runs = self.predictions + self.failures;
+
+        if runs == 0 {
+            None
+        } else {
+            Some(self.total_time / runs as u32)
+        }
+    }
+}
+
+/// Metrics for every Rune managed by a [`Scheduler`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FleetMetrics {
+    /// The number of readings passed to [`Scheduler::publish()`].
+    pub published: usize,
+    /// Metrics for each Rune, keyed by the name it was added with.
+    pub runes: HashMap<String, RuneMetrics>,
+}
+
+/// Owns a set of [`Runtime`]s and shares sensor readings between them.
+///
+/// See the [`crate::fleet`] module for more.
+pub struct Scheduler {
+    policy: Policy,
+    runes: Vec<Entry>,
+    /// Which capabilities (as a `(rune, capability_id)` pair) receive the
+    /// readings from each source.
+    subscriptions: HashMap<String, Vec<(RuneId, u32)>>,
+    /// The index to start looking from when picking the next Rune.
+    cursor: usize,
+    published: usize,
+}
+
+struct Entry {
+    name: String,
+    runtime: Runtime,
+    priority: u32,
+    /// Capabilities which have received a reading since the last run.
+    fresh: Vec<u32>,
+    metrics: RuneMetrics,
+}
+
+impl Scheduler {
+    pub fn new(policy: Policy) -> Self {
+        Scheduler {
+            policy,
+            runes: Vec::new(),
+            subscriptions: HashMap::new(),
+            cursor: 0,
+            published: 0,
+        }
+    }
+
+    /// Take ownership of a [`Runtime`].
+    ///
+    /// The `priority` is only used with [`Policy::Priority`], where larger
+    /// numbers run first.
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        runtime: Runtime,
+        priority: u32,
+    ) -> RuneId {
+        let id = RuneId(self.runes.len());
+
+        self.runes.push(Entry {
+            name: name.into(),
+            runtime,
+            priority,
+            fresh: Vec::new(),
+            metrics: RuneMetrics::default(),
+        });
+
+        id
+    }
+
+    /// Send readings from a `source` to a particular capability.
+    pub fn subscribe(
+        &mut self,
+        source: impl Into<String>,
+        rune: RuneId,
+        capability_id: u32,
+    ) -> Result<(), Error> {
+        let entry = self.entry(rune)?;
+
+        if !entry.runtime.capabilities().contains_key(&capability_id) {
+            anyhow::bail!(
+                "\"{}\" has no capability with ID {}",
+                entry.name,
+                capability_id
+            );
+        }
+
+        let subscribers = self.subscriptions.entry(source.into()).or_default();
+        if !subscribers.contains(&(rune, capability_id)) {
+            subscribers.push((rune, capability_id));
+        }
+
+        Ok(())
+    }
+
+    /// Send readings from a `source` to every capability of a particular
+    /// kind (e.g. `"IMAGE"`) across all Runes added so far, returning the
+    /// number of capabilities that were subscribed.
+    pub fn subscribe_kind(
+        &mut self,
+        source: impl Into<String>,
+        kind: &str,
+    ) -> usize {
+        let mut matches = Vec::new();
+
+        for (i, entry) in self.runes.iter().enumerate() {
+            let mut ids: Vec<u32> = entry
+                .runtime
+                .capabilities()
+                .iter()
+                .filter(|(_, meta)| meta.kind == kind)
+                .map(|(&id, _)| id)
+                .collect();
+            ids.sort_unstable();
+            matches.extend(ids.into_iter().map(|id| (RuneId(i), id)));
+        }
+
+        let subscribers = self.subscriptions.entry(source.into()).or_default();
+        let mut added = 0;
+
+        for subscription in matches {
+            if !subscribers.contains(&subscription) {
+                subscribers.push(subscription);
+                added += 1;
+            }
+        }
+
+        added
+    }
+
+    /// Pass a reading from a `source` to every capability subscribed to it.
+    ///
+    /// Runes which haven't consumed the previous reading will only see the
+    /// newest one, and the older reading is counted as dropped.
+    pub fn publish(&mut self, source: &str, reading: Tensor) {
+        self.published += 1;
+
+        let subscribers = match self.subscriptions.get(source) {
+            Some(s) => s,
+            None => return,
+        };
+
+        for &(RuneId(index), capability_id) in subscribers {
+            let entry = &mut self.runes[index];
+
+            if entry.fresh.contains(&capability_id) {
+                entry.metrics.dropped_inputs += 1;
+            } else {
+                entry.fresh.push(capability_id);
+            }
+
+            entry
+                .runtime
+                .input_tensors()
+                .insert(capability_id, reading.clone());
+        }
+    }
+
+    /// Run the next Rune that has received new input, according to the
+    /// scheduling [`Policy`].
+    ///
+    /// Returns `None` when no Rune has new input.
+    pub fn run_next(&mut self) -> Option<(RuneId, Result<(), Error>)> {
+        let candidates: Vec<_> = self
+            .runes
+            .iter()
+            .map(|e| (e.priority, !e.fresh.is_empty()))
+            .collect();
+        let index = pick(self.policy, &candidates, self.cursor)?;
+        self.cursor = (index + 1) % self.runes.len();
+
+        let entry = &mut self.runes[index];
+        entry.fresh.clear();
+
+        let start = Instant::now();
+        let result = entry.runtime.predict();
+        let duration = start.elapsed();
+
+        entry.metrics.total_time += duration;
+        entry.metrics.last_duration = duration;
+        match result {
+            Ok(_) => entry.metrics.predictions += 1,
+            Err(_) => entry.metrics.failures += 1,
+        }
+
+        Some((RuneId(index), result))
+    }
+
+    /// Keep running Runes until none of them have new input, returning the
+    /// result of each run in the order they happened.
+    pub fn run_ready(&mut self) -> Vec<(RuneId, Result<(), Error>)> {
+        let mut results = Vec::new();
+
+        while let Some(result) = self.run_next() {
+            results.push(result);
+        }
+
+        results
+    }
+
+    /// Does any Rune have input it hasn't been run with yet?
+    pub fn has_pending(&self) -> bool {
+        self.runes.iter().any(|e| !e.fresh.is_empty())
+    }
+
+    /// The name a Rune was [added][Scheduler::add] with.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the [`RuneId`] came from a different [`Scheduler`].
+    pub fn name(&self, rune: RuneId) -> &str { &self.runes[rune.0].name }
+
+    pub fn runtime(&self, rune: RuneId) -> Option<&Runtime> {
+        self.runes.get(rune.0).map(|e| &e.runtime)
+    }
+
+    pub fn runtime_mut(&mut self, rune: RuneId) -> Option<&mut Runtime> {
+        self.runes.get_mut(rune.0).map(|e| &mut e.runtime)
+    }
+
+    pub fn set_priority(&mut self, rune: RuneId, priority: u32) {
+        if let Some(entry) = self.runes.get_mut(rune.0) {
+            entry.priority = priority;
+        }
+    }
+
+    pub fn metrics(&self) -> FleetMetrics {
+        FleetMetrics {
+            published: self.published,
+            runes: self
+                .runes
+                .iter()
+                .map(|e| (e.name.clone(), e.metrics.clone()))
+                .collect(),
+        }
+    }
+
+    fn entry(&self, rune: RuneId) -> Result<&Entry, Error> {
+        self.runes
+            .get(rune.0)
+            .ok_or_else(|| Error::msg("Unknown Rune"))
+    }
+}
+
+/// Pick the index of the next Rune to run, given each Rune's priority and
+/// whether it has new input.
+///
+/// Searching starts at `cursor` and wraps around so Runes with the same
+/// priority take turns.
+fn pick(
+    policy: Policy,
+    candidates: &[(u32, bool)],
+    cursor: usize,
+) -> Option<usize> {
+    let len = candidates.len();
+    let mut ready = (0..len)
+        .map(|offset| (cursor + offset) % len)
+        .filter(|&i| candidates[i].1);
+
+    match policy {
+        Policy::RoundRobin => ready.next(),
+        Policy::Priority => {
+            // Note: max_by_key() returns the last maximum, but we want the
+            // first one after the cursor.
+            ready.fold(None, |best: Option<usize>, i| match best {
+                Some(b) if candidates[b].0 >= candidates[i].0 => Some(b),
+                _ => Some(i),
+            })
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_robin_takes_turns() {
+        let candidates = [(0, true), (5, false), (9, true)];
+
+        assert_eq!(pick(Policy::RoundRobin, &candidates, 0), Some(0));
+        assert_eq!(pick(Policy::RoundRobin, &candidates, 1), Some(2));
+        assert_eq!(pick(Policy::RoundRobin, &candidates, 2), Some(2));
+        assert_eq!(pick(Policy::RoundRobin, &[(0, false)], 0), None);
+    }
+
+    #[test]
+    fn highest_priority_goes_first() {
+        let candidates = [(1, true), (5, true), (5, true), (9, false)];
+
+        assert_eq!(pick(Policy::Priority, &candidates, 0), Some(1));
+        // Runes with the same priority take turns
+        assert_eq!(pick(Policy::Priority, &candidates, 2), Some(2));
+        assert_eq!(pick(Policy::Priority, &candidates, 3), Some(1));
+    }
+}
@@ -0,0 +1,251 @@
+//! Remembering the inputs from recent runs so failures in the field can be
+//! reproduced.
+//!
+//! The flight recorder is opt-in. Once enabled with
+//! [`Runtime::enable_flight_recorder()`][enable], the runtime keeps a copy of
+//! the last few inputs for each capability in a fixed-size ring buffer. When
+//! [`Runtime::predict()`][predict] fails, those inputs and a profile of the
+//! recent runs are captured in a [`FailureReport`] which can be saved as a
+//! diagnostics bundle.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), anyhow::Error> {
+//! # let mut runtime: hotg_rune_runtime::Runtime = unimplemented!();
+//! runtime.enable_flight_recorder(8);
+//!
+//! if let Err(e) = runtime.predict() {
+//!     if let Some(report) = runtime.last_failure_report() {
+//!         report.save("crash.json")?;
+//!     }
+//!     return Err(e);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [enable]: crate::Runtime::enable_flight_recorder
+//! [predict]: crate::Runtime::predict
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Error};
+use serde_json::{json, Value};
+
+use crate::Tensor;
+
+/// Timing information for a single call to `predict()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RunProfile {
+    /// The run's sequence number, starting from `1`.
+    pub run: u64,
+    /// When the run started.
+    pub started: SystemTime,
+    pub duration: Duration,
+    pub succeeded: bool,
+}
+
+/// Everything we know about a failed run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureReport {
+    /// The failed run's sequence number.
+    pub run: u64,
+    /// The error message, followed by each of its causes.
+    pub errors: Vec<String>,
+    /// The recorded inputs for each capability, oldest first. The last
+    /// tensor is the input that triggered the failure.
+    pub inputs: BTreeMap<u32, Vec<Tensor>>,
+    /// The most recent runs, oldest first, ending with the failed run.
+    pub runs: Vec<RunProfile>,
+}
+
+impl FailureReport {
+    /// Convert the report to JSON.
+    pub fn to_json(&self) -> Value {
+        let inputs: BTreeMap<String, Vec<_>> = self
+            .inputs
+            .iter()
+            .map(|(id, tensors)| {
+                (
+                    id.to_string(),
+                    tensors.iter().map(|t| t.serializable()).collect(),
+                )
+            })
+            .collect();
+
+        let runs: Vec<_> = self
+            .runs
+            .iter()
+            .map(|profile| {
+                let started = profile
+                    .started
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+
+                json!({
+                    "run": profile.run,
+                    "started_ms": started.as_millis() as u64,
+                    "duration_us": profile.duration.as_micros() as u64,
+                    "succeeded": profile.succeeded,
+                })
+            })
+            .collect();
+
+        json!({
+            "run": self.run,
+            "errors": self.errors,
+            "inputs": inputs,
+            "runs": runs,
+        })
+    }
+
+    /// Save the report as a JSON diagnostics bundle.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec_pretty(&self.to_json())
+            .context("Unable to serialize the report")?;
+
+        std::fs::write(path, json).with_context(|| {
+            format!("Unable to write to \"{}\"", path.display())
+        })
+    }
+}
+
+/// A bounded history of inputs and runs.
+#[derive(Debug, Clone)]
+pub(crate) struct FlightRecorder {
+    capacity: usize,
+    run: u64,
+    inputs: HashMap<u32, VecDeque<Tensor>>,
+    runs: VecDeque<RunProfile>,
+    started: Option<SystemTime>,
+    last_failure: Option<FailureReport>,
+}
+
+impl FlightRecorder {
+    /// Create a recorder which remembers the last `capacity` inputs for each
+    /// capability.
+    pub(crate) fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+
+        FlightRecorder {
+            capacity,
+            run: 0,
+            inputs: HashMap::new(),
+            runs: VecDeque::with_capacity(capacity),
+            started: None,
+            last_failure: None,
+        }
+    }
+
+    /// Record the inputs for the run that is about to start.
+    pub(crate) fn start_run(
+        &mut self,
+        inputs: &HashMap<u32, Tensor>,
+        started: SystemTime,
+    ) {
+        self.run += 1;
+        self.started = Some(started);
+
+        for (&id, tensor) in inputs {
+            let history = self.inputs.entry(id).or_default();
+
+            if history.len() == self.capacity {
+                history.pop_front();
+            }
+            history.push_back(tensor.clone());
+        }
+    }
+
+    /// Record how the run went, generating a [`FailureReport`] if it failed.
+    pub(crate) fn finish_run(
+        &mut self,
+        duration: Duration,
+        error: Option<&Error>,
+    ) {
+        if self.runs.len() == self.capacity {
+            self.runs.pop_front();
+        }
+        self.runs.push_back(RunProfile {
+            run: self.run,
+            started: self.started.take().unwrap_or_else(SystemTime::now),
+            duration,
+            succeeded: error.is_none(),
+        });
+
+        if let Some(error) = error {
+            self.last_failure = Some(FailureReport {
+                run: self.run,
+                errors: error.chain().map(|e| e.to_string()).collect(),
+                inputs: self
+                    .inputs
+                    .iter()
+                    .map(|(&id, history)| {
+                        (id, history.iter().cloned().collect())
+                    })
+                    .collect(),
+                runs: self.runs.iter().copied().collect(),
+            });
+        }
+    }
+
+    pub(crate) fn last_failure(&self) -> Option<&FailureReport> {
+        self.last_failure.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(value: f32) -> HashMap<u32, Tensor> {
+        let mut inputs = HashMap::new();
+        inputs.insert(1, Tensor::new(&[value], &[1]));
+        inputs
+    }
+
+    #[test]
+    fn only_the_most_recent_inputs_are_kept() {
+        let mut recorder = FlightRecorder::new(2);
+
+        for i in 0..5 {
+            recorder.start_run(&inputs(i as f32), UNIX_EPOCH);
+            recorder.finish_run(Duration::from_millis(1), None);
+        }
+
+        let history: Vec<_> = recorder.inputs[&1].iter().cloned().collect();
+        assert_eq!(
+            history,
+            vec![Tensor::new(&[3.0_f32], &[1]), Tensor::new(&[4.0_f32], &[1])]
+        );
+        assert_eq!(recorder.runs.len(), 2);
+        assert!(recorder.last_failure().is_none());
+    }
+
+    #[test]
+    fn failures_capture_the_triggering_input() {
+        let mut recorder = FlightRecorder::new(4);
+        recorder.start_run(&inputs(1.0), UNIX_EPOCH);
+        recorder.finish_run(Duration::from_millis(1), None);
+        recorder.start_run(&inputs(2.0), UNIX_EPOCH);
+        let error = Error::msg("Out of bounds").context("The Rune trapped");
+
+        recorder.finish_run(Duration::from_millis(3), Some(&error));
+
+        let report = recorder.last_failure().unwrap();
+        assert_eq!(report.run, 2);
+        assert_eq!(report.errors, vec!["The Rune trapped", "Out of bounds"]);
+        assert_eq!(
+            report.inputs[&1].last(),
+            Some(&Tensor::new(&[2.0_f32], &[1]))
+        );
+        assert_eq!(
+            report.runs.iter().map(|r| r.succeeded).collect::<Vec<_>>(),
+            vec![true, false]
+        );
+        assert_eq!(report.to_json()["errors"][1], "Out of bounds");
+    }
+}
@@ -0,0 +1,457 @@
+//! Checking a compiled Rune against a labelled dataset.
+//!
+//! Where [`crate::embedded_tests`] runs the handful of cases baked into the
+//! Rune by its author, the harness runs the Rune over a dataset described by
+//! a manifest, so models can be regression tested after every retrain
+//! without writing a new driver each time.
+//!
+//! A manifest is YAML or JSON and lists the files to use for each capability
+//! and the values each output should produce.
+//!
+//! ```yaml
+//! # The default tolerance for every output
+//! tolerance: 0.01
+//! samples:
+//!   - name: yes_01
+//!     inputs:
+//!       - capability: audio
+//!         file: audio/yes_01.bin
+//!         element_type: i16
+//!         dimensions: [16000]
+//!     outputs:
+//!       - output: serial
+//!         values: [0.0, 0.0, 1.0, 0.0]
+//!   - name: unknown_07
+//!     inputs:
+//!       - capability: audio
+//!         file: audio/unknown_07.bin
+//!         element_type: i16
+//!         dimensions: [16000]
+//!     outputs:
+//!       - output: serial
+//!         values: [0.0, 1.0, 0.0, 0.0]
+//!         tolerance: 0.1
+//! ```
+//!
+//! Capabilities and outputs are referred to by their name in the Runefile.
+//! Runes compiled before node names were reported to the runtime can use the
+//! node's index instead (i.e. `capability: 0` for the first capability).
+//! Files contain the raw bytes for the tensor, and paths are relative to the
+//! manifest.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hotg_rune_runtime::harness::{self, Manifest};
+//!
+//! let rune = std::fs::read("microspeech.rune")?;
+//! let manifest = Manifest::load("dataset/manifest.yaml")?;
+//!
+//! let report = harness::run(&rune, &manifest)?;
+//! println!("{}", report);
+//!
+//! if !report.passed() {
+//!     std::process::exit(1);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+
+use crate::{
+    embedded_tests::{as_f64, raw_tensor, sorted_ids},
+    ElementType, OutputTensor, Runtime,
+};
+
+/// A dataset to run a Rune over.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct Manifest {
+    /// The tolerance used by any [`ExpectedOutput`] that doesn't specify its
+    /// own.
+    #[serde(default)]
+    pub tolerance: f64,
+    pub samples: Vec<Sample>,
+}
+
+/// A single entry in the dataset.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct Sample {
+    pub name: String,
+    #[serde(default)]
+    pub inputs: Vec<SampleInput>,
+    #[serde(default)]
+    pub outputs: Vec<ExpectedOutput>,
+}
+
+/// The file to use as a capability's input tensor.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct SampleInput {
+    pub capability: NodeRef,
+    pub file: PathBuf,
+    pub element_type: ElementType,
+    pub dimensions: Vec<usize>,
+}
+
+/// The values an output should receive.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct ExpectedOutput {
+    pub output: NodeRef,
+    pub values: Vec<f64>,
+    #[serde(default)]
+    pub tolerance: Option<f64>,
+}
+
+/// How a [`Sample`] refers to a capability or output.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(untagged)]
+pub enum NodeRef {
+    /// The node's position among the Rune's capabilities or outputs.
+    Index(usize),
+    /// The node's name in the Runefile.
+    Name(String),
+}
+
+impl NodeRef {
+    fn find(&self, runtime: &Runtime, ids: &[u32]) -> Option<u32> {
+        match self {
+            NodeRef::Index(index) => ids.get(*index).copied(),
+            NodeRef::Name(name) => runtime
+                .node_names()
+                .get(name)
+                .copied()
+                .filter(|id| ids.contains(id)),
+        }
+    }
+}
+
+impl Display for NodeRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeRef::Index(index) => write!(f, "{}", index),
+            NodeRef::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+impl Manifest {
+    /// Read a manifest from disk, using its extension to decide whether it is
+    /// YAML or JSON.
+    ///
+    /// Any relative paths are resolved relative to the manifest's directory.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path).with_context(|| {
+            format!("Unable to read \"{}\"", path.display())
+        })?;
+
+        let mut manifest = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Manifest::from_json(&src)?,
+            _ => Manifest::from_yaml(&src)?,
+        };
+
+        if let Some(parent) = path.parent() {
+            manifest.resolve_paths(parent);
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn from_yaml(src: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(src).context("Unable to parse the manifest")
+    }
+
+    pub fn from_json(src: &str) -> Result<Self, Error> {
+        serde_json::from_str(src).context("Unable to parse the manifest")
+    }
+
+    fn resolve_paths(&mut self, base_dir: &Path) {
+        for input in self.samples.iter_mut().flat_map(|s| &mut s.inputs) {
+            if input.file.is_relative() {
+                input.file = base_dir.join(&input.file);
+            }
+        }
+    }
+}
+
+/// Run the Rune over every [`Sample`] in a [`Manifest`].
+///
+/// Each sample gets a fresh [`Runtime`] so state can't leak between them. An
+/// error is only returned when the Rune can't be loaded, any problems with an
+/// individual sample are recorded in its [`SampleReport`].
+pub fn run(rune: &[u8], manifest: &Manifest) -> Result<Report, Error> {
+    let mut samples = Vec::new();
+
+    for sample in &manifest.samples {
+        let mut runtime = Runtime::builder()
+            .rune(rune)
+            .build()
+            .context("Unable to load the Rune")?;
+
+        let outcome = run_sample(&mut runtime, sample, manifest.tolerance);
+        let report = match outcome {
+            Ok(diffs) => SampleReport {
+                name: sample.name.clone(),
+                diffs,
+                error: None,
+            },
+            Err(e) => SampleReport {
+                name: sample.name.clone(),
+                diffs: Vec::new(),
+                error: Some(format!("{:#}", e)),
+            },
+        };
+
+        samples.push(report);
+    }
+
+    Ok(Report { samples })
+}
+
+fn run_sample(
+    runtime: &mut Runtime,
+    sample: &Sample,
+    default_tolerance: f64,
+) -> Result<Vec<Diff>, Error> {
+    let capability_ids = sorted_ids(runtime.capabilities().keys());
+
+    for input in &sample.inputs {
+        let id = input
+            .capability
+            .find(runtime, &capability_ids)
+            .with_context(|| {
+                format!("The Rune has no capability {}", input.capability)
+            })?;
+        let data = std::fs::read(&input.file).with_context(|| {
+            format!("Unable to read \"{}\"", input.file.display())
+        })?;
+        let tensor = raw_tensor(
+            &input.file.display().to_string(),
+            input.element_type,
+            &input.dimensions,
+            &data,
+        )?;
+
+        runtime.input_tensors().insert(id, tensor);
+    }
+
+    runtime.predict()?;
+
+    let output_ids = sorted_ids(runtime.outputs().keys());
+    let mut diffs = Vec::new();
+
+    for expected in &sample.outputs {
+        let tolerance = expected.tolerance.unwrap_or(default_tolerance);
+        let written = expected
+            .output
+            .find(runtime, &output_ids)
+            .and_then(|id| runtime.output_tensors().get(&id))
+            .and_then(|tensors| tensors.last())
+            .with_context(|| {
+                format!("Nothing was written to output {}", expected.output)
+            })?;
+
+        diffs.extend(compare(expected, written, tolerance)?);
+    }
+
+    Ok(diffs)
+}
+
+fn compare(
+    expected: &ExpectedOutput,
+    actual: &OutputTensor,
+    tolerance: f64,
+) -> Result<Vec<Diff>, Error> {
+    let values = match actual {
+        OutputTensor::Tensor(t) => as_f64(t),
+        OutputTensor::StringTensor { .. } => anyhow::bail!(
+            "Output {} received a string tensor, but only numeric outputs can \
+             be checked",
+            expected.output
+        ),
+    };
+
+    anyhow::ensure!(
+        values.len() == expected.values.len(),
+        "Output {} should have {} elements, but it received {}",
+        expected.output,
+        expected.values.len(),
+        values.len()
+    );
+
+    let diffs = values
+        .iter()
+        .zip(&expected.values)
+        .enumerate()
+        .filter(|(_, (actual, expected))| {
+            (*actual - *expected).abs() > tolerance
+        })
+        .map(|(index, (&actual, &expected_value))| Diff {
+            output: expected.output.clone(),
+            index,
+            expected: expected_value,
+            actual,
+            tolerance,
+        })
+        .collect();
+
+    Ok(diffs)
+}
+
+/// The outcome of running a Rune over a [`Manifest`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub samples: Vec<SampleReport>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool { self.samples.iter().all(|s| s.passed()) }
+
+    /// The samples which didn't produce the expected outputs.
+    pub fn failures(&self) -> impl Iterator<Item = &SampleReport> + '_ {
+        self.samples.iter().filter(|s| !s.passed())
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for sample in &self.samples {
+            writeln!(f, "{}", sample)?;
+        }
+
+        let failed = self.failures().count();
+        write!(
+            f,
+            "{} passed, {} failed",
+            self.samples.len() - failed,
+            failed
+        )
+    }
+}
+
+/// The outcome of running a single [`Sample`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleReport {
+    pub name: String,
+    /// Every output element that was outside its tolerance.
+    pub diffs: Vec<Diff>,
+    /// Why the sample couldn't be checked (e.g. a missing file or the Rune
+    /// crashing).
+    pub error: Option<String>,
+}
+
+impl SampleReport {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.diffs.is_empty()
+    }
+}
+
+impl Display for SampleReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            return write!(f, "{} ... ok", self.name);
+        }
+
+        write!(f, "{} ... FAILED", self.name)?;
+
+        if let Some(error) = &self.error {
+            write!(f, "\n  {}", error)?;
+        }
+        for diff in &self.diffs {
+            write!(f, "\n  {}", diff)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An output element which didn't match the expected value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diff {
+    pub output: NodeRef,
+    /// The element's index within the output tensor.
+    pub index: usize,
+    pub expected: f64,
+    pub actual: f64,
+    pub tolerance: f64,
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "output {}[{}] should be {} ± {}, but it was {}",
+            self.output, self.index, self.expected, self.tolerance, self.actual
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tensor;
+
+    #[test]
+    fn relative_paths_are_relative_to_the_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifest.yaml");
+        std::fs::write(
+            &path,
+            "samples:\n  - name: yes\n    inputs:\n      - capability: 0\n        \
+             file: yes.bin\n        element_type: i16\n        dimensions: \
+             [2]\n",
+        )
+        .unwrap();
+
+        let manifest = Manifest::load(&path).unwrap();
+
+        let input = &manifest.samples[0].inputs[0];
+        assert_eq!(input.capability, NodeRef::Index(0));
+        assert_eq!(input.file, dir.path().join("yes.bin"));
+        assert_eq!(input.element_type, ElementType::I16);
+        assert_eq!(manifest.tolerance, 0.0);
+    }
+
+    #[test]
+    fn nodes_can_be_referred_to_by_name() {
+        let manifest =
+            Manifest::from_yaml(
+                "samples:\n  - name: yes\n    outputs:\n      - output: \
+                 serial\n        values: [1.0]\n",
+            )
+            .unwrap();
+
+        let output = &manifest.samples[0].outputs[0].output;
+        assert_eq!(output, &NodeRef::Name("serial".to_string()));
+        assert_eq!(output.to_string(), "\"serial\"");
+    }
+
+    #[test]
+    fn report_the_elements_outside_the_tolerance() {
+        let expected = ExpectedOutput {
+            output: NodeRef::Index(1),
+            values: vec![0.0, 1.0],
+            tolerance: None,
+        };
+        let actual = OutputTensor::Tensor(Tensor::new(&[0.05_f32, 0.5], &[2]));
+
+        let diffs = compare(&expected, &actual, 0.1).unwrap();
+
+        let report = Report {
+            samples: vec![SampleReport {
+                name: "yes".to_string(),
+                diffs,
+                error: None,
+            }],
+        };
+        assert_eq!(
+            report.to_string(),
+            "yes ... FAILED\n  output 1[1] should be 1 ± 0.1, but it was \
+             0.5\n0 passed, 1 failed"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! The single place the runtime reports what a Rune is doing.
+//!
+//! Profiling ([`crate::profiling`]), `tracing` spans ([`crate::telemetry`]),
+//! and OpenTelemetry ([`crate::otel`]) all want to know when a prediction
+//! starts and finishes, and how long each capability, model, and output took.
+//! Instead of each of them wrapping models and callbacks separately, the
+//! runtime tells an [`Instrumentation`] and it forwards the event to whichever
+//! of them are enabled.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use hotg_rune_core::Shape;
+
+use crate::{
+    callbacks::{Model, ModelOptions, TensorDescriptor},
+    layout::BufferLayout,
+    profiling::{NodeKind, ProfilerSlot},
+};
+
+#[derive(Clone, Default)]
+pub(crate) struct Instrumentation {
+    profiler: ProfilerSlot,
+    #[cfg(feature = "otel")]
+    tracing: Option<crate::otel::Tracing>,
+}
+
+impl Instrumentation {
+    pub(crate) fn profiler(&self) -> &ProfilerSlot { &self.profiler }
+
+    #[cfg(feature = "otel")]
+    pub(crate) fn set_tracing(&mut self, tracing: crate::otel::Tracing) {
+        self.tracing = Some(tracing);
+    }
+
+    /// A call to [`crate::Runtime::predict()`] is about to run the Rune.
+    pub(crate) fn start_predict(&self) -> Prediction {
+        #[cfg(feature = "otel")]
+        if let Some(tracing) = &self.tracing {
+            tracing.start_predict();
+        }
+
+        Prediction {
+            started: Instant::now(),
+            #[cfg(feature = "telemetry")]
+            _span: crate::telemetry::predict().entered(),
+        }
+    }
+
+    /// The Rune has finished running, returning how long it took.
+    pub(crate) fn finish_predict(
+        &self,
+        prediction: Prediction,
+        error: Option<&Error>,
+    ) -> Duration {
+        let elapsed = prediction.started.elapsed();
+        self.profiler.record_predict(elapsed);
+
+        #[cfg(feature = "otel")]
+        if let Some(tracing) = &self.tracing {
+            tracing.finish_predict(error);
+        }
+        #[cfg(not(feature = "otel"))]
+        let _ = error;
+
+        elapsed
+    }
+
+    /// Run a capability, model, or output.
+    ///
+    /// The `shapes` callback is only used when tracing, to find the shapes of
+    /// the tensors the node produced.
+    pub(crate) fn node<T>(
+        &self,
+        kind: NodeKind,
+        node: &Node<'_>,
+        run: impl FnOnce() -> Result<T, Error>,
+        shapes: impl FnOnce(&T) -> Vec<String>,
+    ) -> Result<T, Error> {
+        self.profiler.time(kind, node.id, || {
+            #[cfg(feature = "telemetry")]
+            let _span = crate::telemetry::node(kind, node).entered();
+
+            #[cfg(feature = "otel")]
+            if let Some(tracing) = &self.tracing {
+                return tracing.record(kind, node, run, shapes);
+            }
+            #[cfg(not(feature = "otel"))]
+            let _ = shapes;
+
+            run()
+        })
+    }
+
+    /// Wrap a [`Model`] so each inference is reported as a
+    /// [`NodeKind::Model`].
+    pub(crate) fn model(
+        &self,
+        id: u32,
+        mimetype: &str,
+        name: Option<&str>,
+        model: Box<dyn Model>,
+    ) -> Box<dyn Model> {
+        Box::new(InstrumentedModel {
+            instrumentation: self.clone(),
+            id,
+            mimetype: mimetype.to_string(),
+            name: name.map(String::from),
+            model,
+        })
+    }
+}
+
+/// A prediction that is currently in progress.
+pub(crate) struct Prediction {
+    started: Instant,
+    #[cfg(feature = "telemetry")]
+    _span: tracing::span::EnteredSpan,
+}
+
+/// The node being reported on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Node<'a> {
+    /// The ID the Rune uses for this node.
+    pub id: u32,
+    /// The node's kind (e.g. `RAW`), or a model's mimetype.
+    pub kind: &'a str,
+    /// The node's name in the Runefile, if the Rune reported it.
+    pub name: Option<&'a str>,
+}
+
+struct InstrumentedModel {
+    instrumentation: Instrumentation,
+    id: u32,
+    mimetype: String,
+    name: Option<String>,
+    model: Box<dyn Model>,
+}
+
+impl Model for InstrumentedModel {
+    fn infer(
+        &mut self,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+    ) -> Result<(), Error> {
+        let InstrumentedModel {
+            instrumentation,
+            id,
+            mimetype,
+            name,
+            model,
+        } = self;
+        let node = Node {
+            id: *id,
+            kind: mimetype,
+            name: name.as_deref(),
+        };
+
+        instrumentation
+            .node(
+                NodeKind::Model,
+                &node,
+                move || {
+                    model.infer(inputs, outputs)?;
+                    Ok(&**model)
+                },
+                |model| {
+                    model.output_shapes().iter().map(Shape::to_string).collect()
+                },
+            )
+            .map(|_| ())
+    }
+
+    fn input_shapes(&self) -> &[Shape<'_>] { self.model.input_shapes() }
+
+    fn output_shapes(&self) -> &[Shape<'_>] { self.model.output_shapes() }
+
+    fn buffer_layout(&self) -> Option<BufferLayout> {
+        self.model.buffer_layout()
+    }
+
+    fn providers(&self) -> Vec<String> { self.model.providers() }
+
+    fn reconfigure(&mut self, options: &ModelOptions) -> Result<(), Error> {
+        self.model.reconfigure(options)
+    }
+
+    fn input_descriptors(&self) -> Vec<TensorDescriptor> {
+        self.model.input_descriptors()
+    }
+
+    fn output_descriptors(&self) -> Vec<TensorDescriptor> {
+        self.model.output_descriptors()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::profiling::Profile;
+
+    #[test]
+    fn models_are_timed_as_model_nodes() {
+        struct Noop;
+
+        impl Model for Noop {
+            fn infer(
+                &mut self,
+                _inputs: &[&[u8]],
+                _outputs: &mut [&mut [u8]],
+            ) -> Result<(), Error> {
+                Ok(())
+            }
+
+            fn input_shapes(&self) -> &[Shape<'_>] { &[] }
+
+            fn output_shapes(&self) -> &[Shape<'_>] { &[] }
+        }
+
+        let instrumentation = Instrumentation::default();
+        let profile = Arc::new(Mutex::new(Profile::default()));
+        instrumentation
+            .profiler()
+            .set(Some(Box::new(Arc::clone(&profile))));
+        let mut model =
+            instrumentation.model(2, "application/noop", None, Box::new(Noop));
+
+        model.infer(&[], &mut []).unwrap();
+        model.infer(&[], &mut []).unwrap();
+
+        let profile = profile.lock().unwrap();
+        assert_eq!(profile.node(NodeKind::Model, 2).unwrap().invocations, 2);
+    }
+}
@@ -0,0 +1,303 @@
+//! Control how tensors are laid out in memory when they are passed to a
+//! [`Model`].
+//!
+//! Accelerators like NPUs often need DMA buffers with a particular alignment
+//! (e.g. 64 bytes) and whose length is padded to a multiple of some block
+//! size. A [`Model`] can ask for this by returning a [`BufferLayout`] from
+//! [`Model::buffer_layout()`]. The runtime then keeps a set of aligned,
+//! zero-padded buffers for that model and copies tensors in and out of the
+//! Rune's memory around each [`Model::infer()`] call. That means a handler can
+//! give the buffers straight to its driver without needing its own copy.
+
+use std::{
+    alloc::{self, Layout},
+    fmt::{self, Debug, Formatter},
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+use anyhow::{Context, Error};
+use hotg_rune_core::{ElementType, Shape};
+
+use crate::Model;
+
+/// The alignment and padding a [`Model`] wants for its input and output
+/// buffers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BufferLayout {
+    alignment: usize,
+    padding: usize,
+}
+
+impl BufferLayout {
+    /// Create a new [`BufferLayout`].
+    ///
+    /// # Panics
+    ///
+    /// The `alignment` must be a power of two.
+    pub fn new(alignment: usize, padding: usize) -> Self {
+        assert!(
+            alignment.is_power_of_two(),
+            "The alignment must be a power of two, not {}",
+            alignment
+        );
+
+        BufferLayout { alignment, padding }
+    }
+
+    /// The alignment (in bytes) of each buffer's first element. This is
+    /// always a power of two.
+    pub fn alignment(&self) -> usize { self.alignment }
+
+    /// Each buffer's length is rounded up to a multiple of this many bytes,
+    /// with `0` or `1` meaning no padding.
+    pub fn padding(&self) -> usize { self.padding }
+
+    /// How many bytes a buffer holding `len` bytes of data will take up.
+    pub fn padded_len(&self, len: usize) -> usize {
+        match self.padding {
+            0 | 1 => len,
+            padding => (len + padding - 1) / padding * padding,
+        }
+    }
+
+    /// Describe where a tensor with this [`Shape`] will be inside a buffer.
+    pub fn describe(&self, shape: &Shape<'_>) -> TensorDescriptor {
+        let element_size = shape.element_type().size_of().unwrap_or(1);
+        let dimensions = shape.dimensions().to_vec();
+
+        // Tensors are always row-major, so the last dimension is contiguous
+        let mut strides = vec![0; dimensions.len()];
+        let mut stride = element_size;
+        for (i, &dim) in dimensions.iter().enumerate().rev() {
+            strides[i] = stride;
+            stride *= dim;
+        }
+
+        let len = dimensions.iter().product::<usize>() * element_size;
+
+        TensorDescriptor {
+            element_type: shape.element_type(),
+            dimensions,
+            strides,
+            len,
+            padded_len: self.padded_len(len),
+            alignment: self.alignment,
+        }
+    }
+}
+
+impl Default for BufferLayout {
+    fn default() -> Self {
+        BufferLayout {
+            alignment: 1,
+            padding: 0,
+        }
+    }
+}
+
+/// Where a tensor's elements are inside an aligned buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TensorDescriptor {
+    pub element_type: ElementType,
+    pub dimensions: Vec<usize>,
+    /// The number of bytes between consecutive elements along each dimension.
+    pub strides: Vec<usize>,
+    /// The number of bytes used by the tensor's elements.
+    pub len: usize,
+    /// The length of the buffer, including any zeroed padding at the end.
+    pub padded_len: usize,
+    pub alignment: usize,
+}
+
+/// A zero-initialized byte buffer whose start is aligned to a particular
+/// boundary.
+pub struct AlignedBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+impl AlignedBuffer {
+    /// Allocate a zeroed buffer with `len` bytes.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `alignment` isn't a power of two.
+    pub fn zeroed(len: usize, alignment: usize) -> Self {
+        // Note: zero-sized allocations aren't allowed
+        let layout = Layout::from_size_align(len.max(1), alignment)
+            .expect("The alignment must be a power of two");
+
+        // Safety: the layout always has a non-zero size
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr)
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+
+        AlignedBuffer { ptr, len, layout }
+    }
+
+    /// Allocate a buffer for a tensor, using the padding and alignment from
+    /// [`BufferLayout`].
+    pub fn for_tensor(len: usize, layout: BufferLayout) -> Self {
+        AlignedBuffer::zeroed(layout.padded_len(len), layout.alignment)
+    }
+
+    pub fn alignment(&self) -> usize { self.layout.align() }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // Safety: we own an initialized allocation of at least len bytes
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // Safety: we own an initialized allocation of at least len bytes
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        // Safety: the pointer was allocated with this layout
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+impl Debug for AlignedBuffer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlignedBuffer")
+            .field("len", &self.len)
+            .field("alignment", &self.alignment())
+            .finish()
+    }
+}
+
+// Safety: AlignedBuffer uniquely owns its memory, just like a Vec<u8>
+unsafe impl Send for AlignedBuffer {}
+unsafe impl Sync for AlignedBuffer {}
+
+/// The aligned copies of a model's inputs and outputs.
+#[derive(Debug)]
+pub(crate) struct StagingBuffers {
+    layout: BufferLayout,
+    inputs: Vec<AlignedBuffer>,
+    outputs: Vec<AlignedBuffer>,
+}
+
+impl StagingBuffers {
+    fn new(
+        layout: BufferLayout,
+        inputs: &[&[u8]],
+        outputs: &[&mut [u8]],
+    ) -> Self {
+        StagingBuffers {
+            layout,
+            inputs: inputs
+                .iter()
+                .map(|i| AlignedBuffer::for_tensor(i.len(), layout))
+                .collect(),
+            outputs: outputs
+                .iter()
+                .map(|o| AlignedBuffer::for_tensor(o.len(), layout))
+                .collect(),
+        }
+    }
+
+    fn fits(
+        &self,
+        layout: BufferLayout,
+        inputs: &[&[u8]],
+        outputs: &[&mut [u8]],
+    ) -> bool {
+        let fits = |buffers: &[AlignedBuffer], lengths: Vec<usize>| {
+            buffers.len() == lengths.len()
+                && buffers
+                    .iter()
+                    .zip(lengths)
+                    .all(|(b, len)| b.len() == layout.padded_len(len))
+        };
+
+        self.layout == layout
+            && fits(&self.inputs, inputs.iter().map(|i| i.len()).collect())
+            && fits(&self.outputs, outputs.iter().map(|o| o.len()).collect())
+    }
+
+    /// Run inference using aligned copies of the inputs and outputs, reusing
+    /// the buffers in `staging` if they are still the right size.
+    pub(crate) fn infer(
+        staging: &mut Option<StagingBuffers>,
+        layout: BufferLayout,
+        model: &mut dyn Model,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+    ) -> Result<(), Error> {
+        if !matches!(staging, Some(s) if s.fits(layout, inputs, outputs)) {
+            *staging = Some(StagingBuffers::new(layout, inputs, outputs));
+        }
+        let staging = staging.as_mut().expect("Always initialized");
+
+        for (buffer, input) in staging.inputs.iter_mut().zip(inputs) {
+            let (data, padding) = buffer.split_at_mut(input.len());
+            data.copy_from_slice(input);
+            padding.fill(0);
+        }
+
+        let aligned_inputs: Vec<&[u8]> =
+            staging.inputs.iter().map(|b| &b[..]).collect();
+        let mut aligned_outputs: Vec<&mut [u8]> =
+            staging.outputs.iter_mut().map(|b| &mut b[..]).collect();
+
+        model
+            .infer(&aligned_inputs, &mut aligned_outputs)
+            .context("Inference failed")?;
+
+        for (output, buffer) in outputs.iter_mut().zip(&staging.outputs) {
+            let len = output.len();
+            output.copy_from_slice(&buffer[..len]);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_aligned_and_padded() {
+        let layout = BufferLayout::new(64, 16);
+
+        let buffer = AlignedBuffer::for_tensor(100, layout);
+
+        assert_eq!(buffer.as_ptr() as usize % 64, 0);
+        assert_eq!(buffer.len(), 112);
+        assert!(buffer.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn describe_a_row_major_tensor() {
+        let shape = Shape::new(ElementType::F32, vec![1, 3, 5]);
+        let layout = BufferLayout::new(64, 64);
+
+        let got = layout.describe(&shape);
+
+        assert_eq!(
+            got,
+            TensorDescriptor {
+                element_type: ElementType::F32,
+                dimensions: vec![1, 3, 5],
+                strides: vec![60, 20, 4],
+                len: 60,
+                padded_len: 64,
+                alignment: 64,
+            }
+        );
+    }
+}
@@ -9,33 +9,117 @@
 //!
 //! - `builtins` - (default) enable various builtin outputs and capabilities
 #![cfg_attr(not(feature = "builtins"), doc = "(disabled)")]
+//! - `parquet` - let the builtin [`builtins::outputs::Dataset`] write Parquet
+//!   files
+#![cfg_attr(not(feature = "parquet"), doc = "(disabled)")]
 //! - `tflite` - (default) enable support for TensorFlow Lite models
 #![cfg_attr(not(feature = "tflite"), doc = "(disabled)")]
+//! - `tflite-interpreter` - a pure-Rust TensorFlow Lite interpreter which
+//!   supports a subset of operators and is used when `tflite` is disabled
+#![cfg_attr(not(feature = "tflite-interpreter"), doc = "(disabled)")]
+//! - `onnx` - enable support for ONNX models using [`tract`](https://github.com/sonos/tract)
+#![cfg_attr(not(feature = "onnx"), doc = "(disabled)")]
+//! - `tensorflow` - enable support for frozen TensorFlow graphs using [`tract`](https://github.com/sonos/tract)
+#![cfg_attr(not(feature = "tensorflow"), doc = "(disabled)")]
+//! - `ndarray` - convert between [`Tensor`] and [`ndarray`](https://docs.rs/ndarray)
+//!   arrays
+#![cfg_attr(not(feature = "ndarray"), doc = "(disabled)")]
+//! - `otel` - export trace spans for each prediction using [OpenTelemetry](https://opentelemetry.io/)
+#![cfg_attr(not(feature = "otel"), doc = "(disabled)")]
+//! - `telemetry` - emit [`tracing`](https://docs.rs/tracing) spans for each
+//!   phase of loading and running a Rune
+#![cfg_attr(not(feature = "telemetry"), doc = "(disabled)")]
+//! - `grpc` - expose a [`Runtime`] to other processes with the `serve` module
+#![cfg_attr(not(feature = "grpc"), doc = "(disabled)")]
+//! - `async` - run a Rune from async code with the `async_runtime` module
+#![cfg_attr(not(feature = "async"), doc = "(disabled)")]
 //! - `wasm3` - enable the [WASM3](https://github.com/wasm3/wasm3) engine
 #![cfg_attr(not(feature = "wasm3"), doc = "(disabled)")]
 //! - `wasmer` - enable the [wasmer](https://wasmer.io/) engine
 #![cfg_attr(not(feature = "wasmer"), doc = "(disabled)")]
+//! - `wasmtime` - enable the [wasmtime](https://wasmtime.dev/) engine, which
+//!   also supports bounding a Rune's execution with
+//!   [`RuntimeBuilder::fuel_limit()`]
+#![cfg_attr(not(feature = "wasmtime"), doc = "(disabled)")]
+//! - `wasi` - let the wasmtime engine run Runes which also import [WASI](https://wasi.dev/)
+//!   functions
+#![cfg_attr(not(feature = "wasi"), doc = "(disabled)")]
 #![cfg_attr(feature = "unstable_doc_cfg", feature(doc_cfg))]
 
 #[cfg(feature = "wasm3")]
 pub extern crate wasm3;
 #[cfg(feature = "wasmer")]
 pub extern crate wasmer;
+#[cfg(feature = "wasmtime")]
+pub extern crate wasmtime;
 
+#[cfg(feature = "async")]
+#[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "async")))]
+pub mod async_runtime;
+mod batch;
 mod callbacks;
+pub mod cancellation;
+pub mod clock;
+pub mod delivery;
+pub mod embedded_tests;
 mod engine;
+mod errors;
+pub mod fleet;
+pub mod flight_recorder;
+pub mod harness;
+mod instrumentation;
+pub mod layout;
+pub mod log_filter;
+mod memory_stats;
+pub mod metadata;
 pub mod models;
+pub mod native;
+#[cfg(feature = "otel")]
+#[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "otel")))]
+pub mod otel;
+pub mod profiling;
+pub mod registry;
+pub mod replay;
 mod runtime;
+pub mod scheduler;
+#[cfg(feature = "grpc")]
+#[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "grpc")))]
+pub mod serve;
+pub mod session;
+pub mod sink;
+pub mod streaming;
+#[cfg(feature = "telemetry")]
+#[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "telemetry")))]
+pub mod telemetry;
 mod tensor;
+pub mod testing;
+#[cfg(feature = "wasmer")]
+#[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "wasmer")))]
+pub mod wasm_proc_block;
 
 #[cfg(feature = "builtins")]
 pub mod builtins;
 mod outputs;
 
+pub use hotg_rune_proc_blocks::{
+    ParameterDescriptor, ParameterRange, ProcBlockDescriptor,
+};
+
 pub use crate::{
-    callbacks::{Model, ModelMetadata, NodeMetadata},
-    engine::LoadError,
-    outputs::OutputTensor,
-    runtime::Runtime,
+    callbacks::{
+        Capability, LogRecord, Model, ModelMetadata, ModelOptions,
+        ModelVariant, NodeMetadata, StreamingCapability, TensorDescriptor,
+        CPU_PROVIDER,
+    },
+    engine::{
+        AssertionError, HostFunctions, Import, LoadError, OutOfFuel,
+        ResourceExhausted, TrapError, WebAssemblyEngine, HOST_FUNCTIONS,
+        HOST_MODULE,
+    },
+    errors::{ErrorCode, RuntimeError},
+    memory_stats::MemoryStats,
+    metadata::{NodeInfo, NodeRole, PipelineMetadata},
+    outputs::{OutputTensor, SerialDecoder, TaggedTensor},
+    runtime::{Engine, Runtime, RuntimeBuilder},
     tensor::{ElementType, Tensor, TensorElement},
 };
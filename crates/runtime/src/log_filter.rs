@@ -0,0 +1,307 @@
+//! Controlling how verbose each part of the runtime is.
+//!
+//! The runtime's own log messages (and the messages emitted by the Rune) are
+//! checked against a [`LogFilter`] before they reach the `log` crate, so noisy
+//! subsystems can be silenced on a device without needing to rebuild the
+//! application.
+//!
+//! Each [`crate::Runtime`] can be given its own filter with
+//! [`crate::RuntimeBuilder::log_filter()`], which is used while it loads and
+//! runs the Rune. Runtimes without one fall back to the process-wide filter
+//! set with [`set_log_filter()`].
+//!
+//! Filters use the same directive syntax as `env_logger`, where each
+//! subsystem is named after a module in this crate and messages from the Rune
+//! fall under the `rune` subsystem.
+//!
+//! ```rust
+//! use hotg_rune_runtime::log_filter::{self, LogFilter};
+//!
+//! let filter: LogFilter = "info,models=debug,engine=warn".parse().unwrap();
+//! log_filter::set_log_filter(filter);
+//! ```
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use log::{Level, LevelFilter};
+use once_cell::sync::Lazy;
+
+static FILTER: Lazy<RwLock<LogFilter>> =
+    Lazy::new(|| RwLock::new(LogFilter::default()));
+
+thread_local! {
+    /// The filter belonging to the [`crate::Runtime`] currently running on
+    /// this thread, if it has one.
+    static SCOPED: RefCell<Option<Arc<LogFilter>>> =
+        const { RefCell::new(None) };
+}
+
+/// The prefix stripped from a module path to get its subsystem.
+const CRATE_PREFIX: &str = concat!(env!("CARGO_CRATE_NAME"), "::");
+
+/// Replace the filter used by every [`crate::Runtime`] in this process that
+/// doesn't have its own.
+pub fn set_log_filter(filter: LogFilter) {
+    *FILTER.write().expect("Lock was poisoned") = filter;
+}
+
+/// Get a copy of the filter that is currently in use.
+pub fn log_filter() -> LogFilter {
+    FILTER.read().expect("Lock was poisoned").clone()
+}
+
+/// Should a message from this module be logged?
+pub(crate) fn enabled(module_path: &str, level: Level) -> bool {
+    let subsystem = module_path
+        .strip_prefix(CRATE_PREFIX)
+        .unwrap_or(module_path);
+
+    let scoped = SCOPED.with(|scoped| {
+        scoped
+            .borrow()
+            .as_ref()
+            .map(|filter| filter.enabled(subsystem, level))
+    });
+
+    scoped.unwrap_or_else(|| {
+        FILTER
+            .read()
+            .expect("Lock was poisoned")
+            .enabled(subsystem, level)
+    })
+}
+
+/// Use `filter` (if provided) for everything logged on this thread until the
+/// returned [`Scope`] is dropped.
+pub(crate) fn scope(filter: Option<&Arc<LogFilter>>) -> Scope {
+    let previous = match filter {
+        Some(filter) => SCOPED
+            .with(|scoped| scoped.borrow_mut().replace(Arc::clone(filter))),
+        None => SCOPED.with(|scoped| scoped.borrow().clone()),
+    };
+
+    Scope { previous }
+}
+
+/// A guard which restores the previous filter when dropped.
+pub(crate) struct Scope {
+    previous: Option<Arc<LogFilter>>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        SCOPED.with(|scoped| *scoped.borrow_mut() = previous);
+    }
+}
+
+/// Should a message the Rune logged with this target be passed on?
+pub(crate) fn rune_enabled(target: &str, level: Level) -> bool {
+    enabled(&format!("rune::{}", target), level)
+}
+
+macro_rules! filtered {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log_filter::enabled(module_path!(), $level) {
+            log::log!($level, $($arg)+);
+        }
+    };
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        $crate::log_filter::filtered!(log::Level::Trace, $($arg)+)
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::log_filter::filtered!(log::Level::Debug, $($arg)+)
+    };
+}
+
+// Named `warning` so it doesn't clash with the built-in `#[warn]` attribute
+macro_rules! warning {
+    ($($arg:tt)+) => {
+        $crate::log_filter::filtered!(log::Level::Warn, $($arg)+)
+    };
+}
+
+pub(crate) use debug;
+pub(crate) use filtered;
+pub(crate) use trace;
+pub(crate) use warning as warn;
+
+/// A set of `subsystem=level` directives.
+///
+/// The most specific directive wins, so with `engine=warn,engine::wasm3=debug`
+/// the `engine::wasm3` module will log debug messages while everything else in
+/// `engine` only logs warnings. Subsystems without a directive use the default
+/// level, which is [`LevelFilter::Trace`] unless a bare level (e.g. `info`) is
+/// provided.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    directives: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    /// Create a filter which logs everything at or above `default`.
+    pub fn new(default: LevelFilter) -> Self {
+        LogFilter {
+            default,
+            directives: Vec::new(),
+        }
+    }
+
+    /// Set the level for a particular subsystem.
+    pub fn with(
+        mut self,
+        subsystem: impl Into<String>,
+        level: LevelFilter,
+    ) -> Self {
+        let subsystem = subsystem.into();
+        self.directives.retain(|(s, _)| *s != subsystem);
+        self.directives.push((subsystem, level));
+        self
+    }
+
+    /// The most verbose level a subsystem will log at.
+    pub fn level(&self, subsystem: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter(|(name, _)| is_within(subsystem, name))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    pub fn enabled(&self, subsystem: &str, level: Level) -> bool {
+        level <= self.level(subsystem)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self { LogFilter::new(LevelFilter::Trace) }
+}
+
+fn is_within(subsystem: &str, parent: &str) -> bool {
+    match subsystem.strip_prefix(parent) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = InvalidLogFilter;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = LogFilter::default();
+
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let invalid = || InvalidLogFilter {
+                directive: directive.to_string(),
+            };
+
+            match directive.split_once('=') {
+                Some((subsystem, level)) => {
+                    let level = level.trim().parse().map_err(|_| invalid())?;
+                    filter = filter.with(subsystem.trim(), level);
+                },
+                None => {
+                    filter.default =
+                        directive.parse().map_err(|_| invalid())?;
+                },
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+impl Display for LogFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.default.to_string().to_lowercase())?;
+
+        for (subsystem, level) in &self.directives {
+            write!(f, ",{}={}", subsystem, level.to_string().to_lowercase())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The error returned when a [`LogFilter`] directive can't be parsed.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Unable to parse the \"{}\" log filter directive", directive)]
+pub struct InvalidLogFilter {
+    pub directive: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_directives() {
+        let filter: LogFilter = "models=debug, engine=warn,outputs=info,error"
+            .parse()
+            .unwrap();
+
+        assert_eq!(filter.level("models"), LevelFilter::Debug);
+        assert_eq!(filter.level("models::onnx"), LevelFilter::Debug);
+        assert_eq!(filter.level("engine::wasm3"), LevelFilter::Warn);
+        assert_eq!(filter.level("outputs"), LevelFilter::Info);
+        assert_eq!(filter.level("outputsx"), LevelFilter::Error);
+        assert_eq!(filter.level("sink"), LevelFilter::Error);
+        assert_eq!(
+            filter.to_string(),
+            "error,models=debug,engine=warn,outputs=info"
+        );
+
+        assert!("models=loud".parse::<LogFilter>().is_err());
+    }
+
+    #[test]
+    fn the_most_specific_directive_wins() {
+        let filter = LogFilter::new(LevelFilter::Off)
+            .with("engine::wasm3", LevelFilter::Trace)
+            .with("engine", LevelFilter::Warn);
+
+        assert!(filter.enabled("engine::wasm3", Level::Debug));
+        assert!(!filter.enabled("engine::wasmer", Level::Debug));
+        assert!(filter.enabled("engine::wasmer", Level::Warn));
+        assert!(!filter.enabled("runtime", Level::Error));
+    }
+
+    #[test]
+    fn scoped_filters_only_apply_until_they_are_dropped() {
+        let quiet = Arc::new(LogFilter::new(LevelFilter::Off));
+        let noisy = Arc::new(LogFilter::new(LevelFilter::Trace));
+        let module = concat!(env!("CARGO_CRATE_NAME"), "::models");
+
+        {
+            let _outer = scope(Some(&quiet));
+            assert!(!enabled(module, Level::Error));
+
+            {
+                let _inner = scope(Some(&noisy));
+                assert!(enabled(module, Level::Trace));
+
+                // Runtimes without a filter don't change anything
+                let _none = scope(None);
+                assert!(enabled(module, Level::Trace));
+            }
+
+            assert!(!enabled(module, Level::Error));
+        }
+
+        // The process-wide default logs everything
+        assert!(enabled(module, Level::Trace));
+    }
+}
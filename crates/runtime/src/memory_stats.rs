@@ -0,0 +1,120 @@
+use anyhow::{Context, Error};
+
+/// A snapshot of the Rune's heap, as reported by its own allocator.
+///
+/// [`crate::Runtime::memory_usage()`] only knows how large the Rune's linear
+/// memory has grown, whereas these numbers come from inside the Rune so they
+/// show how much of that memory is actually being used.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize,
+)]
+pub struct MemoryStats {
+    /// The number of bytes which are currently allocated.
+    pub in_use: usize,
+    /// The most bytes that were allocated at once during the last run (or
+    /// while the Rune was being initialized, if it hasn't been run yet).
+    pub peak_in_use: usize,
+    pub allocations: usize,
+    pub deallocations: usize,
+    pub reallocations: usize,
+    pub bytes_allocated: usize,
+    pub bytes_deallocated: usize,
+}
+
+impl MemoryStats {
+    /// The name of the function a Rune exports so the runtime can ask for a
+    /// [`MemoryStats`].
+    ///
+    /// It returns a pointer to a snapshot of the allocator's statistics,
+    /// stored as little-endian `usize`s (i.e. `u32`s, or `u64`s for wasm64
+    /// Runes) in the same order as [`MemoryStats`]'s fields.
+    pub(crate) const EXPORT: &'static str = "_memory_stats";
+    const FIELDS: usize = 7;
+
+    /// Read the snapshot at `ptr` in the Rune's linear memory.
+    pub(crate) fn read(memory: &[u8], ptr: usize) -> Result<Self, Error> {
+        MemoryStats::read_words(memory, ptr, 4)
+    }
+
+    /// Read the snapshot at `ptr` in a wasm64 Rune's linear memory.
+    #[cfg(feature = "wasmtime")]
+    pub(crate) fn read_wasm64(
+        memory: &[u8],
+        ptr: usize,
+    ) -> Result<Self, Error> {
+        MemoryStats::read_words(memory, ptr, 8)
+    }
+
+    fn read_words(
+        memory: &[u8],
+        ptr: usize,
+        word_size: usize,
+    ) -> Result<Self, Error> {
+        let len = MemoryStats::FIELDS * word_size;
+        let bytes = memory.get(ptr..ptr + len).with_context(|| {
+            format!(
+                "The memory stats at {} lie outside of linear memory ({} \
+                 bytes)",
+                ptr,
+                memory.len()
+            )
+        })?;
+
+        let mut fields = bytes.chunks_exact(word_size).map(|chunk| {
+            let mut word = [0_u8; 8];
+            word[..word_size].copy_from_slice(chunk);
+            u64::from_le_bytes(word) as usize
+        });
+        let mut next = || fields.next().unwrap();
+
+        Ok(MemoryStats {
+            in_use: next(),
+            peak_in_use: next(),
+            allocations: next(),
+            deallocations: next(),
+            reallocations: next(),
+            bytes_allocated: next(),
+            bytes_deallocated: next(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_the_snapshot_from_linear_memory() {
+        let mut memory = vec![0xff; 4];
+        for field in 1..=7_u32 {
+            memory.extend(field.to_le_bytes());
+        }
+
+        let got = MemoryStats::read(&memory, 4).unwrap();
+
+        assert_eq!(
+            got,
+            MemoryStats {
+                in_use: 1,
+                peak_in_use: 2,
+                allocations: 3,
+                deallocations: 4,
+                reallocations: 5,
+                bytes_allocated: 6,
+                bytes_deallocated: 7,
+            }
+        );
+        assert!(MemoryStats::read(&memory, 8).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "wasmtime")]
+    fn read_a_wasm64_snapshot() {
+        let mut wasm64 = Vec::new();
+        for field in 1..=7_u64 {
+            wasm64.extend(field.to_le_bytes());
+        }
+        let got = MemoryStats::read_wasm64(&wasm64, 0).unwrap();
+        assert_eq!(got.bytes_deallocated, 7);
+    }
+}
@@ -0,0 +1,372 @@
+//! A small query API over a loaded Rune's pipeline metadata.
+//!
+//! Everything here is a read-only view over information the [`crate::Runtime`]
+//! already has, so dashboards and device UIs can ask questions like "which
+//! capabilities take a `width` argument?" without re-walking and re-joining
+//! the individual metadata maps themselves.
+
+use std::collections::HashMap;
+
+use hotg_rune_core::{ElementType, Shape};
+
+use crate::{NodeMetadata, TensorDescriptor};
+
+/// Information about a model that was loaded by the Rune.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct LoadedModel {
+    /// The model's mimetype (e.g. [`hotg_rune_core::TFLITE_MIMETYPE`]).
+    pub(crate) mimetype: String,
+    /// The input tensors Rune says this model accepts.
+    pub(crate) inputs: Vec<Shape<'static>>,
+    /// The output tensors Rune says this model generates.
+    pub(crate) outputs: Vec<Shape<'static>>,
+    /// Which of the model's variants was loaded, if it has more than one.
+    pub(crate) variant: Option<String>,
+    /// The name of the pipeline node this model belongs to, used by
+    /// [`crate::Runtime::replace_model()`].
+    pub(crate) node: Option<String>,
+    /// The execution providers this model can be switched between using
+    /// [`crate::Runtime::reconfigure_model()`].
+    pub(crate) providers: Vec<String>,
+    /// The model's own description of its input tensors.
+    pub(crate) input_descriptors: Vec<TensorDescriptor>,
+    /// The model's own description of its output tensors.
+    pub(crate) output_descriptors: Vec<TensorDescriptor>,
+}
+
+impl LoadedModel {
+    /// The model's mimetype (e.g. [`hotg_rune_core::TFLITE_MIMETYPE`]).
+    pub fn mimetype(&self) -> &str { &self.mimetype }
+
+    /// The input tensors Rune says this model accepts.
+    pub fn inputs(&self) -> &[Shape<'static>] { &self.inputs }
+
+    /// The output tensors Rune says this model generates.
+    pub fn outputs(&self) -> &[Shape<'static>] { &self.outputs }
+
+    /// Which of the model's variants was loaded, if it has more than one.
+    pub fn variant(&self) -> Option<&str> { self.variant.as_deref() }
+
+    /// The name of the pipeline node this model belongs to, used by
+    /// [`crate::Runtime::replace_model()`].
+    pub fn node(&self) -> Option<&str> { self.node.as_deref() }
+
+    /// The execution providers this model can be switched between using
+    /// [`crate::Runtime::reconfigure_model()`].
+    pub fn providers(&self) -> &[String] { &self.providers }
+
+    /// The model's own description of its input tensors.
+    pub fn input_descriptors(&self) -> &[TensorDescriptor] {
+        &self.input_descriptors
+    }
+
+    /// The model's own description of its output tensors.
+    pub fn output_descriptors(&self) -> &[TensorDescriptor] {
+        &self.output_descriptors
+    }
+}
+
+/// The role a node plays in the Rune's pipeline.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum NodeRole {
+    Capability,
+    Model,
+    Output,
+}
+
+/// A single node in the pipeline, as seen by a [`Query`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeInfo<'rt> {
+    /// The ID used when talking to the Rune about this node.
+    pub id: u32,
+    pub role: NodeRole,
+    /// The node's kind (e.g. `"IMAGE"` or `"SERIAL"`), or the mimetype for
+    /// models.
+    pub kind: &'rt str,
+    /// Any arguments passed to the node in the Runefile.
+    ///
+    /// Models don't pass their arguments to the runtime, so this will always
+    /// be `None` for [`NodeRole::Model`] nodes.
+    pub arguments: Option<&'rt HashMap<String, String>>,
+    /// The shapes of the tensors this node accepts, if known.
+    pub inputs: &'rt [Shape<'static>],
+    /// The shapes of the tensors this node generates, if known.
+    pub outputs: &'rt [Shape<'static>],
+}
+
+impl<'rt> NodeInfo<'rt> {
+    /// Look up a single argument by name.
+    pub fn argument(&self, name: &str) -> Option<&'rt str> {
+        self.arguments
+            .and_then(|args| args.get(name))
+            .map(|s| s.as_str())
+    }
+
+    fn shapes(&self) -> impl Iterator<Item = &'rt Shape<'static>> + 'rt {
+        self.inputs.iter().chain(self.outputs.iter())
+    }
+}
+
+/// A read-only view over everything the runtime knows about a Rune's
+/// pipeline.
+#[derive(Debug, Copy, Clone)]
+pub struct PipelineMetadata<'rt> {
+    capabilities: &'rt HashMap<u32, NodeMetadata>,
+    models: &'rt HashMap<u32, LoadedModel>,
+    outputs: &'rt HashMap<u32, NodeMetadata>,
+}
+
+impl<'rt> PipelineMetadata<'rt> {
+    pub(crate) fn new(
+        capabilities: &'rt HashMap<u32, NodeMetadata>,
+        models: &'rt HashMap<u32, LoadedModel>,
+        outputs: &'rt HashMap<u32, NodeMetadata>,
+    ) -> Self {
+        PipelineMetadata {
+            capabilities,
+            models,
+            outputs,
+        }
+    }
+
+    /// Iterate over every node in the pipeline, ordered by [`NodeRole`] and
+    /// then ID.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeInfo<'rt>> + 'rt {
+        let capabilities = self.capabilities.iter().map(|(&id, meta)| {
+            node_metadata_info(id, NodeRole::Capability, meta)
+        });
+        let models = self.models.iter().map(|(&id, model)| NodeInfo {
+            id,
+            role: NodeRole::Model,
+            kind: &model.mimetype,
+            arguments: None,
+            inputs: &model.inputs,
+            outputs: &model.outputs,
+        });
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|(&id, meta)| node_metadata_info(id, NodeRole::Output, meta));
+
+        let mut nodes: Vec<_> =
+            capabilities.chain(models).chain(outputs).collect();
+        nodes.sort_by_key(|node| (node.role, node.id));
+
+        nodes.into_iter()
+    }
+
+    /// Start building a [`Query`] which will match every node by default.
+    pub fn query(&self) -> Query<'rt> {
+        Query {
+            metadata: *self,
+            filters: Vec::new(),
+        }
+    }
+}
+
+fn node_metadata_info(
+    id: u32,
+    role: NodeRole,
+    meta: &NodeMetadata,
+) -> NodeInfo<'_> {
+    NodeInfo {
+        id,
+        role,
+        kind: &meta.kind,
+        arguments: Some(&meta.arguments),
+        inputs: &[],
+        outputs: &[],
+    }
+}
+
+/// A typed filter over the nodes in a [`PipelineMetadata`].
+///
+/// Each method adds another condition, and a node must satisfy all of them to
+/// be matched.
+///
+/// ```rust,no_run
+/// # fn run(runtime: &hotg_rune_runtime::Runtime) {
+/// use hotg_rune_runtime::metadata::NodeRole;
+///
+/// let images: Vec<_> = runtime
+///     .metadata()
+///     .query()
+///     .role(NodeRole::Capability)
+///     .kind("IMAGE")
+///     .has_argument("width")
+///     .run();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+#[must_use = "A query does nothing unless it is run"]
+pub struct Query<'rt> {
+    metadata: PipelineMetadata<'rt>,
+    filters: Vec<Filter>,
+}
+
+impl<'rt> Query<'rt> {
+    /// Only match nodes with this [`NodeRole`].
+    pub fn role(self, role: NodeRole) -> Self { self.with(Filter::Role(role)) }
+
+    /// Only match nodes with this kind (case-insensitive).
+    pub fn kind(self, kind: impl Into<String>) -> Self {
+        self.with(Filter::Kind(kind.into()))
+    }
+
+    /// Only match nodes which were given a particular argument.
+    pub fn has_argument(self, name: impl Into<String>) -> Self {
+        self.with(Filter::HasArgument(name.into()))
+    }
+
+    /// Only match nodes where an argument has a particular value.
+    pub fn argument(
+        self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.with(Filter::Argument(name.into(), value.into()))
+    }
+
+    /// Only match nodes which accept or generate a tensor with this
+    /// [`ElementType`].
+    pub fn element_type(self, element_type: ElementType) -> Self {
+        self.with(Filter::ElementType(element_type))
+    }
+
+    /// Execute the query, returning an iterator over all matching nodes.
+    pub fn iter(&self) -> impl Iterator<Item = NodeInfo<'rt>> + '_ {
+        self.metadata
+            .nodes()
+            .filter(move |node| self.filters.iter().all(|f| f.matches(node)))
+    }
+
+    /// Execute the query, collecting the matching nodes into a [`Vec`].
+    pub fn run(&self) -> Vec<NodeInfo<'rt>> { self.iter().collect() }
+
+    /// Get the first matching node, if there is one.
+    pub fn first(&self) -> Option<NodeInfo<'rt>> { self.iter().next() }
+
+    /// Count the number of matching nodes.
+    pub fn count(&self) -> usize { self.iter().count() }
+
+    fn with(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Role(NodeRole),
+    Kind(String),
+    HasArgument(String),
+    Argument(String, String),
+    ElementType(ElementType),
+}
+
+impl Filter {
+    fn matches(&self, node: &NodeInfo<'_>) -> bool {
+        match self {
+            Filter::Role(role) => node.role == *role,
+            Filter::Kind(kind) => node.kind.eq_ignore_ascii_case(kind),
+            Filter::HasArgument(name) => node.argument(name).is_some(),
+            Filter::Argument(name, value) => {
+                node.argument(name) == Some(value.as_str())
+            },
+            Filter::ElementType(element_type) => {
+                node.shapes().any(|s| s.element_type() == *element_type)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(kind: &str, args: &[(&str, &str)]) -> NodeMetadata {
+        NodeMetadata {
+            kind: kind.to_string(),
+            arguments: args
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    fn fixtures() -> (
+        HashMap<u32, NodeMetadata>,
+        HashMap<u32, LoadedModel>,
+        HashMap<u32, NodeMetadata>,
+    ) {
+        let mut capabilities = HashMap::new();
+        capabilities
+            .insert(1, node("IMAGE", &[("width", "28"), ("height", "28")]));
+        capabilities.insert(0, node("SOUND", &[("hz", "16000")]));
+
+        let mut models = HashMap::new();
+        models.insert(
+            0,
+            LoadedModel {
+                mimetype: hotg_rune_core::TFLITE_MIMETYPE.to_string(),
+                inputs: vec!["u8[1, 28, 28, 1]".parse().unwrap()],
+                outputs: vec!["f32[1, 10]".parse().unwrap()],
+                variant: None,
+                node: None,
+                providers: vec![crate::CPU_PROVIDER.to_string()],
+                input_descriptors: Vec::new(),
+                output_descriptors: Vec::new(),
+            },
+        );
+
+        let mut outputs = HashMap::new();
+        outputs.insert(0, node("SERIAL", &[]));
+
+        (capabilities, models, outputs)
+    }
+
+    #[test]
+    fn nodes_are_ordered_by_role_then_id() {
+        let (capabilities, models, outputs) = fixtures();
+        let meta = PipelineMetadata::new(&capabilities, &models, &outputs);
+
+        let got: Vec<_> = meta.nodes().map(|n| (n.role, n.id)).collect();
+
+        assert_eq!(
+            got,
+            vec![
+                (NodeRole::Capability, 0),
+                (NodeRole::Capability, 1),
+                (NodeRole::Model, 0),
+                (NodeRole::Output, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_are_combined() {
+        let (capabilities, models, outputs) = fixtures();
+        let meta = PipelineMetadata::new(&capabilities, &models, &outputs);
+
+        let got = meta
+            .query()
+            .role(NodeRole::Capability)
+            .kind("image")
+            .argument("width", "28")
+            .run();
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].id, 1);
+        assert_eq!(got[0].argument("height"), Some("28"));
+
+        assert_eq!(meta.query().has_argument("hz").count(), 1);
+        assert_eq!(meta.query().element_type(ElementType::F32).count(), 1);
+        assert!(meta
+            .query()
+            .kind("IMAGE")
+            .has_argument("hz")
+            .first()
+            .is_none());
+    }
+}
@@ -0,0 +1,196 @@
+#[cfg(any(feature = "onnx", feature = "tensorflow"))]
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Error;
+#[cfg(any(feature = "onnx", feature = "tensorflow"))]
+use hotg_rune_core::Shape;
+
+#[cfg(any(feature = "onnx", feature = "tensorflow"))]
+use super::tract::TractModel;
+use crate::callbacks::{Model, ModelMetadata};
+
+/// A registry of loaded models which can be shared between [`Runtime`]s.
+///
+/// Runes which embed the same model (e.g. a common vision backbone) normally
+/// each get their own copy of the model and its interpreter. Loading models
+/// through a [`ModelCache`] instead means identical models, keyed by a hash of
+/// their contents, are only loaded once.
+///
+/// How much gets shared depends on the backend:
+///
+/// - ONNX and TensorFlow models are read-only once they have been optimized, so
+///   every Rune uses the same optimized graph concurrently
+/// - TensorFlow Lite models share a single interpreter (see
+///   [`InterpreterPool`][super::InterpreterPool]), which is used by one Rune at
+///   a time
+/// - Anything else is loaded by [`default_model_handler()`] as normal
+///
+/// Clones of a cache refer to the same models, so a cache can be handed to
+/// each [`RuntimeBuilder`] with [`RuntimeBuilder::model_cache()`].
+///
+/// ```rust,no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use hotg_rune_runtime::{models::ModelCache, Runtime};
+///
+/// let cache = ModelCache::default();
+///
+/// for rune in &["person_detection.rune", "pose.rune", "objects.rune"] {
+///     let runtime = Runtime::builder()
+///         .rune(std::fs::read(rune)?)
+///         .model_cache(cache.clone())
+///         .build()?;
+///     // ...
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Runtime`]: crate::Runtime
+/// [`RuntimeBuilder`]: crate::RuntimeBuilder
+/// [`RuntimeBuilder::model_cache()`]: crate::RuntimeBuilder::model_cache
+/// [`default_model_handler()`]: super::default_model_handler
+#[derive(Clone, Default)]
+pub struct ModelCache {
+    #[cfg(feature = "tflite")]
+    interpreters: super::InterpreterPool,
+    #[cfg(any(feature = "onnx", feature = "tensorflow"))]
+    graphs: Arc<Mutex<HashMap<Key, TractModel>>>,
+}
+
+/// Identifies a model by its contents, format, and the shapes the Rune uses
+/// it with.
+#[cfg(any(feature = "onnx", feature = "tensorflow"))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    hash: u64,
+    len: usize,
+    mimetype: String,
+    inputs: Vec<Shape<'static>>,
+    outputs: Vec<Shape<'static>>,
+}
+
+#[cfg(any(feature = "onnx", feature = "tensorflow"))]
+impl Key {
+    fn new(meta: &ModelMetadata<'_>, model: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        model.hash(&mut hasher);
+
+        Key {
+            hash: hasher.finish(),
+            len: model.len(),
+            mimetype: meta.mimetype.to_string(),
+            inputs: meta.inputs.iter().map(|s| s.to_owned()).collect(),
+            outputs: meta.outputs.iter().map(|s| s.to_owned()).collect(),
+        }
+    }
+}
+
+impl ModelCache {
+    /// Load a model, reusing an already loaded copy when this cache has seen
+    /// an identical model before.
+    ///
+    /// This has the same signature as a [`crate::RuntimeBuilder`]'s model
+    /// handler.
+    pub fn load(
+        &self,
+        id: u32,
+        meta: &ModelMetadata<'_>,
+        model: &[u8],
+    ) -> Result<Box<dyn Model>, Error> {
+        match meta.mimetype {
+            #[cfg(feature = "tflite")]
+            super::TFLITE_MIMETYPE => {
+                self.interpreters.load(model, meta.inputs, meta.outputs)
+            },
+            #[cfg(feature = "onnx")]
+            super::ONNX_MIMETYPE | super::ONNX_ALTERNATE_MIMETYPE => self
+                .load_graph(meta, model, super::onnx::onnx_model)
+                .map(|m| Box::new(m) as Box<dyn Model>),
+            #[cfg(feature = "tensorflow")]
+            super::TF_MIMETYPE => self
+                .load_graph(meta, model, super::tensorflow::tensorflow_model)
+                .map(|m| Box::new(m) as Box<dyn Model>),
+            _ => super::default_model_handler(id, meta, model),
+        }
+    }
+
+    #[cfg(any(feature = "onnx", feature = "tensorflow"))]
+    fn load_graph(
+        &self,
+        meta: &ModelMetadata<'_>,
+        model: &[u8],
+        load: fn(
+            &[u8],
+            &[Shape<'_>],
+            &[Shape<'_>],
+        ) -> Result<TractModel, Error>,
+    ) -> Result<TractModel, Error> {
+        let key = Key::new(meta, model);
+        let mut graphs = self.graphs.lock().expect("Lock was poisoned");
+
+        if let Some(existing) = graphs.get(&key) {
+            return Ok(existing.clone());
+        }
+
+        let loaded = load(model, meta.inputs, meta.outputs)?;
+        graphs.insert(key, loaded.clone());
+
+        Ok(loaded)
+    }
+
+    /// The number of distinct models which are being shared.
+    pub fn len(&self) -> usize {
+        #[cfg(feature = "tflite")]
+        let interpreters = self.interpreters.len();
+        #[cfg(not(feature = "tflite"))]
+        let interpreters = 0;
+
+        #[cfg(any(feature = "onnx", feature = "tensorflow"))]
+        let graphs = self.graphs.lock().expect("Lock was poisoned").len();
+        #[cfg(not(any(feature = "onnx", feature = "tensorflow")))]
+        let graphs = 0;
+
+        interpreters + graphs
+    }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(mimetype: &str) -> ModelMetadata<'_> {
+        ModelMetadata {
+            mimetype,
+            inputs: &[],
+            outputs: &[],
+            variant: None,
+            cache_dir: None,
+            node: None,
+        }
+    }
+
+    #[test]
+    fn unknown_formats_are_not_cached() {
+        let cache = ModelCache::default();
+
+        let result = cache.load(0, &metadata("application/x-unknown"), &[]);
+
+        assert!(result.is_err());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    #[cfg(any(feature = "onnx", feature = "tensorflow"))]
+    fn identical_models_have_the_same_key() {
+        let meta = metadata(super::super::ONNX_MIMETYPE);
+
+        assert_eq!(Key::new(&meta, b"model"), Key::new(&meta, b"model"));
+        assert_ne!(Key::new(&meta, b"model"), Key::new(&meta, b"other"));
+    }
+}
@@ -0,0 +1,1081 @@
+//! A pure-Rust interpreter for TensorFlow Lite models.
+//!
+//! This is a fallback for platforms where the native TensorFlow Lite library
+//! used by [`hotg_runecoral`] is hard to cross-compile (e.g. musl or Windows on
+//! ARM), and is used automatically when the `tflite` feature is disabled.
+//!
+//! Only the operators used by common example models are supported:
+//! `ADD`, `SUB`, `MUL`, `CONV_2D`, `DEPTHWISE_CONV_2D`, `FULLY_CONNECTED`,
+//! `AVERAGE_POOL_2D`, `MAX_POOL_2D`, `SOFTMAX`, `LOGISTIC`, `RELU`, `RELU6`,
+//! `TANH`, `RESHAPE`, `SQUEEZE`, `QUANTIZE` and `DEQUANTIZE`.
+//!
+//! Quantized models (including per-channel and float16 weights) are evaluated
+//! in floating point, with each operator's result rounded to its output's
+//! quantization grid. That means results may be off by a quantization step
+//! compared to TensorFlow Lite's integer kernels.
+
+mod ops;
+
+use std::convert::TryInto;
+
+use anyhow::{Context, Error};
+use hotg_rune_core::{ElementType, Shape};
+
+use self::ops::{Activation, Padding, Pooling, Window};
+use super::{
+    ensure_shapes_equal,
+    tflite_schema::{self, OperatorDef, Quantization, Table, TensorDef},
+};
+use crate::callbacks::Model;
+
+/// Create a new [`Model`] which uses the pure-Rust TensorFlow Lite interpreter.
+///
+/// If no `inputs` or `outputs` are provided, the shapes from the model itself
+/// will be used.
+pub fn load_tflite_interpreter(
+    model: &[u8],
+    inputs: &[Shape<'_>],
+    outputs: &[Shape<'_>],
+) -> Result<Box<dyn Model>, Error> {
+    let interpreter =
+        Interpreter::load(model).context("Unable to load the model")?;
+
+    if !inputs.is_empty() || !outputs.is_empty() {
+        ensure_shapes_equal(inputs, &interpreter.input_shapes)?;
+        ensure_shapes_equal(outputs, &interpreter.output_shapes)?;
+    }
+
+    Ok(Box::new(interpreter))
+}
+
+#[derive(Debug)]
+struct Interpreter {
+    tensors: Vec<TensorInfo>,
+    /// The current value of each tensor. Constants are filled in when the
+    /// model is loaded.
+    values: Vec<Vec<f32>>,
+    operations: Vec<Operation>,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+    input_shapes: Vec<Shape<'static>>,
+    output_shapes: Vec<Shape<'static>>,
+}
+
+impl Interpreter {
+    fn load(model: &[u8]) -> Result<Self, Error> {
+        let file = tflite_schema::parse(model)?;
+
+        let tensors = file
+            .tensors
+            .iter()
+            .map(|t| {
+                TensorInfo::new(t)
+                    .with_context(|| format!("Invalid tensor, \"{}\"", t.name))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let values = file
+            .tensors
+            .iter()
+            .zip(&tensors)
+            .map(|(def, info)| info.decode(def.data))
+            .collect::<Result<Vec<_>, Error>>()
+            .context("Unable to read the model's weights")?;
+
+        let operations = file
+            .operators
+            .iter()
+            .enumerate()
+            .map(|(i, op)| {
+                Operation::new(op, &tensors)
+                    .with_context(|| format!("Invalid operator {}", i))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let shapes = |indices: &[usize]| -> Result<Vec<Shape<'static>>, Error> {
+            indices
+                .iter()
+                .map(|&ix| tensors.get(ix).context("Invalid tensor")?.shape())
+                .collect()
+        };
+        let input_shapes = shapes(&file.inputs).context("Invalid input")?;
+        let output_shapes = shapes(&file.outputs).context("Invalid output")?;
+
+        Ok(Interpreter {
+            inputs: file.inputs,
+            outputs: file.outputs,
+            tensors,
+            values,
+            operations,
+            input_shapes,
+            output_shapes,
+        })
+    }
+}
+
+impl Model for Interpreter {
+    fn infer(
+        &mut self,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+    ) -> Result<(), Error> {
+        anyhow::ensure!(
+            inputs.len() == self.inputs.len()
+                && outputs.len() == self.outputs.len(),
+            "The model has {} inputs and {} outputs, but was given {} and {}",
+            self.inputs.len(),
+            self.outputs.len(),
+            inputs.len(),
+            outputs.len(),
+        );
+
+        for (&ix, &data) in self.inputs.iter().zip(inputs) {
+            let tensor = &self.tensors[ix];
+            self.values[ix] = tensor.decode(data).with_context(|| {
+                format!("Invalid input, \"{}\"", tensor.name)
+            })?;
+        }
+
+        for (i, operation) in self.operations.iter().enumerate() {
+            operation
+                .run(&self.tensors, &mut self.values)
+                .with_context(|| {
+                    format!(
+                        "Unable to run operator {} ({:?})",
+                        i, operation.kernel
+                    )
+                })?;
+        }
+
+        for (&ix, buffer) in self.outputs.iter().zip(outputs.iter_mut()) {
+            let tensor = &self.tensors[ix];
+            tensor.encode(&self.values[ix], buffer).with_context(|| {
+                format!("Unable to write the \"{}\" output", tensor.name)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn input_shapes(&self) -> &[Shape<'_>] { &self.input_shapes }
+
+    fn output_shapes(&self) -> &[Shape<'_>] { &self.output_shapes }
+}
+
+/// Element types from the schema's `TensorType` enum.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum TensorType {
+    Float32,
+    Float16,
+    Int32,
+    UInt8,
+    Int64,
+    Int16,
+    Int8,
+    Float64,
+    BFloat16,
+}
+
+impl TensorType {
+    fn from_schema(value: i8) -> Result<Self, Error> {
+        Ok(match value {
+            0 => TensorType::Float32,
+            1 => TensorType::Float16,
+            2 => TensorType::Int32,
+            3 => TensorType::UInt8,
+            4 => TensorType::Int64,
+            7 => TensorType::Int16,
+            9 => TensorType::Int8,
+            10 => TensorType::Float64,
+            18 => TensorType::BFloat16,
+            other => anyhow::bail!("Unsupported tensor type ({})", other),
+        })
+    }
+
+    fn size_of(self) -> usize {
+        match self {
+            TensorType::UInt8 | TensorType::Int8 => 1,
+            TensorType::Float16 | TensorType::BFloat16 | TensorType::Int16 => 2,
+            TensorType::Float32 | TensorType::Int32 => 4,
+            TensorType::Int64 | TensorType::Float64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(
+            self,
+            TensorType::Float16
+                | TensorType::BFloat16
+                | TensorType::Float32
+                | TensorType::Float64
+        )
+    }
+
+    /// The `(min, max)` values an integer type can hold.
+    fn range(self) -> (f64, f64) {
+        match self {
+            TensorType::UInt8 => (0.0, u8::MAX as f64),
+            TensorType::Int8 => (i8::MIN as f64, i8::MAX as f64),
+            TensorType::Int16 => (i16::MIN as f64, i16::MAX as f64),
+            TensorType::Int32 => (i32::MIN as f64, i32::MAX as f64),
+            TensorType::Int64 => (i64::MIN as f64, i64::MAX as f64),
+            _ => (f64::NEG_INFINITY, f64::INFINITY),
+        }
+    }
+
+    fn rune_element_type(self) -> Result<ElementType, Error> {
+        Ok(match self {
+            TensorType::Float32 => ElementType::F32,
+            TensorType::Int32 => ElementType::I32,
+            TensorType::UInt8 => ElementType::U8,
+            TensorType::Int64 => ElementType::I64,
+            TensorType::Int16 => ElementType::I16,
+            TensorType::Int8 => ElementType::I8,
+            TensorType::Float64 => ElementType::F64,
+            TensorType::Float16 => ElementType::F16,
+            TensorType::BFloat16 => ElementType::BF16,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct TensorInfo {
+    name: String,
+    dimensions: Vec<usize>,
+    tensor_type: TensorType,
+    quantization: Option<Quantization>,
+}
+
+impl TensorInfo {
+    fn new(def: &TensorDef<'_>) -> Result<Self, Error> {
+        let tensor_type = TensorType::from_schema(def.tensor_type)?;
+
+        // Quantization parameters on float tensors are just informational
+        let quantization =
+            def.quantization.clone().filter(|_| !tensor_type.is_float());
+
+        Ok(TensorInfo {
+            name: def.name.to_string(),
+            dimensions: def.shape.clone(),
+            tensor_type,
+            quantization,
+        })
+    }
+
+    fn len(&self) -> usize { self.dimensions.iter().product() }
+
+    fn shape(&self) -> Result<Shape<'static>, Error> {
+        Ok(Shape::new(
+            self.tensor_type.rune_element_type()?,
+            self.dimensions.clone(),
+        ))
+    }
+
+    /// The `(scale, zero_point)` used by a particular element.
+    fn quantization_parameters(&self, index: usize) -> Option<(f64, f64)> {
+        let Quantization {
+            scale,
+            zero_point,
+            quantized_dimension,
+        } = self.quantization.as_ref()?;
+
+        let channel = if scale.len() > 1 {
+            let stride: usize = self
+                .dimensions
+                .get(quantized_dimension + 1..)
+                .unwrap_or_default()
+                .iter()
+                .product();
+            let channels = self
+                .dimensions
+                .get(*quantized_dimension)
+                .copied()
+                .unwrap_or(1);
+            (index / stride.max(1)) % channels.max(1)
+        } else {
+            0
+        };
+
+        let scale = scale.get(channel).copied().unwrap_or(1.0);
+        let zero_point = zero_point.get(channel).copied().unwrap_or(0);
+
+        Some((f64::from(scale), zero_point as f64))
+    }
+
+    /// Read the raw bytes for this tensor and convert them to real values.
+    fn decode(&self, data: &[u8]) -> Result<Vec<f32>, Error> {
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let size = self.tensor_type.size_of();
+        anyhow::ensure!(
+            data.len() == self.len() * size,
+            "Expected {} bytes but found {}",
+            self.len() * size,
+            data.len()
+        );
+
+        let values = data
+            .chunks_exact(size)
+            .enumerate()
+            .map(|(i, bytes)| {
+                let raw = read_element(self.tensor_type, bytes);
+
+                match self.quantization_parameters(i) {
+                    Some((scale, zero_point)) => {
+                        (scale * (raw - zero_point)) as f32
+                    },
+                    None => raw as f32,
+                }
+            })
+            .collect();
+
+        Ok(values)
+    }
+
+    /// Convert real values back to this tensor's element type.
+    fn encode(&self, values: &[f32], buffer: &mut [u8]) -> Result<(), Error> {
+        let size = self.tensor_type.size_of();
+        anyhow::ensure!(
+            buffer.len() == values.len() * size,
+            "Expected a {} byte buffer but found {}",
+            values.len() * size,
+            buffer.len()
+        );
+
+        for (i, (&value, bytes)) in
+            values.iter().zip(buffer.chunks_exact_mut(size)).enumerate()
+        {
+            let raw = self.quantize(i, value);
+            write_element(self.tensor_type, raw, bytes);
+        }
+
+        Ok(())
+    }
+
+    /// Map a real value to the value that would be stored in the tensor.
+    fn quantize(&self, index: usize, value: f32) -> f64 {
+        let value = f64::from(value);
+
+        if self.tensor_type.is_float() {
+            return value;
+        }
+
+        let (min, max) = self.tensor_type.range();
+        let q = match self.quantization_parameters(index) {
+            Some((scale, zero_point)) => (value / scale).round() + zero_point,
+            None => value.round(),
+        };
+
+        q.max(min).min(max)
+    }
+
+    /// Snap values to the values this tensor can actually represent, so
+    /// quantized models see the same rounding between operators as they
+    /// would with integer kernels.
+    fn round_trip(&self, values: &mut [f32]) {
+        if self.tensor_type.is_float() {
+            return;
+        }
+
+        for (i, value) in values.iter_mut().enumerate() {
+            let q = self.quantize(i, *value);
+
+            *value = match self.quantization_parameters(i) {
+                Some((scale, zero_point)) => (scale * (q - zero_point)) as f32,
+                None => q as f32,
+            };
+        }
+    }
+}
+
+fn read_element(tensor_type: TensorType, bytes: &[u8]) -> f64 {
+    match tensor_type {
+        TensorType::UInt8 => f64::from(bytes[0]),
+        TensorType::Int8 => f64::from(bytes[0] as i8),
+        TensorType::Int16 => {
+            f64::from(i16::from_le_bytes(bytes.try_into().unwrap()))
+        },
+        TensorType::Int32 => {
+            f64::from(i32::from_le_bytes(bytes.try_into().unwrap()))
+        },
+        TensorType::Int64 => {
+            i64::from_le_bytes(bytes.try_into().unwrap()) as f64
+        },
+        TensorType::Float16 => {
+            f64::from(f16_to_f32(u16::from_le_bytes(bytes.try_into().unwrap())))
+        },
+        TensorType::BFloat16 => f64::from(bf16_to_f32(u16::from_le_bytes(
+            bytes.try_into().unwrap(),
+        ))),
+        TensorType::Float32 => {
+            f64::from(f32::from_le_bytes(bytes.try_into().unwrap()))
+        },
+        TensorType::Float64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+fn write_element(tensor_type: TensorType, value: f64, bytes: &mut [u8]) {
+    match tensor_type {
+        TensorType::UInt8 => bytes[0] = value as u8,
+        TensorType::Int8 => bytes[0] = value as i8 as u8,
+        TensorType::Int16 => {
+            bytes.copy_from_slice(&(value as i16).to_le_bytes())
+        },
+        TensorType::Int32 => {
+            bytes.copy_from_slice(&(value as i32).to_le_bytes())
+        },
+        TensorType::Int64 => {
+            bytes.copy_from_slice(&(value as i64).to_le_bytes())
+        },
+        TensorType::Float16 => bytes.copy_from_slice(
+            &half::f16::from_f64(value).to_bits().to_le_bytes(),
+        ),
+        TensorType::BFloat16 => bytes.copy_from_slice(
+            &half::bf16::from_f64(value).to_bits().to_le_bytes(),
+        ),
+        TensorType::Float32 => {
+            bytes.copy_from_slice(&(value as f32).to_le_bytes())
+        },
+        TensorType::Float64 => bytes.copy_from_slice(&value.to_le_bytes()),
+    }
+}
+
+/// Convert an IEEE 754 half-precision float to a `f32`.
+fn f16_to_f32(bits: u16) -> f32 { half::f16::from_bits(bits).to_f32() }
+
+/// Convert a bfloat16 (the top 16 bits of a `f32`) to a `f32`.
+fn bf16_to_f32(bits: u16) -> f32 { half::bf16::from_bits(bits).to_f32() }
+
+#[derive(Debug)]
+struct Operation {
+    kernel: Kernel,
+    inputs: Vec<Option<usize>>,
+    output: usize,
+}
+
+/// The operators which are supported, along with their options.
+#[derive(Debug, Clone, PartialEq)]
+enum Kernel {
+    Add(Activation),
+    Sub(Activation),
+    Mul(Activation),
+    Conv2d(Window, Activation),
+    DepthwiseConv2d(Window, Activation),
+    FullyConnected(Activation),
+    Pool2d {
+        pooling: Pooling,
+        filter: [usize; 2],
+        window: Window,
+        activation: Activation,
+    },
+    Softmax {
+        beta: f32,
+    },
+    Logistic,
+    Relu,
+    Relu6,
+    Tanh,
+    /// Operators which just pass their input through (`RESHAPE`, `SQUEEZE`,
+    /// `QUANTIZE`, `DEQUANTIZE`) because values are always stored as real
+    /// numbers and quantization happens when writing the output.
+    Identity,
+}
+
+mod builtin {
+    pub(super) const ADD: i32 = 0;
+    pub(super) const AVERAGE_POOL_2D: i32 = 1;
+    pub(super) const CONV_2D: i32 = 3;
+    pub(super) const DEPTHWISE_CONV_2D: i32 = 4;
+    pub(super) const DEQUANTIZE: i32 = 6;
+    pub(super) const FULLY_CONNECTED: i32 = 9;
+    pub(super) const LOGISTIC: i32 = 14;
+    pub(super) const MAX_POOL_2D: i32 = 17;
+    pub(super) const MUL: i32 = 18;
+    pub(super) const RELU: i32 = 19;
+    pub(super) const RELU6: i32 = 21;
+    pub(super) const RESHAPE: i32 = 22;
+    pub(super) const SOFTMAX: i32 = 25;
+    pub(super) const TANH: i32 = 28;
+    pub(super) const SUB: i32 = 41;
+    pub(super) const SQUEEZE: i32 = 43;
+    pub(super) const QUANTIZE: i32 = 114;
+}
+
+impl Kernel {
+    fn new(opcode: i32, options: Option<Table<'_>>) -> Result<Self, Error> {
+        let int = |field: usize, default: i32| -> Result<i32, Error> {
+            match options {
+                Some(o) => o.scalar(field, default),
+                None => Ok(default),
+            }
+        };
+        let dimension = |field: usize| -> Result<usize, Error> {
+            Ok(int(field, 1)?.max(1) as usize)
+        };
+        let activation = |field: usize| -> Result<Activation, Error> {
+            let value = match options {
+                Some(o) => o.scalar::<i8>(field, 0)?,
+                None => 0,
+            };
+            activation_function(value)
+        };
+        let padding = || -> Result<Padding, Error> {
+            let value = match options {
+                Some(o) => o.scalar::<i8>(0, 0)?,
+                None => 0,
+            };
+            Ok(if value == 1 {
+                Padding::Valid
+            } else {
+                Padding::Same
+            })
+        };
+
+        let kernel = match opcode {
+            builtin::ADD => Kernel::Add(activation(0)?),
+            builtin::SUB => Kernel::Sub(activation(0)?),
+            builtin::MUL => Kernel::Mul(activation(0)?),
+            // Conv2DOptions
+            builtin::CONV_2D => Kernel::Conv2d(
+                Window {
+                    padding: padding()?,
+                    stride: [dimension(2)?, dimension(1)?],
+                    dilation: [dimension(5)?, dimension(4)?],
+                },
+                activation(3)?,
+            ),
+            // DepthwiseConv2DOptions
+            builtin::DEPTHWISE_CONV_2D => Kernel::DepthwiseConv2d(
+                Window {
+                    padding: padding()?,
+                    stride: [dimension(2)?, dimension(1)?],
+                    dilation: [dimension(6)?, dimension(5)?],
+                },
+                activation(4)?,
+            ),
+            builtin::FULLY_CONNECTED => Kernel::FullyConnected(activation(0)?),
+            // Pool2DOptions
+            builtin::AVERAGE_POOL_2D | builtin::MAX_POOL_2D => Kernel::Pool2d {
+                pooling: if opcode == builtin::MAX_POOL_2D {
+                    Pooling::Max
+                } else {
+                    Pooling::Average
+                },
+                filter: [dimension(4)?, dimension(3)?],
+                window: Window {
+                    padding: padding()?,
+                    stride: [dimension(2)?, dimension(1)?],
+                    dilation: [1, 1],
+                },
+                activation: activation(5)?,
+            },
+            builtin::SOFTMAX => Kernel::Softmax {
+                beta: match options {
+                    Some(o) => o.scalar(0, 0.0)?,
+                    None => 1.0,
+                },
+            },
+            builtin::LOGISTIC => Kernel::Logistic,
+            builtin::RELU => Kernel::Relu,
+            builtin::RELU6 => Kernel::Relu6,
+            builtin::TANH => Kernel::Tanh,
+            builtin::RESHAPE
+            | builtin::SQUEEZE
+            | builtin::QUANTIZE
+            | builtin::DEQUANTIZE => Kernel::Identity,
+            other => anyhow::bail!(
+                "The \"{}\" operator isn't supported by the interpreter",
+                other
+            ),
+        };
+
+        Ok(kernel)
+    }
+
+    fn activation(&self) -> Activation {
+        match self {
+            Kernel::Add(a)
+            | Kernel::Sub(a)
+            | Kernel::Mul(a)
+            | Kernel::Conv2d(_, a)
+            | Kernel::DepthwiseConv2d(_, a)
+            | Kernel::FullyConnected(a)
+            | Kernel::Pool2d { activation: a, .. } => *a,
+            _ => Activation::None,
+        }
+    }
+}
+
+/// The schema's `ActivationFunctionType` enum.
+fn activation_function(value: i8) -> Result<Activation, Error> {
+    Ok(match value {
+        0 => Activation::None,
+        1 => Activation::Relu,
+        2 => Activation::ReluN1To1,
+        3 => Activation::Relu6,
+        4 => Activation::Tanh,
+        other => anyhow::bail!("Unsupported activation function ({})", other),
+    })
+}
+
+impl Operation {
+    fn new(
+        op: &OperatorDef<'_>,
+        tensors: &[TensorInfo],
+    ) -> Result<Self, Error> {
+        let kernel = Kernel::new(op.opcode, op.options)?;
+
+        let output = match op.outputs.as_slice() {
+            [output] => *output,
+            other => {
+                anyhow::bail!("Expected a single output, found {}", other.len())
+            },
+        };
+
+        for &ix in op.inputs.iter().flatten().chain(Some(&output)) {
+            anyhow::ensure!(ix < tensors.len(), "There is no tensor {}", ix);
+        }
+
+        let operation = Operation {
+            kernel,
+            inputs: op.inputs.clone(),
+            output,
+        };
+        operation.validate(tensors)?;
+
+        Ok(operation)
+    }
+
+    /// Make sure the operands have shapes the kernel can handle, so a
+    /// malformed model is rejected when it is loaded instead of making a
+    /// kernel index out of bounds.
+    fn validate(&self, tensors: &[TensorInfo]) -> Result<(), Error> {
+        let dimensions = |n: usize| -> Result<&[usize], Error> {
+            let ix = self
+                .inputs
+                .get(n)
+                .copied()
+                .flatten()
+                .with_context(|| format!("Input {} is missing", n))?;
+            Ok(&tensors[ix].dimensions)
+        };
+        let bias_len = |n: usize| -> Option<usize> {
+            let ix = self.inputs.get(n).copied().flatten()?;
+            Some(tensors[ix].len())
+        };
+        let output = tensors[self.output].dimensions.as_slice();
+        let len = |shape: &[usize]| shape.iter().product::<usize>();
+
+        match &self.kernel {
+            Kernel::Add(_) | Kernel::Sub(_) | Kernel::Mul(_) => {
+                for n in 0..2 {
+                    let shape = dimensions(n)?;
+                    anyhow::ensure!(
+                        broadcasts_to(shape, output),
+                        "Input {} ({:?}) can't be broadcast to the output \
+                         ({:?})",
+                        n,
+                        shape,
+                        output
+                    );
+                }
+            },
+            Kernel::Conv2d(..) | Kernel::DepthwiseConv2d(..) => {
+                let input = rank_4(dimensions(0)?, "input")?;
+                let filter = rank_4(dimensions(1)?, "filter")?;
+                let output = rank_4(output, "output")?;
+                let [batches, _, _, in_c] = input;
+                let [_, k_h, k_w, _] = filter;
+                let [out_batches, out_h, out_w, out_c] = output;
+
+                let expected_filter = match self.kernel {
+                    Kernel::Conv2d(..) => [out_c, k_h, k_w, in_c],
+                    _ => [1, k_h, k_w, out_c],
+                };
+                anyhow::ensure!(
+                    filter == expected_filter,
+                    "Expected a {:?} filter but found {:?}",
+                    expected_filter,
+                    filter
+                );
+                anyhow::ensure!(
+                    batches == out_batches,
+                    "The input has {} batches but the output has {}",
+                    batches,
+                    out_batches
+                );
+                anyhow::ensure!(
+                    in_c > 0 && out_c % in_c == 0,
+                    "Can't produce {} output channels from {} input channels",
+                    out_c,
+                    in_c
+                );
+                anyhow::ensure!(
+                    k_h > 0 && k_w > 0 && out_h > 0 && out_w > 0,
+                    "The filter and output can't be empty"
+                );
+                if let Some(bias) = bias_len(2) {
+                    anyhow::ensure!(
+                        bias == out_c,
+                        "Expected {} bias values but found {}",
+                        out_c,
+                        bias
+                    );
+                }
+            },
+            Kernel::FullyConnected(_) => {
+                let input = dimensions(0)?;
+                let (units, depth) = match *dimensions(1)? {
+                    [units, depth] if depth > 0 => (units, depth),
+                    ref other => anyhow::bail!(
+                        "Expected [units, depth] weights but found {:?}",
+                        other
+                    ),
+                };
+                anyhow::ensure!(
+                    len(input) % depth == 0,
+                    "The input ({:?}) can't be split into rows of {}",
+                    input,
+                    depth
+                );
+                let expected = len(input) / depth * units;
+                anyhow::ensure!(
+                    len(output) == expected,
+                    "Expected {} output elements but the output is {:?}",
+                    expected,
+                    output
+                );
+                if let Some(bias) = bias_len(2) {
+                    anyhow::ensure!(
+                        bias == units,
+                        "Expected {} bias values but found {}",
+                        units,
+                        bias
+                    );
+                }
+            },
+            Kernel::Pool2d { filter, .. } => {
+                let [batches, _, _, channels] =
+                    rank_4(dimensions(0)?, "input")?;
+                let [out_batches, out_h, out_w, out_c] =
+                    rank_4(output, "output")?;
+                anyhow::ensure!(
+                    batches == out_batches && channels == out_c,
+                    "Can't pool a {:?} input into a {:?} output",
+                    dimensions(0)?,
+                    output
+                );
+                anyhow::ensure!(
+                    filter.iter().all(|&d| d > 0) && out_h > 0 && out_w > 0,
+                    "The filter and output can't be empty"
+                );
+            },
+            Kernel::Softmax { .. }
+            | Kernel::Logistic
+            | Kernel::Relu
+            | Kernel::Relu6
+            | Kernel::Tanh
+            | Kernel::Identity => {
+                let input = dimensions(0)?;
+                anyhow::ensure!(
+                    len(input) == len(output),
+                    "Expected {} elements but the input is {:?}",
+                    len(output),
+                    input
+                );
+            },
+        }
+
+        Ok(())
+    }
+
+    fn run(
+        &self,
+        tensors: &[TensorInfo],
+        values: &mut [Vec<f32>],
+    ) -> Result<(), Error> {
+        let info = &tensors[self.output];
+
+        let mut output = std::mem::take(&mut values[self.output]);
+        output.clear();
+        output.resize(info.len(), 0.0);
+
+        let result = self.compute(tensors, values, &mut output);
+
+        let activation = self.kernel.activation();
+        output.iter_mut().for_each(|x| *x = activation.apply(*x));
+        info.round_trip(&mut output);
+
+        values[self.output] = output;
+
+        result
+    }
+
+    fn compute(
+        &self,
+        tensors: &[TensorInfo],
+        values: &[Vec<f32>],
+        output: &mut [f32],
+    ) -> Result<(), Error> {
+        let input = |n| self.input(tensors, values, n);
+        let optional = |n| self.optional_input(tensors, values, n);
+        let output_shape = tensors[self.output].dimensions.as_slice();
+
+        match &self.kernel {
+            Kernel::Add(_) | Kernel::Sub(_) | Kernel::Mul(_) => {
+                let (lhs, lhs_shape) = input(0)?;
+                let (rhs, rhs_shape) = input(1)?;
+                let op: fn(f32, f32) -> f32 = match self.kernel {
+                    Kernel::Add(_) => |a: f32, b: f32| a + b,
+                    Kernel::Sub(_) => |a: f32, b: f32| a - b,
+                    _ => |a: f32, b: f32| a * b,
+                };
+
+                ops::broadcast(
+                    lhs,
+                    lhs_shape,
+                    rhs,
+                    rhs_shape,
+                    output,
+                    output_shape,
+                    op,
+                );
+            },
+            Kernel::Conv2d(window, _) | Kernel::DepthwiseConv2d(window, _) => {
+                let (data, data_shape) = input(0)?;
+                let (filter, filter_shape) = input(1)?;
+                let bias = optional(2)?;
+                let conv = match self.kernel {
+                    Kernel::Conv2d(..) => ops::conv_2d,
+                    _ => ops::depthwise_conv_2d,
+                };
+
+                conv(
+                    data,
+                    data_shape,
+                    filter,
+                    filter_shape,
+                    bias,
+                    output,
+                    output_shape,
+                    window,
+                );
+            },
+            Kernel::FullyConnected(_) => {
+                let (data, _) = input(0)?;
+                let (weights, weights_shape) = input(1)?;
+                let bias = optional(2)?;
+
+                ops::fully_connected(
+                    data,
+                    weights,
+                    weights_shape,
+                    bias,
+                    output,
+                );
+            },
+            Kernel::Pool2d {
+                pooling,
+                filter,
+                window,
+                ..
+            } => {
+                let (data, data_shape) = input(0)?;
+
+                ops::pool_2d(
+                    *pooling,
+                    data,
+                    data_shape,
+                    *filter,
+                    output,
+                    output_shape,
+                    window,
+                );
+            },
+            Kernel::Softmax { beta } => {
+                let (data, data_shape) = input(0)?;
+                let depth = data_shape.last().copied().unwrap_or(1);
+
+                ops::softmax(data, depth, *beta, output);
+            },
+            Kernel::Logistic | Kernel::Relu | Kernel::Relu6 | Kernel::Tanh => {
+                let (data, _) = input(0)?;
+                let f: fn(f32) -> f32 = match self.kernel {
+                    Kernel::Logistic => |x: f32| 1.0 / (1.0 + (-x).exp()),
+                    Kernel::Relu => |x: f32| x.max(0.0),
+                    Kernel::Relu6 => |x: f32| x.max(0.0).min(6.0),
+                    _ => |x: f32| x.tanh(),
+                };
+
+                for (y, &x) in output.iter_mut().zip(data) {
+                    *y = f(x);
+                }
+            },
+            Kernel::Identity => {
+                let (data, _) = input(0)?;
+                anyhow::ensure!(
+                    data.len() == output.len(),
+                    "Expected {} elements but found {}",
+                    output.len(),
+                    data.len()
+                );
+                output.copy_from_slice(data);
+            },
+        }
+
+        Ok(())
+    }
+
+    fn input<'v>(
+        &self,
+        tensors: &'v [TensorInfo],
+        values: &'v [Vec<f32>],
+        n: usize,
+    ) -> Result<(&'v [f32], &'v [usize]), Error> {
+        let ix = self
+            .inputs
+            .get(n)
+            .copied()
+            .flatten()
+            .with_context(|| format!("Input {} is missing", n))?;
+        let (value, info) = (&values[ix], &tensors[ix]);
+
+        anyhow::ensure!(
+            value.len() == info.len(),
+            "Input {} (\"{}\") hasn't been initialized",
+            n,
+            info.name
+        );
+
+        Ok((value, &info.dimensions))
+    }
+
+    /// Get an input which may have been left out (e.g. a bias).
+    fn optional_input<'v>(
+        &self,
+        tensors: &'v [TensorInfo],
+        values: &'v [Vec<f32>],
+        n: usize,
+    ) -> Result<Option<&'v [f32]>, Error> {
+        match self.inputs.get(n).copied().flatten() {
+            Some(_) => Ok(Some(self.input(tensors, values, n)?.0)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Can a tensor with this shape be broadcast to `output` using numpy-style
+/// broadcasting?
+fn broadcasts_to(shape: &[usize], output: &[usize]) -> bool {
+    shape.len() <= output.len()
+        && shape
+            .iter()
+            .rev()
+            .zip(output.iter().rev())
+            .all(|(&dim, &out)| dim == out || dim == 1)
+}
+
+fn rank_4(shape: &[usize], name: &str) -> Result<[usize; 4], Error> {
+    shape.try_into().map_err(|_| {
+        Error::msg(format!(
+            "Expected the {} to be a 4D tensor, but found {:?}",
+            name, shape
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_channel_quantization() {
+        let tensor = TensorInfo {
+            name: String::from("weights"),
+            dimensions: vec![2, 2],
+            tensor_type: TensorType::Int8,
+            quantization: Some(Quantization {
+                scale: vec![0.5, 0.25],
+                zero_point: vec![0, 0],
+                quantized_dimension: 0,
+            }),
+        };
+        let raw = [2_i8, -4, 8, 12].map(|q| q as u8);
+
+        let values = tensor.decode(&raw).unwrap();
+        assert_eq!(values, vec![1.0, -2.0, 2.0, 3.0]);
+
+        let mut buffer = [0_u8; 4];
+        tensor.encode(&values, &mut buffer).unwrap();
+        assert_eq!(buffer, raw);
+    }
+
+    #[test]
+    fn run_the_sine_model() {
+        let model = include_bytes!(
+            "../../../../../integration-tests/run-pass/sine/sinemodel.tflite"
+        );
+        let mut interpreter = Interpreter::load(model).unwrap();
+        let shape: Shape<'static> = "f32[1, 1]".parse().unwrap();
+        assert_eq!(interpreter.input_shapes(), &[shape.clone()]);
+        assert_eq!(interpreter.output_shapes(), &[shape]);
+        let input = 0.8_f32.to_le_bytes();
+        let mut output = [0_u8; 4];
+
+        interpreter.infer(&[&input], &mut [&mut output]).unwrap();
+
+        // The value TensorFlow Lite gives for the run-pass/sine test
+        let got = f32::from_le_bytes(output);
+        assert!((got - 0.697_278_74).abs() < 1e-5, "{}", got);
+    }
+
+    #[test]
+    fn reject_operators_with_mismatched_shapes() {
+        let tensor = |dimensions: &[usize]| TensorInfo {
+            name: String::new(),
+            dimensions: dimensions.to_vec(),
+            tensor_type: TensorType::Float32,
+            quantization: None,
+        };
+        let tensors = [
+            tensor(&[1, 4]),
+            tensor(&[3, 5]),
+            tensor(&[1, 3]),
+            tensor(&[2, 3]),
+        ];
+        let op = |opcode, inputs: &[usize], output| OperatorDef {
+            opcode,
+            inputs: inputs.iter().copied().map(Some).collect(),
+            outputs: vec![output],
+            options: None,
+        };
+
+        // The weights expect rows of 5, but the input has rows of 4
+        let fully_connected = op(builtin::FULLY_CONNECTED, &[0, 1], 2);
+        assert!(Operation::new(&fully_connected, &tensors).is_err());
+        // [1, 4] can't be broadcast to [2, 3]
+        let add = op(builtin::ADD, &[0, 2], 3);
+        assert!(Operation::new(&add, &tensors).is_err());
+        // Convolutions need 4D tensors
+        let conv = op(builtin::CONV_2D, &[0, 1], 2);
+        assert!(Operation::new(&conv, &tensors).is_err());
+
+        let add = op(builtin::ADD, &[2, 3], 3);
+        assert!(Operation::new(&add, &tensors).is_ok());
+    }
+
+    #[test]
+    fn convert_half_precision_floats() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x0001), 5.960_464_5e-8);
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+
+        assert_eq!(bf16_to_f32(0x3f80), 1.0);
+        assert_eq!(bf16_to_f32(0xc000), -2.0);
+        assert_eq!(bf16_to_f32(0x7f80), f32::INFINITY);
+    }
+}
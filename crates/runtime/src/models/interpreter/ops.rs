@@ -0,0 +1,454 @@
+//! Reference implementations of the supported TensorFlow Lite operators.
+//!
+//! Every tensor uses the NHWC layout and is stored as real-valued `f32`s, with
+//! quantized tensors being dequantized on the way in and requantized on the
+//! way out.
+
+/// How a convolution or pooling window handles the edges of its input.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Padding {
+    /// Pad the input so the output has `ceil(input / stride)` elements.
+    Same,
+    /// Only use windows that fit entirely inside the input.
+    Valid,
+}
+
+impl Padding {
+    /// The number of (implicit) zeroes added before the start of a dimension.
+    fn before(
+        self,
+        input: usize,
+        output: usize,
+        stride: usize,
+        filter: usize,
+        dilation: usize,
+    ) -> usize {
+        match self {
+            Padding::Same => {
+                let effective_filter = (filter - 1) * dilation + 1;
+                ((output - 1) * stride + effective_filter).saturating_sub(input)
+                    / 2
+            },
+            Padding::Valid => 0,
+        }
+    }
+}
+
+/// An activation function which is fused into the preceding operator.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Activation {
+    None,
+    Relu,
+    ReluN1To1,
+    Relu6,
+    Tanh,
+}
+
+impl Activation {
+    pub(crate) fn apply(self, x: f32) -> f32 {
+        match self {
+            Activation::None => x,
+            Activation::Relu => x.max(0.0),
+            Activation::ReluN1To1 => x.max(-1.0).min(1.0),
+            Activation::Relu6 => x.max(0.0).min(6.0),
+            Activation::Tanh => x.tanh(),
+        }
+    }
+}
+
+/// A 2D window moving over an NHWC tensor.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Window {
+    pub(crate) padding: Padding,
+    pub(crate) stride: [usize; 2],
+    pub(crate) dilation: [usize; 2],
+}
+
+/// The `[batch, height, width, channels]` of an NHWC tensor.
+fn nhwc(shape: &[usize]) -> [usize; 4] {
+    match *shape {
+        [n, h, w, c] => [n, h, w, c],
+        [h, w, c] => [1, h, w, c],
+        _ => [1, 1, 1, shape.iter().product()],
+    }
+}
+
+/// Visit every in-bounds input position covered by the filter when it is
+/// centered on output position `(y, x)`.
+fn for_each_tap(
+    window: &Window,
+    input: [usize; 2],
+    output: [usize; 2],
+    filter: [usize; 2],
+    position: [usize; 2],
+    mut visit: impl FnMut(usize, usize, usize, usize),
+) {
+    let pad_y = window.padding.before(
+        input[0],
+        output[0],
+        window.stride[0],
+        filter[0],
+        window.dilation[0],
+    );
+    let pad_x = window.padding.before(
+        input[1],
+        output[1],
+        window.stride[1],
+        filter[1],
+        window.dilation[1],
+    );
+
+    for ky in 0..filter[0] {
+        let iy = (position[0] * window.stride[0] + ky * window.dilation[0])
+            .checked_sub(pad_y)
+            .filter(|&iy| iy < input[0]);
+        let iy = match iy {
+            Some(iy) => iy,
+            None => continue,
+        };
+
+        for kx in 0..filter[1] {
+            let ix = (position[1] * window.stride[1] + kx * window.dilation[1])
+                .checked_sub(pad_x)
+                .filter(|&ix| ix < input[1]);
+
+            if let Some(ix) = ix {
+                visit(ky, kx, iy, ix);
+            }
+        }
+    }
+}
+
+/// A 2D convolution with a `[out_channels, height, width, in_channels]`
+/// filter.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn conv_2d(
+    input: &[f32],
+    input_shape: &[usize],
+    filter: &[f32],
+    filter_shape: &[usize],
+    bias: Option<&[f32]>,
+    output: &mut [f32],
+    output_shape: &[usize],
+    window: &Window,
+) {
+    let [batches, in_h, in_w, in_c] = nhwc(input_shape);
+    let [_, out_h, out_w, out_c] = nhwc(output_shape);
+    let [_, k_h, k_w, _] = nhwc(filter_shape);
+
+    for b in 0..batches {
+        for oy in 0..out_h {
+            for ox in 0..out_w {
+                for oc in 0..out_c {
+                    let mut sum = bias.map(|bias| bias[oc]).unwrap_or(0.0);
+
+                    for_each_tap(
+                        window,
+                        [in_h, in_w],
+                        [out_h, out_w],
+                        [k_h, k_w],
+                        [oy, ox],
+                        |ky, kx, iy, ix| {
+                            let pixel = ((b * in_h + iy) * in_w + ix) * in_c;
+                            let weights = ((oc * k_h + ky) * k_w + kx) * in_c;
+
+                            sum += input[pixel..pixel + in_c]
+                                .iter()
+                                .zip(&filter[weights..weights + in_c])
+                                .map(|(x, w)| x * w)
+                                .sum::<f32>();
+                        },
+                    );
+
+                    output[((b * out_h + oy) * out_w + ox) * out_c + oc] = sum;
+                }
+            }
+        }
+    }
+}
+
+/// A depthwise 2D convolution with a `[1, height, width, out_channels]`
+/// filter, where each input channel produces `out_channels / in_channels`
+/// output channels.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn depthwise_conv_2d(
+    input: &[f32],
+    input_shape: &[usize],
+    filter: &[f32],
+    filter_shape: &[usize],
+    bias: Option<&[f32]>,
+    output: &mut [f32],
+    output_shape: &[usize],
+    window: &Window,
+) {
+    let [batches, in_h, in_w, in_c] = nhwc(input_shape);
+    let [_, out_h, out_w, out_c] = nhwc(output_shape);
+    let [_, k_h, k_w, _] = nhwc(filter_shape);
+    let multiplier = (out_c / in_c.max(1)).max(1);
+
+    for b in 0..batches {
+        for oy in 0..out_h {
+            for ox in 0..out_w {
+                for oc in 0..out_c {
+                    let ic = oc / multiplier;
+                    let mut sum = bias.map(|bias| bias[oc]).unwrap_or(0.0);
+
+                    for_each_tap(
+                        window,
+                        [in_h, in_w],
+                        [out_h, out_w],
+                        [k_h, k_w],
+                        [oy, ox],
+                        |ky, kx, iy, ix| {
+                            let pixel = ((b * in_h + iy) * in_w + ix) * in_c;
+                            let weight = (ky * k_w + kx) * out_c + oc;
+
+                            sum += input[pixel + ic] * filter[weight];
+                        },
+                    );
+
+                    output[((b * out_h + oy) * out_w + ox) * out_c + oc] = sum;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Pooling {
+    Average,
+    Max,
+}
+
+pub(crate) fn pool_2d(
+    pooling: Pooling,
+    input: &[f32],
+    input_shape: &[usize],
+    filter: [usize; 2],
+    output: &mut [f32],
+    output_shape: &[usize],
+    window: &Window,
+) {
+    let [batches, in_h, in_w, channels] = nhwc(input_shape);
+    let [_, out_h, out_w, _] = nhwc(output_shape);
+
+    for b in 0..batches {
+        for oy in 0..out_h {
+            for ox in 0..out_w {
+                for c in 0..channels {
+                    let mut sum = 0.0;
+                    let mut max = f32::NEG_INFINITY;
+                    let mut count = 0;
+
+                    for_each_tap(
+                        window,
+                        [in_h, in_w],
+                        [out_h, out_w],
+                        filter,
+                        [oy, ox],
+                        |_, _, iy, ix| {
+                            let value = input
+                                [((b * in_h + iy) * in_w + ix) * channels + c];
+                            sum += value;
+                            max = max.max(value);
+                            count += 1;
+                        },
+                    );
+
+                    // Note: padding isn't included in the average
+                    let value = match pooling {
+                        Pooling::Average if count > 0 => sum / count as f32,
+                        Pooling::Average => 0.0,
+                        Pooling::Max => max,
+                    };
+
+                    output[((b * out_h + oy) * out_w + ox) * channels + c] =
+                        value;
+                }
+            }
+        }
+    }
+}
+
+/// Multiply each row of the input by a `[units, depth]` weight matrix.
+pub(crate) fn fully_connected(
+    input: &[f32],
+    weights: &[f32],
+    weights_shape: &[usize],
+    bias: Option<&[f32]>,
+    output: &mut [f32],
+) {
+    let (units, depth) = match *weights_shape {
+        [units, depth] => (units, depth),
+        _ => (output.len(), input.len()),
+    };
+
+    for (row, out) in input
+        .chunks_exact(depth.max(1))
+        .zip(output.chunks_exact_mut(units.max(1)))
+    {
+        for (unit, out) in out.iter_mut().enumerate() {
+            let weights = &weights[unit * depth..(unit + 1) * depth];
+            let dot: f32 = row.iter().zip(weights).map(|(x, w)| x * w).sum();
+
+            *out = dot + bias.map(|b| b[unit]).unwrap_or(0.0);
+        }
+    }
+}
+
+/// Apply a softmax along the last dimension.
+pub(crate) fn softmax(
+    input: &[f32],
+    depth: usize,
+    beta: f32,
+    output: &mut [f32],
+) {
+    let depth = depth.max(1);
+
+    for (row, out) in input.chunks(depth).zip(output.chunks_mut(depth)) {
+        let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let mut total = 0.0;
+
+        for (x, y) in row.iter().zip(out.iter_mut()) {
+            *y = ((x - max) * beta).exp();
+            total += *y;
+        }
+
+        out.iter_mut().for_each(|y| *y /= total);
+    }
+}
+
+/// Apply a binary operator element-wise, using numpy-style broadcasting.
+pub(crate) fn broadcast(
+    lhs: &[f32],
+    lhs_shape: &[usize],
+    rhs: &[f32],
+    rhs_shape: &[usize],
+    output: &mut [f32],
+    output_shape: &[usize],
+    op: impl Fn(f32, f32) -> f32,
+) {
+    if lhs.len() == rhs.len() && lhs.len() == output.len() {
+        for ((out, l), r) in output.iter_mut().zip(lhs).zip(rhs) {
+            *out = op(*l, *r);
+        }
+        return;
+    }
+
+    let lhs_strides = broadcast_strides(lhs_shape, output_shape);
+    let rhs_strides = broadcast_strides(rhs_shape, output_shape);
+
+    for (i, out) in output.iter_mut().enumerate() {
+        let mut remaining = i;
+        let mut l = 0;
+        let mut r = 0;
+
+        for (axis, &dim) in output_shape.iter().enumerate().rev() {
+            let index = remaining % dim;
+            remaining /= dim;
+            l += index * lhs_strides[axis];
+            r += index * rhs_strides[axis];
+        }
+
+        *out = op(lhs[l], rhs[r]);
+    }
+}
+
+/// The stride for each axis of `output`, with `0` for axes that are
+/// broadcast.
+fn broadcast_strides(shape: &[usize], output: &[usize]) -> Vec<usize> {
+    let offset = output.len() - shape.len().min(output.len());
+    let mut strides = vec![0; output.len()];
+    let mut stride = 1;
+
+    for (axis, &dim) in shape.iter().enumerate().rev() {
+        if dim != 1 {
+            strides[offset + axis] = stride;
+        }
+        stride *= dim;
+    }
+
+    strides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conv_2d_with_same_padding() {
+        // A 3x3 image with a single channel, convolved with a 3x3 box filter
+        let input: Vec<f32> = (1..=9).map(|i| i as f32).collect();
+        let filter = vec![1.0; 9];
+        let mut output = vec![0.0; 9];
+        let window = Window {
+            padding: Padding::Same,
+            stride: [1, 1],
+            dilation: [1, 1],
+        };
+
+        conv_2d(
+            &input,
+            &[1, 3, 3, 1],
+            &filter,
+            &[1, 3, 3, 1],
+            Some(&[0.5]),
+            &mut output,
+            &[1, 3, 3, 1],
+            &window,
+        );
+
+        assert_eq!(
+            output,
+            vec![12.5, 21.5, 16.5, 27.5, 45.5, 33.5, 24.5, 39.5, 28.5]
+        );
+    }
+
+    #[test]
+    fn average_pooling_ignores_padding() {
+        let input = vec![1.0, 2.0, 3.0, 4.0];
+        let mut output = vec![0.0; 4];
+        let window = Window {
+            padding: Padding::Same,
+            stride: [1, 1],
+            dilation: [1, 1],
+        };
+
+        pool_2d(
+            Pooling::Average,
+            &input,
+            &[1, 2, 2, 1],
+            [2, 2],
+            &mut output,
+            &[1, 2, 2, 1],
+            &window,
+        );
+
+        assert_eq!(output, vec![2.5, 3.0, 3.5, 4.0]);
+    }
+
+    #[test]
+    fn broadcast_a_bias_across_rows() {
+        let lhs = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let rhs = vec![10.0, 20.0, 30.0];
+        let mut output = vec![0.0; 6];
+
+        broadcast(&lhs, &[2, 3], &rhs, &[3], &mut output, &[2, 3], |a, b| {
+            a + b
+        });
+
+        assert_eq!(output, vec![11.0, 22.0, 33.0, 14.0, 25.0, 36.0]);
+    }
+
+    #[test]
+    fn softmax_rows_sum_to_one() {
+        let input = vec![1.0, 2.0, 3.0, 0.0, 0.0, 0.0];
+        let mut output = vec![0.0; 6];
+
+        softmax(&input, 3, 1.0, &mut output);
+
+        assert!((output[..3].iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!(output[2] > output[1] && output[1] > output[0]);
+        assert!(output[3..].iter().all(|&y| (y - 1.0 / 3.0).abs() < 1e-6));
+    }
+}
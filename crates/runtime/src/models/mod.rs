@@ -1,21 +1,55 @@
 //! Functions for handling various "well-known" model formats.
 
+mod cache;
+#[cfg(feature = "tflite-interpreter")]
+mod interpreter;
+#[cfg(feature = "onnx")]
+mod onnx;
+#[cfg(feature = "tensorflow")]
+mod tensorflow;
 #[cfg(feature = "tflite")]
 mod tflite;
+pub(crate) mod tflite_schema;
+#[cfg(any(feature = "onnx", feature = "tensorflow"))]
+mod tract;
 
 use anyhow::Error;
-pub use hotg_rune_core::{TFJS_MIMETYPE, TFLITE_MIMETYPE, TF_MIMETYPE};
+pub use hotg_rune_core::{
+    ONNX_MIMETYPE, TFJS_MIMETYPE, TFLITE_MIMETYPE, TF_MIMETYPE,
+};
 
+pub use self::cache::ModelCache;
+#[cfg(feature = "tflite-interpreter")]
+pub use self::interpreter::load_tflite_interpreter;
+#[cfg(feature = "onnx")]
+pub use self::onnx::load_onnx;
+#[cfg(feature = "tensorflow")]
+pub use self::tensorflow::load_tensorflow;
 #[cfg(feature = "tflite")]
-pub use self::tflite::load_tflite;
+pub use self::tflite::{load_tflite, InterpreterPool};
 use crate::callbacks::{Model, ModelMetadata};
 
+/// The mimetype ONNX models are commonly served with, which is accepted as an
+/// alias for [`ONNX_MIMETYPE`].
+pub const ONNX_ALTERNATE_MIMETYPE: &str = "application/x-onnx";
+
 /// A model handler which will try to load a model based on the feature flags
 /// that have been set.
 ///
 /// Supported formats are:
 /// - TensorFlow Lite
-#[cfg_attr(not(feature = "tflite"), doc("(not supported)"))]
+#[cfg_attr(
+    all(not(feature = "tflite"), feature = "tflite-interpreter"),
+    doc = "(using the pure-Rust interpreter)"
+)]
+#[cfg_attr(
+    not(any(feature = "tflite", feature = "tflite-interpreter")),
+    doc = "(not supported)"
+)]
+/// - ONNX
+#[cfg_attr(not(feature = "onnx"), doc = "(not supported)")]
+/// - TensorFlow (frozen graphs)
+#[cfg_attr(not(feature = "tensorflow"), doc = "(not supported)")]
 pub fn default_model_handler(
     _id: u32,
     meta: &ModelMetadata<'_>,
@@ -31,6 +65,14 @@ pub fn default_model_handler(
     match mimetype {
         #[cfg(feature = "tflite")]
         TFLITE_MIMETYPE => load_tflite(model, inputs, outputs),
+        #[cfg(all(not(feature = "tflite"), feature = "tflite-interpreter"))]
+        TFLITE_MIMETYPE => load_tflite_interpreter(model, inputs, outputs),
+        #[cfg(feature = "onnx")]
+        ONNX_MIMETYPE | ONNX_ALTERNATE_MIMETYPE => {
+            load_onnx(model, inputs, outputs)
+        },
+        #[cfg(feature = "tensorflow")]
+        TF_MIMETYPE => load_tensorflow(model, inputs, outputs),
         _ => Err(UnsupportedModelFormat::new(mimetype).into()),
     }
 }
@@ -38,12 +80,31 @@ pub fn default_model_handler(
 /// The error returned when the model handler can't handle a particular model
 /// format.
 #[derive(Debug, Clone, PartialEq, thiserror::Error)]
-#[error("The \"{}\" format isn't supported", mimetype)]
+#[error(
+    "The \"{}\" format isn't supported{}",
+    mimetype,
+    feature_hint(mimetype)
+)]
 pub struct UnsupportedModelFormat {
     /// The model's "mimetype".
     pub mimetype: String,
 }
 
+/// Point the user at the feature flag they forgot to enable when a
+/// well-known format was compiled out of the runtime.
+fn feature_hint(mimetype: &str) -> &'static str {
+    match mimetype {
+        TFLITE_MIMETYPE => {
+            " (enable the \"tflite\" or \"tflite-interpreter\" feature)"
+        },
+        ONNX_MIMETYPE | ONNX_ALTERNATE_MIMETYPE => {
+            " (enable the \"onnx\" feature)"
+        },
+        TF_MIMETYPE => " (enable the \"tensorflow\" feature)",
+        _ => "",
+    }
+}
+
 impl UnsupportedModelFormat {
     pub fn new(mimetype: impl Into<String>) -> Self {
         Self {
@@ -51,3 +112,69 @@ impl UnsupportedModelFormat {
         }
     }
 }
+
+/// Make sure the shapes the Rune was compiled with match the ones the model
+/// actually uses.
+#[cfg(any(
+    feature = "tflite-interpreter",
+    feature = "onnx",
+    feature = "tensorflow"
+))]
+pub(crate) fn ensure_shapes_equal(
+    from_rune: &[hotg_rune_core::Shape<'_>],
+    from_model: &[hotg_rune_core::Shape<'_>],
+) -> Result<(), Error> {
+    if from_rune == from_model {
+        return Ok(());
+    }
+
+    fn pretty_shapes(shapes: &[hotg_rune_core::Shape<'_>]) -> String {
+        format!(
+            "[{}]",
+            shapes
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    anyhow::bail!(
+        "The Rune said tensors would be {}, but the model said they would be \
+         {}",
+        pretty_shapes(from_rune),
+        pretty_shapes(from_model),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggest_the_missing_feature_flag() {
+        let onnx = UnsupportedModelFormat::new(ONNX_ALTERNATE_MIMETYPE);
+        let unknown = UnsupportedModelFormat::new("application/x-unknown");
+
+        assert_eq!(
+            onnx.to_string(),
+            "The \"application/x-onnx\" format isn't supported (enable the \
+             \"onnx\" feature)"
+        );
+        assert_eq!(
+            unknown.to_string(),
+            "The \"application/x-unknown\" format isn't supported"
+        );
+    }
+
+    #[test]
+    fn suggest_the_tensorflow_feature() {
+        let error = UnsupportedModelFormat::new(TF_MIMETYPE);
+
+        assert_eq!(
+            error.to_string(),
+            "The \"application/tf-model\" format isn't supported (enable the \
+             \"tensorflow\" feature)"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use std::io::Cursor;
+
+use anyhow::{Context, Error};
+use hotg_rune_core::Shape;
+use tract_onnx::prelude::Framework;
+
+use super::tract::TractModel;
+use crate::callbacks::Model;
+
+/// Create a new [`Model`] backed by [`tract_onnx`].
+///
+/// If no `inputs` or `outputs` are provided, the shapes from the model itself
+/// will be used.
+pub fn load_onnx(
+    model: &[u8],
+    inputs: &[Shape<'_>],
+    outputs: &[Shape<'_>],
+) -> Result<Box<dyn Model>, Error> {
+    let model = onnx_model(model, inputs, outputs)?;
+    Ok(Box::new(model))
+}
+
+pub(crate) fn onnx_model(
+    model: &[u8],
+    inputs: &[Shape<'_>],
+    outputs: &[Shape<'_>],
+) -> Result<TractModel, Error> {
+    let graph = tract_onnx::onnx()
+        .model_for_read(&mut Cursor::new(model))
+        .context("Unable to parse the ONNX model")?;
+
+    super::tract::load(graph, inputs, outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A model with a single `Add` node, `y = x + x`.
+    const DOUBLE: &[u8] = include_bytes!("../../tests/data/double.onnx");
+
+    #[test]
+    fn run_an_onnx_model() {
+        let shape: Shape<'static> = "f32[3]".parse().unwrap();
+        let mut model = load_onnx(DOUBLE, &[shape.clone()], &[shape]).unwrap();
+        let input: Vec<u8> = [1.0_f32, -2.0, 0.5]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        let mut output = [0_u8; 12];
+
+        model.infer(&[&input], &mut [&mut output]).unwrap();
+
+        let got: Vec<f32> = output
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(got, vec![2.0, -4.0, 1.0]);
+    }
+
+    #[test]
+    fn use_the_models_own_shapes() {
+        let model = load_onnx(DOUBLE, &[], &[]).unwrap();
+
+        let shape: Shape<'static> = "f32[3]".parse().unwrap();
+        assert_eq!(model.input_shapes(), &[shape.clone()]);
+        assert_eq!(model.output_shapes(), &[shape]);
+    }
+
+    #[test]
+    fn reject_shapes_the_model_doesnt_accept() {
+        let shape: Shape<'static> = "f32[3]".parse().unwrap();
+        let wrong: Shape<'static> = "f32[4]".parse().unwrap();
+
+        assert!(load_onnx(DOUBLE, &[shape], &[wrong]).is_err());
+    }
+}
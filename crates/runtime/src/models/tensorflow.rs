@@ -0,0 +1,74 @@
+use std::io::Cursor;
+
+use anyhow::{Context, Error};
+use hotg_rune_core::Shape;
+use tract_tensorflow::prelude::Framework;
+
+use super::tract::TractModel;
+use crate::callbacks::Model;
+
+/// Create a new [`Model`] from a frozen TensorFlow graph (a `GraphDef`
+/// protobuf), backed by [`tract_tensorflow`].
+///
+/// SavedModel directories need to be frozen into a single graph first (e.g.
+/// with `tf.compat.v1.graph_util.convert_variables_to_constants()`).
+///
+/// Like the TensorFlow Lite loader, the `inputs` and `outputs` from
+/// the Rune are checked against the shapes the model expects. If none are
+/// provided the model's own shapes will be used.
+pub fn load_tensorflow(
+    model: &[u8],
+    inputs: &[Shape<'_>],
+    outputs: &[Shape<'_>],
+) -> Result<Box<dyn Model>, Error> {
+    let model = tensorflow_model(model, inputs, outputs)?;
+    Ok(Box::new(model))
+}
+
+pub(crate) fn tensorflow_model(
+    model: &[u8],
+    inputs: &[Shape<'_>],
+    outputs: &[Shape<'_>],
+) -> Result<TractModel, Error> {
+    let graph = tract_tensorflow::tensorflow()
+        .model_for_read(&mut Cursor::new(model))
+        .context("Unable to parse the TensorFlow model")?;
+
+    super::tract::load(graph, inputs, outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frozen graph which adds a `three` constant to its `input`.
+    const PLUS3: &[u8] = include_bytes!("../../tests/data/plus3.pb");
+
+    #[test]
+    fn run_a_frozen_graph() {
+        let shape: Shape<'static> = "f32[2]".parse().unwrap();
+        let mut model =
+            load_tensorflow(PLUS3, &[shape.clone()], &[shape]).unwrap();
+        let input: Vec<u8> = [1.0_f32, -2.5]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        let mut output = [0_u8; 8];
+
+        model.infer(&[&input], &mut [&mut output]).unwrap();
+
+        let got: Vec<f32> = output
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(got, vec![4.0, 0.5]);
+    }
+
+    #[test]
+    fn reject_graphs_that_arent_protobuf() {
+        let shape: Shape<'static> = "f32[2]".parse().unwrap();
+
+        assert!(load_tensorflow(b"not a graph", &[shape.clone()], &[shape])
+            .is_err());
+    }
+}
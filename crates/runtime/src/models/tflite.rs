@@ -1,4 +1,11 @@
-use std::{borrow::Cow, convert::TryInto, ffi::CStr, sync::Mutex};
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap},
+    convert::TryInto,
+    ffi::CStr,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
 
 use anyhow::{Context, Error};
 use hotg_rune_core::{ElementType as RuneElementType, Shape, TFLITE_MIMETYPE};
@@ -7,14 +14,144 @@ use hotg_runecoral::{
     TensorDescriptor, TensorMut,
 };
 
-use crate::callbacks::Model;
+use crate::callbacks::{
+    Model, ModelOptions, TensorDescriptor as RuneTensorDescriptor, CPU_PROVIDER,
+};
+
+/// The execution providers a TensorFlow Lite model can run on, and the
+/// delegate used for each.
+const PROVIDERS: &[(&str, AccelerationBackend)] = &[
+    (CPU_PROVIDER, AccelerationBackend::NONE),
+    ("gpu", AccelerationBackend::GPU),
+    ("edgetpu", AccelerationBackend::EDGETPU),
+];
 
 /// Create a new [`Model`] backed by [`hotg_runecoral`].
+///
+/// If no `inputs` or `outputs` are provided, the shapes from the model itself
+/// will be used.
 pub fn load_tflite(
     model: &[u8],
     inputs: &[Shape<'_>],
     outputs: &[Shape<'_>],
 ) -> Result<Box<dyn Model>, Error> {
+    let ctx = create_context(model, AccelerationBackend::NONE)?;
+    wrap_context(Arc::from(model), Arc::new(Mutex::new(ctx)), inputs, outputs)
+}
+
+/// A set of TensorFlow Lite interpreters which can be shared between models.
+///
+/// Every call to [`load_tflite()`] creates a new interpreter with its own
+/// arena, even when a Runefile uses the same model in several stages. Loading
+/// models through an [`InterpreterPool`] instead means identical models are
+/// backed by a single interpreter, which is used by one stage at a time.
+///
+/// Clones of a pool share the same interpreters, so the pool can be moved into
+/// a model handler and reused by several [`crate::Runtime`]s. A model which is
+/// moved to another provider with [`Model::reconfigure()`] stops sharing and
+/// gets an interpreter of its own.
+///
+/// ```rust,no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use hotg_rune_runtime::{models::InterpreterPool, Runtime};
+///
+/// let pool = InterpreterPool::default();
+/// let runtime = Runtime::builder()
+///     .rune(std::fs::read("person_detection.rune")?)
+///     .model_handler(move |_id, meta, model| {
+///         pool.load(model, meta.inputs, meta.outputs)
+///     })
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct InterpreterPool {
+    interpreters: Arc<Mutex<HashMap<(u64, usize), (Arc<[u8]>, SharedContext)>>>,
+}
+
+type SharedContext = Arc<Mutex<InferenceContext>>;
+
+impl InterpreterPool {
+    /// Load a model, reusing an existing interpreter if this pool has already
+    /// loaded an identical model.
+    pub fn load(
+        &self,
+        model: &[u8],
+        inputs: &[Shape<'_>],
+        outputs: &[Shape<'_>],
+    ) -> Result<Box<dyn Model>, Error> {
+        let mut hasher = DefaultHasher::new();
+        model.hash(&mut hasher);
+        let key = (hasher.finish(), model.len());
+
+        let (model, ctx) = {
+            let mut interpreters =
+                self.interpreters.lock().expect("Lock was poisoned");
+
+            match interpreters.get(&key) {
+                Some((model, ctx)) => (Arc::clone(model), Arc::clone(ctx)),
+                None => {
+                    let ctx = create_context(model, AccelerationBackend::NONE)?;
+                    let entry = (Arc::from(model), Arc::new(Mutex::new(ctx)));
+                    interpreters.insert(key, entry.clone());
+                    entry
+                },
+            }
+        };
+
+        wrap_context(model, ctx, inputs, outputs)
+    }
+
+    /// The number of interpreters that have been created.
+    pub fn len(&self) -> usize {
+        self.interpreters.lock().expect("Lock was poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+fn create_context(
+    model: &[u8],
+    backend: AccelerationBackend,
+) -> Result<InferenceContext, Error> {
+    InferenceContext::create_context(TFLITE_MIMETYPE, model, backend)
+        .context("Unable to create the inference context")
+}
+
+/// Check the Rune's shapes against the interpreter and wrap it in a
+/// [`Model`].
+fn wrap_context(
+    model: Arc<[u8]>,
+    ctx: SharedContext,
+    inputs: &[Shape<'_>],
+    outputs: &[Shape<'_>],
+) -> Result<Box<dyn Model>, Error> {
+    let guard = ctx.lock().expect("Lock was poisoned");
+    let model_input_descriptors: Vec<_> = guard.inputs().collect();
+    let model_output_descriptors: Vec<_> = guard.outputs().collect();
+
+    let (inputs, outputs): (Vec<Shape<'static>>, Vec<Shape<'static>>) =
+        if inputs.is_empty() && outputs.is_empty() {
+            (
+                model_input_descriptors
+                    .iter()
+                    .map(shape)
+                    .collect::<Result<_, Error>>()
+                    .context("Invalid input")?,
+                model_output_descriptors
+                    .iter()
+                    .map(shape)
+                    .collect::<Result<_, Error>>()
+                    .context("Invalid output")?,
+            )
+        } else {
+            (
+                inputs.iter().map(|s| s.to_owned()).collect(),
+                outputs.iter().map(|s| s.to_owned()).collect(),
+            )
+        };
+
     let input_descriptors = inputs
         .iter()
         .map(descriptor)
@@ -26,27 +163,45 @@ pub fn load_tflite(
         .collect::<Result<Vec<_>, Error>>()
         .context("Invalid output")?;
 
-    let ctx = InferenceContext::create_context(
-        TFLITE_MIMETYPE,
-        model,
-        AccelerationBackend::NONE,
-    )
-    .context("Unable to create the inference context")?;
-
-    let model_input_descriptors: Vec<_> = ctx.inputs().collect();
     ensure_shapes_equal(&input_descriptors, &model_input_descriptors)?;
-    let model_output_descriptors: Vec<_> = ctx.outputs().collect();
     ensure_shapes_equal(&output_descriptors, &model_output_descriptors)?;
 
+    let named_inputs = named(&inputs, &model_input_descriptors);
+    let named_outputs = named(&outputs, &model_output_descriptors);
+
+    // The descriptors borrow from the context, so we need to unlock it before
+    // the model can take ownership.
+    drop(guard);
+
     Ok(Box::new(RuneCoralModel {
-        ctx: Mutex::new(ctx),
-        inputs: inputs.iter().map(|s| s.to_owned()).collect(),
+        model,
+        ctx,
+        provider: CPU_PROVIDER,
+        inputs,
         input_descriptors,
-        outputs: outputs.iter().map(|s| s.to_owned()).collect(),
+        outputs,
         output_descriptors,
+        named_inputs,
+        named_outputs,
     }))
 }
 
+/// Attach the names TensorFlow Lite uses for each tensor to their shapes.
+fn named(
+    shapes: &[Shape<'static>],
+    descriptors: &[TensorDescriptor<'_>],
+) -> Vec<RuneTensorDescriptor> {
+    shapes
+        .iter()
+        .zip(descriptors)
+        .map(|(shape, d)| RuneTensorDescriptor {
+            name: Some(d.name.to_string_lossy().into_owned())
+                .filter(|name| !name.is_empty()),
+            shape: shape.clone(),
+        })
+        .collect()
+}
+
 fn descriptor(s: &Shape) -> Result<TensorDescriptor<'static>, Error> {
     let dimensions: Vec<i32> = s
         .dimensions()
@@ -62,12 +217,29 @@ fn descriptor(s: &Shape) -> Result<TensorDescriptor<'static>, Error> {
     })
 }
 
+fn shape(d: &TensorDescriptor<'_>) -> Result<Shape<'static>, Error> {
+    let dimensions = d
+        .shape
+        .iter()
+        .map(|&dim| dim.try_into())
+        .collect::<Result<Vec<usize>, _>>()
+        .context("Invalid dimension")?;
+
+    Ok(Shape::new(rune_element_type(d.element_type)?, dimensions))
+}
+
 struct RuneCoralModel {
-    ctx: Mutex<InferenceContext>,
+    /// The original model, kept around so the interpreter can be recreated
+    /// with a different delegate.
+    model: Arc<[u8]>,
+    ctx: SharedContext,
+    provider: &'static str,
     inputs: Vec<Shape<'static>>,
     input_descriptors: Vec<TensorDescriptor<'static>>,
     outputs: Vec<Shape<'static>>,
     output_descriptors: Vec<TensorDescriptor<'static>>,
+    named_inputs: Vec<RuneTensorDescriptor>,
+    named_outputs: Vec<RuneTensorDescriptor>,
 }
 
 impl Model for RuneCoralModel {
@@ -109,6 +281,42 @@ impl Model for RuneCoralModel {
     fn input_shapes(&self) -> &[Shape<'_>] { &self.inputs }
 
     fn output_shapes(&self) -> &[Shape<'_>] { &self.outputs }
+
+    fn providers(&self) -> Vec<String> {
+        PROVIDERS.iter().map(|(name, _)| name.to_string()).collect()
+    }
+
+    fn reconfigure(&mut self, options: &ModelOptions) -> Result<(), Error> {
+        options.ensure_supported(&self.providers(), false)?;
+
+        let (name, backend) = match &options.provider {
+            Some(provider) => PROVIDERS
+                .iter()
+                .copied()
+                .find(|(name, _)| name == provider)
+                .expect("Checked by ensure_supported()"),
+            None => return Ok(()),
+        };
+
+        if name != self.provider {
+            let ctx =
+                create_context(&self.model, backend).with_context(|| {
+                    format!("Unable to use the \"{}\" delegate", name)
+                })?;
+            self.ctx = Arc::new(Mutex::new(ctx));
+            self.provider = name;
+        }
+
+        Ok(())
+    }
+
+    fn input_descriptors(&self) -> Vec<RuneTensorDescriptor> {
+        self.named_inputs.clone()
+    }
+
+    fn output_descriptors(&self) -> Vec<RuneTensorDescriptor> {
+        self.named_outputs.clone()
+    }
 }
 
 fn element_type(rune_type: RuneElementType) -> Result<ElementType, Error> {
@@ -120,6 +328,7 @@ fn element_type(rune_type: RuneElementType) -> Result<ElementType, Error> {
         RuneElementType::I64 => ElementType::Int64,
         RuneElementType::F32 => ElementType::Float32,
         RuneElementType::F64 => ElementType::Float64,
+        RuneElementType::F16 => ElementType::Float16,
         RuneElementType::String => ElementType::String,
         _ => {
             anyhow::bail!(
@@ -130,6 +339,26 @@ fn element_type(rune_type: RuneElementType) -> Result<ElementType, Error> {
     })
 }
 
+fn rune_element_type(
+    element_type: ElementType,
+) -> Result<RuneElementType, Error> {
+    Ok(match element_type {
+        ElementType::Int8 => RuneElementType::I8,
+        ElementType::UInt8 => RuneElementType::U8,
+        ElementType::Int16 => RuneElementType::I16,
+        ElementType::Int32 => RuneElementType::I32,
+        ElementType::Int64 => RuneElementType::I64,
+        ElementType::Float32 => RuneElementType::F32,
+        ElementType::Float64 => RuneElementType::F64,
+        ElementType::Float16 => RuneElementType::F16,
+        ElementType::String => RuneElementType::String,
+        #[allow(unreachable_patterns)]
+        other => {
+            anyhow::bail!("Rune doesn't support {:?} tensors", other)
+        },
+    })
+}
+
 fn ensure_shapes_equal(
     from_rune: &[TensorDescriptor<'_>],
     from_model: &[TensorDescriptor<'_>],
@@ -0,0 +1,449 @@
+//! Just enough of a [FlatBuffers][flatbuffers] reader to walk the parts of the
+//! [TensorFlow Lite schema][schema] the runtime needs, either to run a model
+//! with the pure-Rust interpreter or to find out what tensors a model loaded
+//! by a legacy Rune uses.
+//!
+//! Field numbers come from the order fields are declared in `schema.fbs`.
+//!
+//! [flatbuffers]: https://google.github.io/flatbuffers/flatbuffers_internals.html
+//! [schema]: https://github.com/tensorflow/tensorflow/blob/master/tensorflow/lite/schema/schema.fbs
+
+// Legacy Runes only need the tensor shapes, so most of the model is only read
+// by the interpreter.
+#![cfg_attr(not(feature = "tflite-interpreter"), allow(dead_code))]
+
+use std::{
+    convert::TryInto,
+    fmt::{self, Debug, Formatter},
+};
+
+use anyhow::{Context, Error};
+use hotg_rune_core::{ElementType, Shape};
+
+/// The file identifier TensorFlow Lite models are tagged with.
+const FILE_IDENTIFIER: &[u8] = b"TFL3";
+
+/// A parsed TensorFlow Lite model, borrowing its buffers from the original
+/// bytes.
+#[derive(Debug)]
+pub(crate) struct ModelFile<'a> {
+    pub(crate) tensors: Vec<TensorDef<'a>>,
+    pub(crate) inputs: Vec<usize>,
+    pub(crate) outputs: Vec<usize>,
+    pub(crate) operators: Vec<OperatorDef<'a>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct TensorDef<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) shape: Vec<usize>,
+    /// The `TensorType` enum from the schema.
+    pub(crate) tensor_type: i8,
+    /// The tensor's constant data, or an empty slice if it is computed at
+    /// runtime.
+    pub(crate) data: &'a [u8],
+    pub(crate) quantization: Option<Quantization>,
+}
+
+/// Affine quantization parameters, where `real = scale * (q - zero_point)`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Quantization {
+    /// Either a single scale, or one per channel along `quantized_dimension`.
+    pub(crate) scale: Vec<f32>,
+    pub(crate) zero_point: Vec<i64>,
+    pub(crate) quantized_dimension: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct OperatorDef<'a> {
+    /// The `BuiltinOperator` enum from the schema.
+    pub(crate) opcode: i32,
+    /// Indices into [`ModelFile::tensors`], with `None` for optional inputs
+    /// which weren't provided (e.g. a convolution without a bias).
+    pub(crate) inputs: Vec<Option<usize>>,
+    pub(crate) outputs: Vec<usize>,
+    pub(crate) options: Option<Table<'a>>,
+}
+
+/// Parse a TensorFlow Lite model's main subgraph.
+pub(crate) fn parse(buffer: &[u8]) -> Result<ModelFile<'_>, Error> {
+    if buffer.get(4..8) != Some(FILE_IDENTIFIER) {
+        anyhow::bail!("This isn't a TensorFlow Lite model");
+    }
+
+    let model = Table::root(buffer)?;
+
+    // Model
+    let opcodes = model
+        .tables(1)?
+        .iter()
+        .map(|code| {
+            // OperatorCode: the builtin_code field replaced the (i8)
+            // deprecated_builtin_code once there were more than 127 ops, so
+            // we need to check both.
+            let deprecated = code.scalar::<i8>(0, 0)?;
+            let builtin = code.scalar::<i32>(3, 0)?;
+            Ok(builtin.max(i32::from(deprecated)))
+        })
+        .collect::<Result<Vec<i32>, Error>>()
+        .context("Unable to read the operator codes")?;
+    let buffers = model.tables(4)?;
+
+    let subgraph = match model.tables(2)?.first() {
+        Some(s) => *s,
+        None => anyhow::bail!("The model doesn't contain any subgraphs"),
+    };
+
+    let tensors = subgraph
+        .tables(0)?
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            tensor_def(buffer, t, &buffers)
+                .with_context(|| format!("Unable to read tensor {}", i))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let operators = subgraph
+        .tables(3)?
+        .iter()
+        .enumerate()
+        .map(|(i, op)| {
+            operator_def(op, &opcodes)
+                .with_context(|| format!("Unable to read operator {}", i))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(ModelFile {
+        inputs: indices(&subgraph.scalars(1)?)?,
+        outputs: indices(&subgraph.scalars(2)?)?,
+        tensors,
+        operators,
+    })
+}
+
+/// Read the shapes of a TensorFlow Lite model's inputs and outputs.
+pub(crate) fn model_shapes(
+    buffer: &[u8],
+) -> Result<(Vec<Shape<'static>>, Vec<Shape<'static>>), Error> {
+    let file = parse(buffer)?;
+
+    let shapes = |indices: &[usize]| -> Result<Vec<Shape<'static>>, Error> {
+        indices
+            .iter()
+            .map(|&ix| {
+                let tensor = file.tensors.get(ix).context("Invalid tensor")?;
+                let element_type = element_type(tensor.tensor_type)
+                    .with_context(|| {
+                        format!("Invalid tensor, \"{}\"", tensor.name)
+                    })?;
+                Ok(Shape::new(element_type, tensor.shape.clone()))
+            })
+            .collect()
+    };
+
+    let inputs = shapes(&file.inputs).context("Invalid input")?;
+    let outputs = shapes(&file.outputs).context("Invalid output")?;
+
+    Ok((inputs, outputs))
+}
+
+/// Map the `TensorType` enum from the schema to an [`ElementType`].
+fn element_type(tensor_type: i8) -> Result<ElementType, Error> {
+    Ok(match tensor_type {
+        0 => ElementType::F32,
+        1 => ElementType::F16,
+        2 => ElementType::I32,
+        3 => ElementType::U8,
+        4 => ElementType::I64,
+        5 => ElementType::String,
+        7 => ElementType::I16,
+        9 => ElementType::I8,
+        10 => ElementType::F64,
+        12 => ElementType::U64,
+        15 => ElementType::U32,
+        16 => ElementType::U16,
+        18 => ElementType::BF16,
+        other => anyhow::bail!("Unsupported tensor type ({})", other),
+    })
+}
+
+fn tensor_def<'a>(
+    file: &'a [u8],
+    tensor: &Table<'a>,
+    buffers: &[Table<'a>],
+) -> Result<TensorDef<'a>, Error> {
+    let shape = tensor
+        .scalars::<i32>(0)?
+        .into_iter()
+        .map(|d| d.try_into().context("Dynamic shapes aren't supported"))
+        .collect::<Result<Vec<usize>, Error>>()?;
+
+    let buffer_index = tensor.scalar::<u32>(2, 0)? as usize;
+    let data = match buffers.get(buffer_index) {
+        Some(b) => buffer_data(file, b)?,
+        None => anyhow::bail!("There is no buffer {}", buffer_index),
+    };
+
+    let quantization = match tensor.table(4)? {
+        Some(q) => {
+            let scale = q.scalars::<f32>(2)?;
+            let zero_point = q.scalars::<i64>(3)?;
+            let quantized_dimension = q.scalar::<i32>(6, 0)? as usize;
+
+            if scale.is_empty() {
+                None
+            } else {
+                Some(Quantization {
+                    scale,
+                    zero_point,
+                    quantized_dimension,
+                })
+            }
+        },
+        None => None,
+    };
+
+    Ok(TensorDef {
+        name: tensor.string(3)?.unwrap_or_default(),
+        shape,
+        tensor_type: tensor.scalar(1, 0)?,
+        data,
+        quantization,
+    })
+}
+
+fn buffer_data<'a>(
+    file: &'a [u8],
+    buffer: &Table<'a>,
+) -> Result<&'a [u8], Error> {
+    let data = buffer.bytes(0)?;
+    if !data.is_empty() {
+        return Ok(data);
+    }
+
+    // Models over 2GB store their weights after the flatbuffer and use the
+    // offset and size fields instead.
+    let offset = buffer.scalar::<u64>(1, 0)? as usize;
+    let size = buffer.scalar::<u64>(2, 0)? as usize;
+
+    if offset > 1 && size > 0 {
+        file.get(offset..offset + size)
+            .context("The buffer extends past the end of the file")
+    } else {
+        Ok(&[])
+    }
+}
+
+fn operator_def<'a>(
+    op: &Table<'a>,
+    opcodes: &[i32],
+) -> Result<OperatorDef<'a>, Error> {
+    let opcode_index = op.scalar::<u32>(0, 0)? as usize;
+    let opcode = *opcodes.get(opcode_index).with_context(|| {
+        format!("There is no operator code {}", opcode_index)
+    })?;
+
+    let inputs = op
+        .scalars::<i32>(1)?
+        .into_iter()
+        .map(|ix| if ix < 0 { None } else { Some(ix as usize) })
+        .collect();
+
+    Ok(OperatorDef {
+        opcode,
+        inputs,
+        outputs: indices(&op.scalars(2)?)?,
+        options: op.table(4)?,
+    })
+}
+
+fn indices(raw: &[i32]) -> Result<Vec<usize>, Error> {
+    raw.iter()
+        .map(|&ix| ix.try_into().context("Invalid tensor index"))
+        .collect()
+}
+
+/// A table somewhere inside a flatbuffer.
+#[derive(Copy, Clone)]
+pub(crate) struct Table<'a> {
+    buffer: &'a [u8],
+    position: usize,
+}
+
+impl Debug for Table<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Table")
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl<'a> Table<'a> {
+    fn root(buffer: &'a [u8]) -> Result<Self, Error> {
+        let position = u32::read(buffer, 0)? as usize;
+
+        Ok(Table { buffer, position })
+    }
+
+    /// Find where a field is stored, returning `None` if it was left out
+    /// (e.g. because it was set to its default value).
+    fn field_position(&self, field: usize) -> Result<Option<usize>, Error> {
+        let vtable_offset = i32::read(self.buffer, self.position)?;
+        let vtable = (self.position as i64 - i64::from(vtable_offset))
+            .try_into()
+            .ok()
+            .filter(|&v: &usize| v < self.buffer.len())
+            .context("Invalid vtable offset")?;
+
+        let vtable_length = usize::from(u16::read(self.buffer, vtable)?);
+        let entry = 4 + 2 * field;
+
+        if entry + 2 > vtable_length {
+            return Ok(None);
+        }
+
+        match u16::read(self.buffer, vtable + entry)? {
+            0 => Ok(None),
+            offset => Ok(Some(self.position + usize::from(offset))),
+        }
+    }
+
+    /// Follow the offset stored in a field.
+    fn indirect(&self, field: usize) -> Result<Option<usize>, Error> {
+        match self.field_position(field)? {
+            Some(position) => {
+                let offset = u32::read(self.buffer, position)? as usize;
+                Ok(Some(position + offset))
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn scalar<T: Scalar>(
+        &self,
+        field: usize,
+        default: T,
+    ) -> Result<T, Error> {
+        match self.field_position(field)? {
+            Some(position) => T::read(self.buffer, position),
+            None => Ok(default),
+        }
+    }
+
+    pub(crate) fn table(
+        &self,
+        field: usize,
+    ) -> Result<Option<Table<'a>>, Error> {
+        Ok(self.indirect(field)?.map(|position| Table {
+            buffer: self.buffer,
+            position,
+        }))
+    }
+
+    /// Get the start and length of a vector.
+    fn vector(&self, field: usize) -> Result<Option<(usize, usize)>, Error> {
+        match self.indirect(field)? {
+            Some(position) => {
+                let len = u32::read(self.buffer, position)? as usize;
+                Ok(Some((position + 4, len)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn bytes(&self, field: usize) -> Result<&'a [u8], Error> {
+        match self.vector(field)? {
+            Some((start, len)) => self
+                .buffer
+                .get(start..start + len)
+                .context("The vector extends past the end of the buffer"),
+            None => Ok(&[]),
+        }
+    }
+
+    fn string(&self, field: usize) -> Result<Option<&'a str>, Error> {
+        if self.indirect(field)?.is_none() {
+            return Ok(None);
+        }
+
+        let bytes = self.bytes(field)?;
+        std::str::from_utf8(bytes)
+            .map(Some)
+            .context("The string isn't valid UTF-8")
+    }
+
+    pub(crate) fn scalars<T: Scalar>(
+        &self,
+        field: usize,
+    ) -> Result<Vec<T>, Error> {
+        match self.vector(field)? {
+            Some((start, len)) => (0..len)
+                .map(|i| T::read(self.buffer, start + i * T::SIZE))
+                .collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn tables(&self, field: usize) -> Result<Vec<Table<'a>>, Error> {
+        match self.vector(field)? {
+            Some((start, len)) => (0..len)
+                .map(|i| {
+                    let element = start + i * 4;
+                    let offset = u32::read(self.buffer, element)? as usize;
+                    Ok(Table {
+                        buffer: self.buffer,
+                        position: element + offset,
+                    })
+                })
+                .collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// A little-endian primitive stored in a flatbuffer.
+pub(crate) trait Scalar: Sized {
+    const SIZE: usize;
+
+    fn read(buffer: &[u8], position: usize) -> Result<Self, Error>;
+}
+
+macro_rules! scalars {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Scalar for $ty {
+                const SIZE: usize = std::mem::size_of::<$ty>();
+
+                fn read(buffer: &[u8], position: usize) -> Result<Self, Error> {
+                    let bytes = buffer
+                        .get(position..position + Self::SIZE)
+                        .context("Attempted to read past the end of the buffer")?;
+
+                    Ok(<$ty>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+scalars!(u8, i8, u16, i32, u32, u64, i64, f32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_the_sine_models_shapes() {
+        let model = include_bytes!(
+            "../../../../integration-tests/run-pass/sine/sinemodel.tflite"
+        );
+        let shape: Shape<'static> = "f32[1, 1]".parse().unwrap();
+
+        let (inputs, outputs) = model_shapes(model).unwrap();
+
+        assert_eq!(inputs, vec![shape.clone()]);
+        assert_eq!(outputs, vec![shape]);
+        assert!(model_shapes(b"not a model").is_err());
+    }
+}
@@ -0,0 +1,272 @@
+//! Helpers shared by the model formats which are executed using
+//! [`tract`](https://github.com/sonos/tract).
+
+use std::sync::Arc;
+
+use anyhow::{Context, Error};
+use hotg_rune_core::{ElementType, Shape};
+use tract_hir::prelude::{
+    tvec, DatumType, InferenceFact, InferenceModel, InferenceModelExt, Tensor,
+    TypedFact, TypedModel, TypedSimplePlan,
+};
+
+use super::ensure_shapes_equal;
+use crate::callbacks::{Model, TensorDescriptor};
+
+/// Turn a model that has been parsed by one of tract's frontends into a
+/// [`Model`].
+///
+/// The optimized plan is immutable, so clones of a [`TractModel`] can share
+/// it.
+///
+/// If no `inputs` or `outputs` are provided, the shapes from the model itself
+/// will be used.
+pub(crate) fn load(
+    mut graph: InferenceModel,
+    inputs: &[Shape<'_>],
+    outputs: &[Shape<'_>],
+) -> Result<TractModel, Error> {
+    // Models often leave their input shapes (or at least the batch size)
+    // unspecified, so we need to tell tract what the Rune will give it.
+    for (i, shape) in inputs.iter().enumerate() {
+        let fact = InferenceFact::dt_shape(
+            datum_type(shape.element_type())?,
+            shape.dimensions().to_vec(),
+        );
+        graph = graph.with_input_fact(i, fact).with_context(|| {
+            format!("Unable to set the shape of input {}", i)
+        })?;
+    }
+
+    let graph = graph
+        .into_optimized()
+        .context("Unable to optimize the model")?;
+
+    let model_inputs = (0..graph.input_outlets()?.len())
+        .map(|i| graph.input_fact(i).and_then(shape))
+        .collect::<Result<Vec<_>, Error>>()
+        .context("Invalid input")?;
+    let model_outputs = (0..graph.output_outlets()?.len())
+        .map(|i| graph.output_fact(i).and_then(shape))
+        .collect::<Result<Vec<_>, Error>>()
+        .context("Invalid output")?;
+
+    let (inputs, outputs): (Vec<Shape<'static>>, Vec<Shape<'static>>) =
+        if inputs.is_empty() && outputs.is_empty() {
+            (model_inputs, model_outputs)
+        } else {
+            let inputs: Vec<_> = inputs.iter().map(|s| s.to_owned()).collect();
+            let outputs: Vec<_> =
+                outputs.iter().map(|s| s.to_owned()).collect();
+
+            ensure_shapes_equal(&inputs, &model_inputs)?;
+            ensure_shapes_equal(&outputs, &model_outputs)?;
+
+            (inputs, outputs)
+        };
+
+    let input_names = graph
+        .input_outlets()?
+        .iter()
+        .map(|outlet| graph.node(outlet.node).name.clone())
+        .collect();
+    let output_names = graph
+        .output_outlets()?
+        .iter()
+        .map(|&outlet| match graph.outlet_label(outlet) {
+            Some(label) => label.to_string(),
+            None => graph.node(outlet.node).name.clone(),
+        })
+        .collect();
+
+    let plan = graph
+        .into_runnable()
+        .context("Unable to prepare the model for execution")?;
+
+    Ok(TractModel {
+        plan: Arc::new(plan),
+        inputs,
+        outputs,
+        input_names,
+        output_names,
+    })
+}
+
+fn named(shapes: &[Shape<'static>], names: &[String]) -> Vec<TensorDescriptor> {
+    shapes
+        .iter()
+        .zip(names)
+        .map(|(shape, name)| TensorDescriptor {
+            name: Some(name.clone()),
+            shape: shape.clone(),
+        })
+        .collect()
+}
+
+fn shape(fact: &TypedFact) -> Result<Shape<'static>, Error> {
+    let dimensions = fact.shape.as_concrete().with_context(|| {
+        format!(
+            "The model has a dynamic shape, {:?}, so the Runefile needs to \
+             specify it",
+            fact.shape
+        )
+    })?;
+
+    Ok(Shape::new(
+        rune_element_type(fact.datum_type)?,
+        dimensions.to_vec(),
+    ))
+}
+
+#[derive(Clone)]
+pub(crate) struct TractModel {
+    plan: Arc<TypedSimplePlan<TypedModel>>,
+    inputs: Vec<Shape<'static>>,
+    outputs: Vec<Shape<'static>>,
+    input_names: Vec<String>,
+    output_names: Vec<String>,
+}
+
+impl Model for TractModel {
+    fn infer(
+        &mut self,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+    ) -> Result<(), Error> {
+        if inputs.len() != self.inputs.len() {
+            anyhow::bail!(
+                "The model expects {} inputs, but {} were provided",
+                self.inputs.len(),
+                inputs.len()
+            );
+        }
+
+        let mut tensors = tvec!();
+
+        for (shape, data) in self.inputs.iter().zip(inputs) {
+            let dt = datum_type(shape.element_type())?;
+            // Safety: all the element types we accept are plain old data, and
+            // tract checks that the buffer is the right length.
+            let tensor =
+                unsafe { Tensor::from_raw_dt(dt, shape.dimensions(), data) }
+                    .context("Invalid input tensor")?;
+            tensors.push(tensor);
+        }
+
+        let results = self.plan.run(tensors).context("Inference failed")?;
+
+        if results.len() != outputs.len() {
+            anyhow::bail!(
+                "The Rune has room for {} outputs, but the model produced {}",
+                outputs.len(),
+                results.len()
+            );
+        }
+
+        for (i, (result, buffer)) in results.iter().zip(outputs).enumerate() {
+            if !result.datum_type().is_copy() {
+                anyhow::bail!(
+                    "Output {} has an unsupported element type, {:?}",
+                    i,
+                    result.datum_type()
+                );
+            }
+
+            // Safety: we just checked that the elements are plain old data
+            let bytes = unsafe { result.as_bytes() };
+
+            if bytes.len() != buffer.len() {
+                anyhow::bail!(
+                    "Output {} should be {} bytes long, but the model \
+                     produced {} bytes",
+                    i,
+                    buffer.len(),
+                    bytes.len(),
+                );
+            }
+
+            buffer.copy_from_slice(bytes);
+        }
+
+        Ok(())
+    }
+
+    fn input_shapes(&self) -> &[Shape<'_>] { &self.inputs }
+
+    fn output_shapes(&self) -> &[Shape<'_>] { &self.outputs }
+
+    fn input_descriptors(&self) -> Vec<TensorDescriptor> {
+        named(&self.inputs, &self.input_names)
+    }
+
+    fn output_descriptors(&self) -> Vec<TensorDescriptor> {
+        named(&self.outputs, &self.output_names)
+    }
+}
+
+fn datum_type(element_type: ElementType) -> Result<DatumType, Error> {
+    Ok(match element_type {
+        ElementType::U8 => DatumType::U8,
+        ElementType::I8 => DatumType::I8,
+        ElementType::U16 => DatumType::U16,
+        ElementType::I16 => DatumType::I16,
+        ElementType::U32 => DatumType::U32,
+        ElementType::I32 => DatumType::I32,
+        ElementType::F32 => DatumType::F32,
+        ElementType::U64 => DatumType::U64,
+        ElementType::I64 => DatumType::I64,
+        ElementType::F64 => DatumType::F64,
+        ElementType::F16 => DatumType::F16,
+        ElementType::BF16 => anyhow::bail!("bf16 tensors aren't supported"),
+        ElementType::String => {
+            anyhow::bail!("String tensors aren't supported")
+        },
+    })
+}
+
+fn rune_element_type(datum_type: DatumType) -> Result<ElementType, Error> {
+    Ok(match datum_type {
+        DatumType::U8 => ElementType::U8,
+        DatumType::I8 => ElementType::I8,
+        DatumType::U16 => ElementType::U16,
+        DatumType::I16 => ElementType::I16,
+        DatumType::U32 => ElementType::U32,
+        DatumType::I32 => ElementType::I32,
+        DatumType::F32 => ElementType::F32,
+        DatumType::U64 => ElementType::U64,
+        DatumType::I64 => ElementType::I64,
+        DatumType::F64 => ElementType::F64,
+        DatumType::F16 => ElementType::F16,
+        other => anyhow::bail!("Rune doesn't support {:?} tensors", other),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn element_types_round_trip() {
+        let element_types = [
+            ElementType::U8,
+            ElementType::I8,
+            ElementType::U16,
+            ElementType::I16,
+            ElementType::U32,
+            ElementType::I32,
+            ElementType::F32,
+            ElementType::U64,
+            ElementType::I64,
+            ElementType::F64,
+            ElementType::F16,
+        ];
+
+        for element_type in element_types {
+            let dt = datum_type(element_type).unwrap();
+            assert_eq!(rune_element_type(dt).unwrap(), element_type);
+        }
+
+        assert!(datum_type(ElementType::String).is_err());
+        assert!(datum_type(ElementType::BF16).is_err());
+    }
+}
@@ -0,0 +1,445 @@
+//! Running a pipeline natively, without compiling it to WebAssembly.
+//!
+//! For server-side deployments the WebAssembly sandbox is mostly overhead.
+//! A [`NativePipeline`] executes the same pipeline graph the compiler embeds
+//! in a Rune's `.rune_graph` section, loading models with the normal model
+//! handler and calling ordinary Rust implementations of each proc-block.
+//!
+//! Because proc-blocks are normally compiled into the Rune, the host needs to
+//! register an implementation for every proc-block the pipeline uses with
+//! [`NativePipeline::proc_block()`], keyed by the path from the Runefile
+//! (without the `@version`).
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hotg_rune_runtime::{native::NativePipeline, Runtime, Tensor};
+//!
+//! let graph = std::fs::read("sine.rune_graph.json")?;
+//!
+//! let pipeline = NativePipeline::from_graph(&graph)?
+//!     .model_dir("./models")
+//!     .proc_block("hotg-ai/rune#proc_blocks/modulo", || {
+//!         |inputs: Vec<Tensor>| -> Result<Vec<Tensor>, anyhow::Error> {
+//!             Ok(inputs)
+//!         }
+//!     });
+//!
+//! let mut runtime = Runtime::builder().native(pipeline).build()?;
+//! runtime
+//!     .input_tensors()
+//!     .insert(1, Tensor::new(&[0.5_f32], &[1, 1]));
+//! runtime.predict()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Proc-blocks implementing [`hotg_rune_proc_blocks::Transform`] can be
+//! registered directly with [`NativePipeline::transform()`].
+//!
+//! Nodes are given the same IDs they would have in the compiled Rune, so use
+//! [`crate::Runtime::capabilities()`] to find out which ID to use for an
+//! input.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::{Context, Error};
+use hotg_rune_core::Shape;
+use hotg_rune_proc_blocks::{ProcBlockDescriptor, Transform};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use wasmparser::{Parser, Payload};
+
+use crate::{Tensor, TensorElement};
+
+/// A natively compiled implementation of a proc-block.
+pub trait ProcBlock: Send + 'static {
+    /// Set one of the arguments from the Runefile.
+    ///
+    /// Arguments referring to a resource are passed the resource's contents.
+    fn set_argument(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let _ = value;
+        anyhow::bail!("Unknown argument, \"{}\"", name)
+    }
+
+    /// Transform the proc-block's input tensors into its outputs.
+    fn transform(&mut self, inputs: Vec<Tensor>) -> Result<Vec<Tensor>, Error>;
+
+    /// The proc-block's type (e.g. `"Normalize"`), used to look up its
+    /// [`hotg_rune_proc_blocks::ProcBlockDescriptor`].
+    fn type_name(&self) -> Option<String> { None }
+
+    /// The current value of each parameter, which will be shown in
+    /// [`crate::Runtime::proc_block_nodes()`].
+    fn parameters(&self) -> Vec<(String, String)> { Vec::new() }
+}
+
+impl<F> ProcBlock for F
+where
+    F: FnMut(Vec<Tensor>) -> Result<Vec<Tensor>, Error> + Send + 'static,
+{
+    fn transform(&mut self, inputs: Vec<Tensor>) -> Result<Vec<Tensor>, Error> {
+        self(inputs)
+    }
+}
+
+/// Adapts a proc-block's [`Transform`] implementation to a native
+/// [`ProcBlock`], so the same code can be compiled into a Rune or run
+/// natively.
+///
+/// Arguments are set using
+/// [`hotg_rune_proc_blocks::ProcBlock::set_parameter()`].
+pub struct Transformer<P, Inputs> {
+    proc_block: P,
+    _inputs: PhantomData<fn(Inputs)>,
+}
+
+impl<P, Inputs> Transformer<P, Inputs> {
+    pub fn new(proc_block: P) -> Self {
+        Transformer {
+            proc_block,
+            _inputs: PhantomData,
+        }
+    }
+}
+
+impl<P, Inputs> ProcBlock for Transformer<P, Inputs>
+where
+    P: Transform<Inputs> + Send,
+    P::Output: Tensors,
+    Inputs: Tensors + 'static,
+{
+    fn set_argument(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        self.proc_block
+            .set_parameter(name, value)
+            .map_err(|e| Error::msg(format!("\"{}\": {}", name, e)))
+    }
+
+    fn transform(&mut self, inputs: Vec<Tensor>) -> Result<Vec<Tensor>, Error> {
+        let inputs = Inputs::from_tensors(inputs)?;
+        Ok(self.proc_block.transform(inputs).into_tensors())
+    }
+
+    fn type_name(&self) -> Option<String> {
+        Some(P::DESCRIPTOR.type_name.to_string())
+    }
+
+    fn parameters(&self) -> Vec<(String, String)> {
+        P::DESCRIPTOR
+            .parameters
+            .iter()
+            .filter_map(|p| {
+                let value = self.proc_block.get_parameter(&p.name)?;
+                Some((p.name.to_string(), value))
+            })
+            .collect()
+    }
+}
+
+/// The typed tensors (or tuples of tensors) passed to and returned from a
+/// [`Transform`].
+pub trait Tensors: Sized {
+    fn from_tensors(tensors: Vec<Tensor>) -> Result<Self, Error>;
+    fn into_tensors(self) -> Vec<Tensor>;
+}
+
+impl<T: TensorElement> Tensors for hotg_rune_core::Tensor<T> {
+    fn from_tensors(tensors: Vec<Tensor>) -> Result<Self, Error> {
+        match tensors.as_slice() {
+            [tensor] => typed_tensor(tensor),
+            _ => anyhow::bail!("Expected 1 tensor, found {}", tensors.len()),
+        }
+    }
+
+    fn into_tensors(self) -> Vec<Tensor> {
+        vec![Tensor::new(self.elements(), self.dimensions())]
+    }
+}
+
+macro_rules! tuple_tensors {
+    ($count:literal; $( $ty:ident ),*) => {
+        impl<$( $ty: TensorElement ),*> Tensors for ($( hotg_rune_core::Tensor<$ty>, )*) {
+            #[allow(non_snake_case)]
+            fn from_tensors(tensors: Vec<Tensor>) -> Result<Self, Error> {
+                match tensors.as_slice() {
+                    [$( $ty ),*] => Ok(($( typed_tensor($ty)?, )*)),
+                    _ => anyhow::bail!(
+                        "Expected {} tensors, found {}",
+                        $count,
+                        tensors.len()
+                    ),
+                }
+            }
+
+            #[allow(non_snake_case)]
+            fn into_tensors(self) -> Vec<Tensor> {
+                let ($( $ty, )*) = self;
+                vec![$( Tensor::new($ty.elements(), $ty.dimensions()) ),*]
+            }
+        }
+    };
+}
+
+tuple_tensors!(1; A);
+tuple_tensors!(2; A, B);
+tuple_tensors!(3; A, B, C);
+tuple_tensors!(4; A, B, C, D);
+
+fn typed_tensor<T: TensorElement>(
+    tensor: &Tensor,
+) -> Result<hotg_rune_core::Tensor<T>, Error> {
+    let elements = tensor.elements::<T>().with_context(|| {
+        format!(
+            "Expected a {} tensor, found {}",
+            T::ELEMENT_TYPE,
+            tensor.element_type()
+        )
+    })?;
+    let dimensions = tensor.dimensions().iter().map(|d| d.get()).collect();
+
+    Ok(hotg_rune_core::Tensor::new_row_major(
+        Arc::from(elements),
+        dimensions,
+    ))
+}
+
+type ProcBlockFactory = Box<dyn Fn() -> Box<dyn ProcBlock> + Send + Sync>;
+
+/// A pipeline which can be run by a [`crate::Runtime`] without WebAssembly.
+///
+/// Pass this to [`crate::RuntimeBuilder::native()`] to load it.
+pub struct NativePipeline {
+    pub(crate) graph: Graph,
+    pub(crate) proc_blocks: HashMap<String, ProcBlockFactory>,
+    /// The descriptors for every proc-block registered with
+    /// [`NativePipeline::transform()`].
+    pub(crate) descriptors: Vec<ProcBlockDescriptor<'static>>,
+    pub(crate) model_dir: PathBuf,
+}
+
+impl NativePipeline {
+    /// Parse a pipeline graph, in the same JSON format the compiler embeds in
+    /// a Rune's `.rune_graph` section.
+    pub fn from_graph(json: &[u8]) -> Result<Self, Error> {
+        let graph = serde_json::from_slice(json)
+            .context("Unable to parse the pipeline graph")?;
+
+        Ok(NativePipeline {
+            graph,
+            proc_blocks: HashMap::new(),
+            descriptors: Vec::new(),
+            model_dir: PathBuf::from("."),
+        })
+    }
+
+    /// Read the pipeline graph out of an existing Rune.
+    pub fn from_rune(wasm: &[u8]) -> Result<Self, Error> {
+        for payload in Parser::default().parse_all(wasm) {
+            if let Payload::CustomSection { name, data, .. } =
+                payload.context("Unable to parse the WebAssembly")?
+            {
+                if name == hotg_rune_core::GRAPH_CUSTOM_SECTION {
+                    return NativePipeline::from_graph(data);
+                }
+            }
+        }
+
+        anyhow::bail!("The Rune doesn't contain a pipeline graph")
+    }
+
+    /// The directory model paths are relative to, usually the one containing
+    /// the Runefile (defaults to the current directory).
+    pub fn model_dir(self, dir: impl Into<PathBuf>) -> Self {
+        NativePipeline {
+            model_dir: dir.into(),
+            ..self
+        }
+    }
+
+    /// Register the implementation used for a proc-block.
+    ///
+    /// The `factory` is called once for each stage using this proc-block.
+    pub fn proc_block<F, P>(mut self, path: &str, factory: F) -> Self
+    where
+        F: Fn() -> P + Send + Sync + 'static,
+        P: ProcBlock,
+    {
+        self.proc_blocks.insert(
+            path.to_string(),
+            Box::new(move || Box::new(factory()) as Box<dyn ProcBlock>),
+        );
+        self
+    }
+
+    /// Register a proc-block which implements [`Transform`], creating each
+    /// instance with [`Default`].
+    pub fn transform<P, Inputs>(mut self, path: &str) -> Self
+    where
+        P: Transform<Inputs> + Send,
+        P::Output: Tensors,
+        Inputs: Tensors + 'static,
+    {
+        self.descriptors.push(P::DESCRIPTOR);
+        self.proc_block(path, || Transformer::<P, Inputs>::new(P::default()))
+    }
+
+    /// Use a proc-block compiled to a standalone WebAssembly module (see
+    /// [`crate::wasm_proc_block`]).
+    #[cfg(feature = "wasmer")]
+    #[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "wasmer")))]
+    pub fn wasm_proc_block(
+        self,
+        path: &str,
+        wasm: &[u8],
+    ) -> Result<Self, Error> {
+        let module = crate::wasm_proc_block::ProcBlockModule::load(wasm)
+            .with_context(|| format!("Unable to load \"{}\"", path))?;
+
+        Ok(self.proc_block(path, move || module.instantiate()))
+    }
+
+    /// The proc-blocks used by this pipeline, keyed by the paths passed to
+    /// [`NativePipeline::proc_block()`].
+    pub fn required_proc_blocks(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .graph
+            .proc_blocks
+            .values()
+            .map(|p| p.registry_key())
+            .collect();
+        paths.sort_unstable();
+        paths.dedup();
+        paths
+    }
+}
+
+/// The subset of the compiler's `RuneGraph` needed to run a pipeline.
+///
+/// Nodes are kept in the order the compiled Rune initializes them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Graph {
+    #[serde(default)]
+    pub capabilities: IndexMap<String, CapabilityNode>,
+    #[serde(default)]
+    pub models: IndexMap<String, ModelNode>,
+    #[serde(default)]
+    pub proc_blocks: IndexMap<String, ProcBlockNode>,
+    #[serde(default)]
+    pub outputs: IndexMap<String, OutputNode>,
+    #[serde(default)]
+    pub tensors: HashMap<String, Shape<'static>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct Kind {
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct CapabilityNode {
+    pub kind: Kind,
+    #[serde(default)]
+    pub args: BTreeMap<String, serde_json::Value>,
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct ModelNode {
+    pub file: String,
+    #[serde(default)]
+    pub mimetype: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    #[serde(default)]
+    pub enabled_when: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct ProcBlockNode {
+    pub path: String,
+    #[serde(default)]
+    pub args: BTreeMap<String, serde_json::Value>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    #[serde(default)]
+    pub enabled_when: Option<String>,
+}
+
+impl ProcBlockNode {
+    /// The proc-block's path with any `@version` removed (e.g.
+    /// `"hotg-ai/rune@v0.11#proc_blocks/modulo"` becomes
+    /// `"hotg-ai/rune#proc_blocks/modulo"`).
+    pub(crate) fn registry_key(&self) -> String {
+        match self.path.split_once('@') {
+            Some((base, rest)) => match rest.split_once('#') {
+                Some((_version, sub_path)) => format!("{}#{}", base, sub_path),
+                None => base.to_string(),
+            },
+            None => self.path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct OutputNode {
+    pub kind: Kind,
+    #[serde(default)]
+    pub args: BTreeMap<String, serde_json::Value>,
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub enabled_when: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_a_compiled_pipeline_graph() {
+        let json = serde_json::json!({
+            "rune": { "name": "sine" },
+            "capabilities": {
+                "rand": {
+                    "kind": { "type": "raw" },
+                    "args": {},
+                    "outputs": ["0"],
+                },
+            },
+            "proc-blocks": {
+                "mod360": {
+                    "path": "hotg-ai/rune@v0.11.3#proc_blocks/modulo",
+                    "args": { "modulo": "360" },
+                    "inputs": ["0"],
+                    "outputs": ["1"],
+                },
+            },
+            "outputs": {
+                "serial": {
+                    "kind": { "type": "serial" },
+                    "args": {},
+                    "inputs": ["1"],
+                },
+            },
+            "tensors": {
+                "0": { "element_type": "F32", "dimensions": [1, 1] },
+                "1": { "element_type": "F32", "dimensions": [1, 1] },
+            },
+        });
+
+        let pipeline =
+            NativePipeline::from_graph(json.to_string().as_bytes()).unwrap();
+
+        assert_eq!(
+            pipeline.required_proc_blocks(),
+            ["hotg-ai/rune#proc_blocks/modulo"]
+        );
+        assert_eq!(pipeline.graph.capabilities["rand"].kind.ty, "raw");
+        assert_eq!(pipeline.graph.tensors.len(), 2);
+    }
+}
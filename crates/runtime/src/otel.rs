@@ -0,0 +1,206 @@
+//! Exporting trace spans to [OpenTelemetry](https://opentelemetry.io/).
+//!
+//! When a [`opentelemetry::global::BoxedTracer`] is passed to
+//! [`crate::RuntimeBuilder::opentelemetry()`], every call to
+//! [`crate::Runtime::predict()`] creates a `rune.predict` span, with child
+//! spans for each capability that was read, model that was run, and output
+//! that was written.
+//!
+//! The runtime doesn't choose an exporter itself. Instead, the host
+//! application sets up whichever exporter it wants (e.g. OTLP using the
+//! `opentelemetry-otlp` crate) and installs it as the global tracer provider.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hotg_rune_runtime::Runtime;
+//!
+//! // Install an exporter with opentelemetry::global::set_tracer_provider()
+//! let tracer = opentelemetry::global::tracer("my-app");
+//!
+//! let runtime = Runtime::builder()
+//!     .rune(std::fs::read("sine.rune")?)
+//!     .opentelemetry(tracer)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Spans have the following attributes:
+//!
+//! - `rune.name` - the Rune's name from its Runefile
+//! - `rune.compiler_version` - the version of `rune` used to compile it
+//! - `rune.node.id` - the ID the Rune uses for a capability, model, or output
+//! - `rune.node.kind` - the node's kind (e.g. `RAW`), or a model's mimetype
+//! - `rune.node.name` - the node's name in the Runefile, for Runes which report
+//!   it
+//! - `rune.tensor.shapes` - the shapes of the tensors the node produced
+
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Error;
+use hotg_rune_core::{GRAPH_CUSTOM_SECTION, VERSION_CUSTOM_SECTION};
+use opentelemetry::{
+    global::BoxedTracer,
+    trace::{Span, StatusCode, TraceContextExt, Tracer},
+    Array, Context, KeyValue, Value,
+};
+use wasmparser::{Parser, Payload};
+
+use crate::{instrumentation::Node, profiling::NodeKind};
+
+/// Creates a span for each prediction and the nodes it touched.
+#[derive(Clone)]
+pub(crate) struct Tracing {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    tracer: BoxedTracer,
+    /// Attributes attached to every span.
+    rune: Vec<KeyValue>,
+    /// The `rune.predict` span currently in progress.
+    current: Mutex<Option<Context>>,
+}
+
+impl Tracing {
+    pub(crate) fn new(tracer: BoxedTracer, wasm: &[u8]) -> Self {
+        Tracing {
+            inner: Arc::new(Inner {
+                tracer,
+                rune: rune_attributes(wasm),
+                current: Mutex::new(None),
+            }),
+        }
+    }
+
+    pub(crate) fn start_predict(&self) {
+        let mut span = self.inner.tracer.start("rune.predict");
+        for attribute in &self.inner.rune {
+            span.set_attribute(attribute.clone());
+        }
+
+        *self.current() = Some(Context::current_with_span(span));
+    }
+
+    pub(crate) fn finish_predict(&self, error: Option<&Error>) {
+        if let Some(cx) = self.current().take() {
+            let span = cx.span();
+            if let Some(e) = error {
+                span.set_status(StatusCode::Error, format!("{:#}", e));
+            }
+            span.end();
+        }
+    }
+
+    /// Record a child span for something the Rune did during the current
+    /// prediction.
+    pub(crate) fn record<T>(
+        &self,
+        kind: NodeKind,
+        node: &Node<'_>,
+        run: impl FnOnce() -> Result<T, Error>,
+        shapes: impl FnOnce(&T) -> Vec<String>,
+    ) -> Result<T, Error> {
+        let name = match kind {
+            NodeKind::Capability => "rune.capability",
+            NodeKind::Model => "rune.model",
+            NodeKind::Output => "rune.output",
+        };
+        let cx = self.current().clone().unwrap_or_else(Context::current);
+        let mut span = self.inner.tracer.start_with_context(name, &cx);
+        for attribute in &self.inner.rune {
+            span.set_attribute(attribute.clone());
+        }
+        span.set_attribute(KeyValue::new("rune.node.id", i64::from(node.id)));
+        span.set_attribute(KeyValue::new(
+            "rune.node.kind",
+            node.kind.to_string(),
+        ));
+        if let Some(name) = node.name {
+            span.set_attribute(KeyValue::new(
+                "rune.node.name",
+                name.to_string(),
+            ));
+        }
+
+        let result = run();
+
+        match &result {
+            Ok(value) => {
+                let shapes: Vec<Cow<'static, str>> =
+                    shapes(value).into_iter().map(Cow::from).collect();
+                span.set_attribute(KeyValue::new(
+                    "rune.tensor.shapes",
+                    Value::Array(Array::String(shapes)),
+                ));
+            },
+            Err(e) => span.set_status(StatusCode::Error, format!("{:#}", e)),
+        }
+        span.end();
+
+        result
+    }
+
+    fn current(&self) -> std::sync::MutexGuard<'_, Option<Context>> {
+        self.inner.current.lock().expect("Lock was poisoned")
+    }
+}
+
+/// Read the Rune's name and compiler version from the custom sections the
+/// compiler embeds in it.
+fn rune_attributes(wasm: &[u8]) -> Vec<KeyValue> {
+    let mut attributes = Vec::new();
+
+    for payload in Parser::default().parse_all(wasm) {
+        let (name, data) = match payload {
+            Ok(Payload::CustomSection { name, data, .. }) => (name, data),
+            _ => continue,
+        };
+        let value: serde_json::Value = match serde_json::from_slice(data) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        let attribute = match name {
+            GRAPH_CUSTOM_SECTION => value["rune"]["name"]
+                .as_str()
+                .map(|n| KeyValue::new("rune.name", n.to_string())),
+            VERSION_CUSTOM_SECTION => value["version"]
+                .as_str()
+                .map(|v| KeyValue::new("rune.compiler_version", v.to_string())),
+            _ => None,
+        };
+        attributes.extend(attribute);
+    }
+
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_the_rune_name_and_version() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (@custom ".rune_graph" "{\"rune\":{\"name\":\"sine\"}}")
+                (@custom ".rune_version" "{\"version\":\"0.11.3\"}")
+            )"#,
+        )
+        .unwrap();
+
+        let attributes = rune_attributes(&wasm);
+
+        assert_eq!(
+            attributes,
+            vec![
+                KeyValue::new("rune.name", "sine"),
+                KeyValue::new("rune.compiler_version", "0.11.3"),
+            ]
+        );
+    }
+}
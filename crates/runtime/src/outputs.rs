@@ -1,10 +1,18 @@
+use std::num::NonZeroUsize;
+
 use anyhow::{Context, Error};
+use hotg_rune_core::{
+    framing::{self, FrameDecoder, Framing},
+    serial_format::{ProtobufMessage, ProtobufTensor, SerialFormat},
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{Map, Value};
 
-use crate::{NodeMetadata, Tensor, TensorElement};
+use crate::{
+    log_filter::trace, ElementType, NodeMetadata, Tensor, TensorElement,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum OutputTensor {
     Tensor(Tensor),
     StringTensor {
@@ -13,29 +21,226 @@ pub enum OutputTensor {
     },
 }
 
+/// A tensor sent to a SERIAL output, tagged with the stage output it came
+/// from.
+///
+/// The `source` is only known when a SERIAL output has multiple inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaggedTensor {
+    pub source: Option<String>,
+    pub tensor: OutputTensor,
+}
+
 pub(crate) fn parse_serial(data: &[u8]) -> Result<Vec<OutputTensor>, Error> {
-    if let Ok(s) = std::str::from_utf8(data) {
-        log::trace!("Parsing serial output: {}", s);
+    let tagged = parse_tagged_serial(data)?;
+    Ok(tagged.into_iter().map(|t| t.tensor).collect())
+}
+
+pub(crate) fn parse_tagged_serial(
+    data: &[u8],
+) -> Result<Vec<TaggedTensor>, Error> {
+    // Note: JSON never contains a null byte, so seeing a frame delimiter means
+    // the SERIAL output was configured to use framing.
+    if data.contains(&framing::FRAME_DELIMITER) {
+        return parse_framed_serial(data, SerialFormat::Json);
+    }
+
+    parse_message(data, SerialFormat::Json)
+}
+
+/// Parse SERIAL output which uses a particular [`SerialFormat`] and
+/// [`Framing`], as reported by the output's `format` and `framing`
+/// arguments.
+pub(crate) fn parse_formatted_serial(
+    data: &[u8],
+    format: SerialFormat,
+    framing: Framing,
+) -> Result<Vec<TaggedTensor>, Error> {
+    match framing {
+        Framing::None => parse_message(data, format),
+        Framing::Cobs => parse_framed_serial(data, format),
     }
+}
+
+fn parse_message(
+    data: &[u8],
+    format: SerialFormat,
+) -> Result<Vec<TaggedTensor>, Error> {
+    match format {
+        SerialFormat::Json => {
+            if let Ok(s) = std::str::from_utf8(data) {
+                trace!("Parsing serial output: {}", s);
+            }
 
-    let deserialized: OneOrMany = serde_json::from_slice(data)
-        .context("Deserializing from JSON failed")?;
+            let deserialized = serde_json::from_slice(data)
+                .context("Deserializing from JSON failed")?;
+            deserialize_message(deserialized)
+        },
+        SerialFormat::Cbor => {
+            let deserialized = ciborium::de::from_reader(data)
+                .context("Deserializing from CBOR failed")?;
+            deserialize_message(deserialized)
+        },
+        SerialFormat::Protobuf => {
+            let msg = ProtobufMessage::decode(data)
+                .context("Deserializing from protobuf failed")?;
+            msg.tensors.into_iter().map(protobuf_tensor).collect()
+        },
+    }
+}
 
+fn deserialize_message(
+    deserialized: OneOrMany,
+) -> Result<Vec<TaggedTensor>, Error> {
     let values = match deserialized {
         OneOrMany::Many(many) => many,
+        OneOrMany::Tagged { tensors } => tensors,
         OneOrMany::One(one) => vec![one],
     };
 
     let mut outputs = Vec::new();
 
     for value in values {
-        let deserialized = deserialize_serial_tensor(value)?;
-        outputs.push(deserialized);
+        let source = value
+            .get("source")
+            .and_then(|s| s.as_str())
+            .map(String::from);
+        let tensor = deserialize_serial_tensor(value)?;
+        outputs.push(TaggedTensor { source, tensor });
+    }
+
+    Ok(outputs)
+}
+
+fn parse_framed_serial(
+    data: &[u8],
+    format: SerialFormat,
+) -> Result<Vec<TaggedTensor>, Error> {
+    let mut decoder = SerialDecoder::with_format(format);
+    let mut outputs = Vec::new();
+
+    for frame in decoder.push_tagged(data) {
+        outputs.extend(frame?);
+    }
+
+    if decoder.frames.pending() > 0 {
+        anyhow::bail!("The SERIAL output ended part way through a frame");
     }
 
     Ok(outputs)
 }
 
+/// A decoder for SERIAL output which was framed using COBS and a CRC-16
+/// checksum (i.e. `framing: cobs` in the Runefile) before being sent over a
+/// byte stream like a UART.
+///
+/// Corrupted frames are reported as errors and skipped, with the decoder
+/// resynchronizing at the start of the next frame.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SerialDecoder {
+    frames: FrameDecoder,
+    format: SerialFormat,
+}
+
+impl SerialDecoder {
+    pub fn new() -> Self { SerialDecoder::default() }
+
+    /// Create a decoder for frames containing messages encoded with a
+    /// particular [`SerialFormat`].
+    pub fn with_format(format: SerialFormat) -> Self {
+        SerialDecoder {
+            format,
+            ..SerialDecoder::default()
+        }
+    }
+
+    /// Feed some bytes to the decoder, returning the outputs from every
+    /// frame that was completed.
+    pub fn push(
+        &mut self,
+        bytes: &[u8],
+    ) -> Vec<Result<Vec<OutputTensor>, Error>> {
+        self.push_tagged(bytes)
+            .into_iter()
+            .map(|frame| {
+                frame.map(|tensors| {
+                    tensors.into_iter().map(|t| t.tensor).collect()
+                })
+            })
+            .collect()
+    }
+
+    /// Like [`SerialDecoder::push()`], but also reporting which stage output
+    /// each tensor came from.
+    pub fn push_tagged(
+        &mut self,
+        bytes: &[u8],
+    ) -> Vec<Result<Vec<TaggedTensor>, Error>> {
+        let format = self.format;
+
+        self.frames
+            .decode(bytes)
+            .map(|frame| {
+                let frame = frame.context("Received a corrupted frame")?;
+                parse_message(&frame, format)
+            })
+            .collect()
+    }
+}
+
+fn protobuf_tensor(tensor: ProtobufTensor) -> Result<TaggedTensor, Error> {
+    let ProtobufTensor {
+        type_name,
+        dimensions,
+        elements,
+        strings,
+        source,
+    } = tensor;
+    let dimensions: Vec<usize> =
+        dimensions.into_iter().map(|d| d as usize).collect();
+
+    let tensor = match type_name.as_str() {
+        "utf8" => {
+            let expected: usize = dimensions.iter().product();
+            anyhow::ensure!(
+                !dimensions.contains(&0) && expected == strings.len(),
+                "A utf8{:?} tensor can't contain {} strings",
+                dimensions,
+                strings.len()
+            );
+            OutputTensor::StringTensor {
+                dimensions,
+                strings,
+            }
+        },
+        other => {
+            let element_type: ElementType =
+                serde_json::from_value(Value::String(type_name.clone()))
+                    .with_context(|| {
+                        format!("Unknown element type, {}", other)
+                    })?;
+            let num_elements: usize = dimensions.iter().product();
+            anyhow::ensure!(
+                !dimensions.contains(&0)
+                    && num_elements * element_type.byte_size()
+                        == elements.len(),
+                "{} bytes isn't a valid {}{:?} tensor",
+                elements.len(),
+                other,
+                dimensions
+            );
+
+            let dimensions = dimensions
+                .iter()
+                .filter_map(|&d| NonZeroUsize::new(d))
+                .collect();
+            Tensor::new_raw(element_type, dimensions, elements).into()
+        },
+    };
+
+    Ok(TaggedTensor { source, tensor })
+}
+
 fn deserialize_serial_tensor(
     value: Map<String, Value>,
 ) -> Result<OutputTensor, Error> {
@@ -71,6 +276,14 @@ fn deserialize_strings(
         elements,
     } = serde_json::from_value(value)?;
 
+    let expected: usize = dimensions.iter().product();
+    anyhow::ensure!(
+        !dimensions.contains(&0) && expected == elements.len(),
+        "A utf8{:?} tensor can't contain {} strings",
+        dimensions,
+        elements.len()
+    );
+
     Ok(OutputTensor::StringTensor {
         dimensions,
         strings: elements,
@@ -103,6 +316,11 @@ where
 #[serde(untagged)]
 enum OneOrMany {
     Many(Vec<Map<String, Value>>),
+    /// The message from a multi-input SERIAL output where each tensor is
+    /// tagged with its source.
+    Tagged {
+        tensors: Vec<Map<String, Value>>,
+    },
     One(Map<String, Value>),
 }
 
@@ -134,7 +352,18 @@ impl Serialize for OutputTensor {
 }
 
 impl From<Tensor> for OutputTensor {
-    fn from(t: Tensor) -> OutputTensor { OutputTensor::Tensor(t) }
+    /// Wrap a [`Tensor`], converting `utf8` tensors to
+    /// [`OutputTensor::StringTensor`] so strings are always represented the
+    /// same way.
+    fn from(t: Tensor) -> OutputTensor {
+        match t.strings() {
+            Some(strings) => OutputTensor::StringTensor {
+                dimensions: t.dimensions().iter().map(|d| d.get()).collect(),
+                strings: strings.into_iter().map(String::from).collect(),
+            },
+            None => OutputTensor::Tensor(t),
+        }
+    }
 }
 
 pub(crate) fn parse_outputs(
@@ -142,7 +371,253 @@ pub(crate) fn parse_outputs(
     data: &[u8],
 ) -> Result<Vec<OutputTensor>, Error> {
     match meta.kind.as_str() {
-        "SERIAL" => crate::outputs::parse_serial(data),
+        "SERIAL" => match meta.arguments.get("format") {
+            Some(format) => {
+                let format =
+                    SerialFormat::from_name(format).with_context(|| {
+                        format!("Unknown SERIAL format, \"{}\"", format)
+                    })?;
+                let framing = match meta.arguments.get("framing") {
+                    Some(name) => {
+                        Framing::from_name(name).with_context(|| {
+                            format!("Unknown framing, \"{}\"", name)
+                        })?
+                    },
+                    None => Framing::None,
+                };
+                let tagged = parse_formatted_serial(data, format, framing)?;
+                Ok(tagged.into_iter().map(|t| t.tensor).collect())
+            },
+            None => crate::outputs::parse_serial(data),
+        },
         _ => anyhow::bail!("Unknown output type"),
     }
 }
+
+/// Describe the shape of each tensor written to an output.
+pub(crate) fn output_shapes(
+    tensors: Option<&Vec<OutputTensor>>,
+) -> Vec<String> {
+    tensors
+        .into_iter()
+        .flatten()
+        .map(|tensor| match tensor {
+            OutputTensor::Tensor(t) => t.shape().to_string(),
+            OutputTensor::StringTensor { dimensions, .. } => {
+                format!("utf8{:?}", dimensions)
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tagged_tensors_with_mixed_element_types() {
+        let msg = serde_json::json!({
+            "channel": 2,
+            "tensors": [
+                {
+                    "type_name": "f32",
+                    "channel": 2,
+                    "elements": [0.5, 1.5],
+                    "dimensions": [1, 2],
+                    "source": "fft",
+                },
+                {
+                    "type_name": "i16",
+                    "channel": 2,
+                    "elements": [-1, 2, 3],
+                    "dimensions": [3],
+                    "source": "noise.1",
+                },
+                {
+                    "type_name": "utf8",
+                    "channel": 2,
+                    "elements": ["up"],
+                    "dimensions": [1],
+                    "source": "label",
+                },
+            ],
+        });
+        let data = serde_json::to_vec(&msg).unwrap();
+
+        let got = parse_tagged_serial(&data).unwrap();
+
+        let should_be = vec![
+            TaggedTensor {
+                source: Some("fft".to_string()),
+                tensor: Tensor::new(&[0.5_f32, 1.5], &[1, 2]).into(),
+            },
+            TaggedTensor {
+                source: Some("noise.1".to_string()),
+                tensor: Tensor::new(&[-1_i16, 2, 3], &[3]).into(),
+            },
+            TaggedTensor {
+                source: Some("label".to_string()),
+                tensor: OutputTensor::StringTensor {
+                    dimensions: vec![1],
+                    strings: vec!["up".to_string()],
+                },
+            },
+        ];
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn untagged_arrays_are_still_supported() {
+        let msg = serde_json::json!([
+            {
+                "type_name": "u8",
+                "channel": 1,
+                "elements": [1, 2],
+                "dimensions": [2],
+            },
+            {
+                "type_name": "f64",
+                "channel": 1,
+                "elements": [0.25],
+                "dimensions": [1],
+            },
+        ]);
+        let data = serde_json::to_vec(&msg).unwrap();
+
+        let got = parse_serial(&data).unwrap();
+
+        assert_eq!(
+            got,
+            vec![
+                Tensor::new(&[1_u8, 2], &[2]).into(),
+                Tensor::new(&[0.25_f64], &[1]).into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_a_framed_tagged_message() {
+        let msg = serde_json::json!({
+            "channel": 1,
+            "tensors": [
+                {
+                    "type_name": "u8",
+                    "elements": [7],
+                    "dimensions": [1],
+                    "source": "a",
+                },
+                {
+                    "type_name": "f32",
+                    "elements": [1.0],
+                    "dimensions": [1],
+                    "source": "b",
+                },
+            ],
+        });
+        let mut stream = Vec::new();
+        framing::encode_frame(&serde_json::to_vec(&msg).unwrap(), &mut stream);
+
+        let mut decoder = SerialDecoder::new();
+        let mut got = decoder.push_tagged(&stream);
+
+        assert_eq!(got.len(), 1);
+        let tensors = got.remove(0).unwrap();
+        let sources: Vec<_> =
+            tensors.iter().map(|t| t.source.as_deref()).collect();
+        assert_eq!(sources, vec![Some("a"), Some("b")]);
+    }
+
+    #[test]
+    fn decode_framed_cbor() {
+        let msg = serde_json::json!({
+            "type_name": "i32",
+            "channel": 1,
+            "elements": [-1, 0, 70000],
+            "dimensions": [3],
+        });
+        let mut cbor = Vec::new();
+        ciborium::ser::into_writer(&msg, &mut cbor).unwrap();
+        let mut stream = Vec::new();
+        framing::encode_frame(&cbor, &mut stream);
+
+        let got =
+            parse_formatted_serial(&stream, SerialFormat::Cbor, Framing::Cobs)
+                .unwrap();
+
+        assert_eq!(
+            got,
+            vec![TaggedTensor {
+                source: None,
+                tensor: Tensor::new(&[-1_i32, 0, 70000], &[3]).into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn outputs_report_their_format() {
+        let msg = ProtobufMessage {
+            channel: 1,
+            tensors: vec![
+                ProtobufTensor {
+                    type_name: "u16".to_string(),
+                    dimensions: vec![2],
+                    elements: vec![1, 0, 0, 1],
+                    ..Default::default()
+                },
+                ProtobufTensor {
+                    type_name: "utf8".to_string(),
+                    dimensions: vec![1],
+                    strings: vec!["up".to_string()],
+                    ..Default::default()
+                },
+            ],
+        };
+        let mut data = Vec::new();
+        msg.encode(&mut data);
+        let mut meta = NodeMetadata {
+            kind: "SERIAL".to_string(),
+            arguments: Default::default(),
+        };
+        meta.arguments
+            .insert("format".to_string(), "protobuf".to_string());
+
+        let got = parse_outputs(&meta, &data).unwrap();
+
+        assert_eq!(
+            got,
+            vec![
+                Tensor::new(&[1_u16, 256], &[2]).into(),
+                OutputTensor::StringTensor {
+                    dimensions: vec![1],
+                    strings: vec!["up".to_string()],
+                },
+            ]
+        );
+
+        meta.arguments
+            .insert("format".to_string(), "xml".to_string());
+        assert!(parse_outputs(&meta, &data).is_err());
+    }
+
+    #[test]
+    fn string_tensors_must_match_their_dimensions() {
+        let msg = serde_json::json!({
+            "type_name": "utf8",
+            "channel": 1,
+            "elements": ["up", "down"],
+            "dimensions": [1, 3],
+        });
+        let data = serde_json::to_vec(&msg).unwrap();
+
+        assert!(parse_serial(&data).is_err());
+
+        let labels = Tensor::from_strings(&["up", "down"], &[1, 2]);
+        assert_eq!(
+            OutputTensor::from(labels),
+            OutputTensor::StringTensor {
+                dimensions: vec![1, 2],
+                strings: vec!["up".to_string(), "down".to_string()],
+            }
+        );
+    }
+}
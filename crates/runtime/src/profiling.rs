@@ -0,0 +1,340 @@
+//! Finding out which parts of a Rune are slow.
+//!
+//! A [`Profiler`] passed to [`crate::Runtime::set_profiler()`] is told how
+//! long every capability read, model inference, and output write took. The
+//! [`Profile`] profiler adds these up so you can see where the time goes
+//! on-device.
+//!
+//! Proc-blocks are compiled into the Rune itself, so the runtime can't time
+//! them individually. Instead, [`Profile::rune_time()`] reports the time
+//! spent inside the Rune that wasn't accounted for by any other node.
+//!
+//! If you only care about the most recent prediction, the runtime always
+//! keeps its timings around and they can be read with
+//! [`crate::Runtime::last_run_profile()`] without setting a profiler.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::sync::{Arc, Mutex};
+//!
+//! use hotg_rune_runtime::{profiling::Profile, Runtime};
+//!
+//! let mut runtime = Runtime::builder()
+//!     .rune(std::fs::read("sine.rune")?)
+//!     .build()?;
+//!
+//! let profile = Arc::new(Mutex::new(Profile::default()));
+//! runtime.set_profiler(Arc::clone(&profile));
+//!
+//! for _ in 0..100 {
+//!     runtime.predict()?;
+//! }
+//!
+//! println!("{}", profile.lock().unwrap());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display, Formatter},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::delivery::DeliveryStats;
+
+/// The kinds of node the runtime can time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NodeKind {
+    Capability,
+    Model,
+    Output,
+}
+
+impl Display for NodeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeKind::Capability => write!(f, "capability"),
+            NodeKind::Model => write!(f, "model"),
+            NodeKind::Output => write!(f, "output"),
+        }
+    }
+}
+
+/// Something which is told how long each part of a prediction took.
+pub trait Profiler: Send + 'static {
+    /// A node finished executing.
+    fn record_node(&mut self, kind: NodeKind, id: u32, elapsed: Duration);
+
+    /// A call to [`crate::Runtime::predict()`] finished, successfully or
+    /// not.
+    fn record_predict(&mut self, _elapsed: Duration) {}
+}
+
+impl<P: Profiler> Profiler for Arc<Mutex<P>> {
+    fn record_node(&mut self, kind: NodeKind, id: u32, elapsed: Duration) {
+        self.lock()
+            .expect("Lock was poisoned")
+            .record_node(kind, id, elapsed);
+    }
+
+    fn record_predict(&mut self, elapsed: Duration) {
+        self.lock()
+            .expect("Lock was poisoned")
+            .record_predict(elapsed);
+    }
+}
+
+/// A [`Profiler`] which keeps running totals for each node.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Profile {
+    /// The number of predictions that were run.
+    pub predictions: u64,
+    /// The total time spent in [`crate::Runtime::predict()`].
+    pub total: Duration,
+    nodes: BTreeMap<(NodeKind, u32), NodeTiming>,
+    deliveries: BTreeMap<u32, DeliveryStats>,
+}
+
+impl Profile {
+    /// Get the timings for a particular node, if it has been executed.
+    pub fn node(&self, kind: NodeKind, id: u32) -> Option<&NodeTiming> {
+        self.nodes.get(&(kind, id))
+    }
+
+    /// Timings for every node that has been executed, ordered by kind and
+    /// ID.
+    pub fn nodes(&self) -> impl Iterator<Item = &NodeTiming> + '_ {
+        self.nodes.values()
+    }
+
+    /// Time spent running the Rune's own code (i.e. proc-blocks) rather
+    /// than a capability, model, or output.
+    pub fn rune_time(&self) -> Duration {
+        let nodes: Duration = self.nodes.values().map(|n| n.total).sum();
+        self.total.saturating_sub(nodes)
+    }
+
+    /// How outputs have been delivered to an output's queue since it was
+    /// created.
+    ///
+    /// This is only filled in by [`crate::Runtime::last_run_profile()`].
+    pub fn delivery_stats(&self, output_id: u32) -> Option<&DeliveryStats> {
+        self.deliveries.get(&output_id)
+    }
+
+    pub(crate) fn set_delivery_stats(
+        &mut self,
+        stats: HashMap<u32, DeliveryStats>,
+    ) {
+        self.deliveries = stats.into_iter().collect();
+    }
+
+    /// Forget everything that has been recorded so far.
+    pub fn reset(&mut self) { *self = Profile::default(); }
+}
+
+impl Profiler for Profile {
+    fn record_node(&mut self, kind: NodeKind, id: u32, elapsed: Duration) {
+        let timing = self.nodes.entry((kind, id)).or_insert(NodeTiming {
+            kind,
+            id,
+            invocations: 0,
+            total: Duration::default(),
+            max: Duration::default(),
+        });
+
+        timing.invocations += 1;
+        timing.total += elapsed;
+        timing.max = timing.max.max(elapsed);
+    }
+
+    fn record_predict(&mut self, elapsed: Duration) {
+        self.predictions += 1;
+        self.total += elapsed;
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Ran {} predictions in {:.1?}",
+            self.predictions, self.total
+        )?;
+
+        for node in self.nodes.values() {
+            writeln!(
+                f,
+                "{} {}: {} calls, {:?} total, {:?} mean, {:?} max",
+                node.kind,
+                node.id,
+                node.invocations,
+                node.total,
+                node.mean(),
+                node.max
+            )?;
+        }
+
+        writeln!(f, "rune: {:?} total", self.rune_time())?;
+
+        for (id, stats) in &self.deliveries {
+            writeln!(
+                f,
+                "queue {}: {} enqueued, {} delivered, {} dropped",
+                id, stats.enqueued, stats.delivered, stats.dropped
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How long a single node took to execute.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NodeTiming {
+    pub kind: NodeKind,
+    /// The ID the Rune uses for this node.
+    pub id: u32,
+    /// How many times the node was executed.
+    pub invocations: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl NodeTiming {
+    pub fn mean(&self) -> Duration {
+        if self.invocations == 0 {
+            Duration::default()
+        } else {
+            self.total.div_f64(self.invocations as f64)
+        }
+    }
+}
+
+/// The [`Profiler`] currently attached to a [`crate::Runtime`], shared with
+/// the models it has loaded.
+#[derive(Clone, Default)]
+pub(crate) struct ProfilerSlot(Arc<Mutex<SlotState>>);
+
+#[derive(Default)]
+struct SlotState {
+    profiler: Option<Box<dyn Profiler>>,
+    /// Timings for the prediction that is currently running.
+    current: Profile,
+    /// Timings for the most recent prediction to finish.
+    last_run: Profile,
+}
+
+impl Profiler for SlotState {
+    fn record_node(&mut self, kind: NodeKind, id: u32, elapsed: Duration) {
+        self.current.record_node(kind, id, elapsed);
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record_node(kind, id, elapsed);
+        }
+    }
+
+    fn record_predict(&mut self, elapsed: Duration) {
+        self.current.record_predict(elapsed);
+        self.last_run = std::mem::take(&mut self.current);
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record_predict(elapsed);
+        }
+    }
+}
+
+impl ProfilerSlot {
+    pub(crate) fn set(&self, profiler: Option<Box<dyn Profiler>>) {
+        self.lock().profiler = profiler;
+    }
+
+    /// Timings for the most recent call to [`crate::Runtime::predict()`].
+    pub(crate) fn last_run(&self) -> Profile { self.lock().last_run.clone() }
+
+    /// Run a node, recording how long it took.
+    pub(crate) fn time<T>(
+        &self,
+        kind: NodeKind,
+        id: u32,
+        run: impl FnOnce() -> T,
+    ) -> T {
+        let started = Instant::now();
+        let result = run();
+        self.lock().record_node(kind, id, started.elapsed());
+
+        result
+    }
+
+    pub(crate) fn record_predict(&self, elapsed: Duration) {
+        self.lock().record_predict(elapsed);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SlotState> {
+        self.0.lock().expect("Lock was poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration { Duration::from_millis(millis) }
+
+    #[test]
+    fn accumulate_timings_for_each_node() {
+        let mut profile = Profile::default();
+
+        profile.record_node(NodeKind::Capability, 1, ms(2));
+        profile.record_node(NodeKind::Model, 2, ms(10));
+        profile.record_predict(ms(15));
+        profile.record_node(NodeKind::Capability, 1, ms(4));
+        profile.record_node(NodeKind::Model, 2, ms(20));
+        profile.record_predict(ms(30));
+
+        assert_eq!(profile.predictions, 2);
+        assert_eq!(
+            profile.node(NodeKind::Capability, 1),
+            Some(&NodeTiming {
+                kind: NodeKind::Capability,
+                id: 1,
+                invocations: 2,
+                total: ms(6),
+                max: ms(4),
+            })
+        );
+        assert_eq!(profile.node(NodeKind::Model, 2).unwrap().mean(), ms(15));
+        assert_eq!(profile.rune_time(), ms(9));
+    }
+
+    #[test]
+    fn nothing_is_recorded_without_a_profiler() {
+        let slot = ProfilerSlot::default();
+        let profile = Arc::new(Mutex::new(Profile::default()));
+
+        assert_eq!(slot.time(NodeKind::Output, 3, || 42), 42);
+        slot.set(Some(Box::new(Arc::clone(&profile))));
+        slot.time(NodeKind::Output, 3, || {});
+
+        let profile = profile.lock().unwrap();
+        assert_eq!(profile.node(NodeKind::Output, 3).unwrap().invocations, 1);
+    }
+
+    #[test]
+    fn the_last_run_only_contains_the_most_recent_prediction() {
+        let slot = ProfilerSlot::default();
+
+        slot.time(NodeKind::Capability, 1, || {});
+        slot.record_predict(ms(5));
+        slot.time(NodeKind::Model, 2, || {});
+        slot.record_predict(ms(7));
+
+        let last_run = slot.last_run();
+        assert_eq!(last_run.predictions, 1);
+        assert_eq!(last_run.total, ms(7));
+        assert!(last_run.node(NodeKind::Capability, 1).is_none());
+        assert_eq!(last_run.node(NodeKind::Model, 2).unwrap().invocations, 1);
+    }
+}
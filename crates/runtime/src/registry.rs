@@ -0,0 +1,479 @@
+//! Discovering the Runes installed in a directory.
+//!
+//! Device agents often manage dozens of Runes, and loading every one of them
+//! at startup is slow (especially on flash storage). A [`LocalRegistry`]
+//! indexes each `*.rune` file using only the metadata the compiler embeds in
+//! it, skipping over the code and data sections entirely, and only creates a
+//! [`Runtime`] the first time a Rune is actually used.
+//!
+//! The same metadata can be read from a Rune that is already in memory using
+//! [`inspect()`], without instantiating it.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hotg_rune_runtime::registry::LocalRegistry;
+//!
+//! let mut registry = LocalRegistry::scan("/var/lib/runes")?;
+//!
+//! for rune in registry.with_capability("IMAGE") {
+//!     println!("{} (compiled with {:?})", rune.name, rune.version);
+//! }
+//!
+//! let runtime = registry.load("person_detection")?;
+//! runtime.predict()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+use hotg_rune_core::{Shape, GRAPH_CUSTOM_SECTION, VERSION_CUSTOM_SECTION};
+
+use crate::{log_filter::warn, Runtime, RuntimeBuilder};
+
+/// What the registry knows about a Rune without loading it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuneInfo {
+    pub path: PathBuf,
+    /// The Rune's name from its Runefile, or the file name if the Rune
+    /// doesn't say.
+    pub name: String,
+    /// The version of `rune` used to compile it.
+    pub version: Option<String>,
+    /// The kind of each capability the Rune reads from (e.g. `"IMAGE"`),
+    /// using the same names as [`crate::NodeMetadata::kind`].
+    pub capabilities: Vec<String>,
+    /// The tensors each capability provides, keyed by the capability's name
+    /// in the Runefile.
+    pub inputs: BTreeMap<String, Vec<Shape<'static>>>,
+    /// The proc-blocks used by the Rune, including the version they were
+    /// compiled from (e.g. `"hotg-ai/rune@v0.11.3#proc_blocks/fft"`).
+    pub proc_blocks: Vec<String>,
+}
+
+impl RuneInfo {
+    /// Read a Rune's metadata from its custom sections.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let f = File::open(path).with_context(|| {
+            format!("Unable to open \"{}\"", path.display())
+        })?;
+
+        let info = RuneInfo::from_reader(BufReader::new(f), path)
+            .with_context(|| {
+                format!("Unable to read \"{}\"", path.display())
+            })?;
+
+        Ok(info)
+    }
+
+    fn from_reader<R: Read + Seek>(
+        reader: R,
+        path: &Path,
+    ) -> Result<Self, Error> {
+        let sections = read_custom_sections(
+            reader,
+            &[GRAPH_CUSTOM_SECTION, VERSION_CUSTOM_SECTION],
+        )?;
+
+        let mut info = RuneInfo {
+            path: path.to_path_buf(),
+            name: path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            version: None,
+            capabilities: Vec::new(),
+            inputs: BTreeMap::new(),
+            proc_blocks: Vec::new(),
+        };
+
+        for (name, data) in sections {
+            let value: serde_json::Value = serde_json::from_slice(&data)
+                .with_context(|| {
+                    format!("The \"{}\" section is invalid", name)
+                })?;
+
+            match name.as_str() {
+                GRAPH_CUSTOM_SECTION => info.apply_graph(&value),
+                VERSION_CUSTOM_SECTION => {
+                    info.version = value["version"].as_str().map(String::from);
+                },
+                _ => {},
+            }
+        }
+
+        Ok(info)
+    }
+
+    fn apply_graph(&mut self, graph: &serde_json::Value) {
+        if let Some(name) = graph["rune"]["name"].as_str() {
+            self.name = name.to_string();
+        }
+
+        if let Some(capabilities) = graph["capabilities"].as_object() {
+            let mut kinds: Vec<String> = capabilities
+                .values()
+                .filter_map(|c| c["kind"]["type"].as_str())
+                .map(capability_kind)
+                .collect();
+            kinds.sort();
+            kinds.dedup();
+            self.capabilities = kinds;
+
+            self.inputs = capabilities
+                .iter()
+                .map(|(name, c)| (name.clone(), tensor_shapes(graph, c)))
+                .collect();
+        }
+
+        if let Some(proc_blocks) = graph["proc-blocks"].as_object() {
+            let mut paths: Vec<String> = proc_blocks
+                .values()
+                .filter_map(|p| p["path"].as_str())
+                .map(String::from)
+                .collect();
+            paths.sort();
+            paths.dedup();
+            self.proc_blocks = paths;
+        }
+    }
+
+    /// Does this Rune read from a capability of this kind?
+    pub fn requires(&self, kind: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(kind))
+    }
+}
+
+/// Read the metadata the compiler embedded in a Rune, without loading it.
+///
+/// This is the in-memory equivalent of [`RuneInfo::read()`], so
+/// [`RuneInfo::path`] is empty and the name defaults to `""` if the Rune
+/// doesn't have one.
+pub fn inspect(wasm: &[u8]) -> Result<RuneInfo, Error> {
+    RuneInfo::from_reader(Cursor::new(wasm), Path::new(""))
+}
+
+/// Look up the shapes of a node's output tensors in the graph's `tensors`
+/// table, skipping any we don't understand.
+fn tensor_shapes(
+    graph: &serde_json::Value,
+    node: &serde_json::Value,
+) -> Vec<Shape<'static>> {
+    node["outputs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|id| id.as_str())
+        .filter_map(|id| {
+            serde_json::from_value(graph["tensors"][id].clone()).ok()
+        })
+        .collect()
+}
+
+/// Convert the compiler's name for a capability kind (e.g. `"float-image"`)
+/// to the one the runtime uses (e.g. `"FLOAT_IMAGE"`).
+pub(crate) fn capability_kind(kind: &str) -> String {
+    match kind {
+        "random" => "RAND".to_string(),
+        "accelerometer" => "ACCEL".to_string(),
+        other => other.replace('-', "_").to_uppercase(),
+    }
+}
+
+/// An index of the Runes in a directory, which are loaded on first use.
+pub struct LocalRegistry {
+    entries: Vec<Entry>,
+    configure: Box<dyn FnMut(&RuneInfo, RuntimeBuilder) -> RuntimeBuilder>,
+}
+
+struct Entry {
+    info: RuneInfo,
+    runtime: Option<Runtime>,
+}
+
+impl LocalRegistry {
+    /// Index every `*.rune` file directly inside `dir`.
+    ///
+    /// Files which can't be read are skipped with a warning. If two Runes
+    /// have the same name, the one whose path sorts first wins.
+    pub fn scan(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+
+        let mut paths = Vec::new();
+        for entry in dir.read_dir().with_context(|| {
+            format!("Unable to read the \"{}\" directory", dir.display())
+        })? {
+            let path = entry?.path();
+            if path.is_file()
+                && path.extension().and_then(|e| e.to_str()) == Some("rune")
+            {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut entries: Vec<Entry> = Vec::new();
+
+        for path in paths {
+            let info = match RuneInfo::read(&path) {
+                Ok(info) => info,
+                Err(e) => {
+                    warn!("Skipping \"{}\": {:?}", path.display(), e);
+                    continue;
+                },
+            };
+
+            if let Some(existing) =
+                entries.iter().find(|e| e.info.name == info.name)
+            {
+                warn!(
+                    "Ignoring \"{}\" because \"{}\" is already called \"{}\"",
+                    path.display(),
+                    existing.info.path.display(),
+                    info.name
+                );
+                continue;
+            }
+
+            entries.push(Entry {
+                info,
+                runtime: None,
+            });
+        }
+
+        Ok(LocalRegistry {
+            entries,
+            configure: Box::new(|_, builder| builder),
+        })
+    }
+
+    /// Customise how each [`Runtime`] is created (e.g. to pick an
+    /// [`crate::Engine`] or register a model handler) before it is loaded.
+    pub fn configure<F>(mut self, configure: F) -> Self
+    where
+        F: FnMut(&RuneInfo, RuntimeBuilder) -> RuntimeBuilder + 'static,
+    {
+        self.configure = Box::new(configure);
+        self
+    }
+
+    /// Every Rune that was found, in order of their paths.
+    pub fn runes(&self) -> impl Iterator<Item = &RuneInfo> + '_ {
+        self.entries.iter().map(|e| &e.info)
+    }
+
+    /// The Runes which read from a capability of this kind.
+    pub fn with_capability<'a>(
+        &'a self,
+        kind: &'a str,
+    ) -> impl Iterator<Item = &'a RuneInfo> + 'a {
+        self.runes().filter(move |info| info.requires(kind))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RuneInfo> {
+        self.runes().find(|info| info.name == name)
+    }
+
+    /// Has [`LocalRegistry::load()`] been called for this Rune?
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|e| e.info.name == name && e.runtime.is_some())
+    }
+
+    /// Get the [`Runtime`] for a Rune, loading it if this is the first time
+    /// it has been used.
+    pub fn load(&mut self, name: &str) -> Result<&mut Runtime, Error> {
+        let LocalRegistry { entries, configure } = self;
+
+        let entry = entries
+            .iter_mut()
+            .find(|e| e.info.name == name)
+            .with_context(|| format!("There is no Rune called \"{}\"", name))?;
+
+        if entry.runtime.is_none() {
+            let rune = std::fs::read(&entry.info.path).with_context(|| {
+                format!("Unable to read \"{}\"", entry.info.path.display())
+            })?;
+            let runtime = configure(&entry.info, Runtime::builder().rune(rune))
+                .build()
+                .with_context(|| format!("Unable to load \"{}\"", name))?;
+            entry.runtime = Some(runtime);
+        }
+
+        Ok(entry.runtime.as_mut().unwrap())
+    }
+
+    /// Drop a loaded Rune's [`Runtime`] to free up memory. It will be loaded
+    /// again the next time it is used.
+    pub fn unload(&mut self, name: &str) -> Option<Runtime> {
+        self.entries
+            .iter_mut()
+            .find(|e| e.info.name == name)
+            .and_then(|e| e.runtime.take())
+    }
+}
+
+/// Walk the sections in a WebAssembly module, reading the custom sections
+/// we are interested in and seeking past everything else.
+fn read_custom_sections<R: Read + Seek>(
+    mut reader: R,
+    wanted: &[&str],
+) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let mut header = [0_u8; 8];
+    reader
+        .read_exact(&mut header)
+        .context("Unable to read the WebAssembly header")?;
+    anyhow::ensure!(
+        header[..4] == *b"\0asm",
+        "This isn't a WebAssembly module"
+    );
+
+    let mut sections = Vec::new();
+
+    loop {
+        let mut id = [0_u8; 1];
+        if reader.read(&mut id)? == 0 {
+            break;
+        }
+        let size = read_leb128(&mut reader)?;
+
+        if id[0] != 0 {
+            reader.seek(SeekFrom::Current(i64::from(size)))?;
+            continue;
+        }
+
+        let name_len = read_leb128(&mut reader)?;
+        let name_len_size = leb128_size(name_len);
+        anyhow::ensure!(
+            name_len_size + name_len <= size,
+            "Invalid custom section"
+        );
+        let mut name = vec![0_u8; name_len as usize];
+        reader.read_exact(&mut name)?;
+        let name = String::from_utf8(name)
+            .context("The custom section's name isn't valid UTF-8")?;
+
+        let data_len = size - name_len_size - name_len;
+
+        if wanted.contains(&name.as_str()) {
+            let mut data = vec![0_u8; data_len as usize];
+            reader.read_exact(&mut data)?;
+            sections.push((name, data));
+        } else {
+            reader.seek(SeekFrom::Current(i64::from(data_len)))?;
+        }
+    }
+
+    Ok(sections)
+}
+
+fn read_leb128(reader: &mut impl Read) -> Result<u32, Error> {
+    let mut value = 0_u32;
+
+    for shift in (0..35).step_by(7) {
+        let mut byte = [0_u8; 1];
+        reader.read_exact(&mut byte)?;
+        value |= u32::from(byte[0] & 0x7f) << shift;
+
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    anyhow::bail!("Invalid LEB128 integer")
+}
+
+fn leb128_size(value: u32) -> u32 {
+    let bits = 32 - value.leading_zeros();
+    std::cmp::max(1, (bits + 6) / 7)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SINE: &str = r#"(module
+        (func $f (result i32) i32.const 42)
+        (@custom ".rune_graph" "{\"rune\":{\"name\":\"sine\"},\"capabilities\":{\"rand\":{\"kind\":{\"type\":\"random\"},\"args\":{},\"outputs\":[]},\"camera\":{\"kind\":{\"type\":\"float-image\"},\"args\":{},\"outputs\":[]}}}")
+        (@custom ".rune_version" "{\"version\":\"0.11.3\"}")
+        (@custom ".rune_resource" "ignored")
+    )"#;
+
+    #[test]
+    fn read_metadata_from_custom_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sine.rune");
+        std::fs::write(&path, wat::parse_str(SINE).unwrap()).unwrap();
+
+        let info = RuneInfo::read(&path).unwrap();
+
+        assert_eq!(
+            info,
+            RuneInfo {
+                path,
+                name: "sine".to_string(),
+                version: Some("0.11.3".to_string()),
+                capabilities: vec![
+                    "FLOAT_IMAGE".to_string(),
+                    "RAND".to_string()
+                ],
+                inputs: vec![
+                    ("camera".to_string(), Vec::new()),
+                    ("rand".to_string(), Vec::new()),
+                ]
+                .into_iter()
+                .collect(),
+                proc_blocks: Vec::new(),
+            }
+        );
+        assert!(info.requires("rand"));
+    }
+
+    #[test]
+    fn inspect_tensors_and_proc_blocks_in_memory() {
+        let wasm = wat::parse_str(r#"(module
+            (@custom ".rune_graph" "{\"rune\":{\"name\":\"sine\"},\"capabilities\":{\"rand\":{\"kind\":{\"type\":\"random\"},\"args\":{},\"outputs\":[\"1\"]}},\"proc-blocks\":{\"mod360\":{\"path\":\"hotg-ai/rune@v0.11.3#proc_blocks/modulo\",\"args\":{},\"inputs\":[\"1\"],\"outputs\":[\"2\"]}},\"tensors\":{\"1\":{\"element_type\":\"F32\",\"dimensions\":[1,1]}}}")
+        )"#).unwrap();
+
+        let info = inspect(&wasm).unwrap();
+
+        assert_eq!(info.name, "sine");
+        let shape: Shape<'static> = "f32[1, 1]".parse().unwrap();
+        assert_eq!(info.inputs["rand"], vec![shape]);
+        assert_eq!(
+            info.proc_blocks,
+            vec!["hotg-ai/rune@v0.11.3#proc_blocks/modulo".to_string()]
+        );
+    }
+
+    #[test]
+    fn scan_only_indexes_rune_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let wasm = wat::parse_str(SINE).unwrap();
+        std::fs::write(dir.path().join("sine.rune"), &wasm).unwrap();
+        std::fs::write(dir.path().join("sine_copy.rune"), &wasm).unwrap();
+        std::fs::write(dir.path().join("broken.rune"), b"not wasm").unwrap();
+        std::fs::write(dir.path().join("README.md"), b"# Runes").unwrap();
+
+        let registry = LocalRegistry::scan(dir.path()).unwrap();
+
+        let names: Vec<&str> =
+            registry.runes().map(|info| info.name.as_str()).collect();
+        assert_eq!(names, &["sine"]);
+        assert_eq!(
+            registry.get("sine").unwrap().path,
+            dir.path().join("sine.rune")
+        );
+        assert!(!registry.is_loaded("sine"));
+    }
+}
@@ -0,0 +1,259 @@
+//! Recording the inputs a Rune was given so its runs can be replayed exactly.
+//!
+//! While recording, the runtime keeps a copy of every capability's input
+//! tensor at the start of each call to [`Runtime::predict()`][predict]. The
+//! resulting [`Recording`] can be saved alongside a bug report and loaded
+//! with [`Runtime::with_replay()`][with-replay], which feeds the recorded
+//! inputs back to the Rune instead of reading from live sources. That makes
+//! it easy to reproduce a problem or write a golden test for a whole Rune.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), anyhow::Error> {
+//! # let mut runtime: hotg_rune_runtime::Runtime = unimplemented!();
+//! use hotg_rune_runtime::Runtime;
+//!
+//! runtime.start_recording();
+//! for _ in 0..10 {
+//!     runtime.predict()?;
+//! }
+//! runtime.stop_recording().unwrap().save("sine.replay.json")?;
+//!
+//! // Later on...
+//! let rune = std::fs::read("sine.rune")?;
+//! let mut replayed = Runtime::with_replay(&rune, "sine.replay.json")?;
+//! for _ in 0..10 {
+//!     replayed.predict()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Buffers attached with [`Runtime::set_input_buffer()`][input-buffer] are
+//! owned by the host and aren't recorded.
+//!
+//! [predict]: crate::Runtime::predict
+//! [with-replay]: crate::Runtime::with_replay
+//! [input-buffer]: crate::Runtime::set_input_buffer
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Write,
+    num::NonZeroUsize,
+    path::Path,
+};
+
+use anyhow::{Context, Error};
+use serde_json::{json, Value};
+
+use crate::{ElementType, Tensor};
+
+/// The inputs passed to a Rune for each of its runs, oldest first.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Recording {
+    runs: Vec<BTreeMap<u32, Tensor>>,
+}
+
+impl Recording {
+    /// The input tensors for each run, keyed by capability ID.
+    pub fn runs(&self) -> &[BTreeMap<u32, Tensor>] { &self.runs }
+
+    pub(crate) fn record(&mut self, inputs: &HashMap<u32, Tensor>) {
+        self.runs.push(
+            inputs
+                .iter()
+                .map(|(&id, tensor)| (id, tensor.clone()))
+                .collect(),
+        );
+    }
+
+    /// Convert the recording to JSON.
+    ///
+    /// Tensors are stored as hex-encoded bytes so they round-trip exactly.
+    pub fn to_json(&self) -> Value {
+        let runs: Vec<BTreeMap<String, Value>> = self
+            .runs
+            .iter()
+            .map(|inputs| {
+                inputs
+                    .iter()
+                    .map(|(id, tensor)| {
+                        (id.to_string(), tensor_to_json(tensor))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        json!({ "runs": runs })
+    }
+
+    /// Parse a recording created by [`Recording::to_json()`].
+    pub fn from_json(json: &Value) -> Result<Self, Error> {
+        let runs = json["runs"]
+            .as_array()
+            .context("The recording doesn't contain any runs")?;
+
+        let runs = runs
+            .iter()
+            .enumerate()
+            .map(|(i, inputs)| {
+                run_from_json(inputs)
+                    .with_context(|| format!("Unable to read run {}", i))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Recording { runs })
+    }
+
+    /// Save the recording as a JSON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec(&self.to_json())
+            .context("Unable to serialize the recording")?;
+
+        std::fs::write(path, json).with_context(|| {
+            format!("Unable to write to \"{}\"", path.display())
+        })
+    }
+
+    /// Load a recording previously written with [`Recording::save()`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let raw = std::fs::read(path).with_context(|| {
+            format!("Unable to read \"{}\"", path.display())
+        })?;
+        let json: Value = serde_json::from_slice(&raw).with_context(|| {
+            format!("\"{}\" isn't valid JSON", path.display())
+        })?;
+
+        Recording::from_json(&json)
+    }
+}
+
+/// A [`Recording`] which is being fed back into a Rune.
+#[derive(Debug, Clone)]
+pub(crate) struct Replay {
+    remaining: VecDeque<BTreeMap<u32, Tensor>>,
+}
+
+impl Replay {
+    pub(crate) fn new(recording: Recording) -> Self {
+        Replay {
+            remaining: recording.runs.into(),
+        }
+    }
+
+    /// Overwrite `inputs` with the tensors recorded for the next run.
+    pub(crate) fn next_run(
+        &mut self,
+        inputs: &mut HashMap<u32, Tensor>,
+    ) -> Result<(), Error> {
+        let recorded = self
+            .remaining
+            .pop_front()
+            .context("The replay has run out of recorded inputs")?;
+
+        inputs.clear();
+        inputs.extend(recorded);
+
+        Ok(())
+    }
+}
+
+fn tensor_to_json(tensor: &Tensor) -> Value {
+    let mut data = String::with_capacity(tensor.buffer().len() * 2);
+    for byte in tensor.buffer() {
+        let _ = write!(data, "{:02x}", byte);
+    }
+
+    json!({
+        "element-type": tensor.element_type(),
+        "dimensions": tensor.dimensions(),
+        "data": data,
+    })
+}
+
+fn run_from_json(inputs: &Value) -> Result<BTreeMap<u32, Tensor>, Error> {
+    let inputs = inputs.as_object().context("Expected an object")?;
+
+    inputs
+        .iter()
+        .map(|(id, tensor)| {
+            let id: u32 =
+                id.parse().context("Capability IDs should be integers")?;
+            let tensor = tensor_from_json(tensor).with_context(|| {
+                format!("Invalid tensor for capability {}", id)
+            })?;
+            Ok((id, tensor))
+        })
+        .collect()
+}
+
+fn tensor_from_json(json: &Value) -> Result<Tensor, Error> {
+    let element_type: ElementType =
+        serde_json::from_value(json["element-type"].clone())
+            .context("Invalid element type")?;
+    let dimensions: Vec<NonZeroUsize> =
+        serde_json::from_value(json["dimensions"].clone())
+            .context("Invalid dimensions")?;
+    let data = json["data"].as_str().context("The data is missing")?;
+
+    if data.len() % 2 != 0 {
+        anyhow::bail!("The data should contain an even number of hex digits");
+    }
+
+    let buffer = (0..data.len())
+        .step_by(2)
+        .map(|i| {
+            data.get(i..i + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .context("The data isn't valid hex")
+        })
+        .collect::<Result<Vec<u8>, Error>>()?;
+
+    let num_elements: usize = dimensions.iter().map(|d| d.get()).product();
+    let expected_length = num_elements * element_type.byte_size();
+    if buffer.len() != expected_length {
+        anyhow::bail!(
+            "Expected {} bytes of data but found {}",
+            expected_length,
+            buffer.len()
+        );
+    }
+
+    Ok(Tensor::new_raw(element_type, dimensions, buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recordings_round_trip_through_json() {
+        let mut recording = Recording::default();
+        let mut inputs = HashMap::new();
+        inputs.insert(1, Tensor::new(&[0.1_f32, -2.5], &[1, 2]));
+        recording.record(&inputs);
+        inputs.insert(2, Tensor::new(&[255_u8], &[1]));
+        recording.record(&inputs);
+
+        let round_tripped = Recording::from_json(&recording.to_json()).unwrap();
+
+        assert_eq!(round_tripped, recording);
+    }
+
+    #[test]
+    fn replays_run_out() {
+        let mut recording = Recording::default();
+        let mut inputs = HashMap::new();
+        inputs.insert(1, Tensor::new(&[1_i32], &[1]));
+        recording.record(&inputs);
+        let mut replay = Replay::new(recording);
+        let mut live = HashMap::new();
+        live.insert(7, Tensor::new(&[3_u8], &[1]));
+
+        replay.next_run(&mut live).unwrap();
+
+        assert_eq!(live.keys().collect::<Vec<_>>(), [&1]);
+        assert!(replay.next_run(&mut live).is_err());
+    }
+}
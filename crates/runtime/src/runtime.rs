@@ -38,291 +38,3431 @@
 //! call a method on the [`Runtime`] which then asks the Rune for a reference to
 //! the tensor's buffer.
 
-use std::{cell::UnsafeCell, collections::HashMap, sync::Arc};
+use std::{
+    cell::UnsafeCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
 use anyhow::{Context, Error};
-use log::Record;
+use hotg_rune_core::{Shape, GRAPH_CUSTOM_SECTION};
+use hotg_rune_proc_blocks::{ParameterDescriptor, ProcBlockDescriptor};
 use wasmparser::{Parser, Payload};
 
 use crate::{
-    callbacks::{Callbacks, Model, ModelMetadata, RuneGraph},
-    engine::{LoadError, WebAssemblyEngine},
+    batch,
+    callbacks::{
+        Callbacks, Capability, LogRecord, Model, ModelMetadata, ModelOptions,
+        ModelVariant, ParameterUpdate, RuneGraph, StreamingCapability,
+    },
+    cancellation::{
+        CancellationToken, Cancelled, DeadlineExceeded, EngineInterrupt,
+        InterruptHandle,
+    },
+    clock::{Clock, SystemClock},
+    delivery::{DeliveryStats, OutputQueue, OutputReceiver, QueueConfig},
+    engine::{
+        HostFunctions, LoadError, LoadableEngine, NativeEngine,
+        ResourceExhausted, WebAssemblyEngine, WASM_PAGE_SIZE,
+    },
+    errors::RuntimeError,
+    flight_recorder::{FailureReport, FlightRecorder},
+    instrumentation::{Instrumentation, Node},
+    log_filter::{debug, warn, LogFilter},
+    memory_stats::MemoryStats,
+    metadata::{LoadedModel, PipelineMetadata},
+    models::ModelCache,
+    native::NativePipeline,
     outputs::{parse_outputs, OutputTensor},
-    NodeMetadata, Tensor,
+    profiling::{NodeKind, Profile, Profiler},
+    replay::{Recording, Replay},
+    scheduler::{ExecutionPlan, Scheduler},
+    session::{Budget, Session},
+    sink::{Delivery, DeliveryReport, RetryPolicy, Sink, SinkEntry},
+    streaming::{PredictionStream, SlidingWindow, StreamingInput},
+    ElementType, NodeMetadata, Tensor, TensorElement,
 };
 
 /// A loaded Rune.
 pub struct Runtime {
     state: Arc<State>,
     engine: Box<dyn WebAssemblyEngine>,
+    streams: HashMap<u32, StreamingInput>,
+    /// Sources which are drained into their capability's stream by
+    /// [`Runtime::poll_streams()`].
+    stream_sources: HashMap<u32, Box<dyn StreamingCapability>>,
+    /// Capabilities created with [`Runtime::register_capability()`], which
+    /// provide a new input tensor before each prediction.
+    custom_capabilities: HashMap<u32, Box<dyn Capability>>,
+    sinks: Vec<SinkEntry>,
+    /// What happened to each sink's output during the last run.
+    deliveries: Vec<DeliveryReport>,
+    delivery_observer: Option<DeliveryObserver>,
+    flight_recorder: Option<FlightRecorder>,
+    /// The inputs recorded since [`Runtime::start_recording()`] was called.
+    recording: Option<Recording>,
+    /// Recorded inputs which are used instead of the live ones.
+    replay: Option<Replay>,
+    /// Used by [`InterruptHandle`]s to stop the engine mid-way through a
+    /// prediction, if it supports that.
+    interrupter: Option<EngineInterrupt>,
+    /// The filter used while this runtime is loading or running the Rune,
+    /// instead of the process-wide one.
+    log_filter: Option<Arc<LogFilter>>,
 }
 
 impl Runtime {
+    /// Start configuring a [`Runtime`].
+    ///
+    /// ```rust,no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hotg_rune_runtime::Runtime;
+    ///
+    /// let rune = std::fs::read("sine.rune")?;
+    /// let runtime = Runtime::builder()
+    ///     .rune(rune)
+    ///     .memory_limit(512 * 1024)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder() -> RuntimeBuilder { RuntimeBuilder::default() }
+
     /// Load a Rune, using WASM3 for executing WebAssembly.
     #[cfg(feature = "wasm3")]
     pub fn wasm3(rune: &[u8]) -> Result<Self, LoadError> {
-        Runtime::load::<crate::engine::Wasm3Engine>(rune)
+        Runtime::builder().engine(Engine::Wasm3).rune(rune).build()
     }
 
     /// Load a Rune, using Wasmer for executing WebAssembly.
     #[cfg(feature = "wasmer")]
     pub fn wasmer(rune: &[u8]) -> Result<Self, LoadError> {
-        Runtime::load::<crate::engine::WasmerEngine>(rune)
+        Runtime::builder().engine(Engine::Wasmer).rune(rune).build()
     }
 
-    fn load<E>(rune: &[u8]) -> Result<Self, LoadError>
+    fn load<E>(rune: &[u8], state: State) -> Result<Self, LoadError>
     where
-        E: WebAssemblyEngine + 'static,
+        E: LoadableEngine + 'static,
     {
-        let state = State::with_embedded_resources(rune);
+        crate::engine::check_imports(rune, E::supports_wasi())?;
+        crate::engine::check_features::<E>(rune)?;
+
+        if state.fuel_limit().is_some() && !E::supports_fuel() {
+            warn!(
+                "The fuel limit is ignored because the engine doesn't support \
+                 fuel metering"
+            );
+        }
+
+        let cache_dir = state.cache_dir.clone();
         let state = Arc::new(state);
         let callbacks = Arc::clone(&state) as Arc<dyn Callbacks>;
-        let mut engine = E::load(rune, callbacks)?;
+        let engine = E::load(rune, callbacks, cache_dir.as_deref())?;
+
+        Runtime::from_engine(state, Box::new(engine))
+    }
 
+    fn from_engine(
+        state: Arc<State>,
+        mut engine: Box<dyn WebAssemblyEngine>,
+    ) -> Result<Self, LoadError> {
         engine.init()?;
+        check_memory_pages(&state, &*engine)?;
+        let interrupter = engine.interrupter();
 
-        Ok(Runtime {
+        let mut runtime = Runtime {
             state,
-            engine: Box::new(engine),
-        })
+            engine,
+            streams: HashMap::new(),
+            stream_sources: HashMap::new(),
+            custom_capabilities: HashMap::new(),
+            sinks: Vec::new(),
+            deliveries: Vec::new(),
+            delivery_observer: None,
+            flight_recorder: None,
+            recording: None,
+            replay: None,
+            interrupter,
+            log_filter: None,
+        };
+        runtime.stream_requested_sounds()?;
+
+        Ok(runtime)
+    }
+
+    /// Start streaming every `SOUND` capability declared with
+    /// `stream: true` in the Runefile.
+    fn stream_requested_sounds(&mut self) -> Result<(), Error> {
+        let mut requested: Vec<u32> = self
+            .capabilities()
+            .iter()
+            .filter(|(_, meta)| {
+                meta.kind == "SOUND"
+                    && SlidingWindow::is_requested(&meta.arguments)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        requested.sort_unstable();
+
+        for id in requested {
+            let window = self.stream_sound(id).with_context(|| {
+                format!("Unable to stream capability {}", id)
+            })?;
+            debug!(
+                "Streaming capability {} with a {}-sample window and \
+                 {}-sample hop",
+                id, window.window, window.hop
+            );
+        }
+
+        Ok(())
     }
 }
 
-impl Runtime {
-    /// Run the Rune.
-    pub fn predict(&mut self) -> Result<(), Error> { self.engine.predict() }
+/// The WebAssembly engine used to run a Rune.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Engine {
+    /// The [WASM3](https://github.com/wasm3/wasm3) interpreter.
+    #[cfg(feature = "wasm3")]
+    Wasm3,
+    /// The [wasmer](https://wasmer.io/) JIT.
+    #[cfg(feature = "wasmer")]
+    Wasmer,
+    /// The [wasmtime](https://wasmtime.dev/) JIT, which supports fuel
+    /// metering.
+    #[cfg(feature = "wasmtime")]
+    Wasmtime,
+    /// Wasmtime, with support for Runes which import
+    /// [WASI](https://wasi.dev/) functions.
+    #[cfg(feature = "wasi")]
+    WasmtimeWasi,
+}
 
-    /// Get all input tensors, keyed by capability ID.
-    pub fn input_tensors(&mut self) -> &mut HashMap<u32, Tensor> {
-        unsafe { self.state.input_tensors() }
+impl Engine {
+    /// The engine used when none is specified, preferring WASM3, then wasmer,
+    /// then wasmtime.
+    #[cfg(feature = "wasm3")]
+    fn preferred() -> Option<Engine> { Some(Engine::Wasm3) }
+
+    #[cfg(all(feature = "wasmer", not(feature = "wasm3")))]
+    fn preferred() -> Option<Engine> { Some(Engine::Wasmer) }
+
+    #[cfg(all(
+        feature = "wasmtime",
+        not(any(feature = "wasm3", feature = "wasmer"))
+    ))]
+    fn preferred() -> Option<Engine> { Some(Engine::Wasmtime) }
+
+    #[cfg(not(any(
+        feature = "wasm3",
+        feature = "wasmer",
+        feature = "wasmtime"
+    )))]
+    fn preferred() -> Option<Engine> { None }
+}
+
+#[cfg(any(feature = "wasm3", feature = "wasmer", feature = "wasmtime"))]
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::preferred().expect("At least one engine is enabled")
     }
+}
 
-    /// Get all output tensors, keyed by output ID.
-    pub fn output_tensors(&self) -> &HashMap<u32, Vec<OutputTensor>> {
-        unsafe { self.state.output_tensors() }
+/// A builder for configuring a [`Runtime`] before its Rune is loaded.
+///
+/// Unlike the setters on [`Runtime`], everything configured here is in place
+/// before the Rune is initialized, so it also applies to models and resources
+/// loaded while setting up the pipeline.
+#[derive(Default)]
+pub struct RuntimeBuilder {
+    engine: Option<Engine>,
+    rune: Option<Vec<u8>>,
+    native: Option<NativePipeline>,
+    custom_engine: Option<EngineFactory>,
+    model_handler: Option<ModelHandler>,
+    logger: Option<Logger>,
+    resources: HashMap<String, Vec<u8>>,
+    input_tensors: HashMap<u32, Tensor>,
+    input_transforms: HashMap<u32, InputTransform>,
+    memory_limit: Option<usize>,
+    max_tensor_bytes: Option<usize>,
+    fuel_limit: Option<u64>,
+    max_memory_pages: Option<u32>,
+    model_options: Option<ModelOptions>,
+    log_filter: Option<LogFilter>,
+    cache_dir: Option<PathBuf>,
+    clock: Option<Arc<dyn Clock>>,
+    #[cfg(feature = "builtins")]
+    random_seed: Option<u64>,
+    #[cfg(feature = "otel")]
+    tracer: Option<opentelemetry::global::BoxedTracer>,
+}
+
+impl RuntimeBuilder {
+    /// Which WebAssembly engine to use (defaults to [`Engine::default()`]).
+    pub fn engine(self, engine: Engine) -> Self {
+        RuntimeBuilder {
+            engine: Some(engine),
+            ..self
+        }
     }
 
-    /// Get a mapping from each capability's ID to its metadata.
-    pub fn capabilities(&self) -> &HashMap<u32, NodeMetadata> {
-        unsafe { self.state.capabilities() }
+    /// The compiled Rune to load.
+    pub fn rune(self, rune: impl Into<Vec<u8>>) -> Self {
+        RuntimeBuilder {
+            rune: Some(rune.into()),
+            ..self
+        }
     }
 
-    /// Get a mapping from each output's ID to its metadata.
-    pub fn outputs(&self) -> &HashMap<u32, NodeMetadata> {
-        unsafe { self.state.outputs() }
+    /// Run a [`NativePipeline`] instead of a compiled Rune, skipping
+    /// WebAssembly entirely.
+    ///
+    /// See the [`crate::native`] module for more.
+    pub fn native(self, pipeline: NativePipeline) -> Self {
+        RuntimeBuilder {
+            native: Some(pipeline),
+            ..self
+        }
     }
 
-    pub fn set_model_handler<F>(&mut self, load_model: F)
+    /// Run the Rune with a [`WebAssemblyEngine`] that isn't built into the
+    /// runtime, instead of one of the [`Engine`]s.
+    ///
+    /// When the Rune is loaded, `load` is given the Rune's WebAssembly and the
+    /// [`HostFunctions`] it must be linked against. See the
+    /// [`WebAssemblyEngine`] docs for what the engine is responsible for.
+    ///
+    /// Custom engines don't support [`RuntimeBuilder::fuel_limit()`] or
+    /// [`RuntimeBuilder::cache_dir()`].
+    pub fn custom_engine<F>(self, load: F) -> Self
     where
-        F: Fn(u32, &ModelMetadata<'_>, &[u8]) -> Result<Box<dyn Model>, Error>,
-        F: Sync + Send + 'static,
+        F: FnOnce(
+                &[u8],
+                HostFunctions,
+            ) -> Result<Box<dyn WebAssemblyEngine>, Error>
+            + Send
+            + 'static,
     {
-        unsafe { self.state.set_model_handler(load_model) }
+        RuntimeBuilder {
+            custom_engine: Some(Box::new(load)),
+            ..self
+        }
     }
 
-    pub fn set_logger<L>(&mut self, log: L)
+    /// Provide the initial input tensor for a capability.
+    pub fn capability(mut self, capability_id: u32, tensor: Tensor) -> Self {
+        self.input_tensors.insert(capability_id, tensor);
+        self
+    }
+
+    /// Provide a resource, overriding any value embedded in the Rune.
+    pub fn resource(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.resources.insert(name.into(), value.into());
+        self
+    }
+
+    /// Run a capability's input tensor through a function on the host before
+    /// it is copied into the Rune.
+    ///
+    /// This lets expensive preprocessing use native libraries (e.g. resizing
+    /// a camera frame with the platform's hardware scaler) without changing
+    /// the pipeline. The tensor returned by `transform` must have the same
+    /// size as the buffer the Rune asks for.
+    pub fn with_input_transform<F>(
+        mut self,
+        capability_id: u32,
+        transform: F,
+    ) -> Self
     where
-        L: Fn(&Record<'_>),
-        L: Send + Sync + 'static,
+        F: Fn(&NodeMetadata, &Tensor) -> Result<Tensor, Error>,
+        F: Send + Sync + 'static,
     {
-        unsafe { self.state.set_logger(log) }
+        self.input_transforms
+            .insert(capability_id, Box::new(transform));
+        self
     }
 
-    pub fn resources(&mut self) -> &mut HashMap<String, Vec<u8>> {
-        unsafe { self.state.resources() }
+    /// Use a custom function for loading models.
+    pub fn model_handler<F>(self, load_model: F) -> Self
+    where
+        F: Fn(u32, &ModelMetadata<'_>, &[u8]) -> Result<Box<dyn Model>, Error>,
+        F: Sync + Send + 'static,
+    {
+        RuntimeBuilder {
+            model_handler: Some(Box::new(load_model)),
+            ..self
+        }
     }
-}
 
-/// State that is shared between the Runtime and the Rune.
-struct State {
-    input_tensors: UnsafeCell<HashMap<u32, Tensor>>,
-    output_tensors: UnsafeCell<HashMap<u32, Vec<OutputTensor>>>,
-    capabilities: UnsafeCell<HashMap<u32, NodeMetadata>>,
-    outputs: UnsafeCell<HashMap<u32, NodeMetadata>>,
-    load_model: UnsafeCell<
-        Box<
-            dyn Fn(
-                    u32,
-                    &ModelMetadata<'_>,
-                    &[u8],
-                ) -> Result<Box<dyn Model>, Error>
-                + Sync
-                + Send,
-        >,
-    >,
-    log: UnsafeCell<Box<dyn Fn(&Record<'_>) + Send + Sync>>,
-    resources: UnsafeCell<HashMap<String, Vec<u8>>>,
-}
+    /// Load models through a [`ModelCache`] so identical models are shared
+    /// with any other [`Runtime`] that uses the same cache.
+    ///
+    /// This replaces the model handler.
+    pub fn model_cache(self, cache: ModelCache) -> Self {
+        self.model_handler(move |id, meta, model| cache.load(id, meta, model))
+    }
 
-impl State {
-    fn with_embedded_resources(wasm: &[u8]) -> Self {
-        let s = State::default();
+    /// Receive log messages emitted by the Rune.
+    pub fn logger<L>(self, log: L) -> Self
+    where
+        L: Fn(&LogRecord<'_>),
+        L: Send + Sync + 'static,
+    {
+        RuntimeBuilder {
+            logger: Some(Box::new(log)),
+            ..self
+        }
+    }
 
-        for payload in Parser::default().parse_all(wasm) {
-            if let Ok(Payload::CustomSection { name, mut data, .. }) = payload {
-                if name != ".rune_resource" {
-                    continue;
-                }
+    /// The number of bytes available for models, used when deciding which
+    /// model variant to load.
+    pub fn memory_limit(self, bytes: usize) -> Self {
+        RuntimeBuilder {
+            memory_limit: Some(bytes),
+            ..self
+        }
+    }
 
-                while let Some((resource_name, value, rest)) =
-                    hotg_rune_core::decode_inline_resource(data)
-                {
-                    // Safety: fine because we are the only ones with access to
-                    // State at the moment.
-                    let resources = unsafe { s.resources() };
-                    resources.insert(resource_name.to_string(), value.to_vec());
-                    data = rest;
-                }
-            }
+    /// The largest tensor, in bytes, that may be copied into or out of the
+    /// Rune using the chunked host functions.
+    pub fn max_tensor_bytes(self, bytes: usize) -> Self {
+        RuntimeBuilder {
+            max_tensor_bytes: Some(bytes),
+            ..self
         }
+    }
 
-        s
+    /// Bound how much work the Rune can do each time the runtime calls into
+    /// it, by giving it `fuel` units of fuel to spend on executing
+    /// instructions.
+    ///
+    /// Running out of fuel fails with an [`crate::OutOfFuel`] error. Only the
+    /// wasmtime engine supports fuel metering, so other engines will ignore
+    /// this.
+    pub fn fuel_limit(self, fuel: u64) -> Self {
+        RuntimeBuilder {
+            fuel_limit: Some(fuel),
+            ..self
+        }
     }
 
-    unsafe fn outputs(&self) -> &HashMap<u32, NodeMetadata> {
-        &*self.outputs.get()
+    /// Stop the Rune's linear memory from growing past `pages` WebAssembly
+    /// pages (64 KiB each).
+    ///
+    /// The wasmtime engine refuses to grow the memory any further, while
+    /// other engines check the Rune's memory usage after it has been loaded
+    /// and after each prediction. Either way, the Rune fails with a
+    /// [`crate::ResourceExhausted::Memory`] error.
+    pub fn max_memory_pages(self, pages: u32) -> Self {
+        RuntimeBuilder {
+            max_memory_pages: Some(pages),
+            ..self
+        }
     }
 
-    unsafe fn capabilities(&self) -> &HashMap<u32, NodeMetadata> {
-        &*self.capabilities.get()
+    /// Apply these options to every model as soon as it is loaded (e.g. to
+    /// run TensorFlow Lite models on the GPU).
+    ///
+    /// Loading the Rune fails if a model doesn't support the options. Use
+    /// [`Runtime::set_model_options()`] to change them afterwards.
+    pub fn model_options(self, options: ModelOptions) -> Self {
+        RuntimeBuilder {
+            model_options: Some(options),
+            ..self
+        }
     }
 
-    unsafe fn output_tensors(&self) -> &HashMap<u32, Vec<OutputTensor>> {
-        &*self.output_tensors.get()
+    /// Set how verbose each of the runtime's subsystems should be (e.g.
+    /// `models=debug,engine=warn`).
+    ///
+    /// The filter only applies to this [`Runtime`] and can be changed later
+    /// with [`Runtime::set_log_filter()`]. Otherwise, the process-wide filter
+    /// from [`crate::log_filter::set_log_filter()`] is used.
+    pub fn log_filter(self, filter: LogFilter) -> Self {
+        RuntimeBuilder {
+            log_filter: Some(filter),
+            ..self
+        }
     }
 
-    unsafe fn input_tensors(&self) -> &mut HashMap<u32, Tensor> {
-        &mut *self.input_tensors.get()
+    /// A directory where compiled artifacts (e.g. the machine code Wasmer
+    /// generates for a Rune) are cached, so loading the same Rune again after
+    /// a restart can skip compilation.
+    ///
+    /// The directory is also passed to the model handler through
+    /// [`ModelMetadata::cache_dir`].
+    ///
+    /// # Security
+    ///
+    /// Cached machine code is executed without being validated, so anyone who
+    /// can write to this directory can run arbitrary native code inside your
+    /// process. Only use a directory that is writable by trusted users (e.g.
+    /// not a shared `/tmp`).
+    pub fn cache_dir(self, dir: impl Into<PathBuf>) -> Self {
+        RuntimeBuilder {
+            cache_dir: Some(dir.into()),
+            ..self
+        }
     }
 
-    unsafe fn resources(&self) -> &mut HashMap<String, Vec<u8>> {
-        &mut *self.resources.get()
+    /// Read timestamps (e.g. [`LogRecord::timestamp`]) from a [`Clock`]
+    /// instead of the system time.
+    ///
+    /// See the [`crate::clock`] module for more.
+    pub fn clock(self, clock: impl Clock) -> Self {
+        RuntimeBuilder {
+            clock: Some(Arc::new(clock)),
+            ..self
+        }
     }
 
-    unsafe fn set_logger<L>(&self, log: L)
-    where
-        L: Fn(&Record<'_>),
-        L: Send + Sync + 'static,
-    {
-        *self.log.get() = Box::new(log);
+    /// Generate the input for each `RAND` capability using a random number
+    /// generator seeded with `seed`, so the Rune sees the same sequence of
+    /// numbers every time it is loaded.
+    ///
+    /// Each capability gets its own generator, seeded with `seed` and the
+    /// capability's ID. Anything registered later with
+    /// [`Runtime::register_capability()`] takes precedence.
+    #[cfg(feature = "builtins")]
+    #[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "builtins")))]
+    pub fn random_seed(self, seed: u64) -> Self {
+        RuntimeBuilder {
+            random_seed: Some(seed),
+            ..self
+        }
     }
 
-    unsafe fn set_model_handler<F>(&self, load_model: F)
-    where
-        F: Fn(u32, &ModelMetadata<'_>, &[u8]) -> Result<Box<dyn Model>, Error>,
-        F: Sync + Send + 'static,
-    {
-        *self.load_model.get() = Box::new(load_model);
+    /// Export trace spans for each prediction using OpenTelemetry.
+    ///
+    /// See the [`crate::otel`] module for more.
+    #[cfg(feature = "otel")]
+    #[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "otel")))]
+    pub fn opentelemetry(
+        self,
+        tracer: opentelemetry::global::BoxedTracer,
+    ) -> Self {
+        RuntimeBuilder {
+            tracer: Some(tracer),
+            ..self
+        }
     }
-}
 
-impl Default for State {
-    fn default() -> Self {
-        State {
-            input_tensors: UnsafeCell::default(),
-            output_tensors: UnsafeCell::default(),
-            capabilities: UnsafeCell::default(),
-            outputs: UnsafeCell::default(),
-            load_model: UnsafeCell::new(Box::new(
-                crate::models::default_model_handler,
-            )),
-            log: UnsafeCell::new(Box::new(|_| {})),
-            resources: UnsafeCell::default(),
+    /// Load the Rune.
+    pub fn build(self) -> Result<Runtime, LoadError> {
+        let RuntimeBuilder {
+            engine,
+            rune,
+            native,
+            custom_engine,
+            model_handler,
+            logger,
+            resources,
+            input_tensors,
+            input_transforms,
+            memory_limit,
+            max_tensor_bytes,
+            fuel_limit,
+            max_memory_pages,
+            model_options,
+            log_filter,
+            cache_dir,
+            clock,
+            #[cfg(feature = "builtins")]
+            random_seed,
+            #[cfg(feature = "otel")]
+            tracer,
+        } = self;
+
+        #[cfg(feature = "telemetry")]
+        let _span = crate::telemetry::load(rune.as_deref().unwrap_or_default())
+            .entered();
+
+        let log_filter = log_filter.map(Arc::new);
+        let _log_scope = crate::log_filter::scope(log_filter.as_ref());
+
+        let mut state = match &rune {
+            Some(rune) if native.is_none() => {
+                State::with_embedded_resources(rune)
+            },
+            _ => State::default(),
+        };
+        if let Some(pipeline) = &native {
+            state
+                .proc_blocks
+                .get_mut()
+                .extend(pipeline.descriptors.iter().cloned());
+        }
+        state.memory_budget = memory_limit;
+        state.max_tensor_bytes = max_tensor_bytes;
+        state.fuel_limit = fuel_limit;
+        state.max_memory_pages = max_memory_pages;
+        state.model_options = model_options;
+        state.cache_dir = cache_dir;
+        if let Some(clock) = clock {
+            state.clock = clock;
+        }
+        state.resources.get_mut().extend(resources);
+        state.input_tensors.get_mut().extend(input_tensors);
+        state.input_transforms = input_transforms;
+        #[cfg(feature = "otel")]
+        if let Some(tracer) = tracer {
+            let wasm = rune.as_deref().unwrap_or_default();
+            state
+                .instrumentation
+                .set_tracing(crate::otel::Tracing::new(tracer, wasm));
+        }
+
+        if let Some(model_handler) = model_handler {
+            *state.load_model.get_mut() = model_handler;
+        }
+        if let Some(logger) = logger {
+            *state.log.get_mut() = logger;
         }
+
+        let mut runtime = match native {
+            Some(pipeline) => {
+                let state = Arc::new(state);
+                let callbacks = Arc::clone(&state) as Arc<dyn Callbacks>;
+                Runtime::from_engine(
+                    state,
+                    Box::new(NativeEngine::new(pipeline, callbacks)),
+                )?
+            },
+            None => load_rune(rune, engine, custom_engine, state)?,
+        };
+
+        #[cfg(feature = "builtins")]
+        if let Some(seed) = random_seed {
+            runtime.seed_random_capabilities(seed)?;
+        }
+        runtime.log_filter = log_filter;
+
+        Ok(runtime)
     }
 }
 
-impl Callbacks for State {
-    fn loaded(&self, rune: &RuneGraph<'_>) -> Result<(), Error> {
-        log::debug!("Loaded {:?}", rune);
+fn load_rune(
+    rune: Option<Vec<u8>>,
+    engine: Option<Engine>,
+    custom_engine: Option<EngineFactory>,
+    state: State,
+) -> Result<Runtime, LoadError> {
+    let rune = rune.context("No Rune was provided")?;
 
-        // Safety: see the safety comments on State
-        let capabilities = unsafe { &mut *self.capabilities.get() };
-        let outputs = unsafe { &mut *self.outputs.get() };
+    if let Some(load) = custom_engine {
+        // The embedder decides whether to provide WASI
+        crate::engine::check_imports(&rune, true)?;
+        let abi = crate::engine::detect_abi(&rune)?;
 
-        *capabilities = rune.capabilities.clone();
-        *outputs = rune.outputs.clone();
+        let state = Arc::new(state);
+        let callbacks = Arc::clone(&state) as Arc<dyn Callbacks>;
+        let engine = load(&rune, HostFunctions::new(callbacks, abi))
+            .context("Unable to load the custom engine")?;
 
-        Ok(())
+        return Runtime::from_engine(state, engine);
     }
 
-    fn read_capability(
-        &self,
-        id: u32,
-        meta: &NodeMetadata,
-        buffer: &mut [u8],
-    ) -> Result<usize, Error> {
-        // Safety: see the safety comments on State
-        let inputs = unsafe { &*self.input_tensors.get() };
-        let tensor = inputs.get(&id).with_context(|| {
-            format!(
-                "No input tensor provided for the \"{}\" capability with ID {}",
-                meta.kind, id
-            )
-        })?;
+    let engine = engine
+        .or_else(Engine::preferred)
+        .context("No WebAssembly engines were enabled")?;
 
-        let src = tensor.buffer();
+    match engine {
+        #[cfg(feature = "wasm3")]
+        Engine::Wasm3 => {
+            Runtime::load::<crate::engine::Wasm3Engine>(&rune, state)
+        },
+        #[cfg(feature = "wasmer")]
+        Engine::Wasmer => {
+            Runtime::load::<crate::engine::WasmerEngine>(&rune, state)
+        },
+        #[cfg(feature = "wasmtime")]
+        Engine::Wasmtime => {
+            Runtime::load::<crate::engine::WasmtimeEngine>(&rune, state)
+        },
+        #[cfg(feature = "wasi")]
+        Engine::WasmtimeWasi => {
+            Runtime::load::<crate::engine::WasiEngine>(&rune, state)
+        },
+    }
+}
 
-        if src.len() != buffer.len() {
-            anyhow::bail!(
-                "The Rune provided a {} byte buffer, but the input tensor is \
-                 {} ({} bytes)",
-                buffer.len(),
-                tensor.shape(),
-                src.len(),
-            );
+impl Runtime {
+    /// Run the Rune.
+    ///
+    /// Once the Rune has finished, anything written to an output with a
+    /// [`Sink`] is delivered to it and the outcome is recorded in
+    /// [`Runtime::deliveries()`].
+    pub fn predict(&mut self) -> Result<(), Error> {
+        self.predict_with_inputs(HashMap::new())
+    }
+
+    /// Run the Rune, using the `provided` tensors as the inputs for their
+    /// capabilities.
+    ///
+    /// These take precedence over anything registered with
+    /// [`Runtime::register_capability()`] for this run, and are kept as the
+    /// capability's input for later runs.
+    pub(crate) fn predict_with_inputs(
+        &mut self,
+        provided: HashMap<u32, Tensor>,
+    ) -> Result<(), Error> {
+        let _log_scope = crate::log_filter::scope(self.log_filter.as_ref());
+
+        // Interrupts only apply to the prediction that is currently running
+        self.state.interrupt.reset();
+
+        // Safety: we have a &mut reference to the runtime, so the Rune can't
+        // be running.
+        let inputs = unsafe { self.state.input_tensors() };
+
+        match self.replay.as_mut() {
+            Some(replay) => replay.next_run(inputs)?,
+            None => generate_inputs(
+                &mut self.custom_capabilities,
+                &provided,
+                inputs,
+            )?,
         }
+        inputs.extend(provided);
 
-        buffer.copy_from_slice(src);
+        if let Some(recording) = self.recording.as_mut() {
+            recording.record(inputs);
+        }
 
-        Ok(src.len())
-    }
+        unsafe { self.state.written_outputs().clear() };
+        self.deliveries.clear();
 
-    fn write_output(
-        &self,
-        id: u32,
-        meta: &NodeMetadata,
-        data: &[u8],
-    ) -> Result<(), Error> {
-        // Safety: see the safety comments on State
-        let outputs = unsafe { &mut *self.output_tensors.get() };
+        if let Some(recorder) = self.flight_recorder.as_mut() {
+            let started = self.state.clock.now();
+            recorder.start_run(unsafe { self.state.input_tensors() }, started);
+        }
 
-        let parsed = parse_outputs(meta, data).with_context(|| {
-            format!(
-                "Unable to parse the \"{}\" output with ID {}",
-                meta.kind, id
-            )
-        })?;
+        let prediction = self.state.instrumentation.start_predict();
+        let result = self
+            .prepare_inputs()
+            .and_then(|_| self.engine.predict())
+            .and_then(|_| {
+                check_memory_pages(&self.state, self.engine.as_ref())
+            });
+        // Safety: the Rune has finished running
+        unsafe { self.state.prepared_inputs().clear() };
+        let elapsed = self
+            .state
+            .instrumentation
+            .finish_predict(prediction, result.as_ref().err());
 
-        outputs.insert(id, parsed);
+        if let Some(recorder) = self.flight_recorder.as_mut() {
+            recorder.finish_run(elapsed, result.as_ref().err());
+        }
+
+        if matches!(&result, Err(e) if e.is::<Cancelled>()) {
+            // Safety: the Rune has finished running
+            unsafe { self.state.written_outputs().clear() };
+        }
+        result?;
+
+        self.deliver_to_queues();
+        self.deliver_to_sinks();
 
         Ok(())
     }
 
-    fn load_model(
-        &self,
-        id: u32,
-        meta: &ModelMetadata<'_>,
-        model: &[u8],
-    ) -> Result<Box<dyn crate::callbacks::Model>, Error> {
-        // Safety: see the safety comments on State
-        let load_model = unsafe { &*self.load_model.get() };
-        load_model(id, meta, model)
+    /// Run the Rune, stopping early with a [`Cancelled`] error if `token` is
+    /// cancelled while the prediction is in progress.
+    ///
+    /// Anything the Rune wrote before it was cancelled is discarded and won't
+    /// be sent to a [`Sink`], so the [`Runtime`] can be used again as normal.
+    ///
+    /// See the [`crate::cancellation`] module for more.
+    pub fn predict_with_token(
+        &mut self,
+        token: &CancellationToken,
+    ) -> Result<(), Error> {
+        token.check()?;
+
+        // Safety: we have a &mut reference to the runtime, so the Rune can't
+        // be running.
+        unsafe { self.state.set_cancellation(Some(token.clone())) };
+        let result = self.predict();
+        unsafe { self.state.set_cancellation(None) };
+
+        result
     }
 
-    fn get_resource(&self, name: &str) -> Option<&[u8]> {
-        // Safety: see the safety comments on State
-        let resources = unsafe { &*self.resources.get() };
+    /// Run the Rune, interrupting it with a [`DeadlineExceeded`] error if it
+    /// takes longer than `timeout`.
+    ///
+    /// This uses an [`InterruptHandle`], so a Rune stuck inside a proc block
+    /// can only be stopped on engines where
+    /// [`InterruptHandle::interrupts_webassembly()`] is `true`. Otherwise the
+    /// prediction stops the next time the Rune calls into the runtime.
+    pub fn predict_with_deadline(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        let handle = self.interrupt_handle();
+        let (finished, wait_for_finish) = mpsc::channel::<()>();
 
-        resources.get(name).map(|s| s.as_slice())
+        let watchdog = std::thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) =
+                wait_for_finish.recv_timeout(timeout)
+            {
+                handle.interrupt();
+            }
+        });
+
+        let started = Instant::now();
+        let result = self.predict();
+        drop(finished);
+        let _ = watchdog.join();
+
+        match result {
+            Err(e) if e.is::<Cancelled>() && started.elapsed() >= timeout => {
+                Err(e.context(DeadlineExceeded { timeout }))
+            },
+            other => other,
+        }
     }
 
-    fn log(&self, record: &Record<'_>) {
-        // Safety: see the safety comments on State
-        let log = unsafe { &*self.log.get() };
-        log(record);
+    /// Run the Rune once for each item in a batch of inputs.
+    ///
+    /// Every tensor in [`Runtime::input_tensors()`] needs an extra leading
+    /// dimension of `batch_size`, so a capability which normally receives a
+    /// `f32[1, 16000]` tensor should be given a `f32[batch_size, 1, 16000]`
+    /// one. Afterwards, each output in [`Runtime::output_tensors()`] has a
+    /// leading batch dimension as well.
+    ///
+    /// A Rune's tensors have fixed sizes, so the items still pass through the
+    /// Rune one at a time. This saves callers from splitting their inputs up
+    /// and stitching the outputs back together themselves.
+    ///
+    /// The batched input tensors are put back afterwards, even if one of the
+    /// items fails.
+    pub fn predict_batch(&mut self, batch_size: usize) -> Result<(), Error> {
+        anyhow::ensure!(batch_size > 0, "The batch can't be empty");
+
+        let batched = std::mem::take(self.input_tensors());
+        let result = self.run_batch(&batched, batch_size);
+        *self.input_tensors() = batched;
+        let stacked = result?;
+
+        // Safety: we have a &mut reference to the runtime, so the Rune can't
+        // be running.
+        let outputs = unsafe { &mut *self.state.output_tensors.get() };
+        let written = unsafe { self.state.written_outputs() };
+
+        written.clear();
+
+        for (id, tensors) in stacked {
+            outputs.insert(id, tensors);
+            written.push(id);
+        }
+
+        Ok(())
     }
-}
 
-// Safety: see comments on the `State` type itself.
-unsafe impl Sync for State {}
+    fn run_batch(
+        &mut self,
+        batched: &HashMap<u32, Tensor>,
+        batch_size: usize,
+    ) -> Result<HashMap<u32, Vec<OutputTensor>>, Error> {
+        let mut items: Vec<HashMap<u32, Tensor>> =
+            vec![HashMap::new(); batch_size];
+
+        for (&id, tensor) in batched {
+            // Custom capabilities generate a new input for every run anyway
+            if self.custom_capabilities.contains_key(&id) {
+                continue;
+            }
+
+            let split =
+                batch::split(tensor, batch_size).with_context(|| {
+                    format!("Unable to split the input for capability {}", id)
+                })?;
+
+            for (item, tensor) in items.iter_mut().zip(split) {
+                item.insert(id, tensor);
+            }
+        }
+
+        let mut written: HashMap<u32, Vec<Vec<OutputTensor>>> = HashMap::new();
+
+        for (i, inputs) in items.into_iter().enumerate() {
+            *self.input_tensors() = inputs;
+            self.predict()
+                .with_context(|| format!("Item {} of the batch failed", i))?;
+
+            for (id, tensors) in self.updated_output_tensors() {
+                written.entry(id).or_default().push(tensors);
+            }
+        }
+
+        written
+            .into_iter()
+            .map(|(id, items)| {
+                anyhow::ensure!(
+                    items.len() == batch_size,
+                    "Only {} of the {} items in the batch wrote to output {}",
+                    items.len(),
+                    batch_size,
+                    id
+                );
+
+                let tensors = batch::stack(&items).with_context(|| {
+                    format!("Unable to batch the tensors from output {}", id)
+                })?;
+
+                Ok((id, tensors))
+            })
+            .collect()
+    }
+
+    /// Get a handle which can be used to stop the current prediction from
+    /// another thread.
+    ///
+    /// See the [`crate::cancellation`] module for more.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle::new(
+            self.state.interrupt.clone(),
+            self.interrupter.clone(),
+        )
+    }
+
+    /// Send everything written to an output to a [`Sink`] after each run,
+    /// retrying failed deliveries according to the [`RetryPolicy`].
+    ///
+    /// Deliveries happen at the end of [`Runtime::predict()`], so a sink
+    /// which keeps failing will block the prediction until its retries (and
+    /// the sleeps between them) are exhausted.
+    ///
+    /// See the [`crate::sink`] module for more.
+    pub fn add_sink(
+        &mut self,
+        output_id: u32,
+        sink: impl Sink + 'static,
+        policy: RetryPolicy,
+    ) -> Result<(), Error> {
+        if !self.outputs().contains_key(&output_id) {
+            return Err(RuntimeError::UnknownOutput { id: output_id }.into());
+        }
+
+        self.sinks
+            .push(SinkEntry::new(output_id, Box::new(sink), policy));
+
+        Ok(())
+    }
+
+    /// Hand everything written to outputs of a particular kind (e.g.
+    /// `"SERIAL"`) to `handler` after each run, along with the output's ID.
+    ///
+    /// This is a shorthand for calling [`Runtime::add_sink()`] on every
+    /// matching output without retries, so any error returned by `handler`
+    /// is reported as a failed delivery. Kinds are compared
+    /// case-insensitively.
+    ///
+    /// Returns the IDs of the outputs the handler was registered for.
+    pub fn register_output_handler<F>(
+        &mut self,
+        kind: &str,
+        handler: F,
+    ) -> Result<Vec<u32>, Error>
+    where
+        F: FnMut(u32, &[OutputTensor]) -> Result<(), Error> + Send + 'static,
+    {
+        let mut ids: Vec<u32> = self
+            .outputs()
+            .iter()
+            .filter(|(_, meta)| meta.kind.eq_ignore_ascii_case(kind))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+
+        let handler = Arc::new(Mutex::new(handler));
+
+        for &id in &ids {
+            let handler = Arc::clone(&handler);
+            let sink = move |outputs: &[OutputTensor]| {
+                let mut handler = handler.lock().expect("Lock was poisoned");
+                match handler(id, outputs) {
+                    Ok(_) => Delivery::Delivered,
+                    Err(e) => Delivery::Fatal(e),
+                }
+            };
+            self.add_sink(id, sink, RetryPolicy::none())?;
+        }
+
+        Ok(ids)
+    }
+
+    /// Get notified about the final outcome every time an output is sent to
+    /// a [`Sink`].
+    pub fn on_delivery<F>(&mut self, observer: F)
+    where
+        F: FnMut(&DeliveryReport) + Send + 'static,
+    {
+        self.delivery_observer = Some(Box::new(observer));
+    }
+
+    /// What happened to each [`Sink`]'s output during the last run.
+    pub fn deliveries(&self) -> &[DeliveryReport] { &self.deliveries }
+
+    /// Keep the last `capacity` inputs for each capability so a
+    /// [`FailureReport`] can be generated when a run fails.
+    ///
+    /// See the [`crate::flight_recorder`] module for more.
+    pub fn enable_flight_recorder(&mut self, capacity: usize) {
+        self.flight_recorder = Some(FlightRecorder::new(capacity));
+    }
+
+    /// Information about the most recent failed run, if the flight recorder
+    /// is enabled.
+    pub fn last_failure_report(&self) -> Option<&FailureReport> {
+        self.flight_recorder.as_ref()?.last_failure()
+    }
+
+    /// Run up to [`Runtime::parallelism()`] input transforms at a time
+    /// before the Rune starts, instead of waiting for the Rune to read each
+    /// capability.
+    fn prepare_inputs(&self) -> Result<(), Error> {
+        let scheduler = self.state.scheduler();
+
+        if scheduler.parallelism() <= 1
+            || self.state.input_transforms.len() <= 1
+        {
+            return Ok(());
+        }
+
+        // Safety: the Rune isn't running
+        let inputs = unsafe { self.state.input_tensors() };
+        let capabilities = unsafe { self.state.capabilities() };
+
+        let pending: Vec<_> = self
+            .state
+            .input_transforms
+            .iter()
+            .filter_map(|(id, transform)| {
+                Some((*id, transform, capabilities.get(id)?, inputs.get(id)?))
+            })
+            .collect();
+
+        let transformed = scheduler
+            .map(pending, |(id, transform, meta, tensor)| {
+                (id, meta, transform(meta, tensor))
+            });
+
+        let prepared = unsafe { self.state.prepared_inputs() };
+
+        for (id, meta, result) in transformed {
+            let tensor = result.with_context(|| {
+                format!(
+                    "Unable to transform the input for the \"{}\" capability \
+                     with ID {}",
+                    meta.kind, id
+                )
+            })?;
+            prepared.insert(id, tensor);
+        }
+
+        Ok(())
+    }
+
+    /// Tell `profiler` how long each capability, model, and output takes to
+    /// execute, replacing any previous profiler.
+    ///
+    /// See the [`crate::profiling`] module for more.
+    pub fn set_profiler(&mut self, profiler: impl Profiler) {
+        self.state
+            .instrumentation
+            .profiler()
+            .set(Some(Box::new(profiler)));
+    }
+
+    /// Stop profiling.
+    pub fn clear_profiler(&mut self) {
+        self.state.instrumentation.profiler().set(None);
+    }
+
+    /// How long each capability, model, and output took during the most
+    /// recent call to [`Runtime::predict()`].
+    ///
+    /// This is always recorded, even if no profiler has been set with
+    /// [`Runtime::set_profiler()`]. The profile also includes the
+    /// [`Runtime::delivery_stats()`] for each [`Runtime::output_queue()`].
+    pub fn last_run_profile(&self) -> Profile {
+        let mut profile = self.state.instrumentation.profiler().last_run();
+        profile.set_delivery_stats(self.delivery_stats());
+        profile
+    }
+
+    /// Push everything the Rune wrote to the [`Runtime::output_queue()`]s,
+    /// once we know the run was successful.
+    fn deliver_to_queues(&mut self) {
+        // Safety: the Rune has finished running
+        let written = unsafe { self.state.written_outputs() };
+        let outputs = unsafe { self.state.output_tensors() };
+        let queues = unsafe { self.state.output_queues() };
+
+        for id in written.iter() {
+            if let (Some(queue), Some(tensors)) =
+                (queues.get(id), outputs.get(id))
+            {
+                queue.push(tensors.clone());
+            }
+        }
+    }
+
+    fn deliver_to_sinks(&mut self) {
+        // Safety: the Rune has finished running
+        let written = unsafe { self.state.written_outputs() };
+        let outputs = unsafe { self.state.output_tensors() };
+
+        let pending: Vec<_> = self
+            .sinks
+            .iter_mut()
+            .filter(|sink| written.contains(&sink.output_id))
+            .filter_map(|sink| {
+                let tensors = outputs.get(&sink.output_id)?;
+                Some((sink, tensors))
+            })
+            .collect();
+
+        #[cfg(feature = "telemetry")]
+        // Safety: the Rune has finished running
+        let metadata = unsafe { self.state.outputs() };
+
+        // Sinks may block while retrying, so deliver to them in parallel
+        let reports = self.state.scheduler().map(pending, |(sink, tensors)| {
+            #[cfg(feature = "telemetry")]
+            let _span = {
+                let kind = metadata
+                    .get(&sink.output_id)
+                    .map(|meta| meta.kind.as_str());
+                crate::telemetry::deliver(sink.output_id, kind).entered()
+            };
+
+            sink.deliver(tensors, std::thread::sleep)
+        });
+
+        for report in reports {
+            if let Some(observer) = self.delivery_observer.as_mut() {
+                observer(&report);
+            }
+            self.deliveries.push(report);
+        }
+    }
+
+    /// Use a pool of `threads` threads to run independent parts of the
+    /// pipeline at the same time.
+    ///
+    /// Native pipelines run the models and proc-blocks in each wave of their
+    /// [`ExecutionPlan`] in parallel. A compiled Rune drives its own stages
+    /// from inside the WebAssembly module, so only its input transforms and
+    /// [`Sink`] deliveries are done in parallel. Setting this to `0` or `1`
+    /// does everything on the current thread, which is the default. See the
+    /// [`crate::scheduler`] module for more.
+    pub fn set_parallelism(&mut self, threads: usize) -> Result<(), Error> {
+        let scheduler = Scheduler::new(threads)?;
+        // Safety: we have a &mut reference, so the Rune isn't running
+        unsafe { self.state.set_scheduler(scheduler) };
+        Ok(())
+    }
+
+    /// The number of threads used by [`Runtime::set_parallelism()`].
+    pub fn parallelism(&self) -> usize { self.state.scheduler().parallelism() }
+
+    /// Which of the Rune's stages are independent of each other, if the Rune
+    /// contains a summary of its pipeline.
+    pub fn execution_plan(&self) -> Option<&ExecutionPlan> {
+        self.state.execution_plan.as_ref()
+    }
+
+    /// Start a [`Session`] which will stop running the Rune once its
+    /// [`Budget`] is used up.
+    ///
+    /// See the [`crate::session`] module for more.
+    pub fn session(&mut self, budget: Budget) -> Session<'_> {
+        Session::new(self, budget)
+    }
+
+    /// Start a [`PredictionStream`] for pushing successive input windows
+    /// through the Rune and getting back each window's outputs.
+    ///
+    /// See the [`crate::streaming`] module for more.
+    pub fn predict_stream(&mut self) -> PredictionStream<'_> {
+        PredictionStream::new(self)
+    }
+
+    /// Start keeping a copy of the inputs passed to the Rune on every run.
+    ///
+    /// See the [`crate::replay`] module for more.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording::default());
+    }
+
+    /// Stop recording, returning everything that was recorded since
+    /// [`Runtime::start_recording()`] was called.
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
+    /// Feed the inputs from a [`Recording`] to the Rune, one run at a time,
+    /// instead of using the input tensors or any registered capabilities.
+    ///
+    /// Calling [`Runtime::predict()`] after every recorded run has been
+    /// replayed is an error.
+    pub fn replay(&mut self, recording: Recording) {
+        self.replay = Some(Replay::new(recording));
+    }
+
+    /// Load a Rune with the default engine and replay the inputs saved in
+    /// `replay_file` (see [`Recording::save()`]).
+    pub fn with_replay(
+        rune: &[u8],
+        replay_file: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let recording = Recording::load(replay_file)?;
+        let mut runtime = Runtime::builder().rune(rune).build()?;
+        runtime.replay(recording);
+
+        Ok(runtime)
+    }
+
+    /// Get all input tensors, keyed by capability ID.
+    pub fn input_tensors(&mut self) -> &mut HashMap<u32, Tensor> {
+        unsafe { self.state.input_tensors() }
+    }
+
+    /// Read a capability's input directly from memory owned by the host,
+    /// rather than from its tensor in [`Runtime::input_tensors()`].
+    ///
+    /// This avoids copying large inputs (e.g. camera frames) into a
+    /// [`Tensor`] first. The buffer must contain exactly as many bytes as
+    /// the Rune asks for, and is used until [`Runtime::clear_input_buffer()`]
+    /// is called. Input transforms aren't applied to these buffers.
+    pub fn set_input_buffer<B>(
+        &mut self,
+        capability_id: u32,
+        buffer: B,
+    ) -> Result<(), Error>
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        if !self.capabilities().contains_key(&capability_id) {
+            return Err(
+                RuntimeError::UnknownCapability { id: capability_id }.into()
+            );
+        }
+
+        // Safety: we have a &mut reference to the runtime, so the Rune can't
+        // be running.
+        unsafe { self.state.input_buffers() }
+            .insert(capability_id, Box::new(buffer));
+
+        Ok(())
+    }
+
+    /// Go back to reading a capability's input from its tensor, returning
+    /// whether a buffer was set with [`Runtime::set_input_buffer()`].
+    pub fn clear_input_buffer(&mut self, capability_id: u32) -> bool {
+        // Safety: we have a &mut reference to the runtime, so the Rune can't
+        // be running.
+        unsafe { self.state.input_buffers() }
+            .remove(&capability_id)
+            .is_some()
+    }
+
+    /// Get all output tensors, keyed by output ID.
+    pub fn output_tensors(&self) -> &HashMap<u32, Vec<OutputTensor>> {
+        unsafe { self.state.output_tensors() }
+    }
+
+    /// The IDs of the outputs which were written to during the last run.
+    pub fn updated_outputs(&self) -> &[u32] {
+        // Safety: we have a & reference to the runtime, so the Rune can't be
+        // running.
+        unsafe { self.state.written_outputs() }
+    }
+
+    /// A copy of just the output tensors written to during the last run.
+    pub(crate) fn updated_output_tensors(
+        &self,
+    ) -> HashMap<u32, Vec<OutputTensor>> {
+        let outputs = self.output_tensors();
+
+        self.updated_outputs()
+            .iter()
+            .filter_map(|id| outputs.get(id).map(|t| (*id, t.clone())))
+            .collect()
+    }
+
+    /// Get a mapping from each capability's ID to its metadata.
+    pub fn capabilities(&self) -> &HashMap<u32, NodeMetadata> {
+        unsafe { self.state.capabilities() }
+    }
+
+    /// Change one of a capability's arguments without reloading the Rune
+    /// (e.g. to move the `IMAGE` capability's `roi` as the scene changes).
+    ///
+    /// Capability arguments are only read by the host, so the new value will
+    /// be picked up the next time an input is loaded from
+    /// [`Runtime::capabilities()`].
+    pub fn set_capability_argument(
+        &mut self,
+        capability_id: u32,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), Error> {
+        // Safety: we have a &mut reference to the runtime, so the Rune can't
+        // be running.
+        let capabilities = unsafe { self.state.capabilities_mut() };
+
+        match capabilities.get_mut(&capability_id) {
+            Some(capability) => {
+                capability.arguments.insert(name.into(), value.into());
+                Ok(())
+            },
+            None => {
+                Err(RuntimeError::UnknownCapability { id: capability_id }
+                    .into())
+            },
+        }
+    }
+
+    /// The proc-blocks compiled into this Rune, including the parameters
+    /// each of them accepts.
+    ///
+    /// Each parameter's [`ParameterDescriptor::default_value`] comes from the
+    /// proc-block's `Default` impl, so it is only known once the Rune has
+    /// been initialized.
+    pub fn proc_blocks(&self) -> &[ProcBlockDescriptor<'static>] {
+        unsafe { self.state.proc_blocks() }
+    }
+
+    /// Look up the tunable parameters for a particular proc-block type (e.g.
+    /// `"Normalize"`).
+    pub fn proc_block_parameters(
+        &self,
+        type_name: &str,
+    ) -> Option<&[ParameterDescriptor<'static>]> {
+        self.proc_blocks()
+            .iter()
+            .find(|pb| pb.type_name == type_name)
+            .map(|pb| &*pb.parameters)
+    }
+
+    /// Get a mapping from each proc-block's name in the Runefile to its
+    /// metadata, where the [`NodeMetadata::kind`] is the proc-block's type
+    /// and the arguments are the current value of each parameter.
+    pub fn proc_block_nodes(&self) -> &HashMap<String, NodeMetadata> {
+        unsafe { self.state.proc_block_nodes() }
+    }
+
+    /// Change one of a proc-block's parameters (e.g. a threshold) without
+    /// recompiling the Rune.
+    ///
+    /// The value is written the same way it would be in the Runefile and is
+    /// checked against the proc-block's [`ParameterDescriptor`], if it has
+    /// one. The Rune applies it at the start of the next run, after which it
+    /// will show up in [`Runtime::proc_block_nodes()`].
+    pub fn set_proc_block_parameter(
+        &mut self,
+        node: &str,
+        name: &str,
+        value: impl Into<String>,
+    ) -> Result<(), Error> {
+        let value = value.into();
+        let meta = self.proc_block_nodes().get(node).with_context(|| {
+            format!("There is no proc-block called \"{}\"", node)
+        })?;
+
+        match self.proc_block_parameters(&meta.kind) {
+            Some(parameters) => {
+                let parameter = parameters
+                    .iter()
+                    .find(|p| p.name == name)
+                    .with_context(|| {
+                        format!(
+                            "The \"{}\" proc-block doesn't have a \"{}\" \
+                             parameter",
+                            node, name
+                        )
+                    })?;
+
+                if let (Some(range), Ok(number)) =
+                    (&parameter.range, value.parse::<f64>())
+                {
+                    anyhow::ensure!(
+                        range.contains(number),
+                        "{} is outside the range for \"{}\" ({})",
+                        value,
+                        name,
+                        range
+                    );
+                }
+            },
+            None => anyhow::ensure!(
+                meta.arguments.contains_key(name),
+                "The \"{}\" proc-block doesn't have a \"{}\" parameter",
+                node,
+                name
+            ),
+        }
+
+        // Safety: we have a &mut reference to the runtime, so the Rune can't
+        // be running.
+        unsafe {
+            self.state.parameter_updates().push(ParameterUpdate {
+                node: node.to_string(),
+                name: name.to_string(),
+                value,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Get a mapping from each output's ID to its metadata.
+    pub fn outputs(&self) -> &HashMap<u32, NodeMetadata> {
+        unsafe { self.state.outputs() }
+    }
+
+    /// Get a mapping from each capability and output's name in the Runefile
+    /// to its ID.
+    ///
+    /// This is empty for Runes compiled before node names were reported to
+    /// the runtime.
+    pub fn node_names(&self) -> &HashMap<String, u32> {
+        unsafe { self.state.node_names() }
+    }
+
+    /// Deliver everything written to a particular output to a bounded queue.
+    ///
+    /// Outputs will still be available via [`Runtime::output_tensors()`],
+    /// but the returned [`OutputReceiver`] lets a consumer on another thread
+    /// see every run's results instead of just the latest one. Calling this
+    /// again for the same output replaces the previous queue.
+    ///
+    /// Outputs are only queued once a run has finished successfully, so a
+    /// run which fails or is cancelled delivers nothing.
+    pub fn output_queue(
+        &mut self,
+        output_id: u32,
+        config: QueueConfig,
+    ) -> Result<OutputReceiver, Error> {
+        if !self.outputs().contains_key(&output_id) {
+            return Err(RuntimeError::UnknownOutput { id: output_id }.into());
+        }
+
+        let (queue, receiver) = OutputQueue::new(config);
+        unsafe {
+            self.state.output_queues().insert(output_id, queue);
+        }
+        Ok(receiver)
+    }
+
+    /// Get the [`DeliveryStats`] for every output with a queue.
+    pub fn delivery_stats(&self) -> HashMap<u32, DeliveryStats> {
+        let queues = unsafe { self.state.output_queues() };
+        queues.iter().map(|(&id, q)| (id, q.stats())).collect()
+    }
+
+    /// Feed a capability from a continuous stream of samples, running the
+    /// Rune over a [`SlidingWindow`] instead of requiring the caller to set
+    /// the input tensor and call [`Runtime::predict()`] manually.
+    ///
+    /// Each window is passed to the Rune as an `element_type` tensor with the
+    /// provided `dimensions` (e.g. `[1, 16000]` for 1 second of 16 kHz
+    /// audio).
+    pub fn stream_capability(
+        &mut self,
+        capability_id: u32,
+        window: SlidingWindow,
+        element_type: ElementType,
+        dimensions: &[usize],
+    ) -> Result<(), Error> {
+        if !self.capabilities().contains_key(&capability_id) {
+            return Err(
+                RuntimeError::UnknownCapability { id: capability_id }.into()
+            );
+        }
+
+        let stream = StreamingInput::new(window, element_type, dimensions)
+            .context("Invalid sliding window")?;
+        self.streams.insert(capability_id, stream);
+
+        Ok(())
+    }
+
+    /// Stream a `SOUND` capability as 16-bit samples, taking the
+    /// [`SlidingWindow`] from its `hz`, `sample_duration_ms`, and
+    /// `hop_length_ms` arguments (see [`SlidingWindow::for_sound()`]).
+    pub fn stream_sound(
+        &mut self,
+        capability_id: u32,
+    ) -> Result<SlidingWindow, Error> {
+        let meta = self
+            .capabilities()
+            .get(&capability_id)
+            .ok_or(RuntimeError::UnknownCapability { id: capability_id })?;
+
+        if meta.kind != "SOUND" {
+            anyhow::bail!(
+                "Expected a SOUND capability but ID {} is {}",
+                capability_id,
+                meta.kind
+            );
+        }
+
+        let window = SlidingWindow::for_sound(&meta.arguments)
+            .context("Unable to determine the sliding window")?;
+        self.stream_capability(
+            capability_id,
+            window,
+            ElementType::I16,
+            &[1, window.window],
+        )?;
+
+        Ok(window)
+    }
+
+    /// Add samples to a capability registered with
+    /// [`Runtime::stream_capability()`], running the Rune once for every
+    /// window that gets completed.
+    ///
+    /// Returns the number of times the Rune was run.
+    pub fn push_samples<T: TensorElement>(
+        &mut self,
+        capability_id: u32,
+        samples: &[T],
+    ) -> Result<usize, Error> {
+        self.push_samples_with(capability_id, samples, |_| {})
+    }
+
+    /// Like [`Runtime::push_samples()`], except `on_outputs` is given the
+    /// outputs written by each run as soon as it finishes.
+    ///
+    /// A single call may complete several windows, so this is the only way to
+    /// see the results from every run and not just the last one.
+    pub fn push_samples_with<T, F>(
+        &mut self,
+        capability_id: u32,
+        samples: &[T],
+        mut on_outputs: F,
+    ) -> Result<usize, Error>
+    where
+        T: TensorElement,
+        F: FnMut(HashMap<u32, Vec<OutputTensor>>),
+    {
+        let stream =
+            self.streams.get_mut(&capability_id).with_context(|| {
+                format!(
+                    "The capability with ID {} isn't being streamed",
+                    capability_id
+                )
+            })?;
+
+        if stream.element_type() != T::ELEMENT_TYPE {
+            anyhow::bail!(
+                "Expected {} samples but received {}",
+                stream.element_type(),
+                T::ELEMENT_TYPE
+            );
+        }
+
+        let windows = stream.push(samples);
+        let runs = windows.len();
+
+        for window in windows {
+            self.input_tensors().insert(capability_id, window);
+            self.predict()?;
+            on_outputs(self.updated_output_tensors());
+        }
+
+        Ok(runs)
+    }
+
+    /// How many more samples need to be pushed to a streamed capability
+    /// before the Rune is run again.
+    pub fn samples_until_next_run(&self, capability_id: u32) -> Option<usize> {
+        self.streams
+            .get(&capability_id)
+            .map(|stream| stream.until_next_window())
+    }
+
+    /// Throw away any samples buffered for a streamed capability, so the next
+    /// run only sees samples pushed after this call (e.g. so a wake word
+    /// isn't detected a second time in the overlap).
+    pub fn reset_stream(&mut self, capability_id: u32) -> Result<(), Error> {
+        let stream =
+            self.streams.get_mut(&capability_id).with_context(|| {
+                format!(
+                    "The capability with ID {} isn't being streamed",
+                    capability_id
+                )
+            })?;
+        stream.reset();
+
+        Ok(())
+    }
+
+    /// Provide the input for every capability of a particular kind (e.g.
+    /// `"THERMAL"`) from the host.
+    ///
+    /// `factory` is called once for each matching capability with the
+    /// arguments from its Runefile stage, and the [`Capability`] it returns
+    /// generates a new input tensor before every prediction. Kinds are
+    /// compared case-insensitively, so `"thermal"` in a Runefile matches
+    /// `"THERMAL"` here.
+    ///
+    /// Returns the IDs of the capabilities that were registered.
+    pub fn register_capability<F, C>(
+        &mut self,
+        kind: &str,
+        factory: F,
+    ) -> Result<Vec<u32>, Error>
+    where
+        F: Fn(&HashMap<String, String>) -> Result<C, Error>,
+        C: Capability,
+    {
+        let mut ids: Vec<u32> = self
+            .capabilities()
+            .iter()
+            .filter(|(_, meta)| meta.kind.eq_ignore_ascii_case(kind))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+
+        for &id in &ids {
+            let arguments = &self.capabilities()[&id].arguments;
+            let capability = factory(arguments).with_context(|| {
+                format!("Unable to create the \"{}\" capability {}", kind, id)
+            })?;
+            self.custom_capabilities.insert(id, Box::new(capability));
+        }
+
+        Ok(ids)
+    }
+
+    /// Give every `RAND` capability without a custom [`Capability`] its own
+    /// [`crate::builtins::SeededRandom`] generator.
+    #[cfg(feature = "builtins")]
+    fn seed_random_capabilities(&mut self, seed: u64) -> Result<(), Error> {
+        let mut ids: Vec<u32> = self
+            .capabilities()
+            .iter()
+            .filter(|(_, meta)| meta.kind.eq_ignore_ascii_case("RAND"))
+            .map(|(id, _)| *id)
+            .filter(|id| !self.custom_capabilities.contains_key(id))
+            .collect();
+        ids.sort_unstable();
+
+        for id in ids {
+            let args = crate::builtins::Arguments(
+                self.capabilities()[&id].arguments.clone(),
+            );
+            let capability = crate::builtins::SeededRandom::new(
+                &args,
+                seed.wrapping_add(u64::from(id)),
+            )
+            .with_context(|| {
+                format!("Unable to create the \"RAND\" capability {}", id)
+            })?;
+            self.custom_capabilities.insert(id, Box::new(capability));
+        }
+
+        Ok(())
+    }
+
+    /// Use a [`Capability`] to generate the input for a single capability,
+    /// replacing anything registered for it before.
+    pub fn set_capability(
+        &mut self,
+        capability_id: u32,
+        capability: impl Capability,
+    ) -> Result<(), Error> {
+        if !self.capabilities().contains_key(&capability_id) {
+            return Err(
+                RuntimeError::UnknownCapability { id: capability_id }.into()
+            );
+        }
+
+        self.custom_capabilities
+            .insert(capability_id, Box::new(capability));
+
+        Ok(())
+    }
+
+    /// Stream a capability's input from a [`StreamingCapability`] (e.g. the
+    /// receiving half of [`crate::builtins::audio_stream()`]), using the
+    /// same windowing as [`Runtime::stream_capability()`].
+    ///
+    /// Nothing is read until [`Runtime::poll_streams()`] is called.
+    pub fn attach_stream<S>(
+        &mut self,
+        capability_id: u32,
+        window: SlidingWindow,
+        dimensions: &[usize],
+        source: S,
+    ) -> Result<(), Error>
+    where
+        S: StreamingCapability,
+    {
+        self.stream_capability(
+            capability_id,
+            window,
+            source.element_type(),
+            dimensions,
+        )?;
+        self.stream_sources.insert(capability_id, Box::new(source));
+
+        Ok(())
+    }
+
+    /// Read everything that has arrived from the sources attached with
+    /// [`Runtime::attach_stream()`], running the Rune once for every window
+    /// that gets completed.
+    ///
+    /// Returns the number of times the Rune was run.
+    pub fn poll_streams(&mut self) -> Result<usize, Error> {
+        let mut windows = Vec::new();
+        let mut buffer = Vec::new();
+
+        for (&capability_id, source) in &mut self.stream_sources {
+            let stream = match self.streams.get_mut(&capability_id) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            buffer.clear();
+            source.read(&mut buffer);
+            windows.extend(
+                stream
+                    .push_bytes(&buffer)
+                    .into_iter()
+                    .map(|window| (capability_id, window)),
+            );
+        }
+
+        let runs = windows.len();
+
+        for (capability_id, window) in windows {
+            self.input_tensors().insert(capability_id, window);
+            self.predict()?;
+        }
+
+        Ok(runs)
+    }
+
+    /// Get a mapping from each model's ID to its metadata.
+    pub fn models(&self) -> &HashMap<u32, LoadedModel> {
+        unsafe { self.state.models() }
+    }
+
+    /// Change how a model is executed (e.g. the number of threads or which
+    /// execution provider it runs on) without reloading the Rune.
+    ///
+    /// Use [`LoadedModel::providers()`] from [`Runtime::models()`] to see which
+    /// providers a model supports.
+    pub fn reconfigure_model(
+        &mut self,
+        model_id: u32,
+        options: &ModelOptions,
+    ) -> Result<(), Error> {
+        self.engine.with_model(model_id, &mut |model| {
+            model.reconfigure(options).with_context(|| {
+                format!("Unable to reconfigure model {}", model_id)
+            })
+        })
+    }
+
+    /// Swap the model for one of the Rune's pipeline nodes (e.g. after it has
+    /// been retrained) without rebuilding the Rune.
+    ///
+    /// The new model is loaded using the same model handler and
+    /// [`RuntimeBuilder::model_options()`] as the original, and must accept
+    /// and produce tensors with the element types and dimensions declared in
+    /// the Runefile. Only Runes which report their models' node names (see
+    /// [`LoadedModel::node()`]) can have models replaced.
+    pub fn replace_model(
+        &mut self,
+        node: &str,
+        model: &[u8],
+    ) -> Result<(), Error> {
+        let (id, previous) = self
+            .models()
+            .iter()
+            .find(|(_, m)| m.node.as_deref() == Some(node))
+            .map(|(&id, m)| (id, m.clone()))
+            .with_context(|| {
+                format!("The Rune doesn't have a model called \"{}\"", node)
+            })?;
+
+        let meta = ModelMetadata {
+            mimetype: &previous.mimetype,
+            inputs: &previous.inputs,
+            outputs: &previous.outputs,
+            variant: None,
+            cache_dir: None,
+            node: Some(node),
+        };
+
+        let result = self
+            .state
+            .load_model(id, &meta, model)
+            .and_then(|replacement| {
+                check_replacement(&previous, replacement.as_ref())?;
+                self.engine.replace_model(id, replacement)
+            })
+            .with_context(|| {
+                format!("Unable to replace the \"{}\" model", node)
+            });
+
+        if result.is_err() {
+            // Safety: we have a &mut reference to the runtime, so the Rune
+            // can't be running.
+            let models = unsafe { &mut *self.state.models.get() };
+            models.insert(id, previous);
+        }
+
+        result
+    }
+
+    /// Replace the filter used while this runtime is loading or running the
+    /// Rune (see [`RuntimeBuilder::log_filter()`]).
+    pub fn set_log_filter(&mut self, filter: LogFilter) {
+        self.log_filter = Some(Arc::new(filter));
+    }
+
+    /// Reconfigure every model in the Rune using the same options (see
+    /// [`Runtime::reconfigure_model()`]).
+    pub fn set_model_options(
+        &mut self,
+        options: &ModelOptions,
+    ) -> Result<(), Error> {
+        let mut model_ids: Vec<u32> = self.models().keys().copied().collect();
+        model_ids.sort_unstable();
+
+        for model_id in model_ids {
+            self.reconfigure_model(model_id, options)?;
+        }
+
+        Ok(())
+    }
+
+    /// The number of bytes of linear memory the Rune is currently using.
+    ///
+    /// WebAssembly memory can grow but never shrinks, so this is the most the
+    /// Rune has needed at any one time.
+    pub fn memory_usage(&self) -> usize { self.engine.memory_usage() }
+
+    /// Ask the Rune's allocator how much heap it is using, including the
+    /// high-water mark for the most recent call to [`Runtime::predict()`].
+    ///
+    /// This returns `None` for Runes compiled before the statistics were
+    /// exported and for [`NativePipeline`]s, which don't have a WebAssembly
+    /// heap.
+    pub fn memory_stats(&mut self) -> Result<Option<MemoryStats>, Error> {
+        self.engine.memory_stats()
+    }
+
+    /// Get a read-only view over the pipeline's metadata which can be
+    /// queried.
+    pub fn metadata(&self) -> PipelineMetadata<'_> {
+        PipelineMetadata::new(
+            self.capabilities(),
+            self.models(),
+            self.outputs(),
+        )
+    }
+
+    pub fn set_model_handler<F>(&mut self, load_model: F)
+    where
+        F: Fn(u32, &ModelMetadata<'_>, &[u8]) -> Result<Box<dyn Model>, Error>,
+        F: Sync + Send + 'static,
+    {
+        unsafe { self.state.set_model_handler(load_model) }
+    }
+
+    pub fn set_logger<L>(&mut self, log: L)
+    where
+        L: Fn(&LogRecord<'_>),
+        L: Send + Sync + 'static,
+    {
+        unsafe { self.state.set_logger(log) }
+    }
+
+    pub fn resources(&mut self) -> &mut HashMap<String, Vec<u8>> {
+        unsafe { self.state.resources() }
+    }
+}
+
+type ModelHandler = Box<
+    dyn Fn(u32, &ModelMetadata<'_>, &[u8]) -> Result<Box<dyn Model>, Error>
+        + Sync
+        + Send,
+>;
+type Logger = Box<dyn Fn(&LogRecord<'_>) + Send + Sync>;
+type EngineFactory = Box<
+    dyn FnOnce(
+            &[u8],
+            HostFunctions,
+        ) -> Result<Box<dyn WebAssemblyEngine>, Error>
+        + Send,
+>;
+type DeliveryObserver = Box<dyn FnMut(&DeliveryReport) + Send>;
+type InputTransform =
+    Box<dyn Fn(&NodeMetadata, &Tensor) -> Result<Tensor, Error> + Send + Sync>;
+type InputBuffer = Box<dyn AsRef<[u8]> + Send + Sync>;
+
+/// State that is shared between the Runtime and the Rune.
+struct State {
+    input_tensors: UnsafeCell<HashMap<u32, Tensor>>,
+    /// Host-owned memory which is copied straight into the Rune instead of
+    /// the capability's input tensor (see [`Runtime::set_input_buffer()`]).
+    input_buffers: UnsafeCell<HashMap<u32, InputBuffer>>,
+    output_tensors: UnsafeCell<HashMap<u32, Vec<OutputTensor>>>,
+    capabilities: UnsafeCell<HashMap<u32, NodeMetadata>>,
+    outputs: UnsafeCell<HashMap<u32, NodeMetadata>>,
+    /// Capability and output IDs, keyed by their name in the Runefile.
+    node_names: UnsafeCell<HashMap<String, u32>>,
+    models: UnsafeCell<HashMap<u32, LoadedModel>>,
+    output_queues: UnsafeCell<HashMap<u32, OutputQueue>>,
+    /// The outputs which were written to during the current run.
+    written_outputs: UnsafeCell<Vec<u32>>,
+    load_model: UnsafeCell<ModelHandler>,
+    log: UnsafeCell<Logger>,
+    resources: UnsafeCell<HashMap<String, Vec<u8>>>,
+    /// Checked by the Rune's host calls while
+    /// [`Runtime::predict_with_token()`] is running.
+    cancellation: UnsafeCell<Option<CancellationToken>>,
+    /// Cancelled by an [`InterruptHandle`] and reset before each prediction.
+    interrupt: CancellationToken,
+    /// Functions applied to a capability's input before the Rune sees it.
+    ///
+    /// These are only set by the [`RuntimeBuilder`], so they never change
+    /// once the Rune is loaded.
+    input_transforms: HashMap<u32, InputTransform>,
+    /// The number of bytes available for models, used when deciding which
+    /// model variant to load.
+    memory_budget: Option<usize>,
+    /// The largest tensor which may cross the host boundary (see
+    /// [`RuntimeBuilder::max_tensor_bytes()`]).
+    max_tensor_bytes: Option<usize>,
+    /// The descriptors for each proc-block compiled into the Rune.
+    ///
+    /// Each parameter's default is filled in the first time the Rune reports
+    /// its value.
+    proc_blocks: UnsafeCell<Vec<ProcBlockDescriptor<'static>>>,
+    /// Every proc-block node which has reported its parameters, keyed by its
+    /// name in the Runefile.
+    proc_block_nodes: UnsafeCell<HashMap<String, NodeMetadata>>,
+    /// Parameter changes which will be passed to the Rune at the start of the
+    /// next run.
+    parameter_updates: UnsafeCell<Vec<ParameterUpdate>>,
+    execution_plan: Option<ExecutionPlan>,
+    /// Where independent work is run (see [`Runtime::set_parallelism()`]).
+    scheduler: UnsafeCell<Scheduler>,
+    /// Inputs which were transformed by the [`Scheduler`] before the Rune
+    /// started running.
+    prepared_inputs: UnsafeCell<HashMap<u32, Tensor>>,
+    /// How much fuel the Rune may use per call (see
+    /// [`RuntimeBuilder::fuel_limit()`]).
+    fuel_limit: Option<u64>,
+    /// How big the Rune's linear memory may get (see
+    /// [`RuntimeBuilder::max_memory_pages()`]).
+    max_memory_pages: Option<u32>,
+    /// Applied to each model when it is loaded (see
+    /// [`RuntimeBuilder::model_options()`]).
+    model_options: Option<ModelOptions>,
+    /// Where compiled artifacts may be cached between runs.
+    cache_dir: Option<PathBuf>,
+    /// Told about each prediction and how long each node takes (see
+    /// [`Runtime::set_profiler()`] and [`RuntimeBuilder::opentelemetry()`]).
+    instrumentation: Instrumentation,
+    /// Where timestamps come from (see [`RuntimeBuilder::clock()`]).
+    clock: Arc<dyn Clock>,
+}
+
+impl State {
+    fn with_embedded_resources(wasm: &[u8]) -> Self {
+        let mut s = State::default();
+
+        for payload in Parser::default().parse_all(wasm) {
+            if let Ok(Payload::CustomSection { name, mut data, .. }) = payload {
+                if name == ProcBlockDescriptor::CUSTOM_SECTION_NAME {
+                    s.proc_blocks
+                        .get_mut()
+                        .extend(parse_proc_block_descriptors(data));
+                    continue;
+                }
+
+                if name == GRAPH_CUSTOM_SECTION {
+                    match ExecutionPlan::from_rune_graph(data) {
+                        Ok(plan) => s.execution_plan = Some(plan),
+                        Err(e) => warn!("{:?}", e),
+                    }
+                    continue;
+                }
+
+                if name != ".rune_resource" {
+                    continue;
+                }
+
+                while let Some((resource_name, value, rest)) =
+                    hotg_rune_core::decode_inline_resource(data)
+                {
+                    // Safety: fine because we are the only ones with access to
+                    // State at the moment.
+                    let resources = unsafe { s.resources() };
+                    resources.insert(resource_name.to_string(), value.to_vec());
+                    data = rest;
+                }
+            }
+        }
+
+        s
+    }
+
+    unsafe fn outputs(&self) -> &HashMap<u32, NodeMetadata> {
+        &*self.outputs.get()
+    }
+
+    unsafe fn proc_blocks(&self) -> &[ProcBlockDescriptor<'static>] {
+        &*self.proc_blocks.get()
+    }
+
+    unsafe fn proc_block_nodes(&self) -> &mut HashMap<String, NodeMetadata> {
+        &mut *self.proc_block_nodes.get()
+    }
+
+    unsafe fn parameter_updates(&self) -> &mut Vec<ParameterUpdate> {
+        &mut *self.parameter_updates.get()
+    }
+
+    unsafe fn capabilities(&self) -> &HashMap<u32, NodeMetadata> {
+        &*self.capabilities.get()
+    }
+
+    unsafe fn node_names(&self) -> &HashMap<String, u32> {
+        &*self.node_names.get()
+    }
+
+    /// The Runefile name of a capability or output, if the Rune reported it.
+    unsafe fn node_name(&self, id: u32) -> Option<&str> {
+        self.node_names()
+            .iter()
+            .find(|(_, &node)| node == id)
+            .map(|(name, _)| name.as_str())
+    }
+
+    unsafe fn capabilities_mut(&self) -> &mut HashMap<u32, NodeMetadata> {
+        &mut *self.capabilities.get()
+    }
+
+    unsafe fn models(&self) -> &HashMap<u32, LoadedModel> {
+        &*self.models.get()
+    }
+
+    unsafe fn output_queues(&self) -> &mut HashMap<u32, OutputQueue> {
+        &mut *self.output_queues.get()
+    }
+
+    unsafe fn output_tensors(&self) -> &HashMap<u32, Vec<OutputTensor>> {
+        &*self.output_tensors.get()
+    }
+
+    unsafe fn written_outputs(&self) -> &mut Vec<u32> {
+        &mut *self.written_outputs.get()
+    }
+
+    unsafe fn input_tensors(&self) -> &mut HashMap<u32, Tensor> {
+        &mut *self.input_tensors.get()
+    }
+
+    unsafe fn prepared_inputs(&self) -> &mut HashMap<u32, Tensor> {
+        &mut *self.prepared_inputs.get()
+    }
+
+    unsafe fn input_buffers(&self) -> &mut HashMap<u32, InputBuffer> {
+        &mut *self.input_buffers.get()
+    }
+
+    unsafe fn resources(&self) -> &mut HashMap<String, Vec<u8>> {
+        &mut *self.resources.get()
+    }
+
+    fn read_input(
+        &self,
+        id: u32,
+        meta: &NodeMetadata,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
+        // Safety: see the safety comments on State
+        let input_buffers = unsafe { &*self.input_buffers.get() };
+
+        if let Some(external) = input_buffers.get(&id) {
+            let src = (**external).as_ref();
+
+            if src.len() != buffer.len() {
+                anyhow::bail!(
+                    "The Rune provided a {} byte buffer, but the input buffer \
+                     for the \"{}\" capability with ID {} is {} bytes",
+                    buffer.len(),
+                    meta.kind,
+                    id,
+                    src.len(),
+                );
+            }
+
+            buffer.copy_from_slice(src);
+            return Ok(src.len());
+        }
+
+        // Safety: see the safety comments on State
+        let inputs = unsafe { &*self.input_tensors.get() };
+        let tensor =
+            inputs.get(&id).ok_or_else(|| RuntimeError::MissingInput {
+                kind: meta.kind.clone(),
+                id,
+            })?;
+
+        // The scheduler may have already transformed this input
+        let prepared = unsafe { &*self.prepared_inputs.get() }.get(&id);
+
+        let transformed = match (prepared, self.input_transforms.get(&id)) {
+            (None, Some(transform)) => {
+                Some(transform(meta, tensor).with_context(|| {
+                    format!(
+                        "Unable to transform the input for the \"{}\" \
+                         capability with ID {}",
+                        meta.kind, id
+                    )
+                })?)
+            },
+            _ => None,
+        };
+        let tensor = transformed.as_ref().or(prepared).unwrap_or(tensor);
+
+        let src = tensor.buffer();
+
+        if src.len() != buffer.len() {
+            anyhow::bail!(
+                "The Rune provided a {} byte buffer, but the input tensor is \
+                 {} ({} bytes)",
+                buffer.len(),
+                tensor.shape(),
+                src.len(),
+            );
+        }
+
+        buffer.copy_from_slice(src);
+
+        Ok(src.len())
+    }
+
+    fn record_output(
+        &self,
+        id: u32,
+        meta: &NodeMetadata,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        // Safety: see the safety comments on State
+        let outputs = unsafe { &mut *self.output_tensors.get() };
+        let written = unsafe { &mut *self.written_outputs.get() };
+
+        let parsed = parse_outputs(meta, data).with_context(|| {
+            format!(
+                "Unable to parse the \"{}\" output with ID {}",
+                meta.kind, id
+            )
+        })?;
+
+        outputs.insert(id, parsed);
+        if !written.contains(&id) {
+            written.push(id);
+        }
+
+        Ok(())
+    }
+
+    unsafe fn set_cancellation(&self, token: Option<CancellationToken>) {
+        *self.cancellation.get() = token;
+    }
+
+    unsafe fn set_scheduler(&self, scheduler: Scheduler) {
+        *self.scheduler.get() = scheduler;
+    }
+
+    unsafe fn set_logger<L>(&self, log: L)
+    where
+        L: Fn(&LogRecord<'_>),
+        L: Send + Sync + 'static,
+    {
+        *self.log.get() = Box::new(log);
+    }
+
+    unsafe fn set_model_handler<F>(&self, load_model: F)
+    where
+        F: Fn(u32, &ModelMetadata<'_>, &[u8]) -> Result<Box<dyn Model>, Error>,
+        F: Sync + Send + 'static,
+    {
+        *self.load_model.get() = Box::new(load_model);
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            input_tensors: UnsafeCell::default(),
+            input_buffers: UnsafeCell::default(),
+            output_tensors: UnsafeCell::default(),
+            capabilities: UnsafeCell::default(),
+            outputs: UnsafeCell::default(),
+            node_names: UnsafeCell::default(),
+            models: UnsafeCell::default(),
+            output_queues: UnsafeCell::default(),
+            written_outputs: UnsafeCell::default(),
+            load_model: UnsafeCell::new(Box::new(
+                crate::models::default_model_handler,
+            )),
+            log: UnsafeCell::new(Box::new(|_| {})),
+            resources: UnsafeCell::default(),
+            cancellation: UnsafeCell::default(),
+            interrupt: CancellationToken::default(),
+            input_transforms: HashMap::new(),
+            memory_budget: None,
+            max_tensor_bytes: None,
+            proc_blocks: UnsafeCell::default(),
+            proc_block_nodes: UnsafeCell::default(),
+            parameter_updates: UnsafeCell::default(),
+            execution_plan: None,
+            scheduler: UnsafeCell::new(Scheduler::sequential()),
+            prepared_inputs: UnsafeCell::default(),
+            fuel_limit: None,
+            max_memory_pages: None,
+            model_options: None,
+            cache_dir: None,
+            instrumentation: Instrumentation::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+/// The linker concatenates every proc-block's custom section, so we need to
+/// parse each of the JSON-encoded descriptors one after the other.
+fn parse_proc_block_descriptors(
+    data: &[u8],
+) -> Vec<ProcBlockDescriptor<'static>> {
+    let mut descriptors = Vec::new();
+
+    for descriptor in serde_json::Deserializer::from_slice(data)
+        .into_iter::<ProcBlockDescriptor<'static>>()
+    {
+        match descriptor {
+            Ok(d) => descriptors.push(d),
+            Err(e) => {
+                warn!("Unable to parse a proc-block descriptor: {}", e);
+                break;
+            },
+        }
+    }
+
+    descriptors
+}
+
+impl Callbacks for State {
+    fn proc_block_parameter(
+        &self,
+        node: &str,
+        type_name: &str,
+        name: &str,
+        value: &str,
+    ) {
+        // Safety: see the safety comments on State
+        let proc_blocks = unsafe { &mut *self.proc_blocks.get() };
+        let nodes = unsafe { self.proc_block_nodes() };
+
+        let default = proc_blocks
+            .iter_mut()
+            .filter(|pb| pb.type_name == type_name)
+            .flat_map(|pb| pb.parameters.to_mut().iter_mut())
+            .find(|p| p.name == name);
+        if let Some(parameter) = default {
+            // The first value we see comes straight from the Default impl
+            if parameter.default_value.is_none() {
+                parameter.default_value = Some(value.to_string().into());
+            }
+        }
+
+        let meta =
+            nodes
+                .entry(node.to_string())
+                .or_insert_with(|| NodeMetadata {
+                    kind: type_name.to_string(),
+                    arguments: HashMap::new(),
+                });
+        meta.arguments.insert(name.to_string(), value.to_string());
+    }
+
+    fn take_parameter_updates(&self) -> Vec<ParameterUpdate> {
+        // Safety: see the safety comments on State
+        std::mem::take(unsafe { self.parameter_updates() })
+    }
+
+    fn loaded(&self, rune: &RuneGraph<'_>) -> Result<(), Error> {
+        debug!("Loaded {:?}", rune);
+
+        // Safety: see the safety comments on State
+        let capabilities = unsafe { &mut *self.capabilities.get() };
+        let outputs = unsafe { &mut *self.outputs.get() };
+        let node_names = unsafe { &mut *self.node_names.get() };
+
+        *capabilities = rune.capabilities.clone();
+        *outputs = rune.outputs.clone();
+        *node_names = rune.node_names.clone();
+
+        Ok(())
+    }
+
+    fn read_capability(
+        &self,
+        id: u32,
+        meta: &NodeMetadata,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
+        let node = Node {
+            id,
+            kind: &meta.kind,
+            // Safety: see the safety comments on State
+            name: unsafe { self.node_name(id) },
+        };
+
+        self.instrumentation.node(
+            NodeKind::Capability,
+            &node,
+            || self.read_input(id, meta, buffer),
+            |_| {
+                // Safety: see the safety comments on State
+                let inputs = unsafe { &*self.input_tensors.get() };
+                inputs
+                    .get(&id)
+                    .map(|t| t.shape().to_string())
+                    .into_iter()
+                    .collect()
+            },
+        )
+    }
+
+    fn write_output(
+        &self,
+        id: u32,
+        meta: &NodeMetadata,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let node = Node {
+            id,
+            kind: &meta.kind,
+            // Safety: see the safety comments on State
+            name: unsafe { self.node_name(id) },
+        };
+
+        self.instrumentation.node(
+            NodeKind::Output,
+            &node,
+            || self.record_output(id, meta, data),
+            |_| {
+                // Safety: see the safety comments on State
+                let outputs = unsafe { &*self.output_tensors.get() };
+                crate::outputs::output_shapes(outputs.get(&id))
+            },
+        )
+    }
+
+    fn load_model(
+        &self,
+        id: u32,
+        meta: &ModelMetadata<'_>,
+        model: &[u8],
+    ) -> Result<Box<dyn crate::callbacks::Model>, Error> {
+        // Safety: see the safety comments on State
+        let load_model = unsafe { &*self.load_model.get() };
+        let meta = ModelMetadata {
+            cache_dir: self.cache_dir.as_deref(),
+            ..meta.clone()
+        };
+        #[cfg(feature = "telemetry")]
+        let _span = crate::telemetry::model_load(id, &meta).entered();
+        let mut model = load_model(id, &meta, model)?;
+
+        if let Some(options) = &self.model_options {
+            model.reconfigure(options).with_context(|| {
+                format!("Unable to apply the model options to model {}", id)
+            })?;
+        }
+
+        let model =
+            self.instrumentation
+                .model(id, meta.mimetype, meta.node, model);
+
+        // Safety: see the safety comments on State
+        let models = unsafe { &mut *self.models.get() };
+        models.insert(
+            id,
+            LoadedModel {
+                mimetype: meta.mimetype.to_string(),
+                inputs: meta.inputs.iter().map(|s| s.to_owned()).collect(),
+                outputs: meta.outputs.iter().map(|s| s.to_owned()).collect(),
+                variant: meta.variant.map(|v| v.to_string()),
+                node: meta.node.map(|n| n.to_string()),
+                providers: model.providers(),
+                input_descriptors: model.input_descriptors(),
+                output_descriptors: model.output_descriptors(),
+            },
+        );
+
+        Ok(model)
+    }
+
+    fn select_model_variant(
+        &self,
+        variants: &[ModelVariant<'_>],
+    ) -> Result<usize, Error> {
+        select_variant(variants, self.memory_budget)
+            .context("The model doesn't have any variants")
+    }
+
+    fn get_resource(&self, name: &str) -> Option<&[u8]> {
+        // Safety: see the safety comments on State
+        let resources = unsafe { &*self.resources.get() };
+
+        resources.get(name).map(|s| s.as_slice())
+    }
+
+    fn max_tensor_bytes(&self) -> Option<usize> { self.max_tensor_bytes }
+
+    fn fuel_limit(&self) -> Option<u64> { self.fuel_limit }
+
+    fn scheduler(&self) -> Scheduler {
+        // Safety: see the safety comments on State
+        unsafe { (*self.scheduler.get()).clone() }
+    }
+
+    fn max_memory_pages(&self) -> Option<u32> { self.max_memory_pages }
+
+    fn check_cancelled(&self) -> Result<(), Error> {
+        self.interrupt.check()?;
+
+        // Safety: see the safety comments on State
+        let token = unsafe { &*self.cancellation.get() };
+
+        match token {
+            Some(token) => token.check().map_err(Error::from),
+            None => Ok(()),
+        }
+    }
+
+    fn log(&self, record: &LogRecord<'_>) {
+        if !crate::log_filter::rune_enabled(record.target, record.level) {
+            return;
+        }
+
+        // Safety: see the safety comments on State
+        let log = unsafe { &*self.log.get() };
+        log(record);
+    }
+
+    fn now(&self) -> SystemTime { self.clock.now() }
+}
+
+// Safety: see comments on the `State` type itself.
+unsafe impl Sync for State {}
+
+/// Pick the largest variant that fits within the memory budget, falling back
+/// to the smallest one if nothing fits.
+///
+/// Without a budget we assume memory is plentiful and use the largest variant.
+fn select_variant(
+    variants: &[ModelVariant<'_>],
+    memory_budget: Option<usize>,
+) -> Option<usize> {
+    let indexed = variants.iter().enumerate();
+
+    let fits = match memory_budget {
+        Some(budget) => indexed
+            .clone()
+            .filter(|(_, v)| v.size <= budget)
+            .max_by_key(|(_, v)| v.size),
+        None => indexed.clone().max_by_key(|(_, v)| v.size),
+    };
+
+    fits.or_else(|| indexed.min_by_key(|(_, v)| v.size))
+        .map(|(i, _)| i)
+}
+
+/// Make sure a model passed to [`Runtime::replace_model()`] is a drop-in
+/// replacement for the one the Rune was built with.
+fn check_replacement(
+    previous: &LoadedModel,
+    replacement: &dyn Model,
+) -> Result<(), Error> {
+    fn pretty_shapes(shapes: &[Shape<'_>]) -> String {
+        format!(
+            "[{}]",
+            shapes
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    if replacement.input_shapes() != previous.inputs.as_slice() {
+        anyhow::bail!(
+            "The Rune expects a model with inputs {}, but the new model has {}",
+            pretty_shapes(&previous.inputs),
+            pretty_shapes(replacement.input_shapes()),
+        );
+    }
+
+    if replacement.output_shapes() != previous.outputs.as_slice() {
+        anyhow::bail!(
+            "The Rune expects a model with outputs {}, but the new model has \
+             {}",
+            pretty_shapes(&previous.outputs),
+            pretty_shapes(replacement.output_shapes()),
+        );
+    }
+
+    Ok(())
+}
+
+/// Make sure the Rune's linear memory hasn't grown past
+/// [`RuntimeBuilder::max_memory_pages()`], for engines which can't stop it
+/// from growing in the first place.
+fn check_memory_pages<E>(state: &State, engine: &E) -> Result<(), Error>
+where
+    E: WebAssemblyEngine + ?Sized,
+{
+    let pages = match state.max_memory_pages() {
+        Some(pages) => pages,
+        None => return Ok(()),
+    };
+
+    if engine.memory_usage() > pages as usize * WASM_PAGE_SIZE {
+        Err(ResourceExhausted::Memory { pages }.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Ask each [`Capability`] for its next input tensor.
+fn generate_inputs(
+    capabilities: &mut HashMap<u32, Box<dyn Capability>>,
+    provided: &HashMap<u32, Tensor>,
+    inputs: &mut HashMap<u32, Tensor>,
+) -> Result<(), Error> {
+    for (&id, capability) in capabilities {
+        if provided.contains_key(&id) {
+            continue;
+        }
+
+        let tensor = capability.generate().with_context(|| {
+            format!("Unable to generate the input for capability {}", id)
+        })?;
+        inputs.insert(id, tensor);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn input_buffers_take_priority_over_input_tensors() {
+        let mut state = State::default();
+        state
+            .input_tensors
+            .get_mut()
+            .insert(1, Tensor::new(&[0_u8; 4], &[1, 4]));
+        state
+            .input_buffers
+            .get_mut()
+            .insert(1, Box::new(vec![1_u8, 2, 3, 4]));
+        let meta = NodeMetadata {
+            kind: "IMAGE".to_string(),
+            arguments: HashMap::new(),
+        };
+        let mut buffer = [0_u8; 4];
+
+        state.read_capability(1, &meta, &mut buffer).unwrap();
+
+        assert_eq!(buffer, [1, 2, 3, 4]);
+        let mut too_small = [0_u8; 2];
+        assert!(state.read_capability(1, &meta, &mut too_small).is_err());
+    }
+
+    #[test]
+    fn each_runtime_uses_its_own_log_filter() {
+        let graph = serde_json::json!({
+            "capabilities": {
+                "value": {
+                    "kind": { "type": "raw" },
+                    "args": {},
+                    "outputs": ["0"],
+                },
+            },
+            "outputs": {
+                "serial": {
+                    "kind": { "type": "serial" },
+                    "args": {},
+                    "inputs": ["0"],
+                },
+            },
+            "tensors": {
+                "0": { "element_type": "F32", "dimensions": [1] },
+            },
+        });
+        // Check whether a debug message would be logged while the Rune runs
+        let debug_enabled = |filter: &str| {
+            let pipeline =
+                NativePipeline::from_graph(graph.to_string().as_bytes())
+                    .unwrap();
+            let mut runtime = Runtime::builder()
+                .native(pipeline)
+                .log_filter(filter.parse().unwrap())
+                .build()
+                .unwrap();
+            let enabled = Arc::new(AtomicUsize::new(0));
+            let e = Arc::clone(&enabled);
+            runtime
+                .register_capability("RAW", move |_| {
+                    let e = Arc::clone(&e);
+                    Ok(move || {
+                        let level = log::Level::Debug;
+                        if crate::log_filter::enabled(module_path!(), level) {
+                            e.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Ok(Tensor::new(&[1.0_f32], &[1]))
+                    })
+                })
+                .unwrap();
+
+            runtime.predict().unwrap();
+            enabled.load(Ordering::SeqCst) > 0
+        };
+
+        assert!(!debug_enabled("runtime=warn"));
+        assert!(debug_enabled("runtime=debug"));
+        // Neither runtime changed the process-wide filter
+        assert_eq!(crate::log_filter::log_filter(), LogFilter::default());
+    }
+
+    #[test]
+    fn custom_capabilities_generate_a_new_input_each_time() {
+        let mut counter = 0.0_f32;
+        let mut capabilities: HashMap<u32, Box<dyn Capability>> =
+            HashMap::new();
+        capabilities.insert(
+            3,
+            Box::new(move || {
+                counter += 1.0;
+                Ok(Tensor::new(&[counter], &[1, 1]))
+            }),
+        );
+        let mut inputs = HashMap::new();
+
+        generate_inputs(&mut capabilities, &HashMap::new(), &mut inputs)
+            .unwrap();
+        generate_inputs(&mut capabilities, &HashMap::new(), &mut inputs)
+            .unwrap();
+
+        assert_eq!(inputs[&3].elements::<f32>().unwrap(), &[2.0]);
+    }
+
+    #[test]
+    fn input_transforms_run_before_copying_into_the_rune() {
+        let mut state = State::default();
+        state
+            .input_tensors
+            .get_mut()
+            .insert(1, Tensor::new(&[1.0_f32, 2.0, 3.0, 4.0], &[1, 4]));
+        let halve: InputTransform = Box::new(|_, tensor| {
+            let elements = tensor.elements::<f32>().unwrap();
+            let halved: Vec<f32> =
+                elements.chunks(2).map(|pair| pair.iter().sum()).collect();
+            Ok(Tensor::new(&halved, &[1, 2]))
+        });
+        state.input_transforms.insert(1, halve);
+        let meta = NodeMetadata {
+            kind: "RAW".to_string(),
+            arguments: HashMap::new(),
+        };
+        let mut buffer = [0_u8; 8];
+
+        let bytes_read = state.read_capability(1, &meta, &mut buffer).unwrap();
+
+        assert_eq!(bytes_read, 8);
+        let expected: Vec<u8> = [3.0_f32, 7.0]
+            .iter()
+            .flat_map(|f| f.to_ne_bytes())
+            .collect();
+        assert_eq!(&buffer[..], expected);
+    }
+
+    #[test]
+    fn host_calls_fail_once_the_token_is_cancelled() {
+        let state = State::default();
+        let token = CancellationToken::new();
+        unsafe { state.set_cancellation(Some(token.clone())) };
+        assert!(state.check_cancelled().is_ok());
+
+        token.cancel();
+
+        let err = state.check_cancelled().unwrap_err();
+        assert_eq!(err.downcast_ref::<Cancelled>(), Some(&Cancelled));
+    }
+
+    #[test]
+    fn parse_concatenated_proc_block_descriptors() {
+        let section = concat!(
+            r#"{"type_name":"Normalize","description":"","#,
+            r#""available_transforms":[]}"#,
+            r#"{"type_name":"Fft","description":"","available_transforms":[],"#,
+            r#""parameters":[{"name":"bins","type_name":"u32","description":"","#,
+            r#""default_value":"480","range":{"min":1.0}}]}"#,
+        );
+
+        let got = parse_proc_block_descriptors(section.as_bytes());
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].type_name, "Normalize");
+        let bins = &got[1].parameters[0];
+        assert_eq!(bins.default_value.as_deref(), Some("480"));
+        assert_eq!(bins.range.unwrap().min, Some(1.0));
+        assert_eq!(bins.range.unwrap().max, None);
+    }
+
+    #[test]
+    fn interrupt_handles_cancel_host_calls_without_a_token() {
+        let state = State::default();
+        let handle = InterruptHandle::new(state.interrupt.clone(), None);
+
+        handle.interrupt();
+
+        let err = state.check_cancelled().unwrap_err();
+        assert_eq!(err.downcast_ref::<Cancelled>(), Some(&Cancelled));
+        assert!(!handle.interrupts_webassembly());
+    }
+
+    #[test]
+    fn replacement_models_must_match_the_runefile() {
+        struct Shapes(Vec<Shape<'static>>, Vec<Shape<'static>>);
+
+        impl Model for Shapes {
+            fn infer(
+                &mut self,
+                _inputs: &[&[u8]],
+                _outputs: &mut [&mut [u8]],
+            ) -> Result<(), Error> {
+                unimplemented!()
+            }
+
+            fn input_shapes(&self) -> &[Shape<'_>] { &self.0 }
+
+            fn output_shapes(&self) -> &[Shape<'_>] { &self.1 }
+        }
+
+        let input: Shape<'static> = "u8[1, 28, 28, 1]".parse().unwrap();
+        let output: Shape<'static> = "f32[1, 10]".parse().unwrap();
+        let previous = LoadedModel {
+            mimetype: hotg_rune_core::TFLITE_MIMETYPE.to_string(),
+            inputs: vec![input.clone()],
+            outputs: vec![output.clone()],
+            variant: None,
+            node: Some("gesture".to_string()),
+            providers: Vec::new(),
+            input_descriptors: Vec::new(),
+            output_descriptors: Vec::new(),
+        };
+
+        let same = Shapes(vec![input.clone()], vec![output]);
+        check_replacement(&previous, &same).unwrap();
+
+        let more_labels =
+            Shapes(vec![input], vec!["f32[1, 12]".parse().unwrap()]);
+        let err = check_replacement(&previous, &more_labels).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The Rune expects a model with outputs [f32[1, 10]], but the new \
+             model has [f32[1, 12]]"
+        );
+    }
+
+    /// An engine which counts predictions instead of running the Rune.
+    struct StubEngine {
+        host: HostFunctions,
+        predictions: Arc<AtomicUsize>,
+    }
+
+    impl WebAssemblyEngine for StubEngine {
+        fn init(&mut self) -> Result<(), Error> { self.host.loaded() }
+
+        fn predict(&mut self) -> Result<(), Error> {
+            self.predictions.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn with_model(
+            &mut self,
+            model_id: u32,
+            action: &mut dyn FnMut(&mut dyn Model) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            let model =
+                self.host.model_by_id(model_id).context("Unknown model")?;
+            action(model)
+        }
+
+        fn replace_model(
+            &mut self,
+            model_id: u32,
+            model: Box<dyn Model>,
+        ) -> Result<(), Error> {
+            self.host.replace_model(model_id, model)
+        }
+
+        fn memory_usage(&self) -> usize { 0 }
+    }
+
+    /// An engine which writes to a SERIAL output each run, failing once
+    /// `fail` is set.
+    struct WritesAnOutput {
+        host: HostFunctions,
+        output: u32,
+        fail: Arc<AtomicBool>,
+    }
+
+    impl WebAssemblyEngine for WritesAnOutput {
+        fn init(&mut self) -> Result<(), Error> { self.host.loaded() }
+
+        fn predict(&mut self) -> Result<(), Error> {
+            self.host.consume_output(
+                self.output,
+                br#"{"type_name":"u8","elements":[1],"dimensions":[1]}"#,
+            )?;
+            anyhow::ensure!(!self.fail.load(Ordering::SeqCst), "Failed");
+            Ok(())
+        }
+
+        fn with_model(
+            &mut self,
+            _model_id: u32,
+            _action: &mut dyn FnMut(&mut dyn Model) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            anyhow::bail!("No models")
+        }
+
+        fn replace_model(
+            &mut self,
+            _model_id: u32,
+            _model: Box<dyn Model>,
+        ) -> Result<(), Error> {
+            anyhow::bail!("No models")
+        }
+
+        fn memory_usage(&self) -> usize { 0 }
+    }
+
+    const EMPTY_RUNE: &str = r#"(module
+        (func (export "_manifest") (result i32) i32.const 1)
+        (func (export "_call") (param i32 i32 i32) (result i32)
+            i32.const 0))"#;
+
+    fn writes_an_output(fail: Arc<AtomicBool>) -> Runtime {
+        Runtime::builder()
+            .rune(wat::parse_str(EMPTY_RUNE).unwrap())
+            .custom_engine(move |_, mut host| {
+                let output =
+                    host.request_output(hotg_rune_core::outputs::SERIAL)?;
+                Ok(Box::new(WritesAnOutput { host, output, fail })
+                    as Box<dyn WebAssemblyEngine>)
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn only_successful_runs_are_queued() {
+        let fail = Arc::new(AtomicBool::new(false));
+        let mut runtime = writes_an_output(Arc::clone(&fail));
+        let output = *runtime.outputs().keys().next().unwrap();
+        let receiver = runtime
+            .output_queue(output, QueueConfig::default())
+            .unwrap();
+
+        runtime.predict().unwrap();
+        fail.store(true, Ordering::SeqCst);
+        runtime.predict().unwrap_err();
+
+        let stats = runtime.last_run_profile();
+        let stats = stats.delivery_stats(output).unwrap();
+        assert_eq!(stats.enqueued, 1);
+        assert!(receiver.try_recv().is_some());
+        assert!(receiver.try_recv().is_none());
+    }
+
+    /// A [`tracing::Subscriber`] which records the `rune.node.name` of each
+    /// span.
+    #[cfg(feature = "telemetry")]
+    #[derive(Default)]
+    struct SpanNames {
+        names: Arc<Mutex<Vec<(&'static str, String)>>>,
+        next_id: AtomicUsize,
+    }
+
+    #[cfg(feature = "telemetry")]
+    impl tracing::Subscriber for SpanNames {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool { true }
+
+        fn new_span(
+            &self,
+            span: &tracing::span::Attributes<'_>,
+        ) -> tracing::span::Id {
+            struct Visitor(Option<String>);
+
+            impl tracing::field::Visit for Visitor {
+                fn record_str(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &str,
+                ) {
+                    if field.name() == "rune.node.name" {
+                        self.0 = Some(value.to_string());
+                    }
+                }
+
+                fn record_debug(
+                    &mut self,
+                    _field: &tracing::field::Field,
+                    _value: &dyn std::fmt::Debug,
+                ) {
+                }
+            }
+
+            let mut visitor = Visitor(None);
+            span.record(&mut visitor);
+            if let Some(name) = visitor.0 {
+                self.names
+                    .lock()
+                    .unwrap()
+                    .push((span.metadata().name(), name));
+            }
+
+            let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+            tracing::span::Id::from_u64(id as u64)
+        }
+
+        fn record(
+            &self,
+            _span: &tracing::span::Id,
+            _values: &tracing::span::Record<'_>,
+        ) {
+        }
+
+        fn record_follows_from(
+            &self,
+            _span: &tracing::span::Id,
+            _follows: &tracing::span::Id,
+        ) {
+        }
+
+        fn event(&self, _event: &tracing::Event<'_>) {}
+
+        fn enter(&self, _span: &tracing::span::Id) {}
+
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[test]
+    #[cfg(feature = "telemetry")]
+    fn output_spans_use_the_node_name() {
+        let mut runtime = Runtime::builder()
+            .rune(wat::parse_str(EMPTY_RUNE).unwrap())
+            .custom_engine(move |_, mut host| {
+                let output =
+                    host.request_output(hotg_rune_core::outputs::SERIAL)?;
+                host.rune_node_name(output, "serial")?;
+                Ok(Box::new(WritesAnOutput {
+                    host,
+                    output,
+                    fail: Arc::default(),
+                }) as Box<dyn WebAssemblyEngine>)
+            })
+            .build()
+            .unwrap();
+        let subscriber = SpanNames::default();
+        let names = Arc::clone(&subscriber.names);
+
+        tracing::subscriber::with_default(subscriber, || runtime.predict())
+            .unwrap();
+
+        assert_eq!(
+            *names.lock().unwrap(),
+            vec![("rune.output", "serial".to_string())]
+        );
+    }
+
+    #[test]
+    fn queues_are_only_created_for_known_outputs() {
+        let mut runtime = writes_an_output(Arc::default());
+
+        let err = runtime
+            .output_queue(42, QueueConfig::default())
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<RuntimeError>(),
+            Some(&RuntimeError::UnknownOutput { id: 42 })
+        );
+    }
+
+    #[test]
+    fn the_model_budget_doesnt_limit_tensor_sizes() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (func (export "_manifest") (result i32) i32.const 1)
+                (func (export "_call") (param i32 i32 i32) (result i32)
+                    i32.const 0))"#,
+        )
+        .unwrap();
+        let stub = |_: &[u8], host| {
+            Ok(Box::new(StubEngine {
+                host,
+                predictions: Arc::default(),
+            }) as Box<dyn WebAssemblyEngine>)
+        };
+
+        let runtime = Runtime::builder()
+            .rune(wasm.clone())
+            .memory_limit(512)
+            .custom_engine(stub)
+            .build()
+            .unwrap();
+
+        assert_eq!(runtime.state.memory_budget, Some(512));
+        assert_eq!(runtime.state.max_tensor_bytes(), None);
+
+        let runtime = Runtime::builder()
+            .rune(wasm)
+            .max_tensor_bytes(4)
+            .custom_engine(stub)
+            .build()
+            .unwrap();
+
+        assert_eq!(runtime.state.memory_budget, None);
+        assert_eq!(runtime.state.max_tensor_bytes(), Some(4));
+    }
+
+    #[test]
+    fn run_a_rune_with_a_custom_engine() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (func (export "_manifest") (result i32) i32.const 1)
+                (func (export "_call") (param i32 i32 i32) (result i32)
+                    i32.const 0))"#,
+        )
+        .unwrap();
+        let predictions = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&predictions);
+
+        let mut runtime = Runtime::builder()
+            .rune(wasm)
+            .custom_engine(move |_, host| {
+                Ok(Box::new(StubEngine {
+                    host,
+                    predictions: counter,
+                }) as Box<dyn WebAssemblyEngine>)
+            })
+            .build()
+            .unwrap();
+        runtime.predict().unwrap();
+        runtime.predict().unwrap();
+
+        assert_eq!(predictions.load(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(any(feature = "wasmer", feature = "wasmtime"))]
+    fn assert_rune_is_cached(
+        load: impl Fn(&[u8], &Path) -> Result<Runtime, LoadError>,
+    ) {
+        let wasm = wat::parse_str(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "_manifest") (result i32) i32.const 1)
+                (func (export "_call") (param i32 i32 i32) (result i32)
+                    i32.const 0))"#,
+        )
+        .unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cached_files = || {
+            std::fs::read_dir(cache_dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect::<Vec<_>>()
+        };
+
+        load(&wasm, cache_dir.path()).unwrap().predict().unwrap();
+        let first = cached_files();
+        // Loading the Rune again should reuse the cached module
+        load(&wasm, cache_dir.path()).unwrap().predict().unwrap();
+
+        assert_eq!(first.len(), 1, "{:?}", first);
+        assert_eq!(cached_files(), first);
+    }
+
+    #[test]
+    #[cfg(feature = "wasmer")]
+    fn wasmer_reuses_the_cached_module() {
+        assert_rune_is_cached(|wasm, dir| {
+            Runtime::builder()
+                .engine(Engine::Wasmer)
+                .rune(wasm)
+                .cache_dir(dir)
+                .build()
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "wasmtime")]
+    fn wasmtime_reuses_the_cached_module() {
+        assert_rune_is_cached(|wasm, dir| {
+            Runtime::builder()
+                .engine(Engine::Wasmtime)
+                .rune(wasm)
+                .cache_dir(dir)
+                .build()
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "wasmtime")]
+    fn a_custom_engine_takes_precedence_over_the_builtin_ones() {
+        let predictions = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&predictions);
+
+        // The plain wasmtime engine doesn't provide WASI, so it would refuse
+        // to load this
+        let wasm = wat::parse_str(
+            r#"(module
+                (import "wasi_snapshot_preview1" "proc_exit"
+                    (func (param i32)))
+                (func (export "_manifest") (result i32) i32.const 1)
+                (func (export "_call") (param i32 i32 i32) (result i32)
+                    i32.const 0))"#,
+        )
+        .unwrap();
+        let mut runtime = Runtime::builder()
+            .engine(Engine::Wasmtime)
+            .rune(wasm)
+            .custom_engine(move |_, host| {
+                Ok(Box::new(StubEngine {
+                    host,
+                    predictions: counter,
+                }) as Box<dyn WebAssemblyEngine>)
+            })
+            .build()
+            .unwrap();
+        runtime.predict().unwrap();
+
+        assert_eq!(predictions.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "wasmtime")]
+    fn building_requires_a_rune_or_pipeline() {
+        let result = Runtime::builder().engine(Engine::Wasmtime).build();
+
+        match result {
+            Err(e) => assert_eq!(e.to_string(), "No Rune was provided"),
+            Ok(_) => panic!("Loaded a Rune without any WebAssembly"),
+        }
+    }
+
+    /// A Rune which loads a model when it starts and logs a message each
+    /// time it runs.
+    #[cfg(feature = "wasmtime")]
+    const LOGS_AND_LOADS_A_MODEL: &str = r#"(module
+        (import "env" "_debug" (func $debug (param i32 i32) (result i32)))
+        (import "env" "rune_model_load"
+            (func $load_model
+                (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "{\"level\":\"INFO\",\"message\":\"hello\",\"target\":\"rune\"}")
+        (data (i32.const 256) "application/x-test")
+        (data (i32.const 512) "model")
+        (func (export "_manifest") (result i32)
+            (drop (call $load_model
+                (i32.const 256) (i32.const 18)
+                (i32.const 512) (i32.const 5)
+                (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0)))
+            i32.const 1)
+        (func (export "_call") (param i32 i32 i32) (result i32)
+            (drop (call $debug (i32.const 0) (i32.const 50)))
+            i32.const 0))"#;
+
+    #[cfg(feature = "wasmtime")]
+    struct NoopModel;
+
+    #[cfg(feature = "wasmtime")]
+    impl Model for NoopModel {
+        fn infer(
+            &mut self,
+            _inputs: &[&[u8]],
+            _outputs: &mut [&mut [u8]],
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn input_shapes(&self) -> &[Shape<'_>] { &[] }
+
+        fn output_shapes(&self) -> &[Shape<'_>] { &[] }
+    }
+
+    #[test]
+    #[cfg(feature = "wasmtime")]
+    fn callbacks_are_used_with_a_cached_rune() {
+        let wasm = wat::parse_str(LOGS_AND_LOADS_A_MODEL).unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let models = Arc::new(Mutex::new(Vec::new()));
+        let messages = Arc::new(Mutex::new(Vec::new()));
+
+        // The second time around, the Rune comes from the cache
+        for _ in 0..2 {
+            let m = Arc::clone(&models);
+            let l = Arc::clone(&messages);
+            let mut runtime = Runtime::builder()
+                .engine(Engine::Wasmtime)
+                .rune(wasm.clone())
+                .cache_dir(cache_dir.path())
+                .model_handler(move |_, meta, model| {
+                    m.lock()
+                        .unwrap()
+                        .push((meta.mimetype.to_string(), model.to_vec()));
+                    Ok(Box::new(NoopModel) as Box<dyn Model>)
+                })
+                .logger(move |record| {
+                    l.lock().unwrap().push(record.message.to_string())
+                })
+                .build()
+                .unwrap();
+            runtime.predict().unwrap();
+        }
+
+        let expected = ("application/x-test".to_string(), b"model".to_vec());
+        assert_eq!(*models.lock().unwrap(), vec![expected.clone(), expected]);
+        assert_eq!(*messages.lock().unwrap(), vec!["hello", "hello"]);
+    }
+
+    /// A Rune compiled before `rune_model_load()` existed, which reads a
+    /// `RAW` capability, runs it through a TensorFlow Lite model, and writes
+    /// the result to a `SERIAL` output.
+    #[cfg(feature = "wasmtime")]
+    fn legacy_rune(model: &[u8]) -> Vec<u8> {
+        let escaped: String =
+            model.iter().map(|b| format!("\\{:02x}", b)).collect();
+        let message =
+            r#"{"type_name":"f32","elements":[0.5],"dimensions":[1]}"#
+                .replace('"', "\\\"");
+
+        let wat = format!(
+            r#"(module
+            (import "env" "_debug" (func $debug (param i32 i32) (result i32)))
+            (import "env" "request_capability"
+                (func $request_capability (param i32) (result i32)))
+            (import "env" "request_provider_response"
+                (func $read (param i32 i32 i32) (result i32)))
+            (import "env" "request_output"
+                (func $request_output (param i32) (result i32)))
+            (import "env" "consume_output"
+                (func $write (param i32 i32 i32) (result i32)))
+            (import "env" "tfm_preload_model"
+                (func $preload (param i32 i32 i32 i32) (result i32)))
+            (import "env" "tfm_model_invoke"
+                (func $invoke (param i32 i32 i32 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (global $capability (mut i32) (i32.const 0))
+            (global $output (mut i32) (i32.const 0))
+            (global $model (mut i32) (i32.const 0))
+            (data (i32.const 256) "running")
+            (data (i32.const 512) "{message}")
+            (data (i32.const 4096) "{model}")
+            (func (export "_manifest") (result i32)
+                (global.set $capability (call $request_capability (i32.const 5)))
+                (global.set $output (call $request_output (i32.const 1)))
+                (global.set $model (call $preload
+                    (i32.const 4096) (i32.const {model_len})
+                    (i32.const 1) (i32.const 1)))
+                i32.const 1)
+            (func (export "_call") (param i32 i32 i32) (result i32)
+                (drop (call $read (i32.const 0) (i32.const 4) (global.get $capability)))
+                (drop (call $invoke (global.get $model)
+                    (i32.const 0) (i32.const 4) (i32.const 64) (i32.const 4)))
+                (drop (call $debug (i32.const 256) (i32.const 7)))
+                (drop (call $write (global.get $output)
+                    (i32.const 512) (i32.const {message_len})))
+                i32.const 0))"#,
+            message = message,
+            message_len = message.len() - message.matches('\\').count(),
+            model = escaped,
+            model_len = model.len(),
+        );
+
+        wat::parse_str(wat).unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "wasmtime")]
+    fn run_a_legacy_rune() {
+        struct Recorder {
+            inputs: Vec<Shape<'static>>,
+            outputs: Vec<Shape<'static>>,
+            received: Arc<Mutex<Vec<Vec<u8>>>>,
+        }
+
+        impl Model for Recorder {
+            fn infer(
+                &mut self,
+                inputs: &[&[u8]],
+                outputs: &mut [&mut [u8]],
+            ) -> Result<(), Error> {
+                self.received.lock().unwrap().push(inputs[0].to_vec());
+                outputs[0].copy_from_slice(&1.5_f32.to_ne_bytes());
+                Ok(())
+            }
+
+            fn input_shapes(&self) -> &[Shape<'_>] { &self.inputs }
+
+            fn output_shapes(&self) -> &[Shape<'_>] { &self.outputs }
+        }
+
+        let model = include_bytes!("../../../examples/sine/sinemodel.tflite");
+        let wasm = legacy_rune(model);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let r = Arc::clone(&received);
+        let l = Arc::clone(&messages);
+        let mut runtime = Runtime::builder()
+            .engine(Engine::Wasmtime)
+            .rune(wasm)
+            .capability(1, Tensor::new(&[0.5_f32], &[1, 1]))
+            .model_handler(move |_, meta, _| {
+                assert_eq!(meta.mimetype, hotg_rune_core::TFLITE_MIMETYPE);
+                Ok(Box::new(Recorder {
+                    inputs: meta.inputs.iter().map(Shape::to_owned).collect(),
+                    outputs: meta.outputs.iter().map(Shape::to_owned).collect(),
+                    received: Arc::clone(&r),
+                }) as Box<dyn Model>)
+            })
+            .logger(move |record| {
+                l.lock().unwrap().push(record.message.to_string())
+            })
+            .build()
+            .unwrap();
+
+        let sine_shape: Shape<'static> = "f32[1, 1]".parse().unwrap();
+        let model = runtime.models().values().next().unwrap();
+        assert_eq!(model.inputs(), [sine_shape.clone()]);
+        assert_eq!(model.outputs(), [sine_shape]);
+        assert_eq!(runtime.node_names()["raw"], 1);
+        assert_eq!(runtime.node_names()["serial"], 2);
+
+        runtime.predict().unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec![0.5_f32.to_ne_bytes()]);
+        assert!(messages.lock().unwrap().contains(&"running".to_string()));
+        assert_eq!(runtime.output_tensors()[&2].len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "wasmtime")]
+    fn reconfigure_a_loaded_model() {
+        struct Reconfigurable(Arc<Mutex<Vec<ModelOptions>>>);
+
+        impl Model for Reconfigurable {
+            fn infer(
+                &mut self,
+                _inputs: &[&[u8]],
+                _outputs: &mut [&mut [u8]],
+            ) -> Result<(), Error> {
+                Ok(())
+            }
+
+            fn input_shapes(&self) -> &[Shape<'_>] { &[] }
+
+            fn output_shapes(&self) -> &[Shape<'_>] { &[] }
+
+            fn providers(&self) -> Vec<String> {
+                vec![crate::CPU_PROVIDER.to_string(), "gpu".to_string()]
+            }
+
+            fn reconfigure(
+                &mut self,
+                options: &ModelOptions,
+            ) -> Result<(), Error> {
+                options.ensure_supported(&self.providers(), true)?;
+                self.0.lock().unwrap().push(options.clone());
+                Ok(())
+            }
+        }
+
+        let wasm = wat::parse_str(LOGS_AND_LOADS_A_MODEL).unwrap();
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let a = Arc::clone(&applied);
+        let mut runtime = Runtime::builder()
+            .engine(Engine::Wasmtime)
+            .rune(wasm)
+            .model_handler(move |_, _, _| {
+                Ok(Box::new(Reconfigurable(Arc::clone(&a))) as Box<dyn Model>)
+            })
+            .build()
+            .unwrap();
+        let (&id, model) = runtime.models().iter().next().unwrap();
+        assert_eq!(model.mimetype(), "application/x-test");
+        assert_eq!(model.providers(), ["cpu", "gpu"]);
+        let options = ModelOptions {
+            threads: Some(2),
+            provider: Some("gpu".to_string()),
+        };
+
+        runtime.reconfigure_model(id, &options).unwrap();
+
+        assert_eq!(*applied.lock().unwrap(), vec![options]);
+        let unsupported = ModelOptions {
+            provider: Some("tpu".to_string()),
+            ..Default::default()
+        };
+        let err = runtime.reconfigure_model(id, &unsupported).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Unable to reconfigure model {}", id)
+        );
+        assert!(runtime
+            .reconfigure_model(id + 1, &ModelOptions::default())
+            .is_err());
+        assert_eq!(applied.lock().unwrap().len(), 1);
+    }
+}
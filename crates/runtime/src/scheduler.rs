@@ -0,0 +1,252 @@
+//! Running independent parts of a pipeline on a thread pool.
+//!
+//! The compiler embeds a summary of the pipeline in each Rune, which the
+//! [`ExecutionPlan`] uses to topologically sort its stages into "waves". Every
+//! stage in a wave only depends on stages from earlier waves, so stages within
+//! the same wave (e.g. two models reading from the same capability) are
+//! independent of each other.
+//!
+//! With [`crate::Runtime::set_parallelism()`] set above `1`, the native engine
+//! (see [`crate::RuntimeBuilder::native()`]) runs the models and proc-blocks
+//! in each wave on a thread pool. Runes compiled to WebAssembly drive their
+//! own stages from inside the module, so for them the runtime can only do the
+//! work on either side of a run in parallel: input transforms for each
+//! capability are run before the Rune starts, and each [`crate::sink::Sink`]
+//! is given its outputs once it finishes.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+use anyhow::{Context, Error};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+
+/// The order a Rune's stages can be run in.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExecutionPlan {
+    waves: Vec<Vec<String>>,
+}
+
+impl ExecutionPlan {
+    /// Create a plan from the pipeline summary the compiler embeds in a Rune.
+    pub fn from_rune_graph(json: &[u8]) -> Result<Self, Error> {
+        let graph: RuneGraph = serde_json::from_slice(json)
+            .context("Unable to parse the pipeline summary")?;
+
+        let nodes = graph
+            .capabilities
+            .into_iter()
+            .chain(graph.models)
+            .chain(graph.proc_blocks)
+            .chain(graph.outputs)
+            .map(|(name, node)| (name, node.inputs, node.outputs));
+
+        ExecutionPlan::from_stages(nodes)
+    }
+
+    /// Create a plan from a list of stages and the tensors each of them reads
+    /// from and writes to.
+    pub fn from_stages<I, T>(stages: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (String, Vec<T>, Vec<T>)>,
+        T: Into<String>,
+    {
+        let mut producers = HashMap::new();
+        let mut dependencies = BTreeMap::new();
+
+        let stages: Vec<(String, Vec<String>)> = stages
+            .into_iter()
+            .map(|(name, inputs, outputs)| {
+                for tensor in outputs {
+                    producers.insert(tensor.into(), name.clone());
+                }
+                (name, inputs.into_iter().map(Into::into).collect())
+            })
+            .collect();
+
+        for (name, inputs) in stages {
+            let depends_on: Vec<String> = inputs
+                .iter()
+                .filter_map(|tensor| producers.get(tensor).cloned())
+                .filter(|producer| *producer != name)
+                .collect();
+            dependencies.insert(name, depends_on);
+        }
+
+        let mut waves = Vec::new();
+
+        while !dependencies.is_empty() {
+            let ready: Vec<String> = dependencies
+                .iter()
+                .filter(|(_, deps)| {
+                    deps.iter().all(|d| !dependencies.contains_key(d))
+                })
+                .map(|(name, _)| name.clone())
+                .collect();
+
+            if ready.is_empty() {
+                let names: Vec<_> = dependencies.keys().collect();
+                anyhow::bail!(
+                    "The pipeline contains a cycle involving {:?}",
+                    names
+                );
+            }
+
+            for name in &ready {
+                dependencies.remove(name);
+            }
+            waves.push(ready);
+        }
+
+        Ok(ExecutionPlan { waves })
+    }
+
+    /// Groups of stages which can be run at the same time, in the order they
+    /// need to be run.
+    pub fn waves(&self) -> &[Vec<String>] { &self.waves }
+
+    /// The most stages that could ever be running at once.
+    pub fn max_concurrency(&self) -> usize {
+        self.waves.iter().map(Vec::len).max().unwrap_or(0)
+    }
+}
+
+/// Just enough of the compiler's `RuneGraph` to see how stages are connected.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RuneGraph {
+    #[serde(default)]
+    capabilities: HashMap<String, Node>,
+    #[serde(default)]
+    models: HashMap<String, Node>,
+    #[serde(default)]
+    proc_blocks: HashMap<String, Node>,
+    #[serde(default)]
+    outputs: HashMap<String, Node>,
+}
+
+#[derive(serde::Deserialize)]
+struct Node {
+    #[serde(default)]
+    inputs: Vec<String>,
+    #[serde(default)]
+    outputs: Vec<String>,
+}
+
+/// Runs work on a thread pool shared by the [`crate::Runtime`] and its
+/// engine.
+#[derive(Clone)]
+pub(crate) struct Scheduler {
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl Scheduler {
+    /// A [`Scheduler`] which does everything on the current thread.
+    pub(crate) fn sequential() -> Self { Scheduler { pool: None } }
+
+    pub(crate) fn new(parallelism: usize) -> Result<Self, Error> {
+        if parallelism <= 1 {
+            return Ok(Scheduler::sequential());
+        }
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(parallelism)
+            .thread_name(|i| format!("rune-scheduler-{}", i))
+            .build()
+            .context("Unable to start the thread pool")?;
+
+        Ok(Scheduler {
+            pool: Some(Arc::new(pool)),
+        })
+    }
+
+    pub(crate) fn parallelism(&self) -> usize {
+        self.pool
+            .as_ref()
+            .map(|p| p.current_num_threads())
+            .unwrap_or(1)
+    }
+
+    /// Apply `f` to each item, returning the results in the same order.
+    pub(crate) fn map<T, R, F>(&self, items: Vec<T>, f: F) -> Vec<R>
+    where
+        T: Send,
+        R: Send,
+        F: Fn(T) -> R + Send + Sync,
+    {
+        match &self.pool {
+            Some(pool) => {
+                pool.install(|| items.into_par_iter().map(f).collect())
+            },
+            None => items.into_iter().map(f).collect(),
+        }
+    }
+}
+
+impl Debug for Scheduler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("parallelism", &self.parallelism())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn independent_models_share_a_wave() {
+        let graph = br#"{
+            "rune": { "name": "multi-model" },
+            "capabilities": {
+                "audio": { "kind": "SOUND", "args": {}, "outputs": ["audio"] }
+            },
+            "models": {
+                "speech": { "inputs": ["audio"], "outputs": ["speech"] },
+                "music": { "inputs": ["audio"], "outputs": ["music"] }
+            },
+            "proc-blocks": {
+                "merge": { "inputs": ["speech", "music"], "outputs": ["merged"] }
+            },
+            "outputs": {
+                "serial": { "kind": "SERIAL", "args": {}, "inputs": ["merged"] }
+            }
+        }"#;
+
+        let plan = ExecutionPlan::from_rune_graph(graph).unwrap();
+
+        assert_eq!(
+            plan.waves(),
+            [
+                vec!["audio"],
+                vec!["music", "speech"],
+                vec!["merge"],
+                vec!["serial"],
+            ]
+        );
+        assert_eq!(plan.max_concurrency(), 2);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let stages = vec![
+            ("first".to_string(), vec!["b"], vec!["a"]),
+            ("second".to_string(), vec!["a"], vec!["b"]),
+        ];
+
+        assert!(ExecutionPlan::from_stages(stages).is_err());
+    }
+
+    #[test]
+    fn parallel_map_preserves_order() {
+        let scheduler = Scheduler::new(4).unwrap();
+
+        let got = scheduler.map((0..100).collect(), |i: u32| i * 2);
+
+        assert_eq!(scheduler.parallelism(), 4);
+        assert_eq!(got, (0..100).map(|i| i * 2).collect::<Vec<_>>());
+    }
+}
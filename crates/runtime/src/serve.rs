@@ -0,0 +1,576 @@
+//! Expose a [`Runtime`] to other processes over gRPC.
+//!
+//! This lets services which aren't written in Rust use a centrally hosted
+//! runtime without needing to go through FFI. See `proto/runtime.proto` for
+//! the service definition.
+//!
+//! ```rust,no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use hotg_rune_runtime::serve::RuntimeService;
+//!
+//! tonic::transport::Server::builder()
+//!     .add_service(RuntimeService::new().into_server())
+//!     .serve("127.0.0.1:50051".parse()?)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The [`Runtime`] can't be shared between threads, so the [`RuntimeService`]
+//! runs it on a dedicated thread and forwards requests to it.
+
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    pin::Pin,
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+use tokio::sync::{broadcast, oneshot};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    log_filter::warn, AssertionError, ElementType, Engine, NodeMetadata,
+    OutputTensor, Runtime, Tensor, TrapError,
+};
+
+/// The generated gRPC types.
+pub mod proto {
+    #![allow(clippy::all)]
+    tonic::include_proto!("rune.runtime.v1");
+}
+
+/// How many outputs may be buffered for a subscriber before it starts
+/// missing them.
+const SUBSCRIPTION_CAPACITY: usize = 64;
+
+type Reply<T> = oneshot::Sender<Result<T, Status>>;
+
+enum Command {
+    Load(proto::LoadRequest, Reply<proto::LoadResponse>),
+    Metadata(Reply<proto::MetadataResponse>),
+    Predict(proto::PredictRequest, Reply<proto::PredictResponse>),
+}
+
+/// A gRPC service which hosts a single [`Runtime`].
+pub struct RuntimeService {
+    commands: Mutex<mpsc::Sender<Command>>,
+    events: broadcast::Sender<proto::OutputEvent>,
+}
+
+impl RuntimeService {
+    pub fn new() -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let (events, _) = broadcast::channel(SUBSCRIPTION_CAPACITY);
+        let worker_events = events.clone();
+
+        thread::Builder::new()
+            .name(String::from("rune-runtime"))
+            .spawn(move || worker(receiver, worker_events))
+            .expect("Unable to start the runtime thread");
+
+        RuntimeService {
+            commands: Mutex::new(commands),
+            events,
+        }
+    }
+
+    /// Wrap the service so it can be passed to
+    /// `tonic::transport::Server::add_service()`.
+    pub fn into_server(self) -> proto::runtime_server::RuntimeServer<Self> {
+        proto::runtime_server::RuntimeServer::new(self)
+    }
+
+    async fn send<T>(
+        &self,
+        command: impl FnOnce(Reply<T>) -> Command,
+    ) -> Result<T, Status> {
+        let (reply, response) = oneshot::channel();
+
+        self.commands
+            .lock()
+            .unwrap()
+            .send(command(reply))
+            .map_err(|_| {
+                Status::unavailable("The runtime thread has stopped")
+            })?;
+
+        response
+            .await
+            .map_err(|_| Status::internal("The runtime thread crashed"))?
+    }
+}
+
+impl Default for RuntimeService {
+    fn default() -> Self { RuntimeService::new() }
+}
+
+#[tonic::async_trait]
+impl proto::runtime_server::Runtime for RuntimeService {
+    type SubscribeStream =
+        Pin<Box<dyn Stream<Item = Result<proto::OutputEvent, Status>> + Send>>;
+
+    async fn load(
+        &self,
+        request: Request<proto::LoadRequest>,
+    ) -> Result<Response<proto::LoadResponse>, Status> {
+        let request = request.into_inner();
+        self.send(|reply| Command::Load(request, reply))
+            .await
+            .map(Response::new)
+    }
+
+    async fn metadata(
+        &self,
+        _request: Request<proto::MetadataRequest>,
+    ) -> Result<Response<proto::MetadataResponse>, Status> {
+        self.send(Command::Metadata).await.map(Response::new)
+    }
+
+    async fn predict(
+        &self,
+        request: Request<proto::PredictRequest>,
+    ) -> Result<Response<proto::PredictResponse>, Status> {
+        let request = request.into_inner();
+        self.send(|reply| Command::Predict(request, reply))
+            .await
+            .map(Response::new)
+    }
+
+    async fn subscribe(
+        &self,
+        request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let output_ids = request.into_inner().output_ids;
+
+        let events = BroadcastStream::new(self.events.subscribe()).filter_map(
+            move |event| match event {
+                Ok(event) => {
+                    let wanted = output_ids.is_empty()
+                        || event
+                            .output
+                            .as_ref()
+                            .map_or(false, |o| output_ids.contains(&o.id));
+
+                    if wanted {
+                        Some(Ok(event))
+                    } else {
+                        None
+                    }
+                },
+                Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                    warn!("A subscriber missed {} outputs", missed);
+                    None
+                },
+            },
+        );
+
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// The loop running on the runtime thread.
+fn worker(
+    commands: mpsc::Receiver<Command>,
+    events: broadcast::Sender<proto::OutputEvent>,
+) {
+    let mut runtime: Option<Runtime> = None;
+    let mut predictions = 0;
+
+    for command in commands {
+        match command {
+            Command::Load(request, reply) => {
+                let result = load(request).map(|loaded| {
+                    runtime = Some(loaded);
+                    predictions = 0;
+                    proto::LoadResponse {}
+                });
+                let _ = reply.send(result);
+            },
+            Command::Metadata(reply) => {
+                let result = match &runtime {
+                    Some(runtime) => Ok(metadata(runtime)),
+                    None => Err(not_loaded()),
+                };
+                let _ = reply.send(result);
+            },
+            Command::Predict(request, reply) => {
+                let result = match &mut runtime {
+                    Some(runtime) => predict(runtime, request),
+                    None => Err(not_loaded()),
+                };
+
+                if let Ok(response) = &result {
+                    predictions += 1;
+
+                    for output in &response.outputs {
+                        // Note: this only fails when nobody is subscribed
+                        let _ = events.send(proto::OutputEvent {
+                            prediction: predictions,
+                            output: Some(output.clone()),
+                        });
+                    }
+                }
+
+                let _ = reply.send(result);
+            },
+        }
+    }
+}
+
+fn not_loaded() -> Status { Status::failed_precondition("No Rune is loaded") }
+
+fn load(request: proto::LoadRequest) -> Result<Runtime, Status> {
+    let proto::LoadRequest {
+        rune,
+        engine,
+        memory_limit,
+        resources,
+    } = request;
+
+    let mut builder = Runtime::builder().rune(rune);
+
+    if let Some(engine) = parse_engine(engine)? {
+        builder = builder.engine(engine);
+    }
+    if memory_limit > 0 {
+        builder = builder.memory_limit(memory_limit as usize);
+    }
+    for (name, value) in resources {
+        builder = builder.resource(name, value);
+    }
+
+    builder
+        .build()
+        .map_err(|e| Status::invalid_argument(format!("{:#}", e)))
+}
+
+fn parse_engine(engine: i32) -> Result<Option<Engine>, Status> {
+    match proto::Engine::from_i32(engine) {
+        Some(proto::Engine::Default) => Ok(None),
+        #[cfg(feature = "wasm3")]
+        Some(proto::Engine::Wasm3) => Ok(Some(Engine::Wasm3)),
+        #[cfg(feature = "wasmer")]
+        Some(proto::Engine::Wasmer) => Ok(Some(Engine::Wasmer)),
+        _ => Err(Status::unimplemented(format!(
+            "Engine {} isn't supported",
+            engine
+        ))),
+    }
+}
+
+fn metadata(runtime: &Runtime) -> proto::MetadataResponse {
+    let mut models: Vec<_> = runtime
+        .models()
+        .iter()
+        .map(|(&id, model)| proto::Model {
+            id,
+            mimetype: model.mimetype().to_string(),
+            inputs: model.inputs().iter().map(|s| s.to_string()).collect(),
+            outputs: model.outputs().iter().map(|s| s.to_string()).collect(),
+            variant: model.variant().unwrap_or_default().to_string(),
+        })
+        .collect();
+    models.sort_by_key(|m| m.id);
+
+    proto::MetadataResponse {
+        capabilities: nodes(runtime.capabilities()),
+        models,
+        outputs: nodes(runtime.outputs()),
+    }
+}
+
+fn nodes(nodes: &HashMap<u32, NodeMetadata>) -> Vec<proto::Node> {
+    let mut nodes: Vec<_> = nodes
+        .iter()
+        .map(|(&id, meta)| proto::Node {
+            id,
+            kind: meta.kind.clone(),
+            arguments: meta.arguments.clone(),
+        })
+        .collect();
+    nodes.sort_by_key(|n| n.id);
+    nodes
+}
+
+fn predict(
+    runtime: &mut Runtime,
+    request: proto::PredictRequest,
+) -> Result<proto::PredictResponse, Status> {
+    for (id, tensor) in request.inputs {
+        let tensor = tensor_from_proto(tensor).map_err(|e| {
+            Status::invalid_argument(format!(
+                "Invalid input for capability {}: {}",
+                id, e
+            ))
+        })?;
+        runtime.input_tensors().insert(id, tensor);
+    }
+
+    runtime.predict().map_err(|e| {
+        if e.is::<TrapError>() || e.is::<AssertionError>() {
+            Status::aborted(format!("{:#}", e))
+        } else {
+            Status::internal(format!("{:#}", e))
+        }
+    })?;
+
+    let mut outputs: Vec<_> = runtime
+        .output_tensors()
+        .iter()
+        .map(|(&id, tensors)| proto::Output {
+            id,
+            tensors: tensors.iter().map(tensor_to_proto).collect(),
+        })
+        .collect();
+    outputs.sort_by_key(|o| o.id);
+
+    Ok(proto::PredictResponse { outputs })
+}
+
+fn tensor_from_proto(tensor: proto::Tensor) -> Result<Tensor, String> {
+    let element_type = proto::ElementType::from_i32(tensor.element_type)
+        .and_then(element_type_from_proto)
+        .ok_or_else(|| {
+            format!("Unsupported element type, {}", tensor.element_type)
+        })?;
+
+    let dimensions = tensor
+        .dimensions
+        .iter()
+        .map(|&d| NonZeroUsize::new(d as usize))
+        .collect::<Option<Vec<_>>>()
+        .ok_or("All dimensions must be nonzero")?;
+
+    let elements: usize = dimensions.iter().map(|d| d.get()).product();
+
+    if element_type == ElementType::Utf8 {
+        if tensor.strings.len() != elements {
+            return Err(format!(
+                "Expected {} strings but received {}",
+                elements,
+                tensor.strings.len()
+            ));
+        }
+
+        let dimensions: Vec<usize> =
+            dimensions.iter().map(|d| d.get()).collect();
+        return Ok(Tensor::from_strings(&tensor.strings, &dimensions));
+    }
+
+    let expected_length = elements * element_type.byte_size();
+
+    if tensor.buffer.len() != expected_length {
+        return Err(format!(
+            "Expected {} bytes but received {}",
+            expected_length,
+            tensor.buffer.len()
+        ));
+    }
+
+    Ok(Tensor::new_raw(element_type, dimensions, tensor.buffer))
+}
+
+fn tensor_to_proto(tensor: &OutputTensor) -> proto::Tensor {
+    match tensor {
+        OutputTensor::Tensor(t) => match t.strings() {
+            Some(strings) => proto::Tensor {
+                element_type: proto::ElementType::String as i32,
+                dimensions: t
+                    .dimensions()
+                    .iter()
+                    .map(|d| d.get() as u32)
+                    .collect(),
+                buffer: Vec::new(),
+                strings: strings.into_iter().map(String::from).collect(),
+            },
+            None => proto::Tensor {
+                element_type: element_type_to_proto(t.element_type()) as i32,
+                dimensions: t
+                    .dimensions()
+                    .iter()
+                    .map(|d| d.get() as u32)
+                    .collect(),
+                buffer: t.buffer().to_vec(),
+                strings: Vec::new(),
+            },
+        },
+        OutputTensor::StringTensor {
+            dimensions,
+            strings,
+        } => proto::Tensor {
+            element_type: proto::ElementType::String as i32,
+            dimensions: dimensions.iter().map(|&d| d as u32).collect(),
+            buffer: Vec::new(),
+            strings: strings.clone(),
+        },
+    }
+}
+
+fn element_type_from_proto(
+    element_type: proto::ElementType,
+) -> Option<ElementType> {
+    match element_type {
+        proto::ElementType::U8 => Some(ElementType::U8),
+        proto::ElementType::I8 => Some(ElementType::I8),
+        proto::ElementType::U16 => Some(ElementType::U16),
+        proto::ElementType::I16 => Some(ElementType::I16),
+        proto::ElementType::U32 => Some(ElementType::U32),
+        proto::ElementType::I32 => Some(ElementType::I32),
+        proto::ElementType::F32 => Some(ElementType::F32),
+        proto::ElementType::U64 => Some(ElementType::U64),
+        proto::ElementType::I64 => Some(ElementType::I64),
+        proto::ElementType::F64 => Some(ElementType::F64),
+        proto::ElementType::F16 => Some(ElementType::F16),
+        proto::ElementType::Bf16 => Some(ElementType::BF16),
+        proto::ElementType::String => Some(ElementType::Utf8),
+    }
+}
+
+fn element_type_to_proto(element_type: ElementType) -> proto::ElementType {
+    match element_type {
+        ElementType::U8 => proto::ElementType::U8,
+        ElementType::I8 => proto::ElementType::I8,
+        ElementType::U16 => proto::ElementType::U16,
+        ElementType::I16 => proto::ElementType::I16,
+        ElementType::U32 => proto::ElementType::U32,
+        ElementType::I32 => proto::ElementType::I32,
+        ElementType::F32 => proto::ElementType::F32,
+        ElementType::U64 => proto::ElementType::U64,
+        ElementType::I64 => proto::ElementType::I64,
+        ElementType::F64 => proto::ElementType::F64,
+        ElementType::F16 => proto::ElementType::F16,
+        ElementType::Utf8 => proto::ElementType::String,
+        ElementType::BF16 => proto::ElementType::Bf16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_tensors() {
+        let tensor = Tensor::new(&[1.0_f32, 2.0, 3.0, 4.0], &[2, 2]);
+
+        let serialized = tensor_to_proto(&OutputTensor::Tensor(tensor.clone()));
+        let got = tensor_from_proto(serialized).unwrap();
+
+        assert_eq!(got, tensor);
+    }
+
+    #[test]
+    fn string_tensors_use_the_strings_field() {
+        let tensor = Tensor::from_strings(&["cat", "dog"], &[2]);
+
+        let serialized = tensor_to_proto(&OutputTensor::Tensor(tensor.clone()));
+        assert!(serialized.buffer.is_empty());
+        assert_eq!(serialized.strings, vec!["cat", "dog"]);
+        let got = tensor_from_proto(serialized).unwrap();
+
+        assert_eq!(got, tensor);
+    }
+
+    #[test]
+    fn reject_inputs_with_the_wrong_length() {
+        let tensor = proto::Tensor {
+            element_type: proto::ElementType::F32 as i32,
+            dimensions: vec![2],
+            buffer: vec![0; 3],
+            strings: Vec::new(),
+        };
+
+        let err = tensor_from_proto(tensor).unwrap_err();
+
+        assert_eq!(err, "Expected 8 bytes but received 3");
+    }
+
+    /// A Rune which reads a single-digit `u8` from a `RAW` capability and
+    /// writes it to a `SERIAL` output.
+    const ECHO_DIGIT: &str = r#"(module
+        (import "env" "request_capability"
+            (func $request_capability (param i32) (result i32)))
+        (import "env" "request_provider_response"
+            (func $read_input (param i32 i32 i32) (result i32)))
+        (import "env" "request_output"
+            (func $request_output (param i32) (result i32)))
+        (import "env" "consume_output"
+            (func $write_output (param i32 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (global $capability (mut i32) (i32.const 0))
+        (global $output (mut i32) (i32.const 0))
+        (data (i32.const 0) "{\"type_name\":\"u8\",\"elements\":[0],\"dimensions\":[1]}")
+        (func (export "_manifest") (result i32)
+            (global.set $capability (call $request_capability (i32.const 5)))
+            (global.set $output (call $request_output (i32.const 1)))
+            i32.const 1)
+        (func (export "_call") (param i32 i32 i32) (result i32)
+            (drop (call $read_input
+                (i32.const 256) (i32.const 1) (global.get $capability)))
+            (i32.store8 (i32.const 30)
+                (i32.add (i32.load8_u (i32.const 256)) (i32.const 48)))
+            (drop (call $write_output
+                (global.get $output) (i32.const 0) (i32.const 50)))
+            i32.const 0))"#;
+
+    #[tokio::test]
+    async fn predict_over_grpc() {
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(RuntimeService::new().into_server())
+                .serve_with_incoming(
+                    tokio_stream::wrappers::TcpListenerStream::new(listener),
+                ),
+        );
+        let mut client = proto::runtime_client::RuntimeClient::connect(
+            format!("http://{}", address),
+        )
+        .await
+        .unwrap();
+
+        client
+            .load(proto::LoadRequest {
+                rune: wat::parse_str(ECHO_DIGIT).unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let metadata = client
+            .metadata(proto::MetadataRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        let capability = metadata.capabilities[0].id;
+        let output = metadata.outputs[0].id;
+        let input = Tensor::new(&[7_u8], &[1]);
+        let response = client
+            .predict(proto::PredictRequest {
+                inputs: vec![(
+                    capability,
+                    tensor_to_proto(&OutputTensor::Tensor(input.clone())),
+                )]
+                .into_iter()
+                .collect(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(response.outputs.len(), 1);
+        assert_eq!(response.outputs[0].id, output);
+        let tensors = response.outputs[0].tensors.clone();
+        let got: Vec<Tensor> = tensors
+            .into_iter()
+            .map(|t| tensor_from_proto(t).unwrap())
+            .collect();
+        assert_eq!(got, vec![input]);
+    }
+}
@@ -0,0 +1,204 @@
+//! Inference sessions with a limited time or energy budget.
+//!
+//! Duty-cycled devices often have a fixed power allotment for their ML
+//! workload. A [`Session`] created with [`crate::Runtime::session()`] keeps
+//! track of how much time (and therefore energy) has been spent running the
+//! Rune and refuses to run it again once its [`Budget`] would be exceeded.
+//!
+//! Because a prediction can't be interrupted part way through, the session is
+//! conservative and won't start a prediction unless there is room for it to
+//! take as long as the slowest prediction seen so far.
+
+use std::{
+    ops::{Deref, DerefMut},
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+
+use crate::Runtime;
+
+/// The resources a [`Session`] may consume.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Budget {
+    /// The maximum amount of time that may be spent running the Rune.
+    pub time: Option<Duration>,
+    /// The maximum amount of energy that may be used, in joules.
+    pub energy: Option<f64>,
+    /// The device's estimated power draw while running the Rune, in watts.
+    ///
+    /// This is used to convert inference time into energy.
+    pub power: f64,
+}
+
+impl Budget {
+    /// A [`Budget`] which only limits the total inference time.
+    pub fn time(limit: Duration) -> Self {
+        Budget {
+            time: Some(limit),
+            ..Default::default()
+        }
+    }
+
+    /// A [`Budget`] which limits the total energy used, given how much power
+    /// the device draws while running the Rune.
+    pub fn energy(joules: f64, watts: f64) -> Self {
+        Budget {
+            energy: Some(joules),
+            power: watts,
+            ..Default::default()
+        }
+    }
+
+    /// Would running another prediction taking `duration` stay within this
+    /// [`Budget`]?
+    fn allows(&self, usage: &Usage, duration: Duration) -> bool {
+        let elapsed = usage.elapsed + duration;
+
+        if let Some(limit) = self.time {
+            if elapsed > limit {
+                return false;
+            }
+        }
+
+        if let Some(limit) = self.energy {
+            if elapsed.as_secs_f64() * self.power > limit {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// The resources consumed by a [`Session`] so far.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Usage {
+    /// The number of times the Rune was run.
+    pub predictions: usize,
+    /// The total time spent running the Rune.
+    pub elapsed: Duration,
+    /// The estimated amount of energy used, in joules.
+    pub energy: f64,
+    /// The longest a single prediction has taken.
+    pub slowest: Duration,
+}
+
+impl Usage {
+    fn record(&mut self, duration: Duration, budget: &Budget) {
+        self.predictions += 1;
+        self.elapsed += duration;
+        self.energy = self.elapsed.as_secs_f64() * budget.power;
+        self.slowest = self.slowest.max(duration);
+    }
+}
+
+/// The error returned when a [`Session`] has used up its [`Budget`].
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error(
+    "The session's budget has been exhausted after {} predictions ({:?})",
+    usage.predictions,
+    usage.elapsed
+)]
+pub struct BudgetExhausted {
+    pub usage: Usage,
+}
+
+/// A scope in which the [`Runtime`] may only run for a limited time.
+///
+/// The [`Session`] dereferences to the [`Runtime`] so inputs and outputs can
+/// be accessed as normal.
+pub struct Session<'rt> {
+    runtime: &'rt mut Runtime,
+    budget: Budget,
+    usage: Usage,
+}
+
+impl<'rt> Session<'rt> {
+    pub(crate) fn new(runtime: &'rt mut Runtime, budget: Budget) -> Self {
+        Session {
+            runtime,
+            budget,
+            usage: Usage::default(),
+        }
+    }
+
+    /// Run the Rune, failing with [`BudgetExhausted`] if doing so could
+    /// exceed the session's [`Budget`].
+    pub fn predict(&mut self) -> Result<(), Error> {
+        if self.is_exhausted() {
+            return Err(BudgetExhausted { usage: self.usage }.into());
+        }
+
+        let start = Instant::now();
+        let result = self.runtime.predict();
+        self.usage.record(start.elapsed(), &self.budget);
+
+        result
+    }
+
+    /// Has the [`Budget`] been used up?
+    pub fn is_exhausted(&self) -> bool {
+        !self.budget.allows(&self.usage, self.usage.slowest)
+    }
+
+    pub fn budget(&self) -> Budget { self.budget }
+
+    pub fn usage(&self) -> Usage { self.usage }
+}
+
+impl<'rt> Deref for Session<'rt> {
+    type Target = Runtime;
+
+    fn deref(&self) -> &Self::Target { self.runtime }
+}
+
+impl<'rt> DerefMut for Session<'rt> {
+    fn deref_mut(&mut self) -> &mut Self::Target { self.runtime }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_budget() {
+        let budget = Budget::time(Duration::from_millis(100));
+        let mut usage = Usage::default();
+
+        usage.record(Duration::from_millis(30), &budget);
+        assert!(budget.allows(&usage, usage.slowest));
+        usage.record(Duration::from_millis(40), &budget);
+
+        // Another 40ms prediction would take us to 110ms
+        assert!(!budget.allows(&usage, usage.slowest));
+        assert_eq!(usage.predictions, 2);
+    }
+
+    #[test]
+    fn energy_budget() {
+        // 1 joule at 2 watts is 500ms of inference
+        let budget = Budget::energy(1.0, 2.0);
+        let mut usage = Usage::default();
+
+        for _ in 0..4 {
+            assert!(budget.allows(&usage, usage.slowest));
+            usage.record(Duration::from_millis(100), &budget);
+        }
+
+        assert!(budget.allows(&usage, usage.slowest));
+        usage.record(Duration::from_millis(100), &budget);
+        assert!(!budget.allows(&usage, usage.slowest));
+        assert!((usage.energy - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unlimited_budget() {
+        let budget = Budget::default();
+        let mut usage = Usage::default();
+
+        usage.record(Duration::from_secs(3600), &budget);
+
+        assert!(budget.allows(&usage, usage.slowest));
+    }
+}
@@ -0,0 +1,286 @@
+//! Delivering outputs to external systems and finding out whether it worked.
+//!
+//! A [`Sink`] is handed everything written to an output after each run and
+//! replies with a [`Delivery`] saying whether the output was accepted. Failed
+//! deliveries which might succeed later (e.g. a network timeout) are retried
+//! according to the sink's [`RetryPolicy`], and the final outcome for every
+//! sink is recorded in a [`DeliveryReport`] so the host can see when outputs
+//! aren't reaching their destination.
+//!
+//! ```rust,no_run
+//! # fn upload(_: &[hotg_rune_runtime::OutputTensor]) -> std::io::Result<()> { Ok(()) }
+//! # fn main() -> Result<(), anyhow::Error> {
+//! # let mut runtime: hotg_rune_runtime::Runtime = unimplemented!();
+//! use hotg_rune_runtime::sink::{Delivery, DeliveryStatus, RetryPolicy};
+//!
+//! runtime.add_sink(
+//!     1,
+//!     |outputs: &[_]| match upload(outputs) {
+//!         Ok(_) => Delivery::Delivered,
+//!         Err(e) => Delivery::Retryable(e.into()),
+//!     },
+//!     RetryPolicy::default(),
+//! )?;
+//! runtime.on_delivery(|report| {
+//!     if let DeliveryStatus::Failed(e) = &report.status {
+//!         eprintln!("Output {} was lost: {}", report.output_id, e);
+//!     }
+//! });
+//!
+//! runtime.predict()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use anyhow::Error;
+
+use crate::{log_filter::debug, OutputTensor};
+
+/// What happened when an output was handed to a [`Sink`].
+#[derive(Debug)]
+pub enum Delivery {
+    /// The output was accepted.
+    Delivered,
+    /// The output wasn't accepted, but trying again might work.
+    Retryable(Error),
+    /// The output was rejected and trying again won't help.
+    Fatal(Error),
+}
+
+/// Something which outputs can be sent to.
+pub trait Sink: Send {
+    /// Deliver the tensors written to an output during a single run.
+    fn deliver(&mut self, outputs: &[OutputTensor]) -> Delivery;
+}
+
+impl<F> Sink for F
+where
+    F: FnMut(&[OutputTensor]) -> Delivery + Send,
+{
+    fn deliver(&mut self, outputs: &[OutputTensor]) -> Delivery {
+        self(outputs)
+    }
+}
+
+/// How many times, and how often, a [`Delivery::Retryable`] failure should be
+/// retried.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of times [`Sink::deliver()`] will be called for a
+    /// single output, including the first attempt.
+    pub max_attempts: u32,
+    /// How long to wait before the first retry.
+    pub initial_backoff: Duration,
+    /// The backoff is multiplied by this much after every retry.
+    pub backoff_multiplier: f32,
+    /// The longest the backoff is allowed to grow.
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Give up after the first failure.
+    pub const fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::from_secs(0),
+            backoff_multiplier: 1.0,
+            max_backoff: Duration::from_secs(0),
+        }
+    }
+
+    /// How long to wait after a failed attempt (starting from `1`), never
+    /// exceeding [`RetryPolicy::max_backoff`].
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let multiplier = f64::from(self.backoff_multiplier).max(1.0);
+        let nanos =
+            self.initial_backoff.as_nanos() as f64 * multiplier.powi(exponent);
+
+        // Note: this also catches infinity and NaN
+        if !(nanos < self.max_backoff.as_nanos() as f64) {
+            return self.max_backoff;
+        }
+
+        Duration::from_nanos(nanos.round() as u64)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            backoff_multiplier: 2.0,
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The final outcome of delivering an output to a [`Sink`].
+#[derive(Debug)]
+pub enum DeliveryStatus {
+    Delivered,
+    /// Every attempt failed with [`Delivery::Retryable`] and the
+    /// [`RetryPolicy`] was exhausted, or the sink returned
+    /// [`Delivery::Fatal`]. Contains the last error.
+    Failed(Error),
+}
+
+/// The result of delivering one run's output to a [`Sink`].
+#[derive(Debug)]
+pub struct DeliveryReport {
+    pub output_id: u32,
+    /// The number of times [`Sink::deliver()`] was called.
+    pub attempts: u32,
+    pub status: DeliveryStatus,
+}
+
+impl DeliveryReport {
+    pub fn is_delivered(&self) -> bool {
+        matches!(self.status, DeliveryStatus::Delivered)
+    }
+}
+
+/// A [`Sink`] registered with a [`crate::Runtime`].
+pub(crate) struct SinkEntry {
+    pub(crate) output_id: u32,
+    sink: Box<dyn Sink>,
+    policy: RetryPolicy,
+}
+
+impl SinkEntry {
+    pub(crate) fn new(
+        output_id: u32,
+        sink: Box<dyn Sink>,
+        policy: RetryPolicy,
+    ) -> Self {
+        SinkEntry {
+            output_id,
+            sink,
+            policy,
+        }
+    }
+
+    /// Try to deliver the outputs, retrying according to the
+    /// [`RetryPolicy`] and using `sleep` to wait between attempts.
+    ///
+    /// The [`crate::Runtime`] passes [`std::thread::sleep()`], so retries
+    /// block the thread doing the delivery until the backoff has elapsed.
+    pub(crate) fn deliver(
+        &mut self,
+        outputs: &[OutputTensor],
+        mut sleep: impl FnMut(Duration),
+    ) -> DeliveryReport {
+        let max_attempts = self.policy.max_attempts.max(1);
+        let mut attempts = 0;
+
+        let status = loop {
+            attempts += 1;
+
+            match self.sink.deliver(outputs) {
+                Delivery::Delivered => break DeliveryStatus::Delivered,
+                Delivery::Fatal(e) => break DeliveryStatus::Failed(e),
+                Delivery::Retryable(e) if attempts >= max_attempts => {
+                    break DeliveryStatus::Failed(e)
+                },
+                Delivery::Retryable(e) => {
+                    debug!(
+                        "Delivering output {} failed (attempt {}/{}): {}",
+                        self.output_id, attempts, max_attempts, e
+                    );
+                    sleep(self.policy.backoff(attempts));
+                },
+            }
+        };
+
+        DeliveryReport {
+            output_id: self.output_id,
+            attempts,
+            status,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_with_exponential_backoff_until_delivered() {
+        let mut failures = 2;
+        let sink = move |_: &[OutputTensor]| {
+            if failures > 0 {
+                failures -= 1;
+                Delivery::Retryable(Error::msg("Timed out"))
+            } else {
+                Delivery::Delivered
+            }
+        };
+        let mut entry =
+            SinkEntry::new(1, Box::new(sink), RetryPolicy::default());
+        let mut sleeps = Vec::new();
+
+        let report = entry.deliver(&[], |d| sleeps.push(d));
+
+        assert!(report.is_delivered());
+        assert_eq!(report.attempts, 3);
+        assert_eq!(
+            sleeps,
+            vec![Duration::from_millis(100), Duration::from_millis(200)]
+        );
+    }
+
+    #[test]
+    fn fatal_errors_and_exhausted_retries_are_reported() {
+        let fatal = |_: &[OutputTensor]| Delivery::Fatal(Error::msg("Denied"));
+        let mut entry =
+            SinkEntry::new(1, Box::new(fatal), RetryPolicy::default());
+
+        let report = entry.deliver(&[], |_| unreachable!());
+
+        assert_eq!(report.attempts, 1);
+        match report.status {
+            DeliveryStatus::Failed(e) => assert_eq!(e.to_string(), "Denied"),
+            other => panic!("Unexpected status: {:?}", other),
+        }
+
+        let flaky =
+            |_: &[OutputTensor]| Delivery::Retryable(Error::msg("Timed out"));
+        let mut entry = SinkEntry::new(
+            2,
+            Box::new(flaky),
+            RetryPolicy {
+                max_attempts: 4,
+                ..RetryPolicy::none()
+            },
+        );
+
+        let report = entry.deliver(&[], |_| {});
+
+        assert_eq!(report.attempts, 4);
+        assert!(!report.is_delivered());
+    }
+
+    #[test]
+    fn backoff_is_capped_even_for_huge_attempt_counts() {
+        let policy = RetryPolicy {
+            max_attempts: u32::MAX,
+            initial_backoff: Duration::from_millis(100),
+            backoff_multiplier: 2.0,
+            max_backoff: Duration::from_secs(1),
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_secs(1));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+
+        let policy = RetryPolicy {
+            backoff_multiplier: f32::INFINITY,
+            ..policy
+        };
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,582 @@
+//! Running a Rune repeatedly over a continuous stream of samples.
+//!
+//! Rather than making the host work out when to call
+//! [`crate::Runtime::predict()`], a capability can be given a
+//! [`SlidingWindow`] with [`crate::Runtime::stream_capability()`]. Samples are
+//! then pushed in as they arrive using [`crate::Runtime::push_samples()`] and
+//! the runtime will run the pipeline every time another hop's worth of
+//! samples is available, passing in the most recent window.
+//!
+//! When samples arrive on another thread (e.g. from a microphone callback),
+//! attach a [`crate::StreamingCapability`] such as
+//! [`crate::builtins::audio_stream()`] with
+//! [`crate::Runtime::attach_stream()`] and call
+//! [`crate::Runtime::poll_streams()`] from the thread which owns the Rune.
+//!
+//! For example, a 1 second window with a 250 ms hop means each run sees 750 ms
+//! of the previous run's audio plus 250 ms of new audio. A `SOUND` capability
+//! can take its window from the Runefile instead, using
+//! [`crate::Runtime::stream_sound()`] and a `hop_length_ms` argument. Setting
+//! `stream: true` means the runtime does this as soon as the Rune is loaded,
+//! so the host only needs to push in samples:
+//!
+//! ```yaml
+//! capabilities:
+//!   audio:
+//!     capability: SOUND
+//!     outputs:
+//!       - type: i16
+//!         dimensions: [1, 16000]
+//!     args:
+//!       hz: 16000
+//!       sample_duration_ms: 1000
+//!       hop_length_ms: 250
+//!       stream: true
+//! ```
+//!
+//! Use [`crate::Runtime::push_samples_with()`] to be handed the outputs from
+//! every run a batch of samples triggers, or
+//! [`crate::async_runtime::AsyncRuntime::push_samples_async()`] to do the
+//! same from async code (requires the `async` feature).
+//!
+//! Hosts which do their own windowing can use a [`PredictionStream`] from
+//! [`crate::Runtime::predict_stream()`] instead, pushing in each window and
+//! getting back just the outputs that run produced.
+//!
+//! In both cases the Rune is only initialized once, so any state kept by its
+//! proc-blocks and models carries over from one run to the next.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    num::NonZeroUsize,
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+
+use anyhow::{Context, Error};
+
+use crate::{
+    ElementType, OutputTensor, Runtime, RuntimeError, Tensor, TensorElement,
+};
+
+/// How a capability's input should be split into overlapping windows.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SlidingWindow {
+    /// The number of samples passed to the Rune on each run.
+    pub window: usize,
+    /// The number of new samples required before the Rune runs again.
+    pub hop: usize,
+}
+
+impl SlidingWindow {
+    pub fn new(window: usize, hop: usize) -> Self {
+        SlidingWindow { window, hop }
+    }
+
+    /// Create a [`SlidingWindow`] from durations, given the stream's sample
+    /// rate (in Hz).
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use hotg_rune_runtime::streaming::SlidingWindow;
+    ///
+    /// let window = SlidingWindow::from_durations(
+    ///     16_000,
+    ///     Duration::from_secs(1),
+    ///     Duration::from_millis(250),
+    /// );
+    ///
+    /// assert_eq!(window, SlidingWindow::new(16_000, 4_000));
+    /// ```
+    pub fn from_durations(
+        sample_rate: u32,
+        window: Duration,
+        hop: Duration,
+    ) -> Self {
+        let samples =
+            |d: Duration| (d.as_secs_f64() * sample_rate as f64).round();
+
+        SlidingWindow::new(samples(window) as usize, samples(hop) as usize)
+    }
+
+    /// Work out the window for a `SOUND` capability from its `hz`,
+    /// `sample_duration_ms`, and optional `hop_length_ms` arguments.
+    ///
+    /// Without a `hop_length_ms`, consecutive windows don't overlap.
+    pub fn for_sound(
+        arguments: &HashMap<String, String>,
+    ) -> Result<Self, Error> {
+        fn parse<T: std::str::FromStr>(
+            arguments: &HashMap<String, String>,
+            name: &str,
+        ) -> Result<Option<T>, Error>
+        where
+            T::Err: std::error::Error + Send + Sync + 'static,
+        {
+            arguments
+                .get(name)
+                .map(|value| {
+                    value.trim().parse().with_context(|| {
+                        format!("Unable to parse \"{}\" as an integer", name)
+                    })
+                })
+                .transpose()
+        }
+
+        let sample_rate: u32 = parse(arguments, "hz")?
+            .context("The \"hz\" argument is missing")?;
+        let window = parse(arguments, "sample_duration_ms")?
+            .context("The \"sample_duration_ms\" argument is missing")?;
+        let hop = parse(arguments, "hop_length_ms")?.unwrap_or(window);
+
+        let config = SlidingWindow::from_durations(
+            sample_rate,
+            Duration::from_millis(window),
+            Duration::from_millis(hop),
+        );
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Whether a capability's Runefile arguments ask for it to be streamed
+    /// (i.e. `stream: true`).
+    ///
+    /// The compiler passes booleans to the runtime as `1` or `0`.
+    pub(crate) fn is_requested(arguments: &HashMap<String, String>) -> bool {
+        arguments
+            .get("stream")
+            .map(|value| {
+                let value = value.trim();
+                value == "1" || value.eq_ignore_ascii_case("true")
+            })
+            .unwrap_or(false)
+    }
+
+    /// How much consecutive windows overlap, as a number of samples.
+    pub fn overlap(&self) -> usize { self.window.saturating_sub(self.hop) }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.window == 0 {
+            anyhow::bail!("The window must contain at least one sample");
+        }
+        if self.hop == 0 {
+            anyhow::bail!("The hop must be at least one sample");
+        }
+
+        Ok(())
+    }
+}
+
+/// The runtime's bookkeeping for a capability which is being streamed.
+#[derive(Debug)]
+pub(crate) struct StreamingInput {
+    config: SlidingWindow,
+    element_type: ElementType,
+    dimensions: Vec<NonZeroUsize>,
+    /// The most recent samples, stored as raw bytes.
+    buffer: VecDeque<u8>,
+    /// Bytes from the end of the last push which didn't make up a whole
+    /// sample.
+    partial: Vec<u8>,
+    /// How many more samples are needed before the next window is ready.
+    until_next_window: usize,
+}
+
+impl StreamingInput {
+    /// Create a [`StreamingInput`] where each window is passed to the Rune as a
+    /// tensor with the provided `dimensions`.
+    ///
+    /// The `dimensions` must contain exactly `config.window` elements.
+    pub(crate) fn new(
+        config: SlidingWindow,
+        element_type: ElementType,
+        dimensions: &[usize],
+    ) -> Result<Self, Error> {
+        config.validate()?;
+
+        if element_type == ElementType::Utf8 {
+            anyhow::bail!("Strings can't be streamed into a Rune");
+        }
+
+        let num_elements: usize = dimensions.iter().product();
+        if num_elements != config.window {
+            anyhow::bail!(
+                "A window of {} samples can't be passed to the Rune as a \
+                 tensor with dimensions {:?}",
+                config.window,
+                dimensions
+            );
+        }
+
+        let dimensions = dimensions
+            .iter()
+            .map(|&d| NonZeroUsize::new(d))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::msg("All dimensions must be nonzero"))?;
+
+        Ok(StreamingInput {
+            config,
+            element_type,
+            dimensions,
+            buffer: VecDeque::with_capacity(
+                config.window * element_type.byte_size(),
+            ),
+            partial: Vec::new(),
+            until_next_window: config.window,
+        })
+    }
+
+    pub(crate) fn element_type(&self) -> ElementType { self.element_type }
+
+    /// How many more samples need to be pushed before the next window is
+    /// passed to the Rune.
+    pub(crate) fn until_next_window(&self) -> usize { self.until_next_window }
+
+    /// Throw away any buffered samples, so the next window is made entirely
+    /// of new samples.
+    pub(crate) fn reset(&mut self) {
+        self.buffer.clear();
+        self.partial.clear();
+        self.until_next_window = self.config.window;
+    }
+
+    /// Add some samples to the stream, returning a tensor for each window
+    /// that was completed.
+    pub(crate) fn push<T: TensorElement>(
+        &mut self,
+        samples: &[T],
+    ) -> Vec<Tensor> {
+        debug_assert_eq!(T::ELEMENT_TYPE, self.element_type);
+
+        self.push_bytes(T::to_bytes(samples))
+    }
+
+    /// Add some samples that have already been encoded as little-endian
+    /// bytes, returning a tensor for each window that was completed.
+    ///
+    /// Any trailing bytes which don't make up a whole sample are kept and
+    /// joined onto the start of the next push.
+    pub(crate) fn push_bytes(&mut self, samples: &[u8]) -> Vec<Tensor> {
+        let sample_size = self.element_type.byte_size();
+        let window_bytes = self.config.window * sample_size;
+        let joined;
+        let mut remaining = if self.partial.is_empty() {
+            samples
+        } else {
+            self.partial.extend_from_slice(samples);
+            joined = std::mem::take(&mut self.partial);
+            &joined[..]
+        };
+        let mut windows = Vec::new();
+
+        while remaining.len() >= sample_size {
+            let available = remaining.len() / sample_size;
+            let take = available.min(self.until_next_window);
+            let (head, tail) = remaining.split_at(take * sample_size);
+            remaining = tail;
+
+            self.buffer.extend(head);
+            self.until_next_window -= take;
+
+            // Note: when the hop is larger than the window this also
+            // throws away samples which never make it into a window.
+            let excess = self.buffer.len().saturating_sub(window_bytes);
+            self.buffer.drain(..excess);
+
+            if self.until_next_window == 0 {
+                let buffer: Vec<u8> = self.buffer.iter().copied().collect();
+                windows.push(Tensor::new_raw(
+                    self.element_type,
+                    self.dimensions.clone(),
+                    buffer,
+                ));
+                self.until_next_window = self.config.hop;
+            }
+        }
+
+        self.partial.extend_from_slice(remaining);
+
+        windows
+    }
+}
+
+/// Run a [`Runtime`] over a sequence of input windows, keeping the Rune's
+/// state between runs.
+///
+/// The [`PredictionStream`] dereferences to the [`Runtime`] so it can still
+/// be configured as normal.
+pub struct PredictionStream<'rt> {
+    runtime: &'rt mut Runtime,
+    runs: usize,
+}
+
+impl<'rt> PredictionStream<'rt> {
+    pub(crate) fn new(runtime: &'rt mut Runtime) -> Self {
+        PredictionStream { runtime, runs: 0 }
+    }
+
+    /// Set the input tensors for the next window, keyed by capability ID,
+    /// then run the Rune.
+    ///
+    /// Capabilities which aren't mentioned keep their previous input, and
+    /// anything pushed takes precedence over a capability registered with
+    /// [`Runtime::register_capability()`]. Only the outputs which were written
+    /// to during this run are returned.
+    pub fn push<I>(
+        &mut self,
+        inputs: I,
+    ) -> Result<HashMap<u32, Vec<OutputTensor>>, Error>
+    where
+        I: IntoIterator<Item = (u32, Tensor)>,
+    {
+        let mut provided = HashMap::new();
+
+        for (capability_id, tensor) in inputs {
+            if !self.runtime.capabilities().contains_key(&capability_id) {
+                return Err(RuntimeError::UnknownCapability {
+                    id: capability_id,
+                }
+                .into());
+            }
+
+            provided.insert(capability_id, tensor);
+        }
+
+        self.runtime.predict_with_inputs(provided)?;
+        self.runs += 1;
+
+        Ok(self.runtime.updated_output_tensors())
+    }
+
+    /// The number of windows that have been run so far.
+    pub fn runs(&self) -> usize { self.runs }
+}
+
+impl<'rt> Deref for PredictionStream<'rt> {
+    type Target = Runtime;
+
+    fn deref(&self) -> &Self::Target { self.runtime }
+}
+
+impl<'rt> DerefMut for PredictionStream<'rt> {
+    fn deref_mut(&mut self) -> &mut Self::Target { self.runtime }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows(input: &mut StreamingInput, samples: &[i16]) -> Vec<Vec<i16>> {
+        input
+            .push(samples)
+            .into_iter()
+            .map(|t| t.elements::<i16>().unwrap().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_windows() {
+        let config = SlidingWindow::new(4, 2);
+        let mut input =
+            StreamingInput::new(config, ElementType::I16, &[1, 4]).unwrap();
+
+        assert!(windows(&mut input, &[1, 2, 3]).is_empty());
+        assert_eq!(windows(&mut input, &[4]), vec![vec![1, 2, 3, 4]]);
+        assert_eq!(
+            windows(&mut input, &[5, 6, 7, 8, 9]),
+            vec![vec![3, 4, 5, 6], vec![5, 6, 7, 8]]
+        );
+        assert_eq!(windows(&mut input, &[10]), vec![vec![7, 8, 9, 10]]);
+    }
+
+    #[test]
+    fn hop_larger_than_the_window_skips_samples() {
+        let config = SlidingWindow::new(2, 3);
+        let mut input =
+            StreamingInput::new(config, ElementType::I16, &[2]).unwrap();
+
+        assert_eq!(
+            windows(&mut input, &[1, 2, 3, 4, 5, 6, 7, 8]),
+            vec![vec![1, 2], vec![4, 5], vec![7, 8]]
+        );
+    }
+
+    #[test]
+    fn partial_samples_are_carried_over_to_the_next_push() {
+        let config = SlidingWindow::new(2, 2);
+        let mut input =
+            StreamingInput::new(config, ElementType::I16, &[2]).unwrap();
+
+        let windows = input.push_bytes(&[1, 0, 2, 0, 3]);
+
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].elements::<i16>().unwrap(), &[1, 2]);
+
+        // The low byte of 3 was left over from the last push
+        assert!(input.push_bytes(&[]).is_empty());
+        assert!(input.push_bytes(&[0, 4]).is_empty());
+        let windows = input.push_bytes(&[0]);
+
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].elements::<i16>().unwrap(), &[3, 4]);
+    }
+
+    #[test]
+    fn resetting_starts_a_fresh_window() {
+        let config = SlidingWindow::new(4, 2);
+        let mut input =
+            StreamingInput::new(config, ElementType::I16, &[4]).unwrap();
+        assert_eq!(windows(&mut input, &[1, 2, 3, 4, 5]).len(), 1);
+        assert_eq!(input.until_next_window(), 1);
+
+        input.reset();
+
+        assert_eq!(input.until_next_window(), 4);
+        assert!(windows(&mut input, &[6, 7, 8]).is_empty());
+        assert_eq!(windows(&mut input, &[9]), vec![vec![6, 7, 8, 9]]);
+    }
+
+    #[test]
+    fn sound_windows_come_from_the_capability_arguments() {
+        let mut args: HashMap<String, String> = [
+            ("hz", "16000"),
+            ("sample_duration_ms", "1000"),
+            ("hop_length_ms", "250"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let config = SlidingWindow::for_sound(&args).unwrap();
+        assert_eq!(config, SlidingWindow::new(16_000, 4_000));
+
+        args.remove("hop_length_ms");
+        let config = SlidingWindow::for_sound(&args).unwrap();
+        assert_eq!(config.overlap(), 0);
+
+        assert!(!SlidingWindow::is_requested(&args));
+        args.insert("stream".to_string(), "true".to_string());
+        assert!(SlidingWindow::is_requested(&args));
+        args.insert("stream".to_string(), "1".to_string());
+        assert!(SlidingWindow::is_requested(&args));
+        args.insert("stream".to_string(), "0".to_string());
+        assert!(!SlidingWindow::is_requested(&args));
+
+        args.remove("hz");
+        assert!(SlidingWindow::for_sound(&args).is_err());
+    }
+
+    #[test]
+    fn dimensions_must_match_the_window() {
+        let config = SlidingWindow::new(16, 4);
+
+        assert!(
+            StreamingInput::new(config, ElementType::F32, &[1, 15]).is_err()
+        );
+        assert!(StreamingInput::new(config, ElementType::F32, &[1, 16]).is_ok());
+    }
+
+    /// A pipeline which forwards `value` to `always`, and to `sometimes` when
+    /// `flag` is set.
+    fn echo() -> Runtime {
+        let graph = serde_json::json!({
+            "capabilities": {
+                "value": {
+                    "kind": { "type": "raw" },
+                    "args": { "name": "value" },
+                    "outputs": ["0"],
+                },
+                "flag": {
+                    "kind": { "type": "raw" },
+                    "args": { "name": "flag" },
+                    "outputs": ["1"],
+                },
+            },
+            "outputs": {
+                "always": {
+                    "kind": { "type": "serial" },
+                    "args": {},
+                    "inputs": ["0"],
+                },
+                "sometimes": {
+                    "kind": { "type": "serial" },
+                    "args": {},
+                    "inputs": ["0"],
+                    "enabled_when": "1",
+                },
+            },
+            "tensors": {
+                "0": { "element_type": "F32", "dimensions": [1] },
+                "1": { "element_type": "U8", "dimensions": [1] },
+            },
+        });
+        let pipeline = crate::native::NativePipeline::from_graph(
+            graph.to_string().as_bytes(),
+        )
+        .unwrap();
+
+        Runtime::builder().native(pipeline).build().unwrap()
+    }
+
+    fn written(outputs: &HashMap<u32, Vec<OutputTensor>>, id: u32) -> f32 {
+        match outputs[&id].last() {
+            Some(OutputTensor::Tensor(t)) => t.elements::<f32>().unwrap()[0],
+            other => panic!("Unexpected output: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn push_only_returns_the_outputs_written_by_that_window() {
+        let mut runtime = echo();
+        let names = runtime.node_names().clone();
+        let (value, flag) = (names["value"], names["flag"]);
+        let (always, sometimes) = (names["always"], names["sometimes"]);
+        let mut stream = runtime.predict_stream();
+
+        let first = stream
+            .push(vec![
+                (value, Tensor::new(&[1.0_f32], &[1])),
+                (flag, Tensor::new(&[1_u8], &[1])),
+            ])
+            .unwrap();
+        let second = stream
+            .push(vec![(flag, Tensor::new(&[0_u8], &[1]))])
+            .unwrap();
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(second.len(), 1);
+        // Capabilities which weren't pushed keep their previous input
+        assert_eq!(written(&second, always), 1.0);
+        assert_eq!(stream.updated_outputs(), &[always]);
+        assert!(stream.output_tensors().contains_key(&sometimes));
+        assert_eq!(stream.runs(), 2);
+        assert!(stream.push(vec![(42, Tensor::new(&[0_u8], &[1]))]).is_err());
+    }
+
+    #[test]
+    fn pushed_inputs_take_precedence_over_registered_capabilities() {
+        let mut runtime = echo();
+        let value = runtime.node_names()["value"];
+        let always = runtime.node_names()["always"];
+        runtime
+            .register_capability("RAW", |args| {
+                let tensor = match args["name"].as_str() {
+                    "flag" => Tensor::new(&[0_u8], &[1]),
+                    _ => Tensor::new(&[7.0_f32], &[1]),
+                };
+                Ok(move || Ok(tensor.clone()))
+            })
+            .unwrap();
+        let mut stream = runtime.predict_stream();
+
+        let pushed = stream
+            .push(vec![(value, Tensor::new(&[1.0_f32], &[1]))])
+            .unwrap();
+        let generated = stream.push(Vec::new()).unwrap();
+
+        assert_eq!(written(&pushed, always), 1.0);
+        assert_eq!(written(&generated, always), 7.0);
+    }
+}
@@ -0,0 +1,118 @@
+//! Emitting [`tracing`] spans for each phase of running a Rune.
+//!
+//! With the `telemetry` feature enabled, the runtime creates spans which any
+//! [`tracing`] subscriber can collect. Runes running inside a server can show
+//! up in its existing OpenTelemetry traces by installing the
+//! [`tracing-opentelemetry`](https://docs.rs/tracing-opentelemetry) layer.
+//!
+//! The following spans are emitted:
+//!
+//! - `rune.load` - loading the Rune and running its initialization code
+//! - `rune.model.load` - initializing one of the Rune's models
+//! - `rune.predict` - a single call to [`crate::Runtime::predict()`]
+//! - `rune.capability` - the Rune reading one of its capabilities
+//! - `rune.model` - running inference with a model
+//! - `rune.output` - the Rune writing to one of its outputs
+//! - `rune.deliver` - sending an output's tensors to a [`crate::Sink`]
+//!
+//! Spans have the following attributes, where applicable:
+//!
+//! - `rune.name` - the Rune's name from its Runefile (`rune.load` only)
+//! - `rune.node.id` - the ID the Rune uses for a capability, model, or output
+//! - `rune.node.kind` - the node's kind (e.g. `RAW`), or a model's mimetype
+//! - `rune.node.name` - the node's name in the Runefile, for Runes which report
+//!   it
+//!
+//! Unlike the [`crate::otel`] module, nothing needs to be passed to the
+//! [`crate::RuntimeBuilder`]. Spans are sent to whichever subscriber is
+//! active on the current thread.
+
+use hotg_rune_core::GRAPH_CUSTOM_SECTION;
+use tracing::Span;
+use wasmparser::{Parser, Payload};
+
+use crate::{instrumentation::Node, profiling::NodeKind, ModelMetadata};
+
+pub(crate) fn load(wasm: &[u8]) -> Span {
+    let name = rune_name(wasm);
+    tracing::info_span!("rune.load", rune.name = name.as_deref())
+}
+
+pub(crate) fn predict() -> Span { tracing::info_span!("rune.predict") }
+
+/// The span for running a capability, model, or output.
+pub(crate) fn node(kind: NodeKind, node: &Node<'_>) -> Span {
+    match kind {
+        NodeKind::Capability => tracing::info_span!(
+            "rune.capability",
+            rune.node.id = node.id,
+            rune.node.kind = node.kind,
+            rune.node.name = node.name,
+        ),
+        NodeKind::Model => tracing::info_span!(
+            "rune.model",
+            rune.node.id = node.id,
+            rune.node.kind = node.kind,
+            rune.node.name = node.name,
+        ),
+        NodeKind::Output => tracing::info_span!(
+            "rune.output",
+            rune.node.id = node.id,
+            rune.node.kind = node.kind,
+            rune.node.name = node.name,
+        ),
+    }
+}
+
+pub(crate) fn deliver(id: u32, kind: Option<&str>) -> Span {
+    tracing::info_span!(
+        "rune.deliver",
+        rune.node.id = id,
+        rune.node.kind = kind
+    )
+}
+
+pub(crate) fn model_load(id: u32, meta: &ModelMetadata<'_>) -> Span {
+    tracing::info_span!(
+        "rune.model.load",
+        rune.node.id = id,
+        rune.node.kind = meta.mimetype,
+        rune.node.name = meta.node,
+    )
+}
+
+/// Read the Rune's name from the `.rune_graph` custom section the compiler
+/// embeds in it.
+fn rune_name(wasm: &[u8]) -> Option<String> {
+    Parser::default()
+        .parse_all(wasm)
+        .find_map(|payload| match payload {
+            Ok(Payload::CustomSection { name, data, .. })
+                if name == GRAPH_CUSTOM_SECTION =>
+            {
+                let graph: serde_json::Value =
+                    serde_json::from_slice(data).ok()?;
+                graph["rune"]["name"].as_str().map(String::from)
+            },
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_the_rune_name() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (@custom ".rune_version" "{\"version\":\"0.11.3\"}")
+                (@custom ".rune_graph" "{\"rune\":{\"name\":\"sine\"}}")
+            )"#,
+        )
+        .unwrap();
+
+        assert_eq!(rune_name(&wasm).as_deref(), Some("sine"));
+        assert_eq!(rune_name(&wat::parse_str("(module)").unwrap()), None);
+    }
+}
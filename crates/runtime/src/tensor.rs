@@ -1,11 +1,12 @@
 use std::{
+    convert::TryInto,
     fmt::{self, Debug, Display, Formatter},
     num::NonZeroUsize,
 };
 
 use serde::ser::{Serialize, SerializeStruct};
 
-/// A n-dimension array of numbers.
+/// A n-dimension array of numbers or strings.
 #[derive(Clone, PartialEq)]
 pub struct Tensor {
     element_type: ElementType,
@@ -19,36 +20,71 @@ impl Tensor {
         E: TensorElement,
     {
         let buffer = E::to_bytes(elements).to_vec();
-        let dimensions = dimensions
-            .iter()
-            .map(|&d| {
-                NonZeroUsize::new(d).expect("All dimensions must be nonzero")
-            })
-            .collect();
 
         Tensor {
             element_type: E::ELEMENT_TYPE,
-            dimensions,
+            dimensions: nonzero_dimensions(dimensions),
             buffer,
         }
     }
 
+    /// Create a tensor of [`ElementType::Utf8`] strings.
+    ///
+    /// Each string is stored in the buffer as a little-endian `u32` length
+    /// followed by its UTF-8 bytes.
+    pub fn from_strings<S>(strings: &[S], dimensions: &[usize]) -> Self
+    where
+        S: AsRef<str>,
+    {
+        let mut buffer = Vec::new();
+
+        for s in strings {
+            let s = s.as_ref();
+            let length: u32 =
+                s.len().try_into().expect("The string is too long");
+            buffer.extend_from_slice(&length.to_le_bytes());
+            buffer.extend_from_slice(s.as_bytes());
+        }
+
+        Tensor::new_raw(
+            ElementType::Utf8,
+            nonzero_dimensions(dimensions),
+            buffer,
+        )
+    }
+
     pub fn new_raw(
         element_type: ElementType,
         dimensions: Vec<NonZeroUsize>,
         buffer: Vec<u8>,
     ) -> Self {
         let num_elements: usize = dimensions.iter().map(|d| d.get()).product();
-        let expected_length = num_elements * element_type.byte_size();
 
-        assert_eq!(
-            expected_length,
-            buffer.len(),
-            "A {} tensor should take up {} bytes, but {} bytes were provided",
-            Shape::new(element_type, &dimensions),
-            expected_length,
-            buffer.len()
-        );
+        if element_type == ElementType::Utf8 {
+            let strings = decode_strings(&buffer)
+                .expect("The buffer should contain length-prefixed strings");
+
+            assert_eq!(
+                num_elements,
+                strings.len(),
+                "A {} tensor should contain {} strings, but {} were provided",
+                Shape::new(element_type, &dimensions),
+                num_elements,
+                strings.len()
+            );
+        } else {
+            let expected_length = num_elements * element_type.byte_size();
+
+            assert_eq!(
+                expected_length,
+                buffer.len(),
+                "A {} tensor should take up {} bytes, but {} bytes were \
+                 provided",
+                Shape::new(element_type, &dimensions),
+                expected_length,
+                buffer.len()
+            );
+        }
 
         Tensor {
             element_type,
@@ -95,6 +131,186 @@ impl Tensor {
 
         E::from_bytes(&self.buffer)
     }
+
+    pub fn elements_mut<E>(&mut self) -> Option<&mut [E]>
+    where
+        E: TensorElement,
+    {
+        if self.element_type != E::ELEMENT_TYPE {
+            return None;
+        }
+
+        E::from_bytes_mut(&mut self.buffer)
+    }
+
+    /// Get the strings in an [`ElementType::Utf8`] tensor.
+    ///
+    /// This returns `None` for any other element type, or if the buffer was
+    /// modified so it no longer contains valid length-prefixed strings.
+    pub fn strings(&self) -> Option<Vec<&str>> {
+        if self.element_type != ElementType::Utf8 {
+            return None;
+        }
+
+        decode_strings(&self.buffer)
+    }
+}
+
+fn nonzero_dimensions(dimensions: &[usize]) -> Vec<NonZeroUsize> {
+    dimensions
+        .iter()
+        .map(|&d| NonZeroUsize::new(d).expect("All dimensions must be nonzero"))
+        .collect()
+}
+
+/// Split a buffer into the length-prefixed strings used by
+/// [`ElementType::Utf8`] tensors.
+pub(crate) fn decode_strings(mut buffer: &[u8]) -> Option<Vec<&str>> {
+    let mut strings = Vec::new();
+
+    while !buffer.is_empty() {
+        if buffer.len() < 4 {
+            return None;
+        }
+
+        let (length, rest) = buffer.split_at(4);
+        let length = u32::from_le_bytes(length.try_into().ok()?) as usize;
+
+        if rest.len() < length {
+            return None;
+        }
+
+        let (s, rest) = rest.split_at(length);
+        strings.push(std::str::from_utf8(s).ok()?);
+        buffer = rest;
+    }
+
+    Some(strings)
+}
+
+impl<E: TensorElement, const N: usize> From<[E; N]> for Tensor {
+    /// Create a 1D tensor.
+    ///
+    /// # Panics
+    ///
+    /// Like [`Tensor::new()`], this will panic if the array is empty.
+    fn from(elements: [E; N]) -> Self { Tensor::new(&elements, &[N]) }
+}
+
+impl<E: TensorElement, const N: usize, const M: usize> From<[[E; N]; M]>
+    for Tensor
+{
+    /// Create a 2D tensor with `M` rows and `N` columns.
+    ///
+    /// # Panics
+    ///
+    /// Like [`Tensor::new()`], this will panic if the array is empty.
+    fn from(rows: [[E; N]; M]) -> Self {
+        let elements: Vec<E> = rows.iter().flatten().copied().collect();
+        Tensor::new(&elements, &[M, N])
+    }
+}
+
+#[cfg(feature = "ndarray")]
+#[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "ndarray")))]
+impl Tensor {
+    /// Copy an [`ndarray`] array into a new [`Tensor`].
+    ///
+    /// # Panics
+    ///
+    /// Like [`Tensor::new()`], this will panic if any of the array's
+    /// dimensions are zero.
+    pub fn from_ndarray<E, S, D>(array: &ndarray::ArrayBase<S, D>) -> Self
+    where
+        E: TensorElement,
+        S: ndarray::Data<Elem = E>,
+        D: ndarray::Dimension,
+    {
+        match array.as_slice() {
+            // The array is already contiguous and in row-major order
+            Some(elements) => Tensor::new(elements, array.shape()),
+            None => {
+                let elements: Vec<E> = array.iter().copied().collect();
+                Tensor::new(&elements, array.shape())
+            },
+        }
+    }
+
+    /// Get a view of this tensor's elements without copying, returning
+    /// `None` if they aren't of type `E`.
+    pub fn view<E>(&self) -> Option<ndarray::ArrayViewD<'_, E>>
+    where
+        E: TensorElement,
+    {
+        let shape = self.ndarray_shape();
+        let elements = self.elements()?;
+
+        ndarray::ArrayViewD::from_shape(shape, elements).ok()
+    }
+
+    /// Get a mutable view of this tensor's elements without copying,
+    /// returning `None` if they aren't of type `E`.
+    pub fn view_mut<E>(&mut self) -> Option<ndarray::ArrayViewMutD<'_, E>>
+    where
+        E: TensorElement,
+    {
+        let shape = self.ndarray_shape();
+        let elements = self.elements_mut()?;
+
+        ndarray::ArrayViewMutD::from_shape(shape, elements).ok()
+    }
+
+    /// Get a view of this tensor's elements with a fixed number of
+    /// dimensions (e.g. `tensor.view_dim::<f32, 3>()` gives an
+    /// [`ndarray::ArrayView3<f32>`]), returning `None` if the elements aren't
+    /// of type `E` or the tensor doesn't have `N` dimensions.
+    pub fn view_dim<E, const N: usize>(
+        &self,
+    ) -> Option<ndarray::ArrayView<'_, E, ndarray::Dim<[usize; N]>>>
+    where
+        E: TensorElement,
+        ndarray::Dim<[usize; N]>: ndarray::Dimension,
+    {
+        self.view()?.into_dimensionality().ok()
+    }
+
+    /// The mutable version of [`Tensor::view_dim()`].
+    pub fn view_dim_mut<E, const N: usize>(
+        &mut self,
+    ) -> Option<ndarray::ArrayViewMut<'_, E, ndarray::Dim<[usize; N]>>>
+    where
+        E: TensorElement,
+        ndarray::Dim<[usize; N]>: ndarray::Dimension,
+    {
+        self.view_mut()?.into_dimensionality().ok()
+    }
+
+    /// Copy this tensor's elements into an owned [`ndarray::ArrayD`],
+    /// returning `None` if they aren't of type `E`.
+    pub fn to_ndarray<E>(&self) -> Option<ndarray::ArrayD<E>>
+    where
+        E: TensorElement,
+    {
+        self.view().map(|view| view.to_owned())
+    }
+
+    fn ndarray_shape(&self) -> ndarray::IxDyn {
+        let dimensions: Vec<usize> =
+            self.dimensions.iter().map(|d| d.get()).collect();
+        ndarray::IxDyn(&dimensions)
+    }
+}
+
+#[cfg(feature = "ndarray")]
+#[cfg_attr(feature = "unstable_doc_cfg", doc(cfg(feature = "ndarray")))]
+impl<E, D> From<ndarray::Array<E, D>> for Tensor
+where
+    E: TensorElement,
+    D: ndarray::Dimension,
+{
+    fn from(array: ndarray::Array<E, D>) -> Self {
+        Tensor::from_ndarray(&array)
+    }
 }
 
 #[derive(Debug)]
@@ -187,6 +403,11 @@ impl Serialize for Serializable<'_> {
             ElementType::U64 => serialize!(ser, self, u64),
             ElementType::I64 => serialize!(ser, self, i64),
             ElementType::F64 => serialize!(ser, self, f64),
+            ElementType::F16 => serialize!(ser, self, half::f16),
+            ElementType::BF16 => serialize!(ser, self, half::bf16),
+            ElementType::Utf8 => {
+                ser.serialize_field("elements", &self.0.strings().unwrap())?;
+            },
         }
 
         ser.end()
@@ -217,9 +438,17 @@ pub enum ElementType {
     U64,
     I64,
     F64,
+    F16,
+    #[serde(rename = "bf16")]
+    BF16,
+    Utf8,
 }
 
 impl ElementType {
+    /// The number of bytes used by each element.
+    ///
+    /// Strings vary in length, so for [`ElementType::Utf8`] this is the size
+    /// of each element's length prefix.
     pub fn byte_size(self) -> usize {
         match self {
             ElementType::U8 => std::mem::size_of::<u8>(),
@@ -232,6 +461,9 @@ impl ElementType {
             ElementType::U64 => std::mem::size_of::<u64>(),
             ElementType::I64 => std::mem::size_of::<i64>(),
             ElementType::F64 => std::mem::size_of::<f64>(),
+            ElementType::F16 => std::mem::size_of::<half::f16>(),
+            ElementType::BF16 => std::mem::size_of::<half::bf16>(),
+            ElementType::Utf8 => std::mem::size_of::<u32>(),
         }
     }
 }
@@ -249,6 +481,9 @@ impl Display for ElementType {
             ElementType::U64 => write!(f, "u64"),
             ElementType::I64 => write!(f, "i64"),
             ElementType::F64 => write!(f, "f64"),
+            ElementType::F16 => write!(f, "f16"),
+            ElementType::BF16 => write!(f, "bf16"),
+            ElementType::Utf8 => write!(f, "utf8"),
         }
     }
 }
@@ -259,6 +494,7 @@ pub trait TensorElement: sealed::Sealed + Copy + 'static {
 
     fn to_bytes(slice: &[Self]) -> &[u8];
     fn from_bytes(bytes: &[u8]) -> Option<&[Self]>;
+    fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut [Self]>;
 }
 
 mod sealed {
@@ -295,6 +531,19 @@ macro_rules! impl_tensor_element {
                     }
                 }
             }
+
+            fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut [Self]> {
+                // Safety: See from_bytes()
+                unsafe {
+                    let (head, elements, tail) = bytes.align_to_mut();
+
+                    if head.is_empty() && tail.is_empty() {
+                        Some(elements)
+                    } else {
+                        None
+                    }
+                }
+            }
         }
 
         impl sealed::Sealed for $type {}
@@ -311,3 +560,91 @@ impl_tensor_element!(f32 => ElementType::F32);
 impl_tensor_element!(u64 => ElementType::U64);
 impl_tensor_element!(i64 => ElementType::I64);
 impl_tensor_element!(f64 => ElementType::F64);
+impl_tensor_element!(half::f16 => ElementType::F16);
+impl_tensor_element!(half::bf16 => ElementType::BF16);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tensors_from_fixed_size_arrays() {
+        let vector = Tensor::from([1.0_f32, 2.0, 3.0]);
+        let matrix = Tensor::from([[1_u8, 2, 3], [4, 5, 6]]);
+
+        assert_eq!(vector, Tensor::new(&[1.0_f32, 2.0, 3.0], &[3]));
+        assert_eq!(matrix, Tensor::new(&[1_u8, 2, 3, 4, 5, 6], &[2, 3]));
+    }
+
+    #[test]
+    fn half_precision_tensors() {
+        let elements = [half::f16::from_f32(1.5), half::f16::from_f32(-2.0)];
+
+        let tensor = Tensor::new(&elements, &[2]);
+
+        assert_eq!(tensor.element_type(), ElementType::F16);
+        assert_eq!(tensor.buffer().len(), 4);
+        assert_eq!(tensor.elements::<half::f16>().unwrap(), &elements);
+        assert_eq!(tensor.shape().to_string(), "f16[2]");
+
+        let elements = [half::bf16::from_f32(1.5), half::bf16::from_f32(-2.0)];
+
+        let tensor = Tensor::new(&elements, &[2]);
+
+        assert_eq!(tensor.element_type(), ElementType::BF16);
+        assert_eq!(tensor.elements::<half::bf16>().unwrap(), &elements);
+        assert_eq!(tensor.shape().to_string(), "bf16[2]");
+    }
+
+    #[test]
+    fn utf8_tensors_are_length_prefixed() {
+        let tensor = Tensor::from_strings(&["cat", "", "dog"], &[3]);
+
+        assert_eq!(tensor.element_type(), ElementType::Utf8);
+        assert_eq!(&tensor.buffer()[..7], &[3, 0, 0, 0, b'c', b'a', b't']);
+        assert_eq!(tensor.strings().unwrap(), vec!["cat", "", "dog"]);
+        assert!(tensor.elements::<u8>().is_none());
+        assert_eq!(
+            serde_json::to_value(tensor.serializable()).unwrap(),
+            serde_json::json!({
+                "element-type": "utf8",
+                "dimensions": [3],
+                "elements": ["cat", "", "dog"],
+            })
+        );
+
+        let empty =
+            Tensor::zeroed(ElementType::Utf8, tensor.dimensions.clone());
+        assert_eq!(empty.strings().unwrap(), vec![""; 3]);
+    }
+
+    #[test]
+    #[cfg(feature = "ndarray")]
+    fn ndarray_round_trip() {
+        let array = ndarray::arr2(&[[1_i16, 2, 3], [4, 5, 6]]);
+
+        // Transposed arrays aren't contiguous, so they need to be copied
+        let mut tensor = Tensor::from_ndarray(&array.t());
+        assert_eq!(tensor.elements::<i16>().unwrap(), &[1, 4, 2, 5, 3, 6]);
+
+        tensor.view_mut::<i16>().unwrap()[[0, 1]] = 42;
+        let got = tensor.to_ndarray::<i16>().unwrap();
+
+        assert_eq!(got, ndarray::arr2(&[[1, 42], [2, 5], [3, 6]]).into_dyn());
+        assert!(tensor.view::<f32>().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "ndarray")]
+    fn fixed_dimension_views() {
+        let mut image = Tensor::new(&[0_u8; 12], &[2, 2, 3]);
+
+        let mut pixels = image.view_dim_mut::<u8, 3>().unwrap();
+        pixels[[1, 0, 2]] = 255;
+        let view: ndarray::ArrayView3<'_, u8> = image.view_dim().unwrap();
+
+        assert_eq!(view[[1, 0, 2]], 255);
+        assert_eq!(image.elements::<u8>().unwrap()[8], 255);
+        assert!(image.view_dim::<u8, 2>().is_none());
+    }
+}
@@ -0,0 +1,374 @@
+//! Long-running stress tests for Runes.
+//!
+//! Some bugs (e.g. a proc-block that leaks a little memory each time it runs,
+//! or an allocator that slowly fragments linear memory) only show up after
+//! thousands of predictions. The [`soak()`] function runs a Rune over and over
+//! for a fixed amount of time while keeping track of memory usage, latency,
+//! and whether its outputs still satisfy a set of [`Invariant`]s.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::time::Duration;
+//!
+//! use hotg_rune_runtime::{
+//!     testing::{soak, Invariant},
+//!     OutputTensor, Tensor,
+//! };
+//!
+//! let rune = std::fs::read("sine.rune")?;
+//!
+//! let report = soak(
+//!     &rune,
+//!     |i, inputs| {
+//!         let x = (i % 100) as f32 / 100.0;
+//!         inputs.insert(1, Tensor::new(&[x], &[1, 1]));
+//!     },
+//!     Duration::from_secs(60 * 60),
+//!     vec![Invariant::new("output is finite", |outputs| {
+//!         outputs.values().flatten().all(|tensor| match tensor {
+//!             OutputTensor::Tensor(t) => {
+//!                 t.elements::<f32>().map_or(true, |values| {
+//!                     values.iter().all(|v| v.is_finite())
+//!                 })
+//!             },
+//!             OutputTensor::StringTensor { .. } => true,
+//!         })
+//!     })],
+//! )?;
+//!
+//! println!("{}", report);
+//! assert!(report.passed());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error};
+
+use crate::{OutputTensor, Runtime, Tensor};
+
+/// How many predictions are summarised by each [`Window`].
+const WINDOW_SIZE: u64 = 1000;
+
+/// A property the Rune's outputs must have after every prediction.
+pub struct Invariant {
+    name: String,
+    check: Box<dyn FnMut(&HashMap<u32, Vec<OutputTensor>>) -> bool>,
+}
+
+impl Invariant {
+    pub fn new<F>(name: impl Into<String>, check: F) -> Self
+    where
+        F: FnMut(&HashMap<u32, Vec<OutputTensor>>) -> bool + 'static,
+    {
+        Invariant {
+            name: name.into(),
+            check: Box::new(check),
+        }
+    }
+}
+
+/// Run a Rune continuously for `duration`.
+///
+/// Before each prediction, `inputs` is called with the iteration number and
+/// the Rune's input tensors so it can update them. Every [`Invariant`] is
+/// checked against the outputs after each successful prediction.
+///
+/// An error is only returned when the Rune can't be loaded. Failed predictions
+/// and violated invariants are recorded in the [`SoakReport`].
+pub fn soak<G>(
+    rune: &[u8],
+    mut inputs: G,
+    duration: Duration,
+    mut invariants: Vec<Invariant>,
+) -> Result<SoakReport, Error>
+where
+    G: FnMut(u64, &mut HashMap<u32, Tensor>),
+{
+    let mut runtime = Runtime::builder()
+        .rune(rune)
+        .build()
+        .context("Unable to load the Rune")?;
+
+    let mut report = SoakReport::new(runtime.memory_usage(), &invariants);
+    let mut window = WindowBuilder::default();
+    let started = Instant::now();
+
+    while started.elapsed() < duration {
+        let iteration = report.iterations;
+        inputs(iteration, runtime.input_tensors());
+
+        let before = Instant::now();
+        let result = runtime.predict();
+        let latency = before.elapsed();
+
+        match result {
+            Ok(()) => {
+                for (invariant, violations) in
+                    invariants.iter_mut().zip(&mut report.violations)
+                {
+                    if !(invariant.check)(runtime.output_tensors()) {
+                        violations.record(iteration);
+                    }
+                }
+            },
+            Err(e) => report.errors.record(iteration, &e),
+        }
+
+        report.iterations += 1;
+        window.add(latency);
+
+        if window.predictions == WINDOW_SIZE {
+            report.windows.push(window.finish(runtime.memory_usage()));
+        }
+    }
+
+    if window.predictions > 0 {
+        report.windows.push(window.finish(runtime.memory_usage()));
+    }
+    report.elapsed = started.elapsed();
+
+    Ok(report)
+}
+
+/// The results from a [`soak()`] test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoakReport {
+    /// The number of predictions that were run.
+    pub iterations: u64,
+    /// How long the test ran for.
+    pub elapsed: Duration,
+    /// The Rune's memory usage (in bytes) before the first prediction.
+    pub initial_memory: usize,
+    /// Summaries of consecutive batches of predictions, in order.
+    pub windows: Vec<Window>,
+    /// Predictions which returned an error.
+    pub errors: Errors,
+    /// How often each [`Invariant`] was violated, in the order they were
+    /// passed to [`soak()`].
+    pub violations: Vec<Violations>,
+}
+
+impl SoakReport {
+    fn new(initial_memory: usize, invariants: &[Invariant]) -> Self {
+        SoakReport {
+            iterations: 0,
+            elapsed: Duration::default(),
+            initial_memory,
+            windows: Vec::new(),
+            errors: Errors::default(),
+            violations: invariants
+                .iter()
+                .map(|i| Violations {
+                    invariant: i.name.clone(),
+                    count: 0,
+                    first: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Did every prediction succeed without violating any invariants?
+    pub fn passed(&self) -> bool {
+        self.errors.count == 0 && self.violations.iter().all(|v| v.count == 0)
+    }
+
+    /// How much the Rune's memory grew (in bytes) over the course of the test.
+    pub fn memory_growth(&self) -> usize {
+        self.windows
+            .last()
+            .map(|w| w.memory.saturating_sub(self.initial_memory))
+            .unwrap_or(0)
+    }
+
+    /// The mean latency of the last [`Window`] divided by the first, where
+    /// anything noticeably above `1.0` means predictions got slower over time.
+    pub fn latency_drift(&self) -> Option<f64> {
+        let first = self.windows.first()?.mean_latency.as_secs_f64();
+        let last = self.windows.last()?.mean_latency.as_secs_f64();
+
+        if first > 0.0 {
+            Some(last / first)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for SoakReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Ran {} predictions in {:.1?}",
+            self.iterations, self.elapsed
+        )?;
+        writeln!(
+            f,
+            "Memory: {} -> {} bytes (+{})",
+            self.initial_memory,
+            self.initial_memory + self.memory_growth(),
+            self.memory_growth()
+        )?;
+
+        if let (Some(first), Some(last)) =
+            (self.windows.first(), self.windows.last())
+        {
+            write!(
+                f,
+                "Latency: {:?} -> {:?} mean",
+                first.mean_latency, last.mean_latency
+            )?;
+            if let Some(drift) = self.latency_drift() {
+                write!(f, " ({:.2}x)", drift)?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "Errors: {}", self.errors.count)?;
+        if let Some((iteration, message)) = &self.errors.first {
+            writeln!(f, "  first at iteration {}: {}", iteration, message)?;
+        }
+
+        for violation in &self.violations {
+            write!(
+                f,
+                "\"{}\" violated {} times",
+                violation.invariant, violation.count
+            )?;
+            if let Some(iteration) = violation.first {
+                write!(f, " (first at iteration {})", iteration)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A summary of consecutive predictions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    /// The number of predictions in this window.
+    pub predictions: u64,
+    pub mean_latency: Duration,
+    pub max_latency: Duration,
+    /// The Rune's memory usage (in bytes) at the end of the window.
+    pub memory: usize,
+}
+
+#[derive(Debug, Default)]
+struct WindowBuilder {
+    predictions: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl WindowBuilder {
+    fn add(&mut self, latency: Duration) {
+        self.predictions += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    fn finish(&mut self, memory: usize) -> Window {
+        let WindowBuilder {
+            predictions,
+            total,
+            max,
+        } = std::mem::take(self);
+
+        Window {
+            predictions,
+            mean_latency: total / predictions.max(1) as u32,
+            max_latency: max,
+            memory,
+        }
+    }
+}
+
+/// The predictions which failed during a [`soak()`] test.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Errors {
+    pub count: u64,
+    /// The iteration and error message for the first failure.
+    pub first: Option<(u64, String)>,
+}
+
+impl Errors {
+    fn record(&mut self, iteration: u64, error: &Error) {
+        self.count += 1;
+        if self.first.is_none() {
+            self.first = Some((iteration, format!("{:#}", error)));
+        }
+    }
+}
+
+/// How often an [`Invariant`] was violated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violations {
+    pub invariant: String,
+    pub count: u64,
+    /// The first iteration it was violated on.
+    pub first: Option<u64>,
+}
+
+impl Violations {
+    fn record(&mut self, iteration: u64) {
+        self.count += 1;
+        self.first.get_or_insert(iteration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(mean_ms: u64, memory: usize) -> Window {
+        Window {
+            predictions: WINDOW_SIZE,
+            mean_latency: Duration::from_millis(mean_ms),
+            max_latency: Duration::from_millis(mean_ms * 2),
+            memory,
+        }
+    }
+
+    #[test]
+    fn summarise_each_window() {
+        let mut builder = WindowBuilder::default();
+        builder.add(Duration::from_millis(2));
+        builder.add(Duration::from_millis(4));
+
+        let got = builder.finish(1024);
+
+        assert_eq!(
+            got,
+            Window {
+                predictions: 2,
+                mean_latency: Duration::from_millis(3),
+                max_latency: Duration::from_millis(4),
+                memory: 1024,
+            }
+        );
+        assert_eq!(builder.predictions, 0);
+    }
+
+    #[test]
+    fn report_memory_growth_and_latency_drift() {
+        let mut report = SoakReport::new(65536, &[]);
+        report.windows.push(window(10, 65536));
+        report.windows.push(window(12, 131072));
+        report.windows.push(window(20, 196608));
+
+        assert_eq!(report.memory_growth(), 131072);
+        assert_eq!(report.latency_drift(), Some(2.0));
+        assert!(report.passed());
+
+        report.errors.record(7, &anyhow::anyhow!("Out of memory"));
+        assert!(!report.passed());
+    }
+}
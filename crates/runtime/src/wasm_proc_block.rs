@@ -0,0 +1,427 @@
+//! Proc-blocks distributed as standalone WebAssembly modules.
+//!
+//! Normally proc-blocks are compiled into the Rune, which ties their version
+//! to the Rune's. A [`ProcBlockModule`] is a proc-block compiled to its own
+//! `.wasm` file which the host loads at runtime and plugs into a
+//! [`crate::native::NativePipeline`], so any language that can target
+//! WebAssembly can be used to write one.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), anyhow::Error> {
+//! use hotg_rune_runtime::native::NativePipeline;
+//!
+//! let graph = std::fs::read("sine.rune_graph.json")?;
+//! let modulo = std::fs::read("modulo.wasm")?;
+//!
+//! let pipeline = NativePipeline::from_graph(&graph)?
+//!     .wasm_proc_block("hotg-ai/rune#proc_blocks/modulo", &modulo)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # The Proc-Block ABI
+//!
+//! The module can't import anything and must export the following (`ptr`
+//! and `len` are all `i32`s referring to the module's memory):
+//!
+//! - `memory`
+//! - `rune_proc_block_alloc(len) -> ptr` - allocate a buffer the host can write
+//!   to
+//! - `rune_proc_block_free(ptr, len)` - (optional) free a buffer returned by
+//!   `rune_proc_block_alloc()` once the host is done with it
+//! - `rune_proc_block_set_argument(name, name_len, value, value_len, result) ->
+//!   i32` - (optional) set one of the arguments from the Runefile
+//! - `rune_proc_block_transform(inputs, inputs_len, result) -> i32` - transform
+//!   the encoded input tensors into output tensors
+//!
+//! Functions which take a `result` return `0` on success and then write the
+//! pointer and length (as two little-endian `u32`s) for their output to
+//! `result`. A non-zero return value means the function failed and `result`
+//! points to a UTF-8 error message instead. The guest owns the memory its
+//! outputs are stored in, and may reuse it on the next call.
+//!
+//! Tensors are encoded as a little-endian `u32` count followed by each tensor
+//! in turn:
+//!
+//! | Field         | Type                   |
+//! | ------------- | ---------------------- |
+//! | element type  | `u32`                  |
+//! | rank          | `u32`                  |
+//! | dimensions    | `rank` x `u32`         |
+//! | buffer length | `u32`                  |
+//! | buffer        | `buffer length` x `u8` |
+//!
+//! where the element type is the [`ElementType`]'s discriminant (`0` for
+//! [`ElementType::U8`], `1` for [`ElementType::I8`], and so on) and the
+//! buffer is laid out the same way as [`Tensor::buffer()`].
+
+use std::{convert::TryInto, num::NonZeroUsize};
+
+use anyhow::{Context, Error};
+use wasmer::{Instance, Memory, Module, NativeFunc, Store};
+
+use crate::{native::ProcBlock, ElementType, Tensor};
+
+const ALLOC: &str = "rune_proc_block_alloc";
+const FREE: &str = "rune_proc_block_free";
+const SET_ARGUMENT: &str = "rune_proc_block_set_argument";
+const TRANSFORM: &str = "rune_proc_block_transform";
+
+/// A compiled proc-block module, which can be instantiated once for every
+/// stage that uses it.
+#[derive(Debug, Clone)]
+pub struct ProcBlockModule {
+    module: Module,
+}
+
+impl ProcBlockModule {
+    /// Compile a proc-block, making sure it implements the proc-block ABI.
+    pub fn load(wasm: &[u8]) -> Result<Self, Error> {
+        let store = Store::default();
+        let module = Module::from_binary(&store, wasm)
+            .context("Unable to compile the proc-block")?;
+
+        if let Some(import) = module.imports().next() {
+            anyhow::bail!(
+                "Proc-blocks can't import anything, but this one imports \
+                 \"{}::{}\"",
+                import.module(),
+                import.name()
+            );
+        }
+
+        for required in &["memory", ALLOC, TRANSFORM] {
+            if !module.exports().any(|e| e.name() == *required) {
+                anyhow::bail!("The proc-block doesn't export \"{}\"", required);
+            }
+        }
+
+        Ok(ProcBlockModule { module })
+    }
+
+    /// Create a new instance of the proc-block.
+    ///
+    /// The module is instantiated the first time it is used, so any errors
+    /// will be reported by the [`ProcBlock`] methods.
+    pub fn instantiate(&self) -> WasmProcBlock {
+        WasmProcBlock {
+            module: self.module.clone(),
+            instance: None,
+        }
+    }
+}
+
+/// An instance of a [`ProcBlockModule`].
+pub struct WasmProcBlock {
+    module: Module,
+    instance: Option<Exports>,
+}
+
+impl WasmProcBlock {
+    fn exports(&mut self) -> Result<&Exports, Error> {
+        if self.instance.is_none() {
+            let instance = Instance::new(&self.module, &wasmer::imports! {})
+                .context("Unable to instantiate the proc-block")?;
+            self.instance = Some(Exports::new(&instance)?);
+        }
+
+        Ok(self.instance.as_ref().unwrap())
+    }
+}
+
+impl ProcBlock for WasmProcBlock {
+    fn set_argument(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let exports = self.exports()?;
+
+        let set_argument = match &exports.set_argument {
+            Some(f) => f,
+            None => anyhow::bail!("Unknown argument, \"{}\"", name),
+        };
+
+        let name = exports.write(name.as_bytes())?;
+        let value = exports.write(value.as_bytes())?;
+        let result = exports.alloc(8)?;
+
+        let status = set_argument
+            .call(name.ptr, name.len, value.ptr, value.len, result.ptr)?;
+        let outcome = exports.read_result(status, result.ptr);
+
+        exports.free(name)?;
+        exports.free(value)?;
+        exports.free(result)?;
+
+        outcome.map(|_| ())
+    }
+
+    fn transform(&mut self, inputs: Vec<Tensor>) -> Result<Vec<Tensor>, Error> {
+        let exports = self.exports()?;
+
+        let inputs = exports.write(&encode_tensors(&inputs))?;
+        let result = exports.alloc(8)?;
+
+        let status =
+            exports.transform.call(inputs.ptr, inputs.len, result.ptr)?;
+        let outcome = exports.read_result(status, result.ptr);
+
+        exports.free(inputs)?;
+        exports.free(result)?;
+
+        let encoded = outcome?;
+        decode_tensors(&encoded)
+            .context("The proc-block returned invalid tensors")
+    }
+}
+
+/// The functions exported by an instantiated proc-block.
+struct Exports {
+    memory: Memory,
+    alloc: NativeFunc<i32, i32>,
+    free: Option<NativeFunc<(i32, i32), ()>>,
+    set_argument: Option<NativeFunc<(i32, i32, i32, i32, i32), i32>>,
+    transform: NativeFunc<(i32, i32, i32), i32>,
+}
+
+/// A buffer inside the proc-block's memory.
+#[derive(Debug, Copy, Clone)]
+struct Buffer {
+    ptr: i32,
+    len: i32,
+}
+
+impl Exports {
+    fn new(instance: &Instance) -> Result<Self, Error> {
+        let exports = &instance.exports;
+
+        Ok(Exports {
+            memory: exports.get_memory("memory")?.clone(),
+            alloc: exports.get_native_function(ALLOC)?,
+            free: exports.get_native_function(FREE).ok(),
+            set_argument: exports.get_native_function(SET_ARGUMENT).ok(),
+            transform: exports.get_native_function(TRANSFORM)?,
+        })
+    }
+
+    fn alloc(&self, len: usize) -> Result<Buffer, Error> {
+        let len: i32 = len
+            .try_into()
+            .context("The buffer is too big for the proc-block's memory")?;
+        let ptr = self.alloc.call(len)?;
+
+        Ok(Buffer { ptr, len })
+    }
+
+    fn free(&self, buffer: Buffer) -> Result<(), Error> {
+        if let Some(free) = &self.free {
+            free.call(buffer.ptr, buffer.len)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, data: &[u8]) -> Result<Buffer, Error> {
+        let buffer = self.alloc(data.len())?;
+
+        // Safety: the proc-block isn't running, so nothing else can access
+        // its memory.
+        let memory = unsafe { self.memory.data_unchecked_mut() };
+        let dest = memory
+            .get_mut(buffer.ptr as u32 as usize..)
+            .and_then(|m| m.get_mut(..data.len()))
+            .context("The proc-block allocated a buffer outside its memory")?;
+        dest.copy_from_slice(data);
+
+        Ok(buffer)
+    }
+
+    fn read(&self, ptr: u32, len: u32) -> Result<Vec<u8>, Error> {
+        // Safety: the proc-block isn't running, so nothing else can access
+        // its memory.
+        let memory = unsafe { self.memory.data_unchecked() };
+
+        memory
+            .get(ptr as usize..)
+            .and_then(|m| m.get(..len as usize))
+            .map(|m| m.to_vec())
+            .context("The proc-block pointed outside its memory")
+    }
+
+    /// Read the buffer a function wrote to its `result`, turning it into an
+    /// error message if the function failed.
+    fn read_result(&self, status: i32, result: i32) -> Result<Vec<u8>, Error> {
+        let location = self.read(result as u32, 8)?;
+        let ptr = u32::from_le_bytes(location[..4].try_into().unwrap());
+        let len = u32::from_le_bytes(location[4..].try_into().unwrap());
+        let data = self.read(ptr, len)?;
+
+        if status == 0 {
+            Ok(data)
+        } else {
+            Err(Error::msg(String::from_utf8_lossy(&data).into_owned()))
+        }
+    }
+}
+
+fn encode_tensors(tensors: &[Tensor]) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    buffer.extend((tensors.len() as u32).to_le_bytes());
+
+    for tensor in tensors {
+        let dimensions = tensor.dimensions();
+        buffer.extend((tensor.element_type() as u32).to_le_bytes());
+        buffer.extend((dimensions.len() as u32).to_le_bytes());
+        for d in dimensions {
+            buffer.extend((d.get() as u32).to_le_bytes());
+        }
+        buffer.extend((tensor.buffer().len() as u32).to_le_bytes());
+        buffer.extend(tensor.buffer());
+    }
+
+    buffer
+}
+
+fn decode_tensors(mut buffer: &[u8]) -> Result<Vec<Tensor>, Error> {
+    let count = read_u32(&mut buffer)?;
+    let mut tensors = Vec::new();
+
+    for _ in 0..count {
+        let element_type = element_type(read_u32(&mut buffer)?)?;
+        let rank = read_u32(&mut buffer)?;
+        let dimensions = (0..rank)
+            .map(|_| {
+                let d = read_u32(&mut buffer)?;
+                NonZeroUsize::new(d as usize)
+                    .context("Dimensions must be greater than zero")
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let len = read_u32(&mut buffer)? as usize;
+        anyhow::ensure!(
+            buffer.len() >= len,
+            "The tensor's buffer is truncated"
+        );
+        let (data, rest) = buffer.split_at(len);
+        buffer = rest;
+
+        let num_elements: usize = dimensions.iter().map(|d| d.get()).product();
+
+        if element_type == ElementType::Utf8 {
+            let strings = crate::tensor::decode_strings(data)
+                .context("Invalid string tensor")?;
+            anyhow::ensure!(
+                strings.len() == num_elements,
+                "Expected {} strings but found {}",
+                num_elements,
+                strings.len()
+            );
+        } else {
+            anyhow::ensure!(
+                data.len() == num_elements * element_type.byte_size(),
+                "A {} tensor with {} elements can't be stored in {} bytes",
+                element_type,
+                num_elements,
+                data.len()
+            );
+        }
+
+        tensors.push(Tensor::new_raw(element_type, dimensions, data.to_vec()));
+    }
+
+    Ok(tensors)
+}
+
+fn read_u32(buffer: &mut &[u8]) -> Result<u32, Error> {
+    anyhow::ensure!(buffer.len() >= 4, "Unexpected end of the tensors");
+    let (head, rest) = buffer.split_at(4);
+    *buffer = rest;
+
+    Ok(u32::from_le_bytes(head.try_into().unwrap()))
+}
+
+fn element_type(value: u32) -> Result<ElementType, Error> {
+    let element_type = match value {
+        0 => ElementType::U8,
+        1 => ElementType::I8,
+        2 => ElementType::U16,
+        3 => ElementType::I16,
+        4 => ElementType::U32,
+        5 => ElementType::I32,
+        6 => ElementType::F32,
+        7 => ElementType::U64,
+        8 => ElementType::I64,
+        9 => ElementType::F64,
+        10 => ElementType::F16,
+        11 => ElementType::BF16,
+        12 => ElementType::Utf8,
+        other => anyhow::bail!("Unknown element type, {}", other),
+    };
+
+    debug_assert_eq!(element_type as u32, value);
+
+    Ok(element_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (data (i32.const 0) "Unknown argument")
+          (func (export "rune_proc_block_alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "rune_proc_block_set_argument")
+            (param i32 i32 i32 i32) (param $result i32) (result i32)
+            (i32.store (local.get $result) (i32.const 0))
+            (i32.store offset=4 (local.get $result) (i32.const 16))
+            (i32.const 1))
+          (func (export "rune_proc_block_transform")
+            (param $inputs i32) (param $len i32) (param $result i32)
+            (result i32)
+            (i32.store (local.get $result) (local.get $inputs))
+            (i32.store offset=4 (local.get $result) (local.get $len))
+            (i32.const 0)))
+    "#;
+
+    #[test]
+    fn tensors_round_trip_through_the_proc_block() {
+        let wasm = wat::parse_str(IDENTITY).unwrap();
+        let module = ProcBlockModule::load(&wasm).unwrap();
+        let mut proc_block = module.instantiate();
+        let inputs = vec![
+            Tensor::new(&[1.0_f32, 2.0, 3.0], &[1, 3]),
+            Tensor::from_strings(&["a", "bc"], &[2]),
+        ];
+
+        let outputs = proc_block.transform(inputs.clone()).unwrap();
+
+        assert_eq!(outputs, inputs);
+    }
+
+    #[test]
+    fn errors_are_passed_back_to_the_host() {
+        let wasm = wat::parse_str(IDENTITY).unwrap();
+        let module = ProcBlockModule::load(&wasm).unwrap();
+        let mut proc_block = module.instantiate();
+
+        let err = proc_block.set_argument("modulus", "2").unwrap_err();
+
+        assert_eq!(err.to_string(), "Unknown argument");
+    }
+
+    #[test]
+    fn proc_blocks_must_export_the_abi() {
+        let wasm =
+            wat::parse_str(r#"(module (memory (export "memory") 1))"#).unwrap();
+
+        let err = ProcBlockModule::load(&wasm).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "The proc-block doesn't export \"rune_proc_block_alloc\""
+        );
+    }
+}
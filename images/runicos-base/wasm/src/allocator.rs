@@ -2,25 +2,63 @@ use alloc::alloc::GlobalAlloc;
 use core::{
     alloc::Layout,
     ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use crate::stats_allocator::StatsAllocator;
 
 #[derive(Debug, Default)]
-pub struct Allocator<A>(StatsAllocator<A>);
+pub struct Allocator<A> {
+    stats: StatsAllocator<A>,
+    heap_limit: AtomicUsize,
+}
 
 impl<A> Allocator<A> {
-    pub const fn new(inner: A) -> Self { Allocator(StatsAllocator::new(inner)) }
+    pub const fn new(inner: A) -> Self {
+        Allocator {
+            stats: StatsAllocator::new(inner),
+            heap_limit: AtomicUsize::new(0),
+        }
+    }
+
+    /// Make allocations fail once more than `bytes` are in use, where `0`
+    /// means there is no limit.
+    ///
+    /// This is set from the `memory.heap` field in the Runefile.
+    pub fn set_heap_limit(&self, bytes: usize) {
+        self.heap_limit.store(bytes, Ordering::SeqCst);
+    }
+
+    pub fn heap_limit(&self) -> Option<usize> {
+        match self.heap_limit.load(Ordering::SeqCst) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    /// Would allocating another `additional` bytes exceed the heap limit?
+    pub(crate) fn exceeds_limit(&self, additional: usize) -> bool {
+        match self.heap_limit() {
+            Some(limit) => {
+                self.stats.stats().in_use().saturating_add(additional) > limit
+            },
+            None => false,
+        }
+    }
 }
 
 unsafe impl<A: GlobalAlloc> GlobalAlloc for Allocator<A> {
     unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
-        let ptr = self.0.alloc(layout);
+        if self.exceeds_limit(layout.size()) {
+            return core::ptr::null_mut();
+        }
+
+        let ptr = self.stats.alloc(layout);
         log::trace!(
             "Alloc {:p}, layout = {:?}, stats = {:?}",
             ptr,
             layout,
-            self.0.stats()
+            self.stats.stats()
         );
 
         ptr
@@ -31,10 +69,10 @@ unsafe impl<A: GlobalAlloc> GlobalAlloc for Allocator<A> {
             "Free {:p}, layout = {:?}, stats = {:?}",
             ptr,
             layout,
-            self.0.stats()
+            self.stats.stats()
         );
 
-        self.0.dealloc(ptr, layout);
+        self.stats.dealloc(ptr, layout);
     }
 
     unsafe fn realloc(
@@ -43,7 +81,11 @@ unsafe impl<A: GlobalAlloc> GlobalAlloc for Allocator<A> {
         layout: Layout,
         new_size: usize,
     ) -> *mut u8 {
-        let new_ptr = self.0.realloc(ptr, layout, new_size);
+        if self.exceeds_limit(new_size.saturating_sub(layout.size())) {
+            return core::ptr::null_mut();
+        }
+
+        let new_ptr = self.stats.realloc(ptr, layout, new_size);
 
         log::trace!(
             "Realloc {:p} to {} bytes at {:p}, layout = {:?}, stats = {:?}",
@@ -51,7 +93,7 @@ unsafe impl<A: GlobalAlloc> GlobalAlloc for Allocator<A> {
             new_size,
             new_ptr,
             layout,
-            self.0.stats(),
+            self.stats.stats(),
         );
 
         new_ptr
@@ -61,9 +103,9 @@ unsafe impl<A: GlobalAlloc> GlobalAlloc for Allocator<A> {
 impl<A> Deref for Allocator<A> {
     type Target = StatsAllocator<A>;
 
-    fn deref(&self) -> &Self::Target { &self.0 }
+    fn deref(&self) -> &Self::Target { &self.stats }
 }
 
 impl<A> DerefMut for Allocator<A> {
-    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.0 }
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.stats }
 }
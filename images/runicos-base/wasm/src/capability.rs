@@ -2,7 +2,7 @@ use core::marker::PhantomData;
 
 use hotg_rune_core::{Shape, Tensor, Value};
 
-use crate::intrinsics;
+use crate::{chunks, intrinsics};
 
 #[derive(Debug, PartialEq)]
 pub struct Capability<T> {
@@ -30,17 +30,16 @@ impl<T: Clone + Default> Capability<T> {
         let mut buffer = Tensor::zeroed(output_dimensions.to_vec());
 
         let elements = buffer.make_elements_mut();
-        let byte_length = (elements.len() * core::mem::size_of::<T>()) as u32;
+        let byte_length = elements.len() * core::mem::size_of::<T>();
 
-        unsafe {
-            let response_size = intrinsics::request_provider_response(
-                elements.as_mut_ptr() as _,
+        // Safety: capabilities only produce plain old data
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(
+                elements.as_mut_ptr().cast::<u8>(),
                 byte_length,
-                self.id,
-            );
-
-            debug_assert_eq!(response_size, byte_length);
-        }
+            )
+        };
+        chunks::read_capability(self.id, bytes);
 
         buffer
     }
@@ -59,13 +58,23 @@ impl<T: Clone + Default> Capability<T> {
             intrinsics::request_capability_set_param(
                 self.id,
                 key.as_ptr(),
-                key.len() as u32,
+                key.len(),
                 buffer.as_ptr(),
-                bytes_written as u32,
+                bytes_written,
                 value.ty().into(),
             );
         }
 
         self
     }
+
+    /// Let the runtime know this capability is the `name` node in the
+    /// pipeline.
+    pub fn set_name(&mut self, name: &str) -> &mut Self {
+        unsafe {
+            intrinsics::rune_node_name(self.id, name.as_ptr(), name.len());
+        }
+
+        self
+    }
 }
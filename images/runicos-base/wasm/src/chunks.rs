@@ -0,0 +1,70 @@
+//! Copying tensors across the host boundary.
+//!
+//! Small tensors use a single `request_provider_response()` or
+//! `consume_output()` call, so Runes keep working with older runtimes. Anything
+//! larger than [`CHUNK_SIZE`] is copied a piece at a time with the `*_chunk()`
+//! host functions, which describe offsets and total lengths using 64-bit
+//! integers and avoid the host needing one huge staging buffer per call.
+
+use crate::intrinsics;
+
+/// The most data that will be copied in a single host call.
+pub(crate) const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Fill `buffer` with the capability's input.
+pub(crate) fn read_capability(capability_id: u32, buffer: &mut [u8]) {
+    if buffer.len() <= CHUNK_SIZE {
+        unsafe {
+            let response_size = intrinsics::request_provider_response(
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                capability_id,
+            );
+            debug_assert_eq!(response_size as usize, buffer.len());
+        }
+        return;
+    }
+
+    let total_len = buffer.len() as u64;
+    let mut offset = 0;
+
+    for chunk in buffer.chunks_mut(CHUNK_SIZE) {
+        unsafe {
+            let bytes_written = intrinsics::request_provider_response_chunk(
+                capability_id,
+                offset,
+                chunk.as_mut_ptr(),
+                chunk.len(),
+                total_len,
+            );
+            debug_assert_eq!(bytes_written as usize, chunk.len());
+        }
+        offset += chunk.len() as u64;
+    }
+}
+
+/// Send `data` to an output.
+pub(crate) fn write_output(output_id: u32, data: &[u8]) {
+    if data.len() <= CHUNK_SIZE {
+        unsafe {
+            intrinsics::consume_output(output_id, data.as_ptr(), data.len());
+        }
+        return;
+    }
+
+    let total_len = data.len() as u64;
+    let mut offset = 0;
+
+    for chunk in data.chunks(CHUNK_SIZE) {
+        unsafe {
+            intrinsics::consume_output_chunk(
+                output_id,
+                offset,
+                chunk.as_ptr(),
+                chunk.len(),
+                total_len,
+            );
+        }
+        offset += chunk.len() as u64;
+    }
+}
@@ -64,6 +64,7 @@ pub struct PipelineGuard {
 impl PipelineGuard {
     pub fn new() -> Self {
         log::debug!("Running the pipeline");
+        ALLOCATOR.reset_peak();
 
         PipelineGuard {
             _log: AllocationLogger::new("Pipeline", ALLOCATOR.stats()),
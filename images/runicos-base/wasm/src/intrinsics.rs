@@ -6,11 +6,14 @@
 use core::marker::PhantomData;
 
 /// A FFI-safe `&str`.
+///
+/// Like every length passed to the host, `len` is pointer-sized so wasm64
+/// Runes aren't limited to 4 GB.
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(C)]
 pub struct StringRef<'a> {
     data: *const u8,
-    len: u32,
+    len: usize,
     _lifetime: PhantomData<&'a str>,
 }
 
@@ -18,7 +21,7 @@ impl<'a> From<&'a str> for StringRef<'a> {
     fn from(s: &'a str) -> StringRef<'a> {
         StringRef {
             data: s.as_ptr(),
-            len: s.len() as u32,
+            len: s.len(),
             _lifetime: PhantomData,
         }
     }
@@ -33,9 +36,9 @@ extern "C" {
     pub fn tfm_model_invoke(
         model_id: u32,
         input: *const u8,
-        input_len: u32,
+        input_len: usize,
         output: *mut u8,
-        output_len: u32,
+        output_len: usize,
     ) -> u32;
 
     /// Load a model using a mimetype and a list of "descriptors" for the inputs
@@ -60,27 +63,43 @@ extern "C" {
     /// # unsafe {
     /// rune_model_load(
     ///     mimetype.as_ptr(),
-    ///     mimetype.len() as u32,
+    ///     mimetype.len(),
     ///     model.as_ptr(),
-    ///     model.len() as u32,
+    ///     model.len(),
     ///     inputs.as_ptr(),
-    ///     inputs.len() as u32,
+    ///     inputs.len(),
     ///     outputs.as_ptr(),
-    ///     outputs.len() as u32,
+    ///     outputs.len(),
     /// );
     /// # }
     /// ```
     pub fn rune_model_load(
         mimetype: *const u8,
-        mimetype_len: u32,
+        mimetype_len: usize,
         model: *const u8,
-        model_len: u32,
+        model_len: usize,
         input_descriptors: *const StringRef<'_>,
-        input_len: u32,
+        input_len: usize,
         output_descriptors: *const StringRef<'_>,
-        output_len: u32,
+        output_len: usize,
     ) -> u32;
 
+    /// Ask the runtime which of a model's variants should be loaded, given
+    /// each variant's name and size in bytes.
+    ///
+    /// The return value is the index of the chosen variant. The runtime will
+    /// expect the next call to [`rune_model_load()`] to use that variant.
+    pub fn rune_model_select_variant(
+        names: *const StringRef<'_>,
+        sizes: *const usize,
+        count: u32,
+    ) -> u32;
+
+    /// Tell the runtime which pipeline node the next call to
+    /// [`rune_model_load()`] is for, so the host can refer to the model by
+    /// name.
+    pub fn rune_model_name(name: *const u8, name_len: usize);
+
     /// Run inference using a model.
     ///
     /// The model's output will be written to the `output` buffers.
@@ -101,13 +120,46 @@ extern "C" {
     /// Model failures will trigger a trap and abort at runtime.
     pub fn tfm_preload_model(
         model: *const u8,
-        model_len: u32,
+        model_len: usize,
         inputs: u32,
         outputs: u32,
     ) -> u32;
 
     /// Write some text to the debug console.
-    pub fn _debug(msg: *const u8, msg_len: u32) -> u32;
+    pub fn _debug(msg: *const u8, msg_len: usize) -> u32;
+
+    /// Tell the runtime that the Rune has panicked.
+    ///
+    /// The runtime will use the message, its location, and the stage that was
+    /// running (`stage_len` is zero if there wasn't one) to trigger a trap,
+    /// so under normal circumstances this function won't return.
+    pub fn rune_panic(
+        msg: *const u8,
+        msg_len: usize,
+        file: *const u8,
+        file_len: usize,
+        line: u32,
+        column: u32,
+        stage: *const u8,
+        stage_len: usize,
+    ) -> u32;
+
+    /// Tell the runtime that a `rune_assert!()` failed, passing along the
+    /// expression that was checked, an optional message (`message_len` is
+    /// zero if there wasn't one), and the names and `Debug` representations
+    /// of any captured values.
+    ///
+    /// The runtime attaches these details to the [`rune_panic()`] that
+    /// follows.
+    pub fn rune_assertion_failed(
+        expression: *const u8,
+        expression_len: usize,
+        message: *const u8,
+        message_len: usize,
+        names: *const StringRef<'_>,
+        values: *const StringRef<'_>,
+        count: u32,
+    ) -> u32;
 
     /// Request a capability with a particular type, yielding a unique handle
     /// that can be used to refer to the capability later on.
@@ -122,9 +174,9 @@ extern "C" {
     pub fn request_capability_set_param(
         capability_id: u32,
         key_ptr: *const u8,
-        key_len: u32,
+        key_len: usize,
         value_ptr: *const u8,
-        value_len: u32,
+        value_len: usize,
         value_type: u32,
     ) -> u32;
 
@@ -135,25 +187,68 @@ extern "C" {
     /// runtime.
     pub fn request_output(out_type: u32) -> u32;
 
+    /// Set an output parameter by name, letting the host know how the output
+    /// has been configured.
+    pub fn request_output_set_param(
+        output_id: u32,
+        key_ptr: *const u8,
+        key_len: usize,
+        value_ptr: *const u8,
+        value_len: usize,
+    ) -> u32;
+
+    /// Tell the runtime which pipeline node a capability or output is for, so
+    /// the host can refer to it by name.
+    pub fn rune_node_name(id: u32, name: *const u8, name_len: usize) -> u32;
+
     /// Write the result of a pipeline to an output device.
     ///
     /// The contents of the buffer are output-specific. Any errors will trigger
     /// a a trap and abort at runtime.
-    pub fn consume_output(output_id: u32, buffer: *const u8, len: u32) -> u32;
+    pub fn consume_output(
+        output_id: u32,
+        buffer: *const u8,
+        len: usize,
+    ) -> usize;
+
+    /// Send part of an output's data to the runtime.
+    ///
+    /// Chunks must be sent in order, starting at `offset` 0, and the runtime
+    /// will pass the output along once all `total_len` bytes have arrived.
+    pub fn consume_output_chunk(
+        output_id: u32,
+        offset: u64,
+        buffer: *const u8,
+        len: usize,
+        total_len: u64,
+    ) -> usize;
 
     /// Ask a particular capability to fill the `buffer` with input.
     ///
     /// Invalid parameters will trigger a trap and abort at runtime.
     pub fn request_provider_response(
         buffer: *mut u8,
-        buffer_len: u32,
+        buffer_len: usize,
         capability_id: u32,
     ) -> u32;
 
+    /// Copy part of a capability's `total_len` byte input into `buffer`,
+    /// starting at `offset`.
+    ///
+    /// Reading from offset 0 asks the capability for a new input. The return
+    /// value is the number of bytes written to `buffer`.
+    pub fn request_provider_response_chunk(
+        capability_id: u32,
+        offset: u64,
+        buffer: *mut u8,
+        buffer_len: usize,
+        total_len: u64,
+    ) -> u32;
+
     /// Open a named resource, returning a unique ID that can be used to .
     ///
     /// Invalid parameters will return a negative value.
-    pub fn rune_resource_open(name: *const u8, name_len: u32) -> i32;
+    pub fn rune_resource_open(name: *const u8, name_len: usize) -> i32;
 
     /// Read data from a resource into the provided buffer.
     ///
@@ -161,11 +256,33 @@ extern "C" {
     pub fn rune_resource_read(
         resource_id: u32,
         buffer: *mut u8,
-        buffer_len: u32,
+        buffer_len: usize,
     ) -> i32;
 
     /// Close a resource.
     ///
     /// Invalid parameters will be ignored.
     pub fn rune_resource_close(resource_id: u32);
+
+    /// Tell the runtime the current value of one of a proc-block's
+    /// parameters.
+    pub fn rune_proc_block_parameter(
+        node: *const u8,
+        node_len: usize,
+        type_name: *const u8,
+        type_name_len: usize,
+        name: *const u8,
+        name_len: usize,
+        value: *const u8,
+        value_len: usize,
+    );
+
+    /// Copy the parameter updates the host has queued up into `buffer`,
+    /// returning the number of bytes they need.
+    ///
+    /// Nothing is written (or consumed) if the buffer is too small.
+    pub fn rune_proc_block_parameter_updates(
+        buffer: *mut u8,
+        buffer_len: usize,
+    ) -> u32;
 }
@@ -1,19 +1,27 @@
-#![cfg(target_arch = "wasm32")]
+#![cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
 #![no_std]
 // Note: The WebAssembly bindings need to provide alloc error handling.
-#![feature(core_intrinsics, lang_items, alloc_error_handler)]
+#![feature(
+    core_intrinsics,
+    lang_items,
+    alloc_error_handler,
+    panic_info_message
+)]
 
 extern crate alloc;
 
 pub mod allocator;
 mod buf_writer;
 mod capability;
+mod chunks;
 mod guards;
 pub mod intrinsics;
 mod logging;
 mod model;
+mod parameters;
 mod resources;
 pub mod serial;
+mod serial_encoding;
 mod stats_allocator;
 pub mod tensor_output;
 
@@ -28,6 +36,7 @@ pub use crate::{
     guards::{PipelineGuard, SetupGuard},
     logging::Logger,
     model::Model,
+    parameters::{parameter_updates, report_parameter, ParameterUpdate},
     resources::{Resource, ResourceError},
     serial::Serial,
     tensor_output::TensorOutput,
@@ -37,6 +46,75 @@ pub use crate::{
 pub static ALLOCATOR: Allocator<GlobalDlmalloc> =
     Allocator::new(GlobalDlmalloc);
 
+/// The name of the stage currently being executed, if any.
+static mut CURRENT_STAGE: &str = "";
+
+/// Record which stage the pipeline is about to execute so it can be included
+/// in any panic messages.
+pub fn set_current_stage(name: &'static str) {
+    // Safety: Runes are single-threaded.
+    unsafe {
+        CURRENT_STAGE = name;
+    }
+}
+
+/// The stage set by [`set_current_stage()`], if there is one.
+pub(crate) fn current_stage() -> Option<&'static str> {
+    // Safety: Runes are single-threaded.
+    let stage = unsafe { CURRENT_STAGE };
+
+    if stage.is_empty() {
+        None
+    } else {
+        Some(stage)
+    }
+}
+
+/// The allocator statistics a Rune gives the runtime through its
+/// `_memory_stats()` export, as a sequence of little-endian `u32`s.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct MemoryStats {
+    pub in_use: u32,
+    pub peak_in_use: u32,
+    pub allocations: u32,
+    pub deallocations: u32,
+    pub reallocations: u32,
+    pub bytes_allocated: u32,
+    pub bytes_deallocated: u32,
+}
+
+/// Take a snapshot of the allocator's statistics, returning a pointer the
+/// runtime can read them from.
+pub fn memory_stats() -> *const MemoryStats {
+    static mut STATS: MemoryStats = MemoryStats {
+        in_use: 0,
+        peak_in_use: 0,
+        allocations: 0,
+        deallocations: 0,
+        reallocations: 0,
+        bytes_allocated: 0,
+        bytes_deallocated: 0,
+    };
+
+    let stats = ALLOCATOR.stats();
+
+    // Safety: Runes are single-threaded and the runtime only reads the
+    // snapshot before calling back into the Rune.
+    unsafe {
+        STATS = MemoryStats {
+            in_use: stats.in_use() as u32,
+            peak_in_use: stats.peak_in_use as u32,
+            allocations: stats.allocations as u32,
+            deallocations: stats.deallocations as u32,
+            reallocations: stats.reallocations as u32,
+            bytes_allocated: stats.bytes_allocated as u32,
+            bytes_deallocated: stats.bytes_deallocated as u32,
+        };
+        &STATS
+    }
+}
+
 #[panic_handler]
 fn on_panic(info: &PanicInfo) -> ! {
     static mut PANICKING: bool = false;
@@ -48,39 +126,64 @@ fn on_panic(info: &PanicInfo) -> ! {
         if !PANICKING {
             PANICKING = true;
 
-            // First we try to log the panic at the ERROR level. This should
-            // be translated into a runtime trap, so under most circumstances
-            // the log call won't return and our user will get a nice error
-            // message.
             log::error!("{}", info);
         }
 
-        // However, some times the runtime won't receive the log message (e.g.
-        // log level filtering or because an OOM in logging recursively
-        // triggered the panic handler). If that is the case, we still try to
-        // send *some* message to the runtime so they know the world is broken.
+        // Next, we tell the runtime where the panic happened so it can be
+        // turned into a proper error. Normally this will trigger a trap and
+        // never return.
 
         // Safety: We need our own buffer for panic messages in case the
         // allocator is fubar. Runes are single-threaded, so we can
         // guarantee we'll never have aliased mutation.
-        static mut DEBUG_BUFFER: [u8; 1024] = [0; 1024];
-        let mut w = BufWriter::new(&mut DEBUG_BUFFER);
+        static mut MESSAGE_BUFFER: [u8; 1024] = [0; 1024];
+        let mut w = BufWriter::new(&mut MESSAGE_BUFFER);
 
-        if write!(w, "{}", info).is_ok() {
-            let written = w.written();
-            intrinsics::_debug(written.as_ptr(), written.len() as u32);
-        }
+        // Note: if the message doesn't fit in our buffer we'll still send
+        // whatever was written.
+        let _ = match info.message() {
+            Some(msg) => write!(w, "{}", msg),
+            None => w.write_str("explicit panic"),
+        };
+        let message = w.written();
+
+        let (file, line, column) = match info.location() {
+            Some(loc) => (loc.file(), loc.line(), loc.column()),
+            None => ("", 0, 0),
+        };
+
+        intrinsics::rune_panic(
+            message.as_ptr(),
+            message.len(),
+            file.as_ptr(),
+            file.len(),
+            line,
+            column,
+            CURRENT_STAGE.as_ptr(),
+            CURRENT_STAGE.len(),
+        );
 
         // And now we've done everything we can, we ungracefully crash.
-        core::arch::wasm32::unreachable()
+        core::intrinsics::abort()
     }
 }
 
 #[alloc_error_handler]
 fn on_alloc_error(layout: Layout) -> ! {
-    panic!(
-        "memory allocation of {} bytes failed ({:?})",
-        layout.size(),
-        ALLOCATOR.stats()
-    );
+    // Only blame the Runefile if the allocation wouldn't have fit in what was
+    // left of the heap
+    match ALLOCATOR.heap_limit() {
+        Some(limit) if ALLOCATOR.exceeds_limit(layout.size()) => panic!(
+            "memory allocation of {} bytes failed because it would exceed the \
+             {} byte heap limit from the Runefile ({:?})",
+            layout.size(),
+            limit,
+            ALLOCATOR.stats()
+        ),
+        _ => panic!(
+            "memory allocation of {} bytes failed ({:?})",
+            layout.size(),
+            ALLOCATOR.stats()
+        ),
+    }
 }
@@ -18,7 +18,7 @@ impl Logger {
 
 fn write(msg: &[u8]) {
     unsafe {
-        intrinsics::_debug(msg.as_ptr(), msg.len() as u32);
+        intrinsics::_debug(msg.as_ptr(), msg.len());
     }
 }
 
@@ -43,6 +43,7 @@ impl Log for Logger {
             module_path: r.module_path().map(Cow::Borrowed),
             file: r.file().map(Cow::Borrowed),
             line: r.line(),
+            node: crate::current_stage().map(Cow::Borrowed),
         };
 
         let mut json_buffer = [0; 4096];
@@ -17,13 +17,17 @@ pub struct Model<Input, Output> {
 }
 
 impl<Input, Output> Model<Input, Output> {
+    /// Load the model for the `name` node in the pipeline.
     pub fn load(
+        name: &str,
         mimetype: &str,
         model_data: &[u8],
         input_shapes: &[Shape<'static>],
         output_shapes: &[Shape<'static>],
     ) -> Self {
         let id = unsafe {
+            crate::intrinsics::rune_model_name(name.as_ptr(), name.len());
+
             let input_shape_descriptors: Vec<String> =
                 input_shapes.iter().map(|s| s.to_string()).collect();
             let input_shape_descriptors: Vec<_> = input_shape_descriptors
@@ -39,13 +43,13 @@ impl<Input, Output> Model<Input, Output> {
 
             crate::intrinsics::rune_model_load(
                 mimetype.as_ptr(),
-                mimetype.len() as u32,
+                mimetype.len(),
                 model_data.as_ptr(),
-                model_data.len() as u32,
+                model_data.len(),
                 input_shape_descriptors.as_ptr(),
-                input_shape_descriptors.len() as u32,
+                input_shape_descriptors.len(),
                 output_shape_descriptors.as_ptr(),
-                output_shape_descriptors.len() as u32,
+                output_shape_descriptors.len(),
             )
         };
 
@@ -56,6 +60,41 @@ impl<Input, Output> Model<Input, Output> {
             _types: PhantomData,
         }
     }
+
+    /// Load one of several variants of a model, letting the runtime pick
+    /// the one which best fits the memory it has available.
+    ///
+    /// Each variant is a `(name, model_data)` pair.
+    pub fn load_variant(
+        name: &str,
+        mimetype: &str,
+        variants: &[(&str, &[u8])],
+        input_shapes: &[Shape<'static>],
+        output_shapes: &[Shape<'static>],
+    ) -> Self {
+        assert!(!variants.is_empty(), "A model needs at least one variant");
+
+        let names: Vec<_> = variants
+            .iter()
+            .map(|(variant, _)| StringRef::from(*variant))
+            .collect();
+        let sizes: Vec<usize> =
+            variants.iter().map(|(_, data)| data.len()).collect();
+
+        let index = unsafe {
+            crate::intrinsics::rune_model_select_variant(
+                names.as_ptr(),
+                sizes.as_ptr(),
+                variants.len() as u32,
+            )
+        };
+
+        let (_, model_data) = variants
+            .get(index as usize)
+            .expect("The runtime chose a variant that doesn't exist");
+
+        Model::load(name, mimetype, model_data, input_shapes, output_shapes)
+    }
 }
 
 impl<Input, Output> Model<Input, Output>
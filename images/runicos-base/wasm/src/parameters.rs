@@ -0,0 +1,71 @@
+use alloc::{string::String, vec, vec::Vec};
+use core::convert::TryInto;
+
+use crate::intrinsics;
+
+/// A request from the host to change one of a proc-block's parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterUpdate {
+    /// The proc-block's name in the Runefile.
+    pub node: String,
+    pub name: String,
+    pub value: String,
+}
+
+/// Tell the runtime the current value of one of a proc-block's parameters.
+pub fn report_parameter(node: &str, type_name: &str, name: &str, value: &str) {
+    unsafe {
+        intrinsics::rune_proc_block_parameter(
+            node.as_ptr(),
+            node.len(),
+            type_name.as_ptr(),
+            type_name.len(),
+            name.as_ptr(),
+            name.len(),
+            value.as_ptr(),
+            value.len(),
+        );
+    }
+}
+
+/// Take any parameter updates the host has queued up since the last call.
+pub fn parameter_updates() -> Vec<ParameterUpdate> {
+    let mut buffer = Vec::new();
+
+    loop {
+        let needed = unsafe {
+            intrinsics::rune_proc_block_parameter_updates(
+                buffer.as_mut_ptr(),
+                buffer.len(),
+            )
+        } as usize;
+
+        if needed <= buffer.len() {
+            buffer.truncate(needed);
+            break;
+        }
+
+        buffer = vec![0; needed];
+    }
+
+    let mut rest = buffer.as_slice();
+    let mut updates = Vec::new();
+
+    while !rest.is_empty() {
+        let node = read_string(&mut rest);
+        let name = read_string(&mut rest);
+        let value = read_string(&mut rest);
+        updates.push(ParameterUpdate { node, name, value });
+    }
+
+    updates
+}
+
+fn read_string(buffer: &mut &[u8]) -> String {
+    let (len, rest) = buffer.split_at(4);
+    let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+    let (s, rest) = rest.split_at(len);
+    *buffer = rest;
+
+    String::from_utf8(s.to_vec()).expect("The host always sends UTF-8")
+}
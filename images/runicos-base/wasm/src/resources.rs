@@ -39,10 +39,7 @@ impl Resource {
 
     pub fn open(name: &str) -> Result<Self, ResourceError> {
         unsafe {
-            let id = intrinsics::rune_resource_open(
-                name.as_ptr(),
-                name.len() as u32,
-            );
+            let id = intrinsics::rune_resource_open(name.as_ptr(), name.len());
 
             if id >= 0 {
                 Ok(Resource {
@@ -62,7 +59,7 @@ impl Resource {
             let bytes_read = intrinsics::rune_resource_read(
                 self.id,
                 buffer.as_mut_ptr().cast(),
-                buffer.len() as u32,
+                buffer.len(),
             );
 
             if bytes_read >= 0 {
@@ -1,40 +1,126 @@
-use alloc::vec::Vec;
+use alloc::{string::ToString, vec::Vec};
 use core::{cell::RefCell, fmt::Debug};
 
-use hotg_rune_core::{outputs, AsElementType, ElementType, Tensor};
+use hotg_rune_core::{
+    framing::{self, Framing},
+    outputs,
+    serial_format::SerialFormat,
+    AsElementType, ElementType, Tensor,
+};
 use serde::ser::{Serialize, SerializeMap, Serializer};
-use serde_json::Value;
+use serde_json::{Map, Value};
 
-use crate::intrinsics;
+use crate::{chunks, intrinsics, serial_encoding};
 
 #[derive(Debug, PartialEq, Clone)]
 #[non_exhaustive]
 pub struct Serial {
     id: u32,
     buffer: RefCell<Vec<u8>>,
+    framing: Framing,
+    format: SerialFormat,
+    sources: &'static [&'static str],
 }
 
 impl Serial {
     const INITIAL_BUFFER_SIZE: usize = 1024;
 
-    pub fn new() -> Self {
+    pub fn new() -> Self { Serial::with_framing(Framing::None) }
+
+    /// Create a new [`Serial`] output which will frame each message using
+    /// the [`Framing`] scheme so it can be sent over a noisy link.
+    pub fn with_framing(framing: Framing) -> Self {
         unsafe {
             Serial {
                 id: intrinsics::request_output(outputs::SERIAL),
                 buffer: RefCell::new(
                     alloc::vec![0; Serial::INITIAL_BUFFER_SIZE],
                 ),
+                framing,
+                format: SerialFormat::Json,
+                sources: &[],
             }
         }
     }
 
-    fn log(&self, msg: &[u8]) {
+    /// Tag each tensor sent to a multi-input [`Serial`] output with the name
+    /// of the stage output it came from.
+    ///
+    /// Instead of a bare array, messages will be sent as an object like
+    /// `{"channel": 1, "tensors": [{"source": "fft", ...}, ...]}`.
+    pub fn with_sources(self, sources: &'static [&'static str]) -> Self {
+        Serial { sources, ..self }
+    }
+
+    /// Let the runtime know this output is the `name` node in the pipeline.
+    pub fn with_name(self, name: &str) -> Self {
         unsafe {
-            intrinsics::consume_output(self.id, msg.as_ptr(), msg.len() as u32);
+            intrinsics::rune_node_name(self.id, name.as_ptr(), name.len());
         }
+
+        self
+    }
+
+    /// Encode messages using a more compact [`SerialFormat`] than JSON.
+    ///
+    /// The format is passed along to the runtime as the output's `format`
+    /// parameter so it knows how to decode each message.
+    pub fn with_format(self, format: SerialFormat) -> Self {
+        self.set_parameter("format", format.name());
+        // Binary formats may contain null bytes, so the runtime can't
+        // detect framing by looking for a frame delimiter like it does for
+        // JSON.
+        self.set_parameter("framing", self.framing.name());
+
+        Serial { format, ..self }
     }
 
+    fn set_parameter(&self, key: &str, value: &str) {
+        unsafe {
+            intrinsics::request_output_set_param(
+                self.id,
+                key.as_ptr(),
+                key.len(),
+                value.as_ptr(),
+                value.len(),
+            );
+        }
+    }
+
+    fn log(&self, msg: &[u8]) {
+        match self.framing {
+            Framing::None => self.write(msg),
+            Framing::Cobs => {
+                let mut frame =
+                    Vec::with_capacity(msg.len() + msg.len() / 254 + 4);
+                framing::encode_frame(msg, &mut frame);
+                self.write(&frame);
+            },
+        }
+    }
+
+    fn write(&self, data: &[u8]) { chunks::write_output(self.id, data); }
+
     fn consume_serializable(&self, msg: &Value) {
+        match self.format {
+            SerialFormat::Json => self.consume_json(msg),
+            SerialFormat::Cbor => self.consume_binary(|buffer| {
+                serial_encoding::write_cbor(msg, buffer)
+            }),
+            SerialFormat::Protobuf => self.consume_binary(|buffer| {
+                serial_encoding::to_protobuf(msg).encode(buffer)
+            }),
+        }
+    }
+
+    fn consume_binary(&self, encode: impl FnOnce(&mut Vec<u8>)) {
+        let mut buffer = self.buffer.borrow_mut();
+        buffer.clear();
+        encode(&mut buffer);
+        self.log(&buffer);
+    }
+
+    fn consume_json(&self, msg: &Value) {
         let mut buffer = self.buffer.borrow_mut();
 
         // Keep resizing our internal buffer until it's big enough to hold the
@@ -64,10 +150,36 @@ impl Serial {
         T: IntoSerialMessage,
     {
         let msg = input.into_serial_message(self.id);
+        let msg = tag_sources(msg, self.id, self.sources);
         self.consume_serializable(&msg);
     }
 }
 
+fn tag_sources(msg: Value, channel: u32, sources: &[&str]) -> Value {
+    let tensors = match msg {
+        Value::Array(tensors) if !sources.is_empty() => tensors,
+        other => return other,
+    };
+
+    let tensors = tensors
+        .into_iter()
+        .enumerate()
+        .map(|(i, tensor)| match (tensor, sources.get(i)) {
+            (Value::Object(mut tensor), Some(source)) => {
+                tensor.insert("source".to_string(), source.to_string().into());
+                Value::Object(tensor)
+            },
+            (other, _) => other,
+        })
+        .collect();
+
+    let mut tagged = Map::new();
+    tagged.insert("channel".to_string(), channel.into());
+    tagged.insert("tensors".to_string(), Value::Array(tensors));
+
+    Value::Object(tagged)
+}
+
 impl Default for Serial {
     fn default() -> Self { Serial::new() }
 }
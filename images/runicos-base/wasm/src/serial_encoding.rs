@@ -0,0 +1,187 @@
+//! Encoders for the binary [`SerialFormat`][hotg_rune_core::serial_format]s.
+//!
+//! Messages are built up as JSON values first (see
+//! [`IntoSerialMessage`][crate::serial::IntoSerialMessage]), so these just
+//! translate a [`Value`] into the chosen format.
+
+use alloc::{string::String, vec::Vec};
+
+use hotg_rune_core::{
+    bf16, f16,
+    serial_format::{ProtobufMessage, ProtobufTensor},
+};
+use serde_json::{Map, Number, Value};
+
+/// Encode a message using CBOR. The resulting message has exactly the same
+/// structure as its JSON equivalent.
+pub(crate) fn write_cbor(value: &Value, buffer: &mut Vec<u8>) {
+    match value {
+        Value::Null => buffer.push(0xF6),
+        Value::Bool(false) => buffer.push(0xF4),
+        Value::Bool(true) => buffer.push(0xF5),
+        Value::Number(n) => write_cbor_number(n, buffer),
+        Value::String(s) => {
+            write_cbor_head(buffer, 3, s.len() as u64);
+            buffer.extend_from_slice(s.as_bytes());
+        },
+        Value::Array(items) => {
+            write_cbor_head(buffer, 4, items.len() as u64);
+            for item in items {
+                write_cbor(item, buffer);
+            }
+        },
+        Value::Object(map) => {
+            write_cbor_head(buffer, 5, map.len() as u64);
+            for (key, value) in map {
+                write_cbor_head(buffer, 3, key.len() as u64);
+                buffer.extend_from_slice(key.as_bytes());
+                write_cbor(value, buffer);
+            }
+        },
+    }
+}
+
+fn write_cbor_number(n: &Number, buffer: &mut Vec<u8>) {
+    if let Some(u) = n.as_u64() {
+        write_cbor_head(buffer, 0, u);
+    } else if let Some(i) = n.as_i64() {
+        write_cbor_head(buffer, 1, (-1 - i) as u64);
+    } else {
+        let f = n.as_f64().unwrap_or_default();
+
+        // Most tensors contain f32s, so don't waste space on the extra
+        // precision when we don't need it
+        if f64::from(f as f32) == f {
+            buffer.push(0xFA);
+            buffer.extend_from_slice(&(f as f32).to_be_bytes());
+        } else {
+            buffer.push(0xFB);
+            buffer.extend_from_slice(&f.to_be_bytes());
+        }
+    }
+}
+
+fn write_cbor_head(buffer: &mut Vec<u8>, major_type: u8, value: u64) {
+    let major_type = major_type << 5;
+
+    if value < 24 {
+        buffer.push(major_type | value as u8);
+    } else if value <= u64::from(u8::MAX) {
+        buffer.push(major_type | 24);
+        buffer.push(value as u8);
+    } else if value <= u64::from(u16::MAX) {
+        buffer.push(major_type | 25);
+        buffer.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u64::from(u32::MAX) {
+        buffer.push(major_type | 26);
+        buffer.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        buffer.push(major_type | 27);
+        buffer.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// Convert a message into its Protocol Buffers equivalent.
+pub(crate) fn to_protobuf(value: &Value) -> ProtobufMessage {
+    let tensors: Vec<&Map<String, Value>> = match value {
+        Value::Object(tagged) if tagged.contains_key("tensors") => tagged
+            ["tensors"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_object)
+            .collect(),
+        Value::Object(single) => alloc::vec![single],
+        Value::Array(many) => {
+            many.iter().filter_map(Value::as_object).collect()
+        },
+        _ => Vec::new(),
+    };
+
+    let channel = value
+        .get("channel")
+        .or_else(|| tensors.first().and_then(|t| t.get("channel")))
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+
+    ProtobufMessage {
+        channel: channel as u32,
+        tensors: tensors.into_iter().map(protobuf_tensor).collect(),
+    }
+}
+
+fn protobuf_tensor(tensor: &Map<String, Value>) -> ProtobufTensor {
+    let type_name = tensor
+        .get("type_name")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let source = tensor
+        .get("source")
+        .and_then(Value::as_str)
+        .map(String::from);
+
+    if let Some(string) = tensor.get("string").and_then(Value::as_str) {
+        return ProtobufTensor {
+            type_name: type_name.into(),
+            dimensions: alloc::vec![1],
+            strings: alloc::vec![string.into()],
+            source,
+            ..Default::default()
+        };
+    }
+
+    let dimensions = tensor
+        .get("dimensions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_u64)
+        .map(|d| d as u32)
+        .collect();
+    let elements = tensor
+        .get("elements")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    let mut msg = ProtobufTensor {
+        type_name: type_name.into(),
+        dimensions,
+        source,
+        ..Default::default()
+    };
+
+    for element in elements {
+        match element {
+            Value::String(s) => msg.strings.push(s.clone()),
+            Value::Number(n) => push_element(type_name, n, &mut msg.elements),
+            other => panic!("Unable to encode {} as a tensor element", other),
+        }
+    }
+
+    msg
+}
+
+fn push_element(type_name: &str, n: &Number, buffer: &mut Vec<u8>) {
+    let int = n.as_i64().unwrap_or_default();
+    let float = n.as_f64().unwrap_or_default();
+
+    match type_name {
+        "u8" => buffer.push(int as u8),
+        "i8" => buffer.push(int as i8 as u8),
+        "u16" => buffer.extend_from_slice(&(int as u16).to_le_bytes()),
+        "i16" => buffer.extend_from_slice(&(int as i16).to_le_bytes()),
+        "u32" => buffer.extend_from_slice(&(int as u32).to_le_bytes()),
+        "i32" => buffer.extend_from_slice(&(int as i32).to_le_bytes()),
+        "u64" => buffer
+            .extend_from_slice(&n.as_u64().unwrap_or_default().to_le_bytes()),
+        "i64" => buffer.extend_from_slice(&int.to_le_bytes()),
+        "f16" => buffer.extend_from_slice(&f16::from_f64(float).to_le_bytes()),
+        "bf16" => {
+            buffer.extend_from_slice(&bf16::from_f64(float).to_le_bytes())
+        },
+        "f32" => buffer.extend_from_slice(&(float as f32).to_le_bytes()),
+        "f64" => buffer.extend_from_slice(&float.to_le_bytes()),
+        other => panic!("Unable to encode a \"{}\" tensor as protobuf", other),
+    }
+}
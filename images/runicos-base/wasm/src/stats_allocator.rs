@@ -17,6 +17,7 @@ pub struct StatsAllocator<T> {
     bytes_allocated: AtomicUsize,
     bytes_deallocated: AtomicUsize,
     bytes_reallocated: AtomicIsize,
+    peak_in_use: AtomicUsize,
     inner: T,
 }
 
@@ -51,6 +52,12 @@ pub struct Stats {
     /// positive value indicates that resizable structures are growing, while
     /// a negative value indicates that such structures are shrinking.
     pub bytes_reallocated: isize,
+    /// The most bytes that have been in use at once since the last call to
+    /// [`StatsAllocator::reset_peak()`].
+    ///
+    /// This isn't a running total, so subtracting two [`Stats`] leaves it
+    /// unchanged.
+    pub peak_in_use: usize,
 }
 
 impl<T> StatsAllocator<T> {
@@ -64,6 +71,7 @@ impl<T> StatsAllocator<T> {
             bytes_allocated: AtomicUsize::new(0),
             bytes_deallocated: AtomicUsize::new(0),
             bytes_reallocated: AtomicIsize::new(0),
+            peak_in_use: AtomicUsize::new(0),
             inner,
         }
     }
@@ -77,8 +85,28 @@ impl<T> StatsAllocator<T> {
             bytes_allocated: self.bytes_allocated.load(Ordering::SeqCst),
             bytes_deallocated: self.bytes_deallocated.load(Ordering::SeqCst),
             bytes_reallocated: self.bytes_reallocated.load(Ordering::SeqCst),
+            peak_in_use: self.peak_in_use.load(Ordering::SeqCst),
         }
     }
+
+    /// Start tracking a new high-water mark from the number of bytes that
+    /// are currently in use.
+    pub fn reset_peak(&self) {
+        let in_use = self.stats().in_use();
+        self.peak_in_use.store(in_use, Ordering::SeqCst);
+    }
+
+    fn update_peak(&self) {
+        let in_use = self.stats().in_use();
+        self.peak_in_use.fetch_max(in_use, Ordering::SeqCst);
+    }
+}
+
+impl Stats {
+    /// The number of bytes which are currently allocated.
+    pub fn in_use(&self) -> usize {
+        self.bytes_allocated.saturating_sub(self.bytes_deallocated)
+    }
 }
 
 impl ops::Sub for Stats {
@@ -127,6 +155,7 @@ unsafe impl<T: GlobalAlloc> GlobalAlloc for StatsAllocator<T> {
         self.allocations.fetch_add(1, Ordering::SeqCst);
         self.bytes_allocated
             .fetch_add(layout.size(), Ordering::SeqCst);
+        self.update_peak();
         self.inner.alloc(layout)
     }
 
@@ -141,6 +170,7 @@ unsafe impl<T: GlobalAlloc> GlobalAlloc for StatsAllocator<T> {
         self.allocations.fetch_add(1, Ordering::SeqCst);
         self.bytes_allocated
             .fetch_add(layout.size(), Ordering::SeqCst);
+        self.update_peak();
         self.inner.alloc_zeroed(layout)
     }
 
@@ -154,6 +184,7 @@ unsafe impl<T: GlobalAlloc> GlobalAlloc for StatsAllocator<T> {
         if new_size > layout.size() {
             let difference = new_size - layout.size();
             self.bytes_allocated.fetch_add(difference, Ordering::SeqCst);
+            self.update_peak();
         } else if new_size < layout.size() {
             let difference = layout.size() - new_size;
             self.bytes_deallocated
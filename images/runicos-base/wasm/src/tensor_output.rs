@@ -17,17 +17,24 @@ impl TensorOutput {
         }
     }
 
-    pub fn consume<'a>(&mut self, inputs: impl Writable) {
-        self.buffer.clear();
-        inputs.encode(&mut self.buffer);
-
+    /// Let the runtime know this output is the `name` node in the pipeline.
+    pub fn with_name(self, name: &str) -> Self {
         unsafe {
-            crate::intrinsics::consume_output(
+            crate::intrinsics::rune_node_name(
                 self.id,
-                self.buffer.as_ptr(),
-                self.buffer.len() as u32,
+                name.as_ptr(),
+                name.len(),
             );
         }
+
+        self
+    }
+
+    pub fn consume<'a>(&mut self, inputs: impl Writable) {
+        self.buffer.clear();
+        inputs.encode(&mut self.buffer);
+
+        crate::chunks::write_output(self.id, &self.buffer);
     }
 }
 
@@ -0,0 +1,168 @@
+//! A proc-block which turns a window of accelerometer samples into the
+//! statistical features commonly used for gesture and activity recognition.
+//!
+//! ```yaml
+//! pipeline:
+//!   features:
+//!     proc-block: "hotg-ai/rune#proc_blocks/accelerometer_features"
+//!     inputs:
+//!       - accelerometer
+//!     outputs:
+//!       - type: f32
+//!         dimensions: [18]
+//!     args:
+//!       center: true
+//! ```
+//!
+//! The input is an `[N, 3]` tensor with one `[x, y, z]` sample per row. For
+//! each axis, the following features are calculated:
+//!
+//! 1. mean
+//! 2. standard deviation
+//! 3. [root mean square][rms]
+//! 4. minimum
+//! 5. maximum
+//! 6. number of zero crossings
+//!
+//! The output is the 6 features for `x`, followed by the 6 features for `y`
+//! and `z`.
+//!
+//! An accelerometer at rest still measures gravity, so an axis may never
+//! cross zero. Setting `center` counts crossings of the axis's mean instead.
+//!
+//! [rms]: https://en.wikipedia.org/wiki/Root_mean_square
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use hotg_rune_proc_blocks::{rune_assert_eq, ProcBlock, Tensor, Transform};
+
+const AXES: usize = 3;
+const FEATURES_PER_AXIS: usize = 6;
+
+/// Calculate the mean, standard deviation, RMS, min, max, and zero crossings
+/// for each axis.
+#[derive(Debug, Default, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [f32; 2], outputs = [f32; 1])]
+#[output_shape(f32, [18])]
+pub struct AccelerometerFeatures {
+    /// Count crossings of each axis's mean rather than zero.
+    center: bool,
+}
+
+impl Transform<Tensor<f32>> for AccelerometerFeatures {
+    type Output = Tensor<f32>;
+
+    fn transform(&mut self, input: Tensor<f32>) -> Tensor<f32> {
+        rune_assert_eq!(
+            input.dimensions().get(1),
+            Some(&AXES),
+            "Expected an [N, 3] window of accelerometer samples, not {:?}",
+            input.dimensions(),
+        );
+
+        let mut features = Vec::with_capacity(AXES * FEATURES_PER_AXIS);
+
+        for axis in 0..AXES {
+            let samples: Vec<f32> = input
+                .elements()
+                .iter()
+                .skip(axis)
+                .step_by(AXES)
+                .copied()
+                .collect();
+            features.extend_from_slice(&self.axis_features(&samples));
+        }
+
+        Tensor::new_vector(features)
+    }
+}
+
+impl AccelerometerFeatures {
+    fn axis_features(&self, samples: &[f32]) -> [f32; FEATURES_PER_AXIS] {
+        if samples.is_empty() {
+            return [0.0; FEATURES_PER_AXIS];
+        }
+
+        let n = samples.len() as f32;
+        let mean = samples.iter().sum::<f32>() / n;
+        let variance =
+            samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f32>() / n;
+        let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / n;
+        let min = samples.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = samples.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+        let origin = if self.center { mean } else { 0.0 };
+
+        [
+            mean,
+            libm::sqrtf(variance),
+            libm::sqrtf(mean_square),
+            min,
+            max,
+            crossings(samples, origin) as f32,
+        ]
+    }
+}
+
+/// Count how many times the signal crosses from one side of `origin` to the
+/// other, ignoring samples which land exactly on it.
+fn crossings(samples: &[f32], origin: f32) -> usize {
+    let mut previous = None;
+    let mut count = 0;
+
+    for &sample in samples {
+        let above = sample > origin;
+
+        if sample != origin {
+            if previous.map_or(false, |p| p != above) {
+                count += 1;
+            }
+            previous = Some(above);
+        }
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_for_each_axis() {
+        let mut proc_block = AccelerometerFeatures::default();
+        let input = Tensor::new_row_major(
+            alloc::vec![
+                1.0, 0.0, 9.8, //
+                -1.0, 2.0, 9.8, //
+                1.0, 4.0, 9.8, //
+                -1.0, 6.0, 9.8, //
+            ]
+            .into(),
+            alloc::vec![4, 3],
+        );
+
+        let got = proc_block.transform(input);
+
+        assert_eq!(got.dimensions(), &[18]);
+        let x = &got.elements()[..6];
+        assert_eq!(x, &[0.0, 1.0, 1.0, -1.0, 1.0, 3.0]);
+        let z = &got.elements()[12..];
+        assert!((z[0] - 9.8).abs() < 1e-5);
+        assert!(z[1].abs() < 1e-5);
+        assert_eq!(z[5], 0.0, "Gravity never crosses zero");
+    }
+
+    #[test]
+    fn centered_crossings() {
+        let samples = [9.0, 11.0, 9.0, 11.0];
+
+        assert_eq!(crossings(&samples, 0.0), 0);
+        assert_eq!(crossings(&samples, 10.0), 3);
+        assert_eq!(crossings(&[1.0, 0.0, -1.0, 0.0, 1.0], 0.0), 2);
+    }
+}
@@ -0,0 +1,335 @@
+//! A proc-block which cleans up raw audio before it is passed to a speech
+//! frontend (e.g. an STFT or MFCC proc-block).
+//!
+//! ```yaml
+//! pipeline:
+//!   filtered:
+//!     proc-block: "hotg-ai/rune#proc_blocks/audio_filters"
+//!     inputs:
+//!       - audio
+//!     outputs:
+//!       - type: f32
+//!         dimensions: [1, 16000]
+//!     args:
+//!       remove_dc: true
+//!       high_pass: 80
+//!       sample_rate: 16000
+//!       pre_emphasis: 0.97
+//!       normalization: asymmetric
+//! ```
+//!
+//! The filters are applied in that order, along the last dimension, so a
+//! `[2, 8000]` input is treated as two independent clips of 8000 samples.
+//!
+//! `i16` samples are scaled to floats before filtering. The default,
+//! `asymmetric`, divides by `32768` so `i16::MIN` becomes exactly `-1.0` and
+//! the output is in `[-1, 1)`. With `symmetric`, both `i16::MIN` and
+//! `i16::MAX` are scaled by `32767` and the output is clamped to `[-1, 1]`, so
+//! they map to exactly `-1.0` and `1.0`.
+//!
+//! - DC removal subtracts the clip's mean, getting rid of any constant offset a
+//!   cheap microphone or ADC adds to the signal
+//! - The high-pass filter is a second-order Butterworth [biquad][biquad] which
+//!   removes rumble and mains hum below the cutoff frequency (in Hz). Set
+//!   `high_pass` to `0` to disable it
+//! - [Pre-emphasis][pre-emphasis] computes `y[n] = x[n] - α·x[n-1]`, boosting
+//!   the high frequencies that carry most of the information in speech. Set
+//!   `pre_emphasis` to `0` to disable it
+//!
+//! [biquad]: https://www.w3.org/TR/audio-eq-cookbook/
+//! [pre-emphasis]: https://en.wikipedia.org/wiki/Emphasis_(telecommunications)
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use hotg_rune_proc_blocks::{ProcBlock, Tensor, Transform};
+
+/// Remove DC offset, high-pass filter, and pre-emphasise each clip of audio.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [i16; _], outputs = [f32; _])]
+#[transform(inputs = [f32; _], outputs = [f32; _])]
+pub struct AudioFilters {
+    /// The pre-emphasis coefficient, `α`, typically between `0.9` and `1.0`.
+    pre_emphasis: f32,
+    /// Subtract the mean from each clip.
+    remove_dc: bool,
+    /// The high-pass filter's cutoff frequency in Hz, or `0` to disable it.
+    high_pass: f32,
+    /// The audio's sample rate in Hz, used by the high-pass filter.
+    sample_rate: u32,
+    /// How `i16` samples are scaled to floats (`asymmetric` or `symmetric`).
+    normalization: Normalization,
+}
+
+impl AudioFilters {
+    fn filter(
+        &self,
+        dimensions: &[usize],
+        mut samples: Vec<f32>,
+    ) -> Tensor<f32> {
+        let clip_length = dimensions.last().copied().unwrap_or(1);
+        let high_pass = Biquad::high_pass(self.high_pass, self.sample_rate);
+
+        if clip_length > 0 {
+            for clip in samples.chunks_mut(clip_length) {
+                if self.remove_dc {
+                    remove_dc(clip);
+                }
+                if let Some(biquad) = &high_pass {
+                    biquad.apply(clip);
+                }
+                if self.pre_emphasis != 0.0 {
+                    pre_emphasis(clip, self.pre_emphasis);
+                }
+            }
+        }
+
+        Tensor::new_row_major(samples.into(), dimensions.to_vec())
+    }
+}
+
+impl Default for AudioFilters {
+    fn default() -> Self {
+        AudioFilters {
+            pre_emphasis: 0.97,
+            remove_dc: true,
+            high_pass: 0.0,
+            sample_rate: 16000,
+            normalization: Normalization::Asymmetric,
+        }
+    }
+}
+
+impl Transform<Tensor<i16>> for AudioFilters {
+    type Output = Tensor<f32>;
+
+    fn transform(&mut self, input: Tensor<i16>) -> Self::Output {
+        let samples = input
+            .elements()
+            .iter()
+            .map(|&s| self.normalization.scale(s))
+            .collect();
+
+        self.filter(input.dimensions(), samples)
+    }
+}
+
+impl Transform<Tensor<f32>> for AudioFilters {
+    type Output = Tensor<f32>;
+
+    fn transform(&mut self, input: Tensor<f32>) -> Self::Output {
+        self.filter(input.dimensions(), input.elements().to_vec())
+    }
+}
+
+/// How `i16` samples are converted to floats.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Normalization {
+    /// Divide by `32768`, giving values in `[-1, 1)`.
+    Asymmetric,
+    /// Divide by `32767` and clamp, giving values in `[-1, 1]`.
+    Symmetric,
+}
+
+impl Normalization {
+    pub fn scale(self, sample: i16) -> f32 {
+        match self {
+            Normalization::Asymmetric => f32::from(sample) / 32768.0,
+            Normalization::Symmetric => (f32::from(sample) / 32767.0).max(-1.0),
+        }
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = UnknownNormalization;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s.eq_ignore_ascii_case("asymmetric") {
+            Ok(Normalization::Asymmetric)
+        } else if s.eq_ignore_ascii_case("symmetric") {
+            Ok(Normalization::Symmetric)
+        } else {
+            Err(UnknownNormalization(s.into()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownNormalization(pub String);
+
+impl Display for UnknownNormalization {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown normalization, \"{}\" (expected \"asymmetric\" or \
+             \"symmetric\")",
+            self.0
+        )
+    }
+}
+
+fn remove_dc(clip: &mut [f32]) {
+    let mean = clip.iter().sum::<f32>() / clip.len().max(1) as f32;
+
+    for sample in clip {
+        *sample -= mean;
+    }
+}
+
+fn pre_emphasis(clip: &mut [f32], coefficient: f32) {
+    // Walk backwards so each sample still sees its unfiltered predecessor.
+    for i in (1..clip.len()).rev() {
+        clip[i] -= coefficient * clip[i - 1];
+    }
+}
+
+/// A second-order IIR filter, using the coefficients from the
+/// [Audio EQ Cookbook](https://www.w3.org/TR/audio-eq-cookbook/) normalised so
+/// `a0` is `1`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+}
+
+impl Biquad {
+    /// A Butterworth (`Q = 1/√2`) high-pass filter, or `None` if the cutoff
+    /// isn't between `0` and the Nyquist frequency.
+    fn high_pass(cutoff: f32, sample_rate: u32) -> Option<Self> {
+        let sample_rate = sample_rate as f32;
+
+        if cutoff <= 0.0 || cutoff >= sample_rate / 2.0 {
+            return None;
+        }
+
+        let w0 = 2.0 * core::f32::consts::PI * cutoff / sample_rate;
+        let (sin, cos) = (libm::sinf(w0), libm::cosf(w0));
+        let alpha = sin / (2.0 * core::f32::consts::FRAC_1_SQRT_2);
+        let a0 = 1.0 + alpha;
+
+        Some(Biquad {
+            b: [
+                (1.0 + cos) / 2.0 / a0,
+                -(1.0 + cos) / a0,
+                (1.0 + cos) / 2.0 / a0,
+            ],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        })
+    }
+
+    /// Filter the samples in place (direct form I).
+    fn apply(&self, samples: &mut [f32]) {
+        let Biquad { b, a } = *self;
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+
+        for sample in samples {
+            let x0 = *sample;
+            let y0 = b[0] * x0 + b[1] * x1 + b[2] * x2 - a[0] * y1 - a[1] * y2;
+
+            x2 = x1;
+            x1 = x0;
+            y2 = y1;
+            y1 = y0;
+            *sample = y0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: f32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|i| {
+                let t = i as f32 / sample_rate;
+                libm::sinf(2.0 * core::f32::consts::PI * frequency * t)
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        let mean_square =
+            samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        libm::sqrtf(mean_square)
+    }
+
+    #[test]
+    fn remove_dc_and_pre_emphasise_each_clip() {
+        let filters = AudioFilters {
+            pre_emphasis: 0.5,
+            ..Default::default()
+        };
+        let samples = alloc::vec![1.0, 2.0, 3.0, 10.0, 10.0, 10.0];
+
+        let got = filters.filter(&[2, 3], samples);
+
+        assert_eq!(got.dimensions(), &[2, 3]);
+        // [-1, 0, 1] and [0, 0, 0] before pre-emphasis
+        assert_eq!(got.elements(), &[-1.0, 0.5, 1.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn high_pass_removes_low_frequencies() {
+        let filters = AudioFilters {
+            pre_emphasis: 0.0,
+            remove_dc: false,
+            high_pass: 200.0,
+            sample_rate: 16000,
+            ..Default::default()
+        };
+        let hum = sine(50.0, 16000.0, 16000);
+        let speech = sine(1000.0, 16000.0, 16000);
+
+        let filtered_hum = filters.filter(&[16000], hum);
+        let filtered_speech = filters.filter(&[16000], speech);
+
+        // Skip the filter's start-up transient
+        assert!(rms(&filtered_hum.elements()[1000..]) < 0.1);
+        assert!(rms(&filtered_speech.elements()[1000..]) > 0.65);
+    }
+
+    #[test]
+    fn scale_i16_samples() {
+        let mut filters = AudioFilters {
+            pre_emphasis: 0.0,
+            remove_dc: false,
+            ..Default::default()
+        };
+        let input = Tensor::new_vector(alloc::vec![i16::MIN, 0, 16384]);
+
+        let got = filters.transform(input);
+
+        assert_eq!(got.elements(), &[-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn symmetric_normalization_reaches_both_ends() {
+        let mut filters = AudioFilters {
+            pre_emphasis: 0.0,
+            remove_dc: false,
+            normalization: "Symmetric".parse().unwrap(),
+            ..Default::default()
+        };
+        let input = Tensor::new_row_major(
+            alloc::vec![i16::MIN, 0, i16::MAX, -16384].into(),
+            alloc::vec![2, 2],
+        );
+
+        let got = filters.transform(input);
+
+        assert_eq!(got.dimensions(), &[2, 2]);
+        assert_eq!(got.elements()[..3], [-1.0, 0.0, 1.0]);
+        assert!((got.elements()[3] + 0.500_015).abs() < 1e-6);
+        assert!("loud".parse::<Normalization>().is_err());
+    }
+}
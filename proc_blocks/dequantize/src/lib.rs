@@ -0,0 +1,101 @@
+//! A proc-block which converts the `u8` or `i8` values produced by quantized
+//! models back to `f32`.
+//!
+//! ```yaml
+//! pipeline:
+//!   scores:
+//!     proc-block: "hotg-ai/rune#proc_blocks/dequantize"
+//!     inputs:
+//!       - model
+//!     outputs:
+//!       - type: f32
+//!         dimensions: [1, 4]
+//!     args:
+//!       scale: 0.00390625
+//!       zero_point: -128
+//! ```
+//!
+//! Each value becomes `(value - zero_point) * scale`, which is the inverse of
+//! the `quantize` proc-block. The `scale` and `zero_point` should match the
+//! quantization parameters of the model's output tensor.
+
+#![no_std]
+
+use hotg_rune_proc_blocks::{rune_assert, ProcBlock, Tensor, Transform};
+
+/// Convert quantized values back to `f32` using a scale and zero point.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [u8; _], outputs = [f32; _])]
+#[transform(inputs = [i8; _], outputs = [f32; _])]
+pub struct Dequantize {
+    /// The difference between two adjacent quantized values.
+    scale: f32,
+    /// The quantized value which represents `0.0`.
+    zero_point: i32,
+}
+
+impl Default for Dequantize {
+    fn default() -> Self {
+        Dequantize {
+            scale: 1.0,
+            zero_point: 0,
+        }
+    }
+}
+
+macro_rules! transform {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl Transform<Tensor<$type>> for Dequantize {
+                type Output = Tensor<f32>;
+
+                fn transform(&mut self, input: Tensor<$type>) -> Tensor<f32> {
+                    rune_assert!(
+                        self.scale.is_finite() && self.scale > 0.0,
+                        "The scale must be a positive number, not {}",
+                        self.scale
+                    );
+
+                    input.map(|_, &value| {
+                        (i32::from(value) - self.zero_point) as f32 * self.scale
+                    })
+                }
+            }
+        )*
+    };
+}
+
+transform!(u8, i8);
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use super::*;
+
+    #[test]
+    fn dequantize_u8() {
+        let mut dequantize = Dequantize {
+            scale: 0.5,
+            zero_point: 128,
+        };
+        let input = Tensor::new_vector(alloc::vec![128_u8, 130, 0]);
+
+        let got = dequantize.transform(input);
+
+        assert_eq!(got.elements(), &[0.0, 1.0, -64.0]);
+    }
+
+    #[test]
+    fn dequantize_i8() {
+        let mut dequantize = Dequantize {
+            scale: 0.25,
+            zero_point: -10,
+        };
+        let input = Tensor::new_vector(alloc::vec![-10_i8, -8, 127]);
+
+        let got = dequantize.transform(input);
+
+        assert_eq!(got.elements(), &[0.0, 0.5, 34.25]);
+    }
+}
@@ -0,0 +1,247 @@
+//! A proc-block which uses [Dynamic Time Warping][dtw] to compare its input
+//! with a set of reference templates.
+//!
+//! This is handy for things like gesture matching, where you have a couple of
+//! recordings of each gesture but not enough data to train a model.
+//!
+//! ```yaml
+//! resources:
+//!   templates:
+//!     path: ./templates.txt
+//!     type: string
+//!
+//! pipeline:
+//!   distances:
+//!     proc-block: "hotg-ai/rune#proc_blocks/dtw"
+//!     inputs:
+//!       - accelerometer
+//!     outputs:
+//!       - type: f32
+//!         dimensions: [3]
+//!     args:
+//!       templates: $templates
+//! ```
+//!
+//! [dtw]: https://en.wikipedia.org/wiki/Dynamic_time_warping
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use core::{
+    fmt::{self, Display, Formatter},
+    num::ParseFloatError,
+    str::FromStr,
+};
+
+use hotg_rune_proc_blocks::{rune_assert, ProcBlock, Tensor, Transform};
+
+/// Calculate the DTW distance between the input and each reference template.
+///
+/// The first dimension of the input is treated as time and everything else is
+/// a single frame (e.g. a `[128, 3]` accelerometer window is 128 frames of 3
+/// values).
+#[derive(Debug, Default, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [f32; _], outputs = [f32; 1])]
+pub struct Dtw {
+    /// The reference templates, one per line, where each line contains the
+    /// template's frames as comma or whitespace-separated values.
+    templates: Templates,
+    /// The furthest a frame may be warped away from its counterpart (a
+    /// Sakoe-Chiba band), or `0` for no limit.
+    window: usize,
+}
+
+impl Transform<Tensor<f32>> for Dtw {
+    type Output = Tensor<f32>;
+
+    fn transform(&mut self, input: Tensor<f32>) -> Tensor<f32> {
+        let features = frame_width(input.dimensions());
+
+        let distances = self.templates.0.iter().enumerate().map(|(i, t)| {
+            rune_assert!(
+                t.len() % features == 0,
+                "Template {} has {} values, which isn't a multiple of the \
+                 input's {} values per frame",
+                i,
+                t.len(),
+                features
+            );
+
+            distance(input.elements(), t, features, self.window)
+        });
+
+        Tensor::new_vector(distances)
+    }
+}
+
+/// The number of values in a single frame.
+fn frame_width(dimensions: &[usize]) -> usize {
+    match dimensions {
+        [] | [_] => 1,
+        [_, rest @ ..] => rest.iter().product::<usize>().max(1),
+    }
+}
+
+/// Reference templates parsed from a string resource.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Templates(pub Vec<Vec<f32>>);
+
+impl FromStr for Templates {
+    type Err = ParseTemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut templates = Vec::new();
+
+        for (line_number, line) in s.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let template = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(|word| word.parse())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|error| ParseTemplateError {
+                    line: line_number + 1,
+                    error,
+                })?;
+
+            templates.push(template);
+        }
+
+        Ok(Templates(templates))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseTemplateError {
+    pub line: usize,
+    pub error: ParseFloatError,
+}
+
+impl Display for ParseTemplateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Unable to parse line {}: {}", self.line, self.error)
+    }
+}
+
+/// Find the DTW distance between two sequences of frames, normalised by their
+/// combined length so templates of different lengths can be compared.
+///
+/// A `window` of `0` means the warping path is unconstrained.
+pub fn distance(a: &[f32], b: &[f32], features: usize, window: usize) -> f32 {
+    let n = a.len() / features;
+    let m = b.len() / features;
+
+    if n == 0 || m == 0 {
+        return f32::INFINITY;
+    }
+
+    // The band needs to be at least as wide as the difference in lengths,
+    // otherwise there is no path from one corner to the other.
+    let window = match window {
+        0 => usize::MAX,
+        w => w.max(if n > m { n - m } else { m - n }),
+    };
+
+    // We only ever need the previous row of the cost matrix.
+    let mut previous = vec![f32::INFINITY; m + 1];
+    let mut current = vec![f32::INFINITY; m + 1];
+    previous[0] = 0.0;
+
+    for i in 1..=n {
+        current.iter_mut().for_each(|cost| *cost = f32::INFINITY);
+
+        let frame = &a[(i - 1) * features..i * features];
+        let start = i.saturating_sub(window).max(1);
+        let end = i.saturating_add(window).min(m);
+
+        for j in start..=end {
+            let cost = euclidean(frame, &b[(j - 1) * features..j * features]);
+            let best = previous[j].min(current[j - 1]).min(previous[j - 1]);
+            current[j] = cost + best;
+        }
+
+        core::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[m] / (n + m) as f32
+}
+
+fn euclidean(a: &[f32], b: &[f32]) -> f32 {
+    let sum_of_squares: f32 =
+        a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum();
+
+    libm::sqrtf(sum_of_squares)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_sequences_have_no_distance() {
+        let sequence = [0.0, 1.0, 2.0, 1.0, 0.0];
+
+        assert_eq!(distance(&sequence, &sequence, 1, 0), 0.0);
+    }
+
+    #[test]
+    fn warping_absorbs_time_shifts() {
+        let template = [0.0, 0.0, 1.0, 2.0, 1.0, 0.0];
+        let shifted = [0.0, 1.0, 2.0, 1.0, 0.0, 0.0];
+        let different = [2.0, 2.0, 0.0, 0.0, 2.0, 2.0];
+
+        let shifted_distance = distance(&shifted, &template, 1, 0);
+        let different_distance = distance(&different, &template, 1, 0);
+
+        assert_eq!(shifted_distance, 0.0);
+        assert!(different_distance > shifted_distance);
+    }
+
+    #[test]
+    fn narrow_window_limits_the_warping() {
+        let a = [0.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+        let b = [0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
+        assert_eq!(distance(&a, &b, 1, 0), 0.0);
+        assert!(distance(&a, &b, 1, 1) > 0.0);
+    }
+
+    #[test]
+    fn multi_dimensional_frames() {
+        let mut dtw = Dtw::default();
+        dtw.set_templates("0, 0, 1, 1, 2, 2\n5 5 5 5").unwrap();
+        let input = Tensor::new_row_major(
+            vec![0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 2.0, 2.0].into(),
+            vec![4, 2],
+        );
+
+        let distances = dtw.transform(input);
+
+        assert_eq!(distances.dimensions(), &[2]);
+        assert_eq!(distances.elements()[0], 0.0);
+        assert!(distances.elements()[1] > 0.0);
+    }
+
+    #[test]
+    fn parse_templates() {
+        let src = "# gesture templates\n1, 2, 3\n\n4 5\n";
+
+        let got: Templates = src.parse().unwrap();
+
+        assert_eq!(got, Templates(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0]]));
+    }
+
+    #[test]
+    fn invalid_templates_report_the_line() {
+        let err = "1, 2\n3, x".parse::<Templates>().unwrap_err();
+
+        assert_eq!(err.line, 2);
+    }
+}
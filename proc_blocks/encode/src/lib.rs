@@ -0,0 +1,319 @@
+//! A proc-block which turns integer class indices into `f32` features, either
+//! by one-hot encoding them or by looking them up in an embedding table.
+//!
+//! ```yaml
+//! resources:
+//!   embeddings:
+//!     path: ./embeddings.txt
+//!     type: string
+//!
+//! pipeline:
+//!   one_hot:
+//!     proc-block: "hotg-ai/rune#proc_blocks/encode"
+//!     inputs:
+//!       - label
+//!     outputs:
+//!       - type: f32
+//!         dimensions: [1, 10]
+//!     args:
+//!       mode: one_hot
+//!       classes: 10
+//!
+//!   embedded:
+//!     proc-block: "hotg-ai/rune#proc_blocks/encode"
+//!     inputs:
+//!       - tokens
+//!     outputs:
+//!       - type: f32
+//!         dimensions: [32, 8]
+//!     args:
+//!       mode: embedding
+//!       table: $embeddings
+//! ```
+//!
+//! Either way, a new dimension is added to the end of the input's shape, so
+//! a `[32]` tensor of token IDs looked up in a table of 8-element embeddings
+//! becomes a `[32, 8]` tensor.
+//!
+//! Like `tf.one_hot()`, an index outside `0..classes` is encoded as all
+//! zeroes. An index which doesn't have a row in the embedding table is an
+//! error.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    convert::TryFrom,
+    fmt::{self, Display, Formatter},
+    num::ParseFloatError,
+    str::FromStr,
+};
+
+use hotg_rune_proc_blocks::{rune_assert, ProcBlock, Tensor, Transform};
+
+/// Encode each class index as a one-hot vector or an embedding.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [u8; _], outputs = [f32; _])]
+#[transform(inputs = [u32; _], outputs = [f32; _])]
+#[transform(inputs = [i32; _], outputs = [f32; _])]
+#[transform(inputs = [i64; _], outputs = [f32; _])]
+pub struct Encode {
+    /// How indices are encoded (`one_hot` or `embedding`).
+    mode: Mode,
+    /// The number of classes when one-hot encoding.
+    classes: usize,
+    /// The embedding table, with one row of comma or whitespace-separated
+    /// values per line.
+    table: EmbeddingTable,
+}
+
+impl Encode {
+    fn encode(&self, dimensions: &[usize], indices: &[i64]) -> Tensor<f32> {
+        let width = match self.mode {
+            Mode::OneHot => self.classes,
+            Mode::Embedding => self.table.width(),
+        };
+        let mut elements = Vec::with_capacity(indices.len() * width);
+
+        for &index in indices {
+            match self.mode {
+                Mode::OneHot => {
+                    let start = elements.len();
+                    elements.resize(start + width, 0.0);
+
+                    if let Some(i) = usize::try_from(index)
+                        .ok()
+                        .filter(|&i| i < self.classes)
+                    {
+                        elements[start + i] = 1.0;
+                    }
+                },
+                Mode::Embedding => {
+                    let row = usize::try_from(index)
+                        .ok()
+                        .and_then(|i| self.table.rows.get(i));
+
+                    rune_assert!(
+                        row.is_some(),
+                        "Index {} is out of range for an embedding table with \
+                         {} rows",
+                        index,
+                        self.table.rows.len()
+                    );
+
+                    elements.extend_from_slice(row.unwrap());
+                },
+            }
+        }
+
+        let mut dimensions = dimensions.to_vec();
+        dimensions.push(width);
+
+        Tensor::new_row_major(elements.into(), dimensions)
+    }
+}
+
+impl Default for Encode {
+    fn default() -> Self {
+        Encode {
+            mode: Mode::OneHot,
+            classes: 0,
+            table: EmbeddingTable::default(),
+        }
+    }
+}
+
+macro_rules! transform {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl Transform<Tensor<$type>> for Encode {
+                type Output = Tensor<f32>;
+
+                fn transform(&mut self, input: Tensor<$type>) -> Tensor<f32> {
+                    let indices: Vec<i64> = input
+                        .elements()
+                        .iter()
+                        .map(|&index| i64::from(index))
+                        .collect();
+
+                    self.encode(input.dimensions(), &indices)
+                }
+            }
+        )*
+    };
+}
+
+transform!(u8, u32, i32, i64);
+
+/// The ways class indices can be encoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    OneHot,
+    Embedding,
+}
+
+impl FromStr for Mode {
+    type Err = UnknownMode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "one_hot" | "one-hot" => Ok(Mode::OneHot),
+            "embedding" => Ok(Mode::Embedding),
+            other => Err(UnknownMode(other.into())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownMode(pub String);
+
+impl Display for UnknownMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown encoding, \"{}\" (expected \"one_hot\" or \"embedding\")",
+            self.0
+        )
+    }
+}
+
+/// An embedding table parsed from a string resource.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EmbeddingTable {
+    pub rows: Vec<Vec<f32>>,
+}
+
+impl EmbeddingTable {
+    /// The number of values in each embedding.
+    pub fn width(&self) -> usize {
+        self.rows.first().map(Vec::len).unwrap_or(0)
+    }
+}
+
+impl FromStr for EmbeddingTable {
+    type Err = ParseTableError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rows: Vec<Vec<f32>> = Vec::new();
+
+        for (line_number, line) in s.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let row = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(|word| word.parse())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|e| ParseTableError {
+                    line: line_number + 1,
+                    kind: TableErrorKind::InvalidNumber(e),
+                })?;
+
+            if let Some(first) = rows.first() {
+                if first.len() != row.len() {
+                    return Err(ParseTableError {
+                        line: line_number + 1,
+                        kind: TableErrorKind::WrongWidth {
+                            expected: first.len(),
+                            actual: row.len(),
+                        },
+                    });
+                }
+            }
+
+            rows.push(row);
+        }
+
+        Ok(EmbeddingTable { rows })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseTableError {
+    pub line: usize,
+    pub kind: TableErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableErrorKind {
+    InvalidNumber(ParseFloatError),
+    WrongWidth { expected: usize, actual: usize },
+}
+
+impl Display for ParseTableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TableErrorKind::InvalidNumber(e) => {
+                write!(f, "Unable to parse line {}: {}", self.line, e)
+            },
+            TableErrorKind::WrongWidth { expected, actual } => write!(
+                f,
+                "Line {} has {} values, but every embedding should have {}",
+                self.line, actual, expected
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_hot_encode_each_index() {
+        let mut encode = Encode {
+            classes: 3,
+            ..Default::default()
+        };
+        let input = Tensor::new_vector(alloc::vec![2_u8, 0, 7]);
+
+        let got = encode.transform(input);
+
+        assert_eq!(got.dimensions(), &[3, 3]);
+        assert_eq!(
+            got.elements(),
+            &[0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn look_up_embeddings() {
+        let mut encode = Encode::default();
+        encode.set_mode("embedding").unwrap();
+        encode
+            .set_table("# id 0\n0.5, 1.0\n# id 1\n-1 2\n")
+            .unwrap();
+        let input = Tensor::new_row_major(
+            alloc::vec![1_i32, 0, 1, 1].into(),
+            alloc::vec![2, 2],
+        );
+
+        let got = encode.transform(input);
+
+        assert_eq!(got.dimensions(), &[2, 2, 2]);
+        assert_eq!(
+            got.elements(),
+            &[-1.0, 2.0, 0.5, 1.0, -1.0, 2.0, -1.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn embeddings_must_all_be_the_same_width() {
+        let err = "1, 2\n3".parse::<EmbeddingTable>().unwrap_err();
+
+        assert_eq!(
+            err.kind,
+            TableErrorKind::WrongWidth {
+                expected: 2,
+                actual: 1
+            }
+        );
+        assert_eq!(err.line, 2);
+    }
+}
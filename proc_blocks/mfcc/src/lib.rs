@@ -0,0 +1,347 @@
+//! A proc-block which turns audio into
+//! [Mel-frequency cepstral coefficients][mfcc], the features most speech
+//! models are trained on.
+//!
+//! ```yaml
+//! pipeline:
+//!   mfcc:
+//!     proc-block: "hotg-ai/rune#proc_blocks/mfcc"
+//!     inputs:
+//!       - audio
+//!     args:
+//!       sample_rate: 16000
+//!       frame_length: 400
+//!       hop: 160
+//!       pad: 0
+//!       n_mels: 40
+//!       n_coeffs: 13
+//! ```
+//!
+//! The audio is split into frames the same way as the `windowing` proc-block.
+//! Each frame has a Hann window applied before its power spectrum is
+//! calculated (using an FFT padded to the next power of two), which is then
+//! passed through `n_mels` triangular filters spaced evenly on the mel scale
+//! between 0 Hz and the Nyquist frequency. Finally, the first `n_coeffs`
+//! coefficients from an orthonormal DCT-II of the log filterbank energies are
+//! kept, giving an `f32[frames, n_coeffs]` tensor.
+//!
+//! Both `f32` samples and `i16` samples (as produced by the `SOUND`
+//! capability, which are scaled to `[-1, 1]`) are accepted.
+//!
+//! The compiler calculates the output's shape from the input's length and
+//! the arguments, so it doesn't need to be written out and any mismatch with
+//! the model that consumes it is reported when the Rune is built.
+//!
+//! [mfcc]: https://en.wikipedia.org/wiki/Mel-frequency_cepstrum
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use core::f32::consts::PI;
+
+use hotg_rune_proc_blocks::{rune_assert, ProcBlock, Tensor, Transform};
+
+/// Added to each filterbank energy so silence doesn't give `log(0)`.
+const LOG_OFFSET: f32 = 1e-6;
+
+/// Calculate the Mel-frequency cepstral coefficients for each frame of an
+/// audio clip.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [f32; 1], outputs = [f32; 2])]
+#[transform(inputs = [i16; 1], outputs = [f32; 2])]
+#[output_shape(
+    f32,
+    [frames(input(0, 0), "frame_length", "hop", "pad"), "n_coeffs"]
+)]
+pub struct Mfcc {
+    /// The audio's sample rate, in Hz.
+    sample_rate: usize,
+    /// The number of samples in each frame.
+    frame_length: usize,
+    /// The number of samples between the start of each frame.
+    hop: usize,
+    /// The number of zeroes to add to each end of the audio.
+    pad: usize,
+    /// The number of mel filterbank channels.
+    n_mels: usize,
+    /// The number of coefficients to keep for each frame.
+    n_coeffs: usize,
+}
+
+impl Mfcc {
+    /// The number of complete frames in a clip with `length` samples.
+    fn frame_count(&self, length: usize) -> usize {
+        let padded_length = length + 2 * self.pad;
+
+        if padded_length < self.frame_length {
+            0
+        } else {
+            (padded_length - self.frame_length) / self.hop + 1
+        }
+    }
+
+    fn mfcc(&self, samples: &[f32]) -> Tensor<f32> {
+        rune_assert!(self.sample_rate > 0, "The sample rate can't be zero");
+        rune_assert!(self.frame_length > 0, "The frame length can't be zero");
+        rune_assert!(self.hop > 0, "The hop can't be zero");
+        rune_assert!(
+            0 < self.n_coeffs && self.n_coeffs <= self.n_mels,
+            "Unable to get {} coefficients from {} mel filters",
+            self.n_coeffs,
+            self.n_mels
+        );
+
+        let fft_size = self.frame_length.next_power_of_two();
+        let window = hann_window(self.frame_length);
+        let filters =
+            mel_filterbank(self.n_mels, fft_size, self.sample_rate as f32);
+        let frames = self.frame_count(samples.len());
+
+        // Index into the padded signal without actually copying it
+        let padded = |i: usize| {
+            i.checked_sub(self.pad)
+                .and_then(|i| samples.get(i))
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        let mut real = vec![0.0; fft_size];
+        let mut imag = vec![0.0; fft_size];
+        let mut power = vec![0.0; fft_size / 2 + 1];
+        let mut log_mel = vec![0.0; self.n_mels];
+        let mut elements = Vec::with_capacity(frames * self.n_coeffs);
+
+        for frame in 0..frames {
+            let start = frame * self.hop;
+
+            for (i, (re, im)) in real.iter_mut().zip(&mut imag).enumerate() {
+                *re = window.get(i).map_or(0.0, |w| w * padded(start + i));
+                *im = 0.0;
+            }
+            fft(&mut real, &mut imag);
+
+            for (k, p) in power.iter_mut().enumerate() {
+                *p = real[k] * real[k] + imag[k] * imag[k];
+            }
+
+            for (energy, filter) in log_mel.iter_mut().zip(&filters) {
+                let e: f32 =
+                    filter.iter().zip(&power).map(|(w, p)| w * p).sum();
+                *energy = libm::logf(e + LOG_OFFSET);
+            }
+
+            elements.extend((0..self.n_coeffs).map(|k| dct(&log_mel, k)));
+        }
+
+        Tensor::new_row_major(elements.into(), vec![frames, self.n_coeffs])
+    }
+}
+
+impl Default for Mfcc {
+    fn default() -> Self {
+        Mfcc {
+            sample_rate: 16000,
+            frame_length: 400,
+            hop: 160,
+            pad: 0,
+            n_mels: 40,
+            n_coeffs: 13,
+        }
+    }
+}
+
+impl Transform<Tensor<f32>> for Mfcc {
+    type Output = Tensor<f32>;
+
+    fn transform(&mut self, input: Tensor<f32>) -> Self::Output {
+        self.mfcc(input.elements())
+    }
+}
+
+impl Transform<Tensor<i16>> for Mfcc {
+    type Output = Tensor<f32>;
+
+    fn transform(&mut self, input: Tensor<i16>) -> Self::Output {
+        let samples: Vec<f32> = input
+            .elements()
+            .iter()
+            .map(|&s| f32::from(s) / 32768.0)
+            .collect();
+
+        self.mfcc(&samples)
+    }
+}
+
+/// A periodic Hann window.
+fn hann_window(length: usize) -> Vec<f32> {
+    (0..length)
+        .map(|i| 0.5 - 0.5 * libm::cosf(2.0 * PI * i as f32 / length as f32))
+        .collect()
+}
+
+fn hz_to_mel(hz: f32) -> f32 { 2595.0 * libm::log10f(1.0 + hz / 700.0) }
+
+fn mel_to_hz(mel: f32) -> f32 { 700.0 * (libm::powf(10.0, mel / 2595.0) - 1.0) }
+
+/// The weight each filter gives to the bins of an `fft_size` power spectrum.
+fn mel_filterbank(
+    n_mels: usize,
+    fft_size: usize,
+    sample_rate: f32,
+) -> Vec<Vec<f32>> {
+    let bins = fft_size / 2 + 1;
+    let max_mel = hz_to_mel(sample_rate / 2.0);
+
+    // Neighbouring filters share their edges, so we need 2 extra points
+    let edges: Vec<f32> = (0..n_mels + 2)
+        .map(|i| mel_to_hz(max_mel * i as f32 / (n_mels + 1) as f32))
+        .collect();
+
+    edges
+        .windows(3)
+        .map(|edge| {
+            let (lower, centre, upper) = (edge[0], edge[1], edge[2]);
+
+            (0..bins)
+                .map(|k| {
+                    let f = k as f32 * sample_rate / fft_size as f32;
+
+                    if f <= lower || f >= upper {
+                        0.0
+                    } else if f <= centre {
+                        (f - lower) / (centre - lower)
+                    } else {
+                        (upper - f) / (upper - centre)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// An in-place radix-2 FFT. The length must be a power of two.
+fn fft(real: &mut [f32], imag: &mut [f32]) {
+    let n = real.len();
+
+    // Reorder the inputs so each butterfly's inputs are next to each other
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            real.swap(i, j);
+            imag.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = libm::sincosf(angle * k as f32);
+                let a = start + k;
+                let b = a + len / 2;
+
+                let re = real[b] * cos - imag[b] * sin;
+                let im = real[b] * sin + imag[b] * cos;
+                real[b] = real[a] - re;
+                imag[b] = imag[a] - im;
+                real[a] += re;
+                imag[a] += im;
+            }
+        }
+
+        len <<= 1;
+    }
+}
+
+/// The `k`'th coefficient of an orthonormal DCT-II.
+fn dct(input: &[f32], k: usize) -> f32 {
+    let n = input.len() as f32;
+    let sum: f32 = input
+        .iter()
+        .enumerate()
+        .map(|(i, x)| x * libm::cosf(PI * k as f32 * (i as f32 + 0.5) / n))
+        .sum();
+    let scale = if k == 0 { 1.0 / n } else { 2.0 / n };
+
+    sum * libm::sqrtf(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_shape_matches_the_transform() {
+        let mut mfcc = Mfcc::default();
+        let input: hotg_rune_proc_blocks::Shape = "f32[16000]".parse().unwrap();
+        let shape = &Mfcc::DESCRIPTOR.output_shapes[0];
+
+        let got = shape
+            .evaluate(&[input], |name| match name {
+                "frame_length" => Some(mfcc.frame_length),
+                "hop" => Some(mfcc.hop),
+                "pad" => Some(mfcc.pad),
+                "n_coeffs" => Some(mfcc.n_coeffs),
+                _ => None,
+            })
+            .unwrap();
+
+        let output = mfcc.transform(Tensor::new_vector(vec![0.0_f32; 16000]));
+        assert_eq!(got.dimensions(), output.dimensions());
+        assert_eq!(got.dimensions(), &[98, 13]);
+    }
+
+    #[test]
+    fn silence_only_has_a_dc_component() {
+        let mut mfcc = Mfcc::default();
+
+        let got = mfcc.transform(Tensor::new_vector(vec![0_i16; 1600]));
+
+        let expected_dc = libm::logf(LOG_OFFSET) * libm::sqrtf(40.0);
+        for frame in got.elements().chunks(13) {
+            assert!((frame[0] - expected_dc).abs() < 1e-3);
+            assert!(frame[1..].iter().all(|c| c.abs() < 1e-3));
+        }
+    }
+
+    #[test]
+    fn a_tone_peaks_in_the_matching_filter() {
+        let sample_rate = 16000.0;
+        let mut real: Vec<f32> = (0..512)
+            .map(|i| libm::sinf(2.0 * PI * 1000.0 * i as f32 / sample_rate))
+            .collect();
+        let mut imag = vec![0.0; 512];
+        fft(&mut real, &mut imag);
+        let power: Vec<f32> = real
+            .iter()
+            .zip(&imag)
+            .take(257)
+            .map(|(r, i)| r * r + i * i)
+            .collect();
+        let filters = mel_filterbank(40, 512, sample_rate);
+
+        let energies: Vec<f32> = filters
+            .iter()
+            .map(|f| f.iter().zip(&power).map(|(w, p)| w * p).sum())
+            .collect();
+        let loudest = (0..40)
+            .max_by(|&a, &b| energies[a].partial_cmp(&energies[b]).unwrap())
+            .unwrap();
+
+        // 1 kHz is exactly 1000 mels, and the filter centres are spaced
+        // max_mel / 41 apart
+        let centre = mel_to_hz(hz_to_mel(8000.0) * (loudest + 1) as f32 / 41.0);
+        assert!((centre - 1000.0).abs() < 100.0, "{}", centre);
+    }
+}
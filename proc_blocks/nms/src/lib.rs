@@ -0,0 +1,219 @@
+//! A proc-block which uses [non-maximum suppression][nms] to get rid of
+//! overlapping boxes from an object detection model.
+//!
+//! ```yaml
+//! pipeline:
+//!   detections:
+//!     proc-block: "hotg-ai/rune#proc_blocks/nms"
+//!     inputs:
+//!       - boxes
+//!       - scores
+//!     args:
+//!       iou_threshold: 0.5
+//!       score_threshold: 0.25
+//! ```
+//!
+//! The first input is an `[N, 4]` tensor of boxes and the second is an `[N]`
+//! tensor with each box's score. Boxes are given by two opposite corners, so
+//! both `[y_min, x_min, y_max, x_max]` (like `tf.image.non_max_suppression()`)
+//! and `[x_min, y_min, x_max, y_max]` work as long as every box uses the same
+//! order.
+//!
+//! Boxes with a score below `score_threshold` are discarded, then boxes are
+//! visited from highest to lowest score and kept unless their
+//! [intersection over union][iou] with a box that was already kept is greater
+//! than `iou_threshold`.
+//!
+//! A Rune's tensors have fixed sizes, so there are always `N` outputs. The
+//! kept boxes come first, ordered by score, followed by boxes of zeroes. The
+//! second output contains the index of each kept box in the original input,
+//! with `-1` for the padding.
+//!
+//! [nms]: https://paperswithcode.com/method/non-maximum-suppression
+//! [iou]: https://en.wikipedia.org/wiki/Jaccard_index
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{cmp::Ordering, convert::TryFrom};
+
+use hotg_rune_proc_blocks::{rune_assert_eq, ProcBlock, Tensor, Transform};
+
+/// Discard boxes which overlap a box with a higher score.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = ([f32; 2], [f32; 1]), outputs = ([f32; 2], [i32; 1]))]
+#[output_shape(input = 0)]
+#[output_shape(i32, [input(1, 0)])]
+pub struct NonMaxSuppression {
+    /// Boxes that overlap a kept box by more than this are discarded.
+    iou_threshold: f32,
+    /// Boxes with a lower score are discarded.
+    score_threshold: f32,
+}
+
+impl NonMaxSuppression {
+    /// The indices of the boxes to keep, in descending order of score.
+    fn keep(&self, boxes: &[[f32; 4]], scores: &[f32]) -> Vec<usize> {
+        let mut candidates: Vec<usize> = (0..scores.len())
+            .filter(|&i| scores[i] >= self.score_threshold)
+            .collect();
+        candidates.sort_by(|&a, &b| {
+            scores[b].partial_cmp(&scores[a]).unwrap_or(Ordering::Equal)
+        });
+
+        let mut kept: Vec<usize> = Vec::new();
+
+        for candidate in candidates {
+            let overlaps = kept.iter().any(|&k| {
+                intersection_over_union(&boxes[k], &boxes[candidate])
+                    > self.iou_threshold
+            });
+
+            if !overlaps {
+                kept.push(candidate);
+            }
+        }
+
+        kept
+    }
+}
+
+impl Default for NonMaxSuppression {
+    fn default() -> Self {
+        NonMaxSuppression {
+            iou_threshold: 0.5,
+            score_threshold: 0.0,
+        }
+    }
+}
+
+impl Transform<(Tensor<f32>, Tensor<f32>)> for NonMaxSuppression {
+    type Output = (Tensor<f32>, Tensor<i32>);
+
+    fn transform(
+        &mut self,
+        (boxes, scores): (Tensor<f32>, Tensor<f32>),
+    ) -> Self::Output {
+        let count = scores.elements().len();
+
+        rune_assert_eq!(
+            boxes.dimensions(),
+            &[count, 4],
+            "Expected a box for each score",
+        );
+
+        let corners: Vec<[f32; 4]> = boxes
+            .elements()
+            .chunks_exact(4)
+            .map(|b| [b[0], b[1], b[2], b[3]])
+            .collect();
+        let kept = self.keep(&corners, scores.elements());
+
+        let mut kept_boxes = Vec::with_capacity(count * 4);
+        let mut indices = Vec::with_capacity(count);
+
+        for &index in &kept {
+            kept_boxes.extend_from_slice(&corners[index]);
+            indices.push(i32::try_from(index).unwrap_or(i32::MAX));
+        }
+
+        kept_boxes.resize(count * 4, 0.0);
+        indices.resize(count, -1);
+
+        (
+            Tensor::new_row_major(
+                kept_boxes.into(),
+                boxes.dimensions().to_vec(),
+            ),
+            Tensor::new_vector(indices),
+        )
+    }
+}
+
+/// The area where two boxes overlap divided by the area they cover.
+fn intersection_over_union(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    let a = normalize(a);
+    let b = normalize(b);
+
+    let intersection = area(&[
+        a[0].max(b[0]),
+        a[1].max(b[1]),
+        a[2].min(b[2]),
+        a[3].min(b[3]),
+    ]);
+    let union = area(&a) + area(&b) - intersection;
+
+    if union <= 0.0 {
+        0.0
+    } else {
+        intersection / union
+    }
+}
+
+/// Make sure the first corner is the minimum and the second is the maximum.
+fn normalize(corners: &[f32; 4]) -> [f32; 4] {
+    [
+        corners[0].min(corners[2]),
+        corners[1].min(corners[3]),
+        corners[0].max(corners[2]),
+        corners[1].max(corners[3]),
+    ]
+}
+
+fn area(corners: &[f32; 4]) -> f32 {
+    let height = corners[2] - corners[0];
+    let width = corners[3] - corners[1];
+
+    if height < 0.0 || width < 0.0 {
+        // The boxes didn't intersect
+        return 0.0;
+    }
+
+    height * width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_boxes_are_suppressed() {
+        let mut nms = NonMaxSuppression {
+            iou_threshold: 0.5,
+            score_threshold: 0.3,
+        };
+        let boxes = Tensor::new_row_major(
+            alloc::vec![
+                0.0, 0.0, 1.0, 1.0, // a
+                0.0, 0.1, 1.0, 1.1, // overlaps "a" with a higher score
+                0.0, 2.0, 1.0, 3.0, // doesn't overlap anything
+                5.0, 5.0, 6.0, 6.0, // score is too low
+            ]
+            .into(),
+            alloc::vec![4, 4],
+        );
+        let scores = Tensor::new_vector(alloc::vec![0.8, 0.9, 0.4, 0.2]);
+
+        let (boxes, indices) = nms.transform((boxes, scores));
+
+        assert_eq!(indices.elements(), &[1, 2, -1, -1]);
+        assert_eq!(boxes.dimensions(), &[4, 4]);
+        assert_eq!(
+            &boxes.elements()[..8],
+            &[0.0, 0.1, 1.0, 1.1, 0.0, 2.0, 1.0, 3.0]
+        );
+        assert!(boxes.elements()[8..].iter().all(|&e| e == 0.0));
+    }
+
+    #[test]
+    fn intersection_over_union_ignores_corner_order() {
+        let a = [0.0, 0.0, 2.0, 2.0];
+        let b = [3.0, 1.0, 1.0, 3.0];
+
+        assert_eq!(intersection_over_union(&a, &a), 1.0);
+        assert_eq!(intersection_over_union(&a, &b), 1.0 / 7.0);
+        assert_eq!(intersection_over_union(&a, &[5.0, 5.0, 6.0, 6.0]), 0.0);
+    }
+}
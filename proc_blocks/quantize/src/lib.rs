@@ -0,0 +1,139 @@
+//! A proc-block which converts `f32` values to the `u8` or `i8` values used by
+//! quantized models.
+//!
+//! ```yaml
+//! pipeline:
+//!   quantized:
+//!     proc-block: "hotg-ai/rune#proc_blocks/quantize"
+//!     inputs:
+//!       - image
+//!     outputs:
+//!       - type: u8
+//!         dimensions: [1, 96, 96, 1]
+//!     args:
+//!       scale: 0.003921569
+//!       zero_point: 0
+//! ```
+//!
+//! Each value becomes `round(value / scale) + zero_point`, saturating at the
+//! limits of the output type. The declared output type decides whether the
+//! result is `u8` or `i8`.
+//!
+//! The `scale` and `zero_point` should match the quantization parameters of
+//! the model's input tensor, which tools like [Netron](https://netron.app/)
+//! or `tf.lite.Interpreter.get_input_details()` will show.
+
+#![no_std]
+
+use core::marker::PhantomData;
+
+use hotg_rune_proc_blocks::{rune_assert, ProcBlock, Tensor, Transform};
+
+/// Quantize `f32` values using a scale and zero point.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[proc_block(element_type(T = [u8, i8]))]
+#[transform(inputs = [f32; _], outputs = [T; _])]
+pub struct Quantize<T> {
+    /// The difference between two adjacent quantized values.
+    scale: f32,
+    /// The quantized value which represents `0.0`.
+    zero_point: i32,
+    #[proc_block(skip)]
+    _type: PhantomData<T>,
+}
+
+impl<T> Default for Quantize<T> {
+    fn default() -> Self {
+        Quantize {
+            scale: 1.0,
+            zero_point: 0,
+            _type: PhantomData,
+        }
+    }
+}
+
+impl<T: Quantized> Transform<Tensor<f32>> for Quantize<T> {
+    type Output = Tensor<T>;
+
+    fn transform(&mut self, input: Tensor<f32>) -> Self::Output {
+        rune_assert!(
+            self.scale.is_finite() && self.scale > 0.0,
+            "The scale must be a positive number, not {}",
+            self.scale
+        );
+
+        input.map(|_, &value| {
+            let quantized =
+                round(value / self.scale).saturating_add(self.zero_point);
+            T::saturating_from(quantized)
+        })
+    }
+}
+
+/// Round half away from zero, like TensorFlow Lite does.
+///
+/// Float to int casts saturate (and turn `NaN` into `0`), so this is well
+/// defined for any input.
+fn round(value: f32) -> i32 {
+    if value >= 0.0 {
+        (value + 0.5) as i32
+    } else {
+        (value - 0.5) as i32
+    }
+}
+
+/// An integer type quantized values can be stored as.
+pub trait Quantized: Copy + 'static {
+    fn saturating_from(value: i32) -> Self;
+}
+
+macro_rules! quantized {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl Quantized for $type {
+                fn saturating_from(value: i32) -> Self {
+                    let min = i32::from(<$type>::MIN);
+                    let max = i32::from(<$type>::MAX);
+                    value.clamp(min, max) as $type
+                }
+            }
+        )*
+    };
+}
+
+quantized!(u8, i8);
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use super::*;
+
+    #[test]
+    fn quantize_to_u8() {
+        let mut quantize = Quantize::<u8> {
+            scale: 0.5,
+            zero_point: 128,
+            ..Default::default()
+        };
+        let input = Tensor::new_vector(alloc::vec![0.0, 1.0, -1.25, 100.0]);
+
+        let got = quantize.transform(input);
+
+        assert_eq!(got.elements(), &[128, 130, 125, 255]);
+    }
+
+    #[test]
+    fn quantize_to_i8() {
+        let mut quantize = Quantize::<i8> {
+            scale: 0.25,
+            zero_point: -10,
+            ..Default::default()
+        };
+        let input = Tensor::new_vector(alloc::vec![0.0, 0.375, -0.375, -40.0]);
+
+        let got = quantize.transform(input);
+
+        assert_eq!(got.elements(), &[-10, -8, -12, -128]);
+    }
+}
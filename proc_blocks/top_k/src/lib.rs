@@ -0,0 +1,180 @@
+//! A proc-block which finds the `k` most confident predictions from a
+//! classifier.
+//!
+//! ```yaml
+//! pipeline:
+//!   top_3:
+//!     proc-block: "hotg-ai/rune#proc_blocks/top_k"
+//!     inputs:
+//!       - model
+//!     outputs:
+//!       - type: i32
+//!         dimensions: [3]
+//!       - type: f32
+//!         dimensions: [3]
+//!     args:
+//!       k: 3
+//!       softmax: true
+//!       threshold: 0.5
+//! ```
+//!
+//! The input is a tensor of scores (e.g. a `[1, N]` tensor of logits), which
+//! are all treated as a single list. If `softmax` is set, the scores are
+//! normalized into probabilities first. Quantized `u8` scores are scaled to
+//! `[0, 1]` (i.e. `value / 255`) so the same `threshold` works for both
+//! quantized and unquantized models.
+//!
+//! The first output contains the indices of the `k` highest scores which are
+//! at least `threshold`, in descending order, and the second contains the
+//! scores themselves. A Rune's tensors have fixed sizes, so when fewer than
+//! `k` scores pass the threshold the indices are padded with `-1` and the
+//! scores with `0.0`.
+//!
+//! The indices can be passed straight to a `label` proc-block, so this
+//! replaces using `most_confident_index` and looking up the label by hand.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{cmp::Ordering, convert::TryFrom};
+
+use hotg_rune_proc_blocks::{ProcBlock, Tensor, Transform};
+
+/// Get the `k` highest scores above a threshold, optionally applying softmax
+/// first.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [f32; _], outputs = ([i32; 1], [f32; 1]))]
+#[transform(inputs = [u8; _], outputs = ([i32; 1], [f32; 1]))]
+#[output_shape(i32, ["k"])]
+#[output_shape(f32, ["k"])]
+pub struct TopK {
+    /// How many predictions to return.
+    k: usize,
+    /// Scores below this are discarded.
+    threshold: f32,
+    /// Apply softmax to the scores before comparing them.
+    softmax: bool,
+}
+
+impl TopK {
+    fn top_k(&self, mut scores: Vec<f32>) -> (Tensor<i32>, Tensor<f32>) {
+        if self.softmax {
+            softmax(&mut scores);
+        }
+
+        let mut candidates: Vec<usize> = (0..scores.len())
+            .filter(|&i| scores[i] >= self.threshold)
+            .collect();
+        // A stable sort means ties keep the lowest index first
+        candidates.sort_by(|&a, &b| {
+            scores[b].partial_cmp(&scores[a]).unwrap_or(Ordering::Equal)
+        });
+        candidates.truncate(self.k);
+
+        let mut indices: Vec<i32> = candidates
+            .iter()
+            .map(|&i| i32::try_from(i).unwrap_or(i32::MAX))
+            .collect();
+        let mut top_scores: Vec<f32> =
+            candidates.iter().map(|&i| scores[i]).collect();
+
+        indices.resize(self.k, -1);
+        top_scores.resize(self.k, 0.0);
+
+        (Tensor::new_vector(indices), Tensor::new_vector(top_scores))
+    }
+}
+
+impl Default for TopK {
+    fn default() -> Self {
+        TopK {
+            k: 1,
+            threshold: 0.0,
+            softmax: false,
+        }
+    }
+}
+
+impl Transform<Tensor<f32>> for TopK {
+    type Output = (Tensor<i32>, Tensor<f32>);
+
+    fn transform(&mut self, input: Tensor<f32>) -> Self::Output {
+        self.top_k(input.elements().to_vec())
+    }
+}
+
+impl Transform<Tensor<u8>> for TopK {
+    type Output = (Tensor<i32>, Tensor<f32>);
+
+    fn transform(&mut self, input: Tensor<u8>) -> Self::Output {
+        let scores = input
+            .elements()
+            .iter()
+            .map(|&value| f32::from(value) / f32::from(u8::MAX))
+            .collect();
+
+        self.top_k(scores)
+    }
+}
+
+/// Normalize the scores into probabilities which add up to `1.0`.
+fn softmax(scores: &mut [f32]) {
+    // Subtract the largest score first so exp() can't overflow
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.0;
+
+    for score in scores.iter_mut() {
+        *score = libm::expf(*score - max);
+        sum += *score;
+    }
+
+    if sum > 0.0 {
+        for score in scores.iter_mut() {
+            *score /= sum;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_scores_above_the_threshold() {
+        let mut top_k = TopK {
+            k: 3,
+            threshold: 0.2,
+            softmax: false,
+        };
+        let input = Tensor::new_row_major(
+            alloc::vec![0.1, 0.5, 0.3, 0.1].into(),
+            alloc::vec![1, 4],
+        );
+
+        let (indices, scores) = top_k.transform(input);
+
+        assert_eq!(indices.elements(), &[1, 2, -1]);
+        assert_eq!(scores.elements(), &[0.5, 0.3, 0.0]);
+    }
+
+    #[test]
+    fn softmax_quantized_scores() {
+        let mut top_k = TopK {
+            k: 2,
+            threshold: 0.0,
+            softmax: true,
+        };
+        let input = Tensor::new_vector(alloc::vec![0_u8, 255, 0]);
+
+        let (indices, scores) = top_k.transform(input);
+
+        assert_eq!(indices.elements(), &[1, 0]);
+        let e = libm::expf(1.0);
+        let expected = [e / (e + 2.0), 1.0 / (e + 2.0)];
+        for (got, expected) in scores.elements().iter().zip(&expected) {
+            assert!((got - expected).abs() < 1e-6, "{} != {}", got, expected);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! A proc-block which detects whether each frame of audio contains speech.
+//!
+//! This uses the frame's energy relative to an adaptive estimate of the
+//! background noise, plus its [spectral flatness][flatness] (speech is
+//! "peaky" while noise is flat), so no model is needed. It is normally used to
+//! gate more expensive speech models so they only run when someone is
+//! talking.
+//!
+//! ```yaml
+//! pipeline:
+//!   speech:
+//!     proc-block: "hotg-ai/rune#proc_blocks/vad"
+//!     inputs:
+//!       - audio
+//!     outputs:
+//!       - type: u8
+//!         dimensions: [50]
+//!       - type: f32
+//!         dimensions: [50]
+//!     args:
+//!       frame_size: 320
+//!       aggressiveness: 2
+//!       hangover: 8
+//! ```
+//!
+//! The first output contains a `1` for every frame that contains speech and
+//! `0` otherwise, while the second contains the speech probability for each
+//! frame before the threshold and hangover were applied.
+//!
+//! [flatness]: https://en.wikipedia.org/wiki/Spectral_flatness
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use hotg_rune_proc_blocks::{ProcBlock, Tensor, Transform};
+
+/// The speech probability a frame needs for each level of aggressiveness.
+const THRESHOLDS: [f32; 4] = [0.2, 0.35, 0.5, 0.65];
+/// Frames quieter than this (in dB relative to full scale) are never speech.
+const MIN_ENERGY_DB: f32 = -60.0;
+/// How far above the noise floor (in dB) a frame needs to be before its
+/// energy is considered a certain sign of speech.
+const SNR_RANGE_DB: f32 = 15.0;
+/// How quickly the noise floor estimate rises towards louder frames. It
+/// drops immediately when a quieter frame is seen.
+const NOISE_FLOOR_RISE: f32 = 0.01;
+/// The expected spectral flatness of white noise (`e^-γ`, where `γ` is the
+/// Euler-Mascheroni constant).
+const WHITE_NOISE_FLATNESS: f32 = 0.561;
+
+/// Detect speech in each frame of the input audio.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [i16; _], outputs = ([u8; _], [f32; _]))]
+#[transform(inputs = [f32; _], outputs = ([u8; _], [f32; _]))]
+pub struct Vad {
+    /// The number of samples in each frame (e.g. `320` for 20 ms at 16 kHz),
+    /// or `0` to treat the entire input as a single frame.
+    frame_size: usize,
+    /// How strict to be when deciding whether a frame contains speech, from
+    /// `0` (let most things through) to `3` (only obvious speech).
+    aggressiveness: u8,
+    /// The number of frames to keep reporting speech after it stops, so short
+    /// pauses between words don't cut the speech up.
+    hangover: usize,
+    #[proc_block(skip)]
+    noise_floor: Option<f32>,
+    #[proc_block(skip)]
+    hangover_remaining: usize,
+}
+
+impl Vad {
+    fn threshold(&self) -> f32 {
+        THRESHOLDS[usize::from(self.aggressiveness).min(THRESHOLDS.len() - 1)]
+    }
+
+    fn detect(&mut self, samples: &[f32]) -> (Tensor<u8>, Tensor<f32>) {
+        let frame_size = match self.frame_size {
+            0 => samples.len().max(1),
+            n => n,
+        };
+
+        let mut decisions = Vec::new();
+        let mut probabilities = Vec::new();
+
+        for frame in samples.chunks(frame_size) {
+            let probability = self.probability(frame);
+
+            let is_speech = if probability >= self.threshold() {
+                self.hangover_remaining = self.hangover;
+                true
+            } else if self.hangover_remaining > 0 {
+                self.hangover_remaining -= 1;
+                true
+            } else {
+                false
+            };
+
+            decisions.push(is_speech as u8);
+            probabilities.push(probability);
+        }
+
+        (
+            Tensor::new_vector(decisions),
+            Tensor::new_vector(probabilities),
+        )
+    }
+
+    /// How likely is it that this frame contains speech?
+    fn probability(&mut self, frame: &[f32]) -> f32 {
+        let energy = energy_db(frame);
+
+        let noise_floor = match self.noise_floor {
+            Some(floor) if energy < floor => energy,
+            Some(floor) => floor + NOISE_FLOOR_RISE * (energy - floor),
+            None => energy,
+        };
+        self.noise_floor = Some(noise_floor);
+
+        if energy < MIN_ENERGY_DB {
+            return 0.0;
+        }
+
+        let energy_score =
+            ((energy - noise_floor) / SNR_RANGE_DB).max(0.0).min(1.0);
+
+        let spectral_score = (1.0
+            - spectral_flatness(frame) / WHITE_NOISE_FLATNESS)
+            .max(0.0)
+            .min(1.0);
+
+        energy_score * spectral_score
+    }
+}
+
+impl Default for Vad {
+    fn default() -> Self {
+        Vad {
+            frame_size: 320,
+            aggressiveness: 2,
+            hangover: 8,
+            noise_floor: None,
+            hangover_remaining: 0,
+        }
+    }
+}
+
+impl Transform<Tensor<i16>> for Vad {
+    type Output = (Tensor<u8>, Tensor<f32>);
+
+    fn transform(&mut self, input: Tensor<i16>) -> Self::Output {
+        let samples: Vec<f32> = input
+            .elements()
+            .iter()
+            .map(|&s| f32::from(s) / 32768.0)
+            .collect();
+
+        self.detect(&samples)
+    }
+}
+
+impl Transform<Tensor<f32>> for Vad {
+    type Output = (Tensor<u8>, Tensor<f32>);
+
+    fn transform(&mut self, input: Tensor<f32>) -> Self::Output {
+        self.detect(input.elements())
+    }
+}
+
+/// The frame's mean power in dB relative to full scale.
+fn energy_db(frame: &[f32]) -> f32 {
+    let mean_square =
+        frame.iter().map(|s| s * s).sum::<f32>() / frame.len().max(1) as f32;
+
+    10.0 * libm::log10f(mean_square + 1e-10)
+}
+
+/// The ratio between the geometric and arithmetic means of the frame's power
+/// spectrum, where `1.0` is white noise and values near `0.0` mean the energy
+/// is concentrated in a couple of frequencies.
+pub fn spectral_flatness(frame: &[f32]) -> f32 {
+    let spectrum = power_spectrum(frame);
+
+    // Skip the DC component, it doesn't tell us anything about the signal.
+    let bins = match spectrum.get(1..) {
+        Some(bins) if !bins.is_empty() => bins,
+        _ => return 1.0,
+    };
+
+    let n = bins.len() as f32;
+    let log_mean = bins.iter().map(|&p| libm::logf(p + 1e-10)).sum::<f32>() / n;
+    let mean = bins.iter().sum::<f32>() / n;
+
+    (libm::expf(log_mean) / (mean + 1e-10)).min(1.0)
+}
+
+/// Calculate the power in each frequency bin (up to the Nyquist frequency),
+/// zero-padding the frame to a power of two.
+fn power_spectrum(frame: &[f32]) -> Vec<f32> {
+    let n = frame.len().next_power_of_two();
+    let mut re: Vec<f32> = frame.iter().copied().collect();
+    re.resize(n, 0.0);
+    let mut im = alloc::vec![0.0; n];
+
+    fft(&mut re, &mut im);
+
+    re.iter()
+        .zip(&im)
+        .take(n / 2 + 1)
+        .map(|(r, i)| r * r + i * i)
+        .collect()
+}
+
+/// An in-place, iterative radix-2 FFT.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two());
+
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * core::f32::consts::PI / len as f32;
+
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (
+                    libm::sinf(angle * k as f32),
+                    libm::cosf(angle * k as f32),
+                );
+                let a = start + k;
+                let b = a + len / 2;
+
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: usize = 256;
+
+    fn tone(frames: usize) -> Vec<f32> {
+        (0..frames * FRAME)
+            .map(|i| 0.5 * libm::sinf(i as f32 * 0.3))
+            .collect()
+    }
+
+    fn noise(frames: usize, amplitude: f32) -> Vec<f32> {
+        // A simple LCG so the test is deterministic
+        let mut state = 12345_u32;
+
+        (0..frames * FRAME)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                amplitude * ((state >> 16) as f32 / 32768.0 - 1.0)
+            })
+            .collect()
+    }
+
+    fn vad(hangover: usize) -> Vad {
+        Vad {
+            frame_size: FRAME,
+            hangover,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tones_are_peaky_and_noise_is_flat() {
+        let tone_flatness = spectral_flatness(&tone(1));
+        let noise_flatness = spectral_flatness(&noise(1, 0.5));
+
+        assert!(tone_flatness < 0.1, "{}", tone_flatness);
+        assert!(noise_flatness > 0.5, "{}", noise_flatness);
+    }
+
+    #[test]
+    fn detect_speech_after_background_noise() {
+        let mut vad = vad(0);
+        let mut input = noise(4, 0.001);
+        input.extend(tone(2));
+
+        let (decisions, probabilities) = vad.detect(&input);
+
+        assert_eq!(decisions.elements(), &[0, 0, 0, 0, 1, 1]);
+        assert!(probabilities.elements()[4] > 0.9);
+    }
+
+    #[test]
+    fn loud_noise_is_not_speech() {
+        let mut vad = vad(0);
+        let mut input = noise(4, 0.001);
+        input.extend(noise(2, 0.5));
+
+        let (decisions, _) = vad.detect(&input);
+
+        assert_eq!(decisions.elements(), &[0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn hangover_bridges_short_pauses() {
+        let mut vad = vad(2);
+        let mut input = noise(2, 0.001);
+        input.extend(tone(1));
+        input.extend(noise(3, 0.001));
+
+        let (decisions, _) = vad.detect(&input);
+
+        assert_eq!(decisions.elements(), &[0, 0, 1, 1, 1, 0]);
+    }
+}
@@ -0,0 +1,202 @@
+//! A proc-block which multiplies each frame of its input by a
+//! [window function][window] before it is passed to an FFT.
+//!
+//! ```yaml
+//! pipeline:
+//!   windowed:
+//!     proc-block: "hotg-ai/rune#proc_blocks/window"
+//!     inputs:
+//!       - frames
+//!     outputs:
+//!       - type: f32
+//!         dimensions: [49, 512]
+//!     args:
+//!       function: hann
+//!       periodic: true
+//! ```
+//!
+//! The window is applied along the last dimension, so a `[49, 512]` input is
+//! treated as 49 frames of 512 samples each.
+//!
+//! Models are normally trained on features generated in Python, so the
+//! coefficients are calculated the same way. Periodic windows (the default)
+//! are sampled from the half-open interval `[0, N)` and match
+//! `scipy.signal.get_window()`, `librosa` and `torch.hann_window()`, while
+//! symmetric windows are sampled from `[0, N - 1]` and match `numpy.hanning()`
+//! and friends.
+//!
+//! [window]: https://en.wikipedia.org/wiki/Window_function
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    f64::consts::PI,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use hotg_rune_proc_blocks::{ProcBlock, Tensor, Transform};
+
+/// Apply a window function to each frame of the input.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [f32; _], outputs = [f32; _])]
+#[output_shape(input = 0)]
+pub struct Window {
+    /// The window function to use (`hann`, `hamming`, or `blackman`).
+    function: WindowFunction,
+    /// Use a periodic window (sampled from `[0, N)`) instead of a symmetric
+    /// one (sampled from `[0, N - 1]`).
+    periodic: bool,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window {
+            function: WindowFunction::Hann,
+            periodic: true,
+        }
+    }
+}
+
+impl Transform<Tensor<f32>> for Window {
+    type Output = Tensor<f32>;
+
+    fn transform(&mut self, mut input: Tensor<f32>) -> Tensor<f32> {
+        let frame_length = input.dimensions().last().copied().unwrap_or(1);
+        let coefficients =
+            self.function.coefficients(frame_length, self.periodic);
+
+        if frame_length > 0 {
+            for frame in input.make_elements_mut().chunks_mut(frame_length) {
+                for (sample, coefficient) in frame.iter_mut().zip(&coefficients)
+                {
+                    *sample *= coefficient;
+                }
+            }
+        }
+
+        input
+    }
+}
+
+/// The supported window functions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindowFunction {
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl WindowFunction {
+    /// Calculate the window's `length` coefficients.
+    pub fn coefficients(self, length: usize, periodic: bool) -> Vec<f32> {
+        // A single-sample window would otherwise divide by zero when
+        // symmetric, and numpy/scipy both define it as 1.
+        if length == 1 {
+            return alloc::vec![1.0];
+        }
+
+        let denominator = if periodic { length } else { length - 1 } as f64;
+
+        (0..length)
+            .map(|n| {
+                let x = 2.0 * PI * n as f64 / denominator;
+                self.evaluate(x) as f32
+            })
+            .collect()
+    }
+
+    /// Evaluate the window where `x` goes from `0` to `2π` across the window.
+    fn evaluate(self, x: f64) -> f64 {
+        match self {
+            WindowFunction::Hann => 0.5 - 0.5 * libm::cos(x),
+            WindowFunction::Hamming => 0.54 - 0.46 * libm::cos(x),
+            WindowFunction::Blackman => {
+                0.42 - 0.5 * libm::cos(x) + 0.08 * libm::cos(2.0 * x)
+            },
+        }
+    }
+}
+
+impl FromStr for WindowFunction {
+    type Err = UnknownWindowFunction;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s.eq_ignore_ascii_case("hann") || s.eq_ignore_ascii_case("hanning") {
+            Ok(WindowFunction::Hann)
+        } else if s.eq_ignore_ascii_case("hamming") {
+            Ok(WindowFunction::Hamming)
+        } else if s.eq_ignore_ascii_case("blackman") {
+            Ok(WindowFunction::Blackman)
+        } else {
+            Err(UnknownWindowFunction(s.into()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownWindowFunction(pub String);
+
+impl Display for UnknownWindowFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown window function, \"{}\" (expected \"hann\", \"hamming\", \
+             or \"blackman\")",
+            self.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(got: &[f32], expected: &[f32]) {
+        assert_eq!(got.len(), expected.len());
+
+        for (g, e) in got.iter().zip(expected) {
+            assert!((g - e).abs() < 1e-6, "{:?} != {:?}", got, expected);
+        }
+    }
+
+    #[test]
+    fn periodic_and_symmetric_windows() {
+        let periodic = WindowFunction::Hann.coefficients(4, true);
+        let symmetric = WindowFunction::Hann.coefficients(4, false);
+        let hamming = WindowFunction::Hamming.coefficients(5, false);
+        let blackman = WindowFunction::Blackman.coefficients(4, true);
+
+        assert_close(&periodic, &[0.0, 0.5, 1.0, 0.5]);
+        assert_close(&symmetric, &[0.0, 0.75, 0.75, 0.0]);
+        assert_close(&hamming, &[0.08, 0.54, 1.0, 0.54, 0.08]);
+        assert_close(&blackman, &[0.0, 0.34, 1.0, 0.34]);
+        assert_close(&WindowFunction::Hann.coefficients(1, false), &[1.0]);
+    }
+
+    #[test]
+    fn window_each_frame_along_the_last_dimension() {
+        let mut window = Window::default();
+        let input = Tensor::new_row_major(
+            alloc::vec![1.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0].into(),
+            alloc::vec![2, 4],
+        );
+
+        let got = window.transform(input);
+
+        assert_eq!(got.dimensions(), &[2, 4]);
+        assert_close(got.elements(), &[0.0, 0.5, 1.0, 0.5, 0.0, 1.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn parse_window_function_names() {
+        assert_eq!("Hanning".parse(), Ok(WindowFunction::Hann));
+        assert_eq!(" blackman ".parse(), Ok(WindowFunction::Blackman));
+        assert!("kaiser".parse::<WindowFunction>().is_err());
+    }
+}
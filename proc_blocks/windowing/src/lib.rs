@@ -0,0 +1,172 @@
+//! A proc-block which splits a 1D signal into overlapping frames by sliding a
+//! window along it.
+//!
+//! ```yaml
+//! pipeline:
+//!   frames:
+//!     proc-block: "hotg-ai/rune#proc_blocks/windowing"
+//!     inputs:
+//!       - audio
+//!     args:
+//!       window_size: 320
+//!       hop: 160
+//!       pad: 0
+//! ```
+//!
+//! Each frame contains `window_size` samples and starts `hop` samples after
+//! the previous one, so a 1 second clip at 16kHz with the arguments above
+//! becomes 99 frames of 20ms which overlap by 10ms.
+//!
+//! Setting `pad` adds that many zeroes to both ends of the signal before it is
+//! split up, which lets the first and last samples sit in the middle of a
+//! frame (e.g. `pad: 160` is equivalent to `librosa`'s `center=True`). Only
+//! complete frames are produced, so any leftover samples at the end are
+//! dropped.
+//!
+//! The compiler knows how many frames will be produced, so the output's
+//! shape doesn't need to be written out.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use hotg_rune_proc_blocks::{rune_assert, ProcBlock, Tensor, Transform};
+
+/// Split a signal into overlapping frames.
+#[derive(Debug, Clone, PartialEq, ProcBlock)]
+#[transform(inputs = [f32; 1], outputs = [f32; 2])]
+#[output_shape(
+    f32,
+    [frames(input(0, 0), "window_size", "hop", "pad"), "window_size"]
+)]
+pub struct Windowing {
+    /// The number of samples in each frame.
+    window_size: usize,
+    /// The number of samples between the start of each frame.
+    hop: usize,
+    /// The number of zeroes to add to each end of the signal.
+    pad: usize,
+}
+
+impl Windowing {
+    /// The number of complete frames in a signal with `length` samples.
+    fn frame_count(&self, length: usize) -> usize {
+        let padded_length = length + 2 * self.pad;
+
+        if padded_length < self.window_size {
+            0
+        } else {
+            (padded_length - self.window_size) / self.hop + 1
+        }
+    }
+}
+
+impl Default for Windowing {
+    fn default() -> Self {
+        Windowing {
+            window_size: 320,
+            hop: 160,
+            pad: 0,
+        }
+    }
+}
+
+impl Transform<Tensor<f32>> for Windowing {
+    type Output = Tensor<f32>;
+
+    fn transform(&mut self, input: Tensor<f32>) -> Self::Output {
+        rune_assert!(self.window_size > 0, "The window size can't be zero");
+        rune_assert!(self.hop > 0, "The hop can't be zero");
+
+        let samples = input.elements();
+        let frames = self.frame_count(samples.len());
+
+        // Index into the padded signal without actually copying it
+        let padded = |i: usize| {
+            i.checked_sub(self.pad)
+                .and_then(|i| samples.get(i))
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        let mut elements = Vec::with_capacity(frames * self.window_size);
+
+        for frame in 0..frames {
+            let start = frame * self.hop;
+            elements.extend((start..start + self.window_size).map(padded));
+        }
+
+        Tensor::new_row_major(
+            elements.into(),
+            alloc::vec![frames, self.window_size],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_into_overlapping_frames() {
+        let mut windowing = Windowing {
+            window_size: 4,
+            hop: 2,
+            pad: 0,
+        };
+        let input =
+            Tensor::new_vector(alloc::vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+
+        let got = windowing.transform(input);
+
+        assert_eq!(got.dimensions(), &[2, 4]);
+        assert_eq!(got.elements(), &[1.0, 2.0, 3.0, 4.0, 3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn pad_both_ends_with_zeroes() {
+        let mut windowing = Windowing {
+            window_size: 3,
+            hop: 2,
+            pad: 1,
+        };
+        let input = Tensor::new_vector(alloc::vec![1.0, 2.0, 3.0, 4.0]);
+
+        let got = windowing.transform(input);
+
+        assert_eq!(got.dimensions(), &[2, 3]);
+        assert_eq!(got.elements(), &[0.0, 1.0, 2.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn output_shape_matches_the_transform() {
+        let padded = Windowing {
+            pad: 160,
+            ..Windowing::default()
+        };
+
+        for (mut windowing, frames) in
+            [(Windowing::default(), 99), (padded, 101)]
+        {
+            let input: hotg_rune_proc_blocks::Shape =
+                "f32[16000]".parse().unwrap();
+            let shape = &Windowing::DESCRIPTOR.output_shapes[0];
+
+            let got = shape
+                .evaluate(&[input], |name| match name {
+                    "window_size" => Some(windowing.window_size),
+                    "hop" => Some(windowing.hop),
+                    "pad" => Some(windowing.pad),
+                    _ => None,
+                })
+                .unwrap();
+
+            let output = windowing
+                .transform(Tensor::new_vector(alloc::vec![0.0; 16000]));
+            assert_eq!(got.dimensions(), output.dimensions());
+            assert_eq!(output.dimensions(), &[frames, 320]);
+        }
+    }
+}